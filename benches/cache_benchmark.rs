@@ -0,0 +1,192 @@
+use async_trait::async_trait;
+use criterion::{criterion_group, criterion_main, Criterion};
+use russh_sftp::{
+    client::{
+        fs::{CachedFile, File},
+        SftpSession,
+    },
+    protocol::{
+        Attrs, Data, FileAttributes, Handle, OpenFlags, RequestId, Status, StatusCode, Version,
+    },
+    server,
+};
+use std::{
+    collections::HashMap,
+    io::SeekFrom,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncSeekExt},
+    runtime::Runtime,
+};
+
+const FILE_SIZE: u64 = 1024 * 1024 * 1024;
+/// Region of the file holding the records that are looked up
+const HOT_REGION: u64 = 4 * 1024 * 1024;
+const RECORD_LEN: usize = 100;
+const LOOKUPS: usize = 500;
+const PAGE_SIZE: u32 = 16 * 1024;
+/// Simulated round trip of a read
+const RTT: Duration = Duration::from_millis(1);
+
+/// Large file of zeros whose reads take [`RTT`]
+struct Remote {
+    reads: Arc<AtomicU64>,
+}
+
+#[async_trait]
+impl server::Handler for Remote {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn open(
+        &mut self,
+        id: RequestId,
+        filename: String,
+        _pflags: OpenFlags,
+        _attrs: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        Ok(Handle {
+            id,
+            handle: filename,
+        })
+    }
+
+    async fn close(&mut self, id: RequestId, _handle: String) -> Result<Status, Self::Error> {
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+
+    async fn read(
+        &mut self,
+        id: RequestId,
+        _handle: String,
+        offset: u64,
+        len: u32,
+    ) -> Result<Data, Self::Error> {
+        if offset >= FILE_SIZE {
+            return Err(StatusCode::Eof);
+        }
+
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        tokio::time::sleep(RTT).await;
+        Ok(Data {
+            id,
+            data: vec![0; len.min((FILE_SIZE - offset) as u32) as usize],
+        })
+    }
+
+    async fn fstat(&mut self, id: RequestId, _handle: String) -> Result<Attrs, Self::Error> {
+        Ok(Attrs {
+            id,
+            attrs: FileAttributes {
+                size: Some(FILE_SIZE),
+                ..FileAttributes::empty()
+            },
+        })
+    }
+}
+
+/// Offsets of the records looked up, scattered across the hot region
+fn lookups() -> Vec<u64> {
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    (0..LOOKUPS)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state % (HOT_REGION - RECORD_LEN as u64)
+        })
+        .collect()
+}
+
+/// Reads each record with a request of its own
+async fn file_read(file: &mut File, offsets: &[u64]) {
+    let mut record = [0; RECORD_LEN];
+    for &offset in offsets {
+        file.seek(SeekFrom::Start(offset)).await.unwrap();
+        file.read_exact(&mut record).await.unwrap();
+    }
+}
+
+async fn cached_read(file: &mut CachedFile, offsets: &[u64]) {
+    let mut record = [0; RECORD_LEN];
+    for &offset in offsets {
+        file.read_at(offset, &mut record).await.unwrap();
+    }
+}
+
+fn cache_benchmark(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let reads = Arc::new(AtomicU64::new(0));
+    let sftp = rt.block_on(async {
+        let (client, server) = tokio::io::duplex(1024 * 1024);
+        let handler = Remote {
+            reads: reads.clone(),
+        };
+        server::run(server, handler).await;
+
+        SftpSession::new(client).await.unwrap()
+    });
+
+    let offsets = lookups();
+    let mut group = c.benchmark_group("scatter_read");
+    let report = |name: &str| {
+        println!(
+            "{name}: {} reads sent for {LOOKUPS} lookups",
+            reads.swap(0, Ordering::Relaxed)
+        );
+    };
+
+    // one round trip per lookup
+    let mut file = rt.block_on(sftp.open("/index")).unwrap();
+    reads.store(0, Ordering::Relaxed);
+    rt.block_on(file_read(&mut file, &offsets));
+    report("file");
+
+    // the hot region fits into the cache: the cold run fetches each page
+    // once, the warm run is served without any round trip
+    let cached = rt.block_on(sftp.open("/index")).unwrap();
+    let pages = (HOT_REGION / PAGE_SIZE as u64) as usize;
+    let mut cached = CachedFile::with_pages(cached, PAGE_SIZE, pages);
+    rt.block_on(cached_read(&mut cached, &offsets));
+    report("cached cold");
+    rt.block_on(cached_read(&mut cached, &offsets));
+    report("cached warm");
+
+    group.bench_function("file", |b| {
+        b.iter(|| rt.block_on(file_read(&mut file, &offsets)))
+    });
+    group.bench_function("cached cold", |b| {
+        b.iter(|| {
+            cached.invalidate();
+            rt.block_on(cached_read(&mut cached, &offsets))
+        })
+    });
+    group.bench_function("cached warm", |b| {
+        b.iter(|| rt.block_on(cached_read(&mut cached, &offsets)))
+    });
+
+    group.finish();
+}
+
+criterion_group!(
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = cache_benchmark
+);
+criterion_main!(benches);
@@ -0,0 +1,204 @@
+use async_trait::async_trait;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use russh_sftp::{
+    client::{RawSftpSession, SftpSession},
+    compression::Compression,
+    protocol::{FileAttributes, Handle, OpenFlags, RequestId, Status, StatusCode, Version},
+    server,
+};
+use std::{
+    collections::HashMap,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt, DuplexStream, ReadBuf},
+    runtime::Runtime,
+    time::{Instant, Sleep},
+};
+
+const FILE_SIZE: usize = 4 * 1024 * 1024;
+/// Bandwidth of the simulated link from the client to the server
+const LINK_BYTES_PER_SEC: u64 = 32 * 1024 * 1024;
+
+/// Accepts and discards all writes
+struct Sink;
+
+#[async_trait]
+impl server::Handler for Sink {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn open(
+        &mut self,
+        id: RequestId,
+        filename: String,
+        _pflags: OpenFlags,
+        _attrs: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        Ok(Handle {
+            id,
+            handle: filename,
+        })
+    }
+
+    async fn write(
+        &mut self,
+        id: RequestId,
+        _handle: String,
+        _offset: u64,
+        _data: Vec<u8>,
+    ) -> Result<Status, Self::Error> {
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+
+    async fn close(&mut self, id: RequestId, _handle: String) -> Result<Status, Self::Error> {
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+}
+
+/// Stream whose writes are paced to [`LINK_BYTES_PER_SEC`] and counted
+struct Link {
+    inner: DuplexStream,
+    sent: Arc<AtomicU64>,
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl AsyncRead for Link {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Link {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if let Some(delay) = self.delay.as_mut() {
+            if delay.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            self.delay = None;
+        }
+
+        let result = Pin::new(&mut self.inner).poll_write(cx, data);
+        if let Poll::Ready(Ok(len)) = result {
+            self.sent.fetch_add(len as u64, Ordering::Relaxed);
+            let pause = Duration::from_nanos(len as u64 * 1_000_000_000 / LINK_BYTES_PER_SEC);
+            self.delay = Some(Box::pin(tokio::time::sleep_until(Instant::now() + pause)));
+        }
+
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Lines of a log, which compress well
+fn logs() -> Vec<u8> {
+    let mut logs = Vec::with_capacity(FILE_SIZE);
+    let mut i = 0u64;
+    while logs.len() < FILE_SIZE {
+        logs.extend(
+            format!(
+                "2024-01-01T00:00:{:02}Z INFO request {i} served in 3ms\n",
+                i % 60
+            )
+            .bytes(),
+        );
+        i += 1;
+    }
+
+    logs.truncate(FILE_SIZE);
+    logs
+}
+
+fn connect(rt: &Runtime, compression: Option<Compression>) -> (SftpSession, Arc<AtomicU64>) {
+    rt.block_on(async {
+        let (client, server) = tokio::io::duplex(1024 * 1024);
+        let config = server::Config {
+            compression,
+            ..Default::default()
+        };
+        server::run_with_config(server, Sink, config).await;
+
+        let sent = Arc::new(AtomicU64::new(0));
+        let session = RawSftpSession::new(Link {
+            inner: client,
+            sent: sent.clone(),
+            delay: None,
+        });
+        session.set_compression(compression);
+
+        (SftpSession::with_raw_session(session).await.unwrap(), sent)
+    })
+}
+
+async fn upload(sftp: &SftpSession, data: &[u8]) {
+    let mut file = sftp.create("/logs").await.unwrap();
+    file.write_all(data).await.unwrap();
+    file.shutdown().await.unwrap();
+}
+
+fn compression_benchmark(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let data = logs();
+
+    let mut group = c.benchmark_group("compression");
+    group.throughput(Throughput::Bytes(FILE_SIZE as u64));
+
+    for (name, compression) in [
+        ("plain", None),
+        ("deflate 1", Some(Compression::deflate(1))),
+        ("deflate 6", Some(Compression::deflate(6))),
+    ] {
+        let (sftp, sent) = connect(&rt, compression);
+
+        rt.block_on(upload(&sftp, &data));
+        println!(
+            "{name}: {} bytes sent for {FILE_SIZE} bytes of logs",
+            sent.swap(0, Ordering::Relaxed)
+        );
+
+        group.bench_function(name, |b| b.to_async(&rt).iter(|| upload(&sftp, &data)));
+    }
+
+    group.finish();
+}
+
+criterion_group!(
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = compression_benchmark
+);
+criterion_main!(benches);
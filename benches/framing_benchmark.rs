@@ -0,0 +1,48 @@
+use bytes::{Buf, Bytes};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use russh_sftp::protocol::{Data, Packet, RequestId, Write};
+
+const CHUNK: usize = 32 * 1024;
+
+fn write_packet() -> Packet {
+    Packet::Write(Write {
+        id: RequestId(1),
+        handle: "handle".to_owned(),
+        offset: 0,
+        data: vec![0; CHUNK],
+    })
+}
+
+fn framing_benchmark(c: &mut Criterion) {
+    c.bench_function("encode write 32k", |b| {
+        b.iter(|| Bytes::try_from(black_box(write_packet())).unwrap())
+    });
+
+    c.bench_function("encode data 32k", |b| {
+        let data = Bytes::from(vec![0; CHUNK]);
+        b.iter(|| {
+            let packet = Packet::Data(Data::from_bytes(RequestId(1), black_box(data.clone())));
+            Bytes::try_from(packet).unwrap()
+        })
+    });
+
+    let frame = Bytes::try_from(write_packet()).unwrap();
+
+    c.bench_function("decode write 32k", |b| {
+        b.iter(|| {
+            let mut bytes = black_box(frame.clone());
+            bytes.advance(4);
+            Packet::try_from(&mut bytes).unwrap()
+        })
+    });
+
+    c.bench_function("decode truncated write", |b| {
+        b.iter(|| {
+            let mut bytes = black_box(frame.slice(4..CHUNK / 2));
+            Packet::try_from(&mut bytes).unwrap_err()
+        })
+    });
+}
+
+criterion_group!(benches, framing_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,107 @@
+use async_trait::async_trait;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use futures::future::join_all;
+use russh_sftp::{
+    client::RawSftpSession,
+    protocol::{Attrs, FileAttributes, RequestId, StatusCode, Version},
+    server,
+};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+use tokio::runtime::Runtime;
+
+const PIPELINE: usize = 64;
+
+/// Counts the allocations of the whole process
+struct Counting;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+struct Mock;
+
+#[async_trait]
+impl server::Handler for Mock {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn stat(&mut self, id: RequestId, _path: String) -> Result<Attrs, Self::Error> {
+        Ok(Attrs {
+            id,
+            attrs: FileAttributes::default(),
+        })
+    }
+}
+
+fn pipeline_benchmark(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let session = rt.block_on(async {
+        let (client, server) = tokio::io::duplex(1024 * 1024);
+        server::run(server, Mock).await;
+
+        let session = RawSftpSession::new(client);
+        session.init().await.unwrap();
+        Arc::new(session)
+    });
+
+    rt.block_on(async {
+        const ROUNDS: u64 = 100;
+
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        for _ in 0..ROUNDS {
+            join_all((0..PIPELINE).map(|_| session.stat("/"))).await;
+        }
+
+        // 21.2 with the oneshot replies, 22.2 with the single-slot mpsc channels
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+        println!(
+            "allocations per stat: {:.1}",
+            allocations as f64 / (ROUNDS * PIPELINE as u64) as f64
+        );
+    });
+
+    let mut group = c.benchmark_group("pipeline");
+    group.throughput(Throughput::Elements(PIPELINE as u64));
+    group.bench_function("64-way stat", |b| {
+        b.to_async(&rt).iter(|| {
+            let session = session.clone();
+            async move {
+                join_all((0..PIPELINE).map(|_| session.stat("/"))).await;
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, pipeline_benchmark);
+criterion_main!(benches);
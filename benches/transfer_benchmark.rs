@@ -0,0 +1,180 @@
+use async_trait::async_trait;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use futures::future::join_all;
+use russh_sftp::{
+    client::{SftpSession, TransferJob, TransferScheduler},
+    protocol::{Attrs, FileAttributes, Handle, OpenFlags, RequestId, Status, StatusCode, Version},
+    server,
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{runtime::Runtime, sync::Notify};
+
+const FILES: usize = 8;
+const FILE_SIZE: usize = 4 * 1024 * 1024;
+
+/// Accepts and discards all writes
+struct Sink;
+
+#[async_trait]
+impl server::Handler for Sink {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn open(
+        &mut self,
+        id: RequestId,
+        filename: String,
+        _pflags: OpenFlags,
+        _attrs: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        Ok(Handle {
+            id,
+            handle: filename,
+        })
+    }
+
+    async fn write(
+        &mut self,
+        id: RequestId,
+        _handle: String,
+        _offset: u64,
+        _data: Vec<u8>,
+    ) -> Result<Status, Self::Error> {
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+
+    async fn close(&mut self, id: RequestId, _handle: String) -> Result<Status, Self::Error> {
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+
+    async fn stat(&mut self, id: RequestId, _path: String) -> Result<Attrs, Self::Error> {
+        Ok(Attrs {
+            id,
+            attrs: FileAttributes::default(),
+        })
+    }
+}
+
+fn local_files() -> Vec<PathBuf> {
+    let dir = std::env::temp_dir().join("russh-sftp-transfer-benchmark");
+    std::fs::create_dir_all(&dir).unwrap();
+
+    (0..FILES)
+        .map(|i| {
+            let path = dir.join(format!("file_{i}"));
+            std::fs::write(&path, vec![i as u8; FILE_SIZE]).unwrap();
+            path
+        })
+        .collect()
+}
+
+async fn scheduler(sftp: &SftpSession, files: &[PathBuf]) {
+    let mut scheduler = TransferScheduler::new(sftp);
+    for (i, path) in files.iter().enumerate() {
+        scheduler.add(TransferJob::upload(path, format!("/file_{i}")));
+    }
+
+    for result in scheduler.run().await {
+        result.unwrap();
+    }
+}
+
+async fn spawn_per_file(sftp: &SftpSession, files: &[PathBuf]) {
+    let mut tasks = Vec::new();
+    for (i, path) in files.iter().enumerate() {
+        let mut local = tokio::fs::File::open(path).await.unwrap();
+        let mut remote = sftp.create(format!("/file_{i}")).await.unwrap();
+
+        tasks.push(tokio::spawn(async move {
+            tokio::io::copy(&mut local, &mut remote).await.unwrap();
+        }));
+    }
+
+    join_all(tasks).await;
+}
+
+/// Returns the longest round trip of a stat sent every millisecond during the transfer
+async fn worst_latency<F>(sftp: &SftpSession, transfer: F) -> Duration
+where
+    F: std::future::Future<Output = ()>,
+{
+    let done = Notify::new();
+    let probe = async {
+        let mut worst = Duration::ZERO;
+        loop {
+            tokio::select! {
+                _ = done.notified() => break worst,
+                _ = tokio::time::sleep(Duration::from_millis(1)) => {
+                    let started = Instant::now();
+                    sftp.metadata("/").await.unwrap();
+                    worst = worst.max(started.elapsed());
+                }
+            }
+        }
+    };
+
+    let transfer = async {
+        transfer.await;
+        done.notify_one();
+    };
+
+    tokio::join!(probe, transfer).0
+}
+
+fn connect(rt: &Runtime) -> Arc<SftpSession> {
+    rt.block_on(async {
+        let (client, server) = tokio::io::duplex(1024 * 1024);
+        server::run(server, Sink).await;
+
+        Arc::new(SftpSession::new(client).await.unwrap())
+    })
+}
+
+fn transfer_benchmark(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let sftp = connect(&rt);
+    let files = local_files();
+
+    rt.block_on(async {
+        let naive = worst_latency(&sftp, spawn_per_file(&sftp, &files)).await;
+        let scheduled = worst_latency(&sftp, scheduler(&sftp, &files)).await;
+        println!("worst stat latency: spawn per file {naive:?}, scheduler {scheduled:?}");
+    });
+
+    let mut group = c.benchmark_group("transfer");
+    group.throughput(Throughput::Bytes((FILES * FILE_SIZE) as u64));
+    group.bench_function("spawn per file", |b| {
+        b.to_async(&rt).iter(|| spawn_per_file(&sftp, &files))
+    });
+    group.bench_function("scheduler", |b| {
+        b.to_async(&rt).iter(|| scheduler(&sftp, &files))
+    });
+    group.finish();
+
+    if let Some(dir) = files.first().and_then(|path| path.parent()) {
+        let _ = std::fs::remove_dir_all(Path::new(dir));
+    }
+}
+
+criterion_group!(
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = transfer_benchmark
+);
+criterion_main!(benches);
@@ -57,12 +57,12 @@ async fn main() {
         let symlink = "./symlink";
 
         sftp.create_dir(path).await.unwrap();
-        sftp.symlink(path, symlink).await.unwrap();
+        sftp.create_symlink(symlink, path).await.unwrap();
 
         info!("dir info: {:?}", sftp.metadata(path).await.unwrap());
         info!(
             "symlink info: {:?}",
-            sftp.symlink_metadata(path).await.unwrap()
+            sftp.symlink_metadata(symlink).await.unwrap()
         );
 
         // scanning directory
@@ -0,0 +1,78 @@
+use async_trait::async_trait;
+use log::{info, LevelFilter};
+use russh::*;
+use russh_keys::*;
+use russh_sftp::client::{fs::ProgressEvent, SftpSession};
+use std::{sync::Arc, time::Duration};
+use tokio::io::AsyncWriteExt;
+
+struct Client;
+
+#[async_trait]
+impl client::Handler for Client {
+    type Error = anyhow::Error;
+
+    async fn check_server_key(
+        &mut self,
+        server_public_key: &ssh_key::PublicKey,
+    ) -> Result<bool, Self::Error> {
+        info!("check_server_key: {:?}", server_public_key);
+        Ok(true)
+    }
+}
+
+fn print_progress(event: ProgressEvent) {
+    info!(
+        "{:?}: {} bytes, offset {}",
+        event.direction, event.bytes_transferred, event.offset
+    );
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::builder()
+        .filter_level(LevelFilter::Debug)
+        .init();
+
+    let config = russh::client::Config::default();
+    let sh = Client {};
+    let mut session = russh::client::connect(Arc::new(config), ("localhost", 22), sh)
+        .await
+        .unwrap();
+    if session
+        .authenticate_password("root", "password")
+        .await
+        .unwrap()
+    {
+        let channel = session.channel_open_session().await.unwrap();
+        channel.request_subsystem(true, "sftp").await.unwrap();
+        let sftp = SftpSession::new(channel.into_stream()).await.unwrap();
+
+        let filename = "progress.bin";
+        let data = vec![0u8; 8 * 1024 * 1024];
+
+        // upload with progress
+        let mut file = sftp
+            .create(filename)
+            .await
+            .unwrap()
+            .with_progress(print_progress)
+            .interval(Duration::from_millis(250));
+
+        tokio::io::copy(&mut &data[..], &mut file).await.unwrap();
+        file.shutdown().await.unwrap();
+
+        // download with progress
+        let mut file = sftp
+            .open(filename)
+            .await
+            .unwrap()
+            .with_progress(print_progress);
+        let mut buffer = Vec::new();
+
+        tokio::io::copy(&mut file, &mut buffer).await.unwrap();
+        info!("downloaded {} bytes", file.bytes_read());
+
+        sftp.remove_file(filename).await.unwrap();
+    }
+}
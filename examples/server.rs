@@ -1,37 +1,45 @@
 use async_trait::async_trait;
-use log::{error, info, LevelFilter};
+use log::{info, LevelFilter};
 use russh::server::{Auth, Msg, Server as _, Session};
 use russh::{Channel, ChannelId};
 use russh_keys::ssh_key;
 use russh_keys::ssh_key::rand_core::OsRng;
-use russh_sftp::protocol::{File, FileAttributes, Handle, Name, Status, StatusCode, Version};
+use russh_sftp::extensions;
+use russh_sftp::protocol::{
+    File, FileAttributes, Handle, Name, RequestId, Status, StatusCode, Version,
+};
+use russh_sftp::server::{HandlerFactory, SessionHub, SessionInfo, StatusError};
 use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::Mutex;
 
 #[derive(Clone)]
-struct Server;
+struct Server {
+    hub: Arc<std::sync::Mutex<SessionHub<SftpFactory>>>,
+}
 
 impl russh::server::Server for Server {
     type Handler = SshSession;
 
-    fn new_client(&mut self, _: Option<SocketAddr>) -> Self::Handler {
-        SshSession::default()
+    fn new_client(&mut self, peer: Option<SocketAddr>) -> Self::Handler {
+        SshSession {
+            clients: Arc::new(Mutex::new(HashMap::new())),
+            hub: self.hub.clone(),
+            info: SessionInfo {
+                peer,
+                ..Default::default()
+            },
+        }
     }
 }
 
 struct SshSession {
     clients: Arc<Mutex<HashMap<ChannelId, Channel<Msg>>>>,
-}
-
-impl Default for SshSession {
-    fn default() -> Self {
-        Self {
-            clients: Arc::new(Mutex::new(HashMap::new())),
-        }
-    }
+    hub: Arc<std::sync::Mutex<SessionHub<SftpFactory>>>,
+    info: SessionInfo,
 }
 
 impl SshSession {
@@ -47,6 +55,7 @@ impl russh::server::Handler for SshSession {
 
     async fn auth_password(&mut self, user: &str, password: &str) -> Result<Auth, Self::Error> {
         info!("credentials: {}, {}", user, password);
+        self.info.user = Some(user.to_owned());
         Ok(Auth::Accept)
     }
 
@@ -56,6 +65,7 @@ impl russh::server::Handler for SshSession {
         public_key: &russh_keys::ssh_key::PublicKey,
     ) -> Result<Auth, Self::Error> {
         info!("credentials: {}, {:?}", user, public_key);
+        self.info.user = Some(user.to_owned());
         Ok(Auth::Accept)
     }
 
@@ -92,9 +102,10 @@ impl russh::server::Handler for SshSession {
 
         if name == "sftp" {
             let channel = self.get_channel(channel_id).await;
-            let sftp = SftpSession::default();
             session.channel_success(channel_id)?;
-            russh_sftp::server::run(channel.into_stream(), sftp).await;
+            // the hub makes a handler for the session and spawns it
+            let mut hub = self.hub.lock().unwrap();
+            hub.spawn(channel.into_stream(), self.info.clone());
         } else {
             session.channel_failure(channel_id)?;
         }
@@ -103,18 +114,38 @@ impl russh::server::Handler for SshSession {
     }
 }
 
+/// State shared by all the SFTP sessions
 #[derive(Default)]
+struct SftpFactory {
+    root_listings: Arc<AtomicU64>,
+}
+
+impl HandlerFactory for SftpFactory {
+    type Handler = SftpSession;
+
+    fn make_handler(&self, info: SessionInfo) -> SftpSession {
+        info!(
+            "sftp session {} of {:?} from {:?}",
+            info.id, info.user, info.peer
+        );
+        SftpSession {
+            root_dir_read_done: false,
+            root_listings: self.root_listings.clone(),
+        }
+    }
+}
+
 struct SftpSession {
-    version: Option<u32>,
     root_dir_read_done: bool,
+    root_listings: Arc<AtomicU64>,
 }
 
 #[async_trait]
 impl russh_sftp::server::Handler for SftpSession {
-    type Error = StatusCode;
+    type Error = StatusError;
 
     fn unimplemented(&self) -> Self::Error {
-        StatusCode::OpUnsupported
+        StatusCode::OpUnsupported.into()
     }
 
     async fn init(
@@ -122,17 +153,15 @@ impl russh_sftp::server::Handler for SftpSession {
         version: u32,
         extensions: HashMap<String, String>,
     ) -> Result<Version, Self::Error> {
-        if self.version.is_some() {
-            error!("duplicate SSH_FXP_VERSION packet");
-            return Err(StatusCode::ConnectionLost);
-        }
-
-        self.version = Some(version);
-        info!("version: {:?}, extensions: {:?}", self.version, extensions);
+        info!("version: {:?}, extensions: {:?}", version, extensions);
         Ok(Version::new())
     }
 
-    async fn close(&mut self, id: u32, _handle: String) -> Result<Status, Self::Error> {
+    fn supported_extensions(&self) -> &'static [&'static str] {
+        &[extensions::FSYNC]
+    }
+
+    async fn close(&mut self, id: RequestId, _handle: String) -> Result<Status, Self::Error> {
         Ok(Status {
             id,
             status_code: StatusCode::Ok,
@@ -141,16 +170,28 @@ impl russh_sftp::server::Handler for SftpSession {
         })
     }
 
-    async fn opendir(&mut self, id: u32, path: String) -> Result<Handle, Self::Error> {
+    async fn fsync(&mut self, id: RequestId, handle: String) -> Result<Status, Self::Error> {
+        info!("fsync: {}", handle);
+        Ok(Status {
+            id,
+            status_code: StatusCode::Ok,
+            error_message: "Ok".to_string(),
+            language_tag: "en-US".to_string(),
+        })
+    }
+
+    async fn opendir(&mut self, id: RequestId, path: String) -> Result<Handle, Self::Error> {
         info!("opendir: {}", path);
         self.root_dir_read_done = false;
         Ok(Handle { id, handle: path })
     }
 
-    async fn readdir(&mut self, id: u32, handle: String) -> Result<Name, Self::Error> {
+    async fn readdir(&mut self, id: RequestId, handle: String) -> Result<Name, Self::Error> {
         info!("readdir handle: {}", handle);
         if handle == "/" && !self.root_dir_read_done {
             self.root_dir_read_done = true;
+            let listings = self.root_listings.fetch_add(1, Ordering::Relaxed) + 1;
+            info!("root listed {listings} times by all the sessions");
             return Ok(Name {
                 id,
                 files: vec![
@@ -160,10 +201,19 @@ impl russh_sftp::server::Handler for SftpSession {
             });
         }
         // If all files have been sent to the client, respond with an EOF
-        Err(StatusCode::Eof)
+        Err(StatusCode::Eof.into())
     }
 
-    async fn realpath(&mut self, id: u32, path: String) -> Result<Name, Self::Error> {
+    async fn remove(&mut self, _id: RequestId, filename: String) -> Result<Status, Self::Error> {
+        info!("remove: {}", filename);
+        // The message is shown to the user by most clients
+        Err(StatusError::new(
+            StatusCode::PermissionDenied,
+            format!("{filename}: this server is read-only"),
+        ))
+    }
+
+    async fn realpath(&mut self, id: RequestId, path: String) -> Result<Name, Self::Error> {
         info!("realpath: {}", path);
         Ok(Name {
             id,
@@ -187,7 +237,12 @@ async fn main() {
         ..Default::default()
     };
 
-    let mut server = Server;
+    let mut server = Server {
+        hub: Arc::new(std::sync::Mutex::new(SessionHub::new(
+            SftpFactory::default(),
+            Default::default(),
+        ))),
+    };
 
     server
         .run_on_address(
@@ -0,0 +1,63 @@
+use async_trait::async_trait;
+use russh_sftp::{
+    client::SftpSession,
+    protocol::{Attrs, File, FileAttributes, Name, RequestId, StatusCode, Version},
+    server::{Config, Handler},
+};
+use std::collections::HashMap;
+use tracing_subscriber::fmt::format::FmtSpan;
+
+/// Answers stat and realpath for any path
+struct Server;
+
+#[async_trait]
+impl Handler for Server {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn stat(&mut self, id: RequestId, _path: String) -> Result<Attrs, Self::Error> {
+        Ok(Attrs {
+            id,
+            attrs: FileAttributes::empty(),
+        })
+    }
+
+    async fn realpath(&mut self, id: RequestId, path: String) -> Result<Name, Self::Error> {
+        Ok(Name {
+            id,
+            files: vec![File::dummy(format!("/home/{path}"))],
+        })
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    // one line per closed span, with the time spent in the request
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .with_span_events(FmtSpan::CLOSE)
+        .init();
+
+    // the paths are only recorded when asked for, on each side
+    let config = Config {
+        record_paths: true,
+        ..Default::default()
+    };
+    let (sftp, _server) = SftpSession::pair_with_config(Server, config).await.unwrap();
+    sftp.set_record_paths(true).await;
+
+    sftp.canonicalize("data").await.unwrap();
+    sftp.metadata("/home/data").await.unwrap();
+    let _ = sftp.read_dir("/home").await;
+}
@@ -0,0 +1,120 @@
+use crate::protocol::FileAttributes;
+
+/// Intent checked by [`SftpSession::access`](crate::client::SftpSession::access)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessCheck {
+    Read,
+    Write,
+    Execute,
+}
+
+impl AccessCheck {
+    fn bit(&self) -> u32 {
+        match self {
+            Self::Read => 0o4,
+            Self::Write => 0o2,
+            Self::Execute => 0o1,
+        }
+    }
+}
+
+/// Outcome of a permission pre-check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Allowed,
+    Denied,
+    /// The attributes or the [`Identity`] do not tell which permission bits
+    /// apply and they disagree, or the user may be root, the operation has to be attempted
+    Indeterminate,
+}
+
+impl Access {
+    /// Returns `true` if the access is known to be allowed
+    pub fn is_allowed(&self) -> bool {
+        matches!(self, Self::Allowed)
+    }
+
+    /// Returns `true` if the access is known to be denied
+    pub fn is_denied(&self) -> bool {
+        matches!(self, Self::Denied)
+    }
+
+    /// Returns the common outcome of the permission bits that may apply
+    fn unanimous<I: IntoIterator<Item = bool>>(bits: I) -> Self {
+        let mut bits = bits.into_iter();
+        let Some(first) = bits.next() else {
+            return Self::Indeterminate;
+        };
+
+        match bits.all(|bit| bit == first) {
+            true if first => Self::Allowed,
+            true => Self::Denied,
+            false => Self::Indeterminate,
+        }
+    }
+}
+
+/// User of the session on the server, see
+/// [`SftpSession::set_identity`](crate::client::SftpSession::set_identity).
+///
+/// The protocol does not tell the client who it is logged in as, so it has
+/// to be known from elsewhere, for example from `id` run over SSH
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    pub uid: u32,
+    /// Primary and supplementary groups
+    pub gids: Vec<u32>,
+}
+
+impl Identity {
+    pub fn new(uid: u32, gids: Vec<u32>) -> Self {
+        Self { uid, gids }
+    }
+}
+
+impl FileAttributes {
+    /// Evaluates the unix permission bits for `check` by `identity`.
+    ///
+    /// Returns [`Access::Indeterminate`] rather than guessing when the permissions
+    /// are missing, or when the owner, the group or the identity are unknown and
+    /// the bits of the classes that may apply disagree. Without an identity the
+    /// access is never [`Access::Denied`], as the user may be root. The root user
+    /// may read and write anything and execute whatever has an execute bit or is
+    /// a directory. ACLs and other server policies are not taken into account.
+    pub fn access(&self, check: AccessCheck, identity: Option<&Identity>) -> Access {
+        let Some(mode) = self.permissions else {
+            return Access::Indeterminate;
+        };
+
+        let allowed = |shift: u32| mode >> shift & check.bit() != 0;
+        let (owner, group, other) = (allowed(6), allowed(3), allowed(0));
+
+        let Some(identity) = identity else {
+            return match Access::unanimous([owner, group, other]) {
+                Access::Denied => Access::Indeterminate,
+                access => access,
+            };
+        };
+
+        if identity.uid == 0 {
+            return match check {
+                AccessCheck::Execute if !self.file_type().is_dir() => {
+                    Access::unanimous([owner || group || other])
+                }
+                _ => Access::Allowed,
+            };
+        }
+
+        let not_owner = match self.gid {
+            Some(gid) if identity.gids.contains(&gid) => vec![group],
+            Some(_) => vec![other],
+            None => vec![group, other],
+        };
+
+        match self.uid {
+            Some(uid) if uid == identity.uid => Access::unanimous([owner]),
+            Some(_) => Access::unanimous(not_owner),
+            None => Access::unanimous(not_owner.into_iter().chain([owner])),
+        }
+    }
+}
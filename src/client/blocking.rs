@@ -0,0 +1,314 @@
+//! Blocking façade over [`SftpSession`] for callers without an async runtime.
+//!
+//! Every call drives a current-thread tokio runtime owned by the session until
+//! the request completes, which also processes the stream in the meantime. The
+//! calls fail if made from within an async runtime, where blocking would stall
+//! the other tasks of its thread.
+//!
+//! ```no_run
+//! use russh_sftp::client::blocking::BlockingSftp;
+//! use std::io::Read;
+//! # fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! # async fn channel() -> std::io::Result<tokio::io::DuplexStream> { unimplemented!() }
+//! let sftp = BlockingSftp::connect(channel)?;
+//! let mut file = sftp.open("/etc/hostname")?;
+//! let mut hostname = String::new();
+//! file.read_to_string(&mut hostname)?;
+//! sftp.close()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    future::Future,
+    io::{self, Read, Seek, SeekFrom, Write},
+    sync::Arc,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt},
+    runtime::{Builder, Handle, Runtime},
+};
+
+use super::{
+    error::Error,
+    fs::{File, Metadata, ReadDir},
+    rawsession::SftpResult,
+    SftpSession,
+};
+use crate::protocol::OpenFlags;
+
+fn blocking_in_runtime() -> Error {
+    Error::UnexpectedBehavior("blocking SFTP call from within an async runtime".to_owned())
+}
+
+/// Runtime shared by a session and its files
+struct Shared {
+    runtime: Option<Runtime>,
+}
+
+impl Shared {
+    fn block_on<F: Future>(&self, future: F) -> SftpResult<F::Output> {
+        if Handle::try_current().is_ok() {
+            return Err(blocking_in_runtime());
+        }
+
+        let runtime = self
+            .runtime
+            .as_ref()
+            .expect("runtime is only taken on drop");
+        Ok(runtime.block_on(future))
+    }
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        // dropping a runtime waits for its blocking threads, which panics within another runtime
+        if let Some(runtime) = self.runtime.take() {
+            if Handle::try_current().is_ok() {
+                runtime.shutdown_background();
+            }
+        }
+    }
+}
+
+/// Blocking equivalent of [`SftpSession`].
+///
+/// Dropping the session closes it like [`BlockingSftp::close`] and shuts the runtime
+/// down. From within an async runtime the session is closed without waiting for the
+/// requests in flight instead.
+pub struct BlockingSftp {
+    session: Option<SftpSession>,
+    shared: Arc<Shared>,
+}
+
+impl BlockingSftp {
+    /// Creates a session on the stream returned by `stream_factory`, which runs on
+    /// a new current-thread runtime, for example to connect an SSH channel.
+    pub fn connect<F, Fut, S>(stream_factory: F) -> SftpResult<Self>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = io::Result<S>>,
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        if Handle::try_current().is_ok() {
+            return Err(blocking_in_runtime());
+        }
+
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        let shared = Arc::new(Shared {
+            runtime: Some(runtime),
+        });
+
+        let session =
+            shared.block_on(async { SftpSession::new(stream_factory().await?).await })??;
+        Ok(Self {
+            session: Some(session),
+            shared,
+        })
+    }
+
+    /// The underlying async session, for the operations without a blocking equivalent
+    /// which can be run with [`BlockingSftp::block_on`]
+    pub fn session(&self) -> &SftpSession {
+        self.session
+            .as_ref()
+            .expect("session is only taken on close")
+    }
+
+    /// Runs `future` on the runtime of the session until it completes
+    pub fn block_on<F: Future>(&self, future: F) -> SftpResult<F::Output> {
+        self.shared.block_on(future)
+    }
+
+    fn run<'a, F, T>(&'a self, request: impl FnOnce(&'a SftpSession) -> F) -> SftpResult<T>
+    where
+        F: Future<Output = SftpResult<T>> + 'a,
+    {
+        self.block_on(request(self.session()))?
+    }
+
+    /// Closes the session after waiting for the requests in flight, see [`SftpSession::close`]
+    pub fn close(mut self) -> SftpResult<()> {
+        let session = self.session.take().expect("session is only taken on close");
+        self.shared.block_on(session.close())?
+    }
+
+    /// Blocking equivalent of [`SftpSession::open`]
+    pub fn open<T: Into<String>>(&self, filename: T) -> SftpResult<BlockingFile> {
+        self.open_with_flags(filename, OpenFlags::READ)
+    }
+
+    /// Blocking equivalent of [`SftpSession::create`]
+    pub fn create<T: Into<String>>(&self, filename: T) -> SftpResult<BlockingFile> {
+        let file = self.run(|session| session.create(filename))?;
+        Ok(BlockingFile::new(file, self.shared.clone()))
+    }
+
+    /// Blocking equivalent of [`SftpSession::open_with_flags`]
+    pub fn open_with_flags<T: Into<String>>(
+        &self,
+        filename: T,
+        flags: OpenFlags,
+    ) -> SftpResult<BlockingFile> {
+        let file = self.run(|session| session.open_with_flags(filename, flags))?;
+        Ok(BlockingFile::new(file, self.shared.clone()))
+    }
+
+    /// Blocking equivalent of [`SftpSession::read`]
+    pub fn read<P: Into<String>>(&self, path: P) -> SftpResult<Vec<u8>> {
+        self.run(|session| session.read(path))
+    }
+
+    /// Blocking equivalent of [`SftpSession::write`]
+    pub fn write<P: Into<String>>(&self, path: P, data: &[u8]) -> SftpResult<()> {
+        self.run(|session| session.write(path, data))
+    }
+
+    /// Blocking equivalent of [`SftpSession::read_dir`]
+    pub fn read_dir<P: Into<String>>(&self, path: P) -> SftpResult<ReadDir> {
+        self.run(|session| session.read_dir(path))
+    }
+
+    /// Blocking equivalent of [`SftpSession::create_dir`]
+    pub fn create_dir<P: Into<String>>(&self, path: P) -> SftpResult<()> {
+        self.run(|session| session.create_dir(path))
+    }
+
+    /// Blocking equivalent of [`SftpSession::remove_dir`]
+    pub fn remove_dir<P: Into<String>>(&self, path: P) -> SftpResult<()> {
+        self.run(|session| session.remove_dir(path))
+    }
+
+    /// Blocking equivalent of [`SftpSession::remove_file`]
+    pub fn remove_file<P: Into<String>>(&self, path: P) -> SftpResult<()> {
+        self.run(|session| session.remove_file(path))
+    }
+
+    /// Blocking equivalent of [`SftpSession::rename`]
+    pub fn rename<O, N>(&self, oldpath: O, newpath: N) -> SftpResult<()>
+    where
+        O: Into<String>,
+        N: Into<String>,
+    {
+        self.run(|session| session.rename(oldpath, newpath))
+    }
+
+    /// Blocking equivalent of [`SftpSession::metadata`]
+    pub fn metadata<P: Into<String>>(&self, path: P) -> SftpResult<Metadata> {
+        self.run(|session| session.metadata(path))
+    }
+
+    /// Blocking equivalent of [`SftpSession::symlink_metadata`]
+    pub fn symlink_metadata<P: Into<String>>(&self, path: P) -> SftpResult<Metadata> {
+        self.run(|session| session.symlink_metadata(path))
+    }
+
+    /// Blocking equivalent of [`SftpSession::try_exists`]
+    pub fn try_exists<P: Into<String>>(&self, path: P) -> SftpResult<bool> {
+        self.run(|session| session.try_exists(path))
+    }
+
+    /// Blocking equivalent of [`SftpSession::canonicalize`]
+    pub fn canonicalize<P: Into<String>>(&self, path: P) -> SftpResult<String> {
+        self.run(|session| session.canonicalize(path))
+    }
+}
+
+impl Drop for BlockingSftp {
+    fn drop(&mut self) {
+        let Some(session) = self.session.take() else {
+            return;
+        };
+
+        if let Err(err) = self
+            .shared
+            .block_on(session.close())
+            .and_then(|result| result)
+        {
+            debug!("unable to close the session on drop: {}", err);
+            let _ = session.close_immediately();
+        }
+    }
+}
+
+/// Blocking equivalent of [`File`], implementing [`Read`], [`Write`] and [`Seek`].
+///
+/// Dropping the file waits for the writes in flight and closes it. The file keeps
+/// the runtime of its session alive, but not the session itself.
+pub struct BlockingFile {
+    file: Option<File>,
+    shared: Arc<Shared>,
+}
+
+impl BlockingFile {
+    fn new(file: File, shared: Arc<Shared>) -> Self {
+        Self {
+            file: Some(file),
+            shared,
+        }
+    }
+
+    fn file(&mut self) -> &mut File {
+        self.file.as_mut().expect("file is only taken on drop")
+    }
+
+    fn io<F: Future<Output = io::Result<T>>, T>(shared: &Shared, future: F) -> io::Result<T> {
+        shared.block_on(future).map_err(io::Error::from)?
+    }
+
+    /// The underlying async file
+    pub fn get_ref(&self) -> &File {
+        self.file.as_ref().expect("file is only taken on drop")
+    }
+
+    /// Blocking equivalent of [`File::metadata`]
+    pub fn metadata(&mut self) -> SftpResult<Metadata> {
+        let shared = self.shared.clone();
+        shared.block_on(self.file().metadata())?
+    }
+
+    /// Blocking equivalent of [`File::sync_all`]
+    pub fn sync_all(&mut self) -> SftpResult<()> {
+        let shared = self.shared.clone();
+        shared.block_on(self.file().sync_all())?
+    }
+}
+
+impl Read for BlockingFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let shared = self.shared.clone();
+        Self::io(&shared, self.file().read(buf))
+    }
+}
+
+impl Write for BlockingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let shared = self.shared.clone();
+        Self::io(&shared, self.file().write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let shared = self.shared.clone();
+        Self::io(&shared, self.file().flush())
+    }
+}
+
+impl Seek for BlockingFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let shared = self.shared.clone();
+        Self::io(&shared, self.file().seek(pos))
+    }
+}
+
+impl Drop for BlockingFile {
+    fn drop(&mut self) {
+        let Some(mut file) = self.file.take() else {
+            return;
+        };
+
+        // within another runtime the drop of the file closes it in the background
+        if let Ok(Err(err)) = self.shared.block_on(file.shutdown()) {
+            warn!("unable to close the file on drop: {}", err);
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use super::{rawsession::SftpResult, RawSftpSession, SftpSession};
+use crate::protocol::Version;
+
+/// Request sent right after SSH_FXP_VERSION, before any extension query,
+/// see [`SessionBuilder::init_probe`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InitProbe {
+    /// The extensions are queried first, if advertised
+    #[default]
+    None,
+    /// SSH_FXP_REALPATH of `.` is sent first, as the OpenSSH client does.
+    /// Some legacy devices stall unless it is the first request
+    RealpathDot,
+    /// Same as [`InitProbe::RealpathDot`] if the server advertises
+    /// none of the `@openssh.com` extensions
+    Auto,
+}
+
+impl InitProbe {
+    /// Returns `true` if SSH_FXP_REALPATH of `.` is sent first to the server
+    pub(crate) fn realpath_dot(&self, version: &Version) -> bool {
+        match self {
+            Self::None => false,
+            Self::RealpathDot => true,
+            Self::Auto => !version
+                .extensions
+                .keys()
+                .any(|name| name.ends_with("@openssh.com")),
+        }
+    }
+}
+
+/// Options of the initialization of a [`SftpSession`], see [`SftpSession::builder`]
+#[derive(Debug, Clone, Default)]
+pub struct SessionBuilder {
+    timeout: Option<u64>,
+    init_probe: InitProbe,
+}
+
+impl SessionBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum response time in seconds, including that of the initialization.
+    /// Default: 10 seconds
+    pub fn timeout(mut self, secs: u64) -> Self {
+        self.timeout = Some(secs);
+        self
+    }
+
+    /// Sets the request sent right after SSH_FXP_VERSION. The working directory
+    /// resolved by the probe is kept, see [`SftpSession::initial_dir`].
+    /// Default: [`InitProbe::None`]
+    pub fn init_probe(mut self, init_probe: InitProbe) -> Self {
+        self.init_probe = init_probe;
+        self
+    }
+
+    /// Creates the session by initializing the protocol and extensions over `stream`
+    pub async fn connect<S>(self, stream: S) -> SftpResult<SftpSession>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        self.with_raw_session(RawSftpSession::new(stream)).await
+    }
+
+    /// Same as [`SessionBuilder::connect`] on top of a raw session
+    /// that has not been initialized yet
+    pub async fn with_raw_session(self, session: RawSftpSession) -> SftpResult<SftpSession> {
+        if let Some(timeout) = self.timeout {
+            session.set_timeout(timeout).await;
+        }
+
+        SftpSession::initialize(session, self.init_probe).await
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+
+use super::error::Error;
+use crate::{de, ser};
+
+/// CRC-32 of a range of a transferred file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkChecksum {
+    pub offset: u64,
+    pub len: u64,
+    pub crc32: u32,
+}
+
+impl ChunkChecksum {
+    /// Hashes the bytes at `offset`. Only the bytes actually transferred
+    /// are hashed, a short read yields a shorter chunk
+    pub fn new(offset: u64, data: &[u8]) -> Self {
+        Self {
+            offset,
+            len: data.len() as u64,
+            crc32: crc32fast::hash(data),
+        }
+    }
+
+    pub fn end(&self) -> u64 {
+        self.offset + self.len
+    }
+
+    /// Returns `true` if `data` read at the offset of the chunk matches it
+    pub fn matches(&self, data: &[u8]) -> bool {
+        data.len() as u64 == self.len && crc32fast::hash(data) == self.crc32
+    }
+
+    fn hasher(&self) -> crc32fast::Hasher {
+        crc32fast::Hasher::new_with_initial_len(self.crc32, self.len)
+    }
+}
+
+/// Checksums of the chunks of a transfer, see [`TransferJob::checksums`](super::TransferJob::checksums).
+///
+/// Chunks may have any length and are kept ordered by offset. The manifest is
+/// independent of the chunk size of the transfer, so a transfer resumed with
+/// another chunk size extends it as is. It is stored with [`ChecksumManifest::to_bytes`]
+/// and loaded by another process with [`ChecksumManifest::from_bytes`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChecksumManifest {
+    chunks: Vec<ChunkChecksum>,
+}
+
+impl ChecksumManifest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Ok(de::from_bytes(&mut bytes.to_vec().into())?)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        Ok(ser::to_bytes(self)?.to_vec())
+    }
+
+    pub fn chunks(&self) -> &[ChunkChecksum] {
+        &self.chunks
+    }
+
+    /// Adds the chunk replacing those it overlaps
+    pub fn insert(&mut self, chunk: ChunkChecksum) {
+        if chunk.len == 0 {
+            return;
+        }
+
+        let start = self.chunks.partition_point(|c| c.end() <= chunk.offset);
+        let end = self.chunks.partition_point(|c| c.offset < chunk.end());
+        self.chunks.splice(start..end, [chunk]);
+    }
+
+    /// Returns the length covered by the chunks without a gap from the start of the file
+    pub fn verified_len(&self) -> u64 {
+        let mut end = 0;
+        for chunk in &self.chunks {
+            if chunk.offset != end {
+                break;
+            }
+            end = chunk.end();
+        }
+
+        end
+    }
+
+    /// Drops the chunks that end after `len`
+    pub fn truncate(&mut self, len: u64) {
+        self.chunks.retain(|chunk| chunk.end() <= len);
+    }
+
+    /// Merges adjacent chunks into chunks of up to `max_len` bytes without rehashing,
+    /// for example after the chunk size of the transfer grew
+    pub fn rechunk(&mut self, max_len: u64) {
+        let mut chunks: Vec<ChunkChecksum> = Vec::with_capacity(self.chunks.len());
+
+        for chunk in self.chunks.drain(..) {
+            match chunks.last_mut() {
+                Some(last) if last.end() == chunk.offset && last.len + chunk.len <= max_len => {
+                    let mut hasher = last.hasher();
+                    hasher.combine(&chunk.hasher());
+
+                    last.len += chunk.len;
+                    last.crc32 = hasher.finalize();
+                }
+                _ => chunks.push(chunk),
+            }
+        }
+
+        self.chunks = chunks;
+    }
+}
+
+/// Checksums of a running transfer
+#[derive(Debug)]
+pub(crate) struct Checksums {
+    pub manifest: ChecksumManifest,
+    /// Manifest of the previous attempts, which the received data has to match
+    pub reference: ChecksumManifest,
+    /// Checksums of the writes awaiting the acknowledgement, by offset
+    pending: HashMap<u64, ChunkChecksum>,
+    /// Checksums recorded since the last call to [`Checksums::take_recorded`]
+    recorded: Vec<ChunkChecksum>,
+}
+
+impl Checksums {
+    pub fn new(manifest: ChecksumManifest) -> Self {
+        Self {
+            reference: manifest.clone(),
+            manifest,
+            pending: HashMap::new(),
+            recorded: Vec::new(),
+        }
+    }
+
+    /// Records data received at `offset` after checking it against the chunks of
+    /// the reference within it. The chunks it only overlaps are checked once downloaded
+    pub fn received(&mut self, offset: u64, data: &[u8]) -> Result<(), Error> {
+        let end = offset + data.len() as u64;
+        let start = self.reference.chunks.partition_point(|c| c.offset < offset);
+        for chunk in self.reference.chunks[start..]
+            .iter()
+            .take_while(|c| c.end() <= end)
+        {
+            let at = (chunk.offset - offset) as usize;
+            if !chunk.matches(&data[at..at + chunk.len as usize]) {
+                return Err(mismatch(chunk));
+            }
+        }
+
+        self.record(ChunkChecksum::new(offset, data));
+        Ok(())
+    }
+
+    /// Hashes data about to be written, recorded once acknowledged
+    pub fn sent(&mut self, offset: u64, data: &[u8]) {
+        self.pending
+            .insert(offset, ChunkChecksum::new(offset, data));
+    }
+
+    pub fn written(&mut self, offset: u64) {
+        if let Some(chunk) = self.pending.remove(&offset) {
+            self.record(chunk);
+        }
+    }
+
+    pub fn take_recorded(&mut self) -> Vec<ChunkChecksum> {
+        std::mem::take(&mut self.recorded)
+    }
+
+    fn record(&mut self, chunk: ChunkChecksum) {
+        if chunk.len > 0 {
+            self.manifest.insert(chunk);
+            self.recorded.push(chunk);
+        }
+    }
+
+    /// Checks the last chunk of the manifest against the destination of a resumed
+    /// transfer, read with `read`, and returns the offset to resume from.
+    /// A mismatching chunk is dropped and transferred again
+    pub async fn resume<F, Fut>(&mut self, read: F) -> Result<u64, Error>
+    where
+        F: FnOnce(u64, u64) -> Fut,
+        Fut: std::future::Future<Output = Result<Vec<u8>, Error>>,
+    {
+        let verified = self.manifest.verified_len();
+        self.manifest.truncate(verified);
+
+        let Some(last) = self.manifest.chunks.last().copied() else {
+            return Ok(0);
+        };
+
+        if last.matches(&read(last.offset, last.len).await?) {
+            return Ok(verified);
+        }
+
+        warn!("{}, resuming before it", mismatch(&last));
+        self.manifest.truncate(last.offset);
+        Ok(last.offset)
+    }
+}
+
+/// Error returned when the data does not match the manifest
+pub(crate) fn mismatch(chunk: &ChunkChecksum) -> Error {
+    Error::UnexpectedBehavior(format!(
+        "checksum mismatch of {} bytes at offset {}",
+        chunk.len, chunk.offset
+    ))
+}
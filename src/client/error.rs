@@ -5,14 +5,18 @@ use tokio::sync::oneshot::error::RecvError as OneshotRecvError;
 use tokio::time::error::Elapsed as TimeElapsed;
 
 use crate::error;
-use crate::protocol::Status;
+use crate::protocol::{FileTime, Status};
 
 /// Enum for client errors
 #[derive(Debug, Clone, Error)]
 pub enum Error {
-    /// Contains an error status packet
-    #[error("{}: {}", .0.status_code, .0.error_message)]
-    Status(Status),
+    /// Contains an error status packet and the path or the handle of the failed
+    /// request, such as `/data/a.csv`, if it has one
+    #[error("{}", describe(.status, .target.as_deref()))]
+    Status {
+        status: Status,
+        target: Option<String>,
+    },
     /// Any errors related to I/O
     #[error("I/O: {0}")]
     IO(String),
@@ -22,6 +26,38 @@ pub enum Error {
     /// Occurs due to exceeding the limits set by the `limits@openssh.com` extension
     #[error("Limit exceeded: {0}")]
     Limited(String),
+    /// Occurs when the server does not support the extension required for the operation
+    #[error("Extension unsupported: {name}")]
+    ExtensionUnsupported { name: &'static str },
+    /// Occurs when the bytes received for a remote file differ from its size,
+    /// which did not change during the transfer
+    #[error("received {received} bytes of a file of {expected} bytes")]
+    LengthMismatch { expected: u64, received: u64 },
+    /// Occurs when the size of a remote file changed during the transfer, such as
+    /// a file that is still being written. `actual` is [`None`] if it is no longer reported
+    #[error("file size changed from {expected} bytes during the transfer")]
+    SizeChanged { expected: u64, actual: Option<u64> },
+    /// Occurs when a protocol version not implemented by the crate is selected,
+    /// see [`RawSftpSession::version_select`](super::RawSftpSession::version_select)
+    #[error("Version unsupported: {0}")]
+    VersionUnsupported(String),
+    /// Occurs when the server no longer recognizes the handle of an open file,
+    /// for example after the file was renamed or removed. The file should be reopened
+    #[error("Stale handle: {}", .0.error_message)]
+    StaleHandle(Status),
+    /// Occurs when the server sent a packet with a length out of bounds or an unknown
+    /// type. The stream is closed, as the packets that follow cannot be delimited
+    #[error("Framing: {0}")]
+    Framing(String),
+    /// Occurs when a pattern passed to [`SftpSession::glob`](super::SftpSession::glob)
+    /// cannot be parsed
+    #[error("Invalid pattern: {0}")]
+    InvalidPattern(String),
+    /// Occurs when a request sets a time after 2106, which version 3 cannot send,
+    /// with [`RawSftpSession::set_strict_times`](super::RawSftpSession::set_strict_times)
+    /// and always with [`SftpSession::set_times`](super::SftpSession::set_times)
+    #[error("{0:?} does not fit into the 32-bit time of the protocol")]
+    TimeOverflow(FileTime),
     /// Occurs when an unexpected packet is sent
     #[error("Unexpected packet")]
     UnexpectedPacket,
@@ -30,9 +66,36 @@ pub enum Error {
     UnexpectedBehavior(String),
 }
 
+impl Error {
+    /// Sets the target of a status error, so that the error of a request on a
+    /// handle reports the path it was opened with
+    pub(crate) fn with_target<T: Into<String>>(self, target: T) -> Self {
+        match self {
+            Self::Status { status, .. } => Self::Status {
+                status,
+                target: Some(target.into()),
+            },
+            err => err,
+        }
+    }
+}
+
+fn describe(status: &Status, target: Option<&str>) -> String {
+    match target {
+        Some(target) => format!(
+            "{}: {} ({target})",
+            status.status_code, status.error_message
+        ),
+        None => format!("{}: {}", status.status_code, status.error_message),
+    }
+}
+
 impl From<Status> for Error {
     fn from(status: Status) -> Self {
-        Self::Status(status)
+        Self::Status {
+            status,
+            target: None,
+        }
     }
 }
 
@@ -42,6 +105,34 @@ impl From<io::Error> for Error {
     }
 }
 
+/// Kind of the [`io::Error`] of each error, the kind of the code for the
+/// status errors, see [`StatusCode`](crate::protocol::StatusCode)
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        let kind = match &err {
+            Error::Status { status, .. } | Error::StaleHandle(status) => status.status_code.into(),
+            Error::IO(_) => io::ErrorKind::Other,
+            Error::Timeout => io::ErrorKind::TimedOut,
+            Error::Limited(_) | Error::InvalidPattern(_) | Error::TimeOverflow(_) => {
+                io::ErrorKind::InvalidInput
+            }
+            Error::ExtensionUnsupported { .. } | Error::VersionUnsupported(_) => {
+                io::ErrorKind::Unsupported
+            }
+            Error::LengthMismatch { expected, received } if received < expected => {
+                io::ErrorKind::UnexpectedEof
+            }
+            Error::LengthMismatch { .. }
+            | Error::SizeChanged { .. }
+            | Error::Framing(_)
+            | Error::UnexpectedPacket
+            | Error::UnexpectedBehavior(_) => io::ErrorKind::InvalidData,
+        };
+
+        io::Error::new(kind, err)
+    }
+}
+
 impl<T> From<MpscSendError<T>> for Error {
     fn from(err: MpscSendError<T>) -> Self {
         Self::UnexpectedBehavior(format!("SendError: {}", err))
@@ -62,6 +153,9 @@ impl From<TimeElapsed> for Error {
 
 impl From<error::Error> for Error {
     fn from(error: error::Error) -> Self {
-        Self::UnexpectedBehavior(error.to_string())
+        match error {
+            error::Error::Framing(msg) => Self::Framing(msg),
+            error => Self::UnexpectedBehavior(error.to_string()),
+        }
     }
 }
@@ -0,0 +1,60 @@
+use std::collections::BTreeMap;
+
+use crate::client::error::Error;
+
+/// Writes of a [`File`](super::File) acknowledged by the server.
+///
+/// Servers may reply to pipelined writes out of order and report an error on a
+/// later request than the one that failed. Only the writes acknowledged without
+/// a gap before the first error are therefore considered to have landed
+#[derive(Debug, Default)]
+pub(crate) struct Acknowledged {
+    /// End of the writes acknowledged without a gap since the first write
+    contiguous: Option<u64>,
+    /// Ends of the writes acknowledged after a gap, by offset
+    ahead: BTreeMap<u64, u64>,
+    poisoned: Option<Error>,
+}
+
+impl Acknowledged {
+    /// Records a write about to be sent
+    pub fn sent(&mut self, offset: u64) {
+        self.contiguous.get_or_insert(offset);
+    }
+
+    /// Records a successful write. Ignored once a write failed
+    pub fn acknowledged(&mut self, offset: u64, len: u64) {
+        let Some(end) = self.contiguous.as_mut().filter(|_| self.poisoned.is_none()) else {
+            return;
+        };
+
+        if offset > *end {
+            let ahead = self.ahead.entry(offset).or_default();
+            *ahead = (*ahead).max(offset + len);
+            return;
+        }
+
+        *end = (*end).max(offset + len);
+        while let Some(entry) = self.ahead.first_entry() {
+            if *entry.key() > *end {
+                break;
+            }
+
+            *end = (*end).max(entry.remove());
+        }
+    }
+
+    /// Records a failed write, the first error is kept
+    pub fn poison(&mut self, err: Error) {
+        self.poisoned.get_or_insert(err);
+        self.ahead.clear();
+    }
+
+    pub fn poisoned(&self) -> Option<Error> {
+        self.poisoned.clone()
+    }
+
+    pub fn size(&self) -> u64 {
+        self.contiguous.unwrap_or(0)
+    }
+}
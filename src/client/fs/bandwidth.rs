@@ -0,0 +1,138 @@
+use std::{
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+use tokio::{
+    select,
+    sync::Notify,
+    time::{self, Instant},
+};
+
+use super::Direction;
+
+/// Share of a second of quota accumulated while idle, so that a transfer
+/// starts at once without sending a full second of quota in a burst
+const BURST: Duration = Duration::from_millis(100);
+
+/// Token bucket of a direction, counting payload bytes
+#[derive(Debug)]
+struct Bucket {
+    rate: Option<u64>,
+    /// Bytes that can be sent, negative once a chunk exceeded them
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Bucket {
+    fn burst(rate: u64) -> f64 {
+        rate as f64 * BURST.as_secs_f64()
+    }
+
+    fn refill(&mut self, now: Instant) {
+        if let Some(rate) = self.rate {
+            let earned = now.duration_since(self.refilled).as_secs_f64() * rate as f64;
+            self.tokens = (self.tokens + earned).min(Self::burst(rate));
+        }
+
+        self.refilled = now;
+    }
+}
+
+/// Bandwidth limits of the files of a [`SftpSession`](crate::client::SftpSession),
+/// see [`SftpSession::set_bandwidth_limit`](crate::client::SftpSession::set_bandwidth_limit).
+///
+/// A chunk is sent as soon as the bucket is not in debt and charged in full, so the
+/// next chunk waits for the time the previous one takes at the limit. Waiters are
+/// woken when the limit changes to recompute their wait
+#[derive(Debug)]
+pub(crate) struct Bandwidth {
+    read: Mutex<Bucket>,
+    write: Mutex<Bucket>,
+    changed: Notify,
+}
+
+impl Default for Bandwidth {
+    fn default() -> Self {
+        let bucket = || {
+            Mutex::new(Bucket {
+                rate: None,
+                tokens: 0.0,
+                refilled: Instant::now(),
+            })
+        };
+
+        Self {
+            read: bucket(),
+            write: bucket(),
+            changed: Notify::new(),
+        }
+    }
+}
+
+impl Bandwidth {
+    fn bucket(&self, direction: Direction) -> MutexGuard<'_, Bucket> {
+        match direction {
+            Direction::Read => &self.read,
+            Direction::Write => &self.write,
+        }
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn limit(&self, direction: Direction) -> Option<u64> {
+        self.bucket(direction).rate
+    }
+
+    pub fn set_limit(&self, direction: Direction, bytes_per_sec: Option<u64>) {
+        let bytes_per_sec = bytes_per_sec.map(|rate| rate.max(1));
+        {
+            let mut bucket = self.bucket(direction);
+            bucket.refill(Instant::now());
+
+            // the debt of the chunks already sent is kept and repaid at the new rate
+            bucket.tokens = match (bucket.rate, bytes_per_sec) {
+                (_, None) => 0.0,
+                (None, Some(rate)) => Bucket::burst(rate),
+                (Some(_), Some(rate)) => bucket.tokens.min(Bucket::burst(rate)),
+            };
+            bucket.rate = bytes_per_sec;
+        }
+
+        self.changed.notify_waiters();
+    }
+
+    /// Waits until `len` bytes can be sent in `direction` and charges them
+    pub async fn acquire(&self, direction: Direction, len: u64) {
+        loop {
+            // created before the check so that a change in between is not missed
+            let changed = self.changed.notified();
+            let wait = {
+                let mut bucket = self.bucket(direction);
+                bucket.refill(Instant::now());
+
+                match bucket.rate {
+                    None => return,
+                    Some(_) if bucket.tokens >= 0.0 => {
+                        bucket.tokens -= len as f64;
+                        return;
+                    }
+                    Some(rate) => Duration::from_secs_f64(-bucket.tokens / rate as f64),
+                }
+            };
+
+            select! {
+                _ = time::sleep(wait) => {}
+                _ = changed => {}
+            }
+        }
+    }
+
+    /// Returns the bytes charged for a chunk that were not transferred,
+    /// such as the rest of a short read
+    pub fn refund(&self, direction: Direction, len: u64) {
+        let mut bucket = self.bucket(direction);
+        if let Some(rate) = bucket.rate {
+            bucket.tokens = (bucket.tokens + len as f64).min(Bucket::burst(rate));
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use tokio::task::JoinSet;
+
+use super::file::{join_error, File};
+use crate::client::rawsession::SftpResult;
+
+/// Default size of the pages of [`CachedFile`]
+pub const DEFAULT_PAGE_SIZE: u32 = 32 * 1024;
+/// Default number of pages kept by [`CachedFile`]
+pub const DEFAULT_PAGE_CAPACITY: usize = 256;
+
+/// Counters of the pages of a [`CachedFile`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PageCacheStats {
+    /// Number of pages read without a round trip
+    pub hits: u64,
+    /// Number of pages that were requested from the server
+    pub misses: u64,
+    /// Number of pages currently stored
+    pub len: usize,
+    /// Maximum number of pages before the least recently used is evicted
+    pub capacity: usize,
+}
+
+struct Page {
+    /// Shorter than the page size only for the page at the end of file
+    data: Vec<u8>,
+    used: u64,
+}
+
+/// Random access over a [`File`] with a cache of page-aligned blocks, for many
+/// small reads scattered across a large file, such as lookups in an index.
+///
+/// A read is served from the cached pages and the missing ones are requested whole
+/// and in parallel, so that reads within the same pages cost a single round trip.
+/// Writes are sent to the server as is and drop the pages they affect. Changes made
+/// to the file by others are only seen after [`CachedFile::invalidate`]
+pub struct CachedFile {
+    file: File,
+    page_size: u64,
+    capacity: usize,
+    pages: HashMap<u64, Page>,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl CachedFile {
+    /// Caches [`DEFAULT_PAGE_CAPACITY`] pages of [`DEFAULT_PAGE_SIZE`] bytes
+    pub fn new(file: File) -> Self {
+        Self::with_pages(file, DEFAULT_PAGE_SIZE, DEFAULT_PAGE_CAPACITY)
+    }
+
+    /// Caches up to `capacity` pages of `page_size` bytes, at least one byte
+    pub fn with_pages(file: File, page_size: u32, capacity: usize) -> Self {
+        Self {
+            file,
+            page_size: page_size.max(1) as u64,
+            capacity,
+            pages: HashMap::with_capacity(capacity),
+            tick: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn get_ref(&self) -> &File {
+        &self.file
+    }
+
+    /// Returns the file, dropping the cached pages
+    pub fn into_inner(self) -> File {
+        self.file
+    }
+
+    pub fn stats(&self) -> PageCacheStats {
+        PageCacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            len: self.pages.len(),
+            capacity: self.capacity,
+        }
+    }
+
+    /// Drops all the cached pages
+    pub fn invalidate(&mut self) {
+        self.pages.clear();
+    }
+
+    /// Drops the cached pages containing bytes from `offset` to `offset + len`
+    pub fn invalidate_range(&mut self, offset: u64, len: u64) {
+        if len == 0 {
+            return;
+        }
+
+        let (first, last) = (offset / self.page_size, (offset + len - 1) / self.page_size);
+        self.pages
+            .retain(|index, _| *index < first || *index > last);
+    }
+
+    /// Reads into `buf` from `offset` and returns the number of bytes read,
+    /// lower than the length of `buf` only at the end of file
+    pub async fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> SftpResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let first = offset / self.page_size;
+        let last = (offset + buf.len() as u64 - 1) / self.page_size;
+
+        self.tick += 1;
+        let mut missing = Vec::new();
+        for index in first..=last {
+            match self.pages.get_mut(&index) {
+                Some(page) => {
+                    page.used = self.tick;
+                    self.hits += 1;
+                }
+                None => missing.push(index),
+            }
+        }
+
+        self.misses += missing.len() as u64;
+        let fetched = self.fetch(&missing).await?;
+
+        let mut read = 0;
+        for index in first..=last {
+            let page = match fetched.get(&index) {
+                Some(data) => data,
+                None => &self.pages[&index].data,
+            };
+
+            let start = (offset + read as u64 - index * self.page_size) as usize;
+            if start >= page.len() {
+                break;
+            }
+
+            let len = (page.len() - start).min(buf.len() - read);
+            buf[read..read + len].copy_from_slice(&page[start..start + len]);
+            read += len;
+
+            if (page.len() as u64) < self.page_size {
+                break;
+            }
+        }
+
+        for (index, data) in fetched {
+            self.insert(index, data);
+        }
+
+        Ok(read)
+    }
+
+    /// Writes `data` at `offset` and waits for the acknowledgement of the server.
+    ///
+    /// The pages containing the written bytes are dropped, as well as the page at
+    /// the end of file if the file grows
+    pub async fn write_at(&mut self, offset: u64, data: &[u8]) -> SftpResult<()> {
+        let page_size = self.page_size;
+        self.invalidate_range(offset, data.len() as u64);
+        self.pages
+            .retain(|_, page| page.data.len() as u64 == page_size);
+
+        self.file.write_at(offset, data.to_vec()).await?;
+        Ok(())
+    }
+
+    /// Requests the pages whole, several requests at once if the pages are
+    /// larger than the reads allowed by the server
+    async fn fetch(&self, indexes: &[u64]) -> SftpResult<HashMap<u64, Vec<u8>>> {
+        let piece = (self.file.max_read_len() as u64).min(self.page_size);
+        let mut pages = HashMap::with_capacity(indexes.len());
+        let mut requests = JoinSet::new();
+
+        for &index in indexes {
+            let start = index * self.page_size;
+            let mut offset = start;
+            while offset < start + self.page_size {
+                let len = piece.min(start + self.page_size - offset);
+                requests.spawn(self.file.read_at(offset, len));
+                offset += len;
+            }
+
+            pages.insert(index, vec![0; self.page_size as usize]);
+        }
+
+        let mut end = u64::MAX;
+        while let Some(result) = requests.join_next().await {
+            let (offset, len, data) = result.map_err(join_error)??;
+            let Some(data) = data else {
+                end = end.min(offset);
+                continue;
+            };
+
+            let index = offset / self.page_size;
+            let start = (offset - index * self.page_size) as usize;
+            let received = data.len().min(len as usize);
+            if let Some(page) = pages.get_mut(&index) {
+                page[start..start + received].copy_from_slice(&data[..received]);
+            }
+
+            // short read, the rest of the piece is requested again
+            let received = received as u64;
+            if received < len && offset + received < end {
+                requests.spawn(self.file.read_at(offset + received, len - received));
+            }
+        }
+
+        for (index, page) in pages.iter_mut() {
+            let start = index * self.page_size;
+            if end < start + self.page_size {
+                page.truncate(end.saturating_sub(start) as usize);
+            }
+        }
+
+        Ok(pages)
+    }
+
+    fn insert(&mut self, index: u64, data: Vec<u8>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if !self.pages.contains_key(&index) && self.pages.len() >= self.capacity {
+            let oldest = self
+                .pages
+                .iter()
+                .min_by_key(|(_, page)| page.used)
+                .map(|(index, _)| *index);
+
+            if let Some(oldest) = oldest {
+                self.pages.remove(&oldest);
+            }
+        }
+
+        self.pages.insert(
+            index,
+            Page {
+                data,
+                used: self.tick,
+            },
+        );
+    }
+}
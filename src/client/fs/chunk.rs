@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+/// Smallest chunk used by the adaptive mode
+pub const MIN_ADAPTIVE_CHUNK: usize = 4096;
+/// Chunk the adaptive mode starts with
+pub const INITIAL_ADAPTIVE_CHUNK: usize = 32768;
+/// Growth of the chunk after a request without a stall
+const INCREASE: usize = 16384;
+/// Latency per byte relative to the best observed one that is considered a stall
+const STALL_FACTOR: u32 = 2;
+
+/// Chooses the size of read and write requests of a [`File`](super::File)
+#[derive(Debug, Clone)]
+pub(crate) enum ChunkSize {
+    /// Always the largest size allowed
+    Max,
+    /// Fixed size set by the user
+    Pinned(usize),
+    /// Adjusted by the latency of the requests
+    Adaptive(Aimd),
+}
+
+impl ChunkSize {
+    /// Size of the next request limited by `max`
+    pub fn get(&self, max: usize) -> usize {
+        match self {
+            Self::Max => max,
+            Self::Pinned(size) => (*size).clamp(1, max),
+            Self::Adaptive(aimd) => aimd.size().min(max),
+        }
+    }
+
+    /// Records a completed request of `len` bytes
+    pub fn record(&mut self, len: usize, latency: Duration) {
+        if let Self::Adaptive(aimd) = self {
+            aimd.record(len, latency);
+        }
+    }
+}
+
+/// Additive increase and multiplicative decrease of the chunk size.
+///
+/// The latency per byte is compared with the best one observed. A request that
+/// took considerably longer halves the chunk, otherwise it grows by a fixed step.
+/// The latency per byte falls with larger chunks while the round trip dominates,
+/// so the chunk grows on fast links and shrinks when requests stall.
+///
+/// Used by [`File`](super::File) in the adaptive mode, it only depends on the
+/// samples it is given
+#[derive(Debug, Clone)]
+pub struct Aimd {
+    size: usize,
+    max: usize,
+    best: Option<Duration>,
+}
+
+impl Aimd {
+    /// Starts at [`INITIAL_ADAPTIVE_CHUNK`] and never exceeds `max`,
+    /// nor goes below [`MIN_ADAPTIVE_CHUNK`]
+    pub fn new(max: usize) -> Self {
+        let max = max.max(MIN_ADAPTIVE_CHUNK);
+
+        Self {
+            size: INITIAL_ADAPTIVE_CHUNK.min(max),
+            max,
+            best: None,
+        }
+    }
+
+    /// Size of the next request
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Records a completed request of `len` bytes and adjusts the size
+    pub fn record(&mut self, len: usize, latency: Duration) {
+        // short requests at the end of file say nothing about the link
+        if len < MIN_ADAPTIVE_CHUNK {
+            return;
+        }
+
+        let per_byte = latency / len as u32;
+        let best = *self.best.get_or_insert(per_byte);
+
+        if per_byte > best * STALL_FACTOR {
+            self.size = (self.size / 2).max(MIN_ADAPTIVE_CHUNK);
+        } else {
+            self.best = Some(best.min(per_byte));
+            self.size = (self.size + INCREASE).min(self.max);
+        }
+    }
+}
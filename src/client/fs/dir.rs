@@ -1,12 +1,27 @@
-use std::collections::VecDeque;
+use futures_core::Stream;
+use std::{
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tokio::{runtime::Handle, task::JoinHandle};
 
 use super::Metadata;
-use crate::protocol::FileType;
+use crate::{
+    client::{
+        error::Error, rawsession::SftpResult, Access, AccessCheck, Identity, RawSftpSession,
+        RemoteFlavor,
+    },
+    protocol::{FileType, Name, StatusCode},
+};
 
 /// Entries returned by the [`ReadDir`] iterator.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DirEntry {
     file: String,
+    path: String,
     metadata: Metadata,
 }
 
@@ -16,20 +31,118 @@ impl DirEntry {
         self.file.to_owned()
     }
 
+    /// Returns the full path to the file that this entry points at,
+    /// joined onto the listed directory in the form accepted by the server.
+    pub fn path(&self) -> String {
+        self.path.to_owned()
+    }
+
     /// Returns the file type for the file that this entry points at.
     pub fn file_type(&self) -> FileType {
         self.metadata.file_type()
     }
 
+    /// Returns `true` if this entry is a directory
+    pub fn is_dir(&self) -> bool {
+        self.file_type().is_dir()
+    }
+
+    /// Returns `true` if this entry is a regular file
+    pub fn is_file(&self) -> bool {
+        self.file_type().is_file()
+    }
+
     /// Returns the metadata for the file that this entry points at.
     pub fn metadata(&self) -> Metadata {
         self.metadata.to_owned()
     }
+
+    /// Returns `true` if the file is read-only, see [`Metadata::is_readonly`].
+    pub fn is_readonly(&self) -> bool {
+        self.metadata.is_readonly()
+    }
 }
 
 /// Iterator over the entries in a remote directory.
+///
+/// The `.` and `..` entries are skipped unless [`ReadDir::include_dot_entries`] is set.
 pub struct ReadDir {
-    pub(crate) entries: VecDeque<(String, Metadata)>,
+    dir: String,
+    flavor: RemoteFlavor,
+    entries: VecDeque<(String, Metadata)>,
+    self_entry: Option<DirEntry>,
+    parent_entry: Option<DirEntry>,
+    include_dots: bool,
+    identity: Option<Identity>,
+}
+
+impl ReadDir {
+    pub(crate) fn new(
+        dir: String,
+        flavor: RemoteFlavor,
+        entries: VecDeque<(String, Metadata)>,
+    ) -> Self {
+        let find = |name: &str| {
+            entries
+                .iter()
+                .find(|(file, _)| file == name)
+                .map(|(file, metadata)| entry(&dir, flavor, file.to_owned(), metadata.to_owned()))
+        };
+
+        Self {
+            self_entry: find("."),
+            parent_entry: find(".."),
+            dir,
+            flavor,
+            entries,
+            include_dots: false,
+            identity: None,
+        }
+    }
+
+    pub(crate) fn with_identity(mut self, identity: Option<Identity>) -> Self {
+        self.identity = identity;
+        self
+    }
+
+    /// Returns the `.` entry if the server included it in the listing
+    pub fn self_entry(&self) -> Option<&DirEntry> {
+        self.self_entry.as_ref()
+    }
+
+    /// Returns the `..` entry if the server included it in the listing
+    pub fn parent_entry(&self) -> Option<&DirEntry> {
+        self.parent_entry.as_ref()
+    }
+
+    /// Sets whether the `.` and `..` entries are yielded in the order of the listing.
+    /// Default: `false`
+    pub fn include_dot_entries(mut self, include: bool) -> Self {
+        self.include_dots = include;
+        self
+    }
+
+    /// Sorts the entries by the bytes of their names, so that the order does not depend
+    /// on the server or the locale. The order of entries with the same name is kept
+    pub fn sorted_by_name(mut self) -> Self {
+        self.entries
+            .make_contiguous()
+            .sort_by(|(a, _), (b, _)| a.as_bytes().cmp(b.as_bytes()));
+        self
+    }
+
+    /// Yields only the entries whose metadata allows `check` by the
+    /// [`SftpSession::identity`](crate::client::SftpSession::identity).
+    /// Entries with [`Access::Indeterminate`] are skipped
+    pub fn filter_access(self, check: AccessCheck) -> impl Iterator<Item = DirEntry> {
+        let identity = self.identity.clone();
+        self.filter(move |entry| entry.metadata.access(check, identity.as_ref()) == Access::Allowed)
+    }
+
+    /// Yields only the entries known to be writable, see [`ReadDir::filter_access`]
+    pub fn filter_writable(self) -> impl Iterator<Item = DirEntry> {
+        self.filter_access(AccessCheck::Write)
+    }
 }
 
 impl Iterator for ReadDir {
@@ -38,11 +151,153 @@ impl Iterator for ReadDir {
     fn next(&mut self) -> Option<Self::Item> {
         match self.entries.pop_front() {
             None => None,
-            Some(entry) if entry.0 == "." || entry.0 == ".." => self.next(),
-            Some(entry) => Some(DirEntry {
-                file: entry.0,
-                metadata: entry.1,
-            }),
+            Some(entry) if !self.include_dots && (entry.0 == "." || entry.0 == "..") => self.next(),
+            Some((file, metadata)) => Some(entry(&self.dir, self.flavor, file, metadata)),
         }
     }
 }
+
+/// Stream over the entries in a remote directory, returned by
+/// [`SftpSession::read_dir_stream`](crate::client::SftpSession::read_dir_stream).
+///
+/// Unlike [`ReadDir`], the entries are yielded as the batches of the server arrive.
+/// While a batch is consumed, the next one is already requested, so that a slow consumer
+/// and a slow server wait for each other only once. At most one request is outstanding
+/// and none is sent after the end of the listing or an error, which ends the stream.
+///
+/// The `.` and `..` entries are skipped. The handle is closed on drop, after the
+/// outstanding reply if any
+pub struct ReadDirStream {
+    session: Arc<RawSftpSession>,
+    handle: String,
+    dir: String,
+    flavor: RemoteFlavor,
+    entries: VecDeque<(String, Metadata)>,
+    pending: Option<JoinHandle<SftpResult<Name>>>,
+    finished: bool,
+    prefetch: bool,
+}
+
+impl ReadDirStream {
+    pub(crate) fn new(
+        session: Arc<RawSftpSession>,
+        handle: String,
+        dir: String,
+        flavor: RemoteFlavor,
+    ) -> Self {
+        Self {
+            session,
+            handle,
+            dir,
+            flavor,
+            entries: VecDeque::new(),
+            pending: None,
+            finished: false,
+            prefetch: true,
+        }
+    }
+
+    /// Sets whether the next batch is requested while the current one is consumed,
+    /// otherwise it is requested once the current one is exhausted. Default: `true`
+    pub fn prefetch(mut self, prefetch: bool) -> Self {
+        self.prefetch = prefetch;
+        self
+    }
+
+    fn request(&mut self) {
+        let session = self.session.clone();
+        let handle = self.handle.to_owned();
+        self.pending = Some(tokio::spawn(async move { session.readdir(handle).await }));
+    }
+}
+
+impl Stream for ReadDirStream {
+    type Item = SftpResult<DirEntry>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+
+        loop {
+            if let Some(pending) = &mut this.pending {
+                if let Poll::Ready(result) = Pin::new(pending).poll(cx) {
+                    this.pending = None;
+
+                    let result = result.unwrap_or_else(|err| {
+                        Err(Error::UnexpectedBehavior(format!(
+                            "readdir task failed: {err}"
+                        )))
+                    });
+
+                    match result {
+                        Ok(name) => this
+                            .entries
+                            .extend(name.files.into_iter().map(|f| (f.filename, f.attrs))),
+                        Err(Error::Status { status, .. })
+                            if status.status_code == StatusCode::Eof =>
+                        {
+                            this.finished = true
+                        }
+                        Err(err) => {
+                            this.finished = true;
+                            return Poll::Ready(Some(Err(err.with_target(this.dir.as_str()))));
+                        }
+                    }
+                }
+            }
+
+            if this.pending.is_none()
+                && !this.finished
+                && (this.prefetch || this.entries.is_empty())
+            {
+                this.request();
+                if this.entries.is_empty() {
+                    continue;
+                }
+            }
+
+            match this.entries.pop_front() {
+                Some((file, _)) if file == "." || file == ".." => continue,
+                Some((file, metadata)) => {
+                    return Poll::Ready(Some(Ok(entry(&this.dir, this.flavor, file, metadata))))
+                }
+                None if this.pending.is_some() => return Poll::Pending,
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+impl Drop for ReadDirStream {
+    fn drop(&mut self) {
+        let dir_handle = std::mem::take(&mut self.handle);
+        let pending = self.pending.take();
+
+        match Handle::try_current() {
+            Ok(handle) => {
+                let session = self.session.clone();
+
+                handle.spawn(async move {
+                    // the handle is in use until the outstanding request is answered
+                    if let Some(pending) = pending {
+                        let _ = pending.await;
+                    }
+
+                    let _ = session.close(dir_handle).await;
+                });
+            }
+            Err(_) => {
+                if let Err(err) = self.session.close_detached(dir_handle) {
+                    warn!("unable to close the directory on drop: {}", err);
+                }
+            }
+        }
+    }
+}
+
+fn entry(dir: &str, flavor: RemoteFlavor, file: String, metadata: Metadata) -> DirEntry {
+    DirEntry {
+        path: flavor.join(dir, &file),
+        file,
+        metadata,
+    }
+}
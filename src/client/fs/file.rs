@@ -1,25 +1,128 @@
 use std::{
+    collections::VecDeque,
     future::Future,
     io::{self, SeekFrom},
     pin::Pin,
-    sync::Arc,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
     task::{ready, Context, Poll},
+    time::{Instant, SystemTime},
 };
+#[cfg(feature = "std-fs")]
+use tokio::{fs, io::AsyncSeekExt};
 use tokio::{
-    io::{AsyncRead, AsyncSeek, AsyncWrite, ReadBuf},
+    io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncWrite, AsyncWriteExt, ReadBuf},
     runtime::Handle,
+    task::{JoinError, JoinSet},
 };
 
-use super::Metadata;
+use super::{
+    ack::Acknowledged,
+    bandwidth::Bandwidth,
+    chunk::{Aimd, ChunkSize},
+    Direction, ExpectedSize, Metadata, ProgressEvent, ProgressFile,
+};
 use crate::{
     client::{error::Error, rawsession::SftpResult, session::Extensions, RawSftpSession},
-    protocol::StatusCode,
+    extensions::{self, Capability, LockFlags},
+    protocol::{StatusCode, Write},
 };
 
-type StateFn<T> = Option<Pin<Box<dyn Future<Output = io::Result<T>> + Send + Sync + 'static>>>;
+type BoxFuture<T> = Pin<Box<dyn Future<Output = io::Result<T>> + Send + 'static>>;
+type StateFn<T> = Option<StateFuture<T>>;
+
+/// Pending operation of the file. It is only polled through `&mut`, so the
+/// [`Mutex`] is never locked and makes [`File`] `Sync` without requiring
+/// the captures of the future to be `Sync`
+struct StateFuture<T>(Mutex<BoxFuture<T>>);
+
+impl<T> StateFuture<T> {
+    fn new<F>(future: F) -> Self
+    where
+        F: Future<Output = io::Result<T>> + Send + 'static,
+    {
+        Self(Mutex::new(Box::pin(future)))
+    }
+}
+
+impl<T> Future for StateFuture<T> {
+    type Output = io::Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.get_mut()
+            .0
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_mut()
+            .poll(cx)
+    }
+}
+
+/// Servers may forget the handle of a renamed or removed file and reply with NoSuchFile
+fn stale_handle(err: Error) -> Error {
+    match err {
+        Error::Status { status, .. } if status.status_code == StatusCode::NoSuchFile => {
+            Error::StaleHandle(status)
+        }
+        err => err,
+    }
+}
+
+fn io_error(err: Error) -> io::Error {
+    stale_handle(err).into()
+}
+
+pub(crate) fn join_error(err: JoinError) -> Error {
+    Error::UnexpectedBehavior(format!("copy request failed: {}", err))
+}
+
+/// Reads `len` bytes at `offset`, [`None`] at the end of file.
+///
+/// Data without any byte is taken as the end of file as well, unless the session
+/// is strict, since a new request at the same offset would get the same reply.
+/// The bytes not received are returned to the bandwidth limit
+async fn read_chunk(
+    session: &RawSftpSession,
+    chunk: &Mutex<ChunkSize>,
+    bandwidth: &Bandwidth,
+    handle: String,
+    offset: u64,
+    len: u64,
+) -> SftpResult<Option<Vec<u8>>> {
+    bandwidth.acquire(Direction::Read, len).await;
+
+    let started = Instant::now();
+    let result = session.read(handle, offset, len as u32).await;
+    let received = result.as_ref().map_or(0, |data| data.data.len() as u64);
+    bandwidth.refund(Direction::Read, len.saturating_sub(received));
+
+    match result {
+        Ok(data) if !data.data.is_empty() => {
+            lock(chunk).record(data.data.len(), started.elapsed());
+            Ok(Some(data.data))
+        }
+        Ok(_) if session.strict_empty_data().await => Err(Error::UnexpectedBehavior(format!(
+            "received data without any byte at offset {offset} instead of the end of file"
+        ))),
+        Ok(_) => Ok(None),
+        Err(Error::Status { status, .. }) if status.status_code == StatusCode::Eof => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<File>();
+};
 
 const MAX_READ_LENGTH: u64 = 261120;
 const MAX_WRITE_LENGTH: u64 = 261120;
+/// Requests kept in flight by the copies of [`File`] from and to local files
+/// and by the streaming copies of the session
+const COPY_REQUESTS: usize = 16;
 
 struct FileState {
     f_read: StateFn<Option<Vec<u8>>>,
@@ -32,8 +135,19 @@ struct FileState {
 /// Provides high-level methods for interaction with a remote file.
 ///
 /// Handle does not necessarily need to be closed because of the [`Drop`] mechanism.
+/// Outside of a runtime the close request is sent without waiting for the response.
 /// Also implement [`AsyncSeek`] and other async i/o implementations.
 ///
+/// Some servers invalidate the handle when the file is renamed or removed. Operations on
+/// such a file fail with [`Error::StaleHandle`] or [`io::ErrorKind::NotFound`], the file can
+/// be reopened at the previous [`position`](File::position) with
+/// [`SftpSession::open_at_offset`](crate::client::SftpSession::open_at_offset).
+///
+/// A file opened with [`OpenFlags::APPEND`](crate::protocol::OpenFlags::APPEND) writes at
+/// the end of the file regardless of the position, as the servers ignore the flag for
+/// the explicit offsets of SSH_FXP_WRITE. The end is read when the file is opened and
+/// advanced by the writes, so concurrent appenders to the same file still race as with [`std`].
+///
 /// # Weakness
 /// Using [`SeekFrom::End`] is costly and time-consuming because we need to
 /// request the actual file size from the remote server.
@@ -41,9 +155,17 @@ pub struct File {
     session: Arc<RawSftpSession>,
     handle: String,
     state: FileState,
-    pos: u64,
+    pub(crate) pos: u64,
+    /// End of the file written to in append mode
+    pub(crate) append: Option<u64>,
+    /// Received data that did not fit into the buffer of the last read
+    spill: Vec<u8>,
     closed: bool,
     extensions: Arc<Extensions>,
+    chunk: Arc<Mutex<ChunkSize>>,
+    adaptive: bool,
+    acks: Arc<Mutex<Acknowledged>>,
+    bandwidth: Arc<Bandwidth>,
 }
 
 impl File {
@@ -51,8 +173,10 @@ impl File {
         session: Arc<RawSftpSession>,
         handle: String,
         extensions: Arc<Extensions>,
+        adaptive: bool,
+        bandwidth: Arc<Bandwidth>,
     ) -> Self {
-        Self {
+        let file = Self {
             session,
             handle,
             state: FileState {
@@ -63,34 +187,507 @@ impl File {
                 f_shutdown: None,
             },
             pos: 0,
+            append: None,
+            spill: Vec::new(),
             closed: false,
             extensions,
-        }
+            chunk: Arc::new(Mutex::new(ChunkSize::Max)),
+            adaptive,
+            acks: Arc::new(Mutex::new(Acknowledged::default())),
+            bandwidth,
+        };
+
+        file.unpin_chunk_size();
+        file
+    }
+
+    /// Wraps a handle opened with [`RawSftpSession::open`] or obtained from
+    /// [`File::leak`], for example by another component of the application.
+    ///
+    /// The file takes the ownership of the handle and closes it on drop. The handle
+    /// must belong to `session` and must not be closed or wrapped elsewhere while the
+    /// file is in use. `capabilities` are the extensions advertised by the server that
+    /// the file may use, such as [`Capability::Fsync`]. Read and write lengths follow
+    /// the limits set with [`RawSftpSession::set_limits`], the bandwidth is not limited
+    pub fn from_raw_parts(
+        session: Arc<RawSftpSession>,
+        handle: String,
+        capabilities: &[Capability],
+    ) -> Self {
+        let extensions = Extensions {
+            hardlink: capabilities.contains(&Capability::Hardlink),
+            fsync: capabilities.contains(&Capability::Fsync),
+            statvfs: capabilities.contains(&Capability::Statvfs),
+            block: capabilities.contains(&Capability::Block),
+            home_directory: capabilities.contains(&Capability::HomeDirectory),
+            limits: Some(session.configured_limits()),
+            versions: Vec::new(),
+            advertised: Default::default(),
+            rejected: Default::default(),
+        };
+
+        Self::new(
+            session,
+            handle,
+            Arc::new(extensions),
+            false,
+            Arc::new(Bandwidth::default()),
+        )
+    }
+
+    pub(crate) fn max_read_len(&self) -> usize {
+        self.extensions
+            .limits
+            .as_ref()
+            .and_then(|l| l.read_len)
+            .unwrap_or(MAX_READ_LENGTH) as usize
+    }
+
+    /// Returns the write limit, lowered so that the framed packet with
+    /// the handle of the file stays within the packet limit
+    fn max_write_len(&self) -> usize {
+        let limits = self.extensions.limits.as_ref();
+        let write_len = limits.and_then(|l| l.write_len).unwrap_or(MAX_WRITE_LENGTH);
+        let packet_data_len = limits
+            .and_then(|l| l.packet_len)
+            .map(|p| p.saturating_sub(Write::overhead(self.handle.len()) as u64));
+
+        write_len.min(packet_data_len.unwrap_or(u64::MAX)).max(1) as usize
+    }
+
+    fn chunk_len(&self, max: usize) -> usize {
+        lock(&self.chunk).get(max)
+    }
+
+    /// Returns the size of the next read or write request.
+    /// It changes over time if the adaptive mode is enabled with
+    /// [`SftpSession::set_adaptive_chunks`](crate::client::SftpSession::set_adaptive_chunks)
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_len(self.max_read_len().min(self.max_write_len()))
+    }
+
+    /// Uses requests of `size` bytes, limited by the server, until
+    /// [`File::unpin_chunk_size`] is called. Disables the adaptive mode
+    pub fn pin_chunk_size(&self, size: usize) {
+        *lock(&self.chunk) = ChunkSize::Pinned(size);
+    }
+
+    /// Restores the chunk size chosen by the session
+    pub fn unpin_chunk_size(&self) {
+        *lock(&self.chunk) = match self.adaptive {
+            true => ChunkSize::Adaptive(Aimd::new(self.max_read_len().min(self.max_write_len()))),
+            false => ChunkSize::Max,
+        };
+    }
+
+    /// Returns the remote handle of the file.
+    ///
+    /// The handle stays owned by the file, requests on it such as vendor extensions
+    /// can be sent through [`File::session`] but it must not be closed there
+    pub fn handle(&self) -> &str {
+        &self.handle
+    }
+
+    /// Returns the session the handle belongs to
+    pub fn session(&self) -> &Arc<RawSftpSession> {
+        &self.session
+    }
+
+    /// Returns the current position in the file
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Returns the end of the writes acknowledged by the server without a gap since
+    /// the first write, or 0 before any write. A resumed upload can continue from there.
+    ///
+    /// Servers may acknowledge pipelined writes out of order and report an error on
+    /// a later write than the one that failed. After a failed write the value is
+    /// no longer advanced and further writes and flushes return the error
+    pub fn acknowledged_size(&self) -> u64 {
+        lock(&self.acks).size()
     }
 
     /// Queries metadata about the remote file.
     pub async fn metadata(&self) -> SftpResult<Metadata> {
-        Ok(self.session.fstat(self.handle.as_str()).await?.attrs)
+        Ok(self
+            .session
+            .fstat(self.handle.as_str())
+            .await
+            .map_err(stale_handle)?
+            .attrs)
     }
 
     /// Sets metadata for a remote file.
-    pub async fn set_metadata(&self, metadata: Metadata) -> SftpResult<()> {
+    ///
+    /// If only one of `uid`/`gid` or `atime`/`mtime` is set,
+    /// the other one is filled in with the current value.
+    pub async fn set_metadata(&self, mut metadata: Metadata) -> SftpResult<()> {
+        // the protocol sends uid/gid and atime/mtime in pairs
+        if metadata.is_half_set() {
+            metadata.merge_halves(&self.metadata().await?);
+        }
+
         self.session
             .fsetstat(self.handle.as_str(), metadata)
             .await
             .map(|_| ())
+            .map_err(stale_handle)
+    }
+
+    /// Sets the access and modification times of the file, see
+    /// [`SftpSession::set_times`](crate::client::SftpSession::set_times)
+    pub async fn set_times(
+        &self,
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    ) -> SftpResult<()> {
+        if accessed.is_none() && modified.is_none() {
+            return Ok(());
+        }
+
+        let mut times = super::times(accessed, modified)?;
+        if times.is_half_set() {
+            times.merge_halves(&self.metadata().await?);
+        }
+
+        super::check_times(&times)?;
+        self.session
+            .fsetstat(self.handle.as_str(), times)
+            .await
+            .map(|_| ())
+            .map_err(stale_handle)
     }
 
     /// Attempts to sync all data.
     ///
     /// If the server does not support `fsync@openssh.com` sending the request will
-    /// be omitted, but will still pseudo-successfully
+    /// be omitted, but will still pseudo-successfully. The same applies once the
+    /// server rejected the request as unsupported although it advertised it
     pub async fn sync_all(&self) -> SftpResult<()> {
-        if !self.extensions.fsync {
+        if let Some(err) = lock(&self.acks).poisoned() {
+            return Err(err);
+        }
+
+        if !self.extensions.supports(Capability::Fsync) {
             return Ok(());
         }
 
-        self.session.fsync(self.handle.as_str()).await.map(|_| ())
+        let result = self.session.fsync(self.handle.as_str()).await;
+        match self.extensions.check(Capability::Fsync, result) {
+            Ok(_) | Err(Error::ExtensionUnsupported { .. }) => Ok(()),
+            Err(err) => Err(stale_handle(err)),
+        }
+    }
+
+    /// Locks the byte-range of the file starting at `offset` using
+    /// `block@russh-sftp.rs`. A `len` of zero locks up to the end of the file.
+    ///
+    /// Fails with [`Error::ExtensionUnsupported`] if the server does not advertise it
+    /// or rejected it
+    pub async fn lock_range(&self, offset: u64, len: u64, flags: LockFlags) -> SftpResult<()> {
+        if !self.extensions.supports(Capability::Block) {
+            return Err(Error::ExtensionUnsupported {
+                name: extensions::BLOCK,
+            });
+        }
+
+        let result = self
+            .session
+            .block(self.handle.as_str(), offset, len, flags)
+            .await;
+        self.extensions.check(Capability::Block, result).map(|_| ())
+    }
+
+    /// Unlocks the byte-range previously locked with [`File::lock_range`]
+    pub async fn unlock_range(&self, offset: u64, len: u64) -> SftpResult<()> {
+        if !self.extensions.supports(Capability::Block) {
+            return Err(Error::ExtensionUnsupported {
+                name: extensions::UNBLOCK,
+            });
+        }
+
+        let result = self
+            .session
+            .unblock(self.handle.as_str(), offset, len)
+            .await;
+        self.extensions.check(Capability::Block, result).map(|_| ())
+    }
+
+    /// Releases the ownership of the remote handle without closing it.
+    ///
+    /// Returns the handle and the session it belongs to. Closing the handle,
+    /// for example with [`RawSftpSession::close`], is up to the caller
+    pub fn detach(mut self) -> (String, Arc<RawSftpSession>) {
+        self.closed = true;
+        (std::mem::take(&mut self.handle), self.session.clone())
+    }
+
+    /// Releases the ownership of the remote handle without closing it and returns it.
+    ///
+    /// The handle remains open until it is closed with [`RawSftpSession::close`] or
+    /// wrapped again with [`File::from_raw_parts`]. Same as [`File::detach`]
+    /// without the session
+    pub fn leak(self) -> String {
+        self.detach().0
+    }
+
+    /// Wraps the file to report the progress of reads and writes to `callback`.
+    /// See [`ProgressFile`] for details.
+    pub fn with_progress<F>(self, callback: F) -> ProgressFile<F>
+    where
+        F: FnMut(ProgressEvent),
+    {
+        ProgressFile::new(self, callback)
+    }
+
+    /// Copies the whole remote file into `local` and returns the number of bytes copied.
+    ///
+    /// The local file is resized to the remote size beforehand and written from its start,
+    /// regardless of the positions of both files. Chunks are requested up to 16 ahead of
+    /// the local writes, as with [`SftpSession::read_to_writer`](crate::client::SftpSession::read_to_writer),
+    /// which is much faster than [`tokio::io::copy`].
+    ///
+    /// The size is checked again once copied, a file that changed in the meantime fails
+    /// with [`Error::SizeChanged`] and a copy of another length with [`Error::LengthMismatch`]
+    #[cfg(feature = "std-fs")]
+    pub async fn copy_to_local(&mut self, local: &mut fs::File) -> SftpResult<u64> {
+        self.copy_to_local_with_progress(local, |_| {}).await
+    }
+
+    /// Same as [`File::copy_to_local`], reporting every received chunk to `callback`
+    #[cfg(feature = "std-fs")]
+    pub async fn copy_to_local_with_progress<F>(
+        &mut self,
+        local: &mut fs::File,
+        callback: F,
+    ) -> SftpResult<u64>
+    where
+        F: FnMut(ProgressEvent),
+    {
+        let size = self.metadata().await?.size;
+        if let Some(size) = size {
+            local.set_len(size).await?;
+        }
+
+        local.seek(SeekFrom::Start(0)).await?;
+        self.read_ahead(size, local, callback).await
+    }
+
+    /// Copies the whole `local` file into the remote file and returns the number of bytes copied.
+    ///
+    /// The local file is read from its start and written at the same offsets of the remote
+    /// file, regardless of the positions of both files. The remote file is not truncated,
+    /// it should be opened with [`OpenFlags::TRUNCATE`](crate::protocol::OpenFlags::TRUNCATE).
+    /// A file opened with [`OpenFlags::APPEND`](crate::protocol::OpenFlags::APPEND) gets the
+    /// local file appended instead.
+    /// Up to 16 write requests are kept in flight
+    #[cfg(feature = "std-fs")]
+    pub async fn copy_from_local(&mut self, local: &mut fs::File) -> SftpResult<u64> {
+        self.copy_from_local_with_progress(local, |_| {}).await
+    }
+
+    /// Same as [`File::copy_from_local`], reporting every acknowledged chunk to `callback`
+    #[cfg(feature = "std-fs")]
+    pub async fn copy_from_local_with_progress<F>(
+        &mut self,
+        local: &mut fs::File,
+        callback: F,
+    ) -> SftpResult<u64>
+    where
+        F: FnMut(ProgressEvent),
+    {
+        local.seek(SeekFrom::Start(0)).await?;
+        self.write_behind(local, callback).await
+    }
+
+    /// Copies the whole remote file into `writer` in order and returns the number of bytes
+    /// copied. The size is checked as with [`File::copy_to_local`]
+    pub(crate) async fn copy_to_writer<W>(&mut self, writer: W) -> SftpResult<u64>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let size = self.metadata().await?.size;
+        self.read_ahead(size, writer, |_| {}).await
+    }
+
+    /// Copies `reader` to its end into the remote file from its start and returns
+    /// the number of bytes copied
+    pub(crate) async fn copy_from_reader<R>(&mut self, reader: R) -> SftpResult<u64>
+    where
+        R: AsyncRead + Unpin,
+    {
+        self.write_behind(reader, |_| {}).await
+    }
+
+    /// Reads the remote file of `size` bytes from its start into `writer`, reporting
+    /// every written chunk to `callback`. At most [`COPY_REQUESTS`] chunks are requested
+    /// ahead of the writer
+    async fn read_ahead<W, F>(
+        &mut self,
+        size: Option<u64>,
+        mut writer: W,
+        mut callback: F,
+    ) -> SftpResult<u64>
+    where
+        W: AsyncWrite + Unpin,
+        F: FnMut(ProgressEvent),
+    {
+        let mut expected = ExpectedSize::new(size);
+        let end = size.unwrap_or(u64::MAX);
+        let mut next = 0;
+        let mut copied = 0;
+        let mut requests = VecDeque::new();
+
+        loop {
+            while requests.len() < COPY_REQUESTS && next < end {
+                let len = (self.chunk_len(self.max_read_len()) as u64).min(end - next);
+                requests.push_back(tokio::spawn(self.read_at(next, len)));
+                next += len;
+            }
+
+            let Some(request) = requests.pop_front() else {
+                break;
+            };
+
+            let (mut offset, mut len, mut data) = request.await.map_err(join_error)??;
+            while let Some(chunk) = &mut data {
+                expected.clamp(offset, len, chunk);
+                writer.write_all(chunk).await?;
+
+                let received = chunk.len() as u64;
+                copied += received;
+                callback(ProgressEvent {
+                    bytes_transferred: copied,
+                    direction: Direction::Read,
+                    offset: offset + received,
+                });
+
+                if received >= len {
+                    break;
+                }
+
+                // short read, the rest of the chunk is written before the next one
+                (offset, len, data) = self.read_at(offset + received, len - received).await?;
+            }
+
+            // the file may have been shrunk since the fstat
+            if data.is_none() {
+                break;
+            }
+        }
+
+        writer.flush().await?;
+        expected.verify(self, copied).await?;
+        Ok(copied)
+    }
+
+    /// Writes `reader` to its end into the remote file from its start, or after its end
+    /// in append mode, reporting every acknowledged chunk to `callback`.
+    /// At most [`COPY_REQUESTS`] chunks are in flight
+    async fn write_behind<R, F>(&mut self, mut reader: R, mut callback: F) -> SftpResult<u64>
+    where
+        R: AsyncRead + Unpin,
+        F: FnMut(ProgressEvent),
+    {
+        let start = self.append.unwrap_or(0);
+        let mut next = 0;
+        let mut copied = 0;
+        let mut eof = false;
+        let mut requests = JoinSet::new();
+
+        loop {
+            while requests.len() < COPY_REQUESTS && !eof {
+                let len = self.chunk_len(self.max_write_len());
+                let mut data = Vec::with_capacity(len);
+                (&mut reader)
+                    .take(len as u64)
+                    .read_to_end(&mut data)
+                    .await?;
+
+                eof = data.len() < len;
+                if data.is_empty() {
+                    break;
+                }
+
+                let sent = data.len() as u64;
+                requests.spawn(self.write_at(next, data));
+                next += sent;
+            }
+
+            let Some(result) = requests.join_next().await else {
+                break;
+            };
+
+            let (offset, len) = result.map_err(join_error)??;
+            copied += len;
+            callback(ProgressEvent {
+                bytes_transferred: copied,
+                direction: Direction::Write,
+                offset: start + offset + len,
+            });
+        }
+
+        if let Some(end) = self.append.as_mut() {
+            *end += copied;
+            self.pos = *end;
+        }
+
+        Ok(copied)
+    }
+
+    /// Reads `len` bytes at `offset`, [`None`] at the end of file
+    pub(crate) fn read_at(
+        &self,
+        offset: u64,
+        len: u64,
+    ) -> impl Future<Output = SftpResult<(u64, u64, Option<Vec<u8>>)>> + Send + 'static {
+        let session = self.session.clone();
+        let chunk = self.chunk.clone();
+        let bandwidth = self.bandwidth.clone();
+        let file_handle = self.handle.clone();
+
+        async move {
+            match read_chunk(&session, &chunk, &bandwidth, file_handle, offset, len).await {
+                Ok(data) => Ok((offset, len, data)),
+                Err(err) => Err(stale_handle(err)),
+            }
+        }
+    }
+
+    /// Writes `data` at `offset`, which is relative to the end of the file in append
+    /// mode, and returns `offset` as given with the length written
+    pub(crate) fn write_at(
+        &self,
+        offset: u64,
+        data: Vec<u8>,
+    ) -> impl Future<Output = SftpResult<(u64, u64)>> + Send + 'static {
+        let session = self.session.clone();
+        let chunk = self.chunk.clone();
+        let acks = self.acks.clone();
+        let bandwidth = self.bandwidth.clone();
+        let file_handle = self.handle.clone();
+        let at = self.append.map_or(offset, |end| end + offset);
+        lock(&acks).sent(at);
+
+        async move {
+            if let Some(err) = lock(&acks).poisoned() {
+                return Err(err);
+            }
+
+            bandwidth.acquire(Direction::Write, data.len() as u64).await;
+
+            let (started, len) = (Instant::now(), data.len());
+            if let Err(err) = session.write(file_handle, at, data).await {
+                let err = stale_handle(err);
+                lock(&acks).poison(err.clone());
+                return Err(err);
+            }
+
+            lock(&chunk).record(len, started.elapsed());
+            lock(&acks).acknowledged(at, len as u64);
+            Ok((offset, len as u64))
+        }
     }
 }
 
@@ -100,13 +697,21 @@ impl Drop for File {
             return;
         }
 
-        if let Ok(handle) = Handle::try_current() {
-            let session = self.session.clone();
-            let file_handle = self.handle.clone();
+        let file_handle = std::mem::take(&mut self.handle);
 
-            handle.spawn(async move {
-                let _ = session.close(file_handle).await;
-            });
+        match Handle::try_current() {
+            Ok(handle) => {
+                let session = self.session.clone();
+
+                handle.spawn(async move {
+                    let _ = session.close(file_handle).await;
+                });
+            }
+            Err(_) => {
+                if let Err(err) = self.session.close_detached(file_handle) {
+                    warn!("unable to close the file on drop: {}", err);
+                }
+            }
         }
     }
 }
@@ -117,16 +722,21 @@ impl AsyncRead for File {
         cx: &mut Context<'_>,
         buf: &mut ReadBuf<'_>,
     ) -> Poll<io::Result<()>> {
+        if !self.spill.is_empty() {
+            let len = self.spill.len().min(buf.remaining());
+            buf.put_slice(&self.spill[..len]);
+            self.spill.drain(..len);
+            self.pos += len as u64;
+            return Poll::Ready(Ok(()));
+        }
+
         let poll = Pin::new(match self.state.f_read.as_mut() {
             Some(f) => f,
             None => {
                 let session = self.session.clone();
-                let max_read_len = self
-                    .extensions
-                    .limits
-                    .as_ref()
-                    .and_then(|l| l.read_len)
-                    .unwrap_or(MAX_READ_LENGTH) as usize;
+                let chunk = self.chunk.clone();
+                let bandwidth = self.bandwidth.clone();
+                let max_read_len = self.chunk_len(self.max_read_len());
 
                 let file_handle = self.handle.clone();
 
@@ -137,17 +747,20 @@ impl AsyncRead for File {
                     buf.remaining()
                 };
 
-                self.state.f_read.get_or_insert(Box::pin(async move {
-                    let result = session.read(file_handle, offset, len as u32).await;
-
-                    match result {
-                        Ok(data) => Ok(Some(data.data)),
-                        Err(Error::Status(status)) if status.status_code == StatusCode::Eof => {
-                            Ok(None)
-                        }
-                        Err(e) => Err(io::Error::new(io::ErrorKind::Other, e.to_string())),
-                    }
-                }))
+                self.state
+                    .f_read
+                    .get_or_insert(StateFuture::new(async move {
+                        read_chunk(
+                            &session,
+                            &chunk,
+                            &bandwidth,
+                            file_handle,
+                            offset,
+                            len as u64,
+                        )
+                        .await
+                        .map_err(io_error)
+                    }))
             }
         })
         .poll(cx);
@@ -160,7 +773,12 @@ impl AsyncRead for File {
             Poll::Pending => Poll::Pending,
             Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
             Poll::Ready(Ok(None)) => Poll::Ready(Ok(())),
-            Poll::Ready(Ok(Some(data))) => {
+            Poll::Ready(Ok(Some(mut data))) => {
+                // the server may return more than requested or the buffer may have shrunk
+                if data.len() > buf.remaining() {
+                    self.spill = data.split_off(buf.remaining());
+                }
+
                 self.pos += data.len() as u64;
                 buf.put_slice(&data[..]);
                 Poll::Ready(Ok(()))
@@ -172,8 +790,7 @@ impl AsyncRead for File {
 impl AsyncSeek for File {
     fn start_seek(mut self: Pin<&mut Self>, position: io::SeekFrom) -> io::Result<()> {
         match self.state.f_seek {
-            Some(_) => Err(io::Error::new(
-                io::ErrorKind::Other,
+            Some(_) => Err(io::Error::other(
                 "other file operation is pending, call poll_complete before start_seek",
             )),
             None => {
@@ -181,31 +798,22 @@ impl AsyncSeek for File {
                 let file_handle = self.handle.clone();
                 let cur_pos = self.pos as i64;
 
-                self.state.f_seek = Some(Box::pin(async move {
+                self.state.f_seek = Some(StateFuture::new(async move {
                     let new_pos = match position {
                         SeekFrom::Start(pos) => pos as i64,
                         SeekFrom::Current(pos) => cur_pos + pos,
                         SeekFrom::End(pos) => {
-                            let result = session
-                                .fstat(file_handle)
-                                .await
-                                .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+                            let result = session.fstat(file_handle).await.map_err(io_error)?;
 
                             match result.attrs.size {
                                 Some(size) => size as i64 + pos,
-                                None => {
-                                    return Err(io::Error::new(
-                                        io::ErrorKind::Other,
-                                        "file size unknown",
-                                    ))
-                                }
+                                None => return Err(io::Error::other("file size unknown")),
                             }
                         }
                     };
 
                     if new_pos < 0 {
-                        return Err(io::Error::new(
-                            io::ErrorKind::Other,
+                        return Err(io::Error::other(
                             "cannot move file pointer before the beginning",
                         ));
                     }
@@ -224,6 +832,7 @@ impl AsyncSeek for File {
             Some(f) => {
                 self.pos = ready!(Pin::new(f).poll(cx))?;
                 self.state.f_seek = None;
+                self.spill.clear();
                 Poll::Ready(Ok(self.pos))
             }
         }
@@ -239,31 +848,48 @@ impl AsyncWrite for File {
         let poll = Pin::new(match self.state.f_write.as_mut() {
             Some(f) => f,
             None => {
+                if let Some(err) = lock(&self.acks).poisoned() {
+                    return Poll::Ready(Err(io_error(err)));
+                }
+
                 let session = self.session.clone();
-                let max_write_len = self
-                    .extensions
-                    .limits
-                    .as_ref()
-                    .and_then(|l| l.write_len)
-                    .unwrap_or(MAX_WRITE_LENGTH) as usize;
+                let chunk = self.chunk.clone();
+                let acks = self.acks.clone();
+                let bandwidth = self.bandwidth.clone();
+                let max_write_len = self.chunk_len(self.max_write_len());
 
                 let file_handle = self.handle.clone();
                 let data = buf.to_vec();
+                self.spill.clear();
 
-                let offset = self.pos;
+                let offset = self.append.unwrap_or(self.pos);
                 let len = if data.len() > max_write_len {
                     max_write_len
                 } else {
                     data.len()
                 };
 
-                self.state.f_write.get_or_insert(Box::pin(async move {
-                    session
-                        .write(file_handle, offset, data[..len].to_vec())
-                        .await
-                        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
-                    Ok(len)
-                }))
+                lock(&acks).sent(offset);
+
+                self.state
+                    .f_write
+                    .get_or_insert(StateFuture::new(async move {
+                        bandwidth.acquire(Direction::Write, len as u64).await;
+
+                        let started = Instant::now();
+                        if let Err(err) = session
+                            .write(file_handle, offset, data[..len].to_vec())
+                            .await
+                        {
+                            let err = stale_handle(err);
+                            lock(&acks).poison(err.clone());
+                            return Err(io_error(err));
+                        }
+
+                        lock(&chunk).record(len, started.elapsed());
+                        lock(&acks).acknowledged(offset, len as u64);
+                        Ok(len)
+                    }))
             }
         })
         .poll(cx);
@@ -273,14 +899,24 @@ impl AsyncWrite for File {
         }
 
         if let Poll::Ready(Ok(len)) = poll {
-            self.pos += len as u64;
+            match self.append.as_mut() {
+                Some(end) => {
+                    *end += len as u64;
+                    self.pos = *end;
+                }
+                None => self.pos += len as u64,
+            }
         }
 
         poll
     }
 
     fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
-        if !self.extensions.fsync {
+        if let Some(err) = lock(&self.acks).poisoned() {
+            return Poll::Ready(Err(io_error(err)));
+        }
+
+        if self.state.f_flush.is_none() && !self.extensions.supports(Capability::Fsync) {
             return Poll::Ready(Ok(()));
         }
 
@@ -288,15 +924,18 @@ impl AsyncWrite for File {
             Some(f) => f,
             None => {
                 let session = self.session.clone();
+                let extensions = self.extensions.clone();
                 let file_handle = self.handle.clone();
 
-                self.state.f_flush.get_or_insert(Box::pin(async move {
-                    session
-                        .fsync(file_handle)
-                        .await
-                        .map(|_| ())
-                        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))
-                }))
+                self.state
+                    .f_flush
+                    .get_or_insert(StateFuture::new(async move {
+                        let result = session.fsync(file_handle).await;
+                        match extensions.check(Capability::Fsync, result) {
+                            Ok(_) | Err(Error::ExtensionUnsupported { .. }) => Ok(()),
+                            Err(err) => Err(io_error(err)),
+                        }
+                    }))
             }
         })
         .poll(cx);
@@ -318,13 +957,12 @@ impl AsyncWrite for File {
                 let session = self.session.clone();
                 let file_handle = self.handle.clone();
 
-                self.state.f_shutdown.get_or_insert(Box::pin(async move {
-                    session
-                        .close(file_handle)
-                        .await
-                        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
-                    Ok(())
-                }))
+                self.state
+                    .f_shutdown
+                    .get_or_insert(StateFuture::new(async move {
+                        session.close(file_handle).await.map_err(io_error)?;
+                        Ok(())
+                    }))
             }
         })
         .poll(cx);
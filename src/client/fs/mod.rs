@@ -3,11 +3,53 @@
 //! This module contains methods for interacting with remote entities on high-level.
 //! The architecture is quite simple because it is built as an analogue of [`std::fs`]
 
+mod ack;
+mod bandwidth;
+mod cache;
+mod chunk;
 mod dir;
 mod file;
+mod progress;
+mod size;
 
-use crate::protocol::FileAttributes;
+use std::time::SystemTime;
 
-pub use dir::{DirEntry, ReadDir};
+use super::{error::Error, rawsession::SftpResult};
+use crate::protocol::{FileAttr, FileAttributes};
+
+pub(crate) use bandwidth::Bandwidth;
+pub use cache::{CachedFile, PageCacheStats, DEFAULT_PAGE_CAPACITY, DEFAULT_PAGE_SIZE};
+pub use chunk::{Aimd, INITIAL_ADAPTIVE_CHUNK, MIN_ADAPTIVE_CHUNK};
+pub use dir::{DirEntry, ReadDir, ReadDirStream};
+#[cfg(feature = "std-fs")]
+pub(crate) use file::join_error;
 pub use file::File;
+pub use progress::{Direction, ProgressEvent, ProgressFile};
+pub(crate) use size::ExpectedSize;
 pub type Metadata = FileAttributes;
+
+/// Returns the attributes of `set_times` with the given times, which fail
+/// after 2106 whether or not the times are strict
+pub(crate) fn times(
+    accessed: Option<SystemTime>,
+    modified: Option<SystemTime>,
+) -> SftpResult<Metadata> {
+    let times = Metadata::with_times(accessed, modified)?;
+    match times.time_beyond_v3() {
+        Some(time) => Err(Error::TimeOverflow(time)),
+        None => Ok(times),
+    }
+}
+
+/// Checks that the attributes of `set_times` carry both times and nothing else,
+/// as the protocol sends them in pairs
+pub(crate) fn check_times(times: &Metadata) -> SftpResult<()> {
+    if times.atime.is_none() || times.mtime.is_none() {
+        return Err(Error::UnexpectedBehavior(
+            "the server did not report the time to keep".to_owned(),
+        ));
+    }
+
+    debug_assert_eq!(times.flags(), FileAttr::ACMODTIME);
+    Ok(())
+}
@@ -0,0 +1,175 @@
+use std::{
+    io::{self, SeekFrom},
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::{Duration, Instant},
+};
+use tokio::io::{AsyncRead, AsyncSeek, AsyncWrite, ReadBuf};
+
+use super::File;
+
+const DEFAULT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Direction of the transfer reported by [`ProgressEvent`] or limited by
+/// [`SftpSession::set_bandwidth_limit`](crate::client::SftpSession::set_bandwidth_limit)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Read,
+    Write,
+}
+
+/// Passed to the callback of [`ProgressFile`] after a completed chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgressEvent {
+    /// Total number of bytes transferred in this direction
+    pub bytes_transferred: u64,
+    pub direction: Direction,
+    /// Position in the remote file after the chunk
+    pub offset: u64,
+}
+
+/// Wrapper around [`File`] that reports the progress of reads and writes.
+///
+/// Created by [`File::with_progress`]. Chunk sizes and requests are the same as for
+/// the inner file. The callback is invoked at most once per interval, except for the
+/// end of file and shutdown which are always reported.
+pub struct ProgressFile<F> {
+    file: File,
+    callback: F,
+    interval: Duration,
+    last: Option<Instant>,
+    read: u64,
+    written: u64,
+}
+
+impl<F> ProgressFile<F>
+where
+    F: FnMut(ProgressEvent),
+{
+    pub(crate) fn new(file: File, callback: F) -> Self {
+        Self {
+            file,
+            callback,
+            interval: DEFAULT_INTERVAL,
+            last: None,
+            read: 0,
+            written: 0,
+        }
+    }
+
+    /// Sets the minimum interval between callback invocations.
+    /// Default: 100 milliseconds
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Total number of bytes read through the wrapper
+    pub fn bytes_read(&self) -> u64 {
+        self.read
+    }
+
+    /// Total number of bytes written through the wrapper
+    pub fn bytes_written(&self) -> u64 {
+        self.written
+    }
+
+    /// Returns a reference to the inner file
+    pub fn get_ref(&self) -> &File {
+        &self.file
+    }
+
+    /// Unwraps the inner file
+    pub fn into_inner(self) -> File {
+        self.file
+    }
+
+    fn report(&mut self, direction: Direction, force: bool) {
+        let now = Instant::now();
+        if !force
+            && self
+                .last
+                .is_some_and(|l| now.duration_since(l) < self.interval)
+        {
+            return;
+        }
+
+        self.last = Some(now);
+        (self.callback)(ProgressEvent {
+            bytes_transferred: match direction {
+                Direction::Read => self.read,
+                Direction::Write => self.written,
+            },
+            direction,
+            offset: self.file.pos,
+        });
+    }
+}
+
+impl<F> AsyncRead for ProgressFile<F>
+where
+    F: FnMut(ProgressEvent) + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut self.file).poll_read(cx, buf))?;
+
+        let len = (buf.filled().len() - filled) as u64;
+        self.read += len;
+        self.report(Direction::Read, len == 0 && buf.remaining() > 0);
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<F> AsyncSeek for ProgressFile<F>
+where
+    F: FnMut(ProgressEvent) + Unpin,
+{
+    fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        Pin::new(&mut self.file).start_seek(position)
+    }
+
+    fn poll_complete(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Pin::new(&mut self.file).poll_complete(cx)
+    }
+}
+
+impl<F> AsyncWrite for ProgressFile<F>
+where
+    F: FnMut(ProgressEvent) + Unpin,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let len = ready!(Pin::new(&mut self.file).poll_write(cx, buf))?;
+
+        self.written += len as u64;
+        self.report(Direction::Write, false);
+
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.file).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), io::Error>> {
+        ready!(Pin::new(&mut self.file).poll_shutdown(cx))?;
+
+        if self.written > 0 {
+            self.report(Direction::Write, true);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
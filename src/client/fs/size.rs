@@ -0,0 +1,60 @@
+use super::File;
+use crate::client::{error::Error, rawsession::SftpResult};
+
+/// Size of a remote file from the fstat before a chunked read, which is authoritative
+/// over the data received. Some servers pad the last chunk beyond the end of file,
+/// the bytes past the requested length are dropped so that they are not counted twice
+#[derive(Debug)]
+pub(crate) struct ExpectedSize {
+    size: Option<u64>,
+    padded: bool,
+}
+
+impl ExpectedSize {
+    pub fn new(size: Option<u64>) -> Self {
+        Self {
+            size,
+            padded: false,
+        }
+    }
+
+    /// Drops the bytes of `data` beyond the `len` requested at `offset`
+    pub fn clamp(&mut self, offset: u64, len: u64, data: &mut Vec<u8>) {
+        if data.len() as u64 <= len {
+            return;
+        }
+
+        if !self.padded {
+            warn!(
+                "received {} bytes at offset {offset} for a read of {len}, the excess is dropped",
+                data.len()
+            );
+        }
+
+        self.padded = true;
+        data.truncate(len as usize);
+    }
+
+    /// Checks that `received` bytes were assembled for a file whose size is
+    /// unchanged, with a new fstat. Nothing is checked when the size was unknown
+    pub async fn verify(&self, file: &File, received: u64) -> SftpResult<()> {
+        let Some(expected) = self.size else {
+            return Ok(());
+        };
+
+        let actual = file.metadata().await?.size;
+        if actual != Some(expected) {
+            return Err(Error::SizeChanged { expected, actual });
+        }
+
+        if received != expected {
+            return Err(Error::LengthMismatch { expected, received });
+        }
+
+        if self.padded {
+            debug!("the padding of a file of {expected} bytes was dropped");
+        }
+
+        Ok(())
+    }
+}
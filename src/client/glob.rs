@@ -0,0 +1,232 @@
+use futures_core::Stream;
+use globset::{GlobBuilder, GlobMatcher};
+use std::{
+    collections::{HashSet, VecDeque},
+    future::poll_fn,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tokio::{sync::mpsc, task::JoinHandle};
+
+use super::{
+    error::Error,
+    fs::{DirEntry, ReadDirStream},
+    rawsession::SftpResult,
+    RawSftpSession, RemoteFlavor,
+};
+use crate::protocol::StatusCode;
+
+/// Component of a pattern between two separators
+enum Component {
+    Literal(String),
+    Pattern(GlobMatcher),
+    /// `**`, any number of directories
+    Recursive,
+}
+
+impl Component {
+    fn parse(component: &str) -> SftpResult<Self> {
+        if component == "**" {
+            return Ok(Self::Recursive);
+        }
+
+        if !component.contains(['*', '?', '[', '{', '\\']) {
+            return Ok(Self::Literal(component.to_owned()));
+        }
+
+        let glob = GlobBuilder::new(component)
+            .literal_separator(true)
+            .build()
+            .map_err(|err| Error::InvalidPattern(err.to_string()))?;
+
+        Ok(Self::Pattern(glob.compile_matcher()))
+    }
+}
+
+/// Splits the pattern into the directory of its leading literal components,
+/// empty for the working directory, and the components matched from there.
+/// The last component is kept even if literal, to check that the path exists
+fn parse(pattern: &str, flavor: RemoteFlavor) -> SftpResult<(String, Vec<Component>)> {
+    let mut base = match pattern.starts_with('/') {
+        true => "/".to_owned(),
+        false => String::new(),
+    };
+
+    let mut components = pattern
+        .split('/')
+        .filter(|component| !component.is_empty() && *component != ".")
+        .map(Component::parse)
+        .collect::<SftpResult<VecDeque<_>>>()?;
+
+    if components.is_empty() {
+        return Err(Error::InvalidPattern(format!(
+            "{pattern:?} matches no file"
+        )));
+    }
+
+    while components.len() > 1 {
+        let Some(Component::Literal(name)) = components.front() else {
+            break;
+        };
+
+        base = flavor.join(&base, name);
+        components.pop_front();
+    }
+
+    Ok((base, components.into()))
+}
+
+/// Entries of the directory, [`None`] if it does not exist
+async fn list(
+    session: &Arc<RawSftpSession>,
+    dir: &str,
+    flavor: RemoteFlavor,
+) -> SftpResult<Option<Vec<DirEntry>>> {
+    let opened = match dir.is_empty() {
+        true => session.opendir(".").await,
+        false => session.opendir(dir).await,
+    };
+
+    let handle = match opened {
+        Ok(handle) => handle.handle,
+        Err(Error::Status { status, .. }) if status.status_code == StatusCode::NoSuchFile => {
+            return Ok(None)
+        }
+        Err(err) => return Err(err.with_target(dir)),
+    };
+
+    let mut stream = ReadDirStream::new(session.clone(), handle, dir.to_owned(), flavor);
+    let mut entries = Vec::new();
+    while let Some(entry) = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+        entries.push(entry?);
+    }
+
+    Ok(Some(entries))
+}
+
+/// Visits the directories required by the components breadth-first and sends
+/// the matching paths. The directories are entered from their listings,
+/// so symlinks to directories are matched but not followed
+async fn walk(
+    session: Arc<RawSftpSession>,
+    flavor: RemoteFlavor,
+    base: String,
+    components: Vec<Component>,
+    tx: mpsc::Sender<SftpResult<String>>,
+) {
+    let mut pending = VecDeque::from([(base, 0)]);
+    let mut visited = HashSet::new();
+    let mut matched = HashSet::new();
+
+    while let Some((dir, index)) = pending.pop_front() {
+        if !visited.insert((dir.to_owned(), index)) {
+            continue;
+        }
+
+        let last = index + 1 == components.len();
+        let mut found = Vec::new();
+
+        match &components[index] {
+            Component::Literal(name) if last => {
+                let path = flavor.join(&dir, name);
+                match session.lstat(path.as_str()).await {
+                    Ok(_) => found.push(Ok(path)),
+                    Err(Error::Status { status, .. })
+                        if status.status_code == StatusCode::NoSuchFile => {}
+                    Err(err) => found.push(Err(err.with_target(path))),
+                }
+            }
+            Component::Literal(name) => pending.push_back((flavor.join(&dir, name), index + 1)),
+            component => {
+                let entries = match list(&session, &dir, flavor).await {
+                    Ok(entries) => entries.unwrap_or_default(),
+                    Err(err) => {
+                        found.push(Err(err));
+                        Vec::new()
+                    }
+                };
+
+                if matches!(component, Component::Recursive) && !last {
+                    // `**` standing for no directory
+                    pending.push_back((dir, index + 1));
+                }
+
+                for entry in entries {
+                    let path = entry.path();
+                    match component {
+                        Component::Recursive => {
+                            if entry.is_dir() {
+                                pending.push_back((path.to_owned(), index));
+                            }
+
+                            if last {
+                                found.push(Ok(path));
+                            }
+                        }
+                        Component::Pattern(matcher) if matcher.is_match(entry.file_name()) => {
+                            if last {
+                                found.push(Ok(path));
+                            } else if entry.is_dir() {
+                                pending.push_back((path, index + 1));
+                            }
+                        }
+                        _ => (),
+                    }
+                }
+            }
+        }
+
+        for result in found {
+            if let Ok(path) = &result {
+                if !matched.insert(path.to_owned()) {
+                    continue;
+                }
+            }
+
+            if tx.send(result).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Stream of the paths matching a pattern, returned by
+/// [`SftpSession::glob`](crate::client::SftpSession::glob).
+///
+/// The paths are found by a background task which is stopped when the stream
+/// is dropped. Directories that cannot be listed are reported as errors and
+/// the stream goes on with the others
+pub struct Glob {
+    rx: mpsc::Receiver<SftpResult<String>>,
+    task: JoinHandle<()>,
+}
+
+impl Glob {
+    pub(crate) fn new(
+        session: Arc<RawSftpSession>,
+        pattern: &str,
+        flavor: RemoteFlavor,
+    ) -> SftpResult<Self> {
+        let (base, components) = parse(pattern, flavor)?;
+
+        let (tx, rx) = mpsc::channel(64);
+        let task = tokio::spawn(walk(session, flavor, base, components, tx));
+
+        Ok(Self { rx, task })
+    }
+}
+
+impl Stream for Glob {
+    type Item = SftpResult<String>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+impl Drop for Glob {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
@@ -6,6 +6,13 @@ use crate::protocol::{Attrs, Data, ExtendedReply, Handle, Name, Status, Version}
 pub trait Handler: Sized {
     type Error: Into<Error>;
 
+    /// Whether SSH_FXP_NAME entries without `longname` are accepted.
+    /// Some servers written against version 4 and later omit it.
+    /// Default: `false`
+    fn lenient_names(&self) -> bool {
+        false
+    }
+
     /// Called on SSH_FXP_VERSION.
     #[allow(unused_variables)]
     async fn version(&mut self, version: Version) -> Result<(), Self::Error> {
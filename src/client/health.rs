@@ -0,0 +1,126 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, OnceLock,
+};
+
+use super::error::Error;
+
+/// Why the processing of the stream of a session ended,
+/// see [`SftpSession::closed`](super::SftpSession::closed)
+#[derive(Debug, Clone)]
+pub enum CloseReason {
+    /// The session was closed by the client
+    Closed,
+    /// The server closed the stream
+    Eof,
+    /// Reading or writing the stream failed with the error, [`Error::Framing`]
+    /// if the server sent a packet that could not be delimited
+    Failed(Error),
+}
+
+/// Half of the stream processed by its own future
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Half {
+    Read,
+    Write,
+}
+
+/// State of the stream of a session, shared by both halves of the stream,
+/// the failure of the pending requests and the session.
+///
+/// The stream is healthy while both halves run and the session is not closing.
+/// The first reason recorded is kept, the later ones are consequences of it
+#[derive(Debug)]
+pub(crate) struct Health {
+    reading: AtomicBool,
+    writing: AtomicBool,
+    closing: AtomicBool,
+    reason: OnceLock<CloseReason>,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self {
+            reading: AtomicBool::new(true),
+            writing: AtomicBool::new(true),
+            closing: AtomicBool::new(false),
+            reason: OnceLock::new(),
+        }
+    }
+}
+
+impl Health {
+    /// Returns a guard marking `half` as ended once dropped,
+    /// including when its task is aborted
+    pub fn running(self: &Arc<Self>, half: Half) -> Running {
+        Running {
+            health: self.clone(),
+            half,
+        }
+    }
+
+    fn flag(&self, half: Half) -> &AtomicBool {
+        match half {
+            Half::Read => &self.reading,
+            Half::Write => &self.writing,
+        }
+    }
+
+    /// Returns `true` if frames can still be written to the stream
+    pub fn is_writing(&self) -> bool {
+        self.writing.load(Ordering::SeqCst)
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.reading.load(Ordering::SeqCst)
+            && self.writing.load(Ordering::SeqCst)
+            && !self.is_closing()
+            && self.reason.get().is_none()
+    }
+
+    /// Stops accepting new requests, the stream is closed by the session
+    pub fn start_closing(&self) {
+        self.closing.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_closing(&self) -> bool {
+        self.closing.load(Ordering::SeqCst)
+    }
+
+    /// Records the reason unless one was recorded and returns the recorded one.
+    /// EOF once the session is closing is the server answering the close
+    pub fn end(&self, reason: CloseReason) -> &CloseReason {
+        let reason = match reason {
+            CloseReason::Eof if self.is_closing() => CloseReason::Closed,
+            reason => reason,
+        };
+
+        self.reason.get_or_init(|| reason)
+    }
+
+    /// Returns the recorded reason, [`CloseReason::Closed`] if none was
+    pub fn reason(&self) -> CloseReason {
+        self.reason.get().cloned().unwrap_or(CloseReason::Closed)
+    }
+
+    /// Returns the cause of the requests failing, if the stream was lost
+    pub fn lost(&self) -> Option<Error> {
+        match self.reason.get()? {
+            CloseReason::Closed => None,
+            CloseReason::Eof => Some(Error::IO("unexpected EOF on stream".to_owned())),
+            CloseReason::Failed(err) => Some(err.clone()),
+        }
+    }
+}
+
+/// Marks a half of the stream as running until dropped
+pub(crate) struct Running {
+    health: Arc<Health>,
+    half: Half,
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.health.flag(self.half).store(false, Ordering::SeqCst);
+    }
+}
@@ -1,22 +1,96 @@
+mod access;
+/// Blocking façade for callers without an async runtime
+#[cfg(feature = "blocking")]
+pub mod blocking;
+mod builder;
+#[cfg(feature = "checksum")]
+mod checksum;
 pub mod error;
 pub mod fs;
+#[cfg(feature = "glob")]
+mod glob;
 mod handler;
+mod health;
+mod path;
+mod path_cache;
 pub mod rawsession;
+mod requests;
 mod session;
+mod summary;
+mod temp;
+mod tracker;
+#[cfg(feature = "std-fs")]
+mod transfer;
+mod watch;
 
+pub use access::{Access, AccessCheck, Identity};
+pub use builder::{InitProbe, SessionBuilder};
+#[cfg(feature = "checksum")]
+pub use checksum::{ChecksumManifest, ChunkChecksum};
+pub use fs::{DirEntry, Metadata};
+#[cfg(feature = "glob")]
+pub use glob::Glob;
 pub use handler::Handler;
+/// The [`Handler`] of the client, named so that it can be imported along with the
+/// one of the server
+///
+/// ```
+/// use russh_sftp::client::{ClientHandler, Handler};
+///
+/// struct Client;
+///
+/// impl ClientHandler for Client {
+///     type Error = russh_sftp::client::error::Error;
+/// }
+///
+/// fn handler<H: Handler>(_handler: &H) {}
+/// handler(&Client);
+/// ```
+pub use handler::Handler as ClientHandler;
+pub use health::CloseReason;
+pub use path::RemoteFlavor;
+pub use path_cache::PathCacheStats;
 pub use rawsession::RawSftpSession;
 pub use session::SftpSession;
+#[cfg(feature = "server")]
+pub use session::PAIR_BUFFER_LEN;
+pub use summary::{DirSummary, SummaryFilter, SummaryOptions, DEFAULT_SUMMARY_CONCURRENCY};
+pub use temp::TempDir;
+pub use tracker::TrackedHandle;
+#[cfg(feature = "std-fs")]
+pub use transfer::{
+    SchedulePolicy, TransferEvent, TransferJob, TransferScheduler, DEFAULT_TRANSFER_REQUESTS,
+};
+pub use watch::{ChangeEvent, Watch, DEFAULT_WATCH_CONCURRENCY};
 
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 use tokio::{
     io::{self, AsyncRead, AsyncWrite, AsyncWriteExt},
-    select,
+    join, select,
     sync::mpsc,
+    task::JoinSet,
+    time,
 };
 use tokio_util::sync::CancellationToken;
 
-use crate::{error::Error, protocol::Packet, utils::read_packet};
+use crate::{
+    error::Error,
+    framing::{read_checked_packet, DEFAULT_MAX_PACKET_LEN},
+    protocol::Packet,
+};
+use health::{Half, Health};
+
+/// Maximum time to write out queued frames after the read half has ended
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Time the read half keeps dispatching replies after the write half failed,
+/// renewed by each reply, before the pending requests are failed
+const LINGER: Duration = Duration::from_millis(100);
 
 macro_rules! into_wrap {
     ($handler:expr) => {
@@ -31,7 +105,13 @@ async fn execute_handler<H>(bytes: &mut Bytes, handler: &mut H) -> Result<(), er
 where
     H: Handler + Send,
 {
-    match Packet::try_from(bytes)? {
+    let packet = if handler.lenient_names() {
+        Packet::try_from_lenient(bytes)?
+    } else {
+        Packet::try_from(bytes)?
+    };
+
+    match packet {
         Packet::Version(p) => into_wrap!(handler.version(p)),
         Packet::Status(p) => into_wrap!(handler.status(p)),
         Packet::Handle(p) => into_wrap!(handler.handle(p)),
@@ -45,65 +125,227 @@ where
     }
 }
 
-async fn process_handler<S, H>(stream: &mut S, handler: &mut H) -> Result<(), Error>
+async fn process_handler<S, H>(
+    stream: &mut S,
+    handler: &mut H,
+    buf: &mut BytesMut,
+) -> Result<(), Error>
 where
     S: AsyncRead + Unpin,
     H: Handler + Send,
 {
-    let mut bytes = read_packet(stream).await?;
+    let mut bytes = read_checked_packet(stream, buf, DEFAULT_MAX_PACKET_LEN).await?;
     Ok(execute_handler(&mut bytes, handler).await?)
 }
 
+/// Writes out the frames already queued, for example closing of handles
+/// enqueued by [`Drop`], and flushes the writer
+async fn drain_queue<W>(rx: &mut mpsc::UnboundedReceiver<Bytes>, wr: &mut W) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    while let Ok(data) = rx.try_recv() {
+        if !data.is_empty() {
+            wr.write_all(&data[..]).await?;
+        }
+    }
+
+    wr.flush().await
+}
+
 /// Run processing stream as SFTP client. Is a simple handler of incoming
 /// and outgoing packets. Can be used for non-standard implementations
-pub fn run<S, H>(stream: S, mut handler: H) -> mpsc::UnboundedSender<Bytes>
+pub fn run<S, H>(stream: S, handler: H) -> mpsc::UnboundedSender<Bytes>
 where
     S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
     H: Handler + Send + 'static,
+{
+    run_with_close(stream, handler, Arc::default(), |_| (), None).tx
+}
+
+/// Number of frames written to the stream so far, excluding the
+/// frames written out while the stream is being closed
+pub(crate) type Written = tokio::sync::watch::Receiver<u64>;
+
+/// Closed once both halves of the stream have ended
+pub(crate) type Finished = tokio::sync::watch::Receiver<()>;
+
+/// Ends of a session connected to the processing of its stream
+pub(crate) struct Channels {
+    pub tx: mpsc::UnboundedSender<Bytes>,
+    pub written: Written,
+    pub finished: Finished,
+}
+
+/// Same as [`run`], but calls `on_close` with the cause when either half
+/// of the stream terminates because of an I/O error or EOF. The halves report
+/// whether they run to `health` and are spawned on `tasks` if given, otherwise detached
+pub(crate) fn run_with_close<S, H, F>(
+    stream: S,
+    handler: H,
+    health: Arc<Health>,
+    on_close: F,
+    tasks: Option<&mut JoinSet<()>>,
+) -> Channels
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    H: Handler + Send + 'static,
+    F: Fn(Error) + Send + Sync + 'static,
+{
+    let (channels, read, write) = io_loops(stream, handler, health, on_close);
+
+    match tasks {
+        Some(tasks) => {
+            tasks.spawn(read);
+            tasks.spawn(write);
+        }
+        None => {
+            tokio::spawn(read);
+            tokio::spawn(write);
+        }
+    }
+
+    channels
+}
+
+/// Same as [`run_with_close`], but returns a future driving both halves
+/// of the stream instead of spawning them
+pub(crate) fn run_unspawned<S, H, F>(
+    stream: S,
+    handler: H,
+    health: Arc<Health>,
+    on_close: F,
+) -> (Channels, impl Future<Output = ()>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    H: Handler + Send,
+    F: Fn(Error),
+{
+    let (channels, read, write) = io_loops(stream, handler, health, on_close);
+
+    (channels, async move {
+        join!(read, write);
+    })
+}
+
+fn io_loops<S, H, F>(
+    stream: S,
+    mut handler: H,
+    health: Arc<Health>,
+    on_close: F,
+) -> (Channels, impl Future<Output = ()>, impl Future<Output = ()>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    H: Handler + Send,
+    F: Fn(Error),
 {
     let (tx, mut rx) = mpsc::unbounded_channel::<Bytes>();
+    let (written_tx, written) = tokio::sync::watch::channel(0);
+    // dropped by each half when it ends, including when its task is aborted
+    let (finished_tx, finished) = tokio::sync::watch::channel(());
+    let finished_tx = Arc::new(finished_tx);
     let (mut rd, mut wr) = io::split(stream);
 
+    let on_close = Arc::new(on_close);
     let rc = CancellationToken::new();
     let wc = rc.clone();
-    {
-        tokio::spawn(async move {
-            loop {
+    // set by the write half, the read half fails the pending requests
+    // with it once the replies already received are dispatched
+    let write_error = Arc::new(Mutex::new(None::<Error>));
+    let (write_failed, wf) = (CancellationToken::new(), write_error.clone());
+    let failed = write_failed.clone();
+
+    let read = {
+        let on_close = on_close.clone();
+        let finished_tx = finished_tx.clone();
+        let reading = health.running(Half::Read);
+        async move {
+            let _finished = finished_tx;
+            let _reading = reading;
+            let mut buf = BytesMut::new();
+            let reason = loop {
+                let linger = async {
+                    write_failed.cancelled().await;
+                    time::sleep(LINGER).await;
+                };
+
                 select! {
-                    result = process_handler(&mut rd, &mut handler) => {
+                    result = process_handler(&mut rd, &mut handler, &mut buf) => {
                         match result {
-                            Err(Error::UnexpectedEof) => break,
+                            Err(err @ (Error::UnexpectedEof | Error::IO(_) | Error::Framing(_))) => {
+                                break Some(err)
+                            }
                             Err(err) => warn!("{}", err),
                             Ok(_) => (),
                         }
                     },
-                    _ = rc.cancelled() => break,
+                    _ = linger => break write_error.lock().unwrap().take(),
+                    _ = rc.cancelled() => break None,
                 }
-            }
+            };
 
+            // fail the pending requests before the write half drains the queue
+            if let Some(reason) = reason {
+                on_close(reason);
+            }
             rc.cancel();
+
             debug!("read half of sftp stream ended");
-        });
-    }
+        }
+    };
 
-    tokio::spawn(async move {
-        loop {
+    let writing = health.running(Half::Write);
+    let write = async move {
+        let _finished = finished_tx;
+        let _writing = writing;
+        let reason = loop {
             select! {
                 Some(data) = rx.recv() => {
                     if data.is_empty() {
+                        // write out everything queued before shutting down
+                        let _ = drain_queue(&mut rx, &mut wr).await;
                         let _ = wr.shutdown().await;
-                        break;
+                        break None;
+                    }
+
+                    let result = match wr.write_all(&data[..]).await {
+                        Ok(()) => wr.flush().await,
+                        Err(err) => Err(err),
+                    };
+
+                    if let Err(err) = result {
+                        break Some(err.into());
                     }
 
-                    let _ = wr.write_all(&data[..]).await;
+                    written_tx.send_modify(|written| *written += 1);
+                },
+                _ = wc.cancelled() => {
+                    // the read half has ended, but the queued frames may still be delivered
+                    let _ = time::timeout(DRAIN_TIMEOUT, drain_queue(&mut rx, &mut wr)).await;
+                    let _ = wr.shutdown().await;
+                    break None;
                 },
-                _ = wc.cancelled() => break,
             }
+        };
+
+        match reason {
+            // the read half has ended and no longer takes the error
+            Some(reason) if wc.is_cancelled() => on_close(reason),
+            Some(reason) => {
+                *wf.lock().unwrap() = Some(reason);
+                failed.cancel();
+            }
+            None => wc.cancel(),
         }
 
-        wc.cancel();
         debug!("write half of sftp stream ended");
-    });
+    };
+
+    let channels = Channels {
+        tx,
+        written,
+        finished,
+    };
 
-    tx
+    (channels, read, write)
 }
@@ -0,0 +1,115 @@
+use crate::{extensions, protocol::Version};
+
+/// Vendors of SFTP servers that run on Windows, matched against `vendor-id`
+const WINDOWS_VENDORS: &[&str] = &[
+    "bitvise",
+    "windows",
+    "cerberus",
+    "solarwinds",
+    "globalscape",
+];
+
+/// Path conventions of the server, see
+/// [`SftpSession::set_remote_flavor`](crate::client::SftpSession::set_remote_flavor)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RemoteFlavor {
+    /// Paths are used as they are
+    #[default]
+    Unix,
+    /// Paths use a drive letter after the leading slash such as `/C:/Users/foo`,
+    /// as returned by OpenSSH for Windows and Bitvise. Backslashes and forms
+    /// such as `C:\Users\foo` are translated to it
+    Windows,
+}
+
+impl RemoteFlavor {
+    /// Detects the flavor from the `vendor-id` and `newline` extensions of the server.
+    /// Falls back to [`RemoteFlavor::Unix`]
+    pub fn detect(version: &Version) -> Self {
+        let vendor = version
+            .extensions
+            .get(extensions::VENDOR_ID)
+            .map(|vendor| vendor.to_lowercase());
+
+        if vendor.is_some_and(|vendor| WINDOWS_VENDORS.iter().any(|name| vendor.contains(name)))
+            || version
+                .extensions
+                .get(extensions::NEWLINE)
+                .is_some_and(|newline| newline == "\r\n")
+        {
+            return Self::Windows;
+        }
+
+        Self::Unix
+    }
+
+    /// Returns `true` if the path starts with a drive letter in the form the
+    /// Windows servers return, such as `/C:/Users`
+    pub(crate) fn has_drive_form(path: &str) -> bool {
+        path.strip_prefix('/').is_some_and(starts_with_drive)
+    }
+
+    /// Converts the path to the form accepted by the server
+    pub fn normalize(&self, path: &str) -> String {
+        match self {
+            Self::Unix => path.to_owned(),
+            Self::Windows => normalize_windows(path),
+        }
+    }
+
+    /// Joins `name` onto the directory `base`. An absolute `name` replaces `base`
+    pub fn join(&self, base: &str, name: &str) -> String {
+        let absolute = match self {
+            Self::Unix => name.starts_with('/'),
+            Self::Windows => name.starts_with(['/', '\\']) || starts_with_drive(name),
+        };
+
+        if absolute {
+            return self.normalize(name);
+        }
+
+        let base = self.normalize(base);
+        let joined = match base.is_empty() || base.ends_with('/') {
+            true => format!("{base}{name}"),
+            false => format!("{base}/{name}"),
+        };
+
+        self.normalize(&joined)
+    }
+}
+
+/// Returns `true` if the path starts with a drive letter followed by nothing or a separator
+fn starts_with_drive(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    bytes.len() >= 2
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && matches!(bytes.get(2), None | Some(b'/' | b'\\'))
+}
+
+fn normalize_windows(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let trimmed = path.trim_start_matches('/');
+
+    let (mut normalized, rest) = if starts_with_drive(trimmed) {
+        (format!("/{}", &trimmed[..2]), &trimmed[2..])
+    } else if path.starts_with('/') {
+        ("/".to_owned(), trimmed)
+    } else {
+        (String::new(), path.as_str())
+    };
+
+    for component in rest.split('/').filter(|c| !c.is_empty()) {
+        if !normalized.is_empty() && !normalized.ends_with('/') {
+            normalized.push('/');
+        }
+        normalized.push_str(component);
+    }
+
+    // the root of a drive
+    if normalized.len() == 3 && starts_with_drive(&normalized[1..]) {
+        normalized.push('/');
+    }
+
+    normalized
+}
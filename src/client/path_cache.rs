@@ -0,0 +1,195 @@
+use std::collections::{BTreeMap, HashMap};
+
+/// Counters of the canonicalization cache enabled by
+/// [`SftpSession::enable_path_cache`](crate::client::SftpSession::enable_path_cache)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PathCacheStats {
+    /// Number of lookups answered without a round trip
+    pub hits: u64,
+    /// Number of lookups that required a request to the server
+    pub misses: u64,
+    /// Number of entries currently stored
+    pub len: usize,
+    /// Maximum number of entries before the least recently used is evicted
+    pub capacity: usize,
+}
+
+struct Entry {
+    canonical: String,
+    /// Key of the entry in [`PathCache::order`]
+    used: u64,
+}
+
+/// Maps a requested path to its canonical form with LRU eviction
+pub(crate) struct PathCache {
+    entries: HashMap<String, Entry>,
+    /// Requested paths by their last use, the least recently used first
+    order: BTreeMap<u64, String>,
+    capacity: usize,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl PathCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::with_capacity(capacity),
+            order: BTreeMap::new(),
+            capacity,
+            tick: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn get(&mut self, path: &str) -> Option<String> {
+        let entry = cache_key(path).and_then(|key| self.entries.get_mut(&key));
+        let Some(entry) = entry else {
+            self.misses += 1;
+            return None;
+        };
+
+        self.tick += 1;
+        if let Some(key) = self.order.remove(&entry.used) {
+            self.order.insert(self.tick, key);
+        }
+
+        entry.used = self.tick;
+        self.hits += 1;
+        Some(entry.canonical.to_owned())
+    }
+
+    pub fn insert(&mut self, path: &str, canonical: String) {
+        let Some(path) = cache_key(path).filter(|_| self.capacity > 0) else {
+            return;
+        };
+
+        if let Some(entry) = self.entries.remove(&path) {
+            self.order.remove(&entry.used);
+        } else if self.entries.len() >= self.capacity {
+            if let Some((_, oldest)) = self.order.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.tick += 1;
+        self.order.insert(self.tick, path.clone());
+        self.entries.insert(
+            path,
+            Entry {
+                canonical,
+                used: self.tick,
+            },
+        );
+    }
+
+    /// Drops every entry whose requested or canonical path is `prefix` or lies below it.
+    /// A relative `prefix` is also resolved against the cached working directory, and
+    /// a `prefix` with `..` drops all the entries as it may resolve anywhere
+    pub fn invalidate(&mut self, prefix: &str) {
+        let Some(prefix) = cache_key(prefix) else {
+            self.clear();
+            return;
+        };
+        let prefix = prefix.as_str();
+
+        let absolute = match prefix.starts_with('/') {
+            true => None,
+            false => self
+                .entries
+                .get(".")
+                .and_then(|cwd| join_canonical(&cwd.canonical, prefix)),
+        };
+
+        let order = &mut self.order;
+        self.entries.retain(|path, entry| {
+            let stale = [Some(prefix), absolute.as_deref()]
+                .into_iter()
+                .flatten()
+                .any(|prefix| has_prefix(path, prefix) || has_prefix(&entry.canonical, prefix));
+
+            if stale {
+                order.remove(&entry.used);
+            }
+            !stale
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+
+    pub fn stats(&self) -> PathCacheStats {
+        PathCacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            len: self.entries.len(),
+            capacity: self.capacity,
+        }
+    }
+}
+
+/// Returns the key of a path in the cache, so that `a/./b/` and `a//b` share the
+/// entry of `a/b`. Returns [`None`] for a path with `..`, which resolves through
+/// the symlinks on the server and cannot be keyed without asking it
+pub(crate) fn cache_key(path: &str) -> Option<String> {
+    let mut key = match path.starts_with('/') {
+        true => String::from("/"),
+        false => String::new(),
+    };
+
+    for component in path.split('/') {
+        match component {
+            "" | "." => continue,
+            ".." => return None,
+            name => {
+                if !key.is_empty() && !key.ends_with('/') {
+                    key.push('/');
+                }
+                key.push_str(name);
+            }
+        }
+    }
+
+    if key.is_empty() {
+        key.push('.');
+    }
+
+    Some(key)
+}
+
+fn has_prefix(path: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+    if prefix.is_empty() {
+        return path.starts_with('/');
+    }
+
+    path.strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Joins `rel` onto an already canonical `base` without asking the server.
+/// Returns [`None`] if the relative part contains `..`, since a parent
+/// component cannot be resolved safely across possible symlinks.
+pub(crate) fn join_canonical(base: &str, rel: &str) -> Option<String> {
+    let mut path = base.trim_end_matches('/').to_owned();
+
+    for component in rel.split('/') {
+        match component {
+            "" | "." => continue,
+            ".." => return None,
+            name => {
+                path.push('/');
+                path.push_str(name);
+            }
+        }
+    }
+
+    if path.is_empty() {
+        path.push('/');
+    }
+
+    Some(path)
+}
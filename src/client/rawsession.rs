@@ -1,42 +1,116 @@
 use bytes::Bytes;
-use flurry::HashMap;
 use std::{
+    collections::HashMap,
+    future::Future,
     sync::{
-        atomic::{AtomicU32, AtomicU64, Ordering},
-        Arc,
+        atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock, PoisonError,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 use tokio::{
     io::{AsyncRead, AsyncWrite},
-    sync::{mpsc, RwLock},
+    select,
+    sync::{mpsc, oneshot, OwnedSemaphorePermit, RwLock, Semaphore},
+    task::JoinSet,
     time,
 };
 
-use super::{error::Error, run, Handler};
+use super::{
+    error::Error,
+    health::{CloseReason, Health},
+    requests::{self, RequestMap, SharedRequests},
+    run_unspawned, run_with_close,
+    tracker::{HandleTracker, TrackedHandle},
+    Channels, Finished, Handler, Written,
+};
+#[cfg(feature = "compression")]
+use crate::compression::Compression;
+#[cfg(feature = "tracing")]
+use crate::spans;
 use crate::{
-    de,
+    de, error,
     extensions::{
-        self, FsyncExtension, HardlinkExtension, LimitsExtension, Statvfs, StatvfsExtension,
+        self, BlockExtension, FsyncExtension, HardlinkExtension, HomeDirectoryExtension,
+        LimitsExtension, LockFlags, Statvfs, StatvfsExtension, UnblockExtension,
+        VersionSelectExtension,
     },
     protocol::{
         Attrs, Close, Data, Extended, ExtendedReply, FSetStat, FileAttributes, Fstat, Handle, Init,
         Lstat, MkDir, Name, Open, OpenDir, OpenFlags, Packet, Read, ReadDir, ReadLink, RealPath,
-        Remove, Rename, RmDir, SetStat, Stat, Status, StatusCode, Symlink, Version, Write,
+        Remove, Rename, RequestId, RmDir, SetStat, Stat, Status, StatusCode, Symlink, Version,
+        Write, MIN_VERSION, VERSION,
     },
+    ser,
 };
 
 pub type SftpResult<T> = Result<T, Error>;
-type SharedRequests = HashMap<Option<u32>, mpsc::Sender<SftpResult<Packet>>>;
+
+/// Error returned for requests that cannot complete because the stream was lost.
+/// Uses the `SSH_FX_CONNECTION_LOST` pseudo-status which is generated locally
+fn connection_lost(id: Option<RequestId>, reason: &Error) -> Error {
+    Error::from(Status {
+        id: id.unwrap_or_default(),
+        status_code: StatusCode::ConnectionLost,
+        error_message: reason.to_string(),
+        language_tag: "en-US".to_owned(),
+    })
+}
+
+/// Attributes set by the request if any
+fn request_attrs(packet: &Packet) -> Option<&FileAttributes> {
+    match packet {
+        Packet::Open(open) => Some(&open.attrs),
+        Packet::SetStat(setstat) => Some(&setstat.attrs),
+        Packet::FSetStat(fsetstat) => Some(&fsetstat.attrs),
+        Packet::MkDir(mkdir) => Some(&mkdir.attrs),
+        _ => None,
+    }
+}
+
+/// Records the first cause of termination and fails every pending request
+fn close_requests(requests: &SharedRequests, health: &Health, reason: error::Error) {
+    let reason = match reason {
+        error::Error::IO(msg) => CloseReason::Failed(Error::IO(msg)),
+        error::Error::UnexpectedEof => CloseReason::Eof,
+        reason => CloseReason::Failed(reason.into()),
+    };
+
+    health.end(reason);
+    let reason = health
+        .lost()
+        .unwrap_or_else(|| Error::UnexpectedBehavior("session closed".into()));
+    requests::fail_all(requests, |id| connection_lost(id, &reason));
+}
 
 pub(crate) struct SessionInner {
     version: Option<u32>,
     requests: Arc<SharedRequests>,
+    lenient_names: Arc<AtomicBool>,
+    /// Whether attributes with unknown flags were reported
+    unknown_flags_logged: bool,
 }
 
 impl SessionInner {
-    pub async fn reply(&mut self, id: Option<u32>, packet: Packet) -> SftpResult<()> {
-        if let Some(sender) = self.requests.pin().remove(&id) {
+    /// Warns once per session about attributes with flags not defined by version 3,
+    /// whose fields were not decoded
+    fn check_flags<'a, I: IntoIterator<Item = &'a FileAttributes>>(&mut self, attrs: I) {
+        if self.unknown_flags_logged {
+            return;
+        }
+
+        if let Some(flags) = attrs
+            .into_iter()
+            .map(|attrs| attrs.unknown_flags)
+            .find(|flags| *flags != 0)
+        {
+            warn!("the server sent attributes with the unknown flags {flags:#010x}, their fields are ignored");
+            self.unknown_flags_logged = true;
+        }
+    }
+
+    pub async fn reply(&mut self, id: Option<RequestId>, packet: Packet) -> SftpResult<()> {
+        if let Some(sender) = self.requests.remove(id) {
             let validate = if id.is_some() && self.version.is_none() {
                 Err(Error::UnexpectedPacket)
             } else if id.is_none() && self.version.is_some() {
@@ -45,9 +119,9 @@ impl SessionInner {
                 Ok(())
             };
 
-            sender
-                .try_send(validate.clone().map(|_| packet))
-                .map_err(|e| Error::UnexpectedBehavior(e.to_string()))?;
+            if sender.send(validate.clone().map(|_| packet)).is_err() {
+                debug!("discarding the response to detached request {:?}", id)
+            }
 
             return validate;
         }
@@ -63,6 +137,10 @@ impl SessionInner {
 impl Handler for SessionInner {
     type Error = Error;
 
+    fn lenient_names(&self) -> bool {
+        self.lenient_names.load(Ordering::Relaxed)
+    }
+
     async fn version(&mut self, packet: Version) -> Result<(), Self::Error> {
         let version = packet.version;
         self.reply(None, packet.into()).await?;
@@ -71,6 +149,7 @@ impl Handler for SessionInner {
     }
 
     async fn name(&mut self, name: Name) -> Result<(), Self::Error> {
+        self.check_flags(name.files.iter().map(|file| &file.attrs));
         self.reply(Some(name.id), name.into()).await
     }
 
@@ -87,6 +166,7 @@ impl Handler for SessionInner {
     }
 
     async fn attrs(&mut self, attrs: Attrs) -> Result<(), Self::Error> {
+        self.check_flags([&attrs.attrs]);
         self.reply(Some(attrs.id), attrs.into()).await
     }
 
@@ -97,9 +177,12 @@ impl Handler for SessionInner {
 
 #[derive(Debug, Clone, Copy, Default)]
 pub struct Limits {
-    // todo: implement
-    //pub packet_len: Option<u64>,
+    /// Maximum length of a framed packet, including the length and the handle
+    /// of `SSH_FXP_WRITE`
+    pub packet_len: Option<u64>,
+    /// Maximum length of the data of `SSH_FXP_READ`
     pub read_len: Option<u64>,
+    /// Maximum length of the data of `SSH_FXP_WRITE`, without the overhead of the packet
     pub write_len: Option<u64>,
     pub open_handles: Option<u64>,
 }
@@ -107,6 +190,11 @@ pub struct Limits {
 impl From<LimitsExtension> for Limits {
     fn from(limits: LimitsExtension) -> Self {
         Self {
+            packet_len: if limits.max_packet_len > 0 {
+                Some(limits.max_packet_len)
+            } else {
+                None
+            },
             read_len: if limits.max_read_len > 0 {
                 Some(limits.max_read_len)
             } else {
@@ -126,9 +214,115 @@ impl From<LimitsExtension> for Limits {
     }
 }
 
+/// Order in which the paths of `SSH_FXP_SYMLINK` are sent to the server.
+///
+/// The draft specifies `linkpath` followed by `targetpath`, but OpenSSH
+/// implemented them reversed and most servers follow OpenSSH.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SymlinkOrder {
+    /// `linkpath` first as in the specification draft
+    Draft,
+    /// `targetpath` first as implemented by OpenSSH
+    OpenSsh,
+    /// Detected from the `vendor-id` extension of the server.
+    /// Falls back to [`SymlinkOrder::OpenSsh`] if the server does not provide it
+    #[default]
+    Auto,
+}
+
+impl SymlinkOrder {
+    /// Resolves [`SymlinkOrder::Auto`] using the server version packet
+    pub fn detect(version: &Version) -> Self {
+        match version.extensions.get(extensions::VENDOR_ID) {
+            Some(vendor) if !vendor.to_lowercase().contains("openssh") => Self::Draft,
+            _ => Self::OpenSsh,
+        }
+    }
+}
+
+/// Requests of a [`RawSftpSession`] awaiting a response, see
+/// [`RawSftpSession::set_max_inflight`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InflightStats {
+    /// Number of requests sent and not yet answered
+    pub in_flight: usize,
+    /// Number of requests waiting for a slot before being sent
+    pub waiting: usize,
+    /// Maximum number of requests in flight, [`None`] if unlimited
+    pub max_in_flight: Option<usize>,
+}
+
+/// Bounds the number of requests in flight and counts them
+#[derive(Default)]
+struct Inflight {
+    cap: Mutex<Option<(usize, Arc<Semaphore>)>>,
+    in_flight: AtomicUsize,
+    waiting: AtomicUsize,
+}
+
+/// Slot of a request in flight, released on drop
+struct InflightGuard<'a> {
+    inflight: &'a Inflight,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Inflight {
+    async fn acquire(&self) -> InflightGuard<'_> {
+        let semaphore = self
+            .cap
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+            .map(|(_, semaphore)| semaphore.clone());
+
+        let permit = match semaphore {
+            Some(semaphore) => {
+                let _waiting = Waiting::new(&self.waiting);
+                semaphore.acquire_owned().await.ok()
+            }
+            None => None,
+        };
+
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        InflightGuard {
+            inflight: self,
+            _permit: permit,
+        }
+    }
+}
+
+/// Request counted as waiting for a slot until dropped, also when cancelled
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl<'a> Waiting<'a> {
+    fn new(waiting: &'a AtomicUsize) -> Self {
+        waiting.fetch_add(1, Ordering::Relaxed);
+        Self(waiting)
+    }
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Drop for InflightGuard<'_> {
+    fn drop(&mut self) {
+        self.inflight.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
 pub(crate) struct Options {
     timeout: RwLock<u64>,
     limits: Arc<Limits>,
+    symlink_order: RwLock<SymlinkOrder>,
+    detected_symlink_order: OnceLock<SymlinkOrder>,
+    strict_data_len: RwLock<bool>,
+    strict_empty_data: RwLock<bool>,
+    strict_times: RwLock<bool>,
+    #[cfg(feature = "tracing")]
+    record_paths: RwLock<bool>,
 }
 
 /// Implements raw work with the protocol in request-response format.
@@ -137,10 +331,29 @@ pub(crate) struct Options {
 /// the packet is stored as Err.
 pub struct RawSftpSession {
     tx: mpsc::UnboundedSender<Bytes>,
+    /// Number of frames passed to `tx`, locked while sending to keep them in order
+    queued: Mutex<u64>,
+    written: Written,
+    finished: Finished,
+    /// Whether the stream is processed by spawned tasks rather than by the caller
+    spawned: bool,
     requests: Arc<SharedRequests>,
+    health: Arc<Health>,
     next_req_id: AtomicU32,
     handles: AtomicU64,
+    tracker: HandleTracker,
+    oversized_data: AtomicBool,
+    lenient_names: Arc<AtomicBool>,
+    inflight: Inflight,
     options: Options,
+    /// Negotiated protocol version, 0 before SSH_FXP_VERSION
+    version: AtomicU32,
+    /// Offered in SSH_FXP_INIT
+    #[cfg(feature = "compression")]
+    offered_compression: Mutex<Option<Compression>>,
+    /// Accepted in SSH_FXP_VERSION
+    #[cfg(feature = "compression")]
+    compression: OnceLock<Compression>,
 }
 
 macro_rules! into_with_status {
@@ -168,21 +381,148 @@ impl RawSftpSession {
     where
         S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
     {
-        let req_map = Arc::new(HashMap::new());
+        Self::spawned(stream, None, None)
+    }
+
+    /// Same as [`RawSftpSession::new`], but the tasks processing the stream are
+    /// spawned on `tasks` instead of being detached, so that the caller can await
+    /// their termination, for example after [`RawSftpSession::close_session`].
+    /// See also [`RawSftpSession::wait_finished`]
+    pub fn new_with_tasks<S>(stream: S, tasks: &mut JoinSet<()>) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        Self::spawned(stream, None, Some(tasks))
+    }
+
+    /// Creates a session on a stream on which SSH_FXP_INIT and SSH_FXP_VERSION were
+    /// already exchanged by another component, for example the backend connection of
+    /// a proxy. [`RawSftpSession::init`] must not be called, requests can be sent right away.
+    ///
+    /// `server_extensions` are those of the SSH_FXP_VERSION of the server,
+    /// they are used to detect the [`SymlinkOrder`]
+    pub fn attach<S>(
+        stream: S,
+        negotiated_version: u32,
+        server_extensions: HashMap<String, String>,
+    ) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let session = Self::spawned(stream, Some(negotiated_version), None);
+        session.version.store(negotiated_version, Ordering::Relaxed);
+
+        let version = Version {
+            version: negotiated_version,
+            extensions: server_extensions,
+        };
+
+        let _ = session
+            .options
+            .detected_symlink_order
+            .set(SymlinkOrder::detect(&version));
+        session
+    }
+
+    fn spawned<S>(stream: S, version: Option<u32>, tasks: Option<&mut JoinSet<()>>) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (inner, requests, health) = Self::inner(version);
+        let lenient_names = inner.lenient_names.clone();
+        let channels = {
+            let requests = requests.clone();
+            let closed = health.clone();
+            let on_close = move |reason| close_requests(&requests, &closed, reason);
+            run_with_close(stream, inner, health.clone(), on_close, tasks)
+        };
+
+        Self::with_parts(channels, true, requests, health, lenient_names)
+    }
+
+    /// Creates a session on top of the channel with the `sftp` subsystem requested
+    #[cfg(feature = "russh")]
+    pub fn from_channel<M>(channel: russh::Channel<M>) -> Self
+    where
+        M: From<(russh::ChannelId, russh::ChannelMsg)> + Send + Sync + 'static,
+    {
+        Self::new(channel.into_stream())
+    }
+
+    /// Creates a session without spawning any tasks.
+    ///
+    /// The returned future processes the stream and must be driven by the caller
+    /// concurrently with the requests, for example with `tokio::task::spawn_local`
+    /// or in a `select!` loop. It completes when the stream is closed.
+    pub fn new_unspawned<S>(stream: S) -> (Self, impl Future<Output = ()>)
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let (inner, requests, health) = Self::inner(None);
+        let lenient_names = inner.lenient_names.clone();
+        let (channels, future) = {
+            let requests = requests.clone();
+            let closed = health.clone();
+            run_unspawned(stream, inner, health.clone(), move |reason| {
+                close_requests(&requests, &closed, reason)
+            })
+        };
+
+        (
+            Self::with_parts(channels, false, requests, health, lenient_names),
+            future,
+        )
+    }
+
+    fn inner(version: Option<u32>) -> (SessionInner, Arc<SharedRequests>, Arc<Health>) {
+        let requests = Arc::new(SharedRequests::new());
         let inner = SessionInner {
-            version: None,
-            requests: req_map.clone(),
+            version,
+            requests: requests.clone(),
+            lenient_names: Arc::new(AtomicBool::new(false)),
+            unknown_flags_logged: false,
         };
 
+        (inner, requests, Arc::default())
+    }
+
+    fn with_parts(
+        channels: Channels,
+        spawned: bool,
+        requests: Arc<SharedRequests>,
+        health: Arc<Health>,
+        lenient_names: Arc<AtomicBool>,
+    ) -> Self {
         Self {
-            tx: run(stream, inner),
-            requests: req_map,
+            tx: channels.tx,
+            queued: Mutex::new(0),
+            written: channels.written,
+            finished: channels.finished,
+            spawned,
+            requests,
+            health,
             next_req_id: AtomicU32::new(1),
             handles: AtomicU64::new(0),
+            tracker: HandleTracker::default(),
+            oversized_data: AtomicBool::new(false),
+            lenient_names,
+            inflight: Inflight::default(),
             options: Options {
                 timeout: RwLock::new(10),
                 limits: Arc::new(Limits::default()),
+                symlink_order: RwLock::new(SymlinkOrder::default()),
+                detected_symlink_order: OnceLock::new(),
+                strict_data_len: RwLock::new(false),
+                strict_empty_data: RwLock::new(false),
+                strict_times: RwLock::new(false),
+                #[cfg(feature = "tracing")]
+                record_paths: RwLock::new(false),
             },
+            version: AtomicU32::new(0),
+            #[cfg(feature = "compression")]
+            offered_compression: Mutex::new(None),
+            #[cfg(feature = "compression")]
+            compression: OnceLock::new(),
         }
     }
 
@@ -192,58 +532,494 @@ impl RawSftpSession {
         *self.options.timeout.write().await = secs;
     }
 
+    /// Returns the maximum response time in seconds
+    pub async fn timeout(&self) -> u64 {
+        *self.options.timeout.read().await
+    }
+
     /// Setting limits. For the `limits@openssh.com` extension
     pub fn set_limits(&mut self, limits: Arc<Limits>) {
         self.options.limits = limits;
     }
 
-    async fn send(&self, id: Option<u32>, packet: Packet) -> SftpResult<Packet> {
-        if self.tx.is_closed() {
+    /// Limits set with [`RawSftpSession::set_limits`]
+    pub(crate) fn configured_limits(&self) -> Arc<Limits> {
+        self.options.limits.clone()
+    }
+
+    /// Tracks the handles returned from now on, listed by [`RawSftpSession::open_handles_report`].
+    /// A warning is logged about a handle without any request for longer than `idle_warning`,
+    /// checked when a handle is returned and when the report is made. With the
+    /// `handle-backtraces` feature, the backtrace of the request of each handle is captured
+    pub fn track_handles(&self, idle_warning: Option<Duration>) {
+        self.tracker.enable(idle_warning);
+    }
+
+    /// Lists the tracked handles not closed yet, the oldest first,
+    /// empty unless [`RawSftpSession::track_handles`] was called
+    pub fn open_handles_report(&self) -> Vec<TrackedHandle> {
+        self.tracker.report()
+    }
+
+    /// Set the order of paths in `SSH_FXP_SYMLINK`.
+    /// Default: [`SymlinkOrder::Auto`]
+    pub async fn set_symlink_order(&self, order: SymlinkOrder) {
+        *self.options.symlink_order.write().await = order;
+    }
+
+    /// Set whether `SSH_FXP_DATA` with more bytes than requested is rejected.
+    /// Otherwise it is returned as is and a warning is logged once per session.
+    /// Default: `false`
+    pub async fn set_strict_data_len(&self, strict: bool) {
+        *self.options.strict_data_len.write().await = strict;
+    }
+
+    /// Set whether the spans of the requests record their path, which may be
+    /// sensitive. The handles are always recorded. Default: `false`
+    #[cfg(feature = "tracing")]
+    pub async fn set_record_paths(&self, record: bool) {
+        *self.options.record_paths.write().await = record;
+    }
+
+    /// Set whether `SSH_FXP_DATA` without any byte is rejected by [`File`](crate::client::fs::File).
+    /// Otherwise it is taken as the end of file, like `SSH_FX_EOF`.
+    /// Default: `false`
+    pub async fn set_strict_empty_data(&self, strict: bool) {
+        *self.options.strict_empty_data.write().await = strict;
+    }
+
+    /// Set with [`RawSftpSession::set_strict_empty_data`]
+    pub(crate) async fn strict_empty_data(&self) -> bool {
+        *self.options.strict_empty_data.read().await
+    }
+
+    /// Set whether requests with a time after 2106, which does not fit into the 32-bit
+    /// times of version 3, fail with [`Error::TimeOverflow`] without being sent.
+    /// Otherwise such times are sent as `u32::MAX`. Default: `false`
+    pub async fn set_strict_times(&self, strict: bool) {
+        *self.options.strict_times.write().await = strict;
+    }
+
+    /// Set whether `SSH_FXP_NAME` entries without `longname` are accepted.
+    /// Some servers negotiated down to version 3 still omit it as in later versions.
+    /// The `longname` of such entries is empty, use [`File::longname`](crate::protocol::File::longname).
+    /// Default: `false`
+    pub fn set_lenient_names(&self, lenient: bool) {
+        self.lenient_names.store(lenient, Ordering::Relaxed);
+    }
+
+    /// Set the maximum number of requests awaiting a response. Further requests
+    /// wait for a slot before they are sent. Applies to requests sent afterwards,
+    /// `usize::MAX` removes the cap.
+    /// Default: unlimited
+    pub fn set_max_inflight(&self, max: usize) {
+        let cap = (max < Semaphore::MAX_PERMITS).then(|| {
+            let max = max.max(1);
+            (max, Arc::new(Semaphore::new(max)))
+        });
+
+        *self
+            .inflight
+            .cap
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = cap;
+    }
+
+    /// Returns the number of requests in flight and waiting for a slot
+    pub fn inflight_stats(&self) -> InflightStats {
+        InflightStats {
+            in_flight: self.inflight.in_flight.load(Ordering::Relaxed),
+            waiting: self.inflight.waiting.load(Ordering::Relaxed),
+            max_in_flight: self
+                .inflight
+                .cap
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .as_ref()
+                .map(|(max, _)| *max),
+        }
+    }
+
+    /// Set the compression offered to the server in SSH_FXP_INIT, which has to be
+    /// called before [`RawSftpSession::init`]. Only servers of this crate accept it,
+    /// the payloads are sent as is otherwise. See [`Compression`].
+    /// Default: [`None`]
+    #[cfg(feature = "compression")]
+    pub fn set_compression(&self, compression: Option<Compression>) {
+        *self
+            .offered_compression
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = compression;
+    }
+
+    /// Returns the compression negotiated in SSH_FXP_INIT, which applies to
+    /// [`RawSftpSession::read`] and [`RawSftpSession::write`]. Packets sent with
+    /// [`RawSftpSession::send_custom`] have to frame the payloads themselves
+    #[cfg(feature = "compression")]
+    pub fn compression(&self) -> Option<Compression> {
+        self.compression.get().copied()
+    }
+
+    /// Returns the order of paths used for `SSH_FXP_SYMLINK` with
+    /// [`SymlinkOrder::Auto`] resolved
+    pub async fn symlink_order(&self) -> SymlinkOrder {
+        match *self.options.symlink_order.read().await {
+            SymlinkOrder::Auto => self
+                .options
+                .detected_symlink_order
+                .get()
+                .copied()
+                .unwrap_or(SymlinkOrder::OpenSsh),
+            order => order,
+        }
+    }
+
+    /// Returns the cause if the stream was terminated by an I/O error or EOF.
+    /// After that all requests fail immediately with [`StatusCode::ConnectionLost`]
+    pub fn closed_reason(&self) -> Option<Error> {
+        self.health.lost()
+    }
+
+    /// Returns `true` while both halves of the stream are processed and the session
+    /// is not closing, without a round trip. A healthy session may still be stalled,
+    /// see [`SftpSession::ping`](super::SftpSession::ping)
+    pub fn is_healthy(&self) -> bool {
+        self.health.is_healthy()
+    }
+
+    /// Waits until the processing of the stream has ended and returns why,
+    /// see [`RawSftpSession::wait_finished`]
+    pub async fn closed(&self) -> CloseReason {
+        self.wait_finished().await;
+        self.health.reason()
+    }
+
+    /// Sends a pre-built request and returns the response with the same id.
+    ///
+    /// The id is taken from the packet, it is not checked against the ids assigned
+    /// to other requests. As with internal requests, reusing the id of a pending
+    /// request replaces it and the previous caller receives an error.
+    /// Response packets can not be sent
+    pub async fn send_custom(&self, packet: Packet) -> SftpResult<Packet> {
+        let id = match &packet {
+            Packet::Init(_) => None,
+            Packet::Version(_)
+            | Packet::Status(_)
+            | Packet::Handle(_)
+            | Packet::Data(_)
+            | Packet::Name(_)
+            | Packet::Attrs(_)
+            | Packet::ExtendedReply(_) => {
+                return Err(Error::UnexpectedBehavior(format!(
+                    "{} is not a request",
+                    packet.name()
+                )))
+            }
+            packet => Some(packet.get_request_id()),
+        };
+
+        self.send(id, packet).await
+    }
+
+    /// Sends a hand-built frame including the length prefix and returns the response
+    /// with `expected_id`. The frame is not validated, which allows testing servers
+    /// with malformed packets. Ids are handled the same as in [`RawSftpSession::send_custom`]
+    pub async fn send_raw_frame<I>(&self, bytes: Bytes, expected_id: I) -> SftpResult<Packet>
+    where
+        I: Into<RequestId>,
+    {
+        // an empty frame is the signal to close the stream
+        if bytes.is_empty() {
+            return Err(Error::UnexpectedBehavior("empty frame".into()));
+        }
+
+        self.send_frame(Some(expected_id.into()), "raw frame", bytes)
+            .await
+    }
+
+    /// Sends a request and returns an error status as [`Error::Status`]
+    /// with the path or the handle of the request as its target
+    async fn request(&self, id: RequestId, packet: Packet) -> SftpResult<Packet> {
+        if let Some(time) = request_attrs(&packet).and_then(FileAttributes::time_beyond_v3) {
+            if *self.options.strict_times.read().await {
+                return Err(Error::TimeOverflow(time));
+            }
+        }
+
+        if let Some(handle) = packet.handle() {
+            self.tracker.used(handle);
+        }
+
+        let target = packet.target().map(str::to_owned);
+        match self.send(Some(id), packet).await? {
+            Packet::Status(status) if status.status_code != StatusCode::Ok => {
+                Err(Error::Status { status, target })
+            }
+            response => Ok(response),
+        }
+    }
+
+    /// Same as [`RawSftpSession::request`] for the extensions sent with
+    /// [`RawSftpSession::extended`], whose target is passed separately
+    async fn extended_request(
+        &self,
+        request: &str,
+        data: Vec<u8>,
+        target: Option<String>,
+    ) -> SftpResult<Packet> {
+        match self.extended(request, data).await? {
+            Packet::Status(status) if status.status_code != StatusCode::Ok => {
+                Err(Error::Status { status, target })
+            }
+            response => Ok(response),
+        }
+    }
+
+    async fn send(&self, id: Option<RequestId>, packet: Packet) -> SftpResult<Packet> {
+        let name = packet.name();
+        #[cfg(feature = "tracing")]
+        let span = spans::request("client", &packet, *self.options.record_paths.read().await);
+        let sending = self.send_frame(id, name, Bytes::try_from(packet)?);
+
+        // entered until the reply is received
+        #[cfg(feature = "tracing")]
+        let sending = tracing::Instrument::instrument(
+            async move {
+                let result = sending.await;
+                if let Ok(reply) = &result {
+                    spans::reply(&tracing::Span::current(), reply);
+                }
+                result
+            },
+            span,
+        );
+
+        sending.await
+    }
+
+    async fn send_frame(
+        &self,
+        id: Option<RequestId>,
+        name: &str,
+        bytes: Bytes,
+    ) -> SftpResult<Packet> {
+        if let Some(reason) = self.health.lost() {
+            return Err(connection_lost(id, &reason));
+        }
+
+        if !self.health.is_writing() {
             return Err(Error::UnexpectedBehavior("session closed".into()));
         }
 
-        let (tx, mut rx) = mpsc::channel(1);
+        if self.health.is_closing() {
+            return Err(Error::UnexpectedBehavior("session closing".into()));
+        }
+
+        let _slot = self.inflight.acquire().await;
+        let (tx, mut rx) = oneshot::channel();
+
+        self.requests.insert(id, tx);
+
+        // the stream may have been lost before the request was registered
+        if let Some(reason) = self.health.lost() {
+            self.requests.remove(id);
+            return Err(connection_lost(id, &reason));
+        }
 
-        self.requests.pin().insert(id, tx);
-        self.tx.send(Bytes::try_from(packet)?)?;
+        let started = Instant::now();
 
+        let queued = match self.enqueue(bytes) {
+            Ok(queued) => queued,
+            Err(err) => {
+                self.requests.remove(id);
+                return Err(err);
+            }
+        };
         let timeout = *self.options.timeout.read().await;
 
-        match time::timeout(Duration::from_secs(timeout), rx.recv()).await {
-            Ok(Some(result)) => result,
-            Ok(None) => {
-                self.requests.pin().remove(&id);
+        // the timeout starts once the frame is written rather than while it
+        // waits behind other frames, a failure may arrive before that
+        let mut written = self.written.clone();
+        let received = select! {
+            _ = written.wait_for(|written| *written >= queued) => None,
+            result = &mut rx => Some(result),
+        };
+
+        let received = match received {
+            Some(result) => Ok(result),
+            None => time::timeout(Duration::from_secs(timeout), rx).await,
+        };
+
+        let result = match received {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => {
+                self.requests.remove(id);
                 Err(Error::UnexpectedBehavior("recv none message".into()))
             }
             Err(error) => {
-                self.requests.pin().remove(&id);
+                self.requests.remove(id);
                 Err(error.into())
             }
+        };
+
+        match &result {
+            Ok(Packet::Status(status)) => trace!(
+                "{name} {id:?} completed in {:?}: {}",
+                started.elapsed(),
+                status.status_code
+            ),
+            Ok(packet) => trace!(
+                "{name} {id:?} completed in {:?}: {}",
+                started.elapsed(),
+                packet.name()
+            ),
+            Err(err) => trace!("{name} {id:?} failed in {:?}: {err}", started.elapsed()),
         }
+
+        result
+    }
+
+    /// Queues the frame for writing and returns its position in the queue
+    fn enqueue(&self, bytes: Bytes) -> SftpResult<u64> {
+        let mut queued = self.queued.lock().unwrap_or_else(PoisonError::into_inner);
+        self.tx.send(bytes)?;
+
+        *queued += 1;
+        Ok(*queued)
     }
 
-    fn use_next_id(&self) -> u32 {
-        self.next_req_id.fetch_add(1, Ordering::SeqCst)
+    fn use_next_id(&self) -> RequestId {
+        RequestId(self.next_req_id.fetch_add(1, Ordering::SeqCst))
     }
 
     /// Closes the inner channel stream. Called by [`Drop`]
+    ///
+    /// Requests already sent are written out, but their responses are not awaited.
     pub fn close_session(&self) -> SftpResult<()> {
-        if self.tx.is_closed() {
+        self.health.start_closing();
+
+        if !self.health.is_writing() {
             return Ok(());
         }
 
         Ok(self.tx.send(Bytes::new())?)
     }
 
+    /// Waits until the processing of the stream has ended, either because the
+    /// session was closed or the stream was lost. Frames queued before
+    /// [`RawSftpSession::close_session`] have been written out by then.
+    ///
+    /// For a session created with [`RawSftpSession::new_unspawned`] the
+    /// returned future must still be driven concurrently
+    pub async fn wait_finished(&self) {
+        let mut finished = self.finished.clone();
+        while finished.changed().await.is_ok() {}
+    }
+
+    /// Returns `true` if the stream is processed by tasks the session spawned,
+    /// `false` for a session created with [`RawSftpSession::new_unspawned`]
+    pub fn is_spawned(&self) -> bool {
+        self.spawned
+    }
+
+    /// Closes the inner channel stream and waits until the processing
+    /// of the stream has ended, see [`RawSftpSession::wait_finished`]
+    pub async fn close_and_wait(&self) -> SftpResult<()> {
+        self.close_session()?;
+        self.wait_finished().await;
+        Ok(())
+    }
+
+    /// Stops accepting new requests, waits up to `deadline` for pending
+    /// requests to be answered and then closes the inner channel stream.
+    ///
+    /// The stream is closed even if the deadline is exceeded, in which case
+    /// [`Error::Timeout`] is returned.
+    pub async fn close_gracefully(&self, deadline: Duration) -> SftpResult<()> {
+        self.health.start_closing();
+
+        // the requests are failed and removed once the stream is lost
+        let pending = time::timeout(deadline, self.requests.wait_empty()).await;
+
+        self.close_session()?;
+        Ok(pending?)
+    }
+
     pub async fn init(&self) -> SftpResult<Version> {
-        let result = self.send(None, Init::default().into()).await?;
+        let init = Init::default();
+
+        #[cfg(feature = "compression")]
+        let offered = *self
+            .offered_compression
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        #[cfg(feature = "compression")]
+        let init = Init {
+            extensions: offered.map(|_| Compression::offer()).into_iter().collect(),
+            ..init
+        };
+
+        let result = self.send(None, init.into()).await?;
         if let Packet::Version(version) = result {
+            self.version.store(version.version, Ordering::Relaxed);
+            let _ = self
+                .options
+                .detected_symlink_order
+                .set(SymlinkOrder::detect(&version));
+
+            #[cfg(feature = "compression")]
+            if let Some(compression) = offered.filter(|_| {
+                version
+                    .extensions
+                    .get(extensions::COMPRESSION)
+                    .is_some_and(|value| Compression::accepts(value))
+            }) {
+                let _ = self.compression.set(compression);
+            }
+
             Ok(version)
         } else {
             Err(Error::UnexpectedPacket)
         }
     }
 
+    /// Returns the negotiated protocol version, [`None`] before [`RawSftpSession::init`]
+    pub fn negotiated_version(&self) -> Option<u32> {
+        match self.version.load(Ordering::Relaxed) {
+            0 => None,
+            version => Some(version),
+        }
+    }
+
+    /// Switches the session to `version`, such as `"3"`, with the `version-select`
+    /// extension. It must be one of [`Version::versions`] and be sent before any
+    /// other request, servers close the session otherwise.
+    ///
+    /// Versions not implemented by the crate, from [`MIN_VERSION`] to [`VERSION`],
+    /// are refused with [`Error::VersionUnsupported`] without being sent
+    pub async fn version_select(&self, version: &str) -> SftpResult<Status> {
+        let selected = version
+            .parse::<u32>()
+            .ok()
+            .filter(|version| (MIN_VERSION..=VERSION).contains(version))
+            .ok_or_else(|| Error::VersionUnsupported(version.to_owned()))?;
+
+        let result = self
+            .extended_request(
+                extensions::VERSION_SELECT,
+                VersionSelectExtension {
+                    version: version.to_owned(),
+                }
+                .try_into()?,
+                None,
+            )
+            .await?;
+
+        let status = into_status!(result)?;
+        self.version.store(selected, Ordering::Relaxed);
+        Ok(status)
+    }
+
     pub async fn open<T: Into<String>>(
         &self,
         filename: T,
@@ -260,12 +1036,13 @@ impl RawSftpSession {
         }
 
         let id = self.use_next_id();
+        let filename = filename.into();
         let result = self
-            .send(
-                Some(id),
+            .request(
+                id,
                 Open {
                     id,
-                    filename: filename.into(),
+                    filename: filename.clone(),
                     pflags: flags,
                     attrs,
                 }
@@ -273,8 +1050,10 @@ impl RawSftpSession {
             )
             .await?;
 
-        if let Packet::Handle(_) = result {
+        if let Packet::Handle(handle) = &result {
             self.handles.fetch_add(1, Ordering::SeqCst);
+            self.tracker
+                .opened(&handle.handle, "SSH_FXP_OPEN", &filename);
         }
 
         into_with_status!(result, Handle)
@@ -282,16 +1061,17 @@ impl RawSftpSession {
 
     pub async fn close<H: Into<String>>(&self, handle: H) -> SftpResult<Status> {
         let id = self.use_next_id();
-        let result = self
-            .send(
-                Some(id),
-                Close {
-                    id,
-                    handle: handle.into(),
-                }
-                .into(),
-            )
-            .await?;
+        let handle = handle.into();
+        let closing = self.tracker.closing(&handle);
+        let result = self.request(id, Close { id, handle }.into()).await;
+
+        let closed =
+            matches!(&result, Ok(Packet::Status(status)) if status.status_code == StatusCode::Ok);
+        if let (false, Some(closing)) = (closed, closing) {
+            self.tracker.restore(closing);
+        }
+
+        let result = result?;
 
         if let Packet::Status(status) = &result {
             if status.status_code == StatusCode::Ok
@@ -313,6 +1093,38 @@ impl RawSftpSession {
         into_status!(result)
     }
 
+    /// Sends SSH_FXP_CLOSE without waiting for the response, which is discarded.
+    /// Unlike [`RawSftpSession::close`] it does not need a runtime
+    pub(crate) fn close_detached(&self, handle: String) -> SftpResult<()> {
+        if let Some(reason) = self.health.lost() {
+            return Err(connection_lost(None, &reason));
+        }
+
+        let id = self.use_next_id();
+        let (tx, _) = oneshot::channel();
+
+        self.requests.insert(Some(id), tx);
+        let closing = self.tracker.closing(&handle);
+        let enqueued = Bytes::try_from(Packet::from(Close { id, handle }))
+            .map_err(Error::from)
+            .and_then(|bytes| self.enqueue(bytes));
+        if let (Err(err), Some(closing)) = (enqueued, closing) {
+            self.tracker.restore(closing);
+            return Err(err);
+        }
+
+        let _ = self
+            .handles
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |h| h.checked_sub(1));
+
+        Ok(())
+    }
+
+    /// Reads up to `len` bytes at `offset`.
+    ///
+    /// The end of file is reported as [`StatusCode::Eof`], but some servers reply
+    /// with `SSH_FXP_DATA` without any byte instead, which is returned as is. The
+    /// data may also be shorter than requested before the end of file
     pub async fn read<H: Into<String>>(
         &self,
         handle: H,
@@ -325,8 +1137,8 @@ impl RawSftpSession {
 
         let id = self.use_next_id();
         let result = self
-            .send(
-                Some(id),
+            .request(
+                id,
                 Read {
                     id,
                     handle: handle.into(),
@@ -337,9 +1149,41 @@ impl RawSftpSession {
             )
             .await?;
 
-        into_with_status!(result, Data)
+        let data = into_with_status!(result, Data)?;
+
+        #[cfg(feature = "compression")]
+        let data = match self.compression.get() {
+            Some(_) => Data {
+                data: Compression::decompress(data.data, len as usize)
+                    .map_err(Error::UnexpectedBehavior)?,
+                ..data
+            },
+            None => data,
+        };
+
+        if data.data.len() > len as usize {
+            if *self.options.strict_data_len.read().await {
+                return Err(Error::UnexpectedBehavior(format!(
+                    "received {} bytes of data for a read of {len}",
+                    data.data.len()
+                )));
+            }
+
+            if !self.oversized_data.swap(true, Ordering::SeqCst) {
+                warn!(
+                    "server returned {} bytes of data for a read of {len}",
+                    data.data.len()
+                );
+            }
+        }
+
+        Ok(data)
     }
 
+    /// Writes `data` at `offset`.
+    ///
+    /// Fails with [`Error::Limited`] if the data exceeds the write limit or the framed
+    /// packet, with the handle and the other fields, exceeds the packet limit
     pub async fn write<H: Into<String>>(
         &self,
         handle: H,
@@ -355,13 +1199,36 @@ impl RawSftpSession {
             return Err(Error::Limited("write limit reached".to_owned()));
         }
 
+        #[cfg(feature = "compression")]
+        let data = match self.compression.get() {
+            Some(compression) => compression.compress(data),
+            None => data,
+        };
+
+        // the length of the data would wrap in its u32 prefix
+        if ser::wire_len(data.len()).is_err() {
+            return Err(Error::Limited(format!(
+                "write of {} bytes exceeds the u32 length of the protocol",
+                data.len()
+            )));
+        }
+
+        // servers drop the session on a packet over their limit
+        let handle = handle.into();
+        let framed_len = (Write::overhead(handle.len()) + data.len()) as u64;
+        if let Some(packet_len) = self.options.limits.packet_len.filter(|&p| framed_len > p) {
+            return Err(Error::Limited(format!(
+                "write packet of {framed_len} bytes exceeds the packet limit of {packet_len}"
+            )));
+        }
+
         let id = self.use_next_id();
         let result = self
-            .send(
-                Some(id),
+            .request(
+                id,
                 Write {
                     id,
-                    handle: handle.into(),
+                    handle,
                     offset,
                     data,
                 }
@@ -375,8 +1242,8 @@ impl RawSftpSession {
     pub async fn lstat<P: Into<String>>(&self, path: P) -> SftpResult<Attrs> {
         let id = self.use_next_id();
         let result = self
-            .send(
-                Some(id),
+            .request(
+                id,
                 Lstat {
                     id,
                     path: path.into(),
@@ -391,8 +1258,8 @@ impl RawSftpSession {
     pub async fn fstat<H: Into<String>>(&self, handle: H) -> SftpResult<Attrs> {
         let id = self.use_next_id();
         let result = self
-            .send(
-                Some(id),
+            .request(
+                id,
                 Fstat {
                     id,
                     handle: handle.into(),
@@ -411,8 +1278,8 @@ impl RawSftpSession {
     ) -> SftpResult<Status> {
         let id = self.use_next_id();
         let result = self
-            .send(
-                Some(id),
+            .request(
+                id,
                 SetStat {
                     id,
                     path: path.into(),
@@ -432,8 +1299,8 @@ impl RawSftpSession {
     ) -> SftpResult<Status> {
         let id = self.use_next_id();
         let result = self
-            .send(
-                Some(id),
+            .request(
+                id,
                 FSetStat {
                     id,
                     handle: handle.into(),
@@ -457,19 +1324,22 @@ impl RawSftpSession {
         }
 
         let id = self.use_next_id();
+        let path = path.into();
         let result = self
-            .send(
-                Some(id),
+            .request(
+                id,
                 OpenDir {
                     id,
-                    path: path.into(),
+                    path: path.clone(),
                 }
                 .into(),
             )
             .await?;
 
-        if let Packet::Handle(_) = result {
+        if let Packet::Handle(handle) = &result {
             self.handles.fetch_add(1, Ordering::SeqCst);
+            self.tracker
+                .opened(&handle.handle, "SSH_FXP_OPENDIR", &path);
         }
 
         into_with_status!(result, Handle)
@@ -478,8 +1348,8 @@ impl RawSftpSession {
     pub async fn readdir<H: Into<String>>(&self, handle: H) -> SftpResult<Name> {
         let id = self.use_next_id();
         let result = self
-            .send(
-                Some(id),
+            .request(
+                id,
                 ReadDir {
                     id,
                     handle: handle.into(),
@@ -494,8 +1364,8 @@ impl RawSftpSession {
     pub async fn remove<T: Into<String>>(&self, filename: T) -> SftpResult<Status> {
         let id = self.use_next_id();
         let result = self
-            .send(
-                Some(id),
+            .request(
+                id,
                 Remove {
                     id,
                     filename: filename.into(),
@@ -514,8 +1384,8 @@ impl RawSftpSession {
     ) -> SftpResult<Status> {
         let id = self.use_next_id();
         let result = self
-            .send(
-                Some(id),
+            .request(
+                id,
                 MkDir {
                     id,
                     path: path.into(),
@@ -531,8 +1401,8 @@ impl RawSftpSession {
     pub async fn rmdir<P: Into<String>>(&self, path: P) -> SftpResult<Status> {
         let id = self.use_next_id();
         let result = self
-            .send(
-                Some(id),
+            .request(
+                id,
                 RmDir {
                     id,
                     path: path.into(),
@@ -547,8 +1417,8 @@ impl RawSftpSession {
     pub async fn realpath<P: Into<String>>(&self, path: P) -> SftpResult<Name> {
         let id = self.use_next_id();
         let result = self
-            .send(
-                Some(id),
+            .request(
+                id,
                 RealPath {
                     id,
                     path: path.into(),
@@ -563,8 +1433,8 @@ impl RawSftpSession {
     pub async fn stat<P: Into<String>>(&self, path: P) -> SftpResult<Attrs> {
         let id = self.use_next_id();
         let result = self
-            .send(
-                Some(id),
+            .request(
+                id,
                 Stat {
                     id,
                     path: path.into(),
@@ -583,8 +1453,8 @@ impl RawSftpSession {
     {
         let id = self.use_next_id();
         let result = self
-            .send(
-                Some(id),
+            .request(
+                id,
                 Rename {
                     id,
                     oldpath: oldpath.into(),
@@ -600,8 +1470,8 @@ impl RawSftpSession {
     pub async fn readlink<P: Into<String>>(&self, path: P) -> SftpResult<Name> {
         let id = self.use_next_id();
         let result = self
-            .send(
-                Some(id),
+            .request(
+                id,
                 ReadLink {
                     id,
                     path: path.into(),
@@ -613,19 +1483,43 @@ impl RawSftpSession {
         into_with_status!(result, Name)
     }
 
+    /// Sends `SSH_FXP_SYMLINK` with `path` as `linkpath` and `target` as `targetpath`,
+    /// in this order on the wire whatever [`RawSftpSession::set_symlink_order`] says.
+    /// OpenSSH takes the first path as the target, so the link is created at `target`
+    #[deprecated(note = "the meaning of the paths depends on the server, use `create_symlink`")]
     pub async fn symlink<P, T>(&self, path: P, target: T) -> SftpResult<Status>
     where
         P: Into<String>,
         T: Into<String>,
     {
+        self.send_symlink(path.into(), target.into()).await
+    }
+
+    /// Creates a symbolic link at `link` pointing to `target`.
+    /// The paths are sent in the order set by [`RawSftpSession::set_symlink_order`]
+    pub async fn create_symlink<L, T>(&self, link: L, target: T) -> SftpResult<Status>
+    where
+        L: Into<String>,
+        T: Into<String>,
+    {
+        let (linkpath, targetpath) = match self.symlink_order().await {
+            SymlinkOrder::Draft => (link.into(), target.into()),
+            _ => (target.into(), link.into()),
+        };
+
+        self.send_symlink(linkpath, targetpath).await
+    }
+
+    /// Sends the paths in the order of the fields of [`Symlink`]
+    async fn send_symlink(&self, linkpath: String, targetpath: String) -> SftpResult<Status> {
         let id = self.use_next_id();
         let result = self
-            .send(
-                Some(id),
+            .request(
+                id,
                 Symlink {
                     id,
-                    linkpath: path.into(),
-                    targetpath: target.into(),
+                    linkpath,
+                    targetpath,
                 }
                 .into(),
             )
@@ -655,9 +1549,7 @@ impl RawSftpSession {
             Packet::ExtendedReply(reply) => {
                 Ok(de::from_bytes::<LimitsExtension>(&mut reply.data.into())?)
             }
-            Packet::Status(status) if status.status_code != StatusCode::Ok => {
-                Err(Error::Status(status))
-            }
+            Packet::Status(status) if status.status_code != StatusCode::Ok => Err(status.into()),
             _ => Err(Error::UnexpectedPacket),
         }
     }
@@ -667,14 +1559,16 @@ impl RawSftpSession {
         O: Into<String>,
         N: Into<String>,
     {
+        let oldpath = oldpath.into();
         let result = self
-            .extended(
+            .extended_request(
                 extensions::HARDLINK,
                 HardlinkExtension {
-                    oldpath: oldpath.into(),
+                    oldpath: oldpath.clone(),
                     newpath: newpath.into(),
                 }
                 .try_into()?,
+                Some(oldpath),
             )
             .await?;
 
@@ -682,35 +1576,104 @@ impl RawSftpSession {
     }
 
     pub async fn fsync<H: Into<String>>(&self, handle: H) -> SftpResult<Status> {
+        let handle = handle.into();
         let result = self
-            .extended(
+            .extended_request(
                 extensions::FSYNC,
                 FsyncExtension {
-                    handle: handle.into(),
+                    handle: handle.clone(),
                 }
                 .try_into()?,
+                Some(handle),
             )
             .await?;
 
         into_status!(result)
     }
 
+    /// Locks the byte-range of the file using `block@russh-sftp.rs`.
+    /// A `length` of zero locks up to the end of the file
+    pub async fn block<H: Into<String>>(
+        &self,
+        handle: H,
+        offset: u64,
+        length: u64,
+        lock_flags: LockFlags,
+    ) -> SftpResult<Status> {
+        let handle = handle.into();
+        let result = self
+            .extended_request(
+                extensions::BLOCK,
+                BlockExtension {
+                    handle: handle.clone(),
+                    offset,
+                    length,
+                    lock_flags,
+                }
+                .try_into()?,
+                Some(handle),
+            )
+            .await?;
+
+        into_status!(result)
+    }
+
+    /// Unlocks the byte-range previously locked with [`RawSftpSession::block`]
+    pub async fn unblock<H: Into<String>>(
+        &self,
+        handle: H,
+        offset: u64,
+        length: u64,
+    ) -> SftpResult<Status> {
+        let handle = handle.into();
+        let result = self
+            .extended_request(
+                extensions::UNBLOCK,
+                UnblockExtension {
+                    handle: handle.clone(),
+                    offset,
+                    length,
+                }
+                .try_into()?,
+                Some(handle),
+            )
+            .await?;
+
+        into_status!(result)
+    }
+
+    /// Requests the home directory of the user using `home-directory`.
+    /// An empty `username` is the user of the session
+    pub async fn home_directory<U: Into<String>>(&self, username: U) -> SftpResult<Name> {
+        let result = self
+            .extended(
+                extensions::HOME_DIRECTORY,
+                HomeDirectoryExtension {
+                    username: username.into(),
+                }
+                .try_into()?,
+            )
+            .await?;
+
+        into_with_status!(result, Name)
+    }
+
     pub async fn statvfs<P>(&self, path: P) -> SftpResult<Statvfs>
     where
         P: Into<String>,
     {
+        let path = path.into();
         let result = self
-            .extended(
+            .extended_request(
                 extensions::STATVFS,
-                StatvfsExtension { path: path.into() }.try_into()?,
+                StatvfsExtension { path: path.clone() }.try_into()?,
+                Some(path),
             )
             .await?;
 
         match result {
             Packet::ExtendedReply(reply) => Ok(de::from_bytes::<Statvfs>(&mut reply.data.into())?),
-            Packet::Status(status) if status.status_code != StatusCode::Ok => {
-                Err(Error::Status(status))
-            }
+            Packet::Status(status) if status.status_code != StatusCode::Ok => Err(status.into()),
             _ => Err(Error::UnexpectedPacket),
         }
     }
@@ -0,0 +1,137 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, MutexGuard, PoisonError,
+    },
+};
+use tokio::sync::{oneshot, Notify};
+
+use super::{error::Error, rawsession::SftpResult};
+use crate::protocol::{Packet, RequestId};
+
+pub(crate) type Sender = oneshot::Sender<SftpResult<Packet>>;
+
+/// Routing of responses to the pending requests by id.
+/// The id of SSH_FXP_INIT is [`None`]
+pub(crate) trait RequestMap: Send + Sync {
+    /// Registers the request replacing a pending one with the same id
+    fn insert(&self, id: Option<RequestId>, sender: Sender);
+    fn remove(&self, id: Option<RequestId>) -> Option<Sender>;
+    fn is_empty(&self) -> bool;
+    /// Removes and returns all pending requests
+    fn drain(&self) -> Vec<(Option<RequestId>, Sender)>;
+}
+
+/// Implementation used by the sessions
+pub(crate) type SharedRequests = ShardedRequests;
+
+const SHARDS: usize = 16;
+
+/// Requests spread over mutex-protected maps by id, so that concurrent
+/// requests rarely contend and no memory is reclaimed in the background
+pub(crate) struct ShardedRequests {
+    init: Mutex<Option<Sender>>,
+    shards: [Mutex<HashMap<RequestId, Sender>>; SHARDS],
+    /// Number of pending requests, waiters are notified when it drops to zero
+    pending: AtomicUsize,
+    emptied: Notify,
+}
+
+impl ShardedRequests {
+    pub fn new() -> Self {
+        Self {
+            init: Mutex::new(None),
+            shards: Default::default(),
+            pending: AtomicUsize::new(0),
+            emptied: Notify::new(),
+        }
+    }
+
+    /// Waits until no request is pending
+    pub async fn wait_empty(&self) {
+        let emptied = self.emptied.notified();
+        tokio::pin!(emptied);
+        // registered before checking, so that a removal in between is not missed
+        emptied.as_mut().enable();
+
+        if !self.is_empty() {
+            emptied.await;
+        }
+    }
+
+    fn added(&self, replaced: bool) {
+        if !replaced {
+            self.pending.fetch_add(1, Ordering::AcqRel);
+        }
+    }
+
+    fn removed(&self, count: usize) {
+        if count > 0 && self.pending.fetch_sub(count, Ordering::AcqRel) == count {
+            self.emptied.notify_waiters();
+        }
+    }
+
+    fn shard(&self, id: RequestId) -> MutexGuard<'_, HashMap<RequestId, Sender>> {
+        lock(&self.shards[id.0 as usize % SHARDS])
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl RequestMap for ShardedRequests {
+    fn insert(&self, id: Option<RequestId>, sender: Sender) {
+        match id {
+            Some(id) => {
+                let mut shard = self.shard(id);
+                self.added(shard.insert(id, sender).is_some());
+            }
+            None => {
+                let mut init = lock(&self.init);
+                self.added(init.replace(sender).is_some());
+            }
+        }
+    }
+
+    fn remove(&self, id: Option<RequestId>) -> Option<Sender> {
+        let sender = match id {
+            Some(id) => self.shard(id).remove(&id),
+            None => lock(&self.init).take(),
+        };
+
+        self.removed(sender.is_some().into());
+        sender
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pending.load(Ordering::Acquire) == 0
+    }
+
+    fn drain(&self) -> Vec<(Option<RequestId>, Sender)> {
+        let mut requests: Vec<_> = lock(&self.init)
+            .take()
+            .map(|s| (None, s))
+            .into_iter()
+            .collect();
+
+        for shard in &self.shards {
+            requests.extend(lock(shard).drain().map(|(id, s)| (Some(id), s)));
+        }
+
+        self.removed(requests.len());
+        requests
+    }
+}
+
+/// Fails all pending requests with the error built for each id
+pub(crate) fn fail_all<M, F>(requests: &M, error: F)
+where
+    M: RequestMap + ?Sized,
+    F: Fn(Option<RequestId>) -> Error,
+{
+    for (id, sender) in requests.drain() {
+        let _ = sender.send(Err(error(id)));
+    }
+}
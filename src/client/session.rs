@@ -1,30 +1,138 @@
-use std::sync::Arc;
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use std::{
+    collections::HashSet,
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    task::JoinSet,
+    time,
+};
 
+#[cfg(feature = "glob")]
+use super::glob::Glob;
 use super::{
+    access::{Access, AccessCheck, Identity},
+    builder::{InitProbe, SessionBuilder},
     error::Error,
-    fs::{File, Metadata, ReadDir},
-    rawsession::{Limits, SftpResult},
-    RawSftpSession,
+    fs::{self, Bandwidth, Direction, File, Metadata, ReadDir, ReadDirStream},
+    path::RemoteFlavor,
+    path_cache::{self, PathCache, PathCacheStats},
+    rawsession::{InflightStats, Limits, SftpResult, SymlinkOrder},
+    summary::{self, DirSummary, SummaryOptions},
+    temp::{self, TempDir},
+    tracker::TrackedHandle,
+    watch::Watch,
+    CloseReason, RawSftpSession,
 };
+#[cfg(feature = "server")]
+use crate::server;
 use crate::{
-    extensions::{self, Statvfs},
-    protocol::{FileAttributes, OpenFlags, StatusCode},
+    extensions::{self, Capability, ServerCapabilities, Statvfs},
+    protocol::{AclEntries, FileAttributes, FileType, OpenFlags, StatusCode},
 };
+#[cfg(feature = "server")]
+use tokio::task::JoinHandle;
+
+/// Buffer of each direction of the stream of [`SftpSession::pair`], which holds a
+/// packet of the default read or write length with its header
+#[cfg(feature = "server")]
+pub const PAIR_BUFFER_LEN: usize = 512 * 1024;
 
 #[derive(Debug, Default)]
 pub(crate) struct Extensions {
     pub hardlink: bool,
     pub fsync: bool,
     pub statvfs: bool,
+    pub block: bool,
+    pub home_directory: bool,
     pub limits: Option<Arc<Limits>>,
+    /// Versions advertised in the `versions` extension
+    pub versions: Vec<u32>,
+    pub advertised: ServerCapabilities,
+    /// Advertised extensions whose requests were rejected as unsupported,
+    /// for example by proxies that forward the version packet only
+    pub rejected: Mutex<HashSet<Capability>>,
+}
+
+impl Extensions {
+    fn advertised(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::Limits => self.limits.is_some(),
+            Capability::Hardlink => self.hardlink,
+            Capability::Fsync => self.fsync,
+            Capability::Statvfs => self.statvfs,
+            Capability::Block => self.block,
+            Capability::HomeDirectory => self.home_directory,
+        }
+    }
+
+    /// Returns `true` if the extension is advertised and was not rejected
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.advertised(capability) && !self.rejected.lock().unwrap().contains(&capability)
+    }
+
+    /// Disables the extension once the server rejects its request as unsupported,
+    /// which is then returned as [`Error::ExtensionUnsupported`]
+    pub fn check<T>(&self, capability: Capability, result: SftpResult<T>) -> SftpResult<T> {
+        match result {
+            Err(Error::Status { status, .. })
+                if status.status_code == StatusCode::OpUnsupported =>
+            {
+                if self.rejected.lock().unwrap().insert(capability) {
+                    debug!(
+                        "{} is advertised but rejected by the server, disabled",
+                        capability.name()
+                    );
+                }
+
+                Err(Error::ExtensionUnsupported {
+                    name: capability.name(),
+                })
+            }
+            result => result,
+        }
+    }
+}
+
+/// Settings and caches of a session shared by its clones
+struct Shared {
+    path_cache: Mutex<Option<PathCache>>,
+    create_mode: Mutex<Option<u32>>,
+    adaptive_chunks: AtomicBool,
+    bandwidth: Arc<Bandwidth>,
+    flavor: Mutex<RemoteFlavor>,
+    flavor_pinned: AtomicBool,
+    identity: Mutex<Option<Identity>>,
+    /// Working directory resolved by [`InitProbe::RealpathDot`]
+    initial_dir: Option<String>,
 }
 
 /// High-level SFTP implementation for easy interaction with a remote file system.
 /// Contains most methods similar to the native [filesystem](std::fs)
+///
+/// # Concurrency
+/// The session is cheap to clone, the clones share the connection, the settings and
+/// the path cache, so that a setting changed through one clone applies to all of them
+/// and [`SftpSession::close`] closes the connection of all of them. It can also be
+/// shared by reference or in an [`Arc`].
+///
+/// Requests of concurrent tasks are sent as soon as they are issued and their replies
+/// are matched by request id, no lock is held while a reply is awaited. The requests
+/// of a task are sent in the order they are awaited, but there is no order between
+/// the requests of concurrent tasks. Servers such as OpenSSH process the requests in
+/// the order received, still the specification allows replies out of order, so a
+/// request depending on the effect of another one should be sent once it completed.
+/// A [`File`] is owned by one task, its operations are sequential
+#[derive(Clone)]
 pub struct SftpSession {
     session: Arc<RawSftpSession>,
     extensions: Arc<Extensions>,
+    shared: Arc<Shared>,
 }
 
 impl SftpSession {
@@ -36,64 +144,435 @@ impl SftpSession {
         Self::new_opts(stream, None).await
     }
 
+    /// Same as [`SftpSession::new`], but the tasks processing the stream are spawned
+    /// on `tasks`, so that the caller can await their termination after
+    /// [`SftpSession::close`]. See [`RawSftpSession::new_with_tasks`]
+    pub async fn new_with_tasks<S>(stream: S, tasks: &mut JoinSet<()>) -> SftpResult<Self>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        Self::with_raw_session(RawSftpSession::new_with_tasks(stream, tasks)).await
+    }
+
+    /// Creates a new session on top of the channel with the `sftp` subsystem requested
+    #[cfg(feature = "russh")]
+    pub async fn from_channel<M>(channel: russh::Channel<M>) -> SftpResult<Self>
+    where
+        M: From<(russh::ChannelId, russh::ChannelMsg)> + Send + Sync + 'static,
+    {
+        Self::new(channel.into_stream()).await
+    }
+
+    /// Creates a session connected to `handler` over an in-memory stream, without SSH
+    /// or sockets, for tests and local pipelines. See [`SftpSession::pair_with_config`]
+    #[cfg(feature = "server")]
+    pub async fn pair<H>(handler: H) -> SftpResult<(Self, JoinHandle<Result<(), crate::Error>>)>
+    where
+        H: server::Handler + Send + 'static,
+    {
+        Self::pair_with_config(handler, server::Config::default()).await
+    }
+
+    /// Same as [`SftpSession::pair`] with the specified [`server::Config`].
+    ///
+    /// The server runs on a spawned task, whose handle completes once the session is
+    /// closed and can abort it. Both ends of the stream are read concurrently, so any
+    /// buffer size is free of deadlocks; [`PAIR_BUFFER_LEN`] bytes per direction hold
+    /// a whole read or write of the default length
+    #[cfg(feature = "server")]
+    pub async fn pair_with_config<H>(
+        handler: H,
+        config: server::Config,
+    ) -> SftpResult<(Self, JoinHandle<Result<(), crate::Error>>)>
+    where
+        H: server::Handler + Send + 'static,
+    {
+        let (client, stream) = tokio::io::duplex(PAIR_BUFFER_LEN);
+        let server = tokio::spawn(server::serve(stream, handler, config));
+
+        match Self::new(client).await {
+            Ok(session) => Ok((session, server)),
+            Err(err) => {
+                server.abort();
+                Err(err)
+            }
+        }
+    }
+
     /// Creates a new session with timeout opt before the first request
     pub async fn new_opts<S>(stream: S, timeout: Option<u64>) -> SftpResult<Self>
     where
         S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
     {
-        let mut session = RawSftpSession::new(stream);
+        let builder = match timeout {
+            Some(timeout) => SessionBuilder::new().timeout(timeout),
+            None => SessionBuilder::new(),
+        };
 
-        // todo: for new options we need builder
-        if let Some(timeout) = timeout {
-            session.set_timeout(timeout).await;
-        }
+        builder.connect(stream).await
+    }
+
+    /// Returns a builder of a session with the options of its initialization
+    pub fn builder() -> SessionBuilder {
+        SessionBuilder::new()
+    }
+
+    /// Creates a new session on top of a raw session that has not been initialized yet,
+    /// for example one created by [`RawSftpSession::new_unspawned`]
+    pub async fn with_raw_session(session: RawSftpSession) -> SftpResult<Self> {
+        Self::initialize(session, InitProbe::None).await
+    }
 
+    pub(crate) async fn initialize(
+        mut session: RawSftpSession,
+        init_probe: InitProbe,
+    ) -> SftpResult<Self> {
         let version = session.init().await?;
+
+        let initial_dir = match init_probe.realpath_dot(&version) {
+            true => match session.realpath(".").await {
+                Ok(mut name) if !name.files.is_empty() => Some(name.files.remove(0).filename),
+                Ok(_) => None,
+                Err(err) => {
+                    debug!("realpath of . sent after the version failed: {err}");
+                    None
+                }
+            },
+            false => None,
+        };
+
         let mut extensions = Extensions {
-            hardlink: version
-                .extensions
-                .get(extensions::HARDLINK)
-                .is_some_and(|e| e == "1"),
-            fsync: version
-                .extensions
-                .get(extensions::FSYNC)
-                .is_some_and(|e| e == "1"),
-            statvfs: version
-                .extensions
-                .get(extensions::STATVFS)
-                .is_some_and(|e| e == "2"),
+            hardlink: version.supports(extensions::HARDLINK, "1"),
+            fsync: version.supports(extensions::FSYNC, "1"),
+            statvfs: version.supports(extensions::STATVFS, "2"),
+            block: [extensions::BLOCK, extensions::UNBLOCK]
+                .iter()
+                .all(|name| version.supports(name, "1")),
+            home_directory: version.supports(extensions::HOME_DIRECTORY, "1"),
             limits: None,
+            versions: version.versions(),
+            advertised: version.capabilities(),
+            rejected: Mutex::default(),
         };
 
-        if version
-            .extensions
-            .get(extensions::LIMITS)
-            .is_some_and(|e| e == "1")
-        {
-            let limits = session.limits().await?;
-            let limits = Arc::new(Limits::from(limits));
+        if version.supports(extensions::LIMITS, "1") {
+            match extensions.check(Capability::Limits, session.limits().await) {
+                Ok(limits) => {
+                    let limits = Arc::new(Limits::from(limits));
 
-            session.set_limits(limits.clone());
-            extensions.limits = Some(limits);
+                    session.set_limits(limits.clone());
+                    extensions.limits = Some(limits);
+                }
+                Err(Error::ExtensionUnsupported { .. }) => (),
+                Err(err) => return Err(err),
+            }
         }
 
         Ok(Self {
             session: Arc::new(session),
             extensions: Arc::new(extensions),
+            shared: Arc::new(Shared {
+                path_cache: Mutex::new(None),
+                create_mode: Mutex::new(None),
+                adaptive_chunks: AtomicBool::new(false),
+                bandwidth: Arc::new(Bandwidth::default()),
+                flavor: Mutex::new(RemoteFlavor::detect(&version)),
+                flavor_pinned: AtomicBool::new(false),
+                identity: Mutex::new(None),
+                initial_dir,
+            }),
         })
     }
 
+    /// Returns `true` if the server advertised support of the extension and
+    /// did not reject its requests, see [`SftpSession::rejected_capabilities`]
+    pub fn has_capability(&self, capability: Capability) -> bool {
+        self.extensions.supports(capability)
+    }
+
+    /// Returns the extensions advertised by the server whose requests were answered
+    /// with [`StatusCode::OpUnsupported`], as some proxies forward the version packet
+    /// but not the extensions. They are no longer sent by the session and its files,
+    /// the operations behave as without the extension
+    pub fn rejected_capabilities(&self) -> Vec<Capability> {
+        self.extensions
+            .rejected
+            .lock()
+            .unwrap()
+            .iter()
+            .copied()
+            .collect()
+    }
+
+    /// Enables again the extensions rejected by the server,
+    /// for example once the connection no longer goes through a proxy
+    pub fn reset_rejected_capabilities(&self) {
+        self.extensions.rejected.lock().unwrap().clear();
+    }
+
+    /// Returns [`Error::ExtensionUnsupported`] if the server
+    /// did not advertise support of the extension or rejected it
+    pub fn require_capability(&self, capability: Capability) -> SftpResult<()> {
+        if !self.has_capability(capability) {
+            return Err(Error::ExtensionUnsupported {
+                name: capability.name(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Returns the canonical working directory resolved right after SSH_FXP_VERSION
+    /// if [`SessionBuilder::init_probe`] sent SSH_FXP_REALPATH of `.`
+    pub fn initial_dir(&self) -> Option<&str> {
+        self.shared.initial_dir.as_deref()
+    }
+
+    /// Returns the protocol versions the server advertised in the `versions` extension,
+    /// empty if it did not. See [`RawSftpSession::version_select`] to switch to one
+    pub fn server_versions(&self) -> &[u32] {
+        &self.extensions.versions
+    }
+
+    /// Returns the extensions the server advertised in SSH_FXP_VERSION, including
+    /// the informational ones this crate does not act upon
+    pub fn server_capabilities(&self) -> &ServerCapabilities {
+        &self.extensions.advertised
+    }
+
     /// Set the maximum response time in seconds.
     /// Default: 10 seconds
     pub async fn set_timeout(&self, secs: u64) {
         self.session.set_timeout(secs).await;
     }
 
-    /// Closes the inner channel stream.
+    /// Set the order of paths in `SSH_FXP_SYMLINK`.
+    /// Default: [`SymlinkOrder::Auto`]
+    pub async fn set_symlink_order(&self, order: SymlinkOrder) {
+        self.session.set_symlink_order(order).await;
+    }
+
+    /// Returns the order of paths used by [`SftpSession::create_symlink`],
+    /// with [`SymlinkOrder::Auto`] resolved
+    pub async fn symlink_order(&self) -> SymlinkOrder {
+        self.session.symlink_order().await
+    }
+
+    /// Tracks the handles of the files and directories opened from now on,
+    /// see [`RawSftpSession::track_handles`]
+    pub fn track_handles(&self, idle_warning: Option<Duration>) {
+        self.session.track_handles(idle_warning);
+    }
+
+    /// Lists the tracked handles not closed yet, the oldest first
+    pub fn open_handles_report(&self) -> Vec<TrackedHandle> {
+        self.session.open_handles_report()
+    }
+
+    /// Set whether `SSH_FXP_DATA` with more bytes than requested is rejected.
+    /// Default: `false`
+    pub async fn set_strict_data_len(&self, strict: bool) {
+        self.session.set_strict_data_len(strict).await;
+    }
+
+    /// Set whether the spans of the requests record their path, which may be
+    /// sensitive. Default: `false`
+    #[cfg(feature = "tracing")]
+    pub async fn set_record_paths(&self, record: bool) {
+        self.session.set_record_paths(record).await;
+    }
+
+    /// Set whether `SSH_FXP_DATA` without any byte is rejected by [`File`].
+    /// Otherwise it is taken as the end of file.
+    /// Default: `false`
+    pub async fn set_strict_empty_data(&self, strict: bool) {
+        self.session.set_strict_empty_data(strict).await;
+    }
+
+    /// Set whether requests with a time after 2106 fail with [`Error::TimeOverflow`]
+    /// instead of sending it as `u32::MAX`, the maximum of version 3.
+    /// Default: `false`
+    pub async fn set_strict_times(&self, strict: bool) {
+        self.session.set_strict_times(strict).await;
+    }
+
+    /// Set whether `SSH_FXP_NAME` entries without `longname` are accepted.
+    /// Default: `false`
+    pub fn set_lenient_names(&self, lenient: bool) {
+        self.session.set_lenient_names(lenient);
+    }
+
+    /// Set the maximum number of requests awaiting a response, see
+    /// [`RawSftpSession::set_max_inflight`].
+    /// Default: unlimited
+    pub fn set_max_inflight(&self, max: usize) {
+        self.session.set_max_inflight(max);
+    }
+
+    /// Returns the number of requests in flight and waiting for a slot
+    pub fn inflight_stats(&self) -> InflightStats {
+        self.session.inflight_stats()
+    }
+
+    /// Set the permissions sent when a file is created by [`SftpSession::create`]
+    /// or [`SftpSession::open_with_flags`] with [`OpenFlags::CREATE`].
+    /// Default: [`None`], the server decides
+    pub fn set_default_create_mode(&self, mode: Option<u32>) {
+        *self.shared.create_mode.lock().unwrap() = mode;
+    }
+
+    /// Set whether files opened afterwards adjust the size of read and write
+    /// requests to the latency of the link, between 4 KB and the server limits.
+    /// Otherwise the largest allowed size is used. See [`File::chunk_size`].
+    /// Default: `false`
+    pub fn set_adaptive_chunks(&self, adaptive: bool) {
+        self.shared
+            .adaptive_chunks
+            .store(adaptive, Ordering::Relaxed);
+    }
+
+    /// Set the maximum number of payload bytes per second read or written by the
+    /// [`File`]s of the session, including the transfers of [`TransferScheduler`],
+    /// or [`None`] to remove the limit. Requests of [`RawSftpSession`] are not limited.
+    ///
+    /// Chunks wait for the quota earned since the previous ones, so the transfers
+    /// are paced over the second rather than bursting. A change applies to the
+    /// chunks already waiting.
+    /// Default: [`None`]
+    ///
+    /// [`TransferScheduler`]: crate::client::TransferScheduler
+    pub fn set_bandwidth_limit(&self, bytes_per_sec: Option<u64>, direction: Direction) {
+        self.shared.bandwidth.set_limit(direction, bytes_per_sec);
+    }
+
+    /// Returns the bandwidth limit of the direction in bytes per second
+    pub fn bandwidth_limit(&self, direction: Direction) -> Option<u64> {
+        self.shared.bandwidth.limit(direction)
+    }
+
+    /// Set the path conventions of the server, disabling the detection.
+    /// The paths of all the operations are normalized accordingly.
+    /// Default: detected from the version packet and switched to
+    /// [`RemoteFlavor::Windows`] once [`SftpSession::canonicalize`]
+    /// returns a path with a drive letter
+    pub fn set_remote_flavor(&self, flavor: RemoteFlavor) {
+        *self.shared.flavor.lock().unwrap() = flavor;
+        self.shared.flavor_pinned.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns the path conventions of the server in use
+    pub fn remote_flavor(&self) -> RemoteFlavor {
+        *self.shared.flavor.lock().unwrap()
+    }
+
+    /// Normalizes the path of a request according to the remote flavor
+    fn remote_path<P: Into<String>>(&self, path: P) -> String {
+        self.remote_flavor().normalize(&path.into())
+    }
+
+    /// Set the user the session is logged in as, used by [`SftpSession::access`]
+    /// and the filters of [`ReadDir`].
+    /// Default: [`None`], only permissions shared by the owner, the group and
+    /// the others are conclusive
+    pub fn set_identity(&self, identity: Option<Identity>) {
+        *self.shared.identity.lock().unwrap() = identity;
+    }
+
+    /// Returns the user set with [`SftpSession::set_identity`]
+    pub fn identity(&self) -> Option<Identity> {
+        self.shared.identity.lock().unwrap().clone()
+    }
+
+    /// Enables caching of [`SftpSession::canonicalize`] results holding up to
+    /// `capacity` paths. The least recently used entry is evicted first.
+    /// Calling it again resets the cache and its counters.
+    ///
+    /// Paths are keyed without their `.` components and repeated or trailing `/`.
+    /// Paths with `..` are always sent to the server, and removing or renaming one
+    /// through the session drops the whole cache.
+    pub fn enable_path_cache(&self, capacity: usize) {
+        *self.shared.path_cache.lock().unwrap() = Some(PathCache::new(capacity));
+    }
+
+    /// Disables and clears the canonicalization cache.
+    pub fn disable_path_cache(&self) {
+        *self.shared.path_cache.lock().unwrap() = None;
+    }
+
+    /// Returns hit and miss counters of the canonicalization cache.
+    /// Returns [`None`] if the cache is not enabled.
+    pub fn path_cache_stats(&self) -> Option<PathCacheStats> {
+        self.shared
+            .path_cache
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|c| c.stats())
+    }
+
+    fn cached_path(&self, path: &str) -> Option<String> {
+        self.shared.path_cache.lock().unwrap().as_mut()?.get(path)
+    }
+
+    fn cache_path(&self, path: &str, canonical: String) {
+        if let Some(cache) = self.shared.path_cache.lock().unwrap().as_mut() {
+            cache.insert(path, canonical);
+        }
+    }
+
+    fn invalidate_path(&self, prefix: &str) {
+        if let Some(cache) = self.shared.path_cache.lock().unwrap().as_mut() {
+            cache.invalidate(prefix);
+        }
+    }
+
+    /// Closes the inner channel stream after waiting for requests in flight.
+    ///
+    /// New requests are rejected immediately. Pending requests are awaited for at most
+    /// the response timeout set by [`SftpSession::set_timeout`]. Returns once the
+    /// stream was closed and nothing more is written to it, within the same timeout.
+    ///
+    /// The stream of a session created with [`RawSftpSession::new_unspawned`] is
+    /// processed by the caller, which may be the one awaiting this call, so it is
+    /// not waited for. Await [`RawSftpSession::wait_finished`] concurrently instead.
     pub async fn close(&self) -> SftpResult<()> {
+        let timeout = Duration::from_secs(self.session.timeout().await);
+        let result = self.session.close_gracefully(timeout).await;
+
+        if self.session.is_spawned() {
+            time::timeout(timeout, self.session.wait_finished()).await?;
+        }
+        result
+    }
+
+    /// Closes the inner channel stream without waiting for requests in flight.
+    pub fn close_immediately(&self) -> SftpResult<()> {
         self.session.close_session()
     }
 
+    /// Returns a future resolving once the processing of the stream has ended, with
+    /// the reason. It does not borrow the session, so that it can be awaited by a
+    /// separate task such as a health probe
+    pub fn closed(&self) -> impl Future<Output = CloseReason> + Send + 'static {
+        let session = self.session.clone();
+        async move { session.closed().await }
+    }
+
+    /// Returns `true` while the stream is processed and the session is not closing,
+    /// without a round trip. See [`RawSftpSession::is_healthy`]
+    pub fn is_healthy(&self) -> bool {
+        self.session.is_healthy()
+    }
+
+    /// Sends SSH_FXP_REALPATH of `.` and returns the round trip time, for health checks.
+    /// Fails with [`Error::Timeout`] if the server does not reply within `timeout`,
+    /// which is usually shorter than the timeout of the requests
+    pub async fn ping(&self, timeout: Duration) -> SftpResult<Duration> {
+        let started = Instant::now();
+        time::timeout(timeout, self.session.realpath(".")).await??;
+        Ok(started.elapsed())
+    }
+
     /// Attempts to open a file in read-only mode.
     pub async fn open<T: Into<String>>(&self, filename: T) -> SftpResult<File> {
         self.open_with_flags(filename, OpenFlags::READ).await
@@ -110,36 +589,169 @@ impl SftpSession {
         .await
     }
 
+    /// Opens a file in write-only mode to append to it.
+    ///
+    /// This function will create a file if it does not exist. Writes land at the end
+    /// of the file, see [`File`] for the handling of [`OpenFlags::APPEND`].
+    pub async fn open_append<T: Into<String>>(&self, filename: T) -> SftpResult<File> {
+        self.open_with_flags(
+            filename,
+            OpenFlags::CREATE | OpenFlags::APPEND | OpenFlags::WRITE,
+        )
+        .await
+    }
+
     /// Attempts to open or create the file in the specified mode
+    ///
+    /// No attributes are sent, except for the permissions set by
+    /// [`SftpSession::set_default_create_mode`] if the file may be created.
     pub async fn open_with_flags<T: Into<String>>(
         &self,
         filename: T,
         flags: OpenFlags,
     ) -> SftpResult<File> {
-        self.open_with_flags_and_attributes(filename, flags, FileAttributes::empty())
+        let mut attributes = FileAttributes::empty();
+        if flags.contains(OpenFlags::CREATE) {
+            attributes.permissions = *self.shared.create_mode.lock().unwrap();
+        }
+
+        self.open_with_flags_and_attributes(filename, flags, attributes)
             .await
     }
 
-    /// Attempts to open or create the file in the specified mode and with specified file attributes
+    /// Attempts to open or create the file in the specified mode and with specified file attributes.
+    /// The attributes are sent as is, use [`FileAttributes::empty`] to leave them to the server
     pub async fn open_with_flags_and_attributes<T: Into<String>>(
         &self,
         filename: T,
         flags: OpenFlags,
         attributes: FileAttributes,
     ) -> SftpResult<File> {
+        let filename = self.remote_path(filename);
         let handle = self.session.open(filename, flags, attributes).await?.handle;
-        Ok(File::new(
+        let mut file = File::new(
             self.session.clone(),
             handle,
             self.extensions.clone(),
-        ))
+            self.shared.adaptive_chunks.load(Ordering::Relaxed),
+            self.shared.bandwidth.clone(),
+        );
+
+        // servers ignore the flag for the explicit offsets of the writes
+        if flags.contains(OpenFlags::APPEND) {
+            let Some(end) = file.metadata().await?.size else {
+                return Err(Error::UnexpectedBehavior(
+                    "file size unknown, cannot append".to_owned(),
+                ));
+            };
+
+            file.append = Some(end);
+            file.pos = end;
+        }
+
+        Ok(file)
+    }
+
+    /// Opens the file in the specified mode with the position set to `offset`.
+    /// Useful for resuming after the handle of a file has become stale
+    pub async fn open_at_offset<T: Into<String>>(
+        &self,
+        filename: T,
+        flags: OpenFlags,
+        offset: u64,
+    ) -> SftpResult<File> {
+        let mut file = self.open_with_flags(filename, flags).await?;
+        file.pos = offset;
+        Ok(file)
     }
 
     /// Requests the remote party for the absolute from the relative path.
+    ///
+    /// If the path cache is enabled, repeated calls for the same path are
+    /// answered without a round trip. Both paths are normalized according
+    /// to [`SftpSession::remote_flavor`].
     pub async fn canonicalize<T: Into<String>>(&self, path: T) -> SftpResult<String> {
-        let name = self.session.realpath(path).await?;
+        let path = self.remote_flavor().normalize(&path.into());
+        if let Some(canonical) = self.cached_path(&path) {
+            return Ok(canonical);
+        }
+
+        let name = self.session.realpath(path.as_str()).await?;
+        let Some(file) = name.files.first() else {
+            return Err(Error::UnexpectedBehavior("no file".to_owned()));
+        };
+
+        if RemoteFlavor::has_drive_form(&file.filename)
+            && !self.shared.flavor_pinned.load(Ordering::Relaxed)
+        {
+            *self.shared.flavor.lock().unwrap() = RemoteFlavor::Windows;
+        }
+
+        let canonical = self.remote_flavor().normalize(&file.filename);
+        self.cache_path(&path, canonical.to_owned());
+        Ok(canonical)
+    }
+
+    /// Resolves `rel` relative to `base` into an absolute path.
+    ///
+    /// When `base` is in the path cache and `rel` has no `..` components the
+    /// path is joined locally without a round trip. Note that in this case the
+    /// existence of the resulting path is not checked. Otherwise the joined path
+    /// is passed to [`SftpSession::canonicalize`].
+    pub async fn resolve_relative<B, R>(&self, base: B, rel: R) -> SftpResult<String>
+    where
+        B: Into<String>,
+        R: Into<String>,
+    {
+        let flavor = self.remote_flavor();
+        let base = flavor.normalize(&base.into());
+        let rel = flavor.normalize(&rel.into());
+
+        if rel.starts_with('/') {
+            return self.canonicalize(rel).await;
+        }
+
+        if let Some(canonical) = self.cached_path(&base) {
+            if let Some(path) = path_cache::join_canonical(&canonical, &rel) {
+                return Ok(path);
+            }
+        }
+
+        let path = match base.trim_end_matches('/') {
+            "" if base.starts_with('/') => format!("/{rel}"),
+            "" => rel,
+            base => format!("{base}/{rel}"),
+        };
+
+        self.canonicalize(path).await
+    }
+
+    /// Returns the home directory of `username` or of the user of the session.
+    ///
+    /// Uses the `home-directory` extension if the server supports it. Otherwise the
+    /// home directory of the session user is assumed to be the working directory
+    /// and resolved with [`SftpSession::canonicalize`], while that of another user
+    /// fails with [`Error::ExtensionUnsupported`].
+    pub async fn home_dir(&self, username: Option<&str>) -> SftpResult<String> {
+        if !self.has_capability(Capability::HomeDirectory) {
+            return match (username, self.initial_dir()) {
+                (None, Some(dir)) => Ok(dir.to_owned()),
+                (None, None) => self.canonicalize(".").await,
+                (Some(_), _) => Err(Error::ExtensionUnsupported {
+                    name: extensions::HOME_DIRECTORY,
+                }),
+            };
+        }
+
+        let name = self.extensions.check(
+            Capability::HomeDirectory,
+            self.session
+                .home_directory(username.unwrap_or_default())
+                .await,
+        )?;
+
         match name.files.first() {
-            Some(file) => Ok(file.filename.to_owned()),
+            Some(file) => Ok(self.remote_flavor().normalize(&file.filename)),
             None => Err(Error::UnexpectedBehavior("no file".to_owned())),
         }
     }
@@ -147,11 +759,79 @@ impl SftpSession {
     /// Creates a new empty directory.
     pub async fn create_dir<T: Into<String>>(&self, path: T) -> SftpResult<()> {
         self.session
-            .mkdir(path, FileAttributes::empty())
+            .mkdir(self.remote_path(path), FileAttributes::empty())
             .await
             .map(|_| ())
     }
 
+    /// Creates a directory with a unique random name in `parent`, readable only by
+    /// its owner. It is removed with its content when the [`TempDir`] is dropped
+    /// or closed, see [`TempDir`] for the caveats of the drop
+    pub async fn tempdir_in<P: Into<String>>(&self, parent: P) -> SftpResult<TempDir> {
+        let attrs = FileAttributes {
+            permissions: Some(0o700),
+            ..FileAttributes::empty()
+        };
+
+        let (path, _) = temp::create(self, &self.remote_path(parent), temp::DIR_PREFIX, |path| {
+            self.session.mkdir(path, attrs.clone())
+        })
+        .await?;
+
+        Ok(TempDir::new(self.clone(), path))
+    }
+
+    /// Creates and opens for reading and writing a file named after `prefix` and a
+    /// unique random suffix in `parent`, readable only by its owner. Returns its path
+    /// with the file, which is left in place when closed
+    pub async fn tempfile_in<P: Into<String>>(
+        &self,
+        parent: P,
+        prefix: &str,
+    ) -> SftpResult<(String, File)> {
+        let flags = OpenFlags::CREATE | OpenFlags::EXCLUDE | OpenFlags::READ | OpenFlags::WRITE;
+        let attrs = FileAttributes {
+            permissions: Some(0o600),
+            ..FileAttributes::empty()
+        };
+
+        temp::create(self, &self.remote_path(parent), prefix, |path| {
+            self.open_with_flags_and_attributes(path, flags, attrs.clone())
+        })
+        .await
+    }
+
+    /// Recursively creates a directory and all of its parent components if they are missing.
+    /// Directories created concurrently by someone else are not treated as an error
+    pub async fn create_dir_all<T: Into<String>>(&self, path: T) -> SftpResult<()> {
+        let path = self.remote_path(path);
+        if self.is_dir(path.as_str()).await? {
+            return Ok(());
+        }
+
+        let mut current = if path.starts_with('/') {
+            String::from("/")
+        } else {
+            String::new()
+        };
+
+        for component in path.split('/').filter(|c| !c.is_empty() && *c != ".") {
+            if !current.is_empty() && !current.ends_with('/') {
+                current.push('/');
+            }
+            current.push_str(component);
+
+            if let Err(err) = self.create_dir(current.as_str()).await {
+                // already exists or has just been created by someone else
+                if !self.is_dir(current.as_str()).await? {
+                    return Err(err);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     /// Reads the contents of a file located at the specified path to the end.
     pub async fn read<P: Into<String>>(&self, path: P) -> SftpResult<Vec<u8>> {
         let mut file = self.open(path).await?;
@@ -169,59 +849,171 @@ impl SftpSession {
         Ok(())
     }
 
+    /// Streams the contents of a file located at the specified path into `writer`
+    /// and returns the number of bytes copied.
+    ///
+    /// Unlike [`SftpSession::read`], the file is not held in memory. Chunks of
+    /// [`File::chunk_size`] are requested up to 16 ahead of the writer. The size
+    /// of the file is checked once copied, as with [`File::copy_to_local`]
+    pub async fn read_to_writer<P, W>(&self, path: P, writer: W) -> SftpResult<u64>
+    where
+        P: Into<String>,
+        W: AsyncWrite + Unpin,
+    {
+        let path = path.into();
+        let mut file = self.open(path.as_str()).await?;
+        file.copy_to_writer(writer)
+            .await
+            .map_err(|err| err.with_target(path))
+    }
+
+    /// Streams `reader` to its end into a file whose path is specified, creating or
+    /// truncating it, and returns the number of bytes copied.
+    ///
+    /// Unlike [`SftpSession::write`], the contents are not held in memory.
+    /// Up to 16 chunks of [`File::chunk_size`] are in flight
+    pub async fn write_from_reader<P, R>(&self, path: P, reader: R) -> SftpResult<u64>
+    where
+        P: Into<String>,
+        R: AsyncRead + Unpin,
+    {
+        let path = path.into();
+        let mut file = self.create(path.as_str()).await?;
+        file.copy_from_reader(reader)
+            .await
+            .map_err(|err| err.with_target(path))
+    }
+
     /// Checks a file or folder exists at the specified path
     pub async fn try_exists<P: Into<String>>(&self, path: P) -> SftpResult<bool> {
         match self.metadata(path).await {
             Ok(_) => Ok(true),
-            Err(Error::Status(status)) if status.status_code == StatusCode::NoSuchFile => Ok(false),
+            Err(Error::Status { status, .. }) if status.status_code == StatusCode::NoSuchFile => {
+                Ok(false)
+            }
             Err(error) => Err(error),
         }
     }
 
-    /// Returns an iterator over the entries within a directory.
-    pub async fn read_dir<P: Into<String>>(&self, path: P) -> SftpResult<ReadDir> {
-        let mut files = vec![];
-        let handle = self.session.opendir(path).await?.handle;
-
-        loop {
-            match self.session.readdir(handle.as_str()).await {
-                Ok(name) => {
-                    files = name
-                        .files
-                        .into_iter()
-                        .map(|f| (f.filename, f.attrs))
-                        .chain(files.into_iter())
-                        .collect();
-                }
-                Err(Error::Status(status)) if status.status_code == StatusCode::Eof => break,
-                Err(err) => return Err(err),
+    /// Returns `true` if the path points at a directory, following symlinks.
+    /// Returns `false` if nothing exists at the path
+    pub async fn is_dir<P: Into<String>>(&self, path: P) -> SftpResult<bool> {
+        Ok(self
+            .file_type(path.into(), true)
+            .await?
+            .is_some_and(|t| t.is_dir()))
+    }
+
+    /// Returns `true` if the path points at a regular file, following symlinks.
+    /// Returns `false` if nothing exists at the path
+    pub async fn is_file<P: Into<String>>(&self, path: P) -> SftpResult<bool> {
+        Ok(self
+            .file_type(path.into(), true)
+            .await?
+            .is_some_and(|t| t.is_file()))
+    }
+
+    /// Returns `true` if the path points at a symlink.
+    /// Returns `false` if nothing exists at the path
+    pub async fn is_symlink<P: Into<String>>(&self, path: P) -> SftpResult<bool> {
+        Ok(self
+            .file_type(path.into(), false)
+            .await?
+            .is_some_and(|t| t.is_symlink()))
+    }
+
+    async fn file_type(&self, path: String, follow: bool) -> SftpResult<Option<FileType>> {
+        let result = match follow {
+            true => self.metadata(path).await,
+            false => self.symlink_metadata(path).await,
+        };
+
+        match result {
+            Ok(metadata) => Ok(Some(metadata.file_type())),
+            Err(Error::Status { status, .. }) if status.status_code == StatusCode::NoSuchFile => {
+                Ok(None)
             }
+            Err(error) => Err(error),
         }
+    }
 
-        self.session.close(handle).await?;
+    /// Returns an iterator over the entries within a directory, in the order
+    /// sent by the server. See [`ReadDir::sorted_by_name`] for a reproducible order.
+    ///
+    /// The paths of the entries are joined according to [`SftpSession::remote_flavor`].
+    pub async fn read_dir<P: Into<String>>(&self, path: P) -> SftpResult<ReadDir> {
+        let flavor = self.remote_flavor();
+        let path = flavor.normalize(&path.into());
+        let files = read_dir_entries(&self.session, path.to_owned()).await?;
+        Ok(ReadDir::new(path, flavor, files.into()).with_identity(self.identity()))
+    }
 
-        Ok(ReadDir {
-            entries: files.into(),
-        })
+    /// Returns a stream over the entries within a directory, yielded as the server
+    /// sends them instead of after the whole listing. See [`ReadDirStream`] for
+    /// the prefetching of the batches.
+    ///
+    /// The paths of the entries are joined according to [`SftpSession::remote_flavor`].
+    pub async fn read_dir_stream<P: Into<String>>(&self, path: P) -> SftpResult<ReadDirStream> {
+        let flavor = self.remote_flavor();
+        let path = flavor.normalize(&path.into());
+        let handle = self.session.opendir(path.to_owned()).await?.handle;
+        Ok(ReadDirStream::new(
+            self.session.clone(),
+            handle,
+            path,
+            flavor,
+        ))
     }
 
     /// Reads a symbolic link, returning the file that the link points to.
     pub async fn read_link<P: Into<String>>(&self, path: P) -> SftpResult<String> {
-        let name = self.session.readlink(path).await?;
+        let name = self.session.readlink(self.remote_path(path)).await?;
         match name.files.first() {
             Some(file) => Ok(file.filename.to_owned()),
             None => Err(Error::UnexpectedBehavior("no file".to_owned())),
         }
     }
 
+    /// Syncs a directory with `fsync@openssh.com` on a handle of SSH_FXP_OPENDIR, so
+    /// that the entries renamed into it persist, as POSIX requires after a rename.
+    ///
+    /// Returns [`Error::ExtensionUnsupported`] if the server does not advertise the
+    /// extension or cannot sync directories, which servers such as older versions of
+    /// OpenSSH report as a plain failure
+    pub async fn sync_dir<P: Into<String>>(&self, path: P) -> SftpResult<()> {
+        self.require_capability(Capability::Fsync)?;
+
+        let path = self.remote_flavor().normalize(&path.into());
+        let handle = self.session.opendir(path.as_str()).await?.handle;
+        let result = self.session.fsync(handle.as_str()).await;
+        let closed = self.session.close(handle).await;
+
+        match self.extensions.check(Capability::Fsync, result) {
+            Ok(_) => closed.map(|_| ()),
+            Err(Error::Status { status, .. }) if status.status_code == StatusCode::Failure => {
+                debug!("fsync of the directory {path} failed, taken as unsupported");
+                Err(Error::ExtensionUnsupported {
+                    name: extensions::FSYNC,
+                })
+            }
+            Err(err) => Err(err),
+        }
+    }
+
     /// Removes the specified folder.
     pub async fn remove_dir<P: Into<String>>(&self, path: P) -> SftpResult<()> {
-        self.session.rmdir(path).await.map(|_| ())
+        let path = self.remote_path(path);
+        let result = self.session.rmdir(path.as_str()).await;
+        self.invalidate_path(&path);
+        result.map(|_| ())
     }
 
     /// Removes the specified file.
     pub async fn remove_file<T: Into<String>>(&self, filename: T) -> SftpResult<()> {
-        self.session.remove(filename).await.map(|_| ())
+        let filename = self.remote_path(filename);
+        let result = self.session.remove(filename.as_str()).await;
+        self.invalidate_path(&filename);
+        result.map(|_| ())
     }
 
     /// Rename a file or directory to a new name.
@@ -230,55 +1022,236 @@ impl SftpSession {
         O: Into<String>,
         N: Into<String>,
     {
-        self.session.rename(oldpath, newpath).await.map(|_| ())
+        let (oldpath, newpath) = (self.remote_path(oldpath), self.remote_path(newpath));
+        let result = self
+            .session
+            .rename(oldpath.as_str(), newpath.as_str())
+            .await;
+
+        self.invalidate_path(&oldpath);
+        self.invalidate_path(&newpath);
+        result.map(|_| ())
     }
 
-    /// Creates a symlink of the specified target.
+    /// Sends `SSH_FXP_SYMLINK` with the paths in the order given, see
+    /// [`RawSftpSession::symlink`]
+    #[deprecated(note = "the meaning of the paths depends on the server, use `create_symlink`")]
     pub async fn symlink<P, T>(&self, path: P, target: T) -> SftpResult<()>
     where
         P: Into<String>,
         T: Into<String>,
     {
-        self.session.symlink(path, target).await.map(|_| ())
+        let (path, target) = (self.remote_path(path), self.remote_path(target));
+        #[allow(deprecated)]
+        let result = self.session.symlink(path.as_str(), target.as_str()).await;
+
+        // either path may be the link depending on the server
+        self.invalidate_path(&path);
+        self.invalidate_path(&target);
+        result.map(|_| ())
+    }
+
+    /// Creates a symbolic link at `link` pointing to `target`.
+    ///
+    /// See [`SftpSession::set_symlink_order`] for servers that
+    /// create links reversed.
+    pub async fn create_symlink<L, T>(&self, link: L, target: T) -> SftpResult<()>
+    where
+        L: Into<String>,
+        T: Into<String>,
+    {
+        let (link, target) = (self.remote_path(link), self.remote_path(target));
+        let result = self.session.create_symlink(link.as_str(), target).await;
+        self.invalidate_path(&link);
+        result.map(|_| ())
+    }
+
+    /// Checks whether the file may be accessed for `check` from its permissions
+    /// and the [`SftpSession::identity`], to fail fast before a sequence of requests.
+    /// See [`FileAttributes::access`] for when the result is [`Access::Indeterminate`].
+    ///
+    /// The server still decides, an [`Access::Allowed`] operation may fail.
+    pub async fn access<P: Into<String>>(&self, path: P, check: AccessCheck) -> SftpResult<Access> {
+        let metadata = self.metadata(path).await?;
+        Ok(metadata.access(check, self.identity().as_ref()))
     }
 
     /// Queries metadata about the remote file.
     pub async fn metadata<P: Into<String>>(&self, path: P) -> SftpResult<Metadata> {
-        Ok(self.session.stat(path).await?.attrs)
+        Ok(self.session.stat(self.remote_path(path)).await?.attrs)
+    }
+
+    /// Returns the access control list of the remote file, [`None`] if the server
+    /// does not attach one or in an unknown format. See [`Metadata::acl`]
+    pub async fn acl<P: Into<String>>(&self, path: P) -> SftpResult<Option<AclEntries>> {
+        Ok(self.metadata(path).await?.acl())
     }
 
     /// Sets metadata for a remote file.
+    ///
+    /// If only one of `uid`/`gid` or `atime`/`mtime` is set, the other one is
+    /// filled in with the current value. [`RawSftpSession::setstat`] sends them as is.
     pub async fn set_metadata<P: Into<String>>(
         &self,
         path: P,
-        metadata: Metadata,
+        mut metadata: Metadata,
     ) -> Result<(), Error> {
+        let path = self.remote_path(path);
+
+        // the protocol sends uid/gid and atime/mtime in pairs
+        if metadata.is_half_set() {
+            metadata.merge_halves(&self.metadata(path.as_str()).await?);
+        }
+
         self.session.setstat(path, metadata).await.map(|_| ())
     }
 
+    /// Sets the access and modification times of a remote file, leaving the other
+    /// attributes untouched. If only one of them is given, the other one is kept.
+    ///
+    /// Fails for times before 1970 or after 2106 that do not fit into the protocol,
+    /// the latter with [`Error::TimeOverflow`] even if the times are not strict.
+    pub async fn set_times<P: Into<String>>(
+        &self,
+        path: P,
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    ) -> SftpResult<()> {
+        if accessed.is_none() && modified.is_none() {
+            return Ok(());
+        }
+
+        let path = self.remote_path(path);
+        let mut times = fs::times(accessed, modified)?;
+        if times.is_half_set() {
+            times.merge_halves(&self.metadata(path.as_str()).await?);
+        }
+
+        fs::check_times(&times)?;
+        self.session.setstat(path, times).await.map(|_| ())
+    }
+
     pub async fn symlink_metadata<P: Into<String>>(&self, path: P) -> SftpResult<Metadata> {
-        Ok(self.session.lstat(path).await?.attrs)
+        Ok(self.session.lstat(self.remote_path(path)).await?.attrs)
+    }
+
+    /// Creates a hard link at `newpath` pointing to `oldpath`.
+    /// Returns [`Error::ExtensionUnsupported`] if the remote SFTP server
+    /// does not support `hardlink@openssh.com`.
+    pub async fn hard_link<O, N>(&self, oldpath: O, newpath: N) -> SftpResult<()>
+    where
+        O: Into<String>,
+        N: Into<String>,
+    {
+        self.require_capability(Capability::Hardlink)?;
+        let (oldpath, newpath) = (self.remote_path(oldpath), self.remote_path(newpath));
+        let result = self.session.hardlink(oldpath, newpath).await;
+        self.extensions
+            .check(Capability::Hardlink, result)
+            .map(|_| ())
     }
 
+    /// Creates a hard link at `newpath` pointing to `oldpath`.
+    /// Returns `Ok(false)` if the remote SFTP server does not support `hardlink@openssh.com`.
+    #[deprecated(note = "use `SftpSession::hard_link` which returns `Error::ExtensionUnsupported`")]
     pub async fn hardlink<O, N>(&self, oldpath: O, newpath: N) -> SftpResult<bool>
     where
         O: Into<String>,
         N: Into<String>,
     {
-        if !self.extensions.hardlink {
-            return Ok(false);
+        match self.hard_link(oldpath, newpath).await {
+            Ok(()) => Ok(true),
+            Err(Error::ExtensionUnsupported { .. }) => Ok(false),
+            Err(err) => Err(err),
         }
+    }
 
-        self.session.hardlink(oldpath, newpath).await.map(|_| true)
+    /// Sums the sizes and counts the entries of the tree under `path`, like `du -s`,
+    /// from the attributes of the listings without downloading anything.
+    ///
+    /// The directories that cannot be listed are reported in [`DirSummary::errors`]
+    /// rather than failing the summary, unless it is `path` itself.
+    pub async fn dir_summary<P: Into<String>>(
+        &self,
+        path: P,
+        options: SummaryOptions,
+    ) -> SftpResult<DirSummary> {
+        let flavor = self.remote_flavor();
+        let path = flavor.normalize(&path.into());
+        summary::summarize(self.session.clone(), path, flavor, options).await
+    }
+
+    /// Returns the paths matching the glob `pattern`, such as `logs/**/*.gz`.
+    ///
+    /// Only the directories below the leading literal components are listed, and
+    /// each component is matched against the names of the entries: `*`, `?`,
+    /// `[...]` and `{a,b}` stay within a component, while a `**` component matches
+    /// any number of directories without entering symlinks. A relative pattern
+    /// is matched from the working directory and yields relative paths.
+    ///
+    /// Returns [`Error::InvalidPattern`] if the pattern cannot be parsed.
+    #[cfg(feature = "glob")]
+    pub fn glob(&self, pattern: &str) -> SftpResult<Glob> {
+        Glob::new(self.session.clone(), pattern, self.remote_flavor())
+    }
+
+    /// Polls the paths every `interval` and reports the differences between the polls.
+    ///
+    /// Each path is stat'ed following symlinks and, if it is a directory, listed.
+    /// The first poll is the baseline and produces no events. Changes are detected
+    /// by comparing the type, size and modification time, so modifications within
+    /// the resolution of the modification time may be missed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero.
+    pub fn watch(&self, paths: Vec<String>, interval: Duration) -> Watch {
+        let paths = paths.into_iter().map(|p| self.remote_path(p)).collect();
+        Watch::new(self.session.clone(), paths, interval)
+    }
+
+    /// Performs a statvfs on the remote file system path.
+    /// Returns [`Error::ExtensionUnsupported`] if the remote SFTP server
+    /// does not support `statvfs@openssh.com` extension v2.
+    pub async fn statvfs<P: Into<String>>(&self, path: P) -> SftpResult<Statvfs> {
+        self.require_capability(Capability::Statvfs)?;
+        let result = self.session.statvfs(self.remote_path(path)).await;
+        self.extensions.check(Capability::Statvfs, result)
     }
 
     /// Performs a statvfs on the remote file system path.
     /// Returns [`Ok(None)`] if the remote SFTP server does not support `statvfs@openssh.com` extension v2.
+    #[deprecated(note = "use `SftpSession::statvfs` which returns `Error::ExtensionUnsupported`")]
     pub async fn fs_info<P: Into<String>>(&self, path: P) -> SftpResult<Option<Statvfs>> {
-        if !self.extensions.statvfs {
-            return Ok(None);
+        match self.statvfs(path).await {
+            Ok(statvfs) => Ok(Some(statvfs)),
+            Err(Error::ExtensionUnsupported { .. }) => Ok(None),
+            Err(err) => Err(err),
         }
+    }
+}
+
+/// Lists all entries of the directory in the order returned by the server
+pub(super) async fn read_dir_entries(
+    session: &RawSftpSession,
+    path: String,
+) -> SftpResult<Vec<(String, Metadata)>> {
+    let mut files = vec![];
+    let handle = session.opendir(path.as_str()).await?.handle;
 
-        self.session.statvfs(path).await.map(Some)
+    // the errors on the handle report the listed directory
+    loop {
+        match session.readdir(handle.as_str()).await {
+            Ok(name) => files.extend(name.files.into_iter().map(|f| (f.filename, f.attrs))),
+            Err(Error::Status { status, .. }) if status.status_code == StatusCode::Eof => break,
+            Err(err) => return Err(err.with_target(path)),
+        }
     }
+
+    session
+        .close(handle)
+        .await
+        .map_err(|err| err.with_target(path))?;
+
+    Ok(files)
 }
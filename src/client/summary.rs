@@ -0,0 +1,305 @@
+use futures_core::Stream;
+use std::{
+    collections::{HashSet, VecDeque},
+    fmt,
+    future::poll_fn,
+    pin::Pin,
+    sync::Arc,
+};
+use tokio::task::JoinSet;
+
+use super::{
+    error::Error,
+    fs::{DirEntry, ReadDirStream},
+    rawsession::SftpResult,
+    RawSftpSession, RemoteFlavor,
+};
+use crate::protocol::FileType;
+
+/// Default number of directories listed at the same time by
+/// [`SftpSession::dir_summary`](crate::client::SftpSession::dir_summary)
+pub const DEFAULT_SUMMARY_CONCURRENCY: usize = 8;
+
+/// Predicate of the entries left out of a [`DirSummary`], see [`SummaryOptions::exclude`]
+pub type SummaryFilter = Arc<dyn Fn(&DirEntry) -> bool + Send + Sync>;
+
+/// Options of [`SftpSession::dir_summary`](crate::client::SftpSession::dir_summary)
+#[derive(Clone)]
+pub struct SummaryOptions {
+    max_depth: Option<usize>,
+    exclude: Option<SummaryFilter>,
+    stat_missing: bool,
+    follow_symlinks: bool,
+    max_concurrent: usize,
+}
+
+impl Default for SummaryOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: None,
+            exclude: None,
+            stat_missing: false,
+            follow_symlinks: false,
+            max_concurrent: DEFAULT_SUMMARY_CONCURRENCY,
+        }
+    }
+}
+
+impl fmt::Debug for SummaryOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SummaryOptions")
+            .field("max_depth", &self.max_depth)
+            .field("exclude", &self.exclude.is_some())
+            .field("stat_missing", &self.stat_missing)
+            .field("follow_symlinks", &self.follow_symlinks)
+            .field("max_concurrent", &self.max_concurrent)
+            .finish()
+    }
+}
+
+impl SummaryOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the levels of subdirectories listed below the path, `0` counts the entries
+    /// of the path only. The directories below are counted without being listed.
+    /// Default: [`None`], unlimited
+    pub fn max_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Leaves out the entries for which `exclude` returns `true`,
+    /// excluded directories are neither counted nor listed
+    pub fn exclude<F>(mut self, exclude: F) -> Self
+    where
+        F: Fn(&DirEntry) -> bool + Send + Sync + 'static,
+    {
+        self.exclude = Some(Arc::new(exclude));
+        self
+    }
+
+    /// Sets whether the files listed without a size are stat'ed,
+    /// otherwise they are counted in [`DirSummary::unknown_sizes`]. Default: `false`
+    pub fn stat_missing(mut self, stat_missing: bool) -> Self {
+        self.stat_missing = stat_missing;
+        self
+    }
+
+    /// Sets whether symlinks are counted as their targets and the linked directories
+    /// are listed, each directory at most once. Default: `false`, counted as symlinks
+    pub fn follow_symlinks(mut self, follow_symlinks: bool) -> Self {
+        self.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    /// Sets the maximum number of directories listed at the same time.
+    /// Default: [`DEFAULT_SUMMARY_CONCURRENCY`]
+    pub fn max_concurrent(mut self, max_concurrent: usize) -> Self {
+        self.max_concurrent = max_concurrent.max(1);
+        self
+    }
+
+    fn excludes(&self, entry: &DirEntry) -> bool {
+        self.exclude.as_ref().is_some_and(|exclude| exclude(entry))
+    }
+
+    fn lists(&self, depth: usize) -> bool {
+        self.max_depth.is_none_or(|max_depth| depth <= max_depth)
+    }
+}
+
+/// Total size and counts of the entries under a directory, the path itself
+/// excluded, returned by [`SftpSession::dir_summary`](crate::client::SftpSession::dir_summary)
+#[derive(Debug, Clone, Default)]
+pub struct DirSummary {
+    /// Sum of the sizes of the files
+    pub size: u64,
+    pub files: u64,
+    pub dirs: u64,
+    pub symlinks: u64,
+    /// Entries of other types, such as sockets and devices
+    pub others: u64,
+    /// Files whose size is not known, not included in [`DirSummary::size`]
+    pub unknown_sizes: u64,
+    /// Paths that could not be listed or stat'ed and the reason,
+    /// the entries read before the failure are counted
+    pub errors: Vec<(String, Error)>,
+}
+
+impl DirSummary {
+    fn merge(&mut self, other: DirSummary) {
+        self.size += other.size;
+        self.files += other.files;
+        self.dirs += other.dirs;
+        self.symlinks += other.symlinks;
+        self.others += other.others;
+        self.unknown_sizes += other.unknown_sizes;
+        self.errors.extend(other.errors);
+    }
+
+    fn add_file(&mut self, size: Option<u64>) {
+        self.files += 1;
+        match size {
+            Some(size) => self.size += size,
+            None => self.unknown_sizes += 1,
+        }
+    }
+}
+
+/// Entries of a listed directory and the subdirectories to list
+#[derive(Default)]
+struct Listing {
+    summary: DirSummary,
+    dirs: Vec<String>,
+}
+
+impl Listing {
+    async fn add(&mut self, session: &RawSftpSession, entry: DirEntry, options: &SummaryOptions) {
+        let path = entry.path();
+        match entry.file_type() {
+            FileType::Dir => {
+                self.summary.dirs += 1;
+                self.dirs.push(path);
+            }
+            FileType::File => {
+                let mut size = entry.metadata().size;
+                if size.is_none() && options.stat_missing {
+                    match session.stat(path.as_str()).await {
+                        Ok(attrs) => size = attrs.attrs.size,
+                        Err(err) => self.summary.errors.push((path, err)),
+                    }
+                }
+
+                self.summary.add_file(size);
+            }
+            FileType::Symlink if options.follow_symlinks => self.follow(session, path).await,
+            FileType::Symlink => self.summary.symlinks += 1,
+            FileType::Other => self.summary.others += 1,
+        }
+    }
+
+    /// Counts the target of a symlink, a dangling symlink is counted as such
+    async fn follow(&mut self, session: &RawSftpSession, path: String) {
+        let attrs = match session.stat(path.as_str()).await {
+            Ok(attrs) => attrs.attrs,
+            Err(_) => return self.summary.symlinks += 1,
+        };
+
+        match attrs.file_type() {
+            // listed under its canonical path, so that it is listed once
+            FileType::Dir => match session.realpath(path.as_str()).await {
+                Ok(mut name) if !name.files.is_empty() => {
+                    self.summary.dirs += 1;
+                    self.dirs.push(name.files.remove(0).filename);
+                }
+                Ok(_) => self.summary.symlinks += 1,
+                Err(err) => self.summary.errors.push((path, err)),
+            },
+            FileType::File => self.summary.add_file(attrs.size),
+            FileType::Symlink => self.summary.symlinks += 1,
+            FileType::Other => self.summary.others += 1,
+        }
+    }
+}
+
+async fn list(
+    session: Arc<RawSftpSession>,
+    dir: String,
+    flavor: RemoteFlavor,
+    options: SummaryOptions,
+) -> Listing {
+    let mut listing = Listing::default();
+    let handle = match session.opendir(dir.as_str()).await {
+        Ok(handle) => handle.handle,
+        Err(err) => {
+            listing.summary.errors.push((dir, err));
+            return listing;
+        }
+    };
+
+    let mut stream = ReadDirStream::new(session.clone(), handle, dir.to_owned(), flavor);
+    while let Some(entry) = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+        match entry {
+            Ok(entry) if options.excludes(&entry) => (),
+            Ok(entry) => listing.add(&session, entry, &options).await,
+            Err(err) => {
+                listing.summary.errors.push((dir, err));
+                break;
+            }
+        }
+    }
+
+    listing
+}
+
+/// Lists the tree under `root` breadth-first with at most
+/// [`SummaryOptions::max_concurrent`] directories listed at the same time
+pub(crate) async fn summarize(
+    session: Arc<RawSftpSession>,
+    root: String,
+    flavor: RemoteFlavor,
+    options: SummaryOptions,
+) -> SftpResult<DirSummary> {
+    // the directories are compared by their canonical paths when symlinks are followed
+    let root = match options.follow_symlinks {
+        true => match session.realpath(root.as_str()).await?.files.first() {
+            Some(file) => file.filename.to_owned(),
+            None => root,
+        },
+        false => root,
+    };
+
+    // the summary fails if the path itself cannot be listed
+    let listing = list(session.clone(), root.to_owned(), flavor, options.clone()).await;
+    let mut summary = DirSummary::default();
+    if let Some((_, err)) = listing
+        .summary
+        .errors
+        .iter()
+        .find(|(path, _)| *path == root)
+    {
+        return Err(err.clone());
+    }
+
+    let mut visited = HashSet::from([root]);
+    let mut pending = VecDeque::new();
+    let mut tasks = JoinSet::new();
+    let mut next = Some((listing, 0));
+
+    loop {
+        if let Some((listing, depth)) = next.take() {
+            let Listing {
+                summary: counted,
+                dirs,
+            } = listing;
+            summary.merge(counted);
+
+            if options.lists(depth + 1) {
+                for dir in dirs {
+                    if visited.insert(dir.to_owned()) {
+                        pending.push_back((dir, depth + 1));
+                    }
+                }
+            }
+        }
+
+        while tasks.len() < options.max_concurrent {
+            let Some((dir, depth)) = pending.pop_front() else {
+                break;
+            };
+
+            let fut = list(session.clone(), dir, flavor, options.clone());
+            tasks.spawn(async move { (fut.await, depth) });
+        }
+
+        match tasks.join_next().await {
+            Some(Ok(result)) => next = Some(result),
+            Some(Err(err)) => warn!("listing of a directory failed: {}", err),
+            None => break,
+        }
+    }
+
+    Ok(summary)
+}
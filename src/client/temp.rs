@@ -0,0 +1,151 @@
+use tokio::runtime::Handle;
+
+use super::{error::Error, rawsession::SftpResult, SftpSession};
+use crate::protocol::StatusCode;
+
+/// Names tried before giving up on the collisions
+const ATTEMPTS: usize = 8;
+/// Prefix of the names of [`TempDir`]
+pub(crate) const DIR_PREFIX: &str = ".tmp";
+
+/// Directory created by [`SftpSession::tempdir_in`] and removed with its content
+/// when dropped.
+///
+/// Drop cannot wait for the server, so the removal is spawned on the current runtime
+/// and its errors are only logged. It may not have completed when the session is
+/// closed right after, and without a runtime the directory is left in place. Use
+/// [`TempDir::close`] to remove it before going on and to get the errors
+pub struct TempDir {
+    /// [`None`] once kept or closed
+    session: Option<SftpSession>,
+    path: String,
+}
+
+impl TempDir {
+    pub(crate) fn new(session: SftpSession, path: String) -> Self {
+        Self {
+            session: Some(session),
+            path,
+        }
+    }
+
+    /// Path of the directory, joined onto the parent
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Keeps the directory on the server and returns its path
+    pub fn keep(mut self) -> String {
+        self.session = None;
+        std::mem::take(&mut self.path)
+    }
+
+    /// Removes the directory and its content
+    pub async fn close(mut self) -> SftpResult<()> {
+        match self.session.take() {
+            Some(session) => remove_all(&session, &self.path).await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl std::fmt::Debug for TempDir {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TempDir").field("path", &self.path).finish()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let Some(session) = self.session.take() else {
+            return;
+        };
+
+        let path = std::mem::take(&mut self.path);
+        match Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn(async move {
+                    if let Err(err) = remove_all(&session, &path).await {
+                        warn!("unable to remove the temporary directory {path}: {err}");
+                    }
+                });
+            }
+            Err(_) => warn!("no runtime to remove the temporary directory {path}"),
+        }
+    }
+}
+
+/// Creates an entry named after `prefix` and a random suffix in `parent`, trying
+/// another name as long as the creation fails because the name is taken
+pub(crate) async fn create<F, Fut, T>(
+    session: &SftpSession,
+    parent: &str,
+    prefix: &str,
+    mut create: F,
+) -> SftpResult<(String, T)>
+where
+    F: FnMut(String) -> Fut,
+    Fut: std::future::Future<Output = SftpResult<T>>,
+{
+    let flavor = session.remote_flavor();
+    let mut attempt = 1;
+
+    loop {
+        let path = flavor.join(parent, &format!("{prefix}{}", random_suffix()?));
+        let err = match create(path.clone()).await {
+            Ok(created) => return Ok((path, created)),
+            Err(err) => err,
+        };
+
+        // servers of version 3 report an existing name as a plain failure
+        let taken = matches!(&err, Error::Status { .. }) && exists(session, &path).await?;
+        if !taken || attempt == ATTEMPTS {
+            return Err(err);
+        }
+
+        debug!("temporary name {path} taken, trying another one");
+        attempt += 1;
+    }
+}
+
+/// 16 hex digits from the random source of the operating system
+fn random_suffix() -> SftpResult<String> {
+    let mut bytes = [0; 8];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|err| Error::UnexpectedBehavior(format!("random source: {err}")))?;
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+/// Checks the path without following a symlink, which takes the name as well
+async fn exists(session: &SftpSession, path: &str) -> SftpResult<bool> {
+    match session.symlink_metadata(path).await {
+        Ok(_) => Ok(true),
+        Err(Error::Status { status, .. }) if status.status_code == StatusCode::NoSuchFile => {
+            Ok(false)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Removes the directory and its content, without following the symlinks
+async fn remove_all(session: &SftpSession, path: &str) -> SftpResult<()> {
+    let mut dirs = vec![path.to_owned()];
+    let mut listed = 0;
+
+    while let Some(dir) = dirs.get(listed).cloned() {
+        for entry in session.read_dir(dir).await? {
+            match entry.is_dir() {
+                true => dirs.push(entry.path()),
+                false => session.remove_file(entry.path()).await?,
+            }
+        }
+        listed += 1;
+    }
+
+    // the deepest directories were listed last
+    for dir in dirs.iter().rev() {
+        session.remove_dir(dir.as_str()).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,182 @@
+#[cfg(feature = "handle-backtraces")]
+use std::backtrace::Backtrace;
+#[cfg(feature = "handle-backtraces")]
+use std::sync::Arc;
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, MutexGuard,
+    },
+    time::Duration,
+};
+use tokio::time::Instant;
+
+/// Handle returned by the server and not yet closed, listed by
+/// [`RawSftpSession::open_handles_report`](super::RawSftpSession::open_handles_report)
+#[derive(Debug, Clone)]
+pub struct TrackedHandle {
+    pub handle: String,
+    /// Request that returned the handle, `SSH_FXP_OPEN` or `SSH_FXP_OPENDIR`
+    pub operation: &'static str,
+    /// Path of the file or the directory
+    pub path: String,
+    /// Time since the handle was returned
+    pub age: Duration,
+    /// Time since the last request on the handle, the age if there was none
+    pub idle: Duration,
+    /// Where the handle was requested
+    #[cfg(feature = "handle-backtraces")]
+    pub backtrace: Arc<Backtrace>,
+}
+
+struct Entry {
+    operation: &'static str,
+    path: String,
+    opened: Instant,
+    used: Instant,
+    warned: bool,
+    #[cfg(feature = "handle-backtraces")]
+    backtrace: Arc<Backtrace>,
+}
+
+impl Entry {
+    fn report(&self, handle: &str, now: Instant) -> TrackedHandle {
+        TrackedHandle {
+            handle: handle.to_owned(),
+            operation: self.operation,
+            path: self.path.clone(),
+            age: now - self.opened,
+            idle: now - self.used,
+            #[cfg(feature = "handle-backtraces")]
+            backtrace: self.backtrace.clone(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    idle_warning: Option<Duration>,
+    handles: HashMap<String, Entry>,
+}
+
+/// Open handles of a session with the request that returned them, for the
+/// diagnostics of leaks. Disabled until [`HandleTracker::enable`] is called
+#[derive(Default)]
+pub(crate) struct HandleTracker {
+    enabled: AtomicBool,
+    state: Mutex<State>,
+}
+
+impl HandleTracker {
+    pub fn enable(&self, idle_warning: Option<Duration>) {
+        self.state.lock().unwrap().idle_warning = idle_warning;
+        self.enabled.store(true, Ordering::Relaxed);
+    }
+
+    fn state(&self) -> Option<MutexGuard<'_, State>> {
+        match self.enabled.load(Ordering::Relaxed) {
+            true => Some(self.state.lock().unwrap()),
+            false => None,
+        }
+    }
+
+    /// Records a handle returned by the server, after warning
+    /// about the handles left idle for too long
+    pub fn opened(&self, handle: &str, operation: &'static str, path: &str) {
+        let Some(mut state) = self.state() else {
+            return;
+        };
+
+        let now = Instant::now();
+        warn_idle(&mut state, now);
+        state.handles.insert(
+            handle.to_owned(),
+            Entry {
+                operation,
+                path: path.to_owned(),
+                opened: now,
+                used: now,
+                warned: false,
+                #[cfg(feature = "handle-backtraces")]
+                backtrace: Arc::new(Backtrace::force_capture()),
+            },
+        );
+    }
+
+    pub fn used(&self, handle: &str) {
+        if let Some(entry) = self
+            .state()
+            .as_mut()
+            .and_then(|s| s.handles.get_mut(handle))
+        {
+            entry.used = Instant::now();
+            entry.warned = false;
+        }
+    }
+
+    /// Forgets the handle before SSH_FXP_CLOSE is sent, so that the server cannot
+    /// return it again for another file before it is forgotten. Returns the record
+    /// to restore if the close fails
+    pub fn closing(&self, handle: &str) -> Option<Closing> {
+        let entry = self.state()?.handles.remove(handle)?;
+        Some(Closing {
+            handle: handle.to_owned(),
+            entry,
+        })
+    }
+
+    pub fn restore(&self, closing: Closing) {
+        if let Some(mut state) = self.state() {
+            state.handles.entry(closing.handle).or_insert(closing.entry);
+        }
+    }
+
+    /// Lists the handles, the oldest first
+    pub fn report(&self) -> Vec<TrackedHandle> {
+        let Some(mut state) = self.state() else {
+            return Vec::new();
+        };
+
+        let now = Instant::now();
+        warn_idle(&mut state, now);
+
+        let mut report = state
+            .handles
+            .iter()
+            .map(|(handle, entry)| entry.report(handle, now))
+            .collect::<Vec<_>>();
+        report.sort_by_key(|handle| Reverse(handle.age));
+        report
+    }
+}
+
+/// Record of a handle being closed, see [`HandleTracker::closing`]
+pub(crate) struct Closing {
+    handle: String,
+    entry: Entry,
+}
+
+/// Warns once about each handle idle for longer than the configured time
+fn warn_idle(state: &mut State, now: Instant) {
+    let Some(idle_warning) = state.idle_warning else {
+        return;
+    };
+
+    for (handle, entry) in &mut state.handles {
+        if entry.warned || now - entry.used < idle_warning {
+            continue;
+        }
+
+        entry.warned = true;
+        warn!(
+            "handle {handle:?} of {} {:?} idle for {:?}, it may have leaked",
+            entry.operation,
+            entry.path,
+            now - entry.used
+        );
+        #[cfg(feature = "handle-backtraces")]
+        warn!("handle {handle:?} requested at:\n{}", entry.backtrace);
+    }
+}
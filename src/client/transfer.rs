@@ -0,0 +1,594 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::SeekFrom,
+    path::PathBuf,
+};
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    task::{self, JoinSet},
+};
+
+#[cfg(feature = "checksum")]
+use super::checksum::{self, ChecksumManifest, Checksums, ChunkChecksum};
+use super::{
+    error::Error,
+    fs::{join_error, Direction, ExpectedSize, File, ProgressEvent},
+    rawsession::SftpResult,
+    SftpSession,
+};
+use crate::protocol::OpenFlags;
+
+/// Default number of requests kept in flight by [`TransferScheduler`] across all jobs
+pub const DEFAULT_TRANSFER_REQUESTS: usize = 32;
+
+/// Upload or download added to a [`TransferScheduler`]
+#[derive(Debug, Clone)]
+pub struct TransferJob {
+    direction: Direction,
+    local: PathBuf,
+    remote: String,
+    priority: u8,
+    #[cfg(feature = "checksum")]
+    checksums: Option<ChecksumManifest>,
+}
+
+impl TransferJob {
+    /// Copies the local file to `remote`, which is created or truncated
+    pub fn upload<L: Into<PathBuf>, R: Into<String>>(local: L, remote: R) -> Self {
+        Self {
+            direction: Direction::Write,
+            local: local.into(),
+            remote: remote.into(),
+            priority: 0,
+            #[cfg(feature = "checksum")]
+            checksums: None,
+        }
+    }
+
+    /// Copies `remote` to the local file, which is created or truncated. The size
+    /// of the remote file is checked once copied, as with [`File::copy_to_local`]
+    pub fn download<R: Into<String>, L: Into<PathBuf>>(remote: R, local: L) -> Self {
+        Self {
+            direction: Direction::Read,
+            local: local.into(),
+            remote: remote.into(),
+            priority: 0,
+            #[cfg(feature = "checksum")]
+            checksums: None,
+        }
+    }
+
+    /// Sets the priority used with [`SchedulePolicy::Priority`], higher goes first.
+    /// Default: 0
+    pub fn priority(mut self, priority: u8) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Hashes each chunk and reports it with [`TransferEvent::Checksum`], so that the
+    /// manifest can be saved along the transfer. Only the bytes actually transferred
+    /// are hashed.
+    ///
+    /// A `manifest` with chunks from a previous attempt resumes the job: the destination
+    /// is kept, its last chunk covered by the manifest is verified and the transfer
+    /// continues after it. The data downloaded again is checked against the chunks of
+    /// `manifest` as received, and the whole local file once complete, without reading
+    /// the remote file again. The chunks of a download without a previous attempt
+    /// have nothing to be checked against
+    #[cfg(feature = "checksum")]
+    pub fn checksums(mut self, manifest: ChecksumManifest) -> Self {
+        self.checksums = Some(manifest);
+        self
+    }
+}
+
+/// Order in which [`TransferScheduler`] sends the chunks of the jobs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SchedulePolicy {
+    /// One chunk of each job in turn
+    #[default]
+    RoundRobin,
+    /// Chunks of the jobs with the highest priority first, in turn among equal priorities
+    Priority,
+}
+
+/// Reported by [`TransferScheduler::run_with_events`]
+#[derive(Debug, Clone)]
+pub enum TransferEvent {
+    /// A chunk of the job was transferred
+    Progress { job: usize, progress: ProgressEvent },
+    /// The job finished with the number of bytes transferred or failed
+    Completed { job: usize, result: SftpResult<u64> },
+    /// The checksum of a chunk of the job was recorded, see [`TransferJob::checksums`]
+    #[cfg(feature = "checksum")]
+    Checksum { job: usize, chunk: ChunkChecksum },
+}
+
+/// Result of a request sent for a job
+enum Chunk {
+    Read(u64, u64, Option<Vec<u8>>),
+    Written(u64, u64),
+}
+
+/// Job with open files
+struct Active {
+    remote: File,
+    local: fs::File,
+    direction: Direction,
+    priority: u8,
+    /// Size of the remote file when a download started
+    size: Option<u64>,
+    expected: ExpectedSize,
+    /// Offset after the range kept from a previous attempt
+    resumed: u64,
+    next: u64,
+    end: u64,
+    /// Rest of the chunks after short reads
+    retry: VecDeque<(u64, u64)>,
+    in_flight: usize,
+    transferred: u64,
+    #[cfg(feature = "checksum")]
+    checksums: Option<Checksums>,
+}
+
+impl Active {
+    async fn open(session: &SftpSession, job: &TransferJob) -> SftpResult<Self> {
+        #[cfg(feature = "checksum")]
+        let resume = job
+            .checksums
+            .as_ref()
+            .is_some_and(|manifest| !manifest.chunks().is_empty());
+        #[cfg(not(feature = "checksum"))]
+        let resume = false;
+
+        let (remote, local, size) = match job.direction {
+            Direction::Read => {
+                let remote = session.open(job.remote.as_str()).await?;
+                let size = remote.metadata().await?.size;
+                let local = fs::OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(!resume)
+                    .open(&job.local)
+                    .await?;
+                if let Some(size) = size {
+                    local.set_len(size).await?;
+                }
+
+                (remote, local, size)
+            }
+            Direction::Write => {
+                let local = fs::File::open(&job.local).await?;
+                let size = local.metadata().await?.len();
+                let remote = match resume {
+                    true => {
+                        let flags = OpenFlags::READ | OpenFlags::WRITE | OpenFlags::CREATE;
+                        session.open_with_flags(job.remote.as_str(), flags).await?
+                    }
+                    false => session.create(job.remote.as_str()).await?,
+                };
+
+                (remote, local, Some(size))
+            }
+        };
+
+        let active = Self {
+            remote,
+            local,
+            direction: job.direction,
+            priority: job.priority,
+            size,
+            expected: ExpectedSize::new(size.filter(|_| job.direction == Direction::Read)),
+            resumed: 0,
+            next: 0,
+            end: size.unwrap_or(u64::MAX),
+            retry: VecDeque::new(),
+            in_flight: 0,
+            transferred: 0,
+            #[cfg(feature = "checksum")]
+            checksums: job.checksums.clone().map(Checksums::new),
+        };
+
+        #[cfg(feature = "checksum")]
+        let active = active.resume().await?;
+
+        Ok(active)
+    }
+
+    fn has_chunks(&self) -> bool {
+        !self.retry.is_empty() || self.next < self.end
+    }
+
+    fn is_done(&self) -> bool {
+        !self.has_chunks() && self.in_flight == 0
+    }
+
+    /// Sends the next chunk of the job and returns the id of the task awaiting the reply
+    async fn send(&mut self, requests: &mut JoinSet<SftpResult<Chunk>>) -> SftpResult<task::Id> {
+        let (offset, len) = match self.retry.pop_front() {
+            Some(part) => part,
+            None => {
+                let len = (self.remote.chunk_size() as u64).min(self.end - self.next);
+                self.next += len;
+                (self.next - len, len)
+            }
+        };
+
+        let task = match self.direction {
+            Direction::Read => {
+                let read = self.remote.read_at(offset, len);
+                requests.spawn(async move {
+                    let (offset, len, data) = read.await?;
+                    Ok(Chunk::Read(offset, len, data))
+                })
+            }
+            Direction::Write => {
+                // chunks of an upload are never retried, so the local file is read in order
+                let mut data = vec![0; len as usize];
+                self.local.read_exact(&mut data).await?;
+
+                #[cfg(feature = "checksum")]
+                if let Some(checksums) = &mut self.checksums {
+                    checksums.sent(offset, &data);
+                }
+
+                let write = self.remote.write_at(offset, data);
+                requests.spawn(async move {
+                    let (offset, len) = write.await?;
+                    Ok(Chunk::Written(offset, len))
+                })
+            }
+        };
+
+        self.in_flight += 1;
+        Ok(task.id())
+    }
+
+    /// Handles the reply to a chunk and returns the progress
+    async fn receive(&mut self, chunk: Chunk) -> SftpResult<Option<ProgressEvent>> {
+        let (offset, len) = match chunk {
+            Chunk::Read(offset, _, None) => {
+                // the file may have been shrunk since the fstat
+                self.end = self.end.min(offset);
+                self.retry.retain(|(offset, _)| *offset < self.end);
+                return Ok(None);
+            }
+            Chunk::Read(offset, len, Some(mut data)) => {
+                self.expected.clamp(offset, len, &mut data);
+                self.local.seek(SeekFrom::Start(offset)).await?;
+                self.local.write_all(&data).await?;
+
+                #[cfg(feature = "checksum")]
+                if let Some(checksums) = &mut self.checksums {
+                    checksums.received(offset, &data)?;
+                }
+
+                let received = data.len() as u64;
+                if received < len && offset + received < self.end {
+                    self.retry.push_back((offset + received, len - received));
+                }
+
+                (offset, received)
+            }
+            Chunk::Written(offset, len) => {
+                #[cfg(feature = "checksum")]
+                if let Some(checksums) = &mut self.checksums {
+                    checksums.written(offset);
+                }
+
+                (offset, len)
+            }
+        };
+
+        self.transferred += len;
+        Ok(Some(ProgressEvent {
+            bytes_transferred: self.transferred,
+            direction: self.direction,
+            offset: offset + len,
+        }))
+    }
+
+    async fn finish(mut self) -> SftpResult<u64> {
+        if self.direction == Direction::Read && self.size.is_some_and(|size| self.end < size) {
+            self.local.set_len(self.end).await?;
+        }
+
+        self.local.flush().await?;
+        self.expected
+            .verify(&self.remote, self.resumed + self.transferred)
+            .await?;
+
+        #[cfg(feature = "checksum")]
+        self.verify().await?;
+
+        let (handle, session) = self.remote.detach();
+        session.close(handle).await?;
+
+        Ok(self.transferred)
+    }
+}
+
+#[cfg(feature = "checksum")]
+impl Active {
+    /// Continues the transfer after the range covered by the manifest
+    async fn resume(mut self) -> SftpResult<Self> {
+        let Some(checksums) = self.checksums.as_mut() else {
+            return Ok(self);
+        };
+
+        let offset = match self.direction {
+            Direction::Read => {
+                let local = &mut self.local;
+                checksums
+                    .resume(|offset, len| read_local(local, offset, len))
+                    .await?
+            }
+            Direction::Write => {
+                let remote = &self.remote;
+                checksums
+                    .resume(|offset, len| read_remote(remote, offset, len))
+                    .await?
+            }
+        };
+
+        self.next = offset.min(self.end);
+        self.resumed = self.next;
+        if self.direction == Direction::Write {
+            self.local.seek(SeekFrom::Start(self.next)).await?;
+        }
+
+        Ok(self)
+    }
+
+    /// Checks the downloaded file against the manifest the job was given
+    async fn verify(&mut self) -> SftpResult<()> {
+        let Some(checksums) = self.checksums.as_mut() else {
+            return Ok(());
+        };
+
+        if self.direction != Direction::Read {
+            return Ok(());
+        }
+
+        checksums.manifest.truncate(self.end);
+        for chunk in checksums.reference.chunks() {
+            if chunk.end() > self.end {
+                break;
+            }
+
+            let data = read_local(&mut self.local, chunk.offset, chunk.len).await?;
+            if !chunk.matches(&data) {
+                return Err(checksum::mismatch(chunk));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn take_checksums(&mut self) -> Vec<ChunkChecksum> {
+        self.checksums
+            .as_mut()
+            .map(Checksums::take_recorded)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(feature = "checksum")]
+async fn read_local(file: &mut fs::File, offset: u64, len: u64) -> SftpResult<Vec<u8>> {
+    file.seek(SeekFrom::Start(offset)).await?;
+
+    let mut data = Vec::with_capacity(len as usize);
+    (&mut *file).take(len).read_to_end(&mut data).await?;
+    Ok(data)
+}
+
+#[cfg(feature = "checksum")]
+async fn read_remote(file: &File, offset: u64, len: u64) -> SftpResult<Vec<u8>> {
+    let mut data = Vec::with_capacity(len as usize);
+    while (data.len() as u64) < len {
+        let rest = (len - data.len() as u64).min(file.chunk_size() as u64);
+        match file.read_at(offset + data.len() as u64, rest).await? {
+            (_, _, Some(chunk)) => data.extend(chunk.into_iter().take(rest as usize)),
+            (_, _, None) => break,
+        }
+    }
+
+    Ok(data)
+}
+
+/// Runs several uploads and downloads on one session.
+///
+/// The number of requests in flight is limited across all jobs, so the time between
+/// sending a request and its reply stays bounded and the requests are less likely to
+/// time out under load. Chunks are sent in turn for each job, or by priority with
+/// [`SchedulePolicy::Priority`], so that no job is starved.
+///
+/// ```no_run
+/// # async fn run(sftp: russh_sftp::client::SftpSession) {
+/// use russh_sftp::client::{TransferJob, TransferScheduler};
+///
+/// let mut scheduler = TransferScheduler::new(&sftp);
+/// scheduler.add(TransferJob::upload("a.bin", "/upload/a.bin"));
+/// scheduler.add(TransferJob::download("/backup/b.bin", "b.bin").priority(1));
+///
+/// for result in scheduler.run().await {
+///     println!("{:?}", result);
+/// }
+/// # }
+/// ```
+pub struct TransferScheduler<'a> {
+    session: &'a SftpSession,
+    jobs: Vec<TransferJob>,
+    max_in_flight: usize,
+    policy: SchedulePolicy,
+}
+
+impl<'a> TransferScheduler<'a> {
+    pub fn new(session: &'a SftpSession) -> Self {
+        Self {
+            session,
+            jobs: Vec::new(),
+            max_in_flight: DEFAULT_TRANSFER_REQUESTS,
+            policy: SchedulePolicy::default(),
+        }
+    }
+
+    /// Sets the number of requests in flight across all jobs.
+    /// Default: [`DEFAULT_TRANSFER_REQUESTS`]
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = max_in_flight.max(1);
+        self
+    }
+
+    /// Default: [`SchedulePolicy::RoundRobin`]
+    pub fn policy(mut self, policy: SchedulePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Adds the job and returns its index in the results
+    pub fn add(&mut self, job: TransferJob) -> usize {
+        self.jobs.push(job);
+        self.jobs.len() - 1
+    }
+
+    /// Runs all jobs and returns the number of bytes transferred by each of them.
+    /// A job whose request task panicked fails with [`Error::UnexpectedBehavior`]
+    /// holding the message of the panic, the other jobs go on
+    pub async fn run(self) -> Vec<SftpResult<u64>> {
+        self.run_with_events(|_| {}).await
+    }
+
+    /// Same as [`TransferScheduler::run`], reporting the progress and
+    /// the completion of the jobs to `callback`
+    pub async fn run_with_events<F>(self, mut callback: F) -> Vec<SftpResult<u64>>
+    where
+        F: FnMut(TransferEvent),
+    {
+        let mut active = Vec::with_capacity(self.jobs.len());
+        let mut results = vec![None; self.jobs.len()];
+
+        let mut complete = |job: usize, result: SftpResult<u64>, callback: &mut F| {
+            callback(TransferEvent::Completed {
+                job,
+                result: result.clone(),
+            });
+            results[job] = Some(result);
+        };
+
+        for (index, job) in self.jobs.iter().enumerate() {
+            match Active::open(self.session, job).await {
+                Ok(job) if job.is_done() => complete(index, job.finish().await, &mut callback),
+                Ok(job) => {
+                    active.push(Some(job));
+                    continue;
+                }
+                Err(err) => complete(index, Err(err), &mut callback),
+            }
+
+            active.push(None);
+        }
+
+        let mut requests = JoinSet::new();
+        // job of each task, a task that panicked has no reply to tell it
+        let mut owners = HashMap::new();
+        let mut cursor = 0;
+
+        loop {
+            while requests.len() < self.max_in_flight {
+                let Some(index) = self.pick(&active, &mut cursor) else {
+                    break;
+                };
+
+                let job = active[index].as_mut().expect("picked job is active");
+                match job.send(&mut requests).await {
+                    Ok(id) => {
+                        owners.insert(id, index);
+                    }
+                    Err(err) => {
+                        active[index] = None;
+                        complete(index, Err(err), &mut callback);
+                    }
+                }
+            }
+
+            let (index, result) = match requests.join_next_with_id().await {
+                Some(Ok((id, result))) => (owners.remove(&id), result),
+                Some(Err(err)) => (owners.remove(&err.id()), Err(join_error(err))),
+                None => break,
+            };
+            let index = index.expect("every task has a job");
+
+            // the replies to a failed job are discarded
+            let Some(job) = active[index].as_mut() else {
+                continue;
+            };
+
+            job.in_flight -= 1;
+            let progress = match result {
+                Ok(chunk) => job.receive(chunk).await,
+                Err(err) => Err(err),
+            };
+
+            match progress {
+                Ok(progress) => {
+                    #[cfg(feature = "checksum")]
+                    for chunk in job.take_checksums() {
+                        callback(TransferEvent::Checksum { job: index, chunk });
+                    }
+
+                    if let Some(progress) = progress {
+                        callback(TransferEvent::Progress {
+                            job: index,
+                            progress,
+                        });
+                    }
+
+                    if job.is_done() {
+                        let job = active[index].take().expect("job is active");
+                        complete(index, job.finish().await, &mut callback);
+                    }
+                }
+                Err(err) => {
+                    active[index] = None;
+                    complete(index, Err(err), &mut callback);
+                }
+            }
+        }
+
+        for (index, job) in active.into_iter().enumerate() {
+            if job.is_some() {
+                let interrupted = Error::UnexpectedBehavior("transfer interrupted".to_owned());
+                complete(index, Err(interrupted), &mut callback);
+            }
+        }
+
+        results
+            .into_iter()
+            .map(|result| result.expect("every job is completed"))
+            .collect()
+    }
+
+    /// Returns the job to send the next chunk of
+    fn pick(&self, active: &[Option<Active>], cursor: &mut usize) -> Option<usize> {
+        fn ready(job: &Option<Active>) -> Option<&Active> {
+            job.as_ref().filter(|job| job.has_chunks())
+        }
+
+        let priority = match self.policy {
+            SchedulePolicy::RoundRobin => None,
+            SchedulePolicy::Priority => active.iter().filter_map(ready).map(|j| j.priority).max(),
+        };
+
+        let index = (0..active.len())
+            .map(|i| (*cursor + i) % active.len())
+            .find(|&index| {
+                ready(&active[index]).is_some_and(|job| priority.is_none_or(|p| job.priority == p))
+            })?;
+
+        *cursor = index + 1;
+        Some(index)
+    }
+}
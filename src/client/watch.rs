@@ -0,0 +1,300 @@
+use futures_core::Stream;
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    sync::mpsc,
+    task::{JoinHandle, JoinSet},
+    time::{self, MissedTickBehavior},
+};
+
+use super::{error::Error, fs::Metadata, rawsession::SftpResult, session, RawSftpSession};
+use crate::protocol::{FileTime, FileType, StatusCode};
+
+/// Default number of paths polled at the same time by [`Watch`]
+pub const DEFAULT_WATCH_CONCURRENCY: usize = 16;
+
+/// Change detected by [`SftpSession::watch`](crate::client::SftpSession::watch)
+/// between two polls
+#[derive(Debug, Clone)]
+pub enum ChangeEvent {
+    /// The path or an entry of a watched directory appeared
+    Created(String),
+    /// The path or an entry of a watched directory disappeared
+    Removed(String),
+    /// The size, the modification time or the type of the file changed
+    Modified(String),
+    /// An entry of a watched directory disappeared and another one with the same
+    /// type, size and modification time appeared in the same poll.
+    /// The server does not report renames, so this is only a guess
+    Renamed { from: String, to: String },
+    /// The path could not be polled. The previous state is kept and
+    /// the path is polled again after the interval
+    Error { path: String, error: Error },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    file_type: FileType,
+    size: Option<u64>,
+    mtime: Option<FileTime>,
+}
+
+impl From<&Metadata> for Stamp {
+    fn from(metadata: &Metadata) -> Self {
+        Self {
+            file_type: metadata.file_type(),
+            size: metadata.size,
+            mtime: metadata.mtime,
+        }
+    }
+}
+
+impl Stamp {
+    /// Directories are compared by their entries, the modification
+    /// time of a directory changes with every created file
+    fn changed(&self, other: &Stamp) -> bool {
+        self.file_type != other.file_type || (!self.file_type.is_dir() && self != other)
+    }
+}
+
+/// State of a watched path after a poll
+#[derive(Debug, Default)]
+struct Snapshot {
+    stamp: Option<Stamp>,
+    entries: HashMap<String, Stamp>,
+}
+
+async fn snapshot(session: Arc<RawSftpSession>, path: String) -> SftpResult<Snapshot> {
+    let stamp = match session.stat(path.as_str()).await {
+        Ok(attrs) => Stamp::from(&attrs.attrs),
+        Err(Error::Status { status, .. }) if status.status_code == StatusCode::NoSuchFile => {
+            return Ok(Snapshot::default())
+        }
+        Err(err) => return Err(err),
+    };
+
+    let entries = match stamp.file_type.is_dir() {
+        true => session::read_dir_entries(&session, path)
+            .await?
+            .into_iter()
+            .filter(|(name, _)| name != "." && name != "..")
+            .map(|(name, metadata)| (name, Stamp::from(&metadata)))
+            .collect(),
+        false => HashMap::new(),
+    };
+
+    Ok(Snapshot {
+        stamp: Some(stamp),
+        entries,
+    })
+}
+
+fn join(dir: &str, name: &str) -> String {
+    match dir.ends_with('/') {
+        true => format!("{dir}{name}"),
+        false => format!("{dir}/{name}"),
+    }
+}
+
+fn diff(path: &str, old: &Snapshot, new: &Snapshot, events: &mut Vec<ChangeEvent>) {
+    let (old_stamp, new_stamp) = match (old.stamp, new.stamp) {
+        (None, None) => return,
+        (None, Some(_)) => return events.push(ChangeEvent::Created(path.to_owned())),
+        (Some(_), None) => return events.push(ChangeEvent::Removed(path.to_owned())),
+        (Some(old), Some(new)) => (old, new),
+    };
+
+    if old_stamp.changed(&new_stamp) {
+        events.push(ChangeEvent::Modified(path.to_owned()));
+    }
+
+    let mut removed = old
+        .entries
+        .iter()
+        .filter(|(name, _)| !new.entries.contains_key(*name))
+        .collect::<Vec<_>>();
+    let mut created = new
+        .entries
+        .iter()
+        .filter(|(name, _)| !old.entries.contains_key(*name))
+        .collect::<Vec<_>>();
+
+    // the order of the events should not depend on the hashing
+    removed.sort_unstable_by_key(|(name, _)| *name);
+    created.sort_unstable_by_key(|(name, _)| *name);
+
+    for (name, stamp) in &new.entries {
+        if old.entries.get(name).is_some_and(|old| old.changed(stamp)) {
+            events.push(ChangeEvent::Modified(join(path, name)));
+        }
+    }
+
+    // pair the entries only when the stamp is unambiguous on both sides
+    let unique = |entries: &[(&String, &Stamp)], stamp: &Stamp| {
+        entries.iter().filter(|(_, s)| *s == stamp).count() == 1
+    };
+
+    let mut renamed = Vec::new();
+    for (from, stamp) in &removed {
+        if stamp.mtime.is_none() || !unique(&removed, stamp) || !unique(&created, stamp) {
+            continue;
+        }
+
+        if let Some((to, _)) = created.iter().find(|(_, s)| s == stamp) {
+            renamed.push((*from, *to));
+        }
+    }
+
+    for (from, to) in &renamed {
+        removed.retain(|(name, _)| name != from);
+        created.retain(|(name, _)| name != to);
+
+        events.push(ChangeEvent::Renamed {
+            from: join(path, from),
+            to: join(path, to),
+        });
+    }
+
+    events.extend(
+        removed
+            .iter()
+            .map(|(name, _)| ChangeEvent::Removed(join(path, name))),
+    );
+    events.extend(
+        created
+            .iter()
+            .map(|(name, _)| ChangeEvent::Created(join(path, name))),
+    );
+}
+
+/// Polls all paths with at most `max_concurrent` of them in flight.
+/// The results are in the order of `paths`
+async fn poll_paths(
+    session: &Arc<RawSftpSession>,
+    paths: &[String],
+    max_concurrent: usize,
+) -> Vec<Option<SftpResult<Snapshot>>> {
+    let mut results = paths.iter().map(|_| None).collect::<Vec<_>>();
+    let mut pending = paths.iter().enumerate();
+    let mut tasks = JoinSet::new();
+
+    loop {
+        while tasks.len() < max_concurrent {
+            let Some((index, path)) = pending.next() else {
+                break;
+            };
+
+            let fut = snapshot(session.clone(), path.to_owned());
+            tasks.spawn(async move { (index, fut.await) });
+        }
+
+        match tasks.join_next().await {
+            Some(Ok((index, result))) => results[index] = Some(result),
+            Some(Err(err)) => warn!("polling of a watched path failed: {}", err),
+            None => break,
+        }
+    }
+
+    results
+}
+
+async fn run(
+    session: Arc<RawSftpSession>,
+    paths: Vec<String>,
+    interval: Duration,
+    max_concurrent: Arc<AtomicUsize>,
+    tx: mpsc::Sender<ChangeEvent>,
+) {
+    let mut snapshots: Vec<Option<Snapshot>> = paths.iter().map(|_| None).collect();
+    let mut ticker = time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        ticker.tick().await;
+
+        let limit = max_concurrent.load(Ordering::Relaxed);
+        let results = poll_paths(&session, &paths, limit).await;
+        let mut events = Vec::new();
+
+        for ((path, previous), result) in paths.iter().zip(&mut snapshots).zip(results) {
+            match result {
+                // the first successful poll is the baseline
+                Some(Ok(current)) => {
+                    if let Some(previous) = previous {
+                        diff(path, previous, &current, &mut events);
+                    }
+                    *previous = Some(current);
+                }
+                Some(Err(error)) => events.push(ChangeEvent::Error {
+                    path: path.to_owned(),
+                    error,
+                }),
+                None => (),
+            }
+        }
+
+        for event in events {
+            if tx.send(event).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Stream of [`ChangeEvent`] returned by [`SftpSession::watch`](crate::client::SftpSession::watch).
+///
+/// The paths are polled by a background task which is stopped when the stream is dropped.
+/// The stream never ends on its own, failures of single paths are reported
+/// as [`ChangeEvent::Error`].
+pub struct Watch {
+    rx: mpsc::Receiver<ChangeEvent>,
+    max_concurrent: Arc<AtomicUsize>,
+    task: JoinHandle<()>,
+}
+
+impl Watch {
+    pub(crate) fn new(
+        session: Arc<RawSftpSession>,
+        paths: Vec<String>,
+        interval: Duration,
+    ) -> Self {
+        let (tx, rx) = mpsc::channel(paths.len().max(16));
+        let max_concurrent = Arc::new(AtomicUsize::new(DEFAULT_WATCH_CONCURRENCY));
+        let task = tokio::spawn(run(session, paths, interval, max_concurrent.clone(), tx));
+
+        Self {
+            rx,
+            max_concurrent,
+            task,
+        }
+    }
+
+    /// Sets the maximum number of paths polled at the same time, starting with the next poll.
+    /// Default: [`DEFAULT_WATCH_CONCURRENCY`]
+    pub fn set_max_concurrent(&self, max_concurrent: usize) {
+        self.max_concurrent
+            .store(max_concurrent.max(1), Ordering::Relaxed);
+    }
+}
+
+impl Stream for Watch {
+    type Item = ChangeEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
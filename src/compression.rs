@@ -0,0 +1,107 @@
+use flate2::{read::DeflateDecoder, write::DeflateEncoder};
+use std::io::{Read, Write};
+
+use crate::extensions;
+
+/// Marker of a payload sent as is
+const RAW: u8 = 0;
+/// Marker of a raw deflate stream
+const DEFLATE: u8 = 1;
+
+/// Algorithm offered in [`extensions::COMPRESSION`]
+const ALGORITHM: &str = "deflate";
+
+/// Largest payload inflated from a packet, so that a small packet cannot
+/// allocate an unbounded amount of memory
+pub(crate) const MAX_INFLATED_LEN: usize = 16 * 1024 * 1024;
+
+/// Compression of the payloads of SSH_FXP_WRITE and SSH_FXP_DATA between two
+/// endpoints of this crate, negotiated with [`extensions::COMPRESSION`].
+///
+/// The client offers the algorithms it supports in SSH_FXP_INIT and the server
+/// replies with the one it accepts in SSH_FXP_VERSION. Once negotiated, each
+/// payload starts with a marker byte and is compressed if that makes it
+/// shorter, otherwise it is sent as is. The level only affects the sending side
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compression {
+    level: u32,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self::deflate(6)
+    }
+}
+
+impl Compression {
+    /// Deflate with the level from 0 (stored) to 9 (smallest), clamped to that range
+    pub fn deflate(level: u32) -> Self {
+        Self {
+            level: level.min(9),
+        }
+    }
+
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+
+    /// Name and value of the extension offered in SSH_FXP_INIT
+    pub(crate) fn offer() -> (String, String) {
+        (extensions::COMPRESSION.to_owned(), ALGORITHM.to_owned())
+    }
+
+    /// Returns `true` if the value of the extension, a comma-separated list
+    /// of algorithms, contains the one supported
+    pub(crate) fn accepts(value: &str) -> bool {
+        value.split(',').any(|name| name.trim() == ALGORITHM)
+    }
+
+    /// Value of the extension replied in SSH_FXP_VERSION
+    pub(crate) fn accepted() -> String {
+        ALGORITHM.to_owned()
+    }
+
+    /// Frames the payload, compressed unless that does not make it shorter
+    pub(crate) fn compress(&self, data: Vec<u8>) -> Vec<u8> {
+        let mut framed = Vec::with_capacity(data.len() / 2 + 1);
+        framed.push(DEFLATE);
+
+        let mut encoder = DeflateEncoder::new(framed, flate2::Compression::new(self.level));
+        match encoder.write_all(&data).and_then(|_| encoder.finish()) {
+            Ok(framed) if framed.len() <= data.len() => framed,
+            _ => {
+                let mut framed = Vec::with_capacity(data.len() + 1);
+                framed.push(RAW);
+                framed.extend_from_slice(&data);
+                framed
+            }
+        }
+    }
+
+    /// Unframes a payload of at most `max_len` bytes
+    pub(crate) fn decompress(data: Vec<u8>, max_len: usize) -> Result<Vec<u8>, String> {
+        let mut data = data;
+        let payload = match data.first() {
+            Some(&RAW) => {
+                data.remove(0);
+                data
+            }
+            Some(&DEFLATE) => {
+                let mut inflated = Vec::new();
+                DeflateDecoder::new(&data[1..])
+                    .take(max_len as u64 + 1)
+                    .read_to_end(&mut inflated)
+                    .map_err(|err| format!("invalid compressed payload: {err}"))?;
+                inflated
+            }
+            Some(marker) => return Err(format!("unknown compression marker {marker}")),
+            None => return Err("compressed payload without a marker".to_owned()),
+        };
+
+        if payload.len() > max_len {
+            return Err(format!("payload inflates beyond {max_len} bytes"));
+        }
+
+        Ok(payload)
+    }
+}
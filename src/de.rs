@@ -4,8 +4,32 @@ use std::fmt;
 
 use crate::{buf::TryBuf, error::Error};
 
+/// Nesting allowed by [`from_bytes`], well above the deepest packet of the protocol
+pub const MAX_DEPTH: usize = 32;
+
+/// Name of the newtype under which [`data_deserialize`] asks for the rest of the
+/// input in one piece
+const DATA_TOKEN: &str = "$russh_sftp::data";
+
 pub struct Deserializer<'a> {
     input: &'a mut Bytes,
+    /// Levels of nesting left before the input is refused
+    depth: usize,
+}
+
+impl Deserializer<'_> {
+    /// Enters a nested structure, so that a crafted packet cannot recurse deep enough
+    /// to exhaust a small stack
+    fn nested<T>(&mut self, visit: impl FnOnce(&mut Self) -> Result<T, Error>) -> Result<T, Error> {
+        if self.depth == 0 {
+            return Err(Error::BadMessage("nesting too deep".to_owned()));
+        }
+
+        self.depth -= 1;
+        let result = visit(self);
+        self.depth += 1;
+        result
+    }
 }
 
 /// Converting bytes to protocol-compliant type
@@ -13,12 +37,24 @@ pub fn from_bytes<'a, T>(bytes: &'a mut Bytes) -> Result<T, Error>
 where
     T: serde::Deserialize<'a>,
 {
-    let mut deserializer = Deserializer { input: bytes };
+    from_bytes_with_max_depth(bytes, MAX_DEPTH)
+}
+
+/// Same as [`from_bytes`], refusing the input nested more than `max_depth` levels
+pub fn from_bytes_with_max_depth<'a, T>(bytes: &'a mut Bytes, max_depth: usize) -> Result<T, Error>
+where
+    T: serde::Deserialize<'a>,
+{
+    let mut deserializer = Deserializer {
+        input: bytes,
+        depth: max_depth,
+    };
     T::deserialize(&mut deserializer)
 }
 
 /// Deserilization of a [`Vec`] without length. Usually reads until the end byte
-/// or end of the packet because the size is unknown.
+/// or end of the packet because the size is unknown. The [`Deserializer`] of the
+/// crate hands the rest of the input over at once instead of byte by byte
 pub fn data_deserialize<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
 where
     D: serde::Deserializer<'de>,
@@ -32,6 +68,20 @@ where
             formatter.write_str("data")
         }
 
+        fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            Ok(v)
+        }
+
+        fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+        {
+            deserializer.deserialize_any(self)
+        }
+
         fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
         where
             A: serde::de::SeqAccess<'de>,
@@ -44,10 +94,42 @@ where
         }
     }
 
-    deserializer.deserialize_any(DataVisitor)
+    deserializer.deserialize_newtype_struct(DATA_TOKEN, DataVisitor)
+}
+
+/// Deserialization of bytes with a length prefix without reading them one by one.
+pub fn bytes_deserialize<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    struct BytesVisitor;
+
+    impl Visitor<'_> for BytesVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("bytes")
+        }
+
+        fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            Ok(v.to_vec())
+        }
+
+        fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            Ok(v)
+        }
+    }
+
+    deserializer.deserialize_byte_buf(BytesVisitor)
 }
 
-impl<'de, 'a> serde::Deserializer<'de> for &'a mut Deserializer<'de> {
+impl<'de> serde::Deserializer<'de> for &mut Deserializer<'de> {
     type Error = Error;
 
     fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
@@ -55,10 +137,7 @@ impl<'de, 'a> serde::Deserializer<'de> for &'a mut Deserializer<'de> {
         V: serde::de::Visitor<'de>,
     {
         let len = self.input.len();
-        visitor.visit_seq(SeqDeserializer {
-            de: self,
-            len: Some(len),
-        })
+        self.nested(|de| visitor.visit_seq(SeqDeserializer { de, len: Some(len) }))
     }
 
     fn deserialize_bool<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
@@ -100,7 +179,7 @@ impl<'de, 'a> serde::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        visitor.visit_u8(self.input.try_get_u8()?)
+        visitor.visit_u8(TryBuf::try_get_u8(self.input)?)
     }
 
     fn deserialize_u16<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
@@ -114,14 +193,14 @@ impl<'de, 'a> serde::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        visitor.visit_u32(self.input.try_get_u32()?)
+        visitor.visit_u32(TryBuf::try_get_u32(self.input)?)
     }
 
     fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        visitor.visit_u64(self.input.try_get_u64()?)
+        visitor.visit_u64(TryBuf::try_get_u64(self.input)?)
     }
 
     fn deserialize_f32<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
@@ -170,7 +249,7 @@ impl<'de, 'a> serde::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        self.deserialize_bytes(visitor)
+        visitor.visit_byte_buf(self.input.try_get_bytes()?)
     }
 
     fn deserialize_option<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
@@ -200,34 +279,33 @@ impl<'de, 'a> serde::Deserializer<'de> for &'a mut Deserializer<'de> {
 
     fn deserialize_newtype_struct<V>(
         self,
-        _name: &'static str,
+        name: &'static str,
         visitor: V,
     ) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        visitor.visit_newtype_struct(self)
+        if name == DATA_TOKEN {
+            let len = self.input.len();
+            return visitor.visit_byte_buf(self.input.split_to(len).into());
+        }
+
+        self.nested(|de| visitor.visit_newtype_struct(de))
     }
 
     fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        let len = self.input.try_get_u32()? as usize;
-        visitor.visit_seq(SeqDeserializer {
-            de: self,
-            len: Some(len),
-        })
+        let len = TryBuf::try_get_u32(self.input)? as usize;
+        self.deserialize_tuple(len, visitor)
     }
 
     fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        visitor.visit_seq(SeqDeserializer {
-            de: self,
-            len: Some(len),
-        })
+        self.nested(|de| visitor.visit_seq(SeqDeserializer { de, len: Some(len) }))
     }
 
     fn deserialize_tuple_struct<V>(
@@ -246,7 +324,7 @@ impl<'de, 'a> serde::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        visitor.visit_map(MapDeserializer { de: self })
+        self.nested(|de| visitor.visit_map(MapDeserializer { de }))
     }
 
     fn deserialize_struct<V>(
@@ -270,7 +348,7 @@ impl<'de, 'a> serde::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        visitor.visit_enum(self)
+        self.nested(|de| visitor.visit_enum(de))
     }
 
     fn deserialize_identifier<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
@@ -297,7 +375,7 @@ struct SeqDeserializer<'a, 'de: 'a> {
     len: Option<usize>,
 }
 
-impl<'a, 'de> SeqAccess<'de> for SeqDeserializer<'a, 'de> {
+impl<'de> SeqAccess<'de> for SeqDeserializer<'_, 'de> {
     type Error = Error;
 
     fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
@@ -324,7 +402,7 @@ struct MapDeserializer<'a, 'de: 'a> {
     de: &'a mut Deserializer<'de>,
 }
 
-impl<'a, 'de> MapAccess<'de> for MapDeserializer<'a, 'de> {
+impl<'de> MapAccess<'de> for MapDeserializer<'_, 'de> {
     type Error = Error;
 
     fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
@@ -346,7 +424,7 @@ impl<'a, 'de> MapAccess<'de> for MapDeserializer<'a, 'de> {
     }
 }
 
-impl<'a, 'de> VariantAccess<'de> for &'a mut Deserializer<'de> {
+impl<'de> VariantAccess<'de> for &mut Deserializer<'de> {
     type Error = Error;
 
     fn unit_variant(self) -> Result<(), Self::Error> {
@@ -381,7 +459,7 @@ impl<'a, 'de> VariantAccess<'de> for &'a mut Deserializer<'de> {
     }
 }
 
-impl<'a, 'de> EnumAccess<'de> for &'a mut Deserializer<'de> {
+impl<'de> EnumAccess<'de> for &mut Deserializer<'de> {
     type Error = Error;
     type Variant = Self;
 
@@ -389,7 +467,8 @@ impl<'a, 'de> EnumAccess<'de> for &'a mut Deserializer<'de> {
     where
         V: serde::de::DeserializeSeed<'de>,
     {
-        let v = IntoDeserializer::<Self::Error>::into_deserializer(self.input.try_get_u32()?);
+        let v =
+            IntoDeserializer::<Self::Error>::into_deserializer(TryBuf::try_get_u32(self.input)?);
         Ok((seed.deserialize(v)?, self))
     }
 }
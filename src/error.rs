@@ -1,6 +1,7 @@
 use std::{fmt, io};
 use thiserror::Error;
 
+#[cfg(feature = "client")]
 use crate::client;
 
 #[derive(Debug, Clone, Error)]
@@ -11,12 +12,17 @@ pub enum Error {
     UnexpectedEof,
     #[error("Bad message: {0}")]
     BadMessage(String),
+    /// The stream is no longer aligned on the packets, such as after a corrupted
+    /// length. Nothing read from it afterwards can be trusted
+    #[error("Framing: {0}")]
+    Framing(String),
     #[error("Client error. ({0})")]
     Client(String),
     #[error("Unexpected behavior: {0}")]
     UnexpectedBehavior(String),
 }
 
+#[cfg(feature = "client")]
 impl From<client::error::Error> for Error {
     fn from(error: client::error::Error) -> Self {
         Self::Client(error.to_string())
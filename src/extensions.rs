@@ -1,9 +1,147 @@
-use crate::{error::Error, ser};
+use std::{borrow::Cow, collections::BTreeMap};
+
+use crate::{error::Error, protocol::Version, ser};
 
 pub const LIMITS: &str = "limits@openssh.com";
 pub const HARDLINK: &str = "hardlink@openssh.com";
 pub const FSYNC: &str = "fsync@openssh.com";
 pub const STATVFS: &str = "statvfs@openssh.com";
+pub const VENDOR_ID: &str = "vendor-id";
+pub const NEWLINE: &str = "newline";
+pub const HOME_DIRECTORY: &str = "home-directory";
+/// Comma-separated protocol versions the server can switch to with [`VERSION_SELECT`]
+pub const VERSIONS: &str = "versions";
+/// Switches the session to another protocol version, sent as the first request
+pub const VERSION_SELECT: &str = "version-select";
+/// Byte-range locking modeled after `SSH_FXP_BLOCK` of protocol version 6
+pub const BLOCK: &str = "block@russh-sftp.rs";
+/// Byte-range unlocking modeled after `SSH_FXP_UNBLOCK` of protocol version 6
+pub const UNBLOCK: &str = "unblock@russh-sftp.rs";
+/// Advertised by servers attaching access control lists to the attributes
+pub const ACL_SUPPORTED: &str = "acl-supported@openssh.com";
+/// Extended attribute holding the access control list, see
+/// [`FileAttributes::acl`](crate::protocol::FileAttributes::acl)
+pub const ACL: &str = "acl@openssh.com";
+/// Compression of data payloads, see [`Compression`](crate::compression::Compression)
+pub const COMPRESSION: &str = "compression@russh-sftp.rs";
+
+/// Extensions advertised with values that vary between servers, such as an empty
+/// value or `1` followed by NUL for `limits@openssh.com`. Their presence is enough,
+/// see [`Version::supports`](crate::protocol::Version::supports)
+pub(crate) const UNVERSIONED: &[&str] = &[LIMITS];
+
+/// Extensions that change the behavior of the session, as opposed to the
+/// informational ones. Also lists those of OpenSSH that this crate does not send
+pub(crate) const BEHAVIOR: &[&str] = &[
+    LIMITS,
+    HARDLINK,
+    FSYNC,
+    STATVFS,
+    HOME_DIRECTORY,
+    VERSIONS,
+    VERSION_SELECT,
+    BLOCK,
+    UNBLOCK,
+    ACL_SUPPORTED,
+    COMPRESSION,
+    "posix-rename@openssh.com",
+    "fstatvfs@openssh.com",
+    "lsetstat@openssh.com",
+    "expand-path@openssh.com",
+    "copy-data",
+    "users-groups-by-id@openssh.com",
+];
+
+/// Known extensions whose support can be checked before use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// `limits@openssh.com`
+    Limits,
+    /// `hardlink@openssh.com`
+    Hardlink,
+    /// `fsync@openssh.com`
+    Fsync,
+    /// `statvfs@openssh.com`
+    Statvfs,
+    /// `block@russh-sftp.rs` and `unblock@russh-sftp.rs`
+    Block,
+    /// `home-directory`
+    HomeDirectory,
+}
+
+impl Capability {
+    /// Returns the name of the extension
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Limits => LIMITS,
+            Self::Hardlink => HARDLINK,
+            Self::Fsync => FSYNC,
+            Self::Statvfs => STATVFS,
+            Self::Block => BLOCK,
+            Self::HomeDirectory => HOME_DIRECTORY,
+        }
+    }
+}
+
+/// Extensions advertised in SSH_FXP_VERSION, sorted by name
+///
+/// The values are held as decoded by [`Version`], so the bytes that are not UTF-8
+/// are already replaced with U+FFFD
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServerCapabilities {
+    extensions: BTreeMap<String, String>,
+}
+
+impl ServerCapabilities {
+    /// Returns `true` if the extension is advertised, whatever its value
+    pub fn contains(&self, name: &str) -> bool {
+        self.extensions.contains_key(name)
+    }
+
+    /// Returns the value of the extension
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.extensions.get(name).map(|value| value.as_bytes())
+    }
+
+    /// Returns the value of the extension as text, without the NUL bytes some
+    /// servers append
+    pub fn get_str(&self, name: &str) -> Option<Cow<'_, str>> {
+        let value = self.get(name)?;
+        let end = value.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+        Some(String::from_utf8_lossy(&value[..end]))
+    }
+
+    /// Returns all the advertised extensions
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.extensions
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_bytes()))
+    }
+
+    /// Returns the extensions that only inform, such as `vendor-id` or the version
+    /// strings of `mod_sftp`, leaving out those that change the behavior
+    pub fn informational(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.iter().filter(|(name, _)| !BEHAVIOR.contains(name))
+    }
+
+    /// Returns the extensions whose name starts with `prefix`, such as `"vendor-"`
+    pub fn find_prefix<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = (&'a str, &'a [u8])> {
+        self.iter()
+            .filter(move |(name, _)| name.starts_with(prefix))
+    }
+}
+
+impl From<&Version> for ServerCapabilities {
+    fn from(version: &Version) -> Self {
+        Self {
+            extensions: version
+                .extensions
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+        }
+    }
+}
 
 macro_rules! impl_try_into_bytes {
     ($struct:ty) => {
@@ -33,6 +171,13 @@ pub struct HardlinkExtension {
 
 impl_try_into_bytes!(HardlinkExtension);
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VersionSelectExtension {
+    pub version: String,
+}
+
+impl_try_into_bytes!(VersionSelectExtension);
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FsyncExtension {
     pub handle: String,
@@ -47,6 +192,46 @@ pub struct StatvfsExtension {
 
 impl_try_into_bytes!(StatvfsExtension);
 
+/// Request of `home-directory`, an empty `username` is the current user
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HomeDirectoryExtension {
+    pub username: String,
+}
+
+impl_try_into_bytes!(HomeDirectoryExtension);
+
+/// Lock flags of the byte-range according to the version 6 specification
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockFlags(u32);
+
+bitflags! {
+    impl LockFlags: u32 {
+        const READ = 0x00000040;
+        const WRITE = 0x00000080;
+        const DELETE = 0x00000100;
+        const ADVISORY = 0x00000200;
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BlockExtension {
+    pub handle: String,
+    pub offset: u64,
+    pub length: u64,
+    pub lock_flags: LockFlags,
+}
+
+impl_try_into_bytes!(BlockExtension);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnblockExtension {
+    pub handle: String,
+    pub offset: u64,
+    pub length: u64,
+}
+
+impl_try_into_bytes!(UnblockExtension);
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Statvfs {
     /// The file system block size
@@ -0,0 +1,174 @@
+use bytes::{Buf, Bytes, BytesMut};
+#[cfg(any(feature = "client", feature = "server"))]
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{error::Error, protocol::Packet};
+
+/// Shortest packet, the type and a request id or a version
+pub const MIN_PACKET_LEN: u32 = 5;
+
+/// Default bound of the length of the packets read by the client and the server,
+/// above the largest packets sent by OpenSSH and by this crate
+pub const DEFAULT_MAX_PACKET_LEN: u32 = 4 * 1024 * 1024;
+
+/// Length that frames each packet
+const LENGTH_LEN: usize = 4;
+
+/// Splits the packets off a byte stream delivered in chunks of any size, for
+/// transports other than tokio streams such as the pipes of a child process
+/// or WebSocket messages. A packet may span several chunks and a chunk may
+/// hold several packets.
+///
+/// Without a maximum length every frame is accepted, otherwise the length and
+/// the type are checked as by [`read_checked_packet`] as soon as they are received
+#[derive(Debug, Default)]
+pub struct FrameDecoder {
+    buf: BytesMut,
+    max_len: Option<u32>,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a decoder that fails with [`Error::Framing`] on a length out of
+    /// [`MIN_PACKET_LEN`]`..=max_len` or a type not defined by the protocol
+    pub fn with_max_len(max_len: u32) -> Self {
+        Self {
+            buf: BytesMut::new(),
+            max_len: Some(max_len),
+        }
+    }
+
+    /// Appends received bytes
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Returns the next complete packet without its length, or [`None`] until more
+    /// bytes are pushed. Once it failed, the stream cannot be delimited any longer
+    /// and the same error is returned again
+    pub fn next_frame(&mut self) -> Result<Option<Bytes>, Error> {
+        split_frame(&mut self.buf, self.max_len)
+    }
+
+    /// Returns the number of bytes received and not yet returned as packets
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+}
+
+/// Serializes the packet with its length, ready to be sent
+pub fn encode_frame(packet: Packet) -> Result<Bytes, Error> {
+    Bytes::try_from(packet)
+}
+
+/// Returns the number of bytes to add to `buf` before [`split_frame`] can decide,
+/// the type being checked on its own before the rest of the packet is awaited
+#[cfg(any(feature = "client", feature = "server"))]
+fn missing(buf: &[u8], checked: bool) -> usize {
+    let Some(length) = buf.first_chunk::<LENGTH_LEN>() else {
+        return LENGTH_LEN - buf.len();
+    };
+
+    if checked && buf.len() == LENGTH_LEN {
+        return 1;
+    }
+
+    (LENGTH_LEN + u32::from_be_bytes(*length) as usize).saturating_sub(buf.len())
+}
+
+/// Splits the next packet off `buf` if complete, checking its header as soon as
+/// it is in `buf` if there is a maximum length
+fn split_frame(buf: &mut BytesMut, max_len: Option<u32>) -> Result<Option<Bytes>, Error> {
+    let Some(length) = buf.first_chunk::<LENGTH_LEN>() else {
+        return Ok(None);
+    };
+    let length = u32::from_be_bytes(*length);
+
+    if let Some(max_len) = max_len {
+        if !(MIN_PACKET_LEN..=max_len).contains(&length) {
+            return Err(Error::Framing(format!(
+                "packet length {length} out of bounds {MIN_PACKET_LEN}..={max_len}"
+            )));
+        }
+
+        if let Some(&r#type) = buf.get(LENGTH_LEN) {
+            if !Packet::is_defined_type(r#type) {
+                return Err(Error::Framing(format!("unknown packet type {}", r#type)));
+            }
+        }
+    }
+
+    let length = length as usize;
+    if buf.len() < LENGTH_LEN + length {
+        return Ok(None);
+    }
+
+    buf.advance(LENGTH_LEN);
+    Ok(Some(buf.split_to(length).freeze()))
+}
+
+/// Reads exactly the bytes of the next packet, so that nothing
+/// of the following packets is taken from the stream
+#[cfg(any(feature = "client", feature = "server"))]
+async fn read_frame<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    buf: &mut BytesMut,
+    max_len: Option<u32>,
+) -> Result<Bytes, Error> {
+    buf.clear();
+    loop {
+        if let Some(frame) = split_frame(buf, max_len)? {
+            return Ok(frame);
+        }
+
+        let start = buf.len();
+        buf.resize(start + missing(buf, max_len.is_some()), 0);
+        stream.read_exact(&mut buf[start..]).await?;
+    }
+}
+
+/// Reads a length-prefixed packet into `buf` and returns it as a view.
+///
+/// The buffer is meant to be reused for every frame of the connection, so its
+/// allocation is reclaimed once the previous packets have been dropped
+#[cfg(any(feature = "client", feature = "server"))]
+pub async fn read_packet<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    buf: &mut BytesMut,
+) -> Result<Bytes, Error> {
+    read_frame(stream, buf, None).await
+}
+
+/// Same as [`read_packet`], but checks that the declared length is between
+/// [`MIN_PACKET_LEN`] and `max_len` and that the type is defined by the protocol
+/// before consuming the packet.
+///
+/// Returns [`Error::Framing`] otherwise, as the stream has most likely lost the
+/// boundaries of the packets and what follows would be parsed as noise
+#[cfg(any(feature = "client", feature = "server"))]
+pub async fn read_checked_packet<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    buf: &mut BytesMut,
+    max_len: u32,
+) -> Result<Bytes, Error> {
+    read_frame(stream, buf, Some(max_len)).await
+}
+
+/// Writes the encoded packet with its length, for example
+/// one obtained from [`read_packet`] that is forwarded unchanged
+#[cfg(any(feature = "client", feature = "server"))]
+pub async fn write_packet<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    payload: &[u8],
+) -> Result<(), Error> {
+    stream
+        .write_u32(crate::ser::wire_len(payload.len())?)
+        .await?;
+    stream.write_all(payload).await?;
+    stream.flush().await?;
+
+    Ok(())
+}
@@ -12,12 +12,37 @@
 //!   from all the nuances and flaws of the SFTP protocol. This also takes into account the extension
 //!   provided by the server provided by the server such as `limits@openssh.com` and `fsync@openssh.com`.
 //!
+//! The common imports are gathered in [`prelude`] for clients and in `server::prelude`
+//! for servers.
+//!
 //! You can find more examples in the repository.
+//!
+//! # Features
+//!
+//! * `client` and `server` (default) - the corresponding sides, both depend on tokio.
+//! * `std-fs` (default) - conversions from [`std::fs`] types, copies and transfers of the
+//!   client from and to local files.
+//! * `russh` - helpers to run on top of a russh channel.
+//! * `blocking` - blocking façade over the client for callers without an async runtime.
+//! * `checksum` - checksums of the chunks of client transfers.
+//! * `handle-backtraces` - backtraces of the requests of the handles tracked by the client.
+//! * `glob` - glob patterns matched against remote directories by the client.
+//! * `audit-json` - JSON lines sink of the server audit log.
+//! * `testkit` - fault injection in front of a server to test the retry and timeout logic of clients.
+//! * `diagnostics` - JSON representation of the packets for diagnostics tooling.
+//! * `compression` - compression of data payloads between two endpoints of this crate.
+//! * `tracing` - a span per request of the client and per request handled by the server,
+//!   with the packet type, the id and the resulting status.
+//!
+//! With `default-features = false` only the packets, their (de)serialization and
+//! the [`FrameDecoder`](crate::framing::FrameDecoder) are compiled, which also builds
+//! for targets without tokio such as `wasm32-unknown-unknown`.
 
 #[macro_use]
 extern crate log;
 #[macro_use]
 extern crate bitflags;
+#[cfg(any(feature = "client", feature = "server"))]
 #[macro_use]
 extern crate async_trait;
 #[macro_use]
@@ -25,13 +50,24 @@ extern crate serde;
 
 mod buf;
 /// Client side
+#[cfg(feature = "client")]
 pub mod client;
+/// Compression of data payloads
+#[cfg(all(feature = "compression", any(feature = "client", feature = "server")))]
+pub mod compression;
 pub mod de;
 mod error;
 pub mod extensions;
+/// Length-prefixed packets of the stream
+pub mod framing;
+pub mod prelude;
 /// Protocol implementation
 pub mod protocol;
 pub mod ser;
 /// Server side
+#[cfg(feature = "server")]
 pub mod server;
-mod utils;
+#[cfg(all(feature = "tracing", any(feature = "client", feature = "server")))]
+mod spans;
+
+pub use error::Error;
@@ -0,0 +1,46 @@
+//! Common types of the client and of the protocol, for a single glob import.
+//!
+//! The [`Handler`](crate::client::Handler) of the client is exported as
+//! [`ClientHandler`] so that it does not collide with the one of the server,
+//! whose authors have [`server::prelude`](crate::server::prelude).
+#![cfg_attr(
+    feature = "client",
+    doc = r#"
+```no_run
+use russh_sftp::prelude::*;
+
+# async fn run(stream: tokio::io::DuplexStream) -> SftpResult<()> {
+let sftp = SftpSession::new(stream).await?;
+
+let mut file = sftp.create("/tmp/hello.txt").await?;
+file.write_all(b"hello").await?;
+file.shutdown().await?;
+
+for entry in sftp.read_dir("/tmp").await? {
+    let metadata: Metadata = entry.metadata();
+    println!("{} {:?} {:?}", entry.file_name(), entry.file_type(), metadata.size);
+}
+
+let mut file = sftp.open_with_flags("/tmp/hello.txt", OpenFlags::READ).await?;
+let mut contents = String::new();
+file.read_to_string(&mut contents).await?;
+
+match sftp.metadata("/missing").await {
+    Err(SftpError::Status { status, .. }) if status.status_code == StatusCode::NoSuchFile => (),
+    result => println!("{result:?}"),
+}
+# Ok(())
+# }
+```
+"#
+)]
+
+pub use crate::protocol::{FileAttributes, FileMode, FileType, OpenFlags, StatusCode};
+
+#[cfg(feature = "client")]
+pub use crate::client::{
+    error::Error as SftpError, fs::File, rawsession::SftpResult, ClientHandler, DirEntry, Metadata,
+    RawSftpSession, SessionBuilder, SftpSession,
+};
+#[cfg(feature = "client")]
+pub use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
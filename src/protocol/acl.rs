@@ -0,0 +1,98 @@
+use std::mem::size_of;
+
+/// Access control list of [`FileAttributes::acl`](super::FileAttributes::acl), in the
+/// format of the `acl` attribute of the specification drafts: the `acl-flags` of
+/// version 6 if present, the number of entries and the entries
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AclEntries {
+    /// `acl-flags` of version 6, [`None`] for the version 4 format
+    pub flags: Option<u32>,
+    pub entries: Vec<AclEntry>,
+}
+
+/// Access control entry of [`AclEntries`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AclEntry {
+    /// One of the `ACE4_*_ACE_TYPE` values such as [`AclEntry::ACCESS_ALLOWED`]
+    pub ace_type: u32,
+    pub ace_flags: u32,
+    /// Permissions the entry applies to, such as `ACE4_READ_DATA`
+    pub ace_mask: u32,
+    /// Principal such as `OWNER@` or `alice@example.com`
+    pub who: String,
+}
+
+impl AclEntry {
+    pub const ACCESS_ALLOWED: u32 = 0;
+    pub const ACCESS_DENIED: u32 = 1;
+    pub const SYSTEM_AUDIT: u32 = 2;
+    pub const SYSTEM_ALARM: u32 = 3;
+}
+
+/// Reads the big-endian fields of a blob
+struct Cursor<'a>(&'a [u8]);
+
+impl Cursor<'_> {
+    fn u32(&mut self) -> Option<u32> {
+        let (word, rest) = self.0.split_first_chunk::<{ size_of::<u32>() }>()?;
+        self.0 = rest;
+        Some(u32::from_be_bytes(*word))
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        if len > self.0.len() {
+            return None;
+        }
+
+        let (string, rest) = self.0.split_at(len);
+        self.0 = rest;
+        String::from_utf8(string.to_vec()).ok()
+    }
+
+    fn entries(&mut self) -> Option<Vec<AclEntry>> {
+        let count = self.u32()? as usize;
+        // every entry takes at least 16 bytes, which bounds the allocation
+        if count > self.0.len() / 16 {
+            return None;
+        }
+
+        (0..count)
+            .map(|_| {
+                Some(AclEntry {
+                    ace_type: self.u32()?,
+                    ace_flags: self.u32()?,
+                    ace_mask: self.u32()?,
+                    who: self.string()?,
+                })
+            })
+            .collect()
+    }
+}
+
+impl AclEntries {
+    /// Parses the blob in the version 4 format, then in the version 6 format.
+    /// [`None`] if the blob is in neither or has trailing bytes
+    pub fn parse(blob: &[u8]) -> Option<Self> {
+        let v4 = || {
+            let mut cursor = Cursor(blob);
+            let entries = cursor.entries()?;
+            cursor.0.is_empty().then_some(Self {
+                flags: None,
+                entries,
+            })
+        };
+
+        let v6 = || {
+            let mut cursor = Cursor(blob);
+            let flags = cursor.u32()?;
+            let entries = cursor.entries()?;
+            cursor.0.is_empty().then_some(Self {
+                flags: Some(flags),
+                entries,
+            })
+        };
+
+        v4().or_else(v6)
+    }
+}
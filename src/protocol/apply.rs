@@ -0,0 +1,189 @@
+use std::{
+    error, fmt,
+    fs::{self, File, FileTimes, OpenOptions, Permissions},
+    io,
+    path::Path,
+};
+
+use super::FileAttributes;
+
+/// Attribute of [`FileAttributes`] reported by [`ApplyAttrsError`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Attribute {
+    /// `size`, applied with [`File::set_len`]
+    Size,
+    /// `uid` and `gid`, applied with `chown` on unix and ignored elsewhere
+    Owner,
+    /// `permissions`, only the read-only flag outside of unix
+    Permissions,
+    /// `atime` and `mtime`
+    Times,
+}
+
+impl fmt::Display for Attribute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Size => "size",
+            Self::Owner => "owner",
+            Self::Permissions => "permissions",
+            Self::Times => "times",
+        })
+    }
+}
+
+/// Attributes that could not be applied by [`FileAttributes::apply_to_path`]
+/// or [`FileAttributes::apply_to_file`], the others are applied anyway.
+///
+/// Returned inside the [`io::Error`] with the kind of the first failure,
+/// it can be obtained with [`io::Error::get_ref`] and `downcast_ref`
+#[derive(Debug)]
+pub struct ApplyAttrsError {
+    pub failures: Vec<(Attribute, io::Error)>,
+}
+
+impl fmt::Display for ApplyAttrsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (attribute, err)) in self.failures.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "unable to apply the {attribute}: {err}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl error::Error for ApplyAttrsError {}
+
+/// Local file the attributes are applied to
+enum Target<'a> {
+    Path(&'a Path),
+    File(&'a File),
+}
+
+impl Target<'_> {
+    fn with_file<T>(&self, write: bool, f: impl FnOnce(&File) -> io::Result<T>) -> io::Result<T> {
+        match self {
+            Self::Path(path) => f(&OpenOptions::new().read(!write).write(write).open(path)?),
+            Self::File(file) => f(file),
+        }
+    }
+
+    fn set_len(&self, size: u64) -> io::Result<()> {
+        self.with_file(true, |file| file.set_len(size))
+    }
+
+    #[cfg(unix)]
+    fn set_owner(&self, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+        match self {
+            Self::Path(path) => std::os::unix::fs::chown(path, uid, gid),
+            Self::File(file) => std::os::unix::fs::fchown(file, uid, gid),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn set_owner(&self, _uid: Option<u32>, _gid: Option<u32>) -> io::Result<()> {
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn set_permissions(&self, mode: u32) -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        // the type of the file is not part of the permissions
+        self.apply_permissions(Permissions::from_mode(mode & 0o7777))
+    }
+
+    #[cfg(not(unix))]
+    fn set_permissions(&self, mode: u32) -> io::Result<()> {
+        let mut permissions = match self {
+            Self::Path(path) => fs::metadata(path)?.permissions(),
+            Self::File(file) => file.metadata()?.permissions(),
+        };
+
+        permissions.set_readonly(mode & 0o222 == 0);
+        self.apply_permissions(permissions)
+    }
+
+    fn apply_permissions(&self, permissions: Permissions) -> io::Result<()> {
+        match self {
+            Self::Path(path) => fs::set_permissions(path, permissions),
+            Self::File(file) => file.set_permissions(permissions),
+        }
+    }
+
+    fn set_times(&self, times: FileTimes) -> io::Result<()> {
+        // changing the times of an open file only requires the ownership on unix
+        self.with_file(cfg!(not(unix)), |file| file.set_times(times))
+    }
+}
+
+impl FileAttributes {
+    /// Applies the attributes that are set to the file at `path`, for example the
+    /// attributes of SSH_FXP_SETSTAT. Symlinks are followed.
+    ///
+    /// Truncating requires the file to be writable. Outside of unix the owner is
+    /// ignored and the permissions only set the read-only flag. If some attributes
+    /// fail, the error contains an [`ApplyAttrsError`] naming each of them
+    pub fn apply_to_path<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.apply(Target::Path(path.as_ref()))
+    }
+
+    /// Same as [`FileAttributes::apply_to_path`] for an open file,
+    /// for example the attributes of SSH_FXP_FSETSTAT
+    pub fn apply_to_file(&self, file: &File) -> io::Result<()> {
+        self.apply(Target::File(file))
+    }
+
+    fn apply(&self, target: Target<'_>) -> io::Result<()> {
+        let mut failures = Vec::new();
+        let mut check = |attribute, result: io::Result<()>| {
+            if let Err(err) = result {
+                failures.push((attribute, err));
+            }
+        };
+
+        if let Some(size) = self.size {
+            check(Attribute::Size, target.set_len(size));
+        }
+
+        // changing the owner may clear the setuid bits, so it goes before the permissions
+        if self.uid.is_some() || self.gid.is_some() {
+            check(Attribute::Owner, target.set_owner(self.uid, self.gid));
+        }
+
+        if let Some(permissions) = self.permissions {
+            check(Attribute::Permissions, target.set_permissions(permissions));
+        }
+
+        // the times go last since truncating changes the modification time
+        if self.atime.is_some() || self.mtime.is_some() {
+            let mut times = FileTimes::new();
+            if let Ok(atime) = self.accessed() {
+                times = times.set_accessed(atime);
+            }
+            if let Ok(mtime) = self.modified() {
+                times = times.set_modified(mtime);
+            }
+
+            check(Attribute::Times, target.set_times(times));
+        }
+
+        match failures.first() {
+            None => Ok(()),
+            Some((_, err)) => Err(io::Error::new(err.kind(), ApplyAttrsError { failures })),
+        }
+    }
+
+    /// Reads the attributes of the file at `path`. With `follow` set to `false`
+    /// the attributes of a symlink itself are returned, as for SSH_FXP_LSTAT
+    pub fn from_path_symlink_aware<P: AsRef<Path>>(path: P, follow: bool) -> io::Result<Self> {
+        let metadata = match follow {
+            true => fs::metadata(path)?,
+            false => fs::symlink_metadata(path)?,
+        };
+
+        Ok(Self::from(&metadata))
+    }
+}
@@ -3,7 +3,7 @@ use super::{impl_packet_for, impl_request_id, FileAttributes, Packet, RequestId}
 /// Implementation for `SSH_FXP_ATTRS`
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Attrs {
-    pub id: u32,
+    pub id: RequestId,
     pub attrs: FileAttributes,
 }
 
@@ -1,11 +1,26 @@
+use bytes::Bytes;
+
 use super::{impl_packet_for, impl_request_id, Packet, RequestId};
+use crate::{de::bytes_deserialize, ser::bytes_serialize};
 
 /// Implementation for `SSH_FXP_DATA`
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Data {
-    pub id: u32,
+    pub id: RequestId,
+    #[serde(serialize_with = "bytes_serialize")]
+    #[serde(deserialize_with = "bytes_deserialize")]
     pub data: Vec<u8>,
 }
 
 impl_request_id!(Data);
 impl_packet_for!(Data);
+
+impl Data {
+    /// Creates a reply from [`Bytes`] without copying if the buffer is not shared
+    pub fn from_bytes<I: Into<RequestId>>(id: I, data: Bytes) -> Self {
+        Self {
+            id: id.into(),
+            data: data.into(),
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use bitflags::Flags;
+use serde_json::{json, Map, Value};
+
+use super::{File, FileAttributes, Packet};
+
+/// Names of the set flags, followed by the unknown bits in hex
+fn flag_names<F: Flags<Bits = u32>>(flags: &F) -> Value {
+    let mut names = flags
+        .iter_names()
+        .map(|(name, _)| Value::from(name))
+        .collect::<Vec<_>>();
+
+    let unknown = flags.bits() & !F::all().bits();
+    if unknown != 0 {
+        names.push(Value::from(format!("{unknown:#010x}")));
+    }
+
+    Value::Array(names)
+}
+
+fn hex(data: &[u8]) -> Value {
+    Value::from(
+        data.iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>(),
+    )
+}
+
+fn attrs(attrs: &FileAttributes) -> Value {
+    let mut value = Map::new();
+    value.insert("flags".to_owned(), flag_names(&attrs.flags()));
+
+    let fields = [
+        ("size", attrs.size.map(Value::from)),
+        ("uid", attrs.uid.map(Value::from)),
+        ("user", attrs.user.clone().map(Value::from)),
+        ("gid", attrs.gid.map(Value::from)),
+        ("group", attrs.group.clone().map(Value::from)),
+        ("permissions", attrs.permissions.map(Value::from)),
+        ("atime", attrs.atime.map(|time| Value::from(time.secs()))),
+        ("mtime", attrs.mtime.map(|time| Value::from(time.secs()))),
+    ];
+
+    for (name, field) in fields {
+        if let Some(field) = field {
+            value.insert(name.to_owned(), field);
+        }
+    }
+
+    if !attrs.extended.is_empty() {
+        let extended = attrs
+            .extended
+            .iter()
+            .map(|attr| json!({ "name": attr.name, "data": hex(&attr.data) }))
+            .collect();
+        value.insert("extended".to_owned(), Value::Array(extended));
+    }
+
+    Value::Object(value)
+}
+
+fn file(file: &File) -> Value {
+    json!({
+        "filename": file.filename,
+        "longname": file.longname,
+        "attrs": attrs(&file.attrs),
+    })
+}
+
+impl Packet {
+    /// Returns the packet as JSON for diagnostics, such as
+    /// `{"handle":"h1","id":3,"len":32768,"offset":0,"type":"SSH_FXP_READ"}`.
+    ///
+    /// The fields are named as in the packet structs and sorted, byte blobs are hex encoded
+    /// and flags are lists of names, with unknown bits in hex. The format is not
+    /// parsed back and differs from the [`Serialize`] implementations, which
+    /// produce the wire format only
+    pub fn to_diagnostic_json(&self) -> Value {
+        let fields = match self {
+            Self::Init(init) => json!({
+                "version": init.version,
+                "extensions": init.extensions,
+            }),
+            Self::Version(version) => json!({
+                "version": version.version,
+                "extensions": version.extensions,
+            }),
+            Self::Open(open) => json!({
+                "id": open.id,
+                "filename": open.filename,
+                "pflags": flag_names(&open.pflags),
+                "attrs": attrs(&open.attrs),
+            }),
+            Self::Close(close) => json!({ "id": close.id, "handle": close.handle }),
+            Self::Read(read) => json!({
+                "id": read.id,
+                "handle": read.handle,
+                "offset": read.offset,
+                "len": read.len,
+            }),
+            Self::Write(write) => json!({
+                "id": write.id,
+                "handle": write.handle,
+                "offset": write.offset,
+                "data": hex(&write.data),
+            }),
+            Self::Lstat(lstat) => json!({ "id": lstat.id, "path": lstat.path }),
+            Self::Fstat(fstat) => json!({ "id": fstat.id, "handle": fstat.handle }),
+            Self::SetStat(setstat) => json!({
+                "id": setstat.id,
+                "path": setstat.path,
+                "attrs": attrs(&setstat.attrs),
+            }),
+            Self::FSetStat(fsetstat) => json!({
+                "id": fsetstat.id,
+                "handle": fsetstat.handle,
+                "attrs": attrs(&fsetstat.attrs),
+            }),
+            Self::OpenDir(opendir) => json!({ "id": opendir.id, "path": opendir.path }),
+            Self::ReadDir(readdir) => json!({ "id": readdir.id, "handle": readdir.handle }),
+            Self::Remove(remove) => json!({ "id": remove.id, "filename": remove.filename }),
+            Self::MkDir(mkdir) => json!({
+                "id": mkdir.id,
+                "path": mkdir.path,
+                "attrs": attrs(&mkdir.attrs),
+            }),
+            Self::RmDir(rmdir) => json!({ "id": rmdir.id, "path": rmdir.path }),
+            Self::RealPath(realpath) => json!({ "id": realpath.id, "path": realpath.path }),
+            Self::Stat(stat) => json!({ "id": stat.id, "path": stat.path }),
+            Self::Rename(rename) => json!({
+                "id": rename.id,
+                "oldpath": rename.oldpath,
+                "newpath": rename.newpath,
+            }),
+            Self::ReadLink(readlink) => json!({ "id": readlink.id, "path": readlink.path }),
+            Self::Symlink(symlink) => json!({
+                "id": symlink.id,
+                "linkpath": symlink.linkpath,
+                "targetpath": symlink.targetpath,
+            }),
+            Self::Status(status) => json!({
+                "id": status.id,
+                "status_code": format!("{:?}", status.status_code),
+                "code": status.status_code as u32,
+                "error_message": status.error_message,
+                "language_tag": status.language_tag,
+            }),
+            Self::Handle(handle) => json!({ "id": handle.id, "handle": handle.handle }),
+            Self::Data(data) => json!({ "id": data.id, "data": hex(&data.data) }),
+            Self::Name(name) => json!({
+                "id": name.id,
+                "files": name.files.iter().map(file).collect::<Vec<_>>(),
+            }),
+            Self::Attrs(reply) => json!({ "id": reply.id, "attrs": attrs(&reply.attrs) }),
+            Self::Extended(extended) => json!({
+                "id": extended.id,
+                "request": extended.request,
+                "data": hex(&extended.data),
+            }),
+            Self::ExtendedReply(reply) => json!({ "id": reply.id, "data": hex(&reply.data) }),
+        };
+
+        let mut value = Map::from_iter([("type".to_owned(), Value::from(self.name()))]);
+        if let Value::Object(fields) = fields {
+            value.extend(fields);
+        }
+
+        Value::Object(value)
+    }
+}
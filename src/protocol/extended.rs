@@ -4,7 +4,7 @@ use crate::{de::data_deserialize, ser::data_serialize};
 /// Implementation for `SSH_FXP_EXTENDED`
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Extended {
-    pub id: u32,
+    pub id: RequestId,
     pub request: String,
     #[serde(serialize_with = "data_serialize")]
     #[serde(deserialize_with = "data_deserialize")]
@@ -17,7 +17,7 @@ impl_packet_for!(Extended);
 /// Implementation for `SSH_FXP_EXTENDED_REPLY`
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ExtendedReply {
-    pub id: u32,
+    pub id: RequestId,
     #[serde(serialize_with = "data_serialize")]
     #[serde(deserialize_with = "data_deserialize")]
     pub data: Vec<u8>,
@@ -1,7 +1,6 @@
 use chrono::{DateTime, Utc};
-use std::time::{Duration, UNIX_EPOCH};
 
-use super::FileAttributes;
+use super::{FileAttributes, FileTime};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct File {
@@ -37,9 +36,12 @@ impl File {
         let permissions = self.attrs.permissions().to_string();
 
         let size = self.attrs.size.unwrap_or(0);
-        let mtime = self.attrs.mtime.unwrap_or(0);
+        let mtime = self.attrs.mtime.map_or(0, FileTime::secs);
 
-        let datetime = DateTime::<Utc>::from(UNIX_EPOCH + Duration::from_secs(mtime as u64));
+        let datetime = i64::try_from(mtime)
+            .ok()
+            .and_then(|secs| DateTime::<Utc>::from_timestamp(secs, 0))
+            .unwrap_or_default();
         let delayed = datetime.format("%b %d %Y %H:%M");
 
         format!(
@@ -1,17 +1,21 @@
 use serde::{de::Visitor, ser::SerializeStruct, Deserialize, Deserializer, Serialize};
-#[cfg(unix)]
+#[cfg(feature = "std-fs")]
+use std::fs::Metadata;
+#[cfg(all(unix, feature = "std-fs"))]
 use std::os::unix::fs::MetadataExt;
-use std::{
-    fmt,
-    fs::Metadata,
-    io::ErrorKind,
-    time::{Duration, SystemTime, UNIX_EPOCH},
+#[cfg(feature = "std-fs")]
+use std::time::UNIX_EPOCH;
+use std::{fmt, io::ErrorKind, time::SystemTime};
+
+use super::{AclEntries, FileTime};
+use crate::{
+    de::bytes_deserialize,
+    extensions,
+    ser::{self, bytes_serialize},
 };
 
-use crate::utils;
-
 /// Attributes flags according to the specification
-#[derive(Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct FileAttr(u32);
 
 /// Type according to mode unix
@@ -55,6 +59,13 @@ bitflags! {
     }
 }
 
+impl FileAttr {
+    /// Returns the bits not defined by version 3
+    pub fn unknown_bits(&self) -> u32 {
+        self.bits() & !Self::all().bits()
+    }
+}
+
 /// Represents a simplified version of the [`FileMode`]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FileType {
@@ -182,8 +193,18 @@ impl From<u32> for FilePermissions {
     }
 }
 
+/// Vendor-specific attribute sent after the standard ones, such as an
+/// access control list. The data is kept as received
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExtendedAttr {
+    pub name: String,
+    #[serde(serialize_with = "bytes_serialize")]
+    #[serde(deserialize_with = "bytes_deserialize")]
+    pub data: Vec<u8>,
+}
+
 /// Used in the implementation of other packets.
-/// Implements most [`Metadata`] methods
+/// Implements most [`Metadata`](std::fs::Metadata) methods
 ///
 /// The fields `user` and `group` are string names of users and groups for
 /// clients that can be displayed in longname. Can be omitted.
@@ -197,8 +218,14 @@ pub struct FileAttributes {
     pub gid: Option<u32>,
     pub group: Option<String>,
     pub permissions: Option<u32>,
-    pub atime: Option<u32>,
-    pub mtime: Option<u32>,
+    /// Kept with nanoseconds and after 2106, sent as 32-bit seconds by version 3
+    pub atime: Option<FileTime>,
+    pub mtime: Option<FileTime>,
+    /// Extended pairs in the order received, serialized back unchanged
+    pub extended: Vec<ExtendedAttr>,
+    /// Bits of the received flags not defined by version 3, never sent. The fields
+    /// they announce are not decoded, see [`Config::strict_flags`](crate::server::Config::strict_flags)
+    pub unknown_flags: u32,
 }
 
 macro_rules! impl_fn_type {
@@ -263,20 +290,155 @@ impl FileAttributes {
         FilePermissionFlags::from_bits_truncate(self.permissions.unwrap_or_default()).into()
     }
 
+    /// Sets the owner and the group of the file.
+    ///
+    /// The protocol always sends them together, so if only one of them is
+    /// set the other is sent as `0`, which may change it to root. The high-level
+    /// [`set_metadata`](crate::client::SftpSession::set_metadata) fills in the
+    /// missing one with the current value
+    pub fn set_owner(&mut self, uid: Option<u32>, gid: Option<u32>) {
+        self.uid = uid;
+        self.gid = gid;
+    }
+
+    /// Returns `true` if only one of the fields sent together is set:
+    /// `uid` and `gid` or `atime` and `mtime`
+    pub(crate) fn is_half_set(&self) -> bool {
+        self.uid.is_some() != self.gid.is_some() || self.atime.is_some() != self.mtime.is_some()
+    }
+
+    /// Fills in the missing halves of `uid`/`gid` and `atime`/`mtime` from `current`
+    #[cfg(feature = "client")]
+    pub(crate) fn merge_halves(&mut self, current: &FileAttributes) {
+        if self.uid.is_some() != self.gid.is_some() {
+            self.uid = self.uid.or(current.uid);
+            self.gid = self.gid.or(current.gid);
+        }
+
+        if self.atime.is_some() != self.mtime.is_some() {
+            self.atime = self.atime.or(current.atime);
+            self.mtime = self.mtime.or(current.mtime);
+        }
+    }
+
+    /// Returns `true` if the file is read-only. See [`FilePermissions::is_readonly`]
+    pub fn is_readonly(&self) -> bool {
+        self.permissions().is_readonly()
+    }
+
     /// Returns the last access time
     pub fn accessed(&self) -> std::io::Result<SystemTime> {
-        match self.atime {
-            Some(time) => Ok(UNIX_EPOCH + Duration::from_secs(time as u64)),
-            None => Err(ErrorKind::InvalidData.into()),
-        }
+        self.atime
+            .and_then(FileTime::to_system_time)
+            .ok_or_else(|| ErrorKind::InvalidData.into())
     }
 
     /// Returns the last modification time
     pub fn modified(&self) -> std::io::Result<SystemTime> {
-        match self.mtime {
-            Some(time) => Ok(UNIX_EPOCH + Duration::from_secs(time as u64)),
-            None => Err(ErrorKind::InvalidData.into()),
+        self.mtime
+            .and_then(FileTime::to_system_time)
+            .ok_or_else(|| ErrorKind::InvalidData.into())
+    }
+
+    /// Returns the access time in seconds as sent by version 3
+    #[deprecated(
+        note = "use the `atime` field, which keeps the nanoseconds and the times after 2106"
+    )]
+    pub fn atime_u32(&self) -> Option<u32> {
+        self.atime.map(FileTime::saturating_v3)
+    }
+
+    /// Returns the modification time in seconds as sent by version 3
+    #[deprecated(
+        note = "use the `mtime` field, which keeps the nanoseconds and the times after 2106"
+    )]
+    pub fn mtime_u32(&self) -> Option<u32> {
+        self.mtime.map(FileTime::saturating_v3)
+    }
+
+    /// Sets the access time in seconds
+    #[deprecated(note = "set the `atime` field")]
+    pub fn set_atime_u32(&mut self, atime: Option<u32>) {
+        self.atime = atime.map(FileTime::from);
+    }
+
+    /// Sets the modification time in seconds
+    #[deprecated(note = "set the `mtime` field")]
+    pub fn set_mtime_u32(&mut self, mtime: Option<u32>) {
+        self.mtime = mtime.map(FileTime::from);
+    }
+
+    /// Returns the first of `atime` and `mtime` after 2106, which version 3
+    /// cannot send and saturates
+    pub fn time_beyond_v3(&self) -> Option<FileTime> {
+        [self.atime, self.mtime]
+            .into_iter()
+            .flatten()
+            .find(|time| time.to_v3().is_none())
+    }
+
+    /// Creates a structure with only the given times, the others are omitted.
+    /// Fails with [`ErrorKind::InvalidInput`] for times before 1970
+    pub fn with_times(
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    ) -> std::io::Result<Self> {
+        let unix = |time: SystemTime| {
+            FileTime::try_from(time).map_err(|_| {
+                std::io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("{time:?} is before the Unix epoch"),
+                )
+            })
+        };
+
+        Ok(Self {
+            atime: accessed.map(unix).transpose()?,
+            mtime: modified.map(unix).transpose()?,
+            ..Self::empty()
+        })
+    }
+
+    /// Returns the flags sent with the attributes
+    pub fn flags(&self) -> FileAttr {
+        let mut attrs = FileAttr::default();
+
+        if self.size.is_some() {
+            attrs |= FileAttr::SIZE;
         }
+
+        if self.uid.is_some() || self.gid.is_some() {
+            attrs |= FileAttr::UIDGID;
+        }
+
+        if self.permissions.is_some() {
+            attrs |= FileAttr::PERMISSIONS;
+        }
+
+        if self.atime.is_some() || self.mtime.is_some() {
+            attrs |= FileAttr::ACMODTIME;
+        }
+
+        if !self.extended.is_empty() {
+            attrs |= FileAttr::EXTENDED;
+        }
+
+        attrs
+    }
+
+    /// Returns the data of the extended pair `name` if present
+    pub fn extended_attr(&self, name: &str) -> Option<&[u8]> {
+        self.extended
+            .iter()
+            .find(|attr| attr.name == name)
+            .map(|attr| attr.data.as_slice())
+    }
+
+    /// Returns the access control list of the [`extensions::ACL`] pair.
+    /// [`None`] if the pair is missing or in an unknown format, whose data
+    /// is still available from [`FileAttributes::extended_attr`]
+    pub fn acl(&self) -> Option<AclEntries> {
+        AclEntries::parse(self.extended_attr(extensions::ACL)?)
     }
 
     /// Creates a structure with omitted attributes
@@ -290,6 +452,8 @@ impl FileAttributes {
             permissions: None,
             atime: None,
             mtime: None,
+            extended: Vec::new(),
+            unknown_flags: 0,
         }
     }
 }
@@ -304,13 +468,16 @@ impl Default for FileAttributes {
             gid: Some(0),
             group: None,
             permissions: Some(0o777 | FileMode::DIR.bits()),
-            atime: Some(0),
-            mtime: Some(0),
+            atime: Some(FileTime::default()),
+            mtime: Some(FileTime::default()),
+            extended: Vec::new(),
+            unknown_flags: 0,
         }
     }
 }
 
 /// For simple conversion of [`Metadata`] into [`FileAttributes`]
+#[cfg(feature = "std-fs")]
 impl From<&Metadata> for FileAttributes {
     fn from(metadata: &Metadata) -> Self {
         let mut attrs = Self {
@@ -327,44 +494,51 @@ impl From<&Metadata> for FileAttributes {
             }),
             #[cfg(unix)]
             permissions: Some(metadata.mode()),
-            atime: Some(utils::unix(metadata.modified().unwrap_or(UNIX_EPOCH))),
-            mtime: Some(utils::unix(metadata.accessed().unwrap_or(UNIX_EPOCH))),
+            atime: Some(file_time(metadata.accessed())),
+            mtime: Some(file_time(metadata.modified())),
             ..Default::default()
         };
 
-        attrs.set_dir(metadata.is_dir());
-        attrs.set_regular(!metadata.is_dir());
+        if metadata.is_symlink() {
+            attrs.set_symlink(true);
+        } else {
+            attrs.set_dir(metadata.is_dir());
+            attrs.set_regular(!metadata.is_dir());
+        }
 
         attrs
     }
 }
 
+/// Time of [`Metadata`], the epoch if unavailable or before it
+#[cfg(feature = "std-fs")]
+fn file_time(time: std::io::Result<SystemTime>) -> FileTime {
+    FileTime::try_from(time.unwrap_or(UNIX_EPOCH)).unwrap_or_default()
+}
+
 impl Serialize for FileAttributes {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        let mut attrs = FileAttr::default();
-        let mut field_count = 1;
-
-        if self.size.is_some() {
-            attrs |= FileAttr::SIZE;
-            field_count += 1;
-        }
-
-        if self.uid.is_some() || self.gid.is_some() {
-            attrs |= FileAttr::UIDGID;
-            field_count += 2;
+        let attrs = self.flags();
+        let field_count = [
+            (FileAttr::SIZE, 1),
+            (FileAttr::UIDGID, 2),
+            (FileAttr::PERMISSIONS, 1),
+            (FileAttr::ACMODTIME, 2),
+            (FileAttr::EXTENDED, 1 + self.extended.len()),
+        ]
+        .iter()
+        .filter(|(flag, _)| attrs.contains(*flag))
+        .fold(1, |count, (_, fields)| count + fields);
+
+        if self.is_half_set() {
+            debug!("only one of uid/gid or atime/mtime is set, the other one is sent as 0");
         }
 
-        if self.permissions.is_some() {
-            attrs |= FileAttr::PERMISSIONS;
-            field_count += 1;
-        }
-
-        if self.atime.is_some() || self.mtime.is_some() {
-            attrs |= FileAttr::ACMODTIME;
-            field_count += 2;
+        if let Some(time) = self.time_beyond_v3() {
+            debug!("{time:?} does not fit into the 32-bit time of the protocol, saturated");
         }
 
         let mut s = serializer.serialize_struct("FileAttributes", field_count)?;
@@ -384,11 +558,17 @@ impl Serialize for FileAttributes {
         }
 
         if self.atime.is_some() || self.mtime.is_some() {
-            s.serialize_field("atime", &self.atime.unwrap_or(0))?;
-            s.serialize_field("mtime", &self.mtime.unwrap_or(0))?;
+            s.serialize_field("atime", &self.atime.map_or(0, FileTime::saturating_v3))?;
+            s.serialize_field("mtime", &self.mtime.map_or(0, FileTime::saturating_v3))?;
         }
 
-        // todo: extended implementation
+        if !self.extended.is_empty() {
+            let count = ser::wire_len(self.extended.len()).map_err(serde::ser::Error::custom)?;
+            s.serialize_field("extended_count", &count)?;
+            for attr in &self.extended {
+                s.serialize_field("extended", attr)?;
+            }
+        }
 
         s.end()
     }
@@ -412,9 +592,10 @@ impl<'de> Deserialize<'de> for FileAttributes {
             where
                 A: serde::de::SeqAccess<'de>,
             {
-                let attrs = FileAttr::from_bits_truncate(seq.next_element::<u32>()?.unwrap_or(0));
+                let bits = FileAttr::from_bits_retain(seq.next_element::<u32>()?.unwrap_or(0));
+                let attrs = FileAttr::from_bits_truncate(bits.bits());
 
-                Ok(FileAttributes {
+                let mut file_attrs = FileAttributes {
                     size: if attrs.contains(FileAttr::SIZE) {
                         seq.next_element::<u64>()?
                     } else {
@@ -438,16 +619,29 @@ impl<'de> Deserialize<'de> for FileAttributes {
                         None
                     },
                     atime: if attrs.contains(FileAttr::ACMODTIME) {
-                        seq.next_element::<u32>()?
+                        seq.next_element::<u32>()?.map(FileTime::from)
                     } else {
                         None
                     },
                     mtime: if attrs.contains(FileAttr::ACMODTIME) {
-                        seq.next_element::<u32>()?
+                        seq.next_element::<u32>()?.map(FileTime::from)
                     } else {
                         None
                     },
-                })
+                    extended: Vec::new(),
+                    unknown_flags: bits.unknown_bits(),
+                };
+
+                if attrs.contains(FileAttr::EXTENDED) {
+                    let count = seq.next_element::<u32>()?.unwrap_or(0);
+                    for _ in 0..count {
+                        file_attrs
+                            .extended
+                            .extend(seq.next_element::<ExtendedAttr>()?);
+                    }
+                }
+
+                Ok(file_attrs)
             }
         }
 
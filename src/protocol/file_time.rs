@@ -0,0 +1,69 @@
+use std::time::{Duration, SystemTime, SystemTimeError, UNIX_EPOCH};
+
+const NANOS_PER_SEC: u32 = 1_000_000_000;
+
+/// Time of a file in seconds and nanoseconds since the Unix epoch.
+///
+/// Version 3 of the protocol only carries whole seconds in 32 bits, so the
+/// nanoseconds are dropped when the time is sent and the times after 2106
+/// are saturated, see [`FileTime::to_v3`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FileTime {
+    secs: u64,
+    nanos: u32,
+}
+
+impl FileTime {
+    /// Creates a time, carrying the nanoseconds over a second into the seconds
+    pub const fn new(secs: u64, nanos: u32) -> Self {
+        Self {
+            secs: secs.saturating_add((nanos / NANOS_PER_SEC) as u64),
+            nanos: nanos % NANOS_PER_SEC,
+        }
+    }
+
+    pub const fn from_secs(secs: u64) -> Self {
+        Self { secs, nanos: 0 }
+    }
+
+    /// Whole seconds since the Unix epoch
+    pub const fn secs(self) -> u64 {
+        self.secs
+    }
+
+    /// Nanoseconds within the second, always zero for the times received
+    pub const fn subsec_nanos(self) -> u32 {
+        self.nanos
+    }
+
+    /// Returns the seconds as sent by version 3, [`None`] after 2106
+    pub fn to_v3(self) -> Option<u32> {
+        u32::try_from(self.secs).ok()
+    }
+
+    /// Returns the seconds as sent by version 3, `u32::MAX` after 2106
+    pub fn saturating_v3(self) -> u32 {
+        self.to_v3().unwrap_or(u32::MAX)
+    }
+
+    /// Returns the time as [`SystemTime`], [`None`] if it does not fit
+    pub fn to_system_time(self) -> Option<SystemTime> {
+        UNIX_EPOCH.checked_add(Duration::new(self.secs, self.nanos))
+    }
+}
+
+impl From<u32> for FileTime {
+    fn from(secs: u32) -> Self {
+        Self::from_secs(secs.into())
+    }
+}
+
+/// Fails for times before the Unix epoch
+impl TryFrom<SystemTime> for FileTime {
+    type Error = SystemTimeError;
+
+    fn try_from(time: SystemTime) -> Result<Self, Self::Error> {
+        let since = time.duration_since(UNIX_EPOCH)?;
+        Ok(Self::new(since.as_secs(), since.subsec_nanos()))
+    }
+}
@@ -3,7 +3,7 @@ use super::{impl_packet_for, impl_request_id, FileAttributes, Packet, RequestId}
 /// Implementation for `SSH_FXP_FSETSTAT`
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FSetStat {
-    pub id: u32,
+    pub id: RequestId,
     pub handle: String,
     pub attrs: FileAttributes,
 }
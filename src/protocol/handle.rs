@@ -1,11 +1,57 @@
 use super::{impl_packet_for, impl_request_id, Packet, RequestId};
+use crate::error::Error;
+
+/// Maximum length of a handle in bytes per the specification
+pub const MAX_HANDLE_LEN: usize = 256;
+
+/// Length of the handles of [`Handle::encode_u64`]
+const U64_HANDLE_LEN: usize = 16;
 
 /// Implementation for `SSH_FXP_HANDLE`
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Handle {
-    pub id: u32,
+    pub id: RequestId,
     pub handle: String,
 }
 
 impl_request_id!(Handle);
 impl_packet_for!(Handle);
+
+impl Handle {
+    /// Creates a handle, failing if it is longer than [`MAX_HANDLE_LEN`] bytes
+    pub fn new_checked<I, H>(id: I, handle: H) -> Result<Self, Error>
+    where
+        I: Into<RequestId>,
+        H: Into<String>,
+    {
+        let handle = handle.into();
+        if handle.len() > MAX_HANDLE_LEN {
+            return Err(Error::BadMessage(format!(
+                "handle of {} bytes is longer than {MAX_HANDLE_LEN}",
+                handle.len()
+            )));
+        }
+
+        Ok(Self {
+            id: id.into(),
+            handle,
+        })
+    }
+
+    /// Encodes a number such as the key of an open file as a handle of
+    /// 16 lowercase hex digits, so that every handle has the same length
+    pub fn encode_u64(value: u64) -> String {
+        format!("{value:016x}")
+    }
+
+    /// Decodes a handle of [`Handle::encode_u64`], [`None`] for any other string
+    pub fn decode_u64(handle: &str) -> Option<u64> {
+        let digits = handle
+            .bytes()
+            .all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'));
+        match handle.len() == U64_HANDLE_LEN && digits {
+            true => u64::from_str_radix(handle, 16).ok(),
+            false => None,
+        }
+    }
+}
@@ -3,7 +3,7 @@ use super::{impl_packet_for, impl_request_id, FileAttributes, Packet, RequestId}
 /// Implementation for `SSH_FXP_MKDIR`
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MkDir {
-    pub id: u32,
+    pub id: RequestId,
     pub path: String,
     pub attrs: FileAttributes,
 }
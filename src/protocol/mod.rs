@@ -1,9 +1,15 @@
+mod acl;
+#[cfg(feature = "std-fs")]
+mod apply;
 mod attrs;
 mod close;
 mod data;
+#[cfg(feature = "diagnostics")]
+mod diagnostic;
 mod extended;
 mod file;
 mod file_attrs;
+mod file_time;
 mod fsetstat;
 mod fstat;
 mod handle;
@@ -13,13 +19,16 @@ mod mkdir;
 mod name;
 mod open;
 mod opendir;
+mod preserved;
 mod read;
 mod readdir;
 mod readlink;
 mod realpath;
 mod remove;
 mod rename;
+mod request_id;
 mod rmdir;
+mod serialized_len;
 mod setstat;
 mod stat;
 mod status;
@@ -31,28 +40,34 @@ use bytes::{BufMut, Bytes, BytesMut};
 
 use crate::{buf::TryBuf, de, error::Error, ser};
 
+#[cfg(feature = "std-fs")]
+pub use self::apply::{ApplyAttrsError, Attribute};
 pub use self::{
+    acl::{AclEntries, AclEntry},
     attrs::Attrs,
     close::Close,
     data::Data,
     extended::{Extended, ExtendedReply},
     file::File,
-    file_attrs::{FileAttr, FileAttributes, FileMode, FileType},
+    file_attrs::{ExtendedAttr, FileAttr, FileAttributes, FileMode, FileType},
+    file_time::FileTime,
     fsetstat::FSetStat,
     fstat::Fstat,
-    handle::Handle,
+    handle::{Handle, MAX_HANDLE_LEN},
     init::Init,
     lstat::Lstat,
     mkdir::MkDir,
-    name::Name,
+    name::{Full, Name, NameBatcher},
     open::{Open, OpenFlags},
     opendir::OpenDir,
+    preserved::PreservedPacket,
     read::Read,
     readdir::ReadDir,
     readlink::ReadLink,
     realpath::RealPath,
     remove::Remove,
     rename::Rename,
+    request_id::RequestId,
     rmdir::RmDir,
     setstat::SetStat,
     stat::Stat,
@@ -63,6 +78,9 @@ pub use self::{
 };
 
 pub const VERSION: u32 = 3;
+/// Lowest version spoken by this crate, the packets of the earlier versions
+/// are a subset of version 3 with the same formats
+pub const MIN_VERSION: u32 = 1;
 
 const SSH_FXP_INIT: u8 = 1;
 const SSH_FXP_VERSION: u8 = 2;
@@ -84,24 +102,26 @@ const SSH_FXP_STAT: u8 = 17;
 const SSH_FXP_RENAME: u8 = 18;
 const SSH_FXP_READLINK: u8 = 19;
 const SSH_FXP_SYMLINK: u8 = 20;
+/// Last request type, defined by version 6
+const SSH_FXP_UNBLOCK: u8 = 23;
 
 const SSH_FXP_STATUS: u8 = 101;
 const SSH_FXP_HANDLE: u8 = 102;
-const SSH_FXP_DATA: u8 = 103;
+pub(crate) const SSH_FXP_DATA: u8 = 103;
 const SSH_FXP_NAME: u8 = 104;
 const SSH_FXP_ATTRS: u8 = 105;
 
 const SSH_FXP_EXTENDED: u8 = 200;
 const SSH_FXP_EXTENDED_REPLY: u8 = 201;
 
-pub(crate) trait RequestId: Sized {
-    fn get_request_id(&self) -> u32;
+pub(crate) trait GetRequestId: Sized {
+    fn get_request_id(&self) -> RequestId;
 }
 
 macro_rules! impl_request_id {
     ($packet:ty) => {
-        impl RequestId for $packet {
-            fn get_request_id(&self) -> u32 {
+        impl $crate::protocol::GetRequestId for $packet {
+            fn get_request_id(&self) -> $crate::protocol::RequestId {
                 self.id
             }
         }
@@ -153,7 +173,75 @@ pub enum Packet {
 }
 
 impl Packet {
-    pub fn get_request_id(&self) -> u32 {
+    /// Returns the name of the packet type as in the specification, e.g. `SSH_FXP_OPEN`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Init(_) => "SSH_FXP_INIT",
+            Self::Version(_) => "SSH_FXP_VERSION",
+            Self::Open(_) => "SSH_FXP_OPEN",
+            Self::Close(_) => "SSH_FXP_CLOSE",
+            Self::Read(_) => "SSH_FXP_READ",
+            Self::Write(_) => "SSH_FXP_WRITE",
+            Self::Lstat(_) => "SSH_FXP_LSTAT",
+            Self::Fstat(_) => "SSH_FXP_FSTAT",
+            Self::SetStat(_) => "SSH_FXP_SETSTAT",
+            Self::FSetStat(_) => "SSH_FXP_FSETSTAT",
+            Self::OpenDir(_) => "SSH_FXP_OPENDIR",
+            Self::ReadDir(_) => "SSH_FXP_READDIR",
+            Self::Remove(_) => "SSH_FXP_REMOVE",
+            Self::MkDir(_) => "SSH_FXP_MKDIR",
+            Self::RmDir(_) => "SSH_FXP_RMDIR",
+            Self::RealPath(_) => "SSH_FXP_REALPATH",
+            Self::Stat(_) => "SSH_FXP_STAT",
+            Self::Rename(_) => "SSH_FXP_RENAME",
+            Self::ReadLink(_) => "SSH_FXP_READLINK",
+            Self::Symlink(_) => "SSH_FXP_SYMLINK",
+            Self::Status(_) => "SSH_FXP_STATUS",
+            Self::Handle(_) => "SSH_FXP_HANDLE",
+            Self::Data(_) => "SSH_FXP_DATA",
+            Self::Name(_) => "SSH_FXP_NAME",
+            Self::Attrs(_) => "SSH_FXP_ATTRS",
+            Self::Extended(_) => "SSH_FXP_EXTENDED",
+            Self::ExtendedReply(_) => "SSH_FXP_EXTENDED_REPLY",
+        }
+    }
+
+    /// Returns the type and the request id of an encoded packet without the length,
+    /// for example to forward packets without decoding them. The id is [`None`]
+    /// for SSH_FXP_INIT and SSH_FXP_VERSION or if the packet is too short
+    pub fn peek(payload: &[u8]) -> Option<(u8, Option<u32>)> {
+        let (&r#type, rest) = payload.split_first()?;
+        let id = match r#type {
+            SSH_FXP_INIT | SSH_FXP_VERSION => None,
+            _ => rest.first_chunk().copied().map(u32::from_be_bytes),
+        };
+
+        Some((r#type, id))
+    }
+
+    /// Returns `true` if the type is defined by a version of the protocol,
+    /// including the packets of the versions after 3 such as SSH_FXP_LINK
+    pub fn is_defined_type(r#type: u8) -> bool {
+        matches!(
+            r#type,
+            SSH_FXP_INIT..=SSH_FXP_UNBLOCK
+                | SSH_FXP_STATUS..=SSH_FXP_ATTRS
+                | SSH_FXP_EXTENDED
+                | SSH_FXP_EXTENDED_REPLY
+        )
+    }
+
+    /// Prepends the length to an encoded packet, the reverse of
+    /// [`read_packet`](crate::framing::read_packet). The payload is expected to
+    /// have been read from a frame, thus not to exceed [`u32::MAX`] bytes
+    pub fn frame(payload: &[u8]) -> Bytes {
+        let mut bytes = BytesMut::with_capacity(payload.len() + 4);
+        bytes.put_u32(payload.len() as u32);
+        bytes.put_slice(payload);
+        bytes.freeze()
+    }
+
+    pub fn get_request_id(&self) -> RequestId {
         match self {
             Self::Open(open) => open.get_request_id(),
             Self::Close(close) => close.get_request_id(),
@@ -174,21 +262,26 @@ impl Packet {
             Self::ReadLink(readlink) => readlink.get_request_id(),
             Self::Symlink(symlink) => symlink.get_request_id(),
             Self::Extended(extended) => extended.get_request_id(),
-            _ => 0,
+            _ => RequestId::default(),
         }
     }
 
-    pub fn status(id: u32, status_code: StatusCode, msg: &str, tag: &str) -> Self {
+    pub fn status<I: Into<RequestId>>(
+        id: I,
+        status_code: StatusCode,
+        msg: &str,
+        tag: &str,
+    ) -> Self {
         Packet::Status(Status {
-            id,
+            id: id.into(),
             status_code,
             error_message: msg.to_string(),
             language_tag: tag.to_string(),
         })
     }
 
-    pub fn error(id: u32, status_code: StatusCode) -> Self {
-        Self::status(id, status_code, &status_code.to_string(), "en-US")
+    pub fn error<I: Into<RequestId>>(id: I, status_code: StatusCode) -> Self {
+        Packet::Status(Status::new(id, status_code))
     }
 }
 
@@ -220,7 +313,7 @@ impl TryFrom<&mut Bytes> for Packet {
             SSH_FXP_RENAME => Self::Rename(de::from_bytes(bytes)?),
             SSH_FXP_READLINK => Self::ReadLink(de::from_bytes(bytes)?),
             SSH_FXP_SYMLINK => Self::Symlink(de::from_bytes(bytes)?),
-            SSH_FXP_STATUS => Self::Status(de::from_bytes(bytes)?),
+            SSH_FXP_STATUS => Self::Status(Status::from_bytes(bytes)?),
             SSH_FXP_HANDLE => Self::Handle(de::from_bytes(bytes)?),
             SSH_FXP_DATA => Self::Data(de::from_bytes(bytes)?),
             SSH_FXP_NAME => Self::Name(de::from_bytes(bytes)?),
@@ -234,6 +327,64 @@ impl TryFrom<&mut Bytes> for Packet {
     }
 }
 
+impl Packet {
+    /// Returns the path or the handle the request operates on
+    #[cfg(any(feature = "client", feature = "server"))]
+    pub(crate) fn target(&self) -> Option<&str> {
+        let target = match self {
+            Self::Open(open) => &open.filename,
+            Self::Close(close) => &close.handle,
+            Self::Read(read) => &read.handle,
+            Self::Write(write) => &write.handle,
+            Self::Lstat(lstat) => &lstat.path,
+            Self::Fstat(fstat) => &fstat.handle,
+            Self::SetStat(setstat) => &setstat.path,
+            Self::FSetStat(fsetstat) => &fsetstat.handle,
+            Self::OpenDir(opendir) => &opendir.path,
+            Self::ReadDir(readdir) => &readdir.handle,
+            Self::Remove(remove) => &remove.filename,
+            Self::MkDir(mkdir) => &mkdir.path,
+            Self::RmDir(rmdir) => &rmdir.path,
+            Self::RealPath(realpath) => &realpath.path,
+            Self::Stat(stat) => &stat.path,
+            Self::Rename(rename) => &rename.oldpath,
+            Self::ReadLink(readlink) => &readlink.path,
+            Self::Symlink(symlink) => &symlink.linkpath,
+            _ => return None,
+        };
+
+        Some(target)
+    }
+
+    /// Returns the handle the request operates on, [`None`] for requests on paths
+    #[cfg(any(feature = "client", feature = "server"))]
+    pub(crate) fn handle(&self) -> Option<&str> {
+        let handle = match self {
+            Self::Close(close) => &close.handle,
+            Self::Read(read) => &read.handle,
+            Self::Write(write) => &write.handle,
+            Self::Fstat(fstat) => &fstat.handle,
+            Self::FSetStat(fsetstat) => &fsetstat.handle,
+            Self::ReadDir(readdir) => &readdir.handle,
+            _ => return None,
+        };
+
+        Some(handle)
+    }
+
+    /// Same as [`Packet::try_from`], but tolerates SSH_FXP_NAME entries without `longname`
+    #[cfg(feature = "client")]
+    pub(crate) fn try_from_lenient(bytes: &mut Bytes) -> Result<Self, Error> {
+        match bytes.first() {
+            Some(&SSH_FXP_NAME) => {
+                bytes::Buf::advance(bytes, 1);
+                Ok(Self::Name(Name::deserialize_lenient(bytes)?))
+            }
+            _ => Self::try_from(bytes),
+        }
+    }
+}
+
 impl TryFrom<Packet> for Bytes {
     type Error = Error;
 
@@ -268,7 +419,7 @@ impl TryFrom<Packet> for Bytes {
             Packet::ExtendedReply(reply) => (SSH_FXP_EXTENDED_REPLY, ser::to_bytes(&reply)?),
         };
 
-        let length = payload.len() as u32 + 1;
+        let length = ser::wire_len(payload.len() + 1)?;
         let mut bytes = BytesMut::new();
         bytes.put_u32(length);
         bytes.put_u8(r#type);
@@ -1,13 +1,127 @@
+#[cfg(feature = "client")]
+use bytes::{Buf, Bytes};
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "client")]
+use super::FileAttributes;
 use super::{impl_packet_for, impl_request_id, File, Packet, RequestId};
+#[cfg(feature = "client")]
+use crate::{buf::TryBuf, de, error::Error};
 
 /// Implementation for `SSH_FXP_NAME`
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Name {
-    pub id: u32,
+    pub id: RequestId,
     pub files: Vec<File>,
 }
 
+#[cfg(feature = "client")]
+impl Name {
+    /// Same as the regular deserialization, but also accepts entries without
+    /// `longname` as sent by some servers written against version 4 and later.
+    /// The `longname` of such entries is left empty, [`File::longname`] forms one
+    pub(crate) fn deserialize_lenient(bytes: &mut Bytes) -> Result<Self, Error> {
+        let mut strict = bytes.clone();
+        let err = match de::from_bytes::<Name>(&mut strict) {
+            Ok(name) if !strict.has_remaining() => {
+                *bytes = strict;
+                return Ok(name);
+            }
+            Ok(_) => Error::BadMessage("trailing bytes in name".to_owned()),
+            Err(err) => err,
+        };
+
+        let name = Self::deserialize_without_longname(bytes).map_err(|_| err)?;
+        debug!("name {} parsed without longname", name.id);
+
+        Ok(name)
+    }
+
+    fn deserialize_without_longname(bytes: &mut Bytes) -> Result<Self, Error> {
+        let id = TryBuf::try_get_u32(bytes)?;
+        let count = TryBuf::try_get_u32(bytes)?;
+        let mut files = Vec::new();
+
+        for _ in 0..count {
+            let filename = bytes.try_get_string()?;
+            let attrs = de::from_bytes::<FileAttributes>(bytes)?;
+
+            files.push(File {
+                filename,
+                longname: String::new(),
+                attrs,
+            });
+        }
+
+        if bytes.has_remaining() {
+            return Err(Error::BadMessage("trailing bytes in name".to_owned()));
+        }
+
+        Ok(Self {
+            id: RequestId(id),
+            files,
+        })
+    }
+}
+
 impl_request_id!(Name);
 impl_packet_for!(Name);
+
+/// Type, request id and count of the entries of SSH_FXP_NAME
+const NAME_HEADER_LEN: usize = 1 + 4 + 4;
+
+/// Entry refused by [`NameBatcher::push`], handed back to go into the next batch
+#[derive(Debug, thiserror::Error)]
+#[error("entry does not fit into the batch")]
+pub struct Full(pub Box<File>);
+
+/// Collects the entries of SSH_FXP_NAME as long as the packet stays within
+/// `max_len` bytes, the length that frames it excluded, such as
+/// [`Config::max_response_packet_len`](crate::server::Config::max_response_packet_len)
+#[derive(Debug)]
+pub struct NameBatcher {
+    max_len: usize,
+    len: usize,
+    files: Vec<File>,
+}
+
+impl NameBatcher {
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            max_len,
+            len: NAME_HEADER_LEN,
+            files: Vec::new(),
+        }
+    }
+
+    /// Adds the entry if it fits. An entry that does not fit even into an empty
+    /// batch is refused as well, no packet within the length can carry it
+    pub fn push(&mut self, file: File) -> Result<(), Full> {
+        let len = file.serialized_len();
+        if self.len + len > self.max_len {
+            return Err(Full(Box::new(file)));
+        }
+
+        self.len += len;
+        self.files.push(file);
+        Ok(())
+    }
+
+    /// Returns the length of the packet of the entries collected so far
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Returns the reply with the entries collected and starts a new batch
+    pub fn take(&mut self, id: RequestId) -> Name {
+        self.len = NAME_HEADER_LEN;
+        Name {
+            id,
+            files: std::mem::take(&mut self.files),
+        }
+    }
+}
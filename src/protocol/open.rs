@@ -1,3 +1,4 @@
+#[cfg(feature = "std-fs")]
 use std::fs;
 
 use super::{impl_packet_for, impl_request_id, FileAttributes, Packet, RequestId};
@@ -17,6 +18,15 @@ bitflags! {
     }
 }
 
+impl OpenFlags {
+    /// Returns the bits not defined by version 3, such as the access
+    /// disposition of version 5, which are kept but not interpreted
+    pub fn unknown_bits(&self) -> u32 {
+        self.bits() & !Self::all().bits()
+    }
+}
+
+#[cfg(feature = "std-fs")]
 impl From<OpenFlags> for fs::OpenOptions {
     fn from(value: OpenFlags) -> Self {
         let mut open_options = fs::OpenOptions::new();
@@ -53,7 +63,7 @@ impl From<OpenFlags> for fs::OpenOptions {
 /// Implementation for `SSH_FXP_OPEN`
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Open {
-    pub id: u32,
+    pub id: RequestId,
     pub filename: String,
     pub pflags: OpenFlags,
     pub attrs: FileAttributes,
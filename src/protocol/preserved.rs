@@ -0,0 +1,79 @@
+use bytes::Bytes;
+
+use super::Packet;
+use crate::error::Error;
+
+/// Decoded packet that keeps the bytes it was decoded from, for proxies
+/// forwarding packets that they may not fully understand.
+///
+/// Decoding drops what the crate does not model, such as the fields appended by later
+/// versions or a status without a message, so a packet serialized again may differ.
+/// Unless the packet was accessed mutably, [`Bytes::try_from`] emits the original
+/// bytes and only the rewritten packets are encoded again
+#[derive(Debug)]
+pub struct PreservedPacket {
+    packet: Packet,
+    /// Encoded packet without the length, [`None`] once modified
+    original: Option<Bytes>,
+}
+
+impl PreservedPacket {
+    pub fn packet(&self) -> &Packet {
+        &self.packet
+    }
+
+    /// Returns the packet to rewrite, which is encoded again from then on
+    pub fn packet_mut(&mut self) -> &mut Packet {
+        self.original = None;
+        &mut self.packet
+    }
+
+    /// Encoded packet without the length, [`None`] if the packet was modified
+    pub fn original(&self) -> Option<&Bytes> {
+        self.original.as_ref()
+    }
+
+    /// Returns `true` if the packet will be encoded again
+    pub fn is_modified(&self) -> bool {
+        self.original.is_none()
+    }
+
+    /// Returns the packet and drops the original bytes
+    pub fn into_modified(self) -> Packet {
+        self.packet
+    }
+}
+
+/// Decodes an encoded packet without the length, as returned by
+/// [`read_packet`](crate::framing::read_packet), and keeps it in full
+impl TryFrom<Bytes> for PreservedPacket {
+    type Error = Error;
+
+    fn try_from(payload: Bytes) -> Result<Self, Self::Error> {
+        let packet = Packet::try_from(&mut payload.clone())?;
+        Ok(Self {
+            packet,
+            original: Some(payload),
+        })
+    }
+}
+
+impl From<Packet> for PreservedPacket {
+    fn from(packet: Packet) -> Self {
+        Self {
+            packet,
+            original: None,
+        }
+    }
+}
+
+impl TryFrom<PreservedPacket> for Bytes {
+    type Error = Error;
+
+    fn try_from(preserved: PreservedPacket) -> Result<Self, Self::Error> {
+        match preserved.original {
+            Some(payload) => Ok(Packet::frame(&payload)),
+            None => Bytes::try_from(preserved.packet),
+        }
+    }
+}
@@ -3,7 +3,7 @@ use super::{impl_packet_for, impl_request_id, Packet, RequestId};
 /// Implementation for `SSH_FXP_READLINK`
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ReadLink {
-    pub id: u32,
+    pub id: RequestId,
     pub path: String,
 }
 
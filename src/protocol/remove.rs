@@ -3,7 +3,7 @@ use super::{impl_packet_for, impl_request_id, Packet, RequestId};
 /// Implementation for `SSH_FXP_REMOVE`
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Remove {
-    pub id: u32,
+    pub id: RequestId,
     pub filename: String,
 }
 
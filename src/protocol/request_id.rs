@@ -0,0 +1,47 @@
+use std::fmt;
+
+/// Id of a request, echoed by its reply so that the client can route the reply
+/// to the request. A distinct type so that it is not mixed up with the other
+/// integers of the packets
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct RequestId(pub u32);
+
+impl RequestId {
+    /// Returns the id that follows, wrapping around
+    pub fn next(self) -> Self {
+        Self(self.0.wrapping_add(1))
+    }
+}
+
+impl From<u32> for RequestId {
+    fn from(id: u32) -> Self {
+        Self(id)
+    }
+}
+
+impl From<RequestId> for u32 {
+    fn from(id: RequestId) -> Self {
+        id.0
+    }
+}
+
+impl PartialEq<u32> for RequestId {
+    fn eq(&self, other: &u32) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialEq<RequestId> for u32 {
+    fn eq(&self, other: &RequestId) -> bool {
+        *self == other.0
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
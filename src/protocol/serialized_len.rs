@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+
+use super::{
+    Attrs, Close, Data, Extended, ExtendedReply, FSetStat, File, FileAttr, FileAttributes, Fstat,
+    Handle, Init, Lstat, MkDir, Name, Open, OpenDir, Packet, Read, ReadDir, ReadLink, RealPath,
+    Remove, Rename, RmDir, SetStat, Stat, Status, Symlink, Version, Write,
+};
+
+/// Length of a string or of bytes with their length prefix
+fn string_len(bytes: impl AsRef<[u8]>) -> usize {
+    4 + bytes.as_ref().len()
+}
+
+/// Length of the extension pairs of SSH_FXP_INIT and SSH_FXP_VERSION
+fn extensions_len(extensions: &HashMap<String, String>) -> usize {
+    extensions
+        .iter()
+        .map(|(name, value)| string_len(name) + string_len(value))
+        .sum()
+}
+
+macro_rules! impl_serialized_len {
+    ($packet:ty, |$this:ident| $len:expr) => {
+        impl $packet {
+            /// Returns the length of the serialized packet without the type
+            pub fn serialized_len(&self) -> usize {
+                let $this = self;
+                $len
+            }
+        }
+    };
+}
+
+impl_serialized_len!(Init, |init| 4 + extensions_len(&init.extensions));
+impl_serialized_len!(Version, |version| 4 + extensions_len(&version.extensions));
+impl_serialized_len!(Open, |open| 4
+    + string_len(&open.filename)
+    + 4
+    + open.attrs.serialized_len());
+impl_serialized_len!(Close, |close| 4 + string_len(&close.handle));
+impl_serialized_len!(Read, |read| 4 + string_len(&read.handle) + 8 + 4);
+impl_serialized_len!(Write, |write| 4
+    + string_len(&write.handle)
+    + 8
+    + string_len(&write.data));
+impl_serialized_len!(Lstat, |lstat| 4 + string_len(&lstat.path));
+impl_serialized_len!(Fstat, |fstat| 4 + string_len(&fstat.handle));
+impl_serialized_len!(SetStat, |setstat| 4
+    + string_len(&setstat.path)
+    + setstat.attrs.serialized_len());
+impl_serialized_len!(FSetStat, |fsetstat| 4
+    + string_len(&fsetstat.handle)
+    + fsetstat.attrs.serialized_len());
+impl_serialized_len!(OpenDir, |opendir| 4 + string_len(&opendir.path));
+impl_serialized_len!(ReadDir, |readdir| 4 + string_len(&readdir.handle));
+impl_serialized_len!(Remove, |remove| 4 + string_len(&remove.filename));
+impl_serialized_len!(MkDir, |mkdir| 4
+    + string_len(&mkdir.path)
+    + mkdir.attrs.serialized_len());
+impl_serialized_len!(RmDir, |rmdir| 4 + string_len(&rmdir.path));
+impl_serialized_len!(RealPath, |realpath| 4 + string_len(&realpath.path));
+impl_serialized_len!(Stat, |stat| 4 + string_len(&stat.path));
+impl_serialized_len!(Rename, |rename| 4
+    + string_len(&rename.oldpath)
+    + string_len(&rename.newpath));
+impl_serialized_len!(ReadLink, |readlink| 4 + string_len(&readlink.path));
+impl_serialized_len!(Symlink, |symlink| 4
+    + string_len(&symlink.linkpath)
+    + string_len(&symlink.targetpath));
+impl_serialized_len!(Status, |status| 4
+    + 4
+    + string_len(&status.error_message)
+    + string_len(&status.language_tag));
+impl_serialized_len!(Handle, |handle| 4 + string_len(&handle.handle));
+impl_serialized_len!(Data, |data| 4 + string_len(&data.data));
+impl_serialized_len!(Name, |name| 4
+    + 4
+    + name.files.iter().map(File::serialized_len).sum::<usize>());
+impl_serialized_len!(Attrs, |attrs| 4 + attrs.attrs.serialized_len());
+impl_serialized_len!(Extended, |extended| 4
+    + string_len(&extended.request)
+    + extended.data.len());
+impl_serialized_len!(ExtendedReply, |reply| 4 + reply.data.len());
+
+impl File {
+    /// Returns the length of the serialized entry of SSH_FXP_NAME
+    pub fn serialized_len(&self) -> usize {
+        string_len(&self.filename) + string_len(&self.longname) + self.attrs.serialized_len()
+    }
+}
+
+impl FileAttributes {
+    /// Returns the length of the serialized attributes, which depends on [`FileAttributes::flags`]
+    pub fn serialized_len(&self) -> usize {
+        let flags = self.flags();
+        let fields = [
+            (FileAttr::SIZE, 8),
+            (FileAttr::UIDGID, 8),
+            (FileAttr::PERMISSIONS, 4),
+            (FileAttr::ACMODTIME, 8),
+        ];
+
+        let mut len = 4 + fields
+            .iter()
+            .filter(|(flag, _)| flags.contains(*flag))
+            .map(|(_, len)| len)
+            .sum::<usize>();
+
+        if flags.contains(FileAttr::EXTENDED) {
+            len += 4 + self
+                .extended
+                .iter()
+                .map(|attr| string_len(&attr.name) + string_len(&attr.data))
+                .sum::<usize>();
+        }
+
+        len
+    }
+}
+
+impl Packet {
+    /// Returns the length of the serialized packet, the type included but not the
+    /// length that frames it, as bounded by the maximum packet lengths
+    pub fn serialized_len(&self) -> usize {
+        1 + match self {
+            Self::Init(init) => init.serialized_len(),
+            Self::Version(version) => version.serialized_len(),
+            Self::Open(open) => open.serialized_len(),
+            Self::Close(close) => close.serialized_len(),
+            Self::Read(read) => read.serialized_len(),
+            Self::Write(write) => write.serialized_len(),
+            Self::Lstat(lstat) => lstat.serialized_len(),
+            Self::Fstat(fstat) => fstat.serialized_len(),
+            Self::SetStat(setstat) => setstat.serialized_len(),
+            Self::FSetStat(fsetstat) => fsetstat.serialized_len(),
+            Self::OpenDir(opendir) => opendir.serialized_len(),
+            Self::ReadDir(readdir) => readdir.serialized_len(),
+            Self::Remove(remove) => remove.serialized_len(),
+            Self::MkDir(mkdir) => mkdir.serialized_len(),
+            Self::RmDir(rmdir) => rmdir.serialized_len(),
+            Self::RealPath(realpath) => realpath.serialized_len(),
+            Self::Stat(stat) => stat.serialized_len(),
+            Self::Rename(rename) => rename.serialized_len(),
+            Self::ReadLink(readlink) => readlink.serialized_len(),
+            Self::Symlink(symlink) => symlink.serialized_len(),
+            Self::Status(status) => status.serialized_len(),
+            Self::Handle(handle) => handle.serialized_len(),
+            Self::Data(data) => data.serialized_len(),
+            Self::Name(name) => name.serialized_len(),
+            Self::Attrs(attrs) => attrs.serialized_len(),
+            Self::Extended(extended) => extended.serialized_len(),
+            Self::ExtendedReply(reply) => reply.serialized_len(),
+        }
+    }
+}
@@ -1,16 +1,22 @@
+use bytes::{Buf, Bytes};
+use std::io;
 use thiserror::Error;
 
 use super::{impl_packet_for, impl_request_id, Packet, RequestId};
+use crate::{de, error};
 
-/// Error Codes for SSH_FXP_STATUS
+/// Error Codes for SSH_FXP_STATUS.
+///
+/// The descriptions are those sent by OpenSSH, such as `No such file`,
+/// which some clients display verbatim
 #[derive(Debug, Error, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum StatusCode {
     /// Indicates successful completion of the operation.
-    #[error("Ok")]
+    #[error("Success")]
     Ok = 0,
     /// Indicates end-of-file condition; for SSH_FX_READ it means that no more data is available in the file,
     /// and for SSH_FX_READDIR it indicates that no more files are contained in the directory.
-    #[error("Eof")]
+    #[error("End of file")]
     Eof = 1,
     /// A reference is made to a file which should exist but doesn't.
     #[error("No such file")]
@@ -40,11 +46,43 @@ pub enum StatusCode {
     OpUnsupported = 8,
 }
 
+/// Kind of the [`io::Error`] of a status, [`io::ErrorKind::Other`]
+/// for the codes without a counterpart
+impl From<StatusCode> for io::ErrorKind {
+    fn from(code: StatusCode) -> Self {
+        match code {
+            StatusCode::Eof => Self::UnexpectedEof,
+            StatusCode::NoSuchFile => Self::NotFound,
+            StatusCode::PermissionDenied => Self::PermissionDenied,
+            StatusCode::NoConnection => Self::NotConnected,
+            StatusCode::ConnectionLost => Self::ConnectionAborted,
+            StatusCode::OpUnsupported => Self::Unsupported,
+            StatusCode::Ok | StatusCode::Failure | StatusCode::BadMessage => Self::Other,
+        }
+    }
+}
+
+/// Status replied for an [`io::Error`], [`StatusCode::Failure`] for the kinds
+/// without a counterpart. The connection kinds are not mapped, as
+/// [`StatusCode::NoConnection`] and [`StatusCode::ConnectionLost`]
+/// must not be sent by servers
+impl From<io::ErrorKind> for StatusCode {
+    fn from(kind: io::ErrorKind) -> Self {
+        match kind {
+            io::ErrorKind::UnexpectedEof => Self::Eof,
+            io::ErrorKind::NotFound => Self::NoSuchFile,
+            io::ErrorKind::PermissionDenied => Self::PermissionDenied,
+            io::ErrorKind::Unsupported => Self::OpUnsupported,
+            _ => Self::Failure,
+        }
+    }
+}
+
 /// Implementation for SSH_FXP_STATUS as defined in the specification draft
 /// <https://datatracker.ietf.org/doc/html/draft-ietf-secsh-filexfer-02#section-7>
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Status {
-    pub id: u32,
+    pub id: RequestId,
     pub status_code: StatusCode,
     pub error_message: String,
     pub language_tag: String,
@@ -52,3 +90,38 @@ pub struct Status {
 
 impl_request_id!(Status);
 impl_packet_for!(Status);
+
+/// SSH_FXP_STATUS without the message and the language tag
+#[derive(Deserialize)]
+struct ShortStatus {
+    id: RequestId,
+    status_code: StatusCode,
+}
+
+impl Status {
+    /// Creates a status with the description of the code as the message
+    pub fn new<I: Into<RequestId>>(id: I, status_code: StatusCode) -> Self {
+        Self {
+            id: id.into(),
+            status_code,
+            error_message: status_code.to_string(),
+            language_tag: "en-US".to_owned(),
+        }
+    }
+
+    /// Same as the regular deserialization, but also accepts a status without
+    /// the message and the language tag as sent by some version 3 servers
+    pub(crate) fn from_bytes(bytes: &mut Bytes) -> Result<Self, error::Error> {
+        if bytes.remaining() > 8 {
+            return de::from_bytes(bytes);
+        }
+
+        let status = de::from_bytes::<ShortStatus>(bytes)?;
+        Ok(Self {
+            id: status.id,
+            status_code: status.status_code,
+            error_message: String::new(),
+            language_tag: String::new(),
+        })
+    }
+}
@@ -1,6 +1,10 @@
 use std::collections::HashMap;
 
-use super::{impl_packet_for, Packet, VERSION};
+use super::{impl_packet_for, Packet, MIN_VERSION, VERSION};
+use crate::{
+    error::Error,
+    extensions::{self, ServerCapabilities},
+};
 
 /// Implementation for `SSH_FXP_VERSION`
 #[derive(Debug, Serialize, Deserialize)]
@@ -18,6 +22,126 @@ impl Version {
             extensions: HashMap::new(),
         }
     }
+
+    /// Replaces the version, which must be spoken by this crate,
+    /// from [`MIN_VERSION`] to [`VERSION`]
+    pub fn with_version(mut self, version: u32) -> Result<Self, Error> {
+        if !(MIN_VERSION..=VERSION).contains(&version) {
+            return Err(Error::BadMessage(format!(
+                "version {version} is not supported, expected {MIN_VERSION} to {VERSION}"
+            )));
+        }
+
+        self.version = version;
+        Ok(self)
+    }
+
+    /// Adds an extension. The value of an extension already added is replaced with a warning
+    pub fn with_extension<N, V>(mut self, name: N, value: V) -> Self
+    where
+        N: Into<String>,
+        V: Into<String>,
+    {
+        let _ = self.merge_extensions([(name.into(), value.into())], false);
+        self
+    }
+
+    /// Adds an extension that only informs the client, such as a version string.
+    /// The names of the extensions that change the behavior of the session are
+    /// refused, as advertising them promises requests the handler may not serve
+    pub fn with_informational<N, V>(self, name: N, value: V) -> Result<Self, Error>
+    where
+        N: Into<String>,
+        V: Into<String>,
+    {
+        let name = name.into();
+        if extensions::BEHAVIOR.contains(&name.as_str()) {
+            return Err(Error::BadMessage(format!(
+                "extension {name} is not informational"
+            )));
+        }
+
+        Ok(self.with_extension(name, value))
+    }
+
+    /// Adds the extensions, the last value of an extension wins. An extension added
+    /// with different values is logged, or rejected if `strict`, in which case none
+    /// of the extensions are added
+    pub fn merge_extensions<I>(&mut self, extensions: I, strict: bool) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let mut merged = self.extensions.clone();
+        for (name, value) in extensions {
+            match merged.insert(name.clone(), value.clone()) {
+                Some(previous) if previous != value && strict => {
+                    return Err(Error::BadMessage(format!(
+                        "extension {name} advertised as both {previous:?} and {value:?}"
+                    )));
+                }
+                Some(previous) if previous != value => {
+                    warn!("extension {name} advertised as both {previous:?} and {value:?}, {value:?} is kept");
+                }
+                _ => (),
+            }
+        }
+
+        self.extensions = merged;
+        Ok(())
+    }
+
+    /// Returns the advertised extensions, see [`ServerCapabilities::informational`]
+    pub fn capabilities(&self) -> ServerCapabilities {
+        ServerCapabilities::from(self)
+    }
+
+    /// Returns `true` if the extension is advertised with `version`, such as `"1"`.
+    ///
+    /// The values are binary strings and some servers append NUL bytes or whitespace,
+    /// which are ignored. The extensions whose value varies between servers, such as
+    /// [`extensions::LIMITS`], are supported once advertised with any value
+    pub fn supports(&self, name: &str, version: &str) -> bool {
+        let Some(raw) = self.extensions.get(name) else {
+            return false;
+        };
+
+        let value = raw.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+        if value == version {
+            if value != raw {
+                debug!("extension {name} advertised as {raw:?}, taken as {version:?}");
+            }
+
+            return true;
+        }
+
+        if extensions::UNVERSIONED.contains(&name) {
+            debug!("extension {name} advertised as {raw:?}, supported regardless of the value");
+            return true;
+        }
+
+        debug!("extension {name} advertised as {raw:?} instead of {version:?}");
+        false
+    }
+
+    /// Returns the versions listed in the [`extensions::VERSIONS`] extension, such as
+    /// `[2, 3, 4, 5, 6]` for `"2,3,4,5,6"`, in the order advertised. The entries
+    /// that are not numbers are skipped
+    pub fn versions(&self) -> Vec<u32> {
+        let Some(raw) = self.extensions.get(extensions::VERSIONS) else {
+            return Vec::new();
+        };
+
+        raw.split(',')
+            .map(|entry| entry.trim_matches(|c: char| c == '\0' || c.is_whitespace()))
+            .filter_map(|entry| match entry.parse() {
+                Ok(version) => Some(version),
+                Err(_) => {
+                    debug!("skipping version {entry:?} advertised in {raw:?}");
+                    None
+                }
+            })
+            .collect()
+    }
 }
 
 impl Default for Version {
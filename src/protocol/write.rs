@@ -1,13 +1,25 @@
 use super::{impl_packet_for, impl_request_id, Packet, RequestId};
+use crate::{de::bytes_deserialize, ser::bytes_serialize};
 
 /// Implementation for `SSH_FXP_WRITE`
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Write {
-    pub id: u32,
+    pub id: RequestId,
     pub handle: String,
     pub offset: u64,
+    #[serde(serialize_with = "bytes_serialize")]
+    #[serde(deserialize_with = "bytes_deserialize")]
     pub data: Vec<u8>,
 }
 
 impl_request_id!(Write);
 impl_packet_for!(Write);
+
+impl Write {
+    /// Returns the length of the framed packet besides the data for a handle of
+    /// `handle_len` bytes: the length, the type, the id, the handle with its length,
+    /// the offset and the length of the data
+    pub fn overhead(handle_len: usize) -> usize {
+        4 + 1 + 4 + 4 + handle_len + 8 + 4
+    }
+}
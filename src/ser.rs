@@ -22,11 +22,23 @@ where
     Ok(serializer.output.freeze())
 }
 
-/// Serialization of a [`Vec`] without length.
+/// Converts the length of a string, a sequence or a packet to its u32 prefix.
+///
+/// Returns [`Error::BadMessage`] with the length if it exceeds [`u32::MAX`],
+/// which would otherwise wrap and corrupt the frame
+pub fn wire_len(len: usize) -> Result<u32, Error> {
+    u32::try_from(len)
+        .map_err(|_| Error::BadMessage(format!("length {len} exceeds the u32 prefix")))
+}
+
+/// Serialization of a [`Vec`] without length. The data is still part of a packet
+/// whose length is a u32, so it is rejected above [`u32::MAX`] bytes
 pub fn data_serialize<S>(data: &Vec<u8>, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: serde::Serializer,
 {
+    wire_len(data.len()).map_err(serde::ser::Error::custom)?;
+
     let mut seq = serializer.serialize_seq(None)?;
     for byte in data {
         seq.serialize_element(byte)?;
@@ -34,6 +46,15 @@ where
     seq.end()
 }
 
+/// Serialization of bytes as a string with a length prefix in a single copy.
+/// Rejected above [`u32::MAX`] bytes
+pub fn bytes_serialize<S>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_bytes(data)
+}
+
 impl<'a> serde::Serializer for &'a mut Serializer {
     type Ok = ();
     type Error = Error;
@@ -97,12 +118,15 @@ impl<'a> serde::Serializer for &'a mut Serializer {
     }
 
     fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
+        wire_len(v.len())?;
         self.output.put_str(v);
         Ok(())
     }
 
-    fn serialize_bytes(self, _v: &[u8]) -> Result<Self::Ok, Self::Error> {
-        Err(Error::BadMessage("bytes not supported".to_owned()))
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        self.output.put_u32(wire_len(v.len())?);
+        self.output.put_slice(v);
+        Ok(())
     }
 
     fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
@@ -159,7 +183,7 @@ impl<'a> serde::Serializer for &'a mut Serializer {
 
     fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
         if let Some(len) = len {
-            self.output.put_u32(len as u32);
+            self.output.put_u32(wire_len(len)?);
         }
 
         Ok(self)
@@ -214,7 +238,7 @@ impl<'a> serde::Serializer for &'a mut Serializer {
     }
 }
 
-impl<'a> SerializeSeq for &'a mut Serializer {
+impl SerializeSeq for &mut Serializer {
     type Ok = ();
     type Error = Error;
 
@@ -230,7 +254,7 @@ impl<'a> SerializeSeq for &'a mut Serializer {
     }
 }
 
-impl<'a> SerializeMap for &'a mut Serializer {
+impl SerializeMap for &mut Serializer {
     type Ok = ();
     type Error = Error;
 
@@ -253,7 +277,7 @@ impl<'a> SerializeMap for &'a mut Serializer {
     }
 }
 
-impl<'a> SerializeTuple for &'a mut Serializer {
+impl SerializeTuple for &mut Serializer {
     type Ok = ();
     type Error = Error;
 
@@ -269,7 +293,7 @@ impl<'a> SerializeTuple for &'a mut Serializer {
     }
 }
 
-impl<'a> SerializeStruct for &'a mut Serializer {
+impl SerializeStruct for &mut Serializer {
     type Ok = ();
     type Error = Error;
 
@@ -285,7 +309,7 @@ impl<'a> SerializeStruct for &'a mut Serializer {
     }
 }
 
-impl<'a> SerializeStructVariant for &'a mut Serializer {
+impl SerializeStructVariant for &mut Serializer {
     type Ok = ();
     type Error = Error;
 
@@ -301,7 +325,7 @@ impl<'a> SerializeStructVariant for &'a mut Serializer {
     }
 }
 
-impl<'a> SerializeTupleStruct for &'a mut Serializer {
+impl SerializeTupleStruct for &mut Serializer {
     type Ok = ();
     type Error = Error;
 
@@ -317,7 +341,7 @@ impl<'a> SerializeTupleStruct for &'a mut Serializer {
     }
 }
 
-impl<'a> SerializeTupleVariant for &'a mut Serializer {
+impl SerializeTupleVariant for &mut Serializer {
     type Ok = ();
     type Error = Error;
 
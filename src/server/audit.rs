@@ -0,0 +1,510 @@
+use async_trait::async_trait;
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use crate::{
+    de,
+    extensions::{self, HardlinkExtension},
+    protocol::{FileAttributes, OpenFlags, Packet, RequestId, StatusCode},
+};
+
+/// Default number of events queued for the sink of an [`AuditLog`]
+pub const DEFAULT_AUDIT_CAPACITY: usize = 1024;
+
+/// Sessions served by the process, numbering [`AuditEvent::session`]
+static SESSIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Mutating operation requested by the client
+#[derive(Debug, Clone)]
+pub enum AuditOperation {
+    /// SSH_FXP_OPEN with [`OpenFlags::WRITE`], `APPEND`, `CREATE` or `TRUNCATE`.
+    /// `handle` is the handle replied on success
+    Open {
+        path: String,
+        pflags: OpenFlags,
+        handle: Option<String>,
+    },
+    /// SSH_FXP_WRITE of `len` bytes. `path` is known if the handle was opened
+    /// for writing in the session
+    Write {
+        handle: String,
+        path: Option<String>,
+        offset: u64,
+        len: u64,
+    },
+    Remove {
+        path: String,
+    },
+    Rename {
+        oldpath: String,
+        newpath: String,
+    },
+    SetStat {
+        path: String,
+        attrs: FileAttributes,
+    },
+    FSetStat {
+        handle: String,
+        path: Option<String>,
+        attrs: FileAttributes,
+    },
+    MkDir {
+        path: String,
+    },
+    RmDir {
+        path: String,
+    },
+    Symlink {
+        linkpath: String,
+        targetpath: String,
+    },
+    /// `hardlink@openssh.com`
+    Hardlink {
+        oldpath: String,
+        newpath: String,
+    },
+}
+
+impl AuditOperation {
+    /// Name of the request packet such as `SSH_FXP_WRITE`, or of the extension
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Open { .. } => "SSH_FXP_OPEN",
+            Self::Write { .. } => "SSH_FXP_WRITE",
+            Self::Remove { .. } => "SSH_FXP_REMOVE",
+            Self::Rename { .. } => "SSH_FXP_RENAME",
+            Self::SetStat { .. } => "SSH_FXP_SETSTAT",
+            Self::FSetStat { .. } => "SSH_FXP_FSETSTAT",
+            Self::MkDir { .. } => "SSH_FXP_MKDIR",
+            Self::RmDir { .. } => "SSH_FXP_RMDIR",
+            Self::Symlink { .. } => "SSH_FXP_SYMLINK",
+            Self::Hardlink { .. } => extensions::HARDLINK,
+        }
+    }
+
+    /// Builds the operation of a mutating request, [`None`] for other requests
+    fn from_request(request: &Packet, handles: &HashMap<String, String>) -> Option<Self> {
+        let path_of = |handle: &str| handles.get(handle).cloned();
+
+        let operation = match request {
+            Packet::Open(open) if open.pflags.intersects(WRITE_FLAGS) => Self::Open {
+                path: open.filename.clone(),
+                pflags: open.pflags,
+                handle: None,
+            },
+            Packet::Write(write) => Self::Write {
+                path: path_of(&write.handle),
+                handle: write.handle.clone(),
+                offset: write.offset,
+                len: write.data.len() as u64,
+            },
+            Packet::Remove(remove) => Self::Remove {
+                path: remove.filename.clone(),
+            },
+            Packet::Rename(rename) => Self::Rename {
+                oldpath: rename.oldpath.clone(),
+                newpath: rename.newpath.clone(),
+            },
+            Packet::SetStat(setstat) => Self::SetStat {
+                path: setstat.path.clone(),
+                attrs: setstat.attrs.clone(),
+            },
+            Packet::FSetStat(fsetstat) => Self::FSetStat {
+                path: path_of(&fsetstat.handle),
+                handle: fsetstat.handle.clone(),
+                attrs: fsetstat.attrs.clone(),
+            },
+            Packet::MkDir(mkdir) => Self::MkDir {
+                path: mkdir.path.clone(),
+            },
+            Packet::RmDir(rmdir) => Self::RmDir {
+                path: rmdir.path.clone(),
+            },
+            Packet::Symlink(symlink) => Self::Symlink {
+                linkpath: symlink.linkpath.clone(),
+                targetpath: symlink.targetpath.clone(),
+            },
+            Packet::Extended(extended) if extended.request == extensions::HARDLINK => {
+                let link =
+                    de::from_bytes::<HardlinkExtension>(&mut extended.data.clone().into()).ok()?;
+
+                Self::Hardlink {
+                    oldpath: link.oldpath,
+                    newpath: link.newpath,
+                }
+            }
+            _ => return None,
+        };
+
+        Some(operation)
+    }
+
+    /// Replaces the paths with the result of [`AuditSink::redact_path`]
+    fn redact(&mut self, sink: &dyn AuditSink) {
+        let paths = match self {
+            Self::Open { path, .. }
+            | Self::Remove { path }
+            | Self::SetStat { path, .. }
+            | Self::MkDir { path }
+            | Self::RmDir { path } => vec![path],
+            Self::Write { path, .. } | Self::FSetStat { path, .. } => path.iter_mut().collect(),
+            Self::Rename { oldpath, newpath } | Self::Hardlink { oldpath, newpath } => {
+                vec![oldpath, newpath]
+            }
+            Self::Symlink {
+                linkpath,
+                targetpath,
+            } => vec![linkpath, targetpath],
+        };
+
+        for path in paths {
+            *path = sink.redact_path(path);
+        }
+    }
+}
+
+const WRITE_FLAGS: OpenFlags = OpenFlags::WRITE
+    .union(OpenFlags::APPEND)
+    .union(OpenFlags::CREATE)
+    .union(OpenFlags::TRUNCATE);
+
+/// Record of a mutating request and its outcome, see [`Config::audit`](super::Config::audit)
+#[derive(Debug, Clone)]
+pub struct AuditEvent {
+    /// Time the response was determined
+    pub time: SystemTime,
+    /// Number of the session within the process
+    pub session: u64,
+    /// [`Config::session_identity`](super::Config::session_identity) of the session
+    pub identity: Option<String>,
+    pub id: RequestId,
+    pub operation: AuditOperation,
+    /// Status replied to the client, [`StatusCode::Ok`] for other replies
+    pub status: StatusCode,
+}
+
+/// Receives the events of an [`AuditLog`]
+#[async_trait]
+pub trait AuditSink: Send + Sync + 'static {
+    /// Stores the event. Called for one event at a time off the request path,
+    /// the events queue up meanwhile
+    async fn record(&self, event: AuditEvent);
+
+    /// Converts a path before it is passed to [`AuditSink::record`],
+    /// for example to store a hash of it. Default: the path as is
+    fn redact_path(&self, path: &str) -> String {
+        path.to_owned()
+    }
+}
+
+/// Counters of an [`AuditLog`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AuditStats {
+    /// Events queued for the sink
+    pub queued: u64,
+    /// Events passed to [`AuditSink::record`]
+    pub recorded: u64,
+    /// Events dropped because the queue was full
+    pub dropped: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    queued: AtomicU64,
+    recorded: AtomicU64,
+    dropped: AtomicU64,
+}
+
+/// Queue of audit events shared by the sessions served with the same [`Config`](super::Config).
+///
+/// The processing loop never waits for the sink: events that do not fit into
+/// the queue are dropped and counted in [`AuditStats::dropped`]
+#[derive(Clone)]
+pub struct AuditLog {
+    tx: mpsc::Sender<AuditEvent>,
+    counters: Arc<Counters>,
+}
+
+impl AuditLog {
+    /// Spawns the task passing the events to `sink`, which ends once all clones
+    /// of the log are dropped. Must be called within a Tokio runtime
+    pub fn new<S: AuditSink>(sink: S, capacity: usize) -> Self {
+        let (tx, mut rx) = mpsc::channel::<AuditEvent>(capacity.max(1));
+        let counters = Arc::new(Counters::default());
+
+        let recorded = counters.clone();
+        tokio::spawn(async move {
+            while let Some(mut event) = rx.recv().await {
+                event.operation.redact(&sink);
+                sink.record(event).await;
+                recorded.recorded.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+
+        Self { tx, counters }
+    }
+
+    pub fn stats(&self) -> AuditStats {
+        AuditStats {
+            queued: self.counters.queued.load(Ordering::Relaxed),
+            recorded: self.counters.recorded.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+        }
+    }
+
+    fn emit(&self, event: AuditEvent) {
+        match self.tx.try_send(event) {
+            Ok(()) => self.counters.queued.fetch_add(1, Ordering::Relaxed),
+            Err(TrySendError::Full(_) | TrySendError::Closed(_)) => {
+                self.counters.dropped.fetch_add(1, Ordering::Relaxed)
+            }
+        };
+    }
+}
+
+impl fmt::Debug for AuditLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuditLog")
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+/// Part of a request kept by [`SessionAudit::before`]
+pub(crate) enum Pending {
+    Operation(AuditOperation),
+    Close(String),
+    None,
+}
+
+/// Auditing state of a session
+pub(crate) struct SessionAudit {
+    log: AuditLog,
+    session: u64,
+    identity: Option<String>,
+    /// Paths of the handles opened for writing
+    handles: HashMap<String, String>,
+}
+
+impl SessionAudit {
+    pub fn new(log: AuditLog, identity: Option<String>) -> Self {
+        Self {
+            log,
+            session: SESSIONS.fetch_add(1, Ordering::Relaxed) + 1,
+            identity,
+            handles: HashMap::new(),
+        }
+    }
+
+    /// Captures what is needed from the request before it is handled
+    pub fn before(&self, request: &Packet) -> Pending {
+        match request {
+            Packet::Close(close) => Pending::Close(close.handle.clone()),
+            request => match AuditOperation::from_request(request, &self.handles) {
+                Some(operation) => Pending::Operation(operation),
+                None => Pending::None,
+            },
+        }
+    }
+
+    /// Emits the event of the request once its response is determined
+    pub fn after(&mut self, id: RequestId, pending: Pending, response: &Packet) {
+        let status = match response {
+            Packet::Status(status) => status.status_code,
+            _ => StatusCode::Ok,
+        };
+
+        match pending {
+            Pending::Operation(operation) => self.emit(id, operation, status, response),
+            Pending::Close(handle) if status == StatusCode::Ok => {
+                self.handles.remove(&handle);
+            }
+            Pending::Close(_) | Pending::None => (),
+        }
+    }
+
+    fn emit(
+        &mut self,
+        id: RequestId,
+        mut operation: AuditOperation,
+        status: StatusCode,
+        response: &Packet,
+    ) {
+        if let (AuditOperation::Open { path, handle, .. }, Packet::Handle(reply)) =
+            (&mut operation, response)
+        {
+            self.handles.insert(reply.handle.clone(), path.clone());
+            *handle = Some(reply.handle.clone());
+        }
+
+        self.log.emit(AuditEvent {
+            time: SystemTime::now(),
+            session: self.session,
+            identity: self.identity.clone(),
+            id,
+            operation,
+            status,
+        });
+    }
+}
+
+#[cfg(feature = "audit-json")]
+pub use self::json::JsonLinesSink;
+
+#[cfg(feature = "audit-json")]
+mod json {
+    use async_trait::async_trait;
+    use chrono::{DateTime, SecondsFormat, Utc};
+    use serde_json::{json, Map, Value};
+    use tokio::{
+        io::{AsyncWrite, AsyncWriteExt},
+        sync::Mutex,
+    };
+
+    use super::{AuditEvent, AuditOperation, AuditSink};
+    use crate::protocol::{FileAttributes, FileTime};
+
+    type Redact = Box<dyn Fn(&str) -> String + Send + Sync>;
+
+    /// Writes each event as a line of JSON such as
+    /// `{"time":"2024-01-01T00:00:00.000Z","session":1,"identity":null,"id":3,
+    /// "op":"SSH_FXP_REMOVE","status":"Ok","path":"/tmp/a"}`.
+    ///
+    /// Write errors are logged and the event is lost
+    pub struct JsonLinesSink<W> {
+        writer: Mutex<W>,
+        redact: Option<Redact>,
+    }
+
+    impl<W> JsonLinesSink<W>
+    where
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        pub fn new(writer: W) -> Self {
+            Self {
+                writer: Mutex::new(writer),
+                redact: None,
+            }
+        }
+
+        /// Sets the conversion of the paths, see [`AuditSink::redact_path`]
+        pub fn redact_paths<F>(mut self, redact: F) -> Self
+        where
+            F: Fn(&str) -> String + Send + Sync + 'static,
+        {
+            self.redact = Some(Box::new(redact));
+            self
+        }
+
+        /// Returns the writer once the log of the sink has ended
+        pub fn into_inner(self) -> W {
+            self.writer.into_inner()
+        }
+    }
+
+    #[async_trait]
+    impl<W> AuditSink for JsonLinesSink<W>
+    where
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        async fn record(&self, event: AuditEvent) {
+            let mut line = to_json(&event).to_string();
+            line.push('\n');
+
+            let mut writer = self.writer.lock().await;
+            let result = match writer.write_all(line.as_bytes()).await {
+                Ok(()) => writer.flush().await,
+                Err(err) => Err(err),
+            };
+
+            if let Err(err) = result {
+                warn!("failed to write the audit event: {err}");
+            }
+        }
+
+        fn redact_path(&self, path: &str) -> String {
+            match &self.redact {
+                Some(redact) => redact(path),
+                None => path.to_owned(),
+            }
+        }
+    }
+
+    fn to_json(event: &AuditEvent) -> Value {
+        let time: DateTime<Utc> = event.time.into();
+        let mut value = json!({
+            "time": time.to_rfc3339_opts(SecondsFormat::Millis, true),
+            "session": event.session,
+            "identity": event.identity,
+            "id": event.id,
+            "op": event.operation.name(),
+            "status": format!("{:?}", event.status),
+        });
+
+        let fields = match &event.operation {
+            AuditOperation::Open {
+                path,
+                pflags,
+                handle,
+            } => json!({ "path": path, "pflags": pflags.bits(), "handle": handle }),
+            AuditOperation::Write {
+                handle,
+                path,
+                offset,
+                len,
+            } => json!({ "handle": handle, "path": path, "offset": offset, "len": len }),
+            AuditOperation::Remove { path }
+            | AuditOperation::MkDir { path }
+            | AuditOperation::RmDir { path } => json!({ "path": path }),
+            AuditOperation::Rename { oldpath, newpath }
+            | AuditOperation::Hardlink { oldpath, newpath } => {
+                json!({ "oldpath": oldpath, "newpath": newpath })
+            }
+            AuditOperation::SetStat { path, attrs } => {
+                json!({ "path": path, "attrs": attrs_json(attrs) })
+            }
+            AuditOperation::FSetStat {
+                handle,
+                path,
+                attrs,
+            } => json!({ "handle": handle, "path": path, "attrs": attrs_json(attrs) }),
+            AuditOperation::Symlink {
+                linkpath,
+                targetpath,
+            } => json!({ "linkpath": linkpath, "targetpath": targetpath }),
+        };
+
+        if let (Value::Object(value), Value::Object(fields)) = (&mut value, fields) {
+            value.extend(fields);
+        }
+
+        value
+    }
+
+    /// The attributes that were set
+    fn attrs_json(attrs: &FileAttributes) -> Value {
+        let fields = [
+            ("size", attrs.size),
+            ("uid", attrs.uid.map(u64::from)),
+            ("gid", attrs.gid.map(u64::from)),
+            ("permissions", attrs.permissions.map(u64::from)),
+            ("atime", attrs.atime.map(FileTime::secs)),
+            ("mtime", attrs.mtime.map(FileTime::secs)),
+        ];
+
+        Value::Object(
+            fields
+                .into_iter()
+                .filter_map(|(name, value)| Some((name.to_owned(), value?.into())))
+                .collect::<Map<_, _>>(),
+        )
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+
+use super::{EndReason, Handler, IntoStatus, SessionContext, StatusError};
+use crate::{
+    extensions::LockFlags,
+    protocol::{
+        Attrs, Data, FileAttributes, Handle, Name, OpenFlags, Packet, RequestId, Status, Version,
+    },
+};
+
+/// Handler as a trait object, for example selected from a registry of plugins
+/// at runtime. Served by [`run`](super::run) and the other entry points as is,
+/// see [`Handler::into_boxed`]
+pub type DynHandler = dyn Handler<Error = StatusError> + Send;
+
+/// Boxed [`DynHandler`]
+pub type BoxedHandler = Box<DynHandler>;
+
+/// Adapter converting the errors of the handler into [`StatusError`],
+/// so that handlers with different errors fit behind a [`DynHandler`]
+#[derive(Debug, Clone, Default)]
+pub struct StatusHandler<H>(pub H);
+
+/// Access to the handler the calls are forwarded to
+trait Forward {
+    type Inner: Handler + Send + ?Sized;
+
+    fn inner(&self) -> &Self::Inner;
+    fn inner_mut(&mut self) -> &mut Self::Inner;
+}
+
+impl<H: Handler + Send + ?Sized> Forward for Box<H> {
+    type Inner = H;
+
+    fn inner(&self) -> &H {
+        self
+    }
+
+    fn inner_mut(&mut self) -> &mut H {
+        self
+    }
+}
+
+impl<H: Handler + Send> Forward for StatusHandler<H> {
+    type Inner = H;
+
+    fn inner(&self) -> &H {
+        &self.0
+    }
+
+    fn inner_mut(&mut self) -> &mut H {
+        &mut self.0
+    }
+}
+
+fn same<T, E>(result: Result<T, E>) -> Result<T, E> {
+    result
+}
+
+fn into_status<T, E: IntoStatus>(result: Result<T, E>) -> Result<T, StatusError> {
+    result.map_err(IntoStatus::into_status)
+}
+
+/// Passes the signatures of the request methods of [`Handler`] to the macro
+macro_rules! with_request_methods {
+    ($callback:ident!($($args:tt)*)) => {
+        $callback!($($args)*;
+            init(version: u32, extensions: HashMap<String, String>) -> Version;
+            open(id: RequestId, filename: String, pflags: OpenFlags, attrs: FileAttributes) -> Handle;
+            close(id: RequestId, handle: String) -> Status;
+            read(id: RequestId, handle: String, offset: u64, len: u32) -> Data;
+            write(id: RequestId, handle: String, offset: u64, data: Vec<u8>) -> Status;
+            lstat(id: RequestId, path: String) -> Attrs;
+            fstat(id: RequestId, handle: String) -> Attrs;
+            setstat(id: RequestId, path: String, attrs: FileAttributes) -> Status;
+            fsetstat(id: RequestId, handle: String, attrs: FileAttributes) -> Status;
+            opendir(id: RequestId, path: String) -> Handle;
+            readdir(id: RequestId, handle: String) -> Name;
+            remove(id: RequestId, filename: String) -> Status;
+            mkdir(id: RequestId, path: String, attrs: FileAttributes) -> Status;
+            rmdir(id: RequestId, path: String) -> Status;
+            realpath(id: RequestId, path: String) -> Name;
+            stat(id: RequestId, path: String) -> Attrs;
+            rename(id: RequestId, oldpath: String, newpath: String) -> Status;
+            readlink(id: RequestId, path: String) -> Name;
+            symlink(id: RequestId, linkpath: String, targetpath: String) -> Status;
+            fsync(id: RequestId, handle: String) -> Status;
+            hardlink(id: RequestId, oldpath: String, newpath: String) -> Status;
+            home_directory(id: RequestId, username: String) -> Name;
+            block(id: RequestId, handle: String, offset: u64, length: u64, lock_flags: LockFlags) -> Status;
+            unblock(id: RequestId, handle: String, offset: u64, length: u64) -> Status;
+            extended(id: RequestId, request: String, data: Vec<u8>) -> Packet;
+        );
+    };
+}
+
+pub(super) use with_request_methods;
+
+/// Implements [`Handler`] by forwarding every call to [`Forward::inner_mut`]
+/// and passing the result through `$map`
+macro_rules! forward_handler {
+    (
+        [$($generics:tt)*] $ty:ty, $error:ty, $map:ident;
+        $($method:ident($($arg:ident: $arg_ty:ty),*) -> $ret:ty;)*
+    ) => {
+        #[async_trait]
+        impl<$($generics)*> Handler for $ty {
+            type Error = $error;
+
+            fn unimplemented(&self) -> Self::Error {
+                $map::<(), _>(Err(self.inner().unimplemented())).unwrap_err()
+            }
+
+            fn supported_extensions(&self) -> &'static [&'static str] {
+                self.inner().supported_extensions()
+            }
+
+            fn set_context(&mut self, context: &SessionContext) {
+                self.inner_mut().set_context(context)
+            }
+
+            async fn session_ended(&mut self, reason: EndReason) {
+                self.inner_mut().session_ended(reason).await
+            }
+
+            $(
+                async fn $method(&mut self, $($arg: $arg_ty),*) -> Result<$ret, Self::Error> {
+                    $map(self.inner_mut().$method($($arg),*).await)
+                }
+            )*
+        }
+    };
+}
+
+with_request_methods!(forward_handler!([H: Handler + Send + ?Sized] Box<H>, H::Error, same));
+with_request_methods!(
+    forward_handler!([H: Handler + Send] StatusHandler<H>, StatusError, into_status)
+);
@@ -0,0 +1,254 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    time::Duration,
+};
+
+use super::{throttle::Throttle, Config, LatencyStats, ReadDirContext, Responder};
+use crate::{
+    framing::DEFAULT_MAX_PACKET_LEN,
+    protocol::{Init, Packet, StatusCode},
+};
+
+/// Number of closed handles remembered to recognize late requests, the oldest being forgotten
+const MAX_CLOSED_HANDLES: usize = 1024;
+
+/// State of a session maintained by the processing loop.
+///
+/// Passed to [`Handler::set_context`](super::Handler::set_context)
+/// before each request is handled.
+#[derive(Debug, Clone, Default)]
+pub struct SessionContext {
+    client_version: Option<u32>,
+    version: Option<u32>,
+    extensions: HashMap<String, String>,
+    language: Option<String>,
+    identity: Option<String>,
+    max_request_packet_len: u32,
+    max_response_packet_len: Option<u32>,
+    requests: u64,
+    responses: u64,
+    open_handles: u64,
+    /// Times each open handle was returned, some handlers returning the same handle
+    /// for the files opened at the same path
+    issued_handles: HashMap<String, u64>,
+    closed_handles: HashSet<String>,
+    closed_order: VecDeque<String>,
+    late_requests: u64,
+    latencies: HashMap<&'static str, LatencyStats>,
+    read_dir: Option<ReadDirContext>,
+    throttle: Option<Throttle>,
+    responder: Responder,
+}
+
+impl SessionContext {
+    pub(crate) fn new(config: &Config) -> Self {
+        Self {
+            language: config.language.clone(),
+            identity: config.session_identity.clone(),
+            max_request_packet_len: config
+                .max_request_packet_len
+                .unwrap_or(DEFAULT_MAX_PACKET_LEN),
+            max_response_packet_len: config.max_response_packet_len,
+            throttle: config.max_requests_per_second.map(Throttle::new),
+            ..Default::default()
+        }
+    }
+
+    /// Version sent by the client in SSH_FXP_INIT
+    pub fn client_version(&self) -> Option<u32> {
+        self.client_version
+    }
+
+    /// Negotiated version, the lowest of the client and server versions.
+    /// Available after SSH_FXP_VERSION was sent
+    pub fn version(&self) -> Option<u32> {
+        self.version
+    }
+
+    /// Extensions sent by the client in SSH_FXP_INIT
+    pub fn extensions(&self) -> &HashMap<String, String> {
+        &self.extensions
+    }
+
+    /// Returns `true` if the client advertised the extension
+    pub fn has_extension(&self, name: &str) -> bool {
+        self.extensions.contains_key(name)
+    }
+
+    /// Language requested by the client in an SSH_FXP_INIT extension named
+    /// `language@<domain>`, otherwise [`Config::language`](super::Config::language)
+    pub fn language(&self) -> Option<&str> {
+        self.extensions
+            .iter()
+            .find(|(name, _)| name.starts_with("language@"))
+            .map(|(_, value)| value.as_str())
+            .or(self.language.as_deref())
+    }
+
+    /// Identity of the session, see [`Config::session_identity`](super::Config::session_identity)
+    pub fn identity(&self) -> Option<&str> {
+        self.identity.as_deref()
+    }
+
+    /// Maximum length of a request packet accepted by the session,
+    /// see [`Config::max_request_packet_len`](super::Config::max_request_packet_len)
+    pub fn max_request_packet_len(&self) -> u32 {
+        self.max_request_packet_len
+    }
+
+    /// Configured maximum length of a response packet,
+    /// see [`Config::max_response_packet_len`](super::Config::max_response_packet_len)
+    pub fn max_response_packet_len(&self) -> Option<u32> {
+        self.max_response_packet_len
+    }
+
+    /// Number of received requests including the current one
+    pub fn requests(&self) -> u64 {
+        self.requests
+    }
+
+    /// Number of sent responses
+    pub fn responses(&self) -> u64 {
+        self.responses
+    }
+
+    /// Number of handles returned to the client and not yet successfully closed
+    pub fn open_handles(&self) -> u64 {
+        self.open_handles
+    }
+
+    /// Number of requests received on a handle after it was successfully closed,
+    /// see [`Config::closed_handle_action`](super::Config::closed_handle_action)
+    pub fn late_requests(&self) -> u64 {
+        self.late_requests
+    }
+
+    /// Handler latency of the requests of the type, such as `SSH_FXP_READ`
+    pub fn latency(&self, packet: &str) -> Option<LatencyStats> {
+        self.latencies.get(packet).copied()
+    }
+
+    /// Handler latency of each request type handled so far
+    pub fn latencies(&self) -> impl Iterator<Item = (&'static str, LatencyStats)> + '_ {
+        self.latencies.iter().map(|(name, stats)| (*name, *stats))
+    }
+
+    /// Number of requests delayed per [`Config::max_requests_per_second`](super::Config::max_requests_per_second)
+    pub fn throttled_requests(&self) -> u64 {
+        self.throttle.as_ref().map_or(0, Throttle::throttled)
+    }
+
+    /// Requests that could be handled right away after the current one,
+    /// [`None`] if the requests are not limited
+    pub fn request_tokens(&self) -> Option<f64> {
+        self.throttle.as_ref().map(Throttle::tokens)
+    }
+
+    /// Replies written by the processing loop, such as the data streamed by
+    /// [`Responder::send_data_streaming`]. Clones share the session
+    pub fn responder(&self) -> &Responder {
+        &self.responder
+    }
+
+    /// Pagination hint of the current SSH_FXP_READDIR, [`None`] for other requests
+    pub fn read_dir(&self) -> Option<ReadDirContext> {
+        self.read_dir
+    }
+
+    pub(crate) fn set_read_dir(&mut self, read_dir: Option<ReadDirContext>) {
+        self.read_dir = read_dir;
+    }
+
+    /// Waits until the request read last can be handled
+    pub(crate) async fn throttle(&mut self) {
+        if let Some(throttle) = &mut self.throttle {
+            throttle.acquire().await;
+        }
+    }
+
+    pub(crate) fn on_handled(&mut self, packet: &'static str, elapsed: Duration) {
+        self.latencies.entry(packet).or_default().record(elapsed);
+    }
+
+    /// Seeds the state of a session whose SSH_FXP_INIT was handled elsewhere
+    pub(crate) fn initialized(&mut self, init: Init, version: u32) {
+        self.client_version = Some(init.version);
+        self.extensions = init.extensions;
+        self.version = Some(init.version.min(version));
+    }
+
+    pub(crate) fn on_request(&mut self, request: &Packet) {
+        self.requests += 1;
+
+        if let Packet::Init(init) = request {
+            self.client_version = Some(init.version);
+            self.extensions = init.extensions.clone();
+        }
+    }
+
+    /// Returns `true` and counts the request if its handle was closed
+    pub(crate) fn on_late_request(&mut self, request: &Packet) -> bool {
+        let late = request
+            .handle()
+            .is_some_and(|handle| self.closed_handles.contains(handle));
+        if late {
+            self.late_requests += 1;
+        }
+
+        late
+    }
+
+    /// `closed` is the handle of the request if it is SSH_FXP_CLOSE
+    pub(crate) fn on_response(&mut self, closed: Option<String>, response: &Packet) {
+        self.responses += 1;
+
+        match response {
+            Packet::Version(version) => {
+                self.version = Some(match self.client_version {
+                    Some(client) => client.min(version.version),
+                    None => version.version,
+                });
+            }
+            Packet::Handle(handle) => {
+                self.open_handles += 1;
+                *self
+                    .issued_handles
+                    .entry(handle.handle.clone())
+                    .or_default() += 1;
+                // the handler may reuse the handle of a closed file
+                if self.closed_handles.remove(&handle.handle) {
+                    self.closed_order.retain(|closed| *closed != handle.handle);
+                }
+            }
+            Packet::Status(status) if status.status_code == StatusCode::Ok => {
+                if let Some(handle) = closed {
+                    self.open_handles = self.open_handles.saturating_sub(1);
+                    self.on_closed(handle);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn on_closed(&mut self, handle: String) {
+        if let Some(issued) = self.issued_handles.get_mut(&handle) {
+            *issued -= 1;
+            if *issued > 0 {
+                return;
+            }
+
+            self.issued_handles.remove(&handle);
+        }
+
+        if !self.closed_handles.insert(handle.clone()) {
+            return;
+        }
+
+        self.closed_order.push_back(handle);
+        if self.closed_order.len() > MAX_CLOSED_HANDLES {
+            if let Some(oldest) = self.closed_order.pop_front() {
+                self.closed_handles.remove(&oldest);
+            }
+        }
+    }
+}
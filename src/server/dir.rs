@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+
+use crate::protocol::{File, StatusCode};
+
+/// Pagination hint of an SSH_FXP_READDIR, see [`SessionContext::read_dir`](super::SessionContext::read_dir).
+///
+/// The protocol has no count for SSH_FXP_READDIR, so the hint is derived from the maximum
+/// response length and the size of the entries previously returned on the handle. Handlers
+/// backed by paginated listings can fetch only what fits into one response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadDirContext {
+    /// Number of entries that are expected to fit into the response
+    pub suggested_max_entries: usize,
+    /// Number of entries already sent to the client for the handle
+    pub previously_returned: u64,
+}
+
+/// Hands out the entries of directories in batches of the suggested size.
+///
+/// For handlers that list a directory as an iterator. The iterator is stored on
+/// SSH_FXP_OPENDIR and only advanced by as many entries as the next response takes,
+/// so a lazily paginated listing is fetched no further than the client reads.
+///
+/// ```
+/// use russh_sftp::{protocol::{File, StatusCode}, server::DirBatcher};
+///
+/// let mut batcher = DirBatcher::new();
+/// batcher.insert("h1", (0..3).map(|i| File::dummy(format!("{i}"))));
+///
+/// assert_eq!(batcher.next_batch("h1", None).map(|b| b.len()), Ok(3));
+/// assert_eq!(batcher.next_batch("h1", None).map(|b| b.len()), Err(StatusCode::Eof));
+/// ```
+#[derive(Debug)]
+pub struct DirBatcher<I> {
+    listings: HashMap<String, I>,
+    batch: usize,
+}
+
+impl<I> Default for DirBatcher<I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<I> DirBatcher<I> {
+    pub fn new() -> Self {
+        Self {
+            listings: HashMap::new(),
+            batch: 100,
+        }
+    }
+
+    /// Sets the number of entries per batch without a [`ReadDirContext`]. Default: 100
+    pub fn batch_size(mut self, batch: usize) -> Self {
+        self.batch = batch.max(1);
+        self
+    }
+
+    /// Stores the entries of the directory opened with the handle
+    pub fn insert<H: Into<String>>(&mut self, handle: H, entries: I) {
+        self.listings.insert(handle.into(), entries);
+    }
+
+    /// Forgets the handle on SSH_FXP_CLOSE, returns `false` if it is unknown
+    pub fn remove(&mut self, handle: &str) -> bool {
+        self.listings.remove(handle).is_some()
+    }
+
+    /// Returns `true` if the handle belongs to a stored directory
+    pub fn contains(&self, handle: &str) -> bool {
+        self.listings.contains_key(handle)
+    }
+}
+
+impl<I> DirBatcher<I>
+where
+    I: Iterator<Item = File>,
+{
+    /// Takes the entries for the reply to SSH_FXP_READDIR on the handle, as many as
+    /// suggested by `context`. Fails with [`StatusCode::Eof`] once the directory is
+    /// exhausted and [`StatusCode::NoSuchFile`] if the handle is unknown
+    pub fn next_batch(
+        &mut self,
+        handle: &str,
+        context: Option<&ReadDirContext>,
+    ) -> Result<Vec<File>, StatusCode> {
+        let entries = self
+            .listings
+            .get_mut(handle)
+            .ok_or(StatusCode::NoSuchFile)?;
+
+        let batch = context.map_or(self.batch, |c| c.suggested_max_entries.max(1));
+        let files = entries.take(batch).collect::<Vec<_>>();
+
+        match files.is_empty() {
+            true => Err(StatusCode::Eof),
+            false => Ok(files),
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::{collections::HashMap, convert::identity, future::Future, pin::Pin};
+
+use super::{
+    boxed::with_request_methods, EndReason, Handler, IntoStatus, LocalHandler, SessionContext,
+};
+use crate::{
+    extensions::LockFlags,
+    protocol::{
+        Attrs, Data, FileAttributes, Handle, Name, OpenFlags, Packet, RequestId, Status, Version,
+    },
+};
+
+/// [`LocalHandler`] served by the processing loop
+pub(crate) struct Local<H>(pub H);
+
+impl<H> Local<H> {
+    fn get(&self) -> &H {
+        &self.0
+    }
+
+    fn get_mut(&mut self) -> &mut H {
+        &mut self.0
+    }
+}
+
+/// Calls of the processing loop on the handler. The futures keep whether they are
+/// `Send`, so that the loop is `Send` for a [`Handler`] and not for a [`LocalHandler`]
+macro_rules! dispatch_trait {
+    (; $($method:ident($($arg:ident: $arg_ty:ty),*) -> $ret:ty;)*) => {
+        pub(crate) trait Dispatch {
+            type Error: IntoStatus;
+            type Future<'a, T: 'a>: Future<Output = Result<T, Self::Error>> + 'a
+            where
+                Self: 'a;
+            type Ended<'a>: Future<Output = ()> + 'a
+            where
+                Self: 'a;
+
+            fn supported_extensions(&self) -> &'static [&'static str];
+            fn set_context(&mut self, context: &SessionContext);
+            fn session_ended(&mut self, reason: EndReason) -> Self::Ended<'_>;
+            $(fn $method(&mut self, $($arg: $arg_ty),*) -> Self::Future<'_, $ret>;)*
+        }
+    };
+}
+
+/// Implements [`Dispatch`] by calling the methods of `$handler` on the handler
+/// returned by `$get` and `$get_mut`
+macro_rules! dispatch_impl {
+    (
+        [$($generics:tt)*] $ty:ty, $handler:ident, $get:path, $get_mut:path, $($send:ident)?;
+        $($method:ident($($arg:ident: $arg_ty:ty),*) -> $ret:ty;)*
+    ) => {
+        impl<$($generics)*> Dispatch for $ty {
+            type Error = H::Error;
+            type Future<'a, T: 'a> =
+                Pin<Box<dyn Future<Output = Result<T, Self::Error>> $(+ $send)? + 'a>>
+            where
+                Self: 'a;
+            type Ended<'a> = Pin<Box<dyn Future<Output = ()> $(+ $send)? + 'a>>
+            where
+                Self: 'a;
+
+            fn supported_extensions(&self) -> &'static [&'static str] {
+                $handler::supported_extensions($get(self))
+            }
+
+            fn set_context(&mut self, context: &SessionContext) {
+                $handler::set_context($get_mut(self), context)
+            }
+
+            fn session_ended(&mut self, reason: EndReason) -> Self::Ended<'_> {
+                $handler::session_ended($get_mut(self), reason)
+            }
+
+            $(
+                fn $method(&mut self, $($arg: $arg_ty),*) -> Self::Future<'_, $ret> {
+                    $handler::$method($get_mut(self), $($arg),*)
+                }
+            )*
+        }
+    };
+}
+
+with_request_methods!(dispatch_trait!());
+with_request_methods!(dispatch_impl!([H: Handler + Send] H, Handler, identity, identity, Send));
+with_request_methods!(dispatch_impl!(
+    [H: LocalHandler] Local<H>, LocalHandler, Local::get, Local::get_mut,
+));
@@ -0,0 +1,216 @@
+use std::{
+    future::Future,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    task::JoinSet,
+};
+
+use super::{serve_with_context, Config, EndReason, Handler, SessionContext};
+
+/// Connection of a session as known by the embedder, passed to [`HandlerFactory`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SessionInfo {
+    /// Number of the session, assigned by [`SessionHub::spawn`] in the order of the sessions
+    pub id: u64,
+    /// Address of the client
+    pub peer: Option<SocketAddr>,
+    /// User authenticated by the SSH layer
+    pub user: Option<String>,
+}
+
+impl SessionInfo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_peer(mut self, peer: SocketAddr) -> Self {
+        self.peer = Some(peer);
+        self
+    }
+
+    pub fn with_user<U: Into<String>>(mut self, user: U) -> Self {
+        self.user = Some(user.into());
+        self
+    }
+}
+
+/// Makes a [`Handler`] for each session of a [`SessionHub`]. The state shared by the
+/// sessions, such as a registry of open files or a quota, is kept by the factory and
+/// handed to the handlers it makes.
+///
+/// Implemented for closures taking the [`SessionInfo`]
+pub trait HandlerFactory: Send + Sync + 'static {
+    type Handler: Handler + Send + 'static;
+
+    fn make_handler(&self, info: SessionInfo) -> Self::Handler;
+
+    /// Called before the first request of a session is read
+    #[allow(unused_variables)]
+    fn session_started(&self, info: &SessionInfo) {}
+
+    /// Called once the session ended, after [`Handler::session_ended`]
+    #[allow(unused_variables)]
+    fn session_ended(&self, info: &SessionInfo, reason: &EndReason) {}
+}
+
+impl<F, H> HandlerFactory for F
+where
+    F: Fn(SessionInfo) -> H + Send + Sync + 'static,
+    H: Handler + Send + 'static,
+{
+    type Handler = H;
+
+    fn make_handler(&self, info: SessionInfo) -> Self::Handler {
+        self(info)
+    }
+}
+
+/// Counters of the sessions of a [`SessionHub`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HubStats {
+    /// Sessions spawned
+    pub sessions: u64,
+    /// Sessions not ended yet
+    pub active: u64,
+    /// Sessions closed by the client, [`EndReason::Closed`]
+    pub closed: u64,
+    /// Sessions ended for another reason
+    pub failed: u64,
+    /// Requests received by the ended sessions
+    pub requests: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    sessions: AtomicU64,
+    active: AtomicU64,
+    closed: AtomicU64,
+    failed: AtomicU64,
+    requests: AtomicU64,
+}
+
+/// Serves many sessions with handlers made by one [`HandlerFactory`], each session
+/// on its own task. The sessions are aborted when the hub is dropped
+pub struct SessionHub<F> {
+    factory: Arc<F>,
+    config: Config,
+    counters: Arc<Counters>,
+    tasks: JoinSet<()>,
+}
+
+impl<F: HandlerFactory> SessionHub<F> {
+    pub fn new(factory: F, config: Config) -> Self {
+        Self {
+            factory: Arc::new(factory),
+            config,
+            counters: Arc::default(),
+            tasks: JoinSet::new(),
+        }
+    }
+
+    /// Returns the factory, for example to inspect the state shared by the sessions
+    pub fn factory(&self) -> &Arc<F> {
+        &self.factory
+    }
+
+    /// Spawns a session serving the stream with a handler made for `info`,
+    /// whose [`SessionInfo::id`] is replaced with the number of the session
+    pub fn spawn<S>(&mut self, stream: S, mut info: SessionInfo)
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        // the ended sessions are reaped so that the set does not grow with them
+        while self.tasks.try_join_next().is_some() {}
+
+        info.id = self.counters.sessions.fetch_add(1, Ordering::Relaxed);
+        self.counters.active.fetch_add(1, Ordering::Relaxed);
+
+        let factory = self.factory.clone();
+        let config = self.config.clone();
+        let counters = self.counters.clone();
+        self.tasks.spawn(async move {
+            let handler = factory.make_handler(info.clone());
+            factory.session_started(&info);
+
+            let context = SessionContext::new(&config);
+            let (result, reason, context) =
+                serve_with_context(stream, handler, config, context).await;
+            if let Err(err) = result {
+                warn!("session {} ended: {err}", info.id);
+            }
+
+            let ended = match reason {
+                EndReason::Closed => &counters.closed,
+                _ => &counters.failed,
+            };
+            ended.fetch_add(1, Ordering::Relaxed);
+            counters
+                .requests
+                .fetch_add(context.requests(), Ordering::Relaxed);
+            counters.active.fetch_sub(1, Ordering::Relaxed);
+
+            factory.session_ended(&info, &reason);
+        });
+    }
+
+    /// Returns the counters of the sessions spawned so far
+    pub fn stats(&self) -> HubStats {
+        HubStats {
+            sessions: self.counters.sessions.load(Ordering::Relaxed),
+            active: self.counters.active.load(Ordering::Relaxed),
+            closed: self.counters.closed.load(Ordering::Relaxed),
+            failed: self.counters.failed.load(Ordering::Relaxed),
+            requests: self.counters.requests.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Waits for the sessions spawned so far to end
+    pub async fn join(&mut self) {
+        while let Some(result) = self.tasks.join_next().await {
+            if let Err(err) = result {
+                warn!("session task failed: {err}");
+            }
+        }
+    }
+}
+
+/// Serves each stream with a handler made by `factory` and returns
+/// the counters once all the sessions ended
+pub async fn serve_many<F, S, I>(factory: F, config: Config, streams: I) -> HubStats
+where
+    F: HandlerFactory,
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    I: IntoIterator<Item = (S, SessionInfo)>,
+{
+    let mut hub = SessionHub::new(factory, config);
+    for (stream, info) in streams {
+        hub.spawn(stream, info);
+    }
+
+    hub.join().await;
+    hub.stats()
+}
+
+/// Same as [`serve_many`] with the streams returned by `accept`, such as
+/// the channels of an SSH server, until it returns [`None`]
+pub async fn serve_accepted<F, S, A, Fut>(factory: F, config: Config, mut accept: A) -> HubStats
+where
+    F: HandlerFactory,
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    A: FnMut() -> Fut,
+    Fut: Future<Output = Option<(S, SessionInfo)>>,
+{
+    let mut hub = SessionHub::new(factory, config);
+    while let Some((stream, info)) = accept().await {
+        hub.spawn(stream, info);
+    }
+
+    hub.join().await;
+    hub.stats()
+}
@@ -1,22 +1,62 @@
 use std::collections::HashMap;
 
-use crate::protocol::{
-    Attrs, Data, FileAttributes, Handle, Name, OpenFlags, Packet, Status, StatusCode, Version,
+use super::{EndReason, IntoStatus, SessionContext};
+use crate::{
+    extensions::LockFlags,
+    protocol::{
+        Attrs, Data, FileAttributes, Handle, Name, OpenFlags, Packet, RequestId, Status, Version,
+    },
 };
 
-/// Server handler for each client. This is `async_trait`
+/// Server handler for each client. This is `async_trait`.
+///
+/// The trait is dyn-compatible, a [`BoxedHandler`](super::BoxedHandler) can be served
+/// like any other handler. Handlers that are not `Send` implement
+/// [`LocalHandler`](super::LocalHandler) instead
 #[async_trait]
-pub trait Handler: Sized {
-    /// The type must have an `Into<StatusCode>`
-    /// implementation because a response must be sent
-    /// to any request, even if completed by error.
-    type Error: Into<StatusCode>;
+pub trait Handler {
+    /// The type must be convertible into a status because a response must be
+    /// sent to any request, even if completed by error. Any `Into<StatusCode>`
+    /// type is, [`StatusError`](super::StatusError) also carries a message
+    type Error: IntoStatus;
 
     /// Called by the handler when the packet is not implemented
     fn unimplemented(&self) -> Self::Error;
 
+    /// Names of the extensions implemented by the handler, such as
+    /// [`extensions::FSYNC`](crate::extensions::FSYNC). They are added
+    /// to the SSH_FXP_VERSION reply unless [`Handler::init`] already did
+    fn supported_extensions(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Boxes the handler as a [`DynHandler`](super::DynHandler), converting
+    /// its errors into [`StatusError`](super::StatusError)
+    fn into_boxed(self) -> super::BoxedHandler
+    where
+        Self: Sized + Send + 'static,
+    {
+        Box::new(super::StatusHandler(self))
+    }
+
+    /// Called before each request with the current state of the session,
+    /// such as the negotiated version and the extensions sent by the client.
+    /// Copy what is needed, the default implementation ignores it
+    #[allow(unused_variables)]
+    fn set_context(&mut self, context: &SessionContext) {}
+
+    /// Called once when the session ends, after the last reply was sent.
+    ///
+    /// After [`EndReason::Panicked`] the handler may be in an inconsistent state,
+    /// only release its resources here
+    #[allow(unused_variables)]
+    async fn session_ended(&mut self, reason: EndReason) {}
+
     /// The default is to send an SSH_FXP_VERSION response with
     /// the protocol version and ignore any extensions.
+    ///
+    /// Not called again once SSH_FXP_VERSION was sent, a repeated SSH_FXP_INIT
+    /// is handled per [`Config::sequence_action`](super::Config::sequence_action)
     #[allow(unused_variables)]
     async fn init(
         &mut self,
@@ -30,7 +70,7 @@ pub trait Handler: Sized {
     #[allow(unused_variables)]
     async fn open(
         &mut self,
-        id: u32,
+        id: RequestId,
         filename: String,
         pflags: OpenFlags,
         attrs: FileAttributes,
@@ -41,7 +81,7 @@ pub trait Handler: Sized {
     /// Called on SSH_FXP_CLOSE.
     /// The status can be returned as Ok or as Err
     #[allow(unused_variables)]
-    async fn close(&mut self, id: u32, handle: String) -> Result<Status, Self::Error> {
+    async fn close(&mut self, id: RequestId, handle: String) -> Result<Status, Self::Error> {
         Err(self.unimplemented())
     }
 
@@ -49,7 +89,7 @@ pub trait Handler: Sized {
     #[allow(unused_variables)]
     async fn read(
         &mut self,
-        id: u32,
+        id: RequestId,
         handle: String,
         offset: u64,
         len: u32,
@@ -61,7 +101,7 @@ pub trait Handler: Sized {
     #[allow(unused_variables)]
     async fn write(
         &mut self,
-        id: u32,
+        id: RequestId,
         handle: String,
         offset: u64,
         data: Vec<u8>,
@@ -71,13 +111,13 @@ pub trait Handler: Sized {
 
     /// Called on SSH_FXP_LSTAT
     #[allow(unused_variables)]
-    async fn lstat(&mut self, id: u32, path: String) -> Result<Attrs, Self::Error> {
+    async fn lstat(&mut self, id: RequestId, path: String) -> Result<Attrs, Self::Error> {
         Err(self.unimplemented())
     }
 
     /// Called on SSH_FXP_FSTAT
     #[allow(unused_variables)]
-    async fn fstat(&mut self, id: u32, handle: String) -> Result<Attrs, Self::Error> {
+    async fn fstat(&mut self, id: RequestId, handle: String) -> Result<Attrs, Self::Error> {
         Err(self.unimplemented())
     }
 
@@ -85,7 +125,7 @@ pub trait Handler: Sized {
     #[allow(unused_variables)]
     async fn setstat(
         &mut self,
-        id: u32,
+        id: RequestId,
         path: String,
         attrs: FileAttributes,
     ) -> Result<Status, Self::Error> {
@@ -96,7 +136,7 @@ pub trait Handler: Sized {
     #[allow(unused_variables)]
     async fn fsetstat(
         &mut self,
-        id: u32,
+        id: RequestId,
         handle: String,
         attrs: FileAttributes,
     ) -> Result<Status, Self::Error> {
@@ -105,21 +145,21 @@ pub trait Handler: Sized {
 
     /// Called on SSH_FXP_OPENDIR
     #[allow(unused_variables)]
-    async fn opendir(&mut self, id: u32, path: String) -> Result<Handle, Self::Error> {
+    async fn opendir(&mut self, id: RequestId, path: String) -> Result<Handle, Self::Error> {
         Err(self.unimplemented())
     }
 
     /// Called on SSH_FXP_READDIR.
     /// EOF error should be returned at the end of reading the directory
     #[allow(unused_variables)]
-    async fn readdir(&mut self, id: u32, handle: String) -> Result<Name, Self::Error> {
+    async fn readdir(&mut self, id: RequestId, handle: String) -> Result<Name, Self::Error> {
         Err(self.unimplemented())
     }
 
     /// Called on SSH_FXP_REMOVE.
     /// The status can be returned as Ok or as Err
     #[allow(unused_variables)]
-    async fn remove(&mut self, id: u32, filename: String) -> Result<Status, Self::Error> {
+    async fn remove(&mut self, id: RequestId, filename: String) -> Result<Status, Self::Error> {
         Err(self.unimplemented())
     }
 
@@ -127,7 +167,7 @@ pub trait Handler: Sized {
     #[allow(unused_variables)]
     async fn mkdir(
         &mut self,
-        id: u32,
+        id: RequestId,
         path: String,
         attrs: FileAttributes,
     ) -> Result<Status, Self::Error> {
@@ -137,20 +177,20 @@ pub trait Handler: Sized {
     /// Called on SSH_FXP_RMDIR.
     /// The status can be returned as Ok or as Err
     #[allow(unused_variables)]
-    async fn rmdir(&mut self, id: u32, path: String) -> Result<Status, Self::Error> {
+    async fn rmdir(&mut self, id: RequestId, path: String) -> Result<Status, Self::Error> {
         Err(self.unimplemented())
     }
 
     /// Called on SSH_FXP_REALPATH.
     /// Must contain only one name and a dummy attributes
     #[allow(unused_variables)]
-    async fn realpath(&mut self, id: u32, path: String) -> Result<Name, Self::Error> {
+    async fn realpath(&mut self, id: RequestId, path: String) -> Result<Name, Self::Error> {
         Err(self.unimplemented())
     }
 
     /// Called on SSH_FXP_STAT
     #[allow(unused_variables)]
-    async fn stat(&mut self, id: u32, path: String) -> Result<Attrs, Self::Error> {
+    async fn stat(&mut self, id: RequestId, path: String) -> Result<Attrs, Self::Error> {
         Err(self.unimplemented())
     }
 
@@ -159,7 +199,7 @@ pub trait Handler: Sized {
     #[allow(unused_variables)]
     async fn rename(
         &mut self,
-        id: u32,
+        id: RequestId,
         oldpath: String,
         newpath: String,
     ) -> Result<Status, Self::Error> {
@@ -168,30 +208,94 @@ pub trait Handler: Sized {
 
     /// Called on SSH_FXP_READLINK
     #[allow(unused_variables)]
-    async fn readlink(&mut self, id: u32, path: String) -> Result<Name, Self::Error> {
+    async fn readlink(&mut self, id: RequestId, path: String) -> Result<Name, Self::Error> {
         Err(self.unimplemented())
     }
 
     /// Called on SSH_FXP_SYMLINK.
     /// The status can be returned as Ok or as Err
+    ///
+    /// The paths are passed in the order of the specification draft: `linkpath`
+    /// is the link to create and `targetpath` is what it points to. OpenSSH clients
+    /// send them reversed, see [`Config::swap_symlink_paths`](super::Config::swap_symlink_paths)
     #[allow(unused_variables)]
     async fn symlink(
         &mut self,
-        id: u32,
+        id: RequestId,
         linkpath: String,
         targetpath: String,
     ) -> Result<Status, Self::Error> {
         Err(self.unimplemented())
     }
 
-    /// Called on SSH_FXP_EXTENDED.
+    /// Called on `fsync@openssh.com`.
+    /// Should be listed in [`Handler::supported_extensions`]
+    #[allow(unused_variables)]
+    async fn fsync(&mut self, id: RequestId, handle: String) -> Result<Status, Self::Error> {
+        Err(self.unimplemented())
+    }
+
+    /// Called on `hardlink@openssh.com`.
+    /// Should be listed in [`Handler::supported_extensions`]
+    #[allow(unused_variables)]
+    async fn hardlink(
+        &mut self,
+        id: RequestId,
+        oldpath: String,
+        newpath: String,
+    ) -> Result<Status, Self::Error> {
+        Err(self.unimplemented())
+    }
+
+    /// Called on `home-directory` with the user whose home directory is returned
+    /// as a single name, an empty `username` is the user of the session.
+    /// Should be listed in [`Handler::supported_extensions`]
+    #[allow(unused_variables)]
+    async fn home_directory(
+        &mut self,
+        id: RequestId,
+        username: String,
+    ) -> Result<Name, Self::Error> {
+        Err(self.unimplemented())
+    }
+
+    /// Called on `block@russh-sftp.rs` to lock the byte-range of the file.
+    /// A `length` of zero means up to the end of the file.
+    /// Should be listed in [`Handler::supported_extensions`] along with
+    /// [`extensions::UNBLOCK`](crate::extensions::UNBLOCK)
+    #[allow(unused_variables)]
+    async fn block(
+        &mut self,
+        id: RequestId,
+        handle: String,
+        offset: u64,
+        length: u64,
+        lock_flags: LockFlags,
+    ) -> Result<Status, Self::Error> {
+        Err(self.unimplemented())
+    }
+
+    /// Called on `unblock@russh-sftp.rs` to unlock the byte-range
+    /// previously locked with [`Handler::block`]
+    #[allow(unused_variables)]
+    async fn unblock(
+        &mut self,
+        id: RequestId,
+        handle: String,
+        offset: u64,
+        length: u64,
+    ) -> Result<Status, Self::Error> {
+        Err(self.unimplemented())
+    }
+
+    /// Called on SSH_FXP_EXTENDED for extensions without a dedicated method.
     /// The extension can return any packet, so it's not specific.
     /// If the server does not recognize the `request' name
     /// the server must respond with an SSH_FX_OP_UNSUPPORTED error
     #[allow(unused_variables)]
     async fn extended(
         &mut self,
-        id: u32,
+        id: RequestId,
         request: String,
         data: Vec<u8>,
     ) -> Result<Packet, Self::Error> {
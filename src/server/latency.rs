@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+use crate::protocol::RequestId;
+
+/// Passed to [`Config::on_slow_request`](super::Config::on_slow_request) for
+/// requests that took longer than [`Config::slow_request_threshold`](super::Config::slow_request_threshold)
+#[derive(Debug, Clone)]
+pub struct SlowRequestInfo {
+    /// Name of the request packet such as `SSH_FXP_READ`
+    pub packet: &'static str,
+    pub id: RequestId,
+    /// Time spent in the handler
+    pub elapsed: Duration,
+    /// Path or handle of the request if it has one
+    pub target: Option<String>,
+}
+
+/// Accumulated handler latency of a request type, see [`SessionContext::latency`](super::SessionContext::latency)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencyStats {
+    /// Number of handled requests
+    pub count: u64,
+    /// Sum of the time spent in the handler
+    pub total: Duration,
+    /// Longest time spent in the handler
+    pub max: Duration,
+}
+
+impl LatencyStats {
+    /// Average time spent in the handler
+    pub fn mean(&self) -> Duration {
+        match self.count {
+            0 => Duration::ZERO,
+            count => Duration::from_nanos((self.total.as_nanos() / count as u128) as u64),
+        }
+    }
+
+    pub(crate) fn record(&mut self, elapsed: Duration) {
+        self.count += 1;
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+    }
+}
@@ -0,0 +1,345 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use std::collections::{HashMap, VecDeque};
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use super::{responder::StreamedData, validate, Config, ReadDirContext, STREAMING_CHUNK_LEN};
+#[cfg(feature = "compression")]
+use crate::compression::{Compression, MAX_INFLATED_LEN};
+use crate::{
+    de,
+    error::Error,
+    extensions::{self, LimitsExtension},
+    protocol::{File, Full, Name, NameBatcher, Packet, RequestId, StatusCode, SSH_FXP_DATA},
+};
+
+/// Maximum length of a response when neither [`Config::max_response_packet_len`]
+/// nor the `limits@openssh.com` reply of the handler set one
+pub const DEFAULT_MAX_RESPONSE_PACKET_LEN: u32 = 256 * 1024;
+
+/// Type, request id and the length of the data or the count of the entries
+const HEADER_LEN: usize = 9;
+/// Assumed length of an entry of SSH_FXP_NAME before any was sent on the handle
+const DEFAULT_ENTRY_LEN: u64 = 128;
+/// Number of directory handles tracked until closed, the oldest being forgotten
+/// along with its deferred entries if the client never closes it
+const MAX_LISTED_HANDLES: usize = 256;
+
+/// Request being handled, as far as the limiter is concerned
+#[derive(Default)]
+enum Current {
+    Init,
+    OpenDir,
+    ReadDir(String),
+    Limits,
+    #[default]
+    Other,
+}
+
+/// Keeps the responses within the maximum packet length.
+///
+/// Data is truncated, which is a legal short read, and the entries of SSH_FXP_NAME
+/// replied to SSH_FXP_READDIR that do not fit are kept per handle for the next SSH_FXP_READDIR
+#[derive(Default)]
+pub(crate) struct ResponseLimiter {
+    advertised: Option<u32>,
+    pending: HashMap<String, Vec<File>>,
+    /// Count and length of the entries sent for each directory handle
+    listings: HashMap<String, (u64, u64)>,
+    /// Handles of `listings` from the oldest
+    listed_order: VecDeque<String>,
+    current: Current,
+    /// Negotiated in SSH_FXP_VERSION
+    #[cfg(feature = "compression")]
+    compression: Option<Compression>,
+}
+
+impl ResponseLimiter {
+    /// Records the request and returns the remaining entries
+    /// of the directory if the previous reply was split
+    pub fn track(&mut self, request: &Packet) -> Option<Packet> {
+        self.current = match request {
+            Packet::Init(_) => Current::Init,
+            Packet::OpenDir(_) => Current::OpenDir,
+            Packet::ReadDir(readdir) => Current::ReadDir(readdir.handle.to_owned()),
+            Packet::Extended(extended) if extended.request == extensions::LIMITS => Current::Limits,
+            _ => Current::Other,
+        };
+
+        match request {
+            Packet::ReadDir(readdir) => self.pending.remove(&readdir.handle).map(|files| {
+                Packet::Name(Name {
+                    id: readdir.id,
+                    files,
+                })
+            }),
+            Packet::Close(close) => {
+                self.forget(&close.handle);
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Drops the state of the directory handle
+    fn forget(&mut self, handle: &str) {
+        self.pending.remove(handle);
+        if self.listings.remove(handle).is_some() {
+            self.listed_order.retain(|h| h != handle);
+        }
+    }
+
+    /// Starts tracking the directory handle, forgetting the oldest one over the limit
+    fn listing(&mut self, handle: &str) -> &mut (u64, u64) {
+        if !self.listings.contains_key(handle) {
+            if self.listed_order.len() >= MAX_LISTED_HANDLES {
+                if let Some(oldest) = self.listed_order.pop_front() {
+                    if let Some(rest) = self.pending.remove(&oldest) {
+                        warn!(
+                            "{} deferred entries of {oldest} dropped, the handle was not closed",
+                            rest.len()
+                        );
+                    }
+                    self.listings.remove(&oldest);
+                }
+            }
+
+            self.listed_order.push_back(handle.to_owned());
+        }
+
+        self.listings.entry(handle.to_owned()).or_default()
+    }
+
+    /// Unframes the payload of SSH_FXP_WRITE once compression was negotiated.
+    /// Fails with the id of the request and the cause
+    pub fn inflate(&self, request: Packet) -> Result<Packet, (RequestId, String)> {
+        #[cfg(feature = "compression")]
+        let request = match (self.compression, request) {
+            (Some(_), Packet::Write(mut write)) => {
+                write.data = Compression::decompress(write.data, MAX_INFLATED_LEN)
+                    .map_err(|err| (write.id, err))?;
+                Packet::Write(write)
+            }
+            (_, request) => request,
+        };
+
+        Ok(request)
+    }
+
+    /// Estimates how many entries of the directory fit into the reply to SSH_FXP_READDIR
+    pub fn read_dir_hint(&self, request: &Packet, config: &Config) -> Option<ReadDirContext> {
+        let Packet::ReadDir(readdir) = request else {
+            return None;
+        };
+
+        let (count, len) = self
+            .listings
+            .get(&readdir.handle)
+            .copied()
+            .unwrap_or_default();
+        let entry_len = match count {
+            0 => DEFAULT_ENTRY_LEN,
+            count => (len / count).max(1),
+        };
+
+        let available = self.max_len(config).saturating_sub(HEADER_LEN) as u64;
+        Some(ReadDirContext {
+            suggested_max_entries: (available / entry_len).max(1) as usize,
+            previously_returned: count,
+        })
+    }
+
+    /// Bytes added to data payloads by the negotiated compression
+    fn framing_len(&self) -> usize {
+        #[cfg(feature = "compression")]
+        if self.compression.is_some() {
+            return 1;
+        }
+
+        0
+    }
+
+    fn max_len(&self, config: &Config) -> usize {
+        config
+            .max_response_packet_len
+            .or(self.advertised)
+            .unwrap_or(DEFAULT_MAX_RESPONSE_PACKET_LEN) as usize
+    }
+
+    /// Serializes the response to the request last passed to [`ResponseLimiter::track`]
+    pub fn encode(&mut self, response: Packet, config: &Config) -> Result<Bytes, Error> {
+        let current = std::mem::take(&mut self.current);
+        let max_len = self.max_len(config);
+        // the marker of compressed data
+        let data_len = max_len.saturating_sub(HEADER_LEN + self.framing_len());
+
+        let response = match (current, response) {
+            (Current::Limits, Packet::ExtendedReply(reply)) => {
+                if let Ok(limits) =
+                    de::from_bytes::<LimitsExtension>(&mut reply.data.clone().into())
+                {
+                    self.advertised = Some(limits.max_packet_len.min(u32::MAX as u64) as u32);
+                }
+
+                Packet::ExtendedReply(reply)
+            }
+            #[cfg(feature = "compression")]
+            (Current::Init, Packet::Version(version)) => {
+                if version.extensions.contains_key(extensions::COMPRESSION) {
+                    self.compression = config.compression;
+                }
+
+                Packet::Version(version)
+            }
+            (_, Packet::Data(mut data)) => {
+                if data.data.len() > data_len {
+                    debug!(
+                        "data of {} truncated from {} to {data_len} bytes",
+                        data.id,
+                        data.data.len(),
+                    );
+
+                    data.data.truncate(data_len);
+                }
+
+                #[cfg(feature = "compression")]
+                if let Some(compression) = self.compression {
+                    data.data = compression.compress(data.data);
+                }
+
+                Packet::Data(data)
+            }
+            // handlers may reuse the handle of a directory that was not closed
+            (Current::OpenDir, Packet::Handle(handle)) => {
+                self.forget(&handle.handle);
+                Packet::Handle(handle)
+            }
+            (Current::ReadDir(handle), Packet::Name(name)) => self.split(handle, name, max_len),
+            (_, response) => response,
+        };
+
+        let id = validate::response_id(&response).unwrap_or_default();
+        let reason = match Bytes::try_from(response) {
+            // the length itself is not part of the packet
+            Ok(bytes) if bytes.len() - 4 <= max_len => return Ok(bytes),
+            Ok(bytes) => format!("of {} bytes exceeds the limit", bytes.len() - 4),
+            // such as data longer than its u32 prefix
+            Err(err) => format!("cannot be encoded: {err}"),
+        };
+
+        warn!("response to {id} {reason}");
+        Bytes::try_from(Packet::status(
+            id,
+            StatusCode::Failure,
+            "Response exceeds the maximum packet length",
+            "en-US",
+        ))
+    }
+
+    /// Writes the reply SSH_FXP_DATA of the request last passed to [`ResponseLimiter::track`],
+    /// copying the reader of `streamed` in chunks after the header
+    pub async fn write_streamed<S>(
+        &mut self,
+        stream: &mut S,
+        streamed: StreamedData,
+        config: &Config,
+    ) -> Result<(), Error>
+    where
+        S: AsyncWrite + Unpin,
+    {
+        let StreamedData {
+            id,
+            len,
+            mut reader,
+        } = streamed;
+        let data_len = self
+            .max_len(config)
+            .saturating_sub(HEADER_LEN + self.framing_len());
+        let len = (len as usize).min(data_len);
+        let read_error = |err: std::io::Error| Error::IO(format!("streaming data of {id}: {err}"));
+
+        // the compressed length is only known once all is read
+        #[cfg(feature = "compression")]
+        if self.compression.is_some() {
+            let mut data = Vec::with_capacity(len);
+            (&mut reader)
+                .take(len as u64)
+                .read_to_end(&mut data)
+                .await
+                .map_err(read_error)?;
+            let packet = self.encode(Packet::Data(crate::protocol::Data { id, data }), config)?;
+            stream.write_all(&packet).await?;
+            return Ok(());
+        }
+
+        self.current = Current::Other;
+
+        let mut header = BytesMut::with_capacity(HEADER_LEN + 4);
+        header.put_u32((HEADER_LEN + len) as u32);
+        header.put_u8(SSH_FXP_DATA);
+        header.put_u32(id.0);
+        header.put_u32(len as u32);
+        stream.write_all(&header).await?;
+
+        let mut buf = vec![0; len.min(STREAMING_CHUNK_LEN)];
+        let mut remaining = len;
+        while remaining > 0 {
+            let chunk = remaining.min(buf.len());
+            let read = reader.read(&mut buf[..chunk]).await.map_err(read_error)?;
+            if read == 0 {
+                // the length was sent, the stream cannot be realigned
+                return Err(Error::IO(format!(
+                    "streaming data of {id}: reader ended {remaining} of {len} bytes short"
+                )));
+            }
+
+            stream.write_all(&buf[..read]).await?;
+            remaining -= read;
+        }
+
+        Ok(())
+    }
+
+    /// Keeps the entries that do not fit for the next SSH_FXP_READDIR on the handle.
+    /// An entry too long for a reply of its own is dropped
+    fn split(&mut self, handle: String, name: Name, max_len: usize) -> Packet {
+        let total = name.files.len();
+        let mut batcher = NameBatcher::new(max_len);
+        let mut files = name.files.into_iter();
+        let mut rest = Vec::new();
+        let mut dropped = 0;
+
+        for file in files.by_ref() {
+            match batcher.push(file) {
+                Ok(()) => (),
+                Err(Full(file)) if batcher.is_empty() => {
+                    warn!(
+                        "entry {} of {handle} exceeds the maximum packet length, dropped",
+                        file.filename
+                    );
+                    dropped += 1;
+                }
+                Err(Full(file)) => {
+                    rest.push(*file);
+                    break;
+                }
+            }
+        }
+        rest.extend(files);
+
+        let count = total - rest.len() - dropped;
+        let listing = self.listing(&handle);
+        listing.0 += count as u64;
+        listing.1 += (batcher.len() - HEADER_LEN) as u64;
+
+        if !rest.is_empty() {
+            debug!(
+                "{} of {total} entries of {handle} deferred to the next readdir",
+                rest.len(),
+            );
+
+            self.pending.insert(handle, rest);
+        }
+
+        Packet::Name(batcher.take(name.id))
+    }
+}
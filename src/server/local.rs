@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+
+use super::{EndReason, IntoStatus, SessionContext};
+use crate::{
+    extensions::LockFlags,
+    protocol::{
+        Attrs, Data, FileAttributes, Handle, Name, OpenFlags, Packet, RequestId, Status, Version,
+    },
+};
+
+/// Server handler whose futures need not be `Send`, such as handlers holding
+/// an `Rc` across an await. This is `async_trait(?Send)`.
+///
+/// Served on the current task by [`serve_local`](super::serve_local), for example
+/// inside a [`LocalSet`](tokio::task::LocalSet). The methods are called as those
+/// of [`Handler`](super::Handler), see their documentation
+#[async_trait(?Send)]
+pub trait LocalHandler {
+    /// See [`Handler::Error`](super::Handler::Error)
+    type Error: IntoStatus;
+
+    /// Called by the handler when the packet is not implemented
+    fn unimplemented(&self) -> Self::Error;
+
+    /// See [`Handler::supported_extensions`](super::Handler::supported_extensions)
+    fn supported_extensions(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// See [`Handler::set_context`](super::Handler::set_context)
+    #[allow(unused_variables)]
+    fn set_context(&mut self, context: &SessionContext) {}
+
+    /// See [`Handler::session_ended`](super::Handler::session_ended)
+    #[allow(unused_variables)]
+    async fn session_ended(&mut self, reason: EndReason) {}
+
+    /// Called on SSH_FXP_INIT, the default replies with the protocol version
+    #[allow(unused_variables)]
+    async fn init(
+        &mut self,
+        version: u32,
+        extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    /// Called on SSH_FXP_OPEN
+    #[allow(unused_variables)]
+    async fn open(
+        &mut self,
+        id: RequestId,
+        filename: String,
+        pflags: OpenFlags,
+        attrs: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        Err(self.unimplemented())
+    }
+
+    /// Called on SSH_FXP_CLOSE
+    #[allow(unused_variables)]
+    async fn close(&mut self, id: RequestId, handle: String) -> Result<Status, Self::Error> {
+        Err(self.unimplemented())
+    }
+
+    /// Called on SSH_FXP_READ
+    #[allow(unused_variables)]
+    async fn read(
+        &mut self,
+        id: RequestId,
+        handle: String,
+        offset: u64,
+        len: u32,
+    ) -> Result<Data, Self::Error> {
+        Err(self.unimplemented())
+    }
+
+    /// Called on SSH_FXP_WRITE
+    #[allow(unused_variables)]
+    async fn write(
+        &mut self,
+        id: RequestId,
+        handle: String,
+        offset: u64,
+        data: Vec<u8>,
+    ) -> Result<Status, Self::Error> {
+        Err(self.unimplemented())
+    }
+
+    /// Called on SSH_FXP_LSTAT
+    #[allow(unused_variables)]
+    async fn lstat(&mut self, id: RequestId, path: String) -> Result<Attrs, Self::Error> {
+        Err(self.unimplemented())
+    }
+
+    /// Called on SSH_FXP_FSTAT
+    #[allow(unused_variables)]
+    async fn fstat(&mut self, id: RequestId, handle: String) -> Result<Attrs, Self::Error> {
+        Err(self.unimplemented())
+    }
+
+    /// Called on SSH_FXP_SETSTAT
+    #[allow(unused_variables)]
+    async fn setstat(
+        &mut self,
+        id: RequestId,
+        path: String,
+        attrs: FileAttributes,
+    ) -> Result<Status, Self::Error> {
+        Err(self.unimplemented())
+    }
+
+    /// Called on SSH_FXP_FSETSTAT
+    #[allow(unused_variables)]
+    async fn fsetstat(
+        &mut self,
+        id: RequestId,
+        handle: String,
+        attrs: FileAttributes,
+    ) -> Result<Status, Self::Error> {
+        Err(self.unimplemented())
+    }
+
+    /// Called on SSH_FXP_OPENDIR
+    #[allow(unused_variables)]
+    async fn opendir(&mut self, id: RequestId, path: String) -> Result<Handle, Self::Error> {
+        Err(self.unimplemented())
+    }
+
+    /// Called on SSH_FXP_READDIR
+    #[allow(unused_variables)]
+    async fn readdir(&mut self, id: RequestId, handle: String) -> Result<Name, Self::Error> {
+        Err(self.unimplemented())
+    }
+
+    /// Called on SSH_FXP_REMOVE
+    #[allow(unused_variables)]
+    async fn remove(&mut self, id: RequestId, filename: String) -> Result<Status, Self::Error> {
+        Err(self.unimplemented())
+    }
+
+    /// Called on SSH_FXP_MKDIR
+    #[allow(unused_variables)]
+    async fn mkdir(
+        &mut self,
+        id: RequestId,
+        path: String,
+        attrs: FileAttributes,
+    ) -> Result<Status, Self::Error> {
+        Err(self.unimplemented())
+    }
+
+    /// Called on SSH_FXP_RMDIR
+    #[allow(unused_variables)]
+    async fn rmdir(&mut self, id: RequestId, path: String) -> Result<Status, Self::Error> {
+        Err(self.unimplemented())
+    }
+
+    /// Called on SSH_FXP_REALPATH
+    #[allow(unused_variables)]
+    async fn realpath(&mut self, id: RequestId, path: String) -> Result<Name, Self::Error> {
+        Err(self.unimplemented())
+    }
+
+    /// Called on SSH_FXP_STAT
+    #[allow(unused_variables)]
+    async fn stat(&mut self, id: RequestId, path: String) -> Result<Attrs, Self::Error> {
+        Err(self.unimplemented())
+    }
+
+    /// Called on SSH_FXP_RENAME
+    #[allow(unused_variables)]
+    async fn rename(
+        &mut self,
+        id: RequestId,
+        oldpath: String,
+        newpath: String,
+    ) -> Result<Status, Self::Error> {
+        Err(self.unimplemented())
+    }
+
+    /// Called on SSH_FXP_READLINK
+    #[allow(unused_variables)]
+    async fn readlink(&mut self, id: RequestId, path: String) -> Result<Name, Self::Error> {
+        Err(self.unimplemented())
+    }
+
+    /// Called on SSH_FXP_SYMLINK, see [`Handler::symlink`](super::Handler::symlink)
+    #[allow(unused_variables)]
+    async fn symlink(
+        &mut self,
+        id: RequestId,
+        linkpath: String,
+        targetpath: String,
+    ) -> Result<Status, Self::Error> {
+        Err(self.unimplemented())
+    }
+
+    /// Called on `fsync@openssh.com`
+    #[allow(unused_variables)]
+    async fn fsync(&mut self, id: RequestId, handle: String) -> Result<Status, Self::Error> {
+        Err(self.unimplemented())
+    }
+
+    /// Called on `hardlink@openssh.com`
+    #[allow(unused_variables)]
+    async fn hardlink(
+        &mut self,
+        id: RequestId,
+        oldpath: String,
+        newpath: String,
+    ) -> Result<Status, Self::Error> {
+        Err(self.unimplemented())
+    }
+
+    /// Called on `home-directory`
+    #[allow(unused_variables)]
+    async fn home_directory(
+        &mut self,
+        id: RequestId,
+        username: String,
+    ) -> Result<Name, Self::Error> {
+        Err(self.unimplemented())
+    }
+
+    /// Called on `block@russh-sftp.rs`
+    #[allow(unused_variables)]
+    async fn block(
+        &mut self,
+        id: RequestId,
+        handle: String,
+        offset: u64,
+        length: u64,
+        lock_flags: LockFlags,
+    ) -> Result<Status, Self::Error> {
+        Err(self.unimplemented())
+    }
+
+    /// Called on `unblock@russh-sftp.rs`
+    #[allow(unused_variables)]
+    async fn unblock(
+        &mut self,
+        id: RequestId,
+        handle: String,
+        offset: u64,
+        length: u64,
+    ) -> Result<Status, Self::Error> {
+        Err(self.unimplemented())
+    }
+
+    /// Called on SSH_FXP_EXTENDED for extensions without a dedicated method
+    #[allow(unused_variables)]
+    async fn extended(
+        &mut self,
+        id: RequestId,
+        request: String,
+        data: Vec<u8>,
+    ) -> Result<Packet, Self::Error> {
+        Err(self.unimplemented())
+    }
+}
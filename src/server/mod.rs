@@ -1,90 +1,926 @@
+mod audit;
+mod boxed;
+mod context;
+mod dir;
+mod dispatch;
+mod factory;
 mod handler;
+mod latency;
+mod limit;
+mod local;
+mod panic;
+pub mod policy;
+pub mod prelude;
+mod responder;
+mod status;
+#[cfg(feature = "testkit")]
+pub mod testkit;
+mod throttle;
+pub mod validate;
 
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
 
-pub use self::handler::Handler;
+pub use self::{
+    audit::{AuditEvent, AuditLog, AuditOperation, AuditSink, AuditStats, DEFAULT_AUDIT_CAPACITY},
+    boxed::{BoxedHandler, DynHandler, StatusHandler},
+    context::SessionContext,
+    dir::{DirBatcher, ReadDirContext},
+    factory::{serve_accepted, serve_many, HandlerFactory, HubStats, SessionHub, SessionInfo},
+    handler::Handler,
+    latency::{LatencyStats, SlowRequestInfo},
+    limit::DEFAULT_MAX_RESPONSE_PACKET_LEN,
+    local::LocalHandler,
+    responder::{Responder, STREAMING_CHUNK_LEN},
+    status::{IntoStatus, StatusError},
+};
+
+/// The [`Handler`] of the server, named so that it can be imported along with the
+/// one of the client
+#[cfg_attr(
+    feature = "client",
+    doc = r#"
+```
+use russh_sftp::{client::ClientHandler, protocol::StatusCode, server::ServerHandler};
+
+struct Client;
+
+impl ClientHandler for Client {
+    type Error = russh_sftp::client::error::Error;
+}
+
+struct Server;
+
+impl ServerHandler for Server {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+}
+```
+"#
+)]
+pub use self::handler::Handler as ServerHandler;
+
+#[cfg(feature = "audit-json")]
+pub use self::audit::JsonLinesSink;
 
+use self::{
+    audit::SessionAudit,
+    dispatch::{Dispatch, Local},
+    limit::ResponseLimiter,
+    validate::RequestInfo,
+};
+
+#[cfg(feature = "compression")]
+use crate::compression::Compression;
 use crate::{
+    de,
     error::Error,
-    protocol::{Packet, StatusCode},
-    utils::read_packet,
+    extensions::{
+        self, BlockExtension, FsyncExtension, HardlinkExtension, HomeDirectoryExtension,
+        UnblockExtension,
+    },
+    framing::read_checked_packet,
+    protocol::{Extended, Init, Packet, RequestId, Status, StatusCode, Version, MAX_HANDLE_LEN},
 };
 
 macro_rules! into_wrap {
-    ($id:expr, $handler:expr, $var:ident; $($arg:ident),*) => {
-        match $handler.$var($($var.$arg),*).await {
-            Err(err) => Packet::error($id, err.into()),
+    ($replies:expr, $id:expr, $handler:expr, $var:ident; $($arg:ident),*) => {
+        into_packet!($replies, $id, $handler.$var($($var.$arg),*))
+    };
+}
+
+macro_rules! into_packet {
+    ($replies:expr, $id:expr, $future:expr) => {
+        match $future.await {
+            Err(err) => $replies.status($id, err.into_status()),
             Ok(packet) => packet.into(),
         }
     };
 }
 
-async fn process_request<H>(packet: Packet, handler: &mut H) -> Packet
+/// Formats the message and the language tag of a status
+/// converted from [`Handler::Error`]
+pub type StatusFormatter =
+    Arc<dyn Fn(StatusCode, &SessionContext) -> (String, String) + Send + Sync>;
+
+/// Called with the requests that exceeded [`Config::slow_request_threshold`]
+pub type SlowRequestCallback = Arc<dyn Fn(SlowRequestInfo) + Send + Sync>;
+
+/// Creates the error replies of a request
+struct Replies<'a> {
+    config: &'a Config,
+    context: &'a SessionContext,
+}
+
+impl Replies<'_> {
+    fn error(&self, id: RequestId, status_code: StatusCode) -> Packet {
+        self.status(id, status_code.into())
+    }
+
+    /// Keeps the message of the handler, otherwise formats one for the code
+    fn status(&self, id: RequestId, error: StatusError) -> Packet {
+        let status = error.into_reply(id, |status_code| match &self.config.status_formatter {
+            Some(formatter) => {
+                let (error_message, language_tag) = formatter(status_code, self.context);
+                Status {
+                    id,
+                    status_code,
+                    error_message,
+                    language_tag,
+                }
+            }
+            None => Status::new(id, status_code),
+        });
+
+        Packet::Status(status)
+    }
+}
+
+/// Version of the extension to advertise in SSH_FXP_VERSION
+fn extension_version(name: &str) -> &'static str {
+    match name {
+        extensions::STATVFS => "2",
+        _ => "1",
+    }
+}
+
+/// Routes extensions with dedicated [`Handler`] methods and falls back to [`Handler::extended`]
+async fn process_extended<H>(extended: Extended, handler: &mut H, replies: &Replies<'_>) -> Packet
 where
-    H: Handler + Send,
+    H: Dispatch,
+{
+    let id = extended.id;
+
+    match extended.request.as_str() {
+        extensions::FSYNC => match de::from_bytes::<FsyncExtension>(&mut extended.data.into()) {
+            Ok(fsync) => into_packet!(replies, id, handler.fsync(id, fsync.handle)),
+            Err(_) => replies.error(id, StatusCode::BadMessage),
+        },
+        extensions::HARDLINK => {
+            match de::from_bytes::<HardlinkExtension>(&mut extended.data.into()) {
+                Ok(link) => into_packet!(
+                    replies,
+                    id,
+                    handler.hardlink(id, link.oldpath, link.newpath)
+                ),
+                Err(_) => replies.error(id, StatusCode::BadMessage),
+            }
+        }
+        extensions::HOME_DIRECTORY => {
+            match de::from_bytes::<HomeDirectoryExtension>(&mut extended.data.into()) {
+                Ok(home) => into_packet!(replies, id, handler.home_directory(id, home.username)),
+                Err(_) => replies.error(id, StatusCode::BadMessage),
+            }
+        }
+        extensions::BLOCK => match de::from_bytes::<BlockExtension>(&mut extended.data.into()) {
+            Ok(block) => into_packet!(
+                replies,
+                id,
+                handler.block(
+                    id,
+                    block.handle,
+                    block.offset,
+                    block.length,
+                    block.lock_flags
+                )
+            ),
+            Err(_) => replies.error(id, StatusCode::BadMessage),
+        },
+        extensions::UNBLOCK => {
+            match de::from_bytes::<UnblockExtension>(&mut extended.data.into()) {
+                Ok(unblock) => into_packet!(
+                    replies,
+                    id,
+                    handler.unblock(id, unblock.handle, unblock.offset, unblock.length)
+                ),
+                Err(_) => replies.error(id, StatusCode::BadMessage),
+            }
+        }
+        _ => into_wrap!(replies, id, handler, extended; id, request, data),
+    }
+}
+
+/// Server options applied to each session
+#[derive(Clone, Default)]
+pub struct Config {
+    /// Swap the paths of `SSH_FXP_SYMLINK` before calling [`Handler::symlink`].
+    ///
+    /// OpenSSH clients send `targetpath` first contrary to the specification draft.
+    /// Enable it so that `linkpath` and `targetpath` have their intended meaning
+    /// for such clients. Default: `false`
+    pub swap_symlink_paths: bool,
+    /// Check the replies of the handler against the request and the specification
+    /// before sending them. Intended for the development of handlers. Default: `false`
+    pub validate_responses: bool,
+    /// What to do with a reply that failed the validation.
+    /// Default: [`ValidationAction::Warn`]
+    pub validation_action: ValidationAction,
+    /// Formats the message and the language tag of the statuses converted from
+    /// errors of the handler. Default: [`None`], the name of the code in `en-US`
+    pub status_formatter: Option<StatusFormatter>,
+    /// Language of the client if known outside of the protocol, for example from
+    /// the `LANG` variable of the SSH session. See [`SessionContext::language`].
+    /// Default: [`None`]
+    pub language: Option<String>,
+    /// What to do with a second SSH_FXP_INIT or a request before SSH_FXP_INIT.
+    /// [`Handler`] is not called for such packets. Default: [`SequenceAction::Reject`]
+    pub sequence_action: SequenceAction,
+    /// Reply with a Failure status to SSH_FXP_INIT when the handler advertises an
+    /// extension with another value than the one advertised for it by the crate,
+    /// from [`Handler::supported_extensions`] or this configuration. Otherwise the
+    /// value of the handler is kept with a warning. Default: `false`
+    pub strict_extensions: bool,
+    /// Reply with a BadMessage status naming the bits to the requests with open flags
+    /// or attribute flags not defined by version 3, such as the access disposition
+    /// of version 5. Otherwise the bits are ignored and [`Handler`] can inspect them
+    /// with [`OpenFlags::unknown_bits`](crate::protocol::OpenFlags::unknown_bits) and
+    /// [`FileAttributes::unknown_flags`](crate::protocol::FileAttributes::unknown_flags).
+    /// Default: `false`
+    pub strict_flags: bool,
+    /// Maximum length of a response packet. Data is truncated, the entries of
+    /// SSH_FXP_NAME replied to SSH_FXP_READDIR are split across several requests, an
+    /// entry too long for any reply being dropped, and other responses become a Failure
+    /// status. Default: [`None`], the `max_packet_len`
+    /// replied by the handler to `limits@openssh.com` or [`DEFAULT_MAX_RESPONSE_PACKET_LEN`]
+    pub max_response_packet_len: Option<u32>,
+    /// What to do when a method of the handler panics. Default: [`PanicAction::Contain`]
+    pub panic_action: PanicAction,
+    /// What to do with a handle of the handler longer than [`MAX_HANDLE_LEN`],
+    /// which some clients truncate or reject. Default: [`HandleAction::Reject`]
+    pub oversized_handle_action: HandleAction,
+    /// What to do with a request on a handle after it was successfully closed, such as
+    /// an SSH_FXP_READ sent by a client racing its own SSH_FXP_CLOSE. Such requests are
+    /// counted in [`SessionContext::late_requests`].
+    /// Default: [`ClosedHandleAction::Reject`] with a Failure status
+    pub closed_handle_action: ClosedHandleAction,
+    /// Time after which a request is reported as slow to [`Config::on_slow_request`].
+    /// Default: [`None`], requests are not reported
+    pub slow_request_threshold: Option<Duration>,
+    /// Receives the slow requests. Default: [`None`], a warning is logged
+    pub on_slow_request: Option<SlowRequestCallback>,
+    /// Receives the mutating requests and their outcome. Default: [`None`]
+    pub audit: Option<AuditLog>,
+    /// Identity of the session recorded in [`AuditEvent::identity`], such as the
+    /// SSH user and the address of the client. Default: [`None`]
+    pub session_identity: Option<String>,
+    /// Maximum length of a request packet. A longer packet, or one of an unknown type,
+    /// means that the stream lost the boundaries of the packets and ends the session
+    /// with [`EndReason::Framing`]. Default: [`None`], [`DEFAULT_MAX_PACKET_LEN`]
+    pub max_request_packet_len: Option<u32>,
+    /// Maximum number of requests of a session handled per second, with bursts of up
+    /// to a second of requests. The requests above it are delayed rather than rejected,
+    /// see [`SessionContext::throttled_requests`]. Default: [`None`], not limited
+    pub max_requests_per_second: Option<u32>,
+    /// Compression of data payloads accepted from clients of this crate that offer it.
+    /// Default: [`None`]
+    #[cfg(feature = "compression")]
+    pub compression: Option<Compression>,
+    /// Record the paths of the requests in their spans, which may be sensitive.
+    /// The handles are always recorded. Default: `false`
+    #[cfg(feature = "tracing")]
+    pub record_paths: bool,
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("Config");
+        debug
+            .field("swap_symlink_paths", &self.swap_symlink_paths)
+            .field("validate_responses", &self.validate_responses)
+            .field("validation_action", &self.validation_action)
+            .field("status_formatter", &self.status_formatter.is_some())
+            .field("language", &self.language)
+            .field("sequence_action", &self.sequence_action)
+            .field("strict_extensions", &self.strict_extensions)
+            .field("strict_flags", &self.strict_flags)
+            .field("max_response_packet_len", &self.max_response_packet_len)
+            .field("panic_action", &self.panic_action)
+            .field("oversized_handle_action", &self.oversized_handle_action)
+            .field("closed_handle_action", &self.closed_handle_action)
+            .field("slow_request_threshold", &self.slow_request_threshold)
+            .field("on_slow_request", &self.on_slow_request.is_some())
+            .field("audit", &self.audit)
+            .field("session_identity", &self.session_identity)
+            .field("max_request_packet_len", &self.max_request_packet_len)
+            .field("max_requests_per_second", &self.max_requests_per_second);
+
+        #[cfg(feature = "compression")]
+        debug.field("compression", &self.compression);
+        #[cfg(feature = "tracing")]
+        debug.field("record_paths", &self.record_paths);
+
+        debug.finish()
+    }
+}
+
+/// Handling of invalid replies when [`Config::validate_responses`] is enabled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ValidationAction {
+    /// Log a warning and send the reply as is
+    #[default]
+    Warn,
+    /// Log a warning and send a Failure status with the validation message instead
+    Replace,
+}
+
+/// Handling of the panics of the methods of the [`Handler`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PanicAction {
+    /// Reply with a Failure status to the request, call [`Handler::session_ended`]
+    /// with [`EndReason::Panicked`] and end the session
+    #[default]
+    Contain,
+    /// Let the panic unwind the task serving the session, for fail-fast processes
+    Propagate,
+}
+
+/// Cause of the end of a session, passed to [`Handler::session_ended`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EndReason {
+    /// The client closed the stream
+    Closed,
+    /// The stream failed with the message
+    Failed(String),
+    /// The SSH_FXP_INIT exchange was violated with [`SequenceAction::Disconnect`]
+    SequenceViolated,
+    /// The client sent a packet that could not be delimited, with the message,
+    /// see [`Config::max_request_packet_len`]
+    Framing(String),
+    /// A method of the handler panicked with the message, see [`PanicAction::Contain`]
+    Panicked(String),
+}
+
+/// Handling of the handles longer than [`MAX_HANDLE_LEN`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HandleAction {
+    /// Log a warning and send a Failure status instead of the handle
+    #[default]
+    Reject,
+    /// Log a warning and send the first [`MAX_HANDLE_LEN`] bytes of the handle,
+    /// cut at a character boundary. The handler must accept the truncated handle
+    Truncate,
+}
+
+/// Handling of the requests on a closed handle, see [`Config::closed_handle_action`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClosedHandleAction {
+    /// Call the [`Handler`] as for any other handle
+    PassThrough,
+    /// Reply with a status of the code without calling the [`Handler`]
+    Reject(StatusCode),
+    /// Same as [`ClosedHandleAction::Reject`] with a Failure status, but reply
+    /// with an Eof status to SSH_FXP_READ and SSH_FXP_READDIR
+    RejectReadEof,
+}
+
+impl Default for ClosedHandleAction {
+    fn default() -> Self {
+        Self::Reject(StatusCode::Failure)
+    }
+}
+
+/// Handling of packets violating the SSH_FXP_INIT exchange
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SequenceAction {
+    /// Reply with a BadMessage status and keep the session
+    #[default]
+    Reject,
+    /// Reply with a BadMessage status and end the session
+    Disconnect,
+}
+
+/// Checks that SSH_FXP_INIT is the first packet of the session and is only sent once
+fn check_sequence(request: &Packet, context: &SessionContext) -> Result<(), String> {
+    match (request, context.version()) {
+        (Packet::Init(_), Some(_)) => Err("duplicate SSH_FXP_INIT".to_owned()),
+        (Packet::Init(_), None) => Ok(()),
+        (request, None) => Err(format!("{} before SSH_FXP_INIT", request.name())),
+        (_, Some(_)) => Ok(()),
+    }
+}
+
+/// Names the bits of the flags of the request not defined by version 3
+/// when [`Config::strict_flags`] is enabled
+fn check_flags(request: &Packet, config: &Config) -> Result<(), String> {
+    if !config.strict_flags {
+        return Ok(());
+    }
+
+    let (pflags, attrs) = match request {
+        Packet::Open(open) => (open.pflags.unknown_bits(), &open.attrs),
+        Packet::SetStat(setstat) => (0, &setstat.attrs),
+        Packet::FSetStat(fsetstat) => (0, &fsetstat.attrs),
+        Packet::MkDir(mkdir) => (0, &mkdir.attrs),
+        _ => return Ok(()),
+    };
+
+    if pflags != 0 {
+        return Err(format!("unknown open flags {pflags:#010x}"));
+    }
+
+    match attrs.unknown_flags {
+        0 => Ok(()),
+        flags => Err(format!("unknown attribute flags {flags:#010x}")),
+    }
+}
+
+/// Replies to a request on a closed handle per [`Config::closed_handle_action`],
+/// [`None`] if the [`Handler`] is called
+fn check_closed(request: &Packet, config: &Config, context: &mut SessionContext) -> Option<Packet> {
+    if !context.on_late_request(request) {
+        return None;
+    }
+
+    let (name, id) = (request.name(), request.get_request_id());
+    let status_code = match config.closed_handle_action {
+        ClosedHandleAction::PassThrough => {
+            debug!("{name} {id} on a closed handle");
+            return None;
+        }
+        ClosedHandleAction::Reject(status_code) => status_code,
+        ClosedHandleAction::RejectReadEof => match request {
+            Packet::Read(_) | Packet::ReadDir(_) => StatusCode::Eof,
+            _ => StatusCode::Failure,
+        },
+    };
+
+    debug!("{name} {id} rejected on a closed handle");
+    let error = StatusError::new(status_code, "handle already closed");
+    Some(Replies { config, context }.status(id, error))
+}
+
+/// Enforces [`MAX_HANDLE_LEN`] on a Handle reply per `action`
+fn check_handle(response: &mut Packet, action: HandleAction) {
+    let Packet::Handle(handle) = response else {
+        return;
+    };
+
+    let len = handle.handle.len();
+    if len <= MAX_HANDLE_LEN {
+        return;
+    }
+
+    let message = format!("handle of {len} bytes is longer than {MAX_HANDLE_LEN}");
+    warn!("{message}");
+
+    match action {
+        HandleAction::Reject => {
+            *response = Packet::status(handle.id, StatusCode::Failure, &message, "en-US");
+        }
+        HandleAction::Truncate => {
+            let end = (0..=MAX_HANDLE_LEN)
+                .rev()
+                .find(|end| handle.handle.is_char_boundary(*end))
+                .unwrap_or_default();
+            handle.handle.truncate(end);
+        }
+    }
+}
+
+async fn process_request<H>(packet: Packet, handler: &mut H, replies: &Replies<'_>) -> Packet
+where
+    H: Dispatch,
 {
+    let config = replies.config;
     let id = packet.get_request_id();
 
     match packet {
-        Packet::Init(init) => into_wrap!(id, handler, init; version, extensions),
-        Packet::Open(open) => into_wrap!(id, handler, open; id, filename, pflags, attrs),
-        Packet::Close(close) => into_wrap!(id, handler, close; id, handle),
-        Packet::Read(read) => into_wrap!(id, handler, read; id, handle, offset, len),
-        Packet::Write(write) => into_wrap!(id, handler, write; id, handle, offset, data),
-        Packet::Lstat(lstat) => into_wrap!(id, handler, lstat; id, path),
-        Packet::Fstat(fstat) => into_wrap!(id, handler, fstat; id, handle),
-        Packet::SetStat(setstat) => into_wrap!(id, handler, setstat; id, path, attrs),
-        Packet::FSetStat(fsetstat) => into_wrap!(id, handler, fsetstat; id, handle, attrs),
-        Packet::OpenDir(opendir) => into_wrap!(id, handler, opendir; id, path),
-        Packet::ReadDir(readdir) => into_wrap!(id, handler, readdir; id, handle),
-        Packet::Remove(remove) => into_wrap!(id, handler, remove; id, filename),
-        Packet::MkDir(mkdir) => into_wrap!(id, handler, mkdir; id, path, attrs),
-        Packet::RmDir(rmdir) => into_wrap!(id, handler, rmdir; id, path),
-        Packet::RealPath(realpath) => into_wrap!(id, handler, realpath; id, path),
-        Packet::Stat(stat) => into_wrap!(id, handler, stat; id, path),
-        Packet::Rename(rename) => into_wrap!(id, handler, rename; id, oldpath, newpath),
-        Packet::ReadLink(readlink) => into_wrap!(id, handler, readlink; id, path),
-        Packet::Symlink(symlink) => into_wrap!(id, handler, symlink; id, linkpath, targetpath),
-        Packet::Extended(extended) => into_wrap!(id, handler, extended; id, request, data),
-        _ => Packet::error(0, StatusCode::BadMessage),
-    }
-}
-
-async fn process_handler<H, S>(stream: &mut S, handler: &mut H) -> Result<(), Error>
+        Packet::Symlink(mut symlink) if config.swap_symlink_paths => {
+            std::mem::swap(&mut symlink.linkpath, &mut symlink.targetpath);
+            into_wrap!(replies, id, handler, symlink; id, linkpath, targetpath)
+        }
+        Packet::Init(init) => {
+            #[cfg(feature = "compression")]
+            let compression = config.compression.is_some()
+                && init
+                    .extensions
+                    .get(extensions::COMPRESSION)
+                    .is_some_and(|value| Compression::accepts(value));
+
+            let client_version = init.version;
+            match handler.init(init.version, init.extensions).await {
+                Ok(reply) => {
+                    // the extensions of the crate first, so that the ones of the handler win
+                    let mut version = Version {
+                        version: reply.version,
+                        extensions: handler
+                            .supported_extensions()
+                            .iter()
+                            .map(|name| (name.to_string(), extension_version(name).to_owned()))
+                            .collect(),
+                    };
+                    let merged =
+                        version.merge_extensions(reply.extensions, config.strict_extensions);
+
+                    // only advertised when it is actually applied to the session
+                    #[cfg(feature = "compression")]
+                    let merged = merged.and_then(|_| match compression {
+                        true => version.merge_extensions(
+                            [(extensions::COMPRESSION.to_owned(), Compression::accepted())],
+                            config.strict_extensions,
+                        ),
+                        false => {
+                            version.extensions.remove(extensions::COMPRESSION);
+                            Ok(())
+                        }
+                    });
+
+                    if let Err(err) = merged {
+                        warn!("SSH_FXP_VERSION rejected: {}", err);
+                        return replies.error(id, StatusCode::Failure);
+                    }
+
+                    // the server replies with the version of the client if it is lower
+                    if version.version > client_version {
+                        warn!(
+                            "handler replied version {} to a client of version {}, downgraded",
+                            version.version, client_version
+                        );
+                        version.version = client_version;
+                    }
+
+                    version.into()
+                }
+                Err(err) => replies.status(id, err.into_status()),
+            }
+        }
+        Packet::Open(open) => into_wrap!(replies, id, handler, open; id, filename, pflags, attrs),
+        Packet::Close(close) => into_wrap!(replies, id, handler, close; id, handle),
+        Packet::Read(read) => into_wrap!(replies, id, handler, read; id, handle, offset, len),
+        Packet::Write(write) => into_wrap!(replies, id, handler, write; id, handle, offset, data),
+        Packet::Lstat(lstat) => into_wrap!(replies, id, handler, lstat; id, path),
+        Packet::Fstat(fstat) => into_wrap!(replies, id, handler, fstat; id, handle),
+        Packet::SetStat(setstat) => into_wrap!(replies, id, handler, setstat; id, path, attrs),
+        Packet::FSetStat(fsetstat) => into_wrap!(replies, id, handler, fsetstat; id, handle, attrs),
+        Packet::OpenDir(opendir) => into_wrap!(replies, id, handler, opendir; id, path),
+        Packet::ReadDir(readdir) => into_wrap!(replies, id, handler, readdir; id, handle),
+        Packet::Remove(remove) => into_wrap!(replies, id, handler, remove; id, filename),
+        Packet::MkDir(mkdir) => into_wrap!(replies, id, handler, mkdir; id, path, attrs),
+        Packet::RmDir(rmdir) => into_wrap!(replies, id, handler, rmdir; id, path),
+        Packet::RealPath(realpath) => into_wrap!(replies, id, handler, realpath; id, path),
+        Packet::Stat(stat) => into_wrap!(replies, id, handler, stat; id, path),
+        Packet::Rename(rename) => into_wrap!(replies, id, handler, rename; id, oldpath, newpath),
+        Packet::ReadLink(readlink) => into_wrap!(replies, id, handler, readlink; id, path),
+        Packet::Symlink(symlink) => {
+            into_wrap!(replies, id, handler, symlink; id, linkpath, targetpath)
+        }
+        Packet::Extended(extended) => process_extended(extended, handler, replies).await,
+        _ => replies.error(RequestId::default(), StatusCode::BadMessage),
+    }
+}
+
+/// Handles a request decoded from `frame`, returns the response and
+/// the message of the panic of the handler if any
+async fn handle_request<H>(
+    request: Packet,
+    frame: &Bytes,
+    handler: &mut H,
+    config: &Config,
+    context: &mut SessionContext,
+    limiter: &mut ResponseLimiter,
+    audit: Option<&mut SessionAudit>,
+) -> (Packet, Option<String>)
 where
-    H: Handler + Send,
+    H: Dispatch,
+{
+    context.on_request(&request);
+    let late = check_closed(&request, config, context);
+    context.set_read_dir(limiter.read_dir_hint(&request, config));
+    handler.set_context(context);
+
+    let closed = match &request {
+        Packet::Close(close) => Some(close.handle.clone()),
+        _ => None,
+    };
+    let (name, id) = (request.name(), request.get_request_id());
+    let pending = audit.as_ref().map(|audit| audit.before(&request));
+    let started = Instant::now();
+
+    #[cfg(feature = "tracing")]
+    let span = crate::spans::request("server", &request, config.record_paths);
+
+    let replies = Replies { config, context };
+    let mut panicked = None;
+    let mut response = match late.or_else(|| limiter.track(&request)) {
+        Some(response) => response,
+        None => {
+            let processing = process_request(request, handler, &replies);
+            #[cfg(feature = "tracing")]
+            let processing = tracing::Instrument::instrument(processing, span.clone());
+
+            if config.panic_action == PanicAction::Propagate {
+                processing.await
+            } else {
+                match panic::catch_unwind(processing).await {
+                    Ok(response) => response,
+                    Err(message) => {
+                        error!("handler panicked on {name} {id}: {message}");
+                        let response = replies.error(id, StatusCode::Failure);
+                        panicked = Some(message);
+                        response
+                    }
+                }
+            }
+        }
+    };
+
+    check_handle(&mut response, config.oversized_handle_action);
+
+    let elapsed = started.elapsed();
+    context.on_handled(name, elapsed);
+
+    if config.slow_request_threshold.is_some_and(|t| elapsed >= t) {
+        // decoded again, the request was consumed by the handler
+        let target = Packet::try_from(&mut frame.clone())
+            .ok()
+            .and_then(|request| request.target().map(str::to_owned));
+        let info = SlowRequestInfo {
+            packet: name,
+            id,
+            elapsed,
+            target,
+        };
+
+        match &config.on_slow_request {
+            Some(callback) => callback(info),
+            None => warn!(
+                "slow {name} {id} on {}: {elapsed:?}",
+                info.target.as_deref().unwrap_or("-")
+            ),
+        }
+    }
+
+    // the reply is routed to its request by the id, whatever the handler returned
+    if let Some(replied) = validate::set_response_id(&mut response, id) {
+        if replied != id {
+            warn!("reply to {name} {id} had id {replied}, replaced");
+        }
+    }
+
+    if config.validate_responses {
+        if let Err(err) = validate::validate(RequestInfo { name, id }, &response) {
+            warn!("invalid reply to {name} {id}: {err}");
+
+            if config.validation_action == ValidationAction::Replace {
+                response = Packet::status(id, StatusCode::Failure, &err, "en-US");
+            }
+        }
+    }
+
+    match &response {
+        Packet::Status(status) => {
+            trace!("{name} {id} handled in {elapsed:?}: {}", status.status_code)
+        }
+        response => trace!("{name} {id} handled in {elapsed:?}: {}", response.name()),
+    }
+
+    #[cfg(feature = "tracing")]
+    crate::spans::reply(&span, &response);
+
+    if let (Some(audit), Some(pending)) = (audit, pending) {
+        audit.after(id, pending, &response);
+    }
+
+    context.on_response(closed, &response);
+    (response, panicked)
+}
+
+/// Handles one packet of the stream. Returns the reason to end the session if any
+async fn process_handler<H, S>(
+    stream: &mut S,
+    handler: &mut H,
+    config: &Config,
+    context: &mut SessionContext,
+    limiter: &mut ResponseLimiter,
+    audit: Option<&mut SessionAudit>,
+    buf: &mut BytesMut,
+) -> Result<Option<EndReason>, Error>
+where
+    H: Dispatch,
     S: AsyncRead + AsyncWrite + Unpin,
 {
-    let mut bytes = read_packet(stream).await?;
+    let max_len = context.max_request_packet_len();
+    let mut bytes = read_checked_packet(stream, buf, max_len).await?;
+    let mut ended = None;
+    context.throttle().await;
 
+    let frame = bytes.clone();
     let response = match Packet::try_from(&mut bytes) {
-        Ok(request) => process_request(request, handler).await,
-        Err(_) => Packet::error(0, StatusCode::BadMessage),
+        Ok(request) => match check_sequence(&request, context) {
+            Ok(()) => match check_flags(&request, config) {
+                Err(err) => {
+                    warn!("{err}");
+                    let error = StatusError::new(StatusCode::BadMessage, err);
+                    Replies { config, context }.status(request.get_request_id(), error)
+                }
+                Ok(()) => match limiter.inflate(request) {
+                    Ok(request) => {
+                        let (response, panicked) = handle_request(
+                            request, &frame, handler, config, context, limiter, audit,
+                        )
+                        .await;
+                        ended = panicked.map(EndReason::Panicked);
+                        response
+                    }
+                    Err((id, err)) => {
+                        warn!("{err}");
+                        Replies { config, context }.error(id, StatusCode::BadMessage)
+                    }
+                },
+            },
+            Err(err) => {
+                warn!("{err}");
+                if config.sequence_action == SequenceAction::Disconnect {
+                    ended = Some(EndReason::SequenceViolated);
+                }
+
+                Replies { config, context }.error(request.get_request_id(), StatusCode::BadMessage)
+            }
+        },
+        Err(_) => Replies { config, context }.error(RequestId::default(), StatusCode::BadMessage),
     };
 
-    let packet = Bytes::try_from(response)?;
-    stream.write_all(&packet).await?;
+    // the data registered by the handler replaces its reply
+    let streamed = context.responder().take();
+    match streamed.filter(|s| matches!(&response, Packet::Data(data) if data.id == s.id)) {
+        Some(streamed) => limiter.write_streamed(stream, streamed, config).await?,
+        None => {
+            let packet = limiter.encode(response, config)?;
+            stream.write_all(&packet).await?;
+        }
+    }
     stream.flush().await?;
 
-    Ok(())
+    Ok(ended)
 }
 
 /// Run processing stream as SFTP
-pub async fn run<S, H>(mut stream: S, mut handler: H)
+pub async fn run<S, H>(stream: S, handler: H)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    H: Handler + Send + 'static,
+{
+    run_with_config(stream, handler, Config::default()).await
+}
+
+/// Run processing stream as SFTP with the specified [`Config`]
+pub async fn run_with_config<S, H>(stream: S, handler: H, config: Config)
 where
     S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
     H: Handler + Send + 'static,
 {
     tokio::spawn(async move {
-        loop {
-            match process_handler(&mut stream, &mut handler).await {
-                Err(Error::UnexpectedEof) => break,
-                Err(err) => warn!("{}", err),
-                Ok(_) => (),
-            }
+        if let Err(err) = serve(stream, handler, config).await {
+            warn!("{}", err);
         }
-
-        debug!("sftp stream ended");
     });
 }
+
+/// Processes the stream as SFTP on the current task without spawning.
+///
+/// Completes when the client closes the stream or with an error if the stream fails
+/// or the session was ended per [`Config::sequence_action`] or [`Config::panic_action`].
+/// Useful with local task sets or inside an existing `select!` loop.
+/// [`run`] and [`run_with_config`] spawn this function.
+pub async fn serve<S, H>(stream: S, handler: H, config: Config) -> Result<(), Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    H: Handler + Send,
+{
+    let context = SessionContext::new(&config);
+    serve_with_context(stream, handler, config, context).await.0
+}
+
+/// Same as [`serve`] for a [`LocalHandler`], whose futures need not be `Send`.
+///
+/// The returned future is not `Send` either, drive it on the current task or
+/// spawn it on a [`LocalSet`](tokio::task::LocalSet)
+pub async fn serve_local<S, H>(stream: S, handler: H, config: Config) -> Result<(), Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    H: LocalHandler,
+{
+    let context = SessionContext::new(&config);
+    serve_with_context(stream, Local(handler), config, context)
+        .await
+        .0
+}
+
+/// Same as [`serve`] for a stream on which SSH_FXP_INIT and SSH_FXP_VERSION were
+/// already exchanged by another component, for example the client side of a proxy.
+///
+/// `client_init` is passed to [`Handler::init`], but its reply is not sent. The
+/// negotiated version is the lowest of both versions and further SSH_FXP_INIT are
+/// handled per [`Config::sequence_action`]
+pub async fn serve_initialized<S, H>(
+    stream: S,
+    mut handler: H,
+    config: Config,
+    client_init: Init,
+) -> Result<(), Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    H: Handler + Send,
+{
+    let version = Handler::init(
+        &mut handler,
+        client_init.version,
+        client_init.extensions.clone(),
+    )
+    .await
+    .map_err(|err| {
+        Error::UnexpectedBehavior(format!("SSH_FXP_INIT rejected: {}", err.into_status()))
+    })?;
+
+    let mut context = SessionContext::new(&config);
+    context.initialized(client_init, version.version);
+
+    serve_with_context(stream, handler, config, context).await.0
+}
+
+/// Serves the session until it ends and returns the result of [`serve`]
+/// with the reason and the context of the session
+async fn serve_with_context<S, H>(
+    mut stream: S,
+    mut handler: H,
+    config: Config,
+    mut context: SessionContext,
+) -> (Result<(), Error>, EndReason, SessionContext)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    H: Dispatch,
+{
+    let mut limiter = ResponseLimiter::default();
+    let mut audit = config
+        .audit
+        .clone()
+        .map(|log| SessionAudit::new(log, config.session_identity.clone()));
+    let mut buf = BytesMut::new();
+    let reason = loop {
+        let result = process_handler(
+            &mut stream,
+            &mut handler,
+            &config,
+            &mut context,
+            &mut limiter,
+            audit.as_mut(),
+            &mut buf,
+        )
+        .await;
+
+        match result {
+            Err(Error::UnexpectedEof) => break EndReason::Closed,
+            Err(Error::IO(err)) => break EndReason::Failed(err),
+            Err(Error::Framing(err)) => break EndReason::Framing(err),
+            Err(err) => warn!("{}", err),
+            Ok(Some(reason)) => break reason,
+            Ok(None) => (),
+        }
+    };
+
+    debug!("sftp stream ended: {reason:?}");
+    let result = match &reason {
+        EndReason::Closed => Ok(()),
+        EndReason::Failed(err) => Err(Error::IO(err.to_owned())),
+        EndReason::SequenceViolated => Err(Error::UnexpectedBehavior(
+            "SSH_FXP_INIT sequence violated".to_owned(),
+        )),
+        EndReason::Framing(err) => {
+            let _ = stream.shutdown().await;
+            Err(Error::Framing(err.to_owned()))
+        }
+        EndReason::Panicked(message) => {
+            // the Failure status is already flushed
+            let _ = stream.shutdown().await;
+            Err(Error::UnexpectedBehavior(format!(
+                "handler panicked: {message}"
+            )))
+        }
+    };
+
+    handler.session_ended(reason.clone()).await;
+    (result, reason, context)
+}
+
+/// Same as [`serve`], but takes the server side channel
+/// on which the `sftp` subsystem was requested
+#[cfg(feature = "russh")]
+pub async fn serve_channel<M, H>(
+    channel: russh::Channel<M>,
+    handler: H,
+    config: Config,
+) -> Result<(), Error>
+where
+    M: From<(russh::ChannelId, russh::ChannelMsg)> + Send + Sync + 'static,
+    H: Handler + Send,
+{
+    serve(channel.into_stream(), handler, config).await
+}
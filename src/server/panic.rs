@@ -0,0 +1,29 @@
+use std::{
+    any::Any,
+    future::{poll_fn, Future},
+    panic::{self, AssertUnwindSafe},
+    pin::pin,
+    task::Poll,
+};
+
+/// Polls `future` and returns the message of its panic instead of unwinding
+pub(crate) async fn catch_unwind<F: Future>(future: F) -> Result<F::Output, String> {
+    let mut future = pin!(future);
+    poll_fn(
+        |cx| match panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(cx))) {
+            Ok(poll) => poll.map(Ok),
+            Err(payload) => Poll::Ready(Err(message(payload))),
+        },
+    )
+    .await
+}
+
+fn message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast_ref::<&str>() {
+            Some(message) => message.to_string(),
+            None => "panic without a message".to_owned(),
+        },
+    }
+}
@@ -0,0 +1,207 @@
+//! Confinement of client paths and restriction of operations for handlers.
+//!
+//! A [`PathPolicy`] maps the paths received from the client onto the local
+//! file system and decides which operations are allowed on them.
+
+use std::{
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::protocol::StatusCode;
+
+/// Symlinks followed while resolving a path before it is denied, as `ELOOP`
+const MAX_LINKS: u32 = 40;
+
+/// Operation requested by the client, passed to [`PathPolicy::check`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    Read,
+    Write,
+    Stat,
+    ReadDir,
+    Remove,
+    Rename,
+    MkDir,
+    RmDir,
+    SetStat,
+    Symlink,
+}
+
+impl Operation {
+    /// Returns `true` if the operation modifies the file system
+    pub fn is_mutating(&self) -> bool {
+        matches!(
+            self,
+            Self::Write
+                | Self::Remove
+                | Self::Rename
+                | Self::MkDir
+                | Self::RmDir
+                | Self::SetStat
+                | Self::Symlink
+        )
+    }
+}
+
+/// Policy applied by a handler to each path received from the client
+pub trait PathPolicy: Send + Sync {
+    /// Maps the client path onto the local file system
+    fn resolve(&self, client_path: &str) -> Result<PathBuf, StatusCode>;
+
+    /// Checks whether the operation is allowed on the resolved path
+    #[allow(unused_variables)]
+    fn check(&self, op: Operation, path: &Path) -> Result<(), StatusCode> {
+        Ok(())
+    }
+}
+
+/// Confines the client to a directory which it sees as `/`.
+///
+/// `..` never leaves the root and symlinks are resolved, so a link
+/// pointing outside of the root is denied with [`StatusCode::PermissionDenied`]
+#[derive(Debug, Clone)]
+pub struct ChrootPolicy {
+    root: PathBuf,
+}
+
+impl ChrootPolicy {
+    /// Creates the policy for an existing directory
+    pub fn new<P: AsRef<Path>>(root: P) -> io::Result<Self> {
+        Ok(Self {
+            root: root.as_ref().canonicalize()?,
+        })
+    }
+
+    /// Returns the canonical root
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Maps a resolved path back to the path seen by the client.
+    /// Returns [`None`] if the path is outside of the root
+    pub fn client_path(&self, path: &Path) -> Option<String> {
+        let relative = path.strip_prefix(&self.root).ok()?;
+        let mut client_path = String::from("/");
+
+        for component in relative.components() {
+            if !client_path.ends_with('/') {
+                client_path.push('/');
+            }
+            client_path.push_str(&component.as_os_str().to_string_lossy());
+        }
+
+        Some(client_path)
+    }
+
+    /// Resolves the symlinks of the path, including the dangling ones, whose
+    /// target is resolved in turn. The components after the longest existing
+    /// part are appended as is
+    fn canonicalize(path: &Path) -> io::Result<PathBuf> {
+        let mut path = path.to_owned();
+        let mut links = 0;
+
+        'resolve: loop {
+            let mut existing = path.as_path();
+            let mut missing = Vec::new();
+
+            loop {
+                let err = match existing.canonicalize() {
+                    Ok(canonical) => {
+                        return Ok(missing
+                            .into_iter()
+                            .rev()
+                            .fold(canonical, |path, name| path.join(name)))
+                    }
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => err,
+                    Err(err) => return Err(err),
+                };
+
+                let (Some(parent), Some(name)) = (existing.parent(), existing.file_name()) else {
+                    return Err(err);
+                };
+
+                // a dangling link, continue with its target
+                if existing
+                    .symlink_metadata()
+                    .is_ok_and(|metadata| metadata.is_symlink())
+                {
+                    links += 1;
+                    if links > MAX_LINKS {
+                        return Err(io::Error::other("too many levels of symbolic links"));
+                    }
+
+                    let target = missing
+                        .into_iter()
+                        .rev()
+                        .fold(parent.join(existing.read_link()?), |path, name| {
+                            path.join(name)
+                        });
+                    path = target;
+                    continue 'resolve;
+                }
+
+                missing.push(name.to_owned());
+                existing = parent;
+            }
+        }
+    }
+}
+
+impl PathPolicy for ChrootPolicy {
+    fn resolve(&self, client_path: &str) -> Result<PathBuf, StatusCode> {
+        let mut path = self.root.clone();
+
+        for component in Path::new(client_path).components() {
+            match component {
+                Component::Normal(name) => path.push(name),
+                Component::ParentDir if path != self.root => {
+                    path.pop();
+                }
+                Component::RootDir => path = self.root.clone(),
+                _ => (),
+            }
+        }
+
+        let canonical = Self::canonicalize(&path).map_err(|_| StatusCode::PermissionDenied)?;
+        if !canonical.starts_with(&self.root) {
+            warn!("path {} escapes the root", client_path);
+            return Err(StatusCode::PermissionDenied);
+        }
+
+        // the link itself is kept so that lstat and readlink see it
+        Ok(path)
+    }
+}
+
+/// Denies the operations modifying the file system and
+/// applies the inner policy to the others
+#[derive(Debug, Clone)]
+pub struct ReadOnlyPolicy<P> {
+    inner: P,
+}
+
+impl<P: PathPolicy> ReadOnlyPolicy<P> {
+    pub fn new(inner: P) -> Self {
+        Self { inner }
+    }
+
+    /// Returns the inner policy
+    pub fn into_inner(self) -> P {
+        self.inner
+    }
+}
+
+impl<P: PathPolicy> PathPolicy for ReadOnlyPolicy<P> {
+    fn resolve(&self, client_path: &str) -> Result<PathBuf, StatusCode> {
+        self.inner.resolve(client_path)
+    }
+
+    fn check(&self, op: Operation, path: &Path) -> Result<(), StatusCode> {
+        if op.is_mutating() {
+            return Err(StatusCode::PermissionDenied);
+        }
+
+        self.inner.check(op, path)
+    }
+}
@@ -0,0 +1,55 @@
+//! Common types of the server, for a single glob import.
+//!
+//! The [`Handler`](super::Handler) of the server is exported as [`ServerHandler`]
+//! so that it does not collide with the one of the client. The [`async_trait`]
+//! attribute its implementations need is exported as well.
+//!
+//! ```no_run
+//! use russh_sftp::server::prelude::*;
+//! use std::collections::HashMap;
+//!
+//! struct Hello;
+//!
+//! #[async_trait]
+//! impl ServerHandler for Hello {
+//!     type Error = StatusCode;
+//!
+//!     fn unimplemented(&self) -> Self::Error {
+//!         StatusCode::OpUnsupported
+//!     }
+//!
+//!     async fn init(
+//!         &mut self,
+//!         _version: u32,
+//!         _extensions: HashMap<String, String>,
+//!     ) -> Result<Version, Self::Error> {
+//!         Ok(Version::new())
+//!     }
+//!
+//!     async fn realpath(&mut self, id: RequestId, _path: String) -> Result<Name, Self::Error> {
+//!         Ok(Name {
+//!             id,
+//!             files: vec![File::dummy("/")],
+//!         })
+//!     }
+//! }
+//!
+//! # async fn run(stream: tokio::io::DuplexStream) {
+//! let config = Config {
+//!     strict_flags: true,
+//!     ..Default::default()
+//! };
+//! run_with_config(stream, Hello, config).await;
+//! # }
+//! ```
+
+pub use async_trait::async_trait;
+
+pub use super::{
+    run, run_with_config, serve, serve_local, Config, EndReason, Handler as ServerHandler,
+    LocalHandler, SessionContext, StatusError,
+};
+pub use crate::protocol::{
+    Attrs, Data, File, FileAttributes, FileMode, FileType, Handle, Name, OpenFlags, RequestId,
+    Status, StatusCode, Version,
+};
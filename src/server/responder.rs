@@ -0,0 +1,61 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+use tokio::io::AsyncRead;
+
+use crate::protocol::{Data, RequestId};
+
+/// Length of the chunks copied from the reader of [`Responder::send_data_streaming`]
+pub const STREAMING_CHUNK_LEN: usize = 32 * 1024;
+
+/// Data registered by [`Responder::send_data_streaming`]
+pub(crate) struct StreamedData {
+    pub id: RequestId,
+    pub len: u32,
+    pub reader: Box<dyn AsyncRead + Send + Unpin>,
+}
+
+/// Replies of the handler written by the processing loop itself, shared by the
+/// session and obtained from [`SessionContext::responder`](super::SessionContext::responder)
+#[derive(Clone, Default)]
+pub struct Responder {
+    streamed: Arc<Mutex<Option<StreamedData>>>,
+}
+
+impl Responder {
+    /// Replies to the SSH_FXP_READ `id` with `len` bytes read from `reader`, copied
+    /// to the stream in chunks of [`STREAMING_CHUNK_LEN`] rather than built in memory.
+    ///
+    /// Return the [`Data`] it returns from [`Handler::read`](super::Handler::read),
+    /// it only carries the id. `len` is shortened to fit the maximum response length.
+    /// If the reader ends before, the session ends as the length was already sent
+    pub fn send_data_streaming<I, R>(&self, id: I, len: u32, reader: R) -> Data
+    where
+        I: Into<RequestId>,
+        R: AsyncRead + Send + Unpin + 'static,
+    {
+        let id = id.into();
+        *self.streamed.lock().unwrap() = Some(StreamedData {
+            id,
+            len,
+            reader: Box::new(reader),
+        });
+
+        Data { id, data: vec![] }
+    }
+
+    /// Takes the data registered while the last request was handled
+    pub(crate) fn take(&self) -> Option<StreamedData> {
+        self.streamed.lock().unwrap().take()
+    }
+}
+
+impl fmt::Debug for Responder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let streamed = self.streamed.lock().unwrap();
+        f.debug_struct("Responder")
+            .field("streamed", &streamed.as_ref().map(|s| (s.id, s.len)))
+            .finish()
+    }
+}
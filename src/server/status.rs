@@ -0,0 +1,102 @@
+use std::{fmt, io};
+
+use crate::protocol::{RequestId, Status, StatusCode};
+
+/// Error of a [`Handler`](super::Handler) with the message sent to the client,
+/// for example `disk quota exceeded: 1.2 GB over`
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatusError {
+    pub code: StatusCode,
+    /// Message of the status. Default: [`None`], formatted by the crate per
+    /// [`Config::status_formatter`](super::Config::status_formatter)
+    pub message: Option<String>,
+    /// Language of the message. Default: [`None`], `en-US`
+    pub language_tag: Option<String>,
+}
+
+impl StatusError {
+    pub fn new<M: Into<String>>(code: StatusCode, message: M) -> Self {
+        Self {
+            code,
+            message: Some(message.into()),
+            language_tag: None,
+        }
+    }
+
+    pub fn with_language_tag<T: Into<String>>(mut self, language_tag: T) -> Self {
+        self.language_tag = Some(language_tag.into());
+        self
+    }
+
+    /// Status replied to the request `id`, `default` provides the message
+    /// and the language tag of an error without a message
+    pub(crate) fn into_reply(
+        self,
+        id: RequestId,
+        default: impl FnOnce(StatusCode) -> Status,
+    ) -> Status {
+        let Some(message) = self.message else {
+            return Status {
+                id,
+                ..default(self.code)
+            };
+        };
+
+        Status {
+            id,
+            status_code: self.code,
+            error_message: message,
+            language_tag: self.language_tag.unwrap_or_else(|| "en-US".to_owned()),
+        }
+    }
+}
+
+impl From<StatusCode> for StatusError {
+    fn from(code: StatusCode) -> Self {
+        Self {
+            code,
+            message: None,
+            language_tag: None,
+        }
+    }
+}
+
+/// Replies with the code of the kind of the error and its message,
+/// such as `No such file or directory (os error 2)`
+impl From<io::Error> for StatusError {
+    fn from(err: io::Error) -> Self {
+        Self::new(err.kind().into(), err.to_string())
+    }
+}
+
+impl fmt::Display for StatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.message {
+            Some(message) => write!(f, "{}: {}", self.code, message),
+            None => write!(f, "{}", self.code),
+        }
+    }
+}
+
+impl std::error::Error for StatusError {}
+
+/// Conversion of the errors of a [`Handler`](super::Handler) into the status
+/// replied to the request, whose id is filled in by the crate.
+///
+/// Implemented for every `Into<StatusCode>` type, with the message formatted
+/// by the crate, and for [`StatusError`] which carries its own message
+pub trait IntoStatus {
+    fn into_status(self) -> StatusError;
+}
+
+impl<T: Into<StatusCode>> IntoStatus for T {
+    fn into_status(self) -> StatusError {
+        StatusError::from(self.into())
+    }
+}
+
+impl IntoStatus for StatusError {
+    fn into_status(self) -> StatusError {
+        self
+    }
+}
@@ -0,0 +1,268 @@
+//! Programmable faults between a client and a server, to test the retry and
+//! timeout logic of applications.
+//!
+//! A [`FaultLayer`] relays the packets of a session and applies its [`Rule`]s to
+//! the replies of the requests they match. It works at the packet layer, so any
+//! server can be wrapped, whatever its handler.
+
+use bytes::{Bytes, BytesMut};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::{
+    io::{self, AsyncRead, AsyncWrite, AsyncWriteExt, DuplexStream},
+    sync::mpsc,
+};
+
+use super::{run_with_config, validate::response_id, Config, Handler};
+use crate::{
+    framing::{read_packet, write_packet},
+    protocol::{Packet, RequestId, StatusCode},
+};
+
+/// Fault applied to the reply of a request matched by a [`Rule`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fault {
+    /// Sends the reply after the duration, without holding back the other replies
+    Delay(Duration),
+    /// Never sends the reply
+    DropReply,
+    /// Replies with a status of the code instead
+    ReplaceStatus(StatusCode),
+    /// Keeps at most the number of bytes of SSH_FXP_DATA
+    TruncateData(usize),
+}
+
+/// Requests to fault, by type, target and id. A rule matches any request
+/// unless narrowed and applies once unless [`Rule::persistent`]
+#[derive(Debug, Clone)]
+pub struct Rule {
+    fault: Fault,
+    packet: Option<String>,
+    target: Option<String>,
+    id: Option<RequestId>,
+    persistent: bool,
+}
+
+impl Rule {
+    pub fn new(fault: Fault) -> Self {
+        Self {
+            fault,
+            packet: None,
+            target: None,
+            id: None,
+            persistent: false,
+        }
+    }
+
+    /// Matches the requests of the type, such as `SSH_FXP_READ`
+    pub fn packet<P: Into<String>>(mut self, packet: P) -> Self {
+        self.packet = Some(packet.into());
+        self
+    }
+
+    /// Matches the requests on the path or the handle
+    pub fn target<T: Into<String>>(mut self, target: T) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    /// Matches the request of the id
+    pub fn id<I: Into<RequestId>>(mut self, id: I) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Keeps the rule after it was applied
+    pub fn persistent(mut self) -> Self {
+        self.persistent = true;
+        self
+    }
+
+    fn matches(&self, request: &Packet) -> bool {
+        self.packet
+            .as_ref()
+            .is_none_or(|name| name == request.name())
+            && self
+                .target
+                .as_ref()
+                .is_none_or(|target| request.target() == Some(target.as_str()))
+            && self.id.is_none_or(|id| match request {
+                Packet::Init(_) => false,
+                request => request.get_request_id() == id,
+            })
+    }
+}
+
+/// Faults of the requests waiting for their reply, SSH_FXP_INIT having no id
+type Pending = HashMap<Option<RequestId>, Vec<Fault>>;
+
+/// Rules applied to the sessions relayed by [`FaultLayer::wrap`]. Clones share the
+/// rules, so that they can be changed while the sessions run
+#[derive(Debug, Clone, Default)]
+pub struct FaultLayer {
+    rules: Arc<Mutex<Vec<Rule>>>,
+    applied: Arc<AtomicU64>,
+}
+
+impl FaultLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a rule, applied after the rules pushed before it
+    pub fn push_rule(&self, rule: Rule) {
+        self.rules.lock().unwrap().push(rule);
+    }
+
+    /// Removes all the rules
+    pub fn clear(&self) {
+        self.rules.lock().unwrap().clear();
+    }
+
+    /// Number of rules left, the one-shot rules being removed once applied
+    pub fn rules(&self) -> usize {
+        self.rules.lock().unwrap().len()
+    }
+
+    /// Number of faults applied so far
+    pub fn applied(&self) -> u64 {
+        self.applied.load(Ordering::Relaxed)
+    }
+
+    /// Relays the packets between the returned stream, for the client, and `server`,
+    /// applying the rules to the replies. The relay runs on its own tasks
+    pub fn wrap<S>(&self, server: S) -> DuplexStream
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (client, relay) = io::duplex(64 * 1024);
+        let (mut client_read, client_write) = io::split(relay);
+        let (mut server_read, mut server_write) = io::split(server);
+        let pending = Arc::new(Mutex::new(Pending::new()));
+
+        let layer = self.clone();
+        let requests = pending.clone();
+        tokio::spawn(async move {
+            let mut buf = BytesMut::new();
+            while let Ok(bytes) = read_packet(&mut client_read, &mut buf).await {
+                if let Ok(request) = Packet::try_from(&mut bytes.clone()) {
+                    let faults = layer.faults(&request);
+                    if !faults.is_empty() {
+                        let key = match request {
+                            Packet::Init(_) => None,
+                            request => Some(request.get_request_id()),
+                        };
+                        requests.lock().unwrap().insert(key, faults);
+                    }
+                }
+
+                if write_packet(&mut server_write, &bytes).await.is_err() {
+                    break;
+                }
+            }
+
+            let _ = server_write.shutdown().await;
+        });
+
+        // the replies go through a channel so that a delayed one does not hold back the others
+        let (tx, mut rx) = mpsc::unbounded_channel::<Bytes>();
+        tokio::spawn(async move {
+            let mut client_write = client_write;
+            while let Some(bytes) = rx.recv().await {
+                if write_packet(&mut client_write, &bytes).await.is_err() {
+                    break;
+                }
+            }
+
+            let _ = client_write.shutdown().await;
+        });
+
+        let layer = self.clone();
+        tokio::spawn(async move {
+            let mut buf = BytesMut::new();
+            while let Ok(bytes) = read_packet(&mut server_read, &mut buf).await {
+                let faults = match Packet::try_from(&mut bytes.clone()) {
+                    Ok(reply) => pending.lock().unwrap().remove(&response_id(&reply)),
+                    Err(_) => None,
+                };
+
+                let Some(faults) = faults else {
+                    let _ = tx.send(bytes);
+                    continue;
+                };
+
+                let applied = faults.len() as u64;
+                layer.applied.fetch_add(applied, Ordering::Relaxed);
+                if let Some((delay, bytes)) = apply(bytes, faults) {
+                    let tx = tx.clone();
+                    tokio::spawn(async move {
+                        tokio::time::sleep(delay).await;
+                        let _ = tx.send(bytes);
+                    });
+                }
+            }
+        });
+
+        client
+    }
+
+    /// Serves the handler behind the layer and returns the stream of the client
+    pub async fn serve<H>(&self, handler: H, config: Config) -> DuplexStream
+    where
+        H: Handler + Send + 'static,
+    {
+        let (server, client) = io::duplex(64 * 1024);
+        run_with_config(server, handler, config).await;
+        self.wrap(client)
+    }
+
+    /// Returns the faults of the rules matching the request, in order
+    fn faults(&self, request: &Packet) -> Vec<Fault> {
+        let mut faults = Vec::new();
+        self.rules.lock().unwrap().retain(|rule| {
+            if !rule.matches(request) {
+                return true;
+            }
+
+            faults.push(rule.fault.clone());
+            rule.persistent
+        });
+
+        faults
+    }
+}
+
+/// Applies the faults to an encoded reply. Returns the reply to send and
+/// its total delay, [`None`] if it is dropped
+fn apply(bytes: Bytes, faults: Vec<Fault>) -> Option<(Duration, Bytes)> {
+    let Ok(mut reply) = Packet::try_from(&mut bytes.clone()) else {
+        return None;
+    };
+
+    let mut delay = Duration::ZERO;
+    for fault in faults {
+        match fault {
+            Fault::Delay(duration) => delay += duration,
+            Fault::DropReply => return None,
+            Fault::ReplaceStatus(status_code) => {
+                let id = response_id(&reply).unwrap_or_default();
+                reply = Packet::error(id, status_code);
+            }
+            Fault::TruncateData(len) => {
+                if let Packet::Data(data) = &mut reply {
+                    data.data.truncate(len);
+                }
+            }
+        }
+    }
+
+    // the frame of the packet is prepended by write_packet
+    let bytes = Bytes::try_from(reply).ok()?.slice(4..);
+    Some((delay, bytes))
+}
@@ -0,0 +1,58 @@
+use tokio::time::{self, Duration, Instant};
+
+/// Token bucket pacing the requests of a session to
+/// [`Config::max_requests_per_second`](super::Config::max_requests_per_second).
+///
+/// It holds up to a second of requests, so that a client idle for a while is not
+/// delayed, and a request waits for its token rather than being rejected
+#[derive(Debug, Clone)]
+pub(crate) struct Throttle {
+    rate: u32,
+    tokens: f64,
+    refilled: Instant,
+    throttled: u64,
+}
+
+impl Throttle {
+    pub fn new(rate: u32) -> Self {
+        let rate = rate.max(1);
+        Self {
+            rate,
+            tokens: rate as f64,
+            refilled: Instant::now(),
+            throttled: 0,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let earned = now.duration_since(self.refilled).as_secs_f64() * self.rate as f64;
+        self.tokens = (self.tokens + earned).min(self.rate as f64);
+        self.refilled = now;
+    }
+
+    /// Waits until a request can be handled and takes its token. The bucket is only
+    /// changed once the wait is over, so that the wait can be cancelled with the session
+    pub async fn acquire(&mut self) {
+        self.refill(Instant::now());
+
+        if self.tokens < 1.0 {
+            let wait = Duration::from_secs_f64((1.0 - self.tokens) / self.rate as f64);
+            time::sleep(wait).await;
+
+            self.throttled += 1;
+            self.refill(Instant::now());
+        }
+
+        self.tokens = (self.tokens - 1.0).max(0.0);
+    }
+
+    /// Number of requests delayed so far
+    pub fn throttled(&self) -> u64 {
+        self.throttled
+    }
+
+    /// Requests that can be handled right away
+    pub fn tokens(&self) -> f64 {
+        self.tokens
+    }
+}
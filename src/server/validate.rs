@@ -0,0 +1,160 @@
+//! Rules checking the replies of a handler when [`Config::validate_responses`](super::Config::validate_responses)
+//! is enabled.
+//!
+//! The rules only depend on the request and the reply, so they can be applied
+//! to packets built by hand, for example in the tests of a handler.
+
+use crate::protocol::{Packet, RequestId, StatusCode};
+
+/// Properties of the request kept to validate the reply
+#[derive(Debug, Clone, Copy)]
+pub struct RequestInfo {
+    /// Name of the packet type, as returned by [`Packet::name`]
+    pub name: &'static str,
+    pub id: RequestId,
+}
+
+/// Returns the violation of the rule by the reply if any
+pub type Check = fn(RequestInfo, &Packet) -> Result<(), String>;
+
+/// Rules applied to each reply in order, the first violation is reported
+pub const RULES: &[(&str, Check)] = &[
+    ("response type", response_type),
+    ("request id", request_id),
+    ("single name", single_name),
+    ("data length", data_len),
+];
+
+/// Packets allowed in reply to each request. A Status with an error
+/// code is allowed for any request, a Status with Ok only if listed
+const RESPONSES: &[(&str, &[&str])] = &[
+    ("SSH_FXP_INIT", &["SSH_FXP_VERSION"]),
+    ("SSH_FXP_OPEN", &["SSH_FXP_HANDLE"]),
+    ("SSH_FXP_CLOSE", &["SSH_FXP_STATUS"]),
+    ("SSH_FXP_READ", &["SSH_FXP_DATA"]),
+    ("SSH_FXP_WRITE", &["SSH_FXP_STATUS"]),
+    ("SSH_FXP_LSTAT", &["SSH_FXP_ATTRS"]),
+    ("SSH_FXP_FSTAT", &["SSH_FXP_ATTRS"]),
+    ("SSH_FXP_SETSTAT", &["SSH_FXP_STATUS"]),
+    ("SSH_FXP_FSETSTAT", &["SSH_FXP_STATUS"]),
+    ("SSH_FXP_OPENDIR", &["SSH_FXP_HANDLE"]),
+    ("SSH_FXP_READDIR", &["SSH_FXP_NAME"]),
+    ("SSH_FXP_REMOVE", &["SSH_FXP_STATUS"]),
+    ("SSH_FXP_MKDIR", &["SSH_FXP_STATUS"]),
+    ("SSH_FXP_RMDIR", &["SSH_FXP_STATUS"]),
+    ("SSH_FXP_REALPATH", &["SSH_FXP_NAME"]),
+    ("SSH_FXP_STAT", &["SSH_FXP_ATTRS"]),
+    ("SSH_FXP_RENAME", &["SSH_FXP_STATUS"]),
+    ("SSH_FXP_READLINK", &["SSH_FXP_NAME"]),
+    ("SSH_FXP_SYMLINK", &["SSH_FXP_STATUS"]),
+    (
+        "SSH_FXP_EXTENDED",
+        &[
+            "SSH_FXP_EXTENDED_REPLY",
+            "SSH_FXP_STATUS",
+            "SSH_FXP_HANDLE",
+            "SSH_FXP_DATA",
+            "SSH_FXP_NAME",
+            "SSH_FXP_ATTRS",
+        ],
+    ),
+];
+
+/// Checks the reply against [`RULES`], returns the first violation
+/// prefixed with the name of its rule
+pub fn validate(request: RequestInfo, response: &Packet) -> Result<(), String> {
+    for (name, check) in RULES {
+        check(request, response).map_err(|err| format!("{name}: {err}"))?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn response_id(response: &Packet) -> Option<RequestId> {
+    match response {
+        Packet::Status(status) => Some(status.id),
+        Packet::Handle(handle) => Some(handle.id),
+        Packet::Data(data) => Some(data.id),
+        Packet::Name(name) => Some(name.id),
+        Packet::Attrs(attrs) => Some(attrs.id),
+        Packet::ExtendedReply(reply) => Some(reply.id),
+        _ => None,
+    }
+}
+
+/// Replaces the id of the reply, returns the id it had. Returns [`None`]
+/// for the replies without an id
+pub(crate) fn set_response_id(response: &mut Packet, id: RequestId) -> Option<RequestId> {
+    let replied = match response {
+        Packet::Status(status) => &mut status.id,
+        Packet::Handle(handle) => &mut handle.id,
+        Packet::Data(data) => &mut data.id,
+        Packet::Name(name) => &mut name.id,
+        Packet::Attrs(attrs) => &mut attrs.id,
+        Packet::ExtendedReply(reply) => &mut reply.id,
+        _ => return None,
+    };
+
+    Some(std::mem::replace(replied, id))
+}
+
+fn response_type(request: RequestInfo, response: &Packet) -> Result<(), String> {
+    if let Packet::Status(status) = response {
+        if status.status_code != StatusCode::Ok {
+            return Ok(());
+        }
+    }
+
+    let allowed = RESPONSES
+        .iter()
+        .find(|(name, _)| *name == request.name)
+        .map_or(&[][..], |(_, allowed)| *allowed);
+
+    match allowed.contains(&response.name()) {
+        true => Ok(()),
+        false if matches!(response, Packet::Status(_)) => Err(format!(
+            "Ok status is not a valid reply to {}, expected {}",
+            request.name,
+            allowed.join(" or ")
+        )),
+        false => Err(format!(
+            "{} is not a valid reply to {}",
+            response.name(),
+            request.name
+        )),
+    }
+}
+
+fn request_id(request: RequestInfo, response: &Packet) -> Result<(), String> {
+    if matches!(response, Packet::Version(_)) {
+        return Ok(());
+    }
+
+    match response_id(response) {
+        Some(id) if id == request.id => Ok(()),
+        Some(id) => Err(format!("reply has id {id} instead of {}", request.id)),
+        None => Err(format!("{} has no id", response.name())),
+    }
+}
+
+fn single_name(request: RequestInfo, response: &Packet) -> Result<(), String> {
+    match (request.name, response) {
+        ("SSH_FXP_REALPATH" | "SSH_FXP_READLINK", Packet::Name(name)) if name.files.len() != 1 => {
+            Err(format!(
+                "{} requires exactly one name, got {}",
+                request.name,
+                name.files.len()
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn data_len(_: RequestInfo, response: &Packet) -> Result<(), String> {
+    match response {
+        Packet::Data(data) if data.data.is_empty() => {
+            Err("empty data, the end of file must be reported with the Eof status".to_owned())
+        }
+        _ => Ok(()),
+    }
+}
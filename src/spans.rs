@@ -0,0 +1,58 @@
+//! Spans of the requests with the `tracing` crate
+
+use tracing::{field, Span};
+
+use crate::protocol::Packet;
+
+/// Span `sftp_request` of the request sent by the client or handled by the server,
+/// its `side`, with its packet type, id, handle and payload length. The path is
+/// only recorded with `record_paths`, it may be sensitive
+pub(crate) fn request(side: &'static str, request: &Packet, record_paths: bool) -> Span {
+    let span = tracing::debug_span!(
+        "sftp_request",
+        side,
+        packet = request.name(),
+        id = request.get_request_id().0,
+        handle = field::Empty,
+        path = field::Empty,
+        len = field::Empty,
+        status = field::Empty,
+        reply = field::Empty,
+    );
+
+    if span.is_disabled() {
+        return span;
+    }
+
+    match request.handle() {
+        Some(handle) => {
+            span.record("handle", handle);
+        }
+        None if record_paths => {
+            if let Some(path) = request.target() {
+                span.record("path", path);
+            }
+        }
+        None => (),
+    }
+
+    let len = match request {
+        Packet::Read(read) => Some(read.len as u64),
+        Packet::Write(write) => Some(write.data.len() as u64),
+        Packet::Extended(extended) => Some(extended.data.len() as u64),
+        _ => None,
+    };
+    if let Some(len) = len {
+        span.record("len", len);
+    }
+
+    span
+}
+
+/// Records the status code or the packet type of the reply
+pub(crate) fn reply(span: &Span, reply: &Packet) {
+    match reply {
+        Packet::Status(status) => span.record("status", field::debug(status.status_code)),
+        reply => span.record("reply", reply.name()),
+    };
+}
@@ -0,0 +1,131 @@
+//! Permission pre-checks evaluated from the mode, the owner and the group of the
+//! attributes and the identity of the session, with and without each of them.
+
+mod common;
+
+use common::Memory;
+use russh_sftp::{
+    client::{Access, AccessCheck, Identity, SftpSession},
+    protocol::FileAttributes,
+};
+
+use Access::{Allowed, Denied, Indeterminate};
+use AccessCheck::{Execute, Read, Write};
+
+const USER: u32 = 1000;
+const GROUP: u32 = 100;
+const OTHER: u32 = 1;
+
+fn user() -> Identity {
+    Identity::new(USER, vec![GROUP, 200])
+}
+
+fn root() -> Identity {
+    Identity::new(0, vec![0])
+}
+
+fn attrs(mode: u32, uid: Option<u32>, gid: Option<u32>) -> FileAttributes {
+    FileAttributes {
+        permissions: Some(mode),
+        uid,
+        gid,
+        ..FileAttributes::empty()
+    }
+}
+
+/// Mode, owner, group and check of the attributes with the expected access
+type Case = (u32, Option<u32>, Option<u32>, AccessCheck, Access);
+
+#[test]
+fn permission_matrix() {
+    // without an identity the user may be root, nothing is denied
+    let anyone: &[Case] = &[
+        (0o777, Some(OTHER), Some(OTHER), Read, Allowed),
+        (0o444, None, None, Read, Allowed),
+        (0o000, Some(OTHER), Some(OTHER), Read, Indeterminate),
+        (0o740, Some(USER), Some(GROUP), Read, Indeterminate),
+        (0o666, None, None, Execute, Indeterminate),
+    ];
+
+    let user_cases: &[Case] = &[
+        // owner known, only the owner bits apply
+        (0o400, Some(USER), Some(OTHER), Read, Allowed),
+        (0o400, Some(USER), Some(OTHER), Write, Denied),
+        (0o077, Some(USER), Some(GROUP), Read, Denied),
+        (0o100, Some(USER), None, Execute, Allowed),
+        // another owner, the group bits of a group of the user
+        (0o040, Some(OTHER), Some(GROUP), Read, Allowed),
+        (0o404, Some(OTHER), Some(200), Read, Denied),
+        // another owner and group, the bits of the others
+        (0o004, Some(OTHER), Some(OTHER), Read, Allowed),
+        (0o770, Some(OTHER), Some(OTHER), Write, Denied),
+        // another owner, group unknown
+        (0o022, Some(OTHER), None, Write, Allowed),
+        (0o020, Some(OTHER), None, Write, Indeterminate),
+        (0o700, Some(OTHER), None, Write, Denied),
+        // owner unknown, the group of the user
+        (0o440, None, Some(GROUP), Read, Allowed),
+        (0o040, None, Some(GROUP), Read, Indeterminate),
+        (0o007, None, Some(GROUP), Read, Denied),
+        // owner unknown, another group
+        (0o404, None, Some(OTHER), Read, Allowed),
+        (0o440, None, Some(OTHER), Read, Indeterminate),
+        // owner and group unknown
+        (0o444, None, None, Read, Allowed),
+        (0o440, None, None, Read, Indeterminate),
+        (0o000, None, None, Read, Denied),
+    ];
+
+    // root reads and writes anything, executes with any execute bit
+    let root_cases: &[Case] = &[
+        (0o000, Some(OTHER), Some(OTHER), Read, Allowed),
+        (0o000, None, None, Write, Allowed),
+        (0o100644, Some(OTHER), Some(OTHER), Execute, Denied),
+        (0o100001, Some(OTHER), Some(OTHER), Execute, Allowed),
+        (0o040000, Some(OTHER), Some(OTHER), Execute, Allowed),
+    ];
+
+    for (identity, cases) in [
+        (None, anyone),
+        (Some(user()), user_cases),
+        (Some(root()), root_cases),
+    ] {
+        for &(mode, uid, gid, check, expected) in cases {
+            let access = attrs(mode, uid, gid).access(check, identity.as_ref());
+            let case = format!("{mode:o} {uid:?} {gid:?} {check:?} {identity:?}");
+            assert_eq!(access, expected, "{case}");
+        }
+
+        // nothing is known without the permissions
+        let attrs = FileAttributes::empty();
+        assert_eq!(attrs.access(Read, identity.as_ref()), Indeterminate);
+    }
+}
+
+#[tokio::test]
+async fn access_of_a_remote_file() {
+    let server = Memory::default();
+    server
+        .files
+        .lock()
+        .unwrap()
+        .insert("/file".to_owned(), b"data".to_vec());
+    let (sftp, _server) = SftpSession::pair(server).await.unwrap();
+
+    // the server sends 0o644 without an owner
+    let cases = [
+        (None, [Allowed, Indeterminate, Indeterminate]),
+        (Some(user()), [Allowed, Indeterminate, Denied]),
+        (Some(root()), [Allowed, Allowed, Denied]),
+    ];
+
+    for (identity, expected) in cases {
+        sftp.set_identity(identity.clone());
+        for (check, expected) in [Read, Write, Execute].into_iter().zip(expected) {
+            let access = sftp.access("/file", check).await.unwrap();
+            assert_eq!(access, expected, "{identity:?} {check:?}");
+        }
+    }
+
+    assert!(sftp.access("/missing", Read).await.is_err());
+}
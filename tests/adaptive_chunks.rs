@@ -0,0 +1,118 @@
+//! Adaptive chunk sizing, the controller fed with synthetic latencies and files
+//! using it.
+
+mod common;
+
+use common::Memory;
+use russh_sftp::client::{
+    fs::{Aimd, INITIAL_ADAPTIVE_CHUNK, MIN_ADAPTIVE_CHUNK},
+    SftpSession,
+};
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+
+const MAX: usize = 256 * 1024;
+
+/// Latency of a request of `len` bytes at `nanos` per byte
+fn latency(len: usize, nanos: u64) -> Duration {
+    Duration::from_nanos(len as u64 * nanos)
+}
+
+#[test]
+fn starts_within_the_bounds() {
+    assert_eq!(Aimd::new(MAX).size(), INITIAL_ADAPTIVE_CHUNK);
+    assert_eq!(Aimd::new(10_000).size(), 10_000);
+    assert_eq!(Aimd::new(100).size(), MIN_ADAPTIVE_CHUNK);
+}
+
+#[test]
+fn grows_up_to_the_limit_on_a_steady_link() {
+    let mut aimd = Aimd::new(MAX);
+    let mut sizes = vec![aimd.size()];
+
+    for _ in 0..20 {
+        let len = aimd.size();
+        aimd.record(len, latency(len, 10));
+        sizes.push(aimd.size());
+    }
+
+    // additive steps of 16 KB, then capped
+    assert_eq!(sizes[1] - sizes[0], 16 * 1024);
+    assert!(sizes.windows(2).all(|w| w[0] <= w[1]), "{sizes:?}");
+    assert_eq!(aimd.size(), MAX);
+}
+
+#[test]
+fn halves_on_stalls_down_to_the_minimum() {
+    let mut aimd = Aimd::new(MAX);
+    for _ in 0..10 {
+        let len = aimd.size();
+        aimd.record(len, latency(len, 10));
+    }
+    let before = aimd.size();
+
+    // more than twice the best latency per byte
+    let len = aimd.size();
+    aimd.record(len, latency(len, 30));
+    assert_eq!(aimd.size(), before / 2);
+
+    for _ in 0..20 {
+        let len = aimd.size();
+        aimd.record(len, latency(len, 30));
+    }
+    assert_eq!(aimd.size(), MIN_ADAPTIVE_CHUNK);
+
+    // recovers once the link is fast again
+    let len = aimd.size();
+    aimd.record(len, latency(len, 10));
+    assert_eq!(aimd.size(), MIN_ADAPTIVE_CHUNK + 16 * 1024);
+}
+
+#[test]
+fn tolerates_moderate_jitter() {
+    let mut aimd = Aimd::new(MAX);
+    for nanos in [10, 15, 20, 12, 19] {
+        let before = aimd.size();
+        aimd.record(before, latency(before, nanos));
+        assert!(aimd.size() > before, "{nanos}");
+    }
+}
+
+#[test]
+fn short_requests_ignored() {
+    let mut aimd = Aimd::new(MAX);
+    aimd.record(MIN_ADAPTIVE_CHUNK - 1, Duration::from_secs(10));
+    assert_eq!(aimd.size(), INITIAL_ADAPTIVE_CHUNK);
+}
+
+#[tokio::test]
+async fn files_use_the_controller() {
+    let handler = Memory::default();
+    let data = vec![7; 2 * 1024 * 1024];
+    handler
+        .files
+        .lock()
+        .unwrap()
+        .insert("/f".to_owned(), data.clone());
+    let (sftp, _server) = SftpSession::pair(handler).await.unwrap();
+
+    let max = sftp.open("/f").await.unwrap().chunk_size();
+    assert!(max > INITIAL_ADAPTIVE_CHUNK);
+
+    sftp.set_adaptive_chunks(true);
+    let mut file = sftp.open("/f").await.unwrap();
+    assert_eq!(file.chunk_size(), INITIAL_ADAPTIVE_CHUNK);
+
+    let mut read = Vec::new();
+    file.read_to_end(&mut read).await.unwrap();
+    assert!(read == data);
+    assert!((MIN_ADAPTIVE_CHUNK..=max).contains(&file.chunk_size()));
+
+    file.pin_chunk_size(10_000);
+    assert_eq!(file.chunk_size(), 10_000);
+    file.pin_chunk_size(usize::MAX);
+    assert_eq!(file.chunk_size(), max);
+
+    file.unpin_chunk_size();
+    assert_eq!(file.chunk_size(), INITIAL_ADAPTIVE_CHUNK);
+}
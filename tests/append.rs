@@ -0,0 +1,78 @@
+//! Files opened in append mode on a server that ignores the flag for the explicit
+//! offsets of the writes, as OpenSSH does.
+
+mod common;
+
+use common::{Local, Memory};
+use russh_sftp::client::{fs::ProgressEvent, SftpSession};
+use std::io::SeekFrom;
+use tokio::{
+    fs,
+    io::{AsyncSeekExt, AsyncWriteExt},
+};
+
+const CHUNK: usize = 100;
+
+async fn session(existing: &[u8]) -> (SftpSession, Memory) {
+    let server = Memory::default();
+    server
+        .files
+        .lock()
+        .unwrap()
+        .insert("/log".to_owned(), existing.to_vec());
+    let (sftp, _server) = SftpSession::pair(server.clone()).await.unwrap();
+    (sftp, server)
+}
+
+#[tokio::test]
+async fn writes_appended_in_order() {
+    let (sftp, server) = session(b"first\n").await;
+
+    let mut file = sftp.open_append("/log").await.unwrap();
+    assert_eq!(file.position(), 6);
+    file.write_all(b"second\n").await.unwrap();
+
+    // the position does not move the writes away from the end
+    file.seek(SeekFrom::Start(0)).await.unwrap();
+    file.write_all(b"third\n").await.unwrap();
+    assert_eq!(file.position(), 19);
+
+    let mut other = sftp.open_append("/log").await.unwrap();
+    other.write_all(b"fourth\n").await.unwrap();
+
+    assert_eq!(
+        server.files.lock().unwrap()["/log"],
+        b"first\nsecond\nthird\nfourth\n"
+    );
+}
+
+#[tokio::test]
+async fn local_copy_appended() {
+    let existing = vec![b'a'; 150];
+    let (sftp, server) = session(&existing).await;
+    let copied = vec![b'b'; 3 * CHUNK + 50];
+    let local = Local::with_data("copy", &copied);
+
+    let mut file = sftp.open_append("/log").await.unwrap();
+    file.pin_chunk_size(CHUNK);
+    let mut offsets = Vec::new();
+    let len = file
+        .copy_from_local_with_progress(
+            &mut fs::File::open(&local.0).await.unwrap(),
+            |event: ProgressEvent| offsets.push(event.offset),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(len, copied.len() as u64);
+    let end = (existing.len() + copied.len()) as u64;
+    assert_eq!(offsets.iter().max(), Some(&end));
+    assert_eq!(file.acknowledged_size(), end);
+    assert_eq!(file.position(), end);
+
+    // and the next writes after it
+    file.write_all(b"c").await.unwrap();
+
+    let expected = [existing, copied, b"c".to_vec()].concat();
+    assert_eq!(server.files.lock().unwrap()["/log"], expected);
+}
@@ -0,0 +1,273 @@
+//! Attributes applied to local files by path and by open file, the failures
+//! reported per attribute, and the attributes read with or without following symlinks.
+
+use russh_sftp::protocol::{ApplyAttrsError, Attribute, FileAttributes, FileTime};
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// Temporary directory removed on drop
+struct Dir(PathBuf);
+
+impl Dir {
+    fn new(name: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("russh-sftp-apply-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    /// Creates `name` with `len` bytes
+    fn file(&self, name: &str, len: usize) -> PathBuf {
+        let path = self.0.join(name);
+        fs::write(&path, vec![b'x'; len]).unwrap();
+        path
+    }
+}
+
+impl Drop for Dir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Applies `attrs` by path or through a file opened for reading and writing
+fn apply(attrs: &FileAttributes, path: &Path, by_file: bool) -> io::Result<()> {
+    match by_file {
+        true => attrs.apply_to_file(&File::options().read(true).write(true).open(path)?),
+        false => attrs.apply_to_path(path),
+    }
+}
+
+fn failed(err: &io::Error) -> Vec<Attribute> {
+    let err = err.get_ref().unwrap().downcast_ref::<ApplyAttrsError>();
+    err.unwrap().failures.iter().map(|(a, _)| *a).collect()
+}
+
+#[test]
+fn size_applied() {
+    let dir = Dir::new("size");
+
+    for by_file in [false, true] {
+        let path = dir.file("f", 10);
+        for size in [4, 0, 100] {
+            let attrs = FileAttributes {
+                size: Some(size),
+                ..FileAttributes::empty()
+            };
+            apply(&attrs, &path, by_file).unwrap();
+            assert_eq!(fs::metadata(&path).unwrap().len(), size, "{by_file}");
+        }
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn permissions_applied() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = Dir::new("permissions");
+
+    for by_file in [false, true] {
+        let path = dir.file("f", 1);
+        // the type bits are not part of the permissions
+        for (mode, expected) in [(0o100600, 0o600), (0o640, 0o640), (0o4755, 0o4755)] {
+            let attrs = FileAttributes {
+                permissions: Some(mode),
+                ..FileAttributes::empty()
+            };
+            apply(&attrs, &path, by_file).unwrap();
+
+            let actual = fs::metadata(&path).unwrap().permissions().mode() & 0o7777;
+            assert_eq!(actual, expected, "{mode:o} {by_file}");
+        }
+    }
+}
+
+#[test]
+fn read_only_flag_applied() {
+    let dir = Dir::new("readonly");
+    let path = dir.file("f", 1);
+
+    for (mode, readonly) in [(0o444, true), (0o644, false)] {
+        let attrs = FileAttributes {
+            permissions: Some(mode),
+            ..FileAttributes::empty()
+        };
+        attrs.apply_to_path(&path).unwrap();
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().readonly(),
+            readonly
+        );
+    }
+}
+
+#[test]
+fn times_applied() {
+    let dir = Dir::new("times");
+    let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+
+    for by_file in [false, true] {
+        let path = dir.file("f", 1);
+        let attrs = FileAttributes {
+            atime: Some(FileTime::from_secs(1_000_000)),
+            mtime: Some(FileTime::from_secs(2_000_000)),
+            ..FileAttributes::empty()
+        };
+        apply(&attrs, &path, by_file).unwrap();
+
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(metadata.accessed().unwrap(), at(1_000_000), "{by_file}");
+        assert_eq!(metadata.modified().unwrap(), at(2_000_000), "{by_file}");
+
+        // a single time leaves the other one
+        let attrs = FileAttributes {
+            mtime: Some(FileTime::from_secs(3_000_000)),
+            ..FileAttributes::empty()
+        };
+        apply(&attrs, &path, by_file).unwrap();
+
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(metadata.accessed().unwrap(), at(1_000_000), "{by_file}");
+        assert_eq!(metadata.modified().unwrap(), at(3_000_000), "{by_file}");
+    }
+}
+
+#[test]
+fn times_applied_after_the_size() {
+    let dir = Dir::new("order");
+    let path = dir.file("f", 10);
+
+    let attrs = FileAttributes {
+        size: Some(5),
+        atime: Some(FileTime::from_secs(1_000_000)),
+        mtime: Some(FileTime::from_secs(1_000_000)),
+        ..FileAttributes::empty()
+    };
+    attrs.apply_to_path(&path).unwrap();
+
+    let metadata = fs::metadata(&path).unwrap();
+    assert_eq!(metadata.len(), 5);
+    assert_eq!(
+        metadata.modified().unwrap(),
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000)
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn owner_applied() {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = Dir::new("owner");
+
+    for by_file in [false, true] {
+        let path = dir.file("f", 1);
+        let metadata = fs::metadata(&path).unwrap();
+        // only the superuser can give the file away
+        let (uid, gid) = match metadata.uid() {
+            0 => (1234, 5678),
+            uid => (uid, metadata.gid()),
+        };
+
+        let attrs = FileAttributes {
+            uid: Some(uid),
+            gid: Some(gid),
+            ..FileAttributes::empty()
+        };
+        apply(&attrs, &path, by_file).unwrap();
+
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!((metadata.uid(), metadata.gid()), (uid, gid), "{by_file}");
+    }
+}
+
+// the times of a file opened for reading only can be changed on unix
+#[cfg(unix)]
+#[test]
+fn partial_failures_reported() {
+    let dir = Dir::new("partial");
+    let path = dir.file("f", 10);
+
+    // the size cannot be changed through a file opened for reading only
+    let attrs = FileAttributes {
+        size: Some(1),
+        permissions: Some(0o600),
+        mtime: Some(FileTime::from_secs(1_000_000)),
+        atime: Some(FileTime::from_secs(1_000_000)),
+        ..FileAttributes::empty()
+    };
+    let err = attrs
+        .apply_to_file(&File::open(&path).unwrap())
+        .unwrap_err();
+    assert_eq!(failed(&err), [Attribute::Size]);
+    assert!(err.to_string().starts_with("unable to apply the size: "));
+
+    // the other attributes were applied anyway
+    let metadata = fs::metadata(&path).unwrap();
+    assert_eq!(metadata.len(), 10);
+    assert_eq!(
+        metadata.modified().unwrap(),
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000)
+    );
+}
+
+#[test]
+fn every_failure_reported() {
+    let dir = Dir::new("missing");
+
+    let attrs = FileAttributes {
+        size: Some(1),
+        permissions: Some(0o600),
+        mtime: Some(FileTime::from_secs(1_000_000)),
+        atime: Some(FileTime::from_secs(1_000_000)),
+        ..FileAttributes::empty()
+    };
+    let err = attrs.apply_to_path(dir.0.join("missing")).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    assert_eq!(
+        failed(&err),
+        [Attribute::Size, Attribute::Permissions, Attribute::Times]
+    );
+}
+
+#[test]
+fn nothing_applied_without_attributes() {
+    let dir = Dir::new("empty");
+    let path = dir.file("f", 10);
+    let before = fs::metadata(&path).unwrap();
+
+    FileAttributes::empty().apply_to_path(&path).unwrap();
+    let after = fs::metadata(&path).unwrap();
+    assert_eq!(after.len(), 10);
+    assert_eq!(after.modified().unwrap(), before.modified().unwrap());
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinks_read_with_and_without_following() {
+    let dir = Dir::new("symlink");
+    let target = dir.file("target", 123);
+    let link = dir.0.join("link");
+    std::os::unix::fs::symlink(&target, &link).unwrap();
+
+    let followed = FileAttributes::from_path_symlink_aware(&link, true).unwrap();
+    assert!(followed.file_type().is_file());
+    assert_eq!(followed.size, Some(123));
+
+    let link_itself = FileAttributes::from_path_symlink_aware(&link, false).unwrap();
+    assert!(link_itself.file_type().is_symlink());
+    assert_eq!(
+        link_itself.size,
+        Some(target.as_os_str().len() as u64),
+        "the size of a symlink is the length of its target"
+    );
+
+    // the same for a regular file
+    let file = FileAttributes::from_path_symlink_aware(&target, false).unwrap();
+    assert!(file.file_type().is_file());
+}
@@ -0,0 +1,266 @@
+//! Audit log of a scripted session: the events of the mutating requests with their
+//! outcome, the redaction of the paths, the JSON lines of [`JsonLinesSink`] and the
+//! events dropped while the sink is busy.
+
+mod common;
+
+use async_trait::async_trait;
+use common::Memory;
+use russh_sftp::{
+    client::{Metadata, SftpSession},
+    protocol::{OpenFlags, StatusCode},
+    server::{AuditEvent, AuditLog, AuditOperation, AuditSink, Config, JsonLinesSink},
+};
+use serde_json::Value;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    sync::Semaphore,
+    time,
+};
+
+const IDENTITY: &str = "user@192.0.2.1";
+
+/// Flags of [`SftpSession::create`]
+const CREATED: u32 = OpenFlags::CREATE
+    .union(OpenFlags::TRUNCATE)
+    .union(OpenFlags::WRITE)
+    .bits();
+
+/// Keeps the events, redacting the paths with `redact`
+#[derive(Clone, Default)]
+struct Collect {
+    events: Arc<Mutex<Vec<AuditEvent>>>,
+    redact: Option<fn(&str) -> String>,
+}
+
+#[async_trait]
+impl AuditSink for Collect {
+    async fn record(&self, event: AuditEvent) {
+        self.events.lock().unwrap().push(event);
+    }
+
+    fn redact_path(&self, path: &str) -> String {
+        match self.redact {
+            Some(redact) => redact(path),
+            None => path.to_owned(),
+        }
+    }
+}
+
+/// Sends the mutating requests of the script to [`Memory`], and a read
+async fn requests(config: Config) {
+    let (sftp, _server) = SftpSession::pair_with_config(Memory::default(), config)
+        .await
+        .unwrap();
+
+    let mut file = sftp.create("/file").await.unwrap();
+    file.write_all(b"data").await.unwrap();
+    file.shutdown().await.unwrap();
+
+    // reads are not audited
+    assert_eq!(sftp.read("/file").await.unwrap(), b"data");
+
+    let permissions = Metadata {
+        permissions: Some(0o600),
+        ..Metadata::empty()
+    };
+    assert!(sftp.set_metadata("/file", permissions).await.is_err());
+    sftp.rename("/file", "/moved").await.unwrap();
+    assert!(sftp.remove_file("/missing").await.is_err());
+    sftp.create_dir("/dir").await.unwrap();
+    sftp.remove_dir("/dir").await.unwrap();
+    sftp.close().await.unwrap();
+}
+
+/// Runs the script and returns once `log` recorded its events
+async fn scripted(log: AuditLog) {
+    let config = Config {
+        audit: Some(log.clone()),
+        session_identity: Some(IDENTITY.to_owned()),
+        ..Default::default()
+    };
+    requests(config).await;
+    recorded(&log, 7).await;
+}
+
+async fn recorded(log: &AuditLog, events: u64) {
+    time::timeout(Duration::from_secs(1), async {
+        while log.stats().recorded < events {
+            time::sleep(Duration::from_millis(1)).await;
+        }
+    })
+    .await
+    .expect("the events were not recorded");
+}
+
+#[tokio::test]
+async fn events_of_a_scripted_session() {
+    let sink = Collect::default();
+    let log = AuditLog::new(sink.clone(), 16);
+    scripted(log.clone()).await;
+
+    let events = sink.events.lock().unwrap().clone();
+    let summary: Vec<_> = events
+        .iter()
+        .map(|event| (event.operation.name(), event.status))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            ("SSH_FXP_OPEN", StatusCode::Ok),
+            ("SSH_FXP_WRITE", StatusCode::Ok),
+            ("SSH_FXP_SETSTAT", StatusCode::OpUnsupported),
+            ("SSH_FXP_RENAME", StatusCode::Ok),
+            ("SSH_FXP_REMOVE", StatusCode::NoSuchFile),
+            ("SSH_FXP_MKDIR", StatusCode::Ok),
+            ("SSH_FXP_RMDIR", StatusCode::Ok),
+        ]
+    );
+
+    // one session, in the order of the requests
+    for pair in events.windows(2) {
+        assert!(pair[0].id < pair[1].id, "{pair:?}");
+        assert_eq!(pair[0].session, pair[1].session);
+    }
+    assert!(events
+        .iter()
+        .all(|e| e.identity.as_deref() == Some(IDENTITY)));
+
+    assert!(matches!(
+        &events[0].operation,
+        AuditOperation::Open { path, pflags, handle: Some(handle) }
+            if path == "/file" && handle == "/file"
+                && pflags.bits() == CREATED
+    ));
+    assert!(matches!(
+        &events[1].operation,
+        AuditOperation::Write { handle, path: Some(path), offset: 0, len: 4 }
+            if handle == "/file" && path == "/file"
+    ));
+    assert!(matches!(
+        &events[2].operation,
+        AuditOperation::SetStat { path, attrs }
+            if path == "/file" && attrs.permissions == Some(0o600)
+    ));
+    assert!(matches!(
+        &events[3].operation,
+        AuditOperation::Rename { oldpath, newpath } if oldpath == "/file" && newpath == "/moved"
+    ));
+
+    assert_eq!(log.stats().queued, 7);
+    assert_eq!(log.stats().dropped, 0);
+}
+
+#[tokio::test]
+async fn paths_redacted_before_recorded() {
+    let sink = Collect {
+        redact: Some(|path| format!("<{}>", path.len())),
+        ..Default::default()
+    };
+    scripted(AuditLog::new(sink.clone(), 16)).await;
+
+    let events = sink.events.lock().unwrap().clone();
+    assert!(matches!(
+        &events[1].operation,
+        AuditOperation::Write { handle, path: Some(path), .. } if handle == "/file" && path == "<5>"
+    ));
+    assert!(matches!(
+        &events[3].operation,
+        AuditOperation::Rename { oldpath, newpath } if oldpath == "<5>" && newpath == "<6>"
+    ));
+    assert!(matches!(
+        &events[6].operation,
+        AuditOperation::RmDir { path } if path == "<4>"
+    ));
+}
+
+#[tokio::test]
+async fn json_lines_of_a_scripted_session() {
+    let (writer, reader) = tokio::io::duplex(64 * 1024);
+    let sink = JsonLinesSink::new(writer).redact_paths(|path| path.replace("moved", "***"));
+    scripted(AuditLog::new(sink, 16)).await;
+
+    let mut lines = BufReader::new(reader).lines();
+    let mut events = Vec::new();
+    while events.len() < 7 {
+        let line = lines.next_line().await.unwrap().unwrap();
+        events.push(serde_json::from_str::<Value>(&line).unwrap());
+    }
+
+    let ops: Vec<_> = events.iter().map(|event| event["op"].clone()).collect();
+    assert_eq!(
+        ops,
+        [
+            "SSH_FXP_OPEN",
+            "SSH_FXP_WRITE",
+            "SSH_FXP_SETSTAT",
+            "SSH_FXP_RENAME",
+            "SSH_FXP_REMOVE",
+            "SSH_FXP_MKDIR",
+            "SSH_FXP_RMDIR",
+        ]
+    );
+
+    for event in &events {
+        assert_eq!(event["identity"], IDENTITY);
+        assert!(event["id"].is_u64(), "{event}");
+        assert!(event["session"].is_u64(), "{event}");
+        let time = event["time"].as_str().unwrap();
+        assert!(chrono::DateTime::parse_from_rfc3339(time).is_ok(), "{time}");
+    }
+
+    assert_eq!(events[0]["path"], "/file");
+    assert_eq!(events[0]["pflags"], CREATED);
+    assert_eq!(events[0]["handle"], "/file");
+    assert_eq!(events[0]["status"], "Ok");
+
+    assert_eq!(events[1]["offset"], 0);
+    assert_eq!(events[1]["len"], 4);
+    assert_eq!(events[1]["path"], "/file");
+
+    assert_eq!(events[2]["status"], "OpUnsupported");
+    assert_eq!(
+        events[2]["attrs"],
+        serde_json::json!({ "permissions": 0o600 })
+    );
+
+    assert_eq!(events[3]["oldpath"], "/file");
+    assert_eq!(events[3]["newpath"], "/***");
+    assert_eq!(events[4]["status"], "NoSuchFile");
+    assert_eq!(events[5]["path"], "/dir");
+}
+
+/// Records one event at a time once permitted
+struct Blocked(Arc<Semaphore>);
+
+#[async_trait]
+impl AuditSink for Blocked {
+    async fn record(&self, _event: AuditEvent) {
+        self.0.acquire().await.unwrap().forget();
+    }
+}
+
+#[tokio::test]
+async fn events_dropped_while_the_sink_is_busy() {
+    let permits = Arc::new(Semaphore::new(0));
+    let log = AuditLog::new(Blocked(permits.clone()), 1);
+    requests(Config {
+        audit: Some(log.clone()),
+        ..Default::default()
+    })
+    .await;
+
+    // the sink holds at most one event and the queue another one
+    let stats = log.stats();
+    assert_eq!(stats.queued + stats.dropped, 7);
+    assert!(stats.queued <= 2, "{stats:?}");
+    assert_eq!(stats.recorded, 0);
+
+    permits.add_permits(7);
+    recorded(&log, stats.queued).await;
+    assert_eq!(log.stats().dropped, stats.dropped);
+}
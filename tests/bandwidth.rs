@@ -0,0 +1,111 @@
+//! Pacing of the transfers by the bandwidth limit, on a paused clock.
+
+mod common;
+
+use common::Memory;
+use russh_sftp::{
+    client::{
+        fs::{Direction, File},
+        SftpSession, TransferJob, TransferScheduler,
+    },
+    server,
+};
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    io::AsyncWriteExt,
+    time::{self, Instant},
+};
+
+const CHUNK: usize = 10_000;
+
+async fn connect(memory: &Memory) -> SftpSession {
+    let (client, server) = tokio::io::duplex(256 * 1024);
+    server::run(server, memory.clone()).await;
+    SftpSession::new(client).await.unwrap()
+}
+
+async fn create(sftp: &SftpSession) -> File {
+    let file = sftp.create("/file").await.unwrap();
+    file.pin_chunk_size(CHUNK);
+    file
+}
+
+fn millis(from: Instant) -> u128 {
+    from.elapsed().as_millis()
+}
+
+#[tokio::test(start_paused = true)]
+async fn chunks_paced_within_second() {
+    let memory = Memory::default();
+    let sftp = connect(&memory).await;
+    sftp.set_bandwidth_limit(Some(100_000), Direction::Write);
+
+    let mut file = create(&sftp).await;
+    let started = Instant::now();
+    let mut sent = Vec::new();
+    for _ in 0..5 {
+        file.write_all(&[1; CHUNK]).await.unwrap();
+        sent.push(millis(started));
+    }
+
+    // 100 ms of quota at once, then a chunk every 100 ms
+    assert_eq!(sent, [0, 0, 100, 200, 300]);
+    assert_eq!(memory.files.lock().unwrap()["/file"].len(), 5 * CHUNK);
+}
+
+#[tokio::test(start_paused = true)]
+async fn transfers_limited_per_direction() {
+    let memory = Memory::default();
+    memory
+        .files
+        .lock()
+        .unwrap()
+        .insert("/remote".to_owned(), vec![7; 1_000_000]);
+
+    let sftp = connect(&memory).await;
+    sftp.set_bandwidth_limit(Some(200_000), Direction::Read);
+    assert_eq!(sftp.bandwidth_limit(Direction::Read), Some(200_000));
+    assert_eq!(sftp.bandwidth_limit(Direction::Write), None);
+
+    let local = std::env::temp_dir().join(format!("russh-sftp-bandwidth-{}", std::process::id()));
+    let started = Instant::now();
+    let mut scheduler = TransferScheduler::new(&sftp);
+    scheduler.add(TransferJob::download("/remote", &local));
+    let results = scheduler.run().await;
+    let elapsed = millis(started);
+
+    assert_eq!(results[0].as_ref().unwrap(), &1_000_000);
+    assert_eq!(std::fs::read(&local).unwrap(), vec![7; 1_000_000]);
+    std::fs::remove_file(&local).unwrap();
+
+    // the last of the four chunks of 255 KB waits for the first three,
+    // less the 20 KB accumulated before the transfer, at 200 KB/s
+    assert!((3800..3850).contains(&elapsed), "{elapsed} ms");
+
+    // uploads are not limited
+    let started = Instant::now();
+    let mut file = create(&sftp).await;
+    file.write_all(&[1; 10 * CHUNK]).await.unwrap();
+    assert_eq!(millis(started), 0);
+}
+
+#[tokio::test(start_paused = true)]
+async fn limit_changed_during_transfer() {
+    let memory = Memory::default();
+    let sftp = Arc::new(connect(&memory).await);
+    sftp.set_bandwidth_limit(Some(10_000), Direction::Write);
+
+    let mut file = create(&sftp).await;
+    let started = Instant::now();
+    let upload = tokio::spawn(async move { file.write_all(&[1; 20 * CHUNK]).await });
+
+    // the third chunk waits for one second per chunk
+    time::sleep(Duration::from_millis(1500)).await;
+    sftp.set_bandwidth_limit(Some(1_000_000), Direction::Write);
+    time::sleep(Duration::from_millis(50)).await;
+    sftp.set_bandwidth_limit(None, Direction::Write);
+
+    upload.await.unwrap().unwrap();
+    assert!((1500..1600).contains(&millis(started)));
+    assert_eq!(memory.files.lock().unwrap()["/file"].len(), 20 * CHUNK);
+}
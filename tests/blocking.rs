@@ -0,0 +1,103 @@
+//! Runs the common operations through [`BlockingSftp`] against an in-memory server.
+
+mod common;
+
+use common::Memory;
+use russh_sftp::{client::blocking::BlockingSftp, server};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+fn connect() -> (BlockingSftp, Memory) {
+    let memory = Memory::default();
+    let handler = memory.clone();
+    let sftp = BlockingSftp::connect(|| async move {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        server::run(server, handler).await;
+        Ok::<_, io::Error>(client)
+    })
+    .unwrap();
+
+    (sftp, memory)
+}
+
+#[test]
+fn read_write() {
+    let (sftp, memory) = connect();
+
+    sftp.create("/hello.txt").unwrap();
+    sftp.write("/hello.txt", b"hello world").unwrap();
+    assert_eq!(sftp.read("/hello.txt").unwrap(), b"hello world");
+    assert_eq!(memory.files.lock().unwrap()["/hello.txt"], b"hello world");
+
+    assert!(sftp.read("/missing").is_err());
+    sftp.close().unwrap();
+}
+
+#[test]
+fn file_read_write_seek() {
+    let (sftp, _) = connect();
+
+    let mut file = sftp.create("/file").unwrap();
+    file.write_all(&[7; 100_000]).unwrap();
+    file.seek(SeekFrom::Start(10)).unwrap();
+    file.write_all(b"marker").unwrap();
+    file.flush().unwrap();
+    assert_eq!(file.metadata().unwrap().size, Some(100_000));
+    drop(file);
+
+    let mut file = sftp.open("/file").unwrap();
+    let mut data = Vec::new();
+    file.read_to_end(&mut data).unwrap();
+    assert_eq!(data.len(), 100_000);
+    assert_eq!(&data[10..16], b"marker");
+
+    file.seek(SeekFrom::End(-4)).unwrap();
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail).unwrap();
+    assert_eq!(tail, [7; 4]);
+}
+
+#[test]
+fn directories() {
+    let (sftp, _) = connect();
+
+    sftp.create_dir("/dir").unwrap();
+    sftp.create("/dir/a").unwrap().write_all(b"a").unwrap();
+    sftp.create("/dir/b").unwrap().write_all(b"bb").unwrap();
+    assert!(sftp.metadata("/dir").unwrap().is_dir());
+
+    let mut entries = sftp
+        .read_dir("/dir")
+        .unwrap()
+        .map(|entry| (entry.file_name(), entry.metadata().size))
+        .collect::<Vec<_>>();
+    entries.sort();
+    assert_eq!(
+        entries,
+        [("a".to_owned(), Some(1)), ("b".to_owned(), Some(2))]
+    );
+
+    sftp.rename("/dir/a", "/dir/c").unwrap();
+    assert!(!sftp.try_exists("/dir/a").unwrap());
+    assert!(sftp.try_exists("/dir/c").unwrap());
+
+    sftp.remove_file("/dir/b").unwrap();
+    sftp.remove_file("/dir/c").unwrap();
+    sftp.remove_dir("/dir").unwrap();
+    assert!(!sftp.try_exists("/dir").unwrap());
+}
+
+#[test]
+fn within_runtime() {
+    let (sftp, _) = connect();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    runtime.block_on(async move {
+        assert!(BlockingSftp::connect(|| async { Ok(tokio::io::duplex(1024).0) }).is_err());
+        assert!(sftp.read("/missing").is_err());
+
+        // the session is closed without blocking and its runtime is shut down in the background
+        drop(sftp);
+    });
+}
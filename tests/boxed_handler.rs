@@ -0,0 +1,194 @@
+//! Handlers selected at runtime from a registry of plugins and served as a
+//! [`BoxedHandler`], with their own error types converted into statuses.
+
+mod common;
+
+use common::Memory;
+use russh_sftp::{
+    client::{error::Error, SftpSession},
+    protocol::{
+        Attrs, Data, File, FileAttributes, Handle, Name, OpenFlags, RequestId, Status, StatusCode,
+        Version,
+    },
+    server::{BoxedHandler, EndReason, Handler, StatusError},
+};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+const MOTD: &[u8] = b"welcome\n";
+
+/// Serves [`MOTD`] at `/motd` and denies writes with a message
+#[derive(Clone, Default)]
+struct ReadOnly {
+    ended: Arc<AtomicBool>,
+}
+
+impl ReadOnly {
+    fn attrs(path: &str) -> Result<FileAttributes, StatusError> {
+        match path {
+            "/motd" => Ok(FileAttributes {
+                size: Some(MOTD.len() as u64),
+                permissions: Some(0o100444),
+                ..FileAttributes::empty()
+            }),
+            _ => Err(StatusCode::NoSuchFile.into()),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Handler for ReadOnly {
+    type Error = StatusError;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusError::new(StatusCode::OpUnsupported, "not supported by the plugin")
+    }
+
+    async fn session_ended(&mut self, _reason: EndReason) {
+        self.ended.store(true, Ordering::Relaxed);
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn open(
+        &mut self,
+        id: RequestId,
+        filename: String,
+        pflags: OpenFlags,
+        _attrs: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        if pflags.intersects(OpenFlags::WRITE | OpenFlags::APPEND | OpenFlags::CREATE) {
+            return Err(StatusError::new(
+                StatusCode::PermissionDenied,
+                "read-only plugin",
+            ));
+        }
+
+        Self::attrs(&filename)?;
+        Ok(Handle {
+            id,
+            handle: filename,
+        })
+    }
+
+    async fn close(&mut self, id: RequestId, _handle: String) -> Result<Status, Self::Error> {
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+
+    async fn read(
+        &mut self,
+        id: RequestId,
+        _handle: String,
+        offset: u64,
+        len: u32,
+    ) -> Result<Data, Self::Error> {
+        let start = MOTD.len().min(offset as usize);
+        if start == MOTD.len() {
+            return Err(StatusCode::Eof.into());
+        }
+
+        let end = MOTD.len().min(start + len as usize);
+        Ok(Data {
+            id,
+            data: MOTD[start..end].to_vec(),
+        })
+    }
+
+    async fn fstat(&mut self, id: RequestId, handle: String) -> Result<Attrs, Self::Error> {
+        let attrs = Self::attrs(&handle)?;
+        Ok(Attrs { id, attrs })
+    }
+
+    async fn stat(&mut self, id: RequestId, path: String) -> Result<Attrs, Self::Error> {
+        let attrs = Self::attrs(&path)?;
+        Ok(Attrs { id, attrs })
+    }
+
+    async fn realpath(&mut self, id: RequestId, path: String) -> Result<Name, Self::Error> {
+        Ok(Name {
+            id,
+            files: vec![File::dummy(path)],
+        })
+    }
+}
+
+/// Registry of the plugins by name
+fn plugin(name: &str, read_only: &ReadOnly) -> Option<BoxedHandler> {
+    match name {
+        "memory" => Some(Memory::default().into_boxed()),
+        "read-only" => Some(read_only.clone().into_boxed()),
+        _ => None,
+    }
+}
+
+fn status(result: Result<impl std::fmt::Debug, Error>) -> (StatusCode, String) {
+    match result {
+        Err(Error::Status { status, .. }) => (status.status_code, status.error_message),
+        result => panic!("{result:?}"),
+    }
+}
+
+#[tokio::test]
+async fn plugins_selected_at_runtime() {
+    let read_only = ReadOnly::default();
+    assert!(plugin("missing", &read_only).is_none());
+
+    for name in ["read-only", "memory"] {
+        let handler = plugin(name, &read_only).unwrap();
+        let (sftp, server) = SftpSession::pair(handler).await.unwrap();
+
+        let written = sftp.write("/motd", b"changed\n").await;
+        let metadata = sftp.metadata("/missing").await;
+        let renamed = sftp.rename("/motd", "/other").await;
+
+        match name {
+            "memory" => {
+                // the file does not exist, write does not create it
+                assert_eq!(status(written).0, StatusCode::NoSuchFile);
+                sftp.create("/motd").await.unwrap();
+                sftp.write("/motd", MOTD).await.unwrap();
+                assert_eq!(status(metadata).0, StatusCode::NoSuchFile);
+                renamed.unwrap_err();
+                sftp.rename("/motd", "/other").await.unwrap();
+                assert_eq!(sftp.read("/other").await.unwrap(), MOTD);
+            }
+            _ => {
+                // the messages of the plugin reach the client
+                let denied = (StatusCode::PermissionDenied, "read-only plugin".to_owned());
+                assert_eq!(status(written), denied);
+                assert_eq!(status(metadata).0, StatusCode::NoSuchFile);
+                let unsupported = "not supported by the plugin".to_owned();
+                assert_eq!(status(renamed), (StatusCode::OpUnsupported, unsupported));
+                assert_eq!(sftp.read("/motd").await.unwrap(), MOTD);
+            }
+        }
+
+        sftp.close().await.unwrap();
+        server.await.unwrap().unwrap();
+    }
+
+    // the end of the session reached the plugin through the box
+    assert!(read_only.ended.load(Ordering::Relaxed));
+}
+
+#[tokio::test]
+async fn boxed_handler_served_as_is() {
+    let server = Memory::default();
+    let handler: Box<Memory> = Box::new(server.clone());
+    let (sftp, _server) = SftpSession::pair(handler).await.unwrap();
+
+    sftp.create("/file").await.unwrap();
+    sftp.write("/file", b"data").await.unwrap();
+    assert_eq!(server.files.lock().unwrap()["/file"], b"data");
+}
@@ -0,0 +1,224 @@
+//! Two clients locking byte-ranges of the same file through block@russh-sftp.rs.
+
+mod common;
+
+use common::Memory;
+use russh_sftp::{
+    client::{error::Error, SftpSession},
+    extensions::{self, LockFlags},
+    protocol::{FileAttributes, Handle, OpenFlags, RequestId, Status, StatusCode, Version},
+    server::Handler,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::io::AsyncWriteExt;
+
+struct Lock {
+    owner: u32,
+    path: String,
+    offset: u64,
+    /// Exclusive end, [`u64::MAX`] up to the end of the file
+    end: u64,
+    flags: LockFlags,
+}
+
+fn end(offset: u64, length: u64) -> u64 {
+    match length {
+        0 => u64::MAX,
+        length => offset.saturating_add(length),
+    }
+}
+
+/// Keeps the locks of all the sessions, the handles are the paths
+#[derive(Clone)]
+struct Locker {
+    owner: u32,
+    locks: Arc<Mutex<Vec<Lock>>>,
+}
+
+#[async_trait::async_trait]
+impl Handler for Locker {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    fn supported_extensions(&self) -> &'static [&'static str] {
+        &[extensions::BLOCK, extensions::UNBLOCK]
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn open(
+        &mut self,
+        id: RequestId,
+        filename: String,
+        _pflags: OpenFlags,
+        _attrs: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        Ok(Handle {
+            id,
+            handle: filename,
+        })
+    }
+
+    async fn close(&mut self, id: RequestId, handle: String) -> Result<Status, Self::Error> {
+        // the locks are released with the handle
+        let mut locks = self.locks.lock().unwrap();
+        locks.retain(|lock| lock.owner != self.owner || lock.path != handle);
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+
+    async fn block(
+        &mut self,
+        id: RequestId,
+        handle: String,
+        offset: u64,
+        length: u64,
+        lock_flags: LockFlags,
+    ) -> Result<Status, Self::Error> {
+        let end = end(offset, length);
+        let mut locks = self.locks.lock().unwrap();
+
+        // shared unless one of them is a write lock
+        let conflict = locks.iter().any(|lock| {
+            lock.owner != self.owner
+                && lock.path == handle
+                && lock.offset < end
+                && offset < lock.end
+                && (lock.flags | lock_flags).contains(LockFlags::WRITE)
+        });
+        if conflict {
+            return Err(StatusCode::Failure);
+        }
+
+        locks.push(Lock {
+            owner: self.owner,
+            path: handle,
+            offset,
+            end,
+            flags: lock_flags,
+        });
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+
+    async fn unblock(
+        &mut self,
+        id: RequestId,
+        handle: String,
+        offset: u64,
+        length: u64,
+    ) -> Result<Status, Self::Error> {
+        let end = end(offset, length);
+        let mut locks = self.locks.lock().unwrap();
+        let held = locks.iter().position(|lock| {
+            lock.owner == self.owner
+                && lock.path == handle
+                && (lock.offset, lock.end) == (offset, end)
+        });
+
+        match held {
+            Some(held) => {
+                locks.remove(held);
+                Ok(Status::new(id, StatusCode::Ok))
+            }
+            None => Err(StatusCode::NoSuchFile),
+        }
+    }
+}
+
+/// Two sessions sharing the locks
+async fn clients() -> (SftpSession, SftpSession) {
+    let locks = Arc::new(Mutex::new(Vec::new()));
+    let mut sessions = Vec::new();
+    for owner in [1, 2] {
+        let handler = Locker {
+            owner,
+            locks: locks.clone(),
+        };
+        let (sftp, _server) = SftpSession::pair(handler).await.unwrap();
+        sessions.push(sftp);
+    }
+
+    let second = sessions.pop().unwrap();
+    (sessions.pop().unwrap(), second)
+}
+
+fn assert_conflict(result: Result<(), Error>) {
+    match result {
+        Err(Error::Status { status, .. }) => assert_eq!(status.status_code, StatusCode::Failure),
+        result => panic!("{result:?}"),
+    }
+}
+
+#[tokio::test]
+async fn write_locks_exclude_each_other() {
+    let (first, second) = clients().await;
+    let a = first.open("/shared").await.unwrap();
+    let b = second.open("/shared").await.unwrap();
+
+    a.lock_range(0, 100, LockFlags::WRITE).await.unwrap();
+    assert_conflict(b.lock_range(50, 10, LockFlags::WRITE).await);
+    assert_conflict(b.lock_range(99, 0, LockFlags::READ).await);
+
+    // disjoint ranges are independent
+    b.lock_range(100, 100, LockFlags::WRITE).await.unwrap();
+    assert_conflict(a.lock_range(150, 1, LockFlags::WRITE).await);
+
+    a.unlock_range(0, 100).await.unwrap();
+    b.lock_range(50, 10, LockFlags::WRITE).await.unwrap();
+}
+
+#[tokio::test]
+async fn read_locks_are_shared() {
+    let (first, second) = clients().await;
+    let a = first.open("/shared").await.unwrap();
+    let b = second.open("/shared").await.unwrap();
+
+    a.lock_range(0, 0, LockFlags::READ).await.unwrap();
+    b.lock_range(10, 10, LockFlags::READ).await.unwrap();
+    assert_conflict(b.lock_range(1000, 10, LockFlags::WRITE).await);
+
+    // other files are not locked
+    let c = second.open("/other").await.unwrap();
+    c.lock_range(0, 0, LockFlags::WRITE).await.unwrap();
+}
+
+#[tokio::test]
+async fn released_when_the_handle_is_closed() {
+    let (first, second) = clients().await;
+    let mut a = first.open("/shared").await.unwrap();
+    let b = second.open("/shared").await.unwrap();
+
+    a.lock_range(0, 100, LockFlags::WRITE).await.unwrap();
+    assert_conflict(b.lock_range(0, 100, LockFlags::WRITE).await);
+
+    a.shutdown().await.unwrap();
+    b.lock_range(0, 100, LockFlags::WRITE).await.unwrap();
+}
+
+#[tokio::test]
+async fn unsupported_by_other_servers() {
+    let (sftp, _server) = SftpSession::pair(Memory::default()).await.unwrap();
+    sftp.create("/file").await.unwrap();
+    let file = sftp.open("/file").await.unwrap();
+
+    for result in [
+        file.lock_range(0, 10, LockFlags::WRITE).await,
+        file.unlock_range(0, 10).await,
+    ] {
+        assert!(
+            matches!(result, Err(Error::ExtensionUnsupported { .. })),
+            "{result:?}"
+        );
+    }
+}
@@ -0,0 +1,130 @@
+//! Reads through [`CachedFile`] against an in-memory server.
+
+mod common;
+
+use common::Memory;
+use russh_sftp::{
+    client::{fs::CachedFile, SftpSession},
+    server,
+};
+use std::sync::atomic::Ordering;
+
+const LEN: usize = 10_000;
+const PAGE: u32 = 1024;
+
+fn pattern() -> Vec<u8> {
+    (0..LEN).map(|i| (i % 251) as u8).collect()
+}
+
+async fn open(capacity: usize) -> (CachedFile, Memory) {
+    let memory = Memory::default();
+    memory
+        .files
+        .lock()
+        .unwrap()
+        .insert("/index".to_owned(), pattern());
+
+    let (client, server) = tokio::io::duplex(256 * 1024);
+    server::run(server, memory.clone()).await;
+
+    let sftp = SftpSession::new(client).await.unwrap();
+    let file = sftp.open("/index").await.unwrap();
+    (CachedFile::with_pages(file, PAGE, capacity), memory)
+}
+
+async fn read(file: &mut CachedFile, offset: u64, len: usize) -> Vec<u8> {
+    let mut buf = vec![0; len];
+    let read = file.read_at(offset, &mut buf).await.unwrap();
+    buf.truncate(read);
+    buf
+}
+
+#[tokio::test]
+async fn overlapping_reads() {
+    let (mut file, memory) = open(16).await;
+    let data = pattern();
+
+    // crosses the boundary of the first two pages
+    assert_eq!(read(&mut file, 1000, 100).await, data[1000..1100]);
+    assert_eq!(memory.reads.load(Ordering::Relaxed), 2);
+
+    assert_eq!(read(&mut file, 1020, 10).await, data[1020..1030]);
+    assert_eq!(read(&mut file, 0, 2048).await, data[..2048]);
+    assert_eq!(memory.reads.load(Ordering::Relaxed), 2);
+
+    // one page cached and three missing
+    assert_eq!(read(&mut file, 1500, 3000).await, data[1500..4500]);
+    assert_eq!(memory.reads.load(Ordering::Relaxed), 5);
+
+    let stats = file.stats();
+    assert_eq!((stats.hits, stats.misses, stats.len), (5, 5, 5));
+}
+
+#[tokio::test]
+async fn end_of_file() {
+    let (mut file, _) = open(16).await;
+    let data = pattern();
+
+    assert_eq!(read(&mut file, 9990, 100).await, data[9990..]);
+    assert_eq!(read(&mut file, 9000, 2000).await, data[9000..]);
+    assert!(read(&mut file, 20_000, 10).await.is_empty());
+    assert!(read(&mut file, LEN as u64, 10).await.is_empty());
+}
+
+#[tokio::test]
+async fn invalidate() {
+    let (mut file, memory) = open(16).await;
+    assert_eq!(read(&mut file, 0, 4).await, [0, 1, 2, 3]);
+
+    memory.files.lock().unwrap().get_mut("/index").unwrap()[..4].fill(9);
+    assert_eq!(read(&mut file, 0, 4).await, [0, 1, 2, 3]);
+
+    file.invalidate();
+    assert_eq!(read(&mut file, 0, 4).await, [9; 4]);
+
+    memory.files.lock().unwrap().get_mut("/index").unwrap()[1024..1028].fill(8);
+    file.invalidate_range(1020, 5);
+    assert_eq!(read(&mut file, 0, 4).await, [9; 4]);
+    assert_eq!(read(&mut file, 1024, 4).await, [8; 4]);
+    assert_eq!(file.stats().misses, 4);
+}
+
+#[tokio::test]
+async fn writes_invalidate() {
+    let (mut file, memory) = open(16).await;
+    let mut data = pattern();
+    assert_eq!(read(&mut file, 0, LEN).await, data);
+
+    file.write_at(1020, b"written").await.unwrap();
+    data[1020..1027].copy_from_slice(b"written");
+    assert_eq!(read(&mut file, 1000, 100).await, data[1000..1100]);
+
+    // the page at the end of file grows
+    file.write_at(LEN as u64 + 10, b"tail").await.unwrap();
+    data.extend_from_slice(&[0; 10]);
+    data.extend_from_slice(b"tail");
+    assert_eq!(read(&mut file, 9000, 2000).await, data[9000..]);
+    assert_eq!(memory.files.lock().unwrap()["/index"], data);
+}
+
+#[tokio::test]
+async fn least_recently_used() {
+    let (mut file, memory) = open(2).await;
+
+    read(&mut file, 0, 1).await;
+    read(&mut file, 1024, 1).await;
+    read(&mut file, 0, 1).await;
+    read(&mut file, 2048, 1).await;
+    assert_eq!(memory.reads.load(Ordering::Relaxed), 3);
+
+    // the second page was evicted, the first one was used more recently
+    read(&mut file, 0, 1).await;
+    assert_eq!(memory.reads.load(Ordering::Relaxed), 3);
+    read(&mut file, 1024, 1).await;
+    assert_eq!(memory.reads.load(Ordering::Relaxed), 4);
+
+    let (mut file, memory) = open(0).await;
+    read(&mut file, 0, 1).await;
+    read(&mut file, 0, 1).await;
+    assert_eq!(memory.reads.load(Ordering::Relaxed), 2);
+}
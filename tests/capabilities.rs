@@ -0,0 +1,259 @@
+//! Extensions advertised by the server, absent or rejected as unsupported.
+
+use russh_sftp::{
+    client::{error::Error, SftpSession},
+    extensions::{self, Capability, LimitsExtension},
+    protocol::{
+        ExtendedReply, FileAttributes, Handle, OpenFlags, Packet, RequestId, Status, StatusCode,
+        Version,
+    },
+    ser,
+    server::{self, Handler},
+};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+use tokio::io::AsyncWriteExt;
+
+/// Advertises fsync as a proxy forwarding the version packet would, but rejects it
+struct Proxy {
+    fsyncs: Arc<AtomicU64>,
+}
+
+#[async_trait::async_trait]
+impl Handler for Proxy {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    fn supported_extensions(&self) -> &'static [&'static str] {
+        &[extensions::FSYNC]
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn open(
+        &mut self,
+        id: RequestId,
+        filename: String,
+        _pflags: OpenFlags,
+        _attrs: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        Ok(Handle {
+            id,
+            handle: filename,
+        })
+    }
+
+    async fn close(&mut self, id: RequestId, _handle: String) -> Result<Status, Self::Error> {
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+
+    async fn write(
+        &mut self,
+        id: RequestId,
+        _handle: String,
+        _offset: u64,
+        _data: Vec<u8>,
+    ) -> Result<Status, Self::Error> {
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+
+    async fn fsync(&mut self, _id: RequestId, _handle: String) -> Result<Status, Self::Error> {
+        self.fsyncs.fetch_add(1, Ordering::Relaxed);
+        Err(StatusCode::OpUnsupported)
+    }
+}
+
+#[tokio::test]
+async fn rejected_fsync_disabled() {
+    let fsyncs = Arc::new(AtomicU64::new(0));
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    server::run(
+        server,
+        Proxy {
+            fsyncs: fsyncs.clone(),
+        },
+    )
+    .await;
+
+    let sftp = SftpSession::new(client).await.unwrap();
+    assert!(sftp.has_capability(Capability::Fsync));
+
+    let mut file = sftp.create("/file").await.unwrap();
+    for _ in 0..3 {
+        file.write_all(b"data").await.unwrap();
+        file.flush().await.unwrap();
+    }
+    file.sync_all().await.unwrap();
+
+    // only the first flush was sent, the clones of the session see the rejection
+    assert_eq!(fsyncs.load(Ordering::Relaxed), 1);
+    assert!(!sftp.clone().has_capability(Capability::Fsync));
+    assert_eq!(sftp.rejected_capabilities(), [Capability::Fsync]);
+    assert!(sftp.require_capability(Capability::Fsync).is_err());
+
+    sftp.reset_rejected_capabilities();
+    assert!(sftp.has_capability(Capability::Fsync));
+    file.sync_all().await.unwrap();
+    assert_eq!(fsyncs.load(Ordering::Relaxed), 2);
+    assert_eq!(sftp.rejected_capabilities(), [Capability::Fsync]);
+}
+
+const ALL: [Capability; 6] = [
+    Capability::Limits,
+    Capability::Hardlink,
+    Capability::Fsync,
+    Capability::Statvfs,
+    Capability::Block,
+    Capability::HomeDirectory,
+];
+
+/// Advertises the extensions of `capabilities` in SSH_FXP_VERSION
+#[derive(Clone, Default)]
+struct Advertiser {
+    capabilities: Vec<Capability>,
+    links: Arc<Mutex<Vec<(String, String)>>>,
+}
+
+#[async_trait::async_trait]
+impl Handler for Advertiser {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        let mut version = Version::new();
+        for capability in &self.capabilities {
+            let value = match capability {
+                Capability::Statvfs => "2",
+                _ => "1",
+            };
+            version = version.with_extension(capability.name(), value);
+            if *capability == Capability::Block {
+                version = version.with_extension(extensions::UNBLOCK, "1");
+            }
+        }
+        Ok(version)
+    }
+
+    async fn hardlink(
+        &mut self,
+        id: RequestId,
+        oldpath: String,
+        newpath: String,
+    ) -> Result<Status, Self::Error> {
+        self.links.lock().unwrap().push((oldpath, newpath));
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+
+    async fn extended(
+        &mut self,
+        id: RequestId,
+        request: String,
+        _data: Vec<u8>,
+    ) -> Result<Packet, Self::Error> {
+        if request != extensions::LIMITS {
+            return Err(StatusCode::OpUnsupported);
+        }
+
+        let limits = LimitsExtension {
+            max_packet_len: 34000,
+            max_read_len: 32768,
+            max_write_len: 32768,
+            max_open_handles: 64,
+        };
+        Ok(Packet::ExtendedReply(ExtendedReply {
+            id,
+            data: ser::to_bytes(&limits).unwrap().to_vec(),
+        }))
+    }
+}
+
+async fn advertising(capabilities: &[Capability]) -> (SftpSession, Advertiser) {
+    let server = Advertiser {
+        capabilities: capabilities.to_vec(),
+        ..Default::default()
+    };
+    let (sftp, _server) = SftpSession::pair(server.clone()).await.unwrap();
+    (sftp, server)
+}
+
+fn unsupported(result: Result<impl std::fmt::Debug, Error>, capability: Capability) {
+    match result {
+        Err(Error::ExtensionUnsupported { name }) => assert_eq!(name, capability.name()),
+        result => panic!("{capability:?}: {result:?}"),
+    }
+}
+
+#[tokio::test]
+async fn each_capability_advertised_alone() {
+    for capability in ALL {
+        let (sftp, _server) = advertising(&[capability]).await;
+
+        for other in ALL {
+            assert_eq!(sftp.has_capability(other), other == capability, "{other:?}");
+            if other == capability {
+                sftp.require_capability(other).unwrap();
+            } else {
+                unsupported(sftp.require_capability(other), other);
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn nothing_advertised() {
+    let (sftp, server) = advertising(&[]).await;
+
+    for capability in ALL {
+        assert!(!sftp.has_capability(capability));
+        unsupported(sftp.require_capability(capability), capability);
+    }
+
+    unsupported(sftp.hard_link("/a", "/b").await, Capability::Hardlink);
+    unsupported(sftp.statvfs("/").await, Capability::Statvfs);
+    assert!(server.links.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn hard_link_when_advertised() {
+    let (sftp, server) = advertising(&[Capability::Hardlink]).await;
+
+    sftp.hard_link("/a", "/b").await.unwrap();
+    assert_eq!(
+        *server.links.lock().unwrap(),
+        [("/a".to_owned(), "/b".to_owned())]
+    );
+}
+
+#[tokio::test]
+#[allow(deprecated)]
+async fn deprecated_methods_keep_their_results() {
+    let (sftp, server) = advertising(&[]).await;
+    assert!(!sftp.hardlink("/a", "/b").await.unwrap());
+    assert!(sftp.fs_info("/").await.unwrap().is_none());
+    assert!(server.links.lock().unwrap().is_empty());
+
+    let (sftp, server) = advertising(&[Capability::Hardlink]).await;
+    assert!(sftp.hardlink("/a", "/b").await.unwrap());
+    assert_eq!(server.links.lock().unwrap().len(), 1);
+}
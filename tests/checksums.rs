@@ -0,0 +1,283 @@
+//! Checksum manifests of transfers: merging and storing them, the chunks of short
+//! reads, and downloads resumed and verified against the manifest of a previous attempt.
+
+mod common;
+
+use common::Local;
+use russh_sftp::{
+    client::{
+        ChecksumManifest, ChunkChecksum, SftpSession, TransferEvent, TransferJob, TransferScheduler,
+    },
+    protocol::{
+        Attrs, Data, FileAttributes, Handle, OpenFlags, RequestId, Status, StatusCode, Version,
+    },
+    server::Handler,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+const SIZE: usize = 10_000;
+const CHUNK: usize = 1000;
+
+fn contents() -> Vec<u8> {
+    (0..SIZE).map(|i| (i * 31 % 251) as u8).collect()
+}
+
+/// Checksums of `data` in chunks of `len`
+fn manifest(data: &[u8], len: usize) -> ChecksumManifest {
+    let mut manifest = ChecksumManifest::new();
+    for (i, chunk) in data.chunks(len).enumerate() {
+        manifest.insert(ChunkChecksum::new((i * len) as u64, chunk));
+    }
+    manifest
+}
+
+/// Serves one file, answering reads with at most `max_read` bytes
+#[derive(Clone)]
+struct Remote {
+    data: Arc<Vec<u8>>,
+    max_read: usize,
+    /// Offsets of the reads received
+    reads: Arc<Mutex<Vec<u64>>>,
+}
+
+impl Remote {
+    fn new(data: Vec<u8>, max_read: usize) -> Self {
+        Self {
+            data: Arc::new(data),
+            max_read,
+            reads: Default::default(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Handler for Remote {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn open(
+        &mut self,
+        id: RequestId,
+        filename: String,
+        _pflags: OpenFlags,
+        _attrs: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        Ok(Handle {
+            id,
+            handle: filename,
+        })
+    }
+
+    async fn close(&mut self, id: RequestId, _handle: String) -> Result<Status, Self::Error> {
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+
+    async fn read(
+        &mut self,
+        id: RequestId,
+        _handle: String,
+        offset: u64,
+        len: u32,
+    ) -> Result<Data, Self::Error> {
+        self.reads.lock().unwrap().push(offset);
+
+        let start = self.data.len().min(offset as usize);
+        if start == self.data.len() {
+            return Err(StatusCode::Eof);
+        }
+
+        let end = self.data.len().min(start + self.max_read.min(len as usize));
+        Ok(Data {
+            id,
+            data: self.data[start..end].to_vec(),
+        })
+    }
+
+    async fn fstat(&mut self, id: RequestId, _handle: String) -> Result<Attrs, Self::Error> {
+        Ok(Attrs {
+            id,
+            attrs: FileAttributes {
+                size: Some(self.data.len() as u64),
+                ..FileAttributes::empty()
+            },
+        })
+    }
+}
+
+/// Downloads `/file` and returns the result with the checksums reported
+async fn download(
+    remote: Remote,
+    local: &Local,
+    manifest: Option<ChecksumManifest>,
+) -> (Result<u64, String>, Vec<ChunkChecksum>) {
+    let (sftp, _server) = SftpSession::pair(remote).await.unwrap();
+    let mut scheduler = TransferScheduler::new(&sftp);
+    let job = TransferJob::download("/file", &local.0);
+    scheduler.add(job.checksums(manifest.unwrap_or_default()));
+
+    let mut chunks = Vec::new();
+    let mut results = scheduler
+        .run_with_events(|event| {
+            if let TransferEvent::Checksum { chunk, .. } = event {
+                chunks.push(chunk);
+            }
+        })
+        .await;
+
+    (results.remove(0).map_err(|err| err.to_string()), chunks)
+}
+
+#[test]
+fn manifest_round_trips() {
+    let data = contents();
+    for manifest in [
+        ChecksumManifest::new(),
+        manifest(&data, CHUNK),
+        manifest(&data[..999], 7),
+    ] {
+        let bytes = manifest.to_bytes().unwrap();
+        assert_eq!(ChecksumManifest::from_bytes(&bytes).unwrap(), manifest);
+    }
+
+    let bytes = manifest(&data, CHUNK).to_bytes().unwrap();
+    assert!(ChecksumManifest::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+}
+
+#[test]
+fn rechunked_as_if_hashed_in_larger_chunks() {
+    let data = contents();
+
+    for (from, to) in [(100, 300), (100, 1000), (333, 1000), (1000, SIZE)] {
+        let mut rechunked = manifest(&data, from);
+        rechunked.rechunk(to as u64);
+
+        // the chunks are merged whole, never above the maximum length
+        let merged = (to / from) * from;
+        assert_eq!(rechunked, manifest(&data, merged), "{from} to {to}");
+    }
+}
+
+#[test]
+fn gaps_not_merged() {
+    let data = contents();
+    let mut manifest = ChecksumManifest::new();
+    manifest.insert(ChunkChecksum::new(0, &data[..100]));
+    manifest.insert(ChunkChecksum::new(200, &data[200..300]));
+    manifest.insert(ChunkChecksum::new(300, &data[300..400]));
+    assert_eq!(manifest.verified_len(), 100);
+
+    manifest.rechunk(1000);
+    let chunks = manifest.chunks();
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(chunks[1], ChunkChecksum::new(200, &data[200..400]));
+
+    // overlapping chunks are replaced
+    manifest.insert(ChunkChecksum::new(50, &data[50..250]));
+    assert_eq!(manifest.chunks(), [ChunkChecksum::new(50, &data[50..250])]);
+}
+
+#[tokio::test]
+async fn short_reads_hash_the_bytes_received() {
+    let data = contents();
+    let local = Local::new("short");
+    let (result, chunks) = download(Remote::new(data.clone(), 300), &local, None).await;
+    assert_eq!(result, Ok(SIZE as u64));
+
+    let mut manifest = ChecksumManifest::new();
+    for chunk in chunks {
+        assert!(chunk.len <= 300, "{chunk:?}");
+        let range = chunk.offset as usize..chunk.end() as usize;
+        assert!(chunk.matches(&data[range]), "{chunk:?}");
+        manifest.insert(chunk);
+    }
+
+    assert_eq!(manifest.verified_len(), SIZE as u64);
+    assert_eq!(std::fs::read(&local.0).unwrap(), data);
+}
+
+#[tokio::test]
+async fn resumed_after_the_verified_chunks() {
+    let data = contents();
+
+    // an interrupted attempt wrote the first 4 chunks, the last one torn
+    for (torn, resumed_at) in [(false, 4 * CHUNK), (true, 3 * CHUNK)] {
+        let local = Local::new(&format!("resume-{torn}"));
+        let mut written = data[..4 * CHUNK].to_vec();
+        if torn {
+            written[4 * CHUNK - 1] ^= 0xff;
+        }
+        std::fs::write(&local.0, &written).unwrap();
+
+        let remote = Remote::new(data.clone(), usize::MAX);
+        let reads = remote.reads.clone();
+        let previous = manifest(&data[..4 * CHUNK], CHUNK);
+        let (result, _) = download(remote, &local, Some(previous)).await;
+
+        assert_eq!(result, Ok((SIZE - resumed_at) as u64), "{torn}");
+        assert_eq!(std::fs::read(&local.0).unwrap(), data, "{torn}");
+        let first = reads.lock().unwrap().iter().copied().min();
+        assert_eq!(first, Some(resumed_at as u64), "{torn}");
+    }
+}
+
+#[tokio::test]
+async fn mismatching_data_fails_the_download() {
+    let data = contents();
+    let mut changed = data.clone();
+    changed[5 * CHUNK + 10] ^= 0xff;
+
+    // the previous attempt covered more than it wrote, the rest is checked as received
+    let local = Local::new("received");
+    std::fs::write(&local.0, &data[..2 * CHUNK]).unwrap();
+    let mut previous = manifest(&data, CHUNK);
+    previous.truncate(2 * CHUNK as u64);
+    previous.insert(ChunkChecksum::new(
+        5 * CHUNK as u64,
+        &data[5 * CHUNK..6 * CHUNK],
+    ));
+
+    let (result, _) = download(
+        Remote::new(changed.clone(), usize::MAX),
+        &local,
+        Some(previous),
+    )
+    .await;
+    let err = result.unwrap_err();
+    assert!(
+        err.contains("checksum mismatch of 1000 bytes at offset 5000"),
+        "{err}"
+    );
+
+    // a chunk straddling two short reads is checked in the assembled file
+    let local = Local::new("assembled");
+    let mut previous = ChecksumManifest::new();
+    previous.insert(ChunkChecksum::new(0, &data[..10]));
+    previous.insert(ChunkChecksum::new(
+        5 * CHUNK as u64 - 500,
+        &data[4500..5500],
+    ));
+    std::fs::write(&local.0, &data[..10]).unwrap();
+    let mut changed = data.clone();
+    changed[5 * CHUNK - 1] ^= 0xff;
+
+    let (result, _) = download(Remote::new(changed, 5000), &local, Some(previous)).await;
+    let err = result.unwrap_err();
+    assert!(
+        err.contains("checksum mismatch of 1000 bytes at offset 4500"),
+        "{err}"
+    );
+}
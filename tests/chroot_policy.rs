@@ -0,0 +1,215 @@
+//! Confinement of the client paths under the root, escape attempts through
+//! `..` and symlinks, existing or dangling, denied.
+#![cfg(unix)]
+
+use russh_sftp::{
+    protocol::StatusCode,
+    server::policy::{ChrootPolicy, Operation, PathPolicy, ReadOnlyPolicy},
+};
+use std::{
+    fs,
+    os::unix::fs::symlink,
+    path::{Path, PathBuf},
+};
+
+/// Temporary tree with `root/dir/file` and `outside/secret`, removed on drop
+struct Tree {
+    base: PathBuf,
+}
+
+impl Tree {
+    fn new(name: &str) -> Self {
+        let base =
+            std::env::temp_dir().join(format!("russh-sftp-chroot-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        fs::create_dir_all(base.join("root/dir")).unwrap();
+        fs::create_dir_all(base.join("outside")).unwrap();
+        fs::write(base.join("root/dir/file"), b"in").unwrap();
+        fs::write(base.join("outside/secret"), b"out").unwrap();
+        Self { base }
+    }
+
+    fn root(&self) -> PathBuf {
+        self.base.join("root")
+    }
+
+    fn link(&self, target: impl AsRef<Path>, name: &str) {
+        symlink(target, self.root().join(name)).unwrap();
+    }
+
+    fn policy(&self) -> ChrootPolicy {
+        ChrootPolicy::new(self.root()).unwrap()
+    }
+}
+
+impl Drop for Tree {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.base);
+    }
+}
+
+fn assert_denied(policy: &impl PathPolicy, path: &str) {
+    assert_eq!(
+        policy.resolve(path),
+        Err(StatusCode::PermissionDenied),
+        "{path}"
+    );
+}
+
+#[test]
+fn paths_inside_the_root() {
+    let tree = Tree::new("inside");
+    let policy = tree.policy();
+    let root = policy.root().to_owned();
+
+    // client path, resolved under the root
+    let table = [
+        ("/", ""),
+        (".", ""),
+        ("/dir/file", "dir/file"),
+        ("dir/./file", "dir/file"),
+        ("/dir/../dir/file", "dir/file"),
+        ("/missing/new", "missing/new"),
+        // `..` clamped at the root
+        ("/../../dir", "dir"),
+        ("../outside/secret", "outside/secret"),
+    ];
+    for (path, resolved) in table {
+        let local = policy.resolve(path).unwrap();
+        assert_eq!(local, root.join(resolved), "{path}");
+    }
+
+    let local = policy.resolve("/dir/file").unwrap();
+    assert_eq!(policy.client_path(&local).as_deref(), Some("/dir/file"));
+}
+
+#[test]
+fn links_inside_the_root_followed() {
+    let tree = Tree::new("links-inside");
+    tree.link("dir", "relative");
+    tree.link(tree.root().join("dir"), "absolute");
+    tree.link("dir/missing", "dangling");
+    tree.link("relative", "chain");
+    let policy = tree.policy();
+
+    for path in [
+        "/relative/file",
+        "/absolute/file",
+        "/dangling",
+        "/chain/file",
+    ] {
+        // the link is kept for lstat and readlink
+        let local = policy.resolve(path).unwrap();
+        assert_eq!(local, policy.root().join(&path[1..]), "{path}");
+    }
+}
+
+#[test]
+fn links_escaping_the_root_denied() {
+    let tree = Tree::new("links-outside");
+    tree.link(tree.base.join("outside"), "absolute");
+    tree.link("../outside", "relative");
+    tree.link("/etc", "etc");
+    tree.link(tree.base.join("outside/secret"), "file");
+    tree.link("dir/../../outside", "through-dir");
+    tree.link("relative", "chain");
+    let policy = tree.policy();
+
+    for path in [
+        "/absolute",
+        "/absolute/secret",
+        "/absolute/new",
+        "/relative/secret",
+        "/etc/passwd",
+        "/file",
+        "/through-dir/secret",
+        "/chain/secret",
+        "/dir/../relative/secret",
+    ] {
+        assert_denied(&policy, path);
+    }
+}
+
+#[test]
+fn dangling_links_escaping_the_root_denied() {
+    let tree = Tree::new("dangling");
+    tree.link(tree.base.join("outside/missing"), "absolute");
+    tree.link("../outside/missing", "relative");
+    tree.link("/nonexistent/missing", "nowhere");
+    tree.link(tree.base.join("outside/missing-dir/new"), "deep");
+    tree.link("relative", "chain");
+    // relative to its own directory
+    tree.link("../../outside/other", "dir/nested");
+    let policy = tree.policy();
+
+    for path in [
+        "/absolute",
+        "/relative",
+        "/nowhere",
+        "/nowhere/below",
+        "/deep",
+        "/chain",
+        "/dir/nested",
+    ] {
+        assert_denied(&policy, path);
+    }
+}
+
+#[test]
+fn parent_dir_through_links() {
+    let tree = Tree::new("parent");
+    tree.link(tree.base.join("outside"), "out");
+    tree.link("dir", "in");
+    let policy = tree.policy();
+
+    // `..` is applied to the client path, the link is never entered
+    let local = policy.resolve("/out/../dir/file").unwrap();
+    assert_eq!(local, policy.root().join("dir/file"));
+    let local = policy.resolve("/in/../dir").unwrap();
+    assert_eq!(local, policy.root().join("dir"));
+
+    assert_denied(&policy, "/out/secret/..");
+    assert_denied(&policy, "/dir/../out/secret");
+}
+
+#[test]
+fn link_loops_denied() {
+    let tree = Tree::new("loop");
+    tree.link("b", "a");
+    tree.link("a", "b");
+    let policy = tree.policy();
+
+    assert_denied(&policy, "/a");
+    assert_denied(&policy, "/a/file");
+}
+
+#[test]
+fn read_only_denies_mutations() {
+    let tree = Tree::new("read-only");
+    let policy = ReadOnlyPolicy::new(tree.policy());
+    let path = policy.resolve("/dir/file").unwrap();
+
+    for op in [Operation::Read, Operation::Stat, Operation::ReadDir] {
+        assert_eq!(policy.check(op, &path), Ok(()), "{op:?}");
+    }
+    for op in [
+        Operation::Write,
+        Operation::Remove,
+        Operation::Rename,
+        Operation::MkDir,
+        Operation::RmDir,
+        Operation::SetStat,
+        Operation::Symlink,
+    ] {
+        assert_eq!(
+            policy.check(op, &path),
+            Err(StatusCode::PermissionDenied),
+            "{op:?}"
+        );
+    }
+
+    assert_eq!(
+        policy.into_inner().root(),
+        tree.root().canonicalize().unwrap()
+    );
+}
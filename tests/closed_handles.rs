@@ -0,0 +1,137 @@
+//! Requests sent on a handle after SSH_FXP_CLOSE, rejected by default
+//! or passed to the handler.
+
+mod common;
+
+use common::{raw_pair, Memory};
+use russh_sftp::{
+    client::{error::Error, rawsession::RawSftpSession},
+    protocol::{Data, FileAttributes, OpenFlags, StatusCode},
+    server::{ClosedHandleAction, Config},
+};
+use std::sync::atomic::Ordering;
+
+/// Serves `/a` and `/b`, the handle being the path
+async fn connect(config: Config) -> (RawSftpSession, Memory) {
+    let memory = Memory::default();
+    for path in ["/a", "/b"] {
+        let data = b"data".to_vec();
+        memory.files.lock().unwrap().insert(path.to_owned(), data);
+    }
+
+    let (session, _served) = raw_pair(memory.clone(), config).await;
+    (session, memory)
+}
+
+/// Number of SSH_FXP_READ passed to the handler
+fn reads(memory: &Memory) -> u64 {
+    memory.reads.load(Ordering::Relaxed)
+}
+
+async fn open(session: &RawSftpSession, path: &str) -> String {
+    let attrs = FileAttributes::empty();
+    let handle = session.open(path, OpenFlags::READ, attrs).await.unwrap();
+    handle.handle
+}
+
+fn status_code(result: Result<Data, Error>) -> StatusCode {
+    match result {
+        Err(Error::Status { status, .. }) => status.status_code,
+        result => panic!("not a status: {result:?}"),
+    }
+}
+
+#[tokio::test]
+async fn read_after_close_rejected() {
+    let (session, memory) = connect(Config::default()).await;
+
+    let handle = open(&session, "/a").await;
+    session.read(handle.as_str(), 0, 4).await.unwrap();
+    session.close(handle.as_str()).await.unwrap();
+
+    let late = session.read(handle.as_str(), 4, 4).await;
+    assert_eq!(status_code(late), StatusCode::Failure);
+    assert_eq!(reads(&memory), 1);
+
+    // a second close is late as well
+    assert!(session.close(handle.as_str()).await.is_err());
+
+    // the context is passed before the next request is handled
+    open(&session, "/b").await;
+    assert_eq!(memory.context.lock().unwrap().late_requests(), 2);
+}
+
+#[tokio::test]
+async fn reopened_handle_accepted() {
+    let (session, memory) = connect(Config::default()).await;
+
+    let handle = open(&session, "/a").await;
+    session.close(handle.as_str()).await.unwrap();
+
+    // the handler hands out the same handle again
+    assert_eq!(open(&session, "/a").await, handle);
+    session.read(handle.as_str(), 0, 4).await.unwrap();
+    assert_eq!(reads(&memory), 1);
+
+    open(&session, "/b").await;
+    assert_eq!(memory.context.lock().unwrap().late_requests(), 0);
+}
+
+#[tokio::test]
+async fn read_eof_and_custom_status() {
+    let config = Config {
+        closed_handle_action: ClosedHandleAction::RejectReadEof,
+        ..Default::default()
+    };
+    let (session, memory) = connect(config).await;
+
+    let handle = open(&session, "/a").await;
+    session.close(handle.as_str()).await.unwrap();
+    let late = session.read(handle.as_str(), 0, 4).await;
+    assert_eq!(status_code(late), StatusCode::Eof);
+
+    let config = Config {
+        closed_handle_action: ClosedHandleAction::Reject(StatusCode::NoSuchFile),
+        ..Default::default()
+    };
+    let (session, other) = connect(config).await;
+
+    let handle = open(&session, "/a").await;
+    session.close(handle.as_str()).await.unwrap();
+    let late = session.read(handle.as_str(), 0, 4).await;
+    assert_eq!(status_code(late), StatusCode::NoSuchFile);
+    assert_eq!((reads(&memory), reads(&other)), (0, 0));
+}
+
+#[tokio::test]
+async fn pass_through_counts() {
+    let config = Config {
+        closed_handle_action: ClosedHandleAction::PassThrough,
+        ..Default::default()
+    };
+    let (session, memory) = connect(config).await;
+
+    let handle = open(&session, "/a").await;
+    session.close(handle.as_str()).await.unwrap();
+    session.read(handle.as_str(), 0, 4).await.unwrap();
+    assert_eq!(reads(&memory), 1);
+
+    open(&session, "/b").await;
+    assert_eq!(memory.context.lock().unwrap().late_requests(), 1);
+}
+
+#[tokio::test]
+async fn shared_handle_open_until_last_close() {
+    let (session, memory) = connect(Config::default()).await;
+
+    // the same handle for both files opened at the path
+    let handle = open(&session, "/a").await;
+    open(&session, "/a").await;
+    session.close(handle.as_str()).await.unwrap();
+    session.read(handle.as_str(), 0, 4).await.unwrap();
+
+    session.close(handle.as_str()).await.unwrap();
+    let late = session.read(handle.as_str(), 0, 4).await;
+    assert_eq!(status_code(late), StatusCode::Failure);
+    assert_eq!(reads(&memory), 1);
+}
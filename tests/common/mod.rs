@@ -0,0 +1,368 @@
+//! In-memory server shared by the tests, the client ends of the streams it
+//! serves below [`SftpSession`](russh_sftp::client::SftpSession), the
+//! generator of the randomized tests and their local files.
+
+#![allow(dead_code)]
+
+use bytes::{Bytes, BytesMut};
+use russh_sftp::{
+    client::{RawSftpSession, PAIR_BUFFER_LEN},
+    framing::read_packet,
+    protocol::{
+        Attrs, Data, File, FileAttributes, Handle, Init, Name, OpenFlags, Packet, RequestId,
+        Status, StatusCode, Version,
+    },
+    server::{self, Config, EndReason, Handler, SessionContext},
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+use tokio::{
+    io::{AsyncWriteExt, DuplexStream},
+    task::JoinHandle,
+};
+
+/// Deterministic xorshift generator, so that a failure can be replayed
+pub struct Rng(pub u64);
+
+impl Rng {
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+}
+
+/// Local file in the temporary directory, removed on drop
+pub struct Local(pub PathBuf);
+
+impl Local {
+    /// File named after the process and `name`, not created
+    pub fn new(name: &str) -> Self {
+        let name = format!("russh-sftp-{}-{name}", std::process::id());
+        Self(std::env::temp_dir().join(name))
+    }
+
+    /// File holding `data`
+    pub fn with_data(name: &str, data: &[u8]) -> Self {
+        let local = Self::new(name);
+        std::fs::write(&local.0, data).unwrap();
+        local
+    }
+}
+
+impl Drop for Local {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Result of the task serving a stream
+pub type Served = JoinHandle<Result<(), russh_sftp::Error>>;
+
+/// Initialized raw session with `handler` served with `config`
+pub async fn raw_pair<H>(handler: H, config: Config) -> (RawSftpSession, Served)
+where
+    H: Handler + Send + 'static,
+{
+    let (client, stream) = tokio::io::duplex(PAIR_BUFFER_LEN);
+    let served = tokio::spawn(server::serve(stream, handler, config));
+
+    let session = RawSftpSession::new(client);
+    session.init().await.unwrap();
+    (session, served)
+}
+
+/// Stream to `handler` served with `config` on which the test writes the frames
+/// itself, once SSH_FXP_INIT of version 3 was answered
+pub async fn raw_stream<H>(handler: H, config: Config) -> (DuplexStream, Served)
+where
+    H: Handler + Send + 'static,
+{
+    let (mut client, stream) = tokio::io::duplex(PAIR_BUFFER_LEN);
+    let served = tokio::spawn(server::serve(stream, handler, config));
+
+    let init = Init {
+        version: 3,
+        extensions: HashMap::new(),
+    };
+    let init = Bytes::try_from(Packet::from(init)).unwrap();
+    client.write_all(&init).await.unwrap();
+    read_packet(&mut client, &mut BytesMut::new())
+        .await
+        .unwrap();
+
+    (client, served)
+}
+
+/// Files by path, directories are the paths without an entry ending with `/`
+#[derive(Clone, Default)]
+pub struct Memory {
+    pub files: Arc<Mutex<BTreeMap<String, Vec<u8>>>>,
+    /// Number of SSH_FXP_READ received
+    pub reads: Arc<AtomicU64>,
+    /// Number of exclusive creations to fail as if the name was taken just before,
+    /// the name being created with the data `taken`
+    pub collisions: Arc<AtomicU64>,
+    /// Context passed before the last request
+    pub context: Arc<Mutex<SessionContext>>,
+    /// Reason of the end of the session
+    pub ended: Arc<Mutex<Option<EndReason>>>,
+    listed: HashMap<String, bool>,
+}
+
+impl Memory {
+    fn ok(id: RequestId) -> Result<Status, StatusCode> {
+        Ok(Status {
+            id,
+            status_code: StatusCode::Ok,
+            error_message: "Ok".to_owned(),
+            language_tag: "en-US".to_owned(),
+        })
+    }
+
+    fn attrs(&self, path: &str) -> Result<FileAttributes, StatusCode> {
+        let files = self.files.lock().unwrap();
+        match files.get(path) {
+            Some(data) => Ok(FileAttributes {
+                size: Some(data.len() as u64),
+                permissions: Some(0o100644),
+                ..FileAttributes::empty()
+            }),
+            None if files.contains_key(&format!("{path}/")) => Ok(FileAttributes {
+                permissions: Some(0o040755),
+                ..FileAttributes::empty()
+            }),
+            None => Err(StatusCode::NoSuchFile),
+        }
+    }
+
+    /// Takes the name if a collision is pending
+    fn collide(&self, name: String) -> Result<(), StatusCode> {
+        let pending = self
+            .collisions
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+        match pending {
+            Ok(_) => {
+                self.files.lock().unwrap().insert(name, b"taken".to_vec());
+                Err(StatusCode::Failure)
+            }
+            Err(_) => Ok(()),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Handler for Memory {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    fn set_context(&mut self, context: &SessionContext) {
+        *self.context.lock().unwrap() = context.clone();
+    }
+
+    async fn session_ended(&mut self, reason: EndReason) {
+        *self.ended.lock().unwrap() = Some(reason);
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn open(
+        &mut self,
+        id: RequestId,
+        filename: String,
+        pflags: OpenFlags,
+        _attrs: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        if pflags.contains(OpenFlags::CREATE | OpenFlags::EXCLUDE) {
+            self.collide(filename.clone())?;
+        }
+
+        let mut files = self.files.lock().unwrap();
+        if pflags.contains(OpenFlags::EXCLUDE) && files.contains_key(&filename) {
+            return Err(StatusCode::Failure);
+        }
+
+        if pflags.contains(OpenFlags::CREATE) {
+            files.entry(filename.clone()).or_default();
+        }
+
+        match files.get_mut(&filename) {
+            Some(data) if pflags.contains(OpenFlags::TRUNCATE) => data.clear(),
+            Some(_) => {}
+            None => return Err(StatusCode::NoSuchFile),
+        }
+
+        Ok(Handle {
+            id,
+            handle: filename,
+        })
+    }
+
+    async fn close(&mut self, id: RequestId, handle: String) -> Result<Status, Self::Error> {
+        self.listed.remove(&handle);
+        Self::ok(id)
+    }
+
+    async fn read(
+        &mut self,
+        id: RequestId,
+        handle: String,
+        offset: u64,
+        len: u32,
+    ) -> Result<Data, Self::Error> {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        let files = self.files.lock().unwrap();
+        let data = files.get(&handle).ok_or(StatusCode::NoSuchFile)?;
+        let offset = offset as usize;
+        if offset >= data.len() {
+            return Err(StatusCode::Eof);
+        }
+
+        let end = data.len().min(offset + len as usize);
+        Ok(Data {
+            id,
+            data: data[offset..end].to_vec(),
+        })
+    }
+
+    async fn write(
+        &mut self,
+        id: RequestId,
+        handle: String,
+        offset: u64,
+        data: Vec<u8>,
+    ) -> Result<Status, Self::Error> {
+        let mut files = self.files.lock().unwrap();
+        let file = files.get_mut(&handle).ok_or(StatusCode::NoSuchFile)?;
+        let offset = offset as usize;
+        if file.len() < offset + data.len() {
+            file.resize(offset + data.len(), 0);
+        }
+
+        file[offset..offset + data.len()].copy_from_slice(&data);
+        Self::ok(id)
+    }
+
+    async fn lstat(&mut self, id: RequestId, path: String) -> Result<Attrs, Self::Error> {
+        self.stat(id, path).await
+    }
+
+    async fn fstat(&mut self, id: RequestId, handle: String) -> Result<Attrs, Self::Error> {
+        self.stat(id, handle).await
+    }
+
+    async fn opendir(&mut self, id: RequestId, path: String) -> Result<Handle, Self::Error> {
+        self.attrs(&path)?;
+        self.listed.insert(path.clone(), false);
+        Ok(Handle { id, handle: path })
+    }
+
+    async fn readdir(&mut self, id: RequestId, handle: String) -> Result<Name, Self::Error> {
+        if self.listed.insert(handle.clone(), true) != Some(false) {
+            return Err(StatusCode::Eof);
+        }
+
+        let prefix = format!("{handle}/");
+        let names = self
+            .files
+            .lock()
+            .unwrap()
+            .keys()
+            .filter_map(|path| path.strip_prefix(&prefix))
+            .map(|name| name.strip_suffix('/').unwrap_or(name))
+            .filter(|name| !name.is_empty() && !name.contains('/'))
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+
+        let mut files = Vec::new();
+        for name in names {
+            let attrs = self.attrs(&format!("{prefix}{name}"))?;
+            files.push(File::new(name, attrs));
+        }
+
+        Ok(Name { id, files })
+    }
+
+    async fn remove(&mut self, id: RequestId, filename: String) -> Result<Status, Self::Error> {
+        match self.files.lock().unwrap().remove(&filename) {
+            Some(_) => Self::ok(id),
+            None => Err(StatusCode::NoSuchFile),
+        }
+    }
+
+    async fn mkdir(
+        &mut self,
+        id: RequestId,
+        path: String,
+        _attrs: FileAttributes,
+    ) -> Result<Status, Self::Error> {
+        self.collide(format!("{path}/"))?;
+        if self.attrs(&path).is_ok() {
+            return Err(StatusCode::Failure);
+        }
+
+        self.files
+            .lock()
+            .unwrap()
+            .insert(format!("{path}/"), vec![]);
+        Self::ok(id)
+    }
+
+    async fn rmdir(&mut self, id: RequestId, path: String) -> Result<Status, Self::Error> {
+        match self.files.lock().unwrap().remove(&format!("{path}/")) {
+            Some(_) => Self::ok(id),
+            None => Err(StatusCode::NoSuchFile),
+        }
+    }
+
+    async fn realpath(&mut self, id: RequestId, path: String) -> Result<Name, Self::Error> {
+        let path = match path.as_str() {
+            "." | "" => "/".to_owned(),
+            _ => path,
+        };
+
+        Ok(Name {
+            id,
+            files: vec![File::dummy(path)],
+        })
+    }
+
+    async fn stat(&mut self, id: RequestId, path: String) -> Result<Attrs, Self::Error> {
+        Ok(Attrs {
+            id,
+            attrs: self.attrs(&path)?,
+        })
+    }
+
+    async fn rename(
+        &mut self,
+        id: RequestId,
+        oldpath: String,
+        newpath: String,
+    ) -> Result<Status, Self::Error> {
+        let mut files = self.files.lock().unwrap();
+        let data = files.remove(&oldpath).ok_or(StatusCode::NoSuchFile)?;
+        files.insert(newpath, data);
+        Self::ok(id)
+    }
+}
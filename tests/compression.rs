@@ -0,0 +1,321 @@
+//! Compression of data payloads: round trips through a server of this crate with
+//! and without each side offering it, and corrupt or oversized payloads rejected
+//! by both sides.
+
+mod common;
+
+use bytes::{Bytes, BytesMut};
+use common::Memory;
+use flate2::write::DeflateEncoder;
+use russh_sftp::{
+    client::{error::Error, RawSftpSession, SftpSession},
+    compression::Compression,
+    extensions,
+    framing::read_packet,
+    protocol::{
+        Data, FileAttributes, Handle, Init, Open, OpenFlags, Packet, RequestId, Status, StatusCode,
+        Version, Write,
+    },
+    server::{self, Config},
+};
+use std::{
+    collections::HashMap,
+    io::{self, Write as _},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, DuplexStream, ReadBuf};
+
+/// Largest payload inflated by the server
+const MAX_INFLATED_LEN: usize = 16 * 1024 * 1024;
+
+const RAW: u8 = 0;
+const DEFLATE: u8 = 1;
+
+/// Lines of a log, which compress well
+fn logs(len: usize) -> Vec<u8> {
+    let mut logs = Vec::with_capacity(len);
+    let mut i = 0u64;
+    while logs.len() < len {
+        let line = format!("2024-01-01T00:00:{:02}Z INFO request {i}\n", i % 60);
+        logs.extend(line.bytes());
+        i += 1;
+    }
+
+    logs.truncate(len);
+    logs
+}
+
+/// Bytes that do not compress
+fn noise(len: usize) -> Vec<u8> {
+    let mut state = 0x2545_f491_u32;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect()
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = DeflateEncoder::new(vec![DEFLATE], flate2::Compression::fast());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Duplex stream counting the bytes written by the client
+struct Counted {
+    inner: DuplexStream,
+    sent: Arc<AtomicU64>,
+}
+
+impl AsyncRead for Counted {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Counted {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(len)) = result {
+            self.sent.fetch_add(len as u64, Ordering::Relaxed);
+        }
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Session with the compression offered by the client and accepted by the server,
+/// and the bytes sent by the client
+async fn connect(
+    server: Memory,
+    offered: Option<Compression>,
+    accepted: Option<Compression>,
+) -> (SftpSession, Arc<AtomicU64>) {
+    let (client, stream) = tokio::io::duplex(1024 * 1024);
+    let config = Config {
+        compression: accepted,
+        ..Default::default()
+    };
+    tokio::spawn(server::serve(stream, server, config));
+
+    let sent = Arc::new(AtomicU64::new(0));
+    let session = RawSftpSession::new(Counted {
+        inner: client,
+        sent: sent.clone(),
+    });
+    session.set_compression(offered);
+
+    (SftpSession::with_raw_session(session).await.unwrap(), sent)
+}
+
+#[tokio::test]
+async fn round_trips() {
+    let fast = Some(Compression::deflate(1));
+    // the data and whether it compresses
+    let cases = [
+        (b"x".to_vec(), false),
+        (noise(64 * 1024), false),
+        // over the inflated length of a packet, in many packets
+        (logs(MAX_INFLATED_LEN + 1000), true),
+    ];
+
+    for (offered, accepted) in [(fast, fast), (fast, None), (None, fast)] {
+        let negotiated = offered.is_some() && accepted.is_some();
+
+        for (data, compressible) in &cases {
+            let server = Memory::default();
+            let (sftp, sent) = connect(server.clone(), offered, accepted).await;
+
+            let before = sent.load(Ordering::Relaxed);
+            let mut file = sftp.create("/file").await.unwrap();
+            file.write_all(data).await.unwrap();
+            file.shutdown().await.unwrap();
+            let sent = sent.load(Ordering::Relaxed) - before;
+
+            let case = format!("{offered:?} {accepted:?} {}", data.len());
+            assert_eq!(&server.files.lock().unwrap()["/file"], data, "{case}");
+            assert_eq!(&sftp.read("/file").await.unwrap(), data, "{case}");
+
+            // only compressible data shrinks, once negotiated
+            let shrunk = sent < data.len() as u64 / 4;
+            assert_eq!(shrunk, negotiated && *compressible, "{case}: {sent} sent");
+        }
+    }
+}
+
+/// Sends the request and returns the reply
+async fn exchange(stream: &mut DuplexStream, buf: &mut BytesMut, request: Packet) -> Packet {
+    let frame = Bytes::try_from(request).unwrap();
+    stream.write_all(&frame).await.unwrap();
+    let mut payload = read_packet(stream, buf).await.unwrap();
+    Packet::try_from(&mut payload).unwrap()
+}
+
+#[tokio::test]
+async fn corrupt_writes_rejected_by_the_server() {
+    let server = Memory::default();
+    let config = Config {
+        compression: Some(Compression::default()),
+        ..Default::default()
+    };
+    let (mut stream, serving) = tokio::io::duplex(1024 * 1024);
+    tokio::spawn(server::serve(serving, server.clone(), config));
+
+    let mut buf = BytesMut::new();
+    let offer = HashMap::from([(
+        extensions::COMPRESSION.to_owned(),
+        "zstd,deflate".to_owned(),
+    )]);
+    let init = Init {
+        version: 3,
+        extensions: offer,
+    };
+    let Packet::Version(version) = exchange(&mut stream, &mut buf, init.into()).await else {
+        panic!("no version");
+    };
+    assert_eq!(version.extensions[extensions::COMPRESSION], "deflate");
+
+    let open = Open {
+        id: RequestId(1),
+        filename: "/file".to_owned(),
+        pflags: OpenFlags::CREATE | OpenFlags::WRITE,
+        attrs: FileAttributes::empty(),
+    };
+    let reply = exchange(&mut stream, &mut buf, open.into()).await;
+    assert!(matches!(reply, Packet::Handle(_)), "{reply:?}");
+
+    let mut truncated = deflate(&logs(10_000));
+    truncated.truncate(truncated.len() / 2);
+    let bomb = deflate(&vec![0; MAX_INFLATED_LEN + 1]);
+    let cases = [
+        (vec![RAW, b'a', b'b'], StatusCode::Ok),
+        (deflate(b"cd"), StatusCode::Ok),
+        (deflate(&vec![0; MAX_INFLATED_LEN]), StatusCode::Ok),
+        (bomb, StatusCode::BadMessage),
+        (vec![DEFLATE, 0xff, 0xff, 0xff], StatusCode::BadMessage),
+        (truncated, StatusCode::BadMessage),
+        (vec![7, b'e'], StatusCode::BadMessage),
+        (vec![], StatusCode::BadMessage),
+    ];
+
+    for (i, (data, expected)) in cases.into_iter().enumerate() {
+        let write = Write {
+            id: RequestId(2 + i as u32),
+            handle: "/file".to_owned(),
+            offset: 0,
+            data,
+        };
+        let reply = exchange(&mut stream, &mut buf, write.into()).await;
+        assert!(
+            matches!(&reply, Packet::Status(status) if status.status_code == expected),
+            "{i}: {reply:?}"
+        );
+    }
+
+    // only the valid payloads reached the handler
+    let files = server.files.lock().unwrap();
+    assert_eq!(files["/file"].len(), MAX_INFLATED_LEN);
+    assert!(files["/file"].iter().all(|&byte| byte == 0));
+}
+
+/// Answers each SSH_FXP_READ with the next payload, framed as is
+async fn scripted(mut stream: DuplexStream, mut payloads: Vec<Vec<u8>>) {
+    let mut buf = BytesMut::new();
+    payloads.reverse();
+
+    while let Ok(mut payload) = read_packet(&mut stream, &mut buf).await {
+        let reply: Packet = match Packet::try_from(&mut payload).unwrap() {
+            Packet::Init(init) => {
+                assert!(init.extensions.contains_key(extensions::COMPRESSION));
+                let mut version = Version::new();
+                version
+                    .extensions
+                    .insert(extensions::COMPRESSION.to_owned(), "deflate".to_owned());
+                version.into()
+            }
+            Packet::Open(open) => Handle {
+                id: open.id,
+                handle: open.filename,
+            }
+            .into(),
+            Packet::Read(read) => Data {
+                id: read.id,
+                data: payloads.pop().unwrap(),
+            }
+            .into(),
+            request => Status::new(request.get_request_id(), StatusCode::OpUnsupported).into(),
+        };
+
+        stream
+            .write_all(&Bytes::try_from(reply).unwrap())
+            .await
+            .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn corrupt_data_rejected_by_the_client() {
+    const LEN: u32 = 1000;
+
+    let mut truncated = deflate(&logs(10_000));
+    truncated.truncate(truncated.len() / 2);
+    let cases = [
+        (vec![RAW, b'a'], Some(b"a".to_vec())),
+        (deflate(&logs(LEN as usize)), Some(logs(LEN as usize))),
+        // beyond the length of the read
+        (deflate(&logs(LEN as usize + 1)), None),
+        (vec![DEFLATE, 0xff, 0xff, 0xff], None),
+        (truncated, None),
+        (vec![7, b'a'], None),
+        (vec![], None),
+    ];
+
+    let (client, stream) = tokio::io::duplex(1024 * 1024);
+    let payloads = cases.iter().map(|(payload, _)| payload.clone()).collect();
+    tokio::spawn(scripted(stream, payloads));
+
+    let session = RawSftpSession::new(client);
+    session.set_compression(Some(Compression::default()));
+    session.init().await.unwrap();
+    assert!(session.compression().is_some());
+
+    let handle = session
+        .open("/file", OpenFlags::READ, FileAttributes::empty())
+        .await
+        .unwrap()
+        .handle;
+
+    for (i, (_, expected)) in cases.into_iter().enumerate() {
+        let result = session.read(&handle, 0, LEN).await;
+        match expected {
+            Some(expected) => assert_eq!(result.unwrap().data, expected, "{i}"),
+            None => assert!(
+                matches!(result, Err(Error::UnexpectedBehavior(_))),
+                "{i}: {result:?}"
+            ),
+        }
+    }
+}
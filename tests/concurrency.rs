@@ -0,0 +1,137 @@
+//! Concurrent requests from clones of one session over a link with latency,
+//! on a paused clock.
+
+mod common;
+
+use common::Memory;
+use russh_sftp::{client::SftpSession, server};
+use std::time::Duration;
+use tokio::{
+    io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::mpsc,
+    task::JoinSet,
+    time::{self, Instant},
+};
+
+const TASKS: usize = 200;
+/// One-way latency of the link
+const LATENCY: Duration = Duration::from_millis(5);
+
+/// Forwards the bytes read from `from` to `to` after [`LATENCY`], keeping their order
+fn delay<R, W>(mut from: R, mut to: W)
+where
+    R: AsyncRead + Unpin + Send + 'static,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let mut buf = vec![0; 64 * 1024];
+        while let Ok(len @ 1..) = from.read(&mut buf).await {
+            let _ = tx.send((Instant::now() + LATENCY, buf[..len].to_vec()));
+        }
+    });
+
+    tokio::spawn(async move {
+        while let Some((at, data)) = rx.recv().await {
+            time::sleep_until(at).await;
+            if to.write_all(&data).await.is_err() {
+                break;
+            }
+        }
+    });
+}
+
+async fn connect() -> (SftpSession, Memory) {
+    let memory = Memory::default();
+    {
+        let mut files = memory.files.lock().unwrap();
+        files.insert("/seed/".to_owned(), Vec::new());
+        files.insert("/moved/".to_owned(), Vec::new());
+        files.insert("/dirs/".to_owned(), Vec::new());
+        for task in 0..TASKS {
+            files.insert(format!("/seed/{task}"), vec![task as u8; task]);
+        }
+    }
+
+    let (client, client_link) = io::duplex(64 * 1024);
+    let (server_link, server) = io::duplex(64 * 1024);
+    let (client_read, client_write) = io::split(client_link);
+    let (server_read, server_write) = io::split(server_link);
+    delay(client_read, server_write);
+    delay(server_read, client_write);
+
+    server::run(server, memory.clone()).await;
+    (SftpSession::new(client).await.unwrap(), memory)
+}
+
+/// Requests of a task, checking that the replies are its own
+async fn operate(sftp: SftpSession, task: usize) {
+    let seeded = vec![task as u8; task];
+    match task % 4 {
+        0 => {
+            let path = format!("/written/{task}");
+            let data = format!("task {task}").into_bytes();
+            sftp.create(path.as_str()).await.unwrap();
+            sftp.write(path.as_str(), &data).await.unwrap();
+            assert_eq!(sftp.read(path.as_str()).await.unwrap(), data);
+        }
+        1 => {
+            let metadata = sftp.metadata(format!("/seed/{task}")).await.unwrap();
+            assert_eq!(metadata.size, Some(task as u64));
+        }
+        2 => {
+            sftp.create_dir(format!("/dirs/{task}")).await.unwrap();
+            assert!(sftp.try_exists(format!("/dirs/{task}")).await.unwrap());
+            assert_eq!(sftp.read(format!("/seed/{task}")).await.unwrap(), seeded);
+        }
+        _ => {
+            let path = format!("/moved/{task}");
+            sftp.rename(format!("/seed/{task}"), path.as_str())
+                .await
+                .unwrap();
+            assert_eq!(sftp.read(path).await.unwrap(), seeded);
+        }
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn clones_run_concurrently() {
+    let (sftp, _) = connect().await;
+    let started = Instant::now();
+    for task in 0..TASKS {
+        operate(sftp.clone(), task).await;
+    }
+    let serial = started.elapsed();
+
+    let (sftp, memory) = connect().await;
+    let started = Instant::now();
+    let mut tasks = JoinSet::new();
+    for task in 0..TASKS {
+        tasks.spawn(operate(sftp.clone(), task));
+    }
+
+    let all = async {
+        while let Some(result) = tasks.join_next().await {
+            result.unwrap();
+        }
+    };
+    time::timeout(Duration::from_secs(10), all)
+        .await
+        .expect("deadlock");
+    let concurrent = started.elapsed();
+
+    assert!(
+        concurrent * 20 < serial,
+        "{concurrent:?} concurrently, {serial:?} serially"
+    );
+
+    let files = memory.files.lock().unwrap();
+    assert_eq!(
+        files.keys().filter(|p| p.starts_with("/moved/")).count(),
+        51
+    );
+    assert_eq!(
+        files.keys().filter(|p| p.starts_with("/written/")).count(),
+        50
+    );
+}
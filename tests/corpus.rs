@@ -0,0 +1,693 @@
+//! Decodes the packets of `tests/corpus` and compares the re-serialized bytes.
+//!
+//! Each fixture holds one frame, including the length, as hex with `#` comments.
+//! The `# packet:` header names the expected type and `# roundtrip: true` marks
+//! the frames that must serialize back to the same bytes. Every frame is emitted
+//! unchanged when decoded as a [`PreservedPacket`].
+//!
+//! The fixtures of `tests/corpus/russh-sftp` are produced by [`own_packets`] and
+//! are regenerated with `cargo test --test corpus -- --ignored`. Those of
+//! `tests/corpus/proftpd` and `tests/corpus/bitvise` are reconstructed replies
+//! of these servers with their known quirks.
+
+use bytes::Bytes;
+use russh_sftp::{
+    de,
+    extensions::{
+        HardlinkExtension, HomeDirectoryExtension, LimitsExtension, ACL, FSYNC, HARDLINK, LIMITS,
+        STATVFS,
+    },
+    protocol::{
+        AclEntries, AclEntry, Attrs, Data, ExtendedReply, File, FileAttributes, FileMode, Handle,
+        Name, OpenFlags, Packet, PreservedPacket, RequestId, Status, StatusCode, Version,
+    },
+};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+const CORPUS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/corpus");
+
+struct Fixture {
+    path: PathBuf,
+    packet: String,
+    roundtrip: bool,
+    frame: Vec<u8>,
+}
+
+impl Fixture {
+    fn read(path: &Path) -> Self {
+        let text = fs::read_to_string(path).unwrap();
+        let header = |key: &str| {
+            text.lines()
+                .find_map(|line| line.strip_prefix(&format!("# {key}:")))
+                .map(|value| value.trim().to_owned())
+                .unwrap_or_else(|| panic!("{} has no `{key}` header", path.display()))
+        };
+
+        let hex = text
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .flat_map(|line| line.split_whitespace())
+            .collect::<String>();
+
+        Self {
+            path: path.to_owned(),
+            packet: header("packet"),
+            roundtrip: header("roundtrip") == "true",
+            frame: decode_hex(&hex),
+        }
+    }
+
+    fn decode(&self) -> Packet {
+        let (length, payload) = self.frame.split_at(4);
+        let length = u32::from_be_bytes(length.try_into().unwrap()) as usize;
+        assert_eq!(length, payload.len(), "{}", self.path.display());
+
+        Packet::try_from(&mut Bytes::copy_from_slice(payload))
+            .unwrap_or_else(|err| panic!("{}: {err}", self.path.display()))
+    }
+}
+
+fn decode_hex(hex: &str) -> Vec<u8> {
+    assert!(hex.len().is_multiple_of(2), "odd number of hex digits");
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+fn encode_hex(frame: &[u8]) -> String {
+    let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{b:02x}")).collect::<String>();
+
+    // the length, the type and the payload in rows of 32 bytes
+    let (header, payload) = frame.split_at(5);
+    [&header[..4], &header[4..]]
+        .into_iter()
+        .chain(payload.chunks(32))
+        .map(|line| hex(line) + "\n")
+        .collect()
+}
+
+fn fixtures() -> Vec<Fixture> {
+    let mut fixtures = Vec::new();
+    for source in fs::read_dir(CORPUS).unwrap() {
+        for entry in fs::read_dir(source.unwrap().path()).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "hex") {
+                fixtures.push(Fixture::read(&path));
+            }
+        }
+    }
+
+    fixtures.sort_by(|a, b| a.path.cmp(&b.path));
+    fixtures
+}
+
+fn fixture(name: &str) -> Packet {
+    Fixture::read(&Path::new(CORPUS).join(name)).decode()
+}
+
+fn file_attrs() -> FileAttributes {
+    FileAttributes {
+        size: Some(13),
+        uid: Some(1000),
+        gid: Some(1000),
+        permissions: Some(FileMode::REG.bits() | 0o644),
+        atime: Some(1_700_000_000.into()),
+        mtime: Some(1_700_000_000.into()),
+        ..FileAttributes::empty()
+    }
+}
+
+/// Replies of the server side, serialized into `tests/corpus/russh-sftp`
+fn own_packets() -> Vec<(&'static str, &'static str, Packet)> {
+    let status = |id, status_code| Packet::error(id, status_code);
+
+    vec![
+        (
+            "version",
+            "Version with a single extension",
+            Version {
+                version: 3,
+                extensions: HashMap::from([("fsync@openssh.com".to_owned(), "1".to_owned())]),
+            }
+            .into(),
+        ),
+        (
+            "name_realpath",
+            "Reply to realpath with a dummy entry",
+            Name {
+                id: RequestId(1),
+                files: vec![File::dummy("/")],
+            }
+            .into(),
+        ),
+        (
+            "name_readdir",
+            "Reply to readdir with the formed longname",
+            Name {
+                id: RequestId(4),
+                files: vec![File::new("a.txt", file_attrs())],
+            }
+            .into(),
+        ),
+        (
+            "attrs",
+            "Reply to stat with all the fields of version 3",
+            Attrs {
+                id: RequestId(2),
+                attrs: file_attrs(),
+            }
+            .into(),
+        ),
+        (
+            "handle",
+            "Reply to open",
+            Handle {
+                id: RequestId(3),
+                handle: "h1".to_owned(),
+            }
+            .into(),
+        ),
+        (
+            "data",
+            "Reply to read",
+            Data {
+                id: RequestId(13),
+                data: b"hello corpus\n".to_vec(),
+            }
+            .into(),
+        ),
+        (
+            "status_ok",
+            "Ok status",
+            status(RequestId(6), StatusCode::Ok),
+        ),
+        (
+            "status_eof",
+            "End of file",
+            status(RequestId(5), StatusCode::Eof),
+        ),
+        (
+            "status_no_such_file",
+            "Missing file",
+            status(RequestId(7), StatusCode::NoSuchFile),
+        ),
+        (
+            "extended_reply_limits",
+            "Reply to limits@openssh.com",
+            ExtendedReply {
+                id: RequestId(8),
+                data: russh_sftp::ser::to_bytes(&LimitsExtension {
+                    max_packet_len: 256 * 1024,
+                    max_read_len: 255 * 1024,
+                    max_write_len: 255 * 1024,
+                    max_open_handles: 64,
+                })
+                .unwrap()
+                .to_vec(),
+            }
+            .into(),
+        ),
+    ]
+}
+
+fn own_fixture(description: &str, packet: Packet) -> String {
+    let frame = Bytes::try_from(packet).unwrap();
+    format!(
+        "# {description}. Generated by `cargo test --test corpus -- --ignored`\n\
+         # packet: {}\n\
+         # roundtrip: true\n{}",
+        Packet::try_from(&mut frame.slice(4..)).unwrap().name(),
+        encode_hex(&frame)
+    )
+}
+
+#[test]
+#[ignore = "regenerates the fixtures of the own serializer"]
+fn generate_own_fixtures() {
+    let dir = Path::new(CORPUS).join("russh-sftp");
+    fs::create_dir_all(&dir).unwrap();
+
+    for (name, description, packet) in own_packets() {
+        let path = dir.join(format!("{name}.hex"));
+        fs::write(path, own_fixture(description, packet)).unwrap();
+    }
+}
+
+#[test]
+fn own_fixtures_are_current() {
+    let dir = Path::new(CORPUS).join("russh-sftp");
+
+    for (name, description, packet) in own_packets() {
+        let path = dir.join(format!("{name}.hex"));
+        let current = fs::read_to_string(&path).unwrap_or_default();
+        assert_eq!(
+            current,
+            own_fixture(description, packet),
+            "{} is outdated, regenerate with `cargo test --test corpus -- --ignored`",
+            path.display()
+        );
+    }
+}
+
+#[test]
+fn decode_corpus() {
+    let fixtures = fixtures();
+    assert!(!fixtures.is_empty());
+
+    for fixture in fixtures {
+        let packet = fixture.decode();
+        assert_eq!(packet.name(), fixture.packet, "{}", fixture.path.display());
+
+        if fixture.roundtrip {
+            let bytes = Bytes::try_from(packet).unwrap();
+            assert_eq!(
+                bytes.as_ref(),
+                fixture.frame.as_slice(),
+                "{} does not round-trip",
+                fixture.path.display()
+            );
+        }
+    }
+}
+
+#[test]
+fn preserve_corpus() {
+    for fixture in fixtures() {
+        let preserved = PreservedPacket::try_from(Bytes::copy_from_slice(&fixture.frame[4..]))
+            .unwrap_or_else(|err| panic!("{}: {err}", fixture.path.display()));
+        assert!(!preserved.is_modified());
+        assert_eq!(preserved.packet().name(), fixture.packet);
+
+        let bytes = Bytes::try_from(preserved).unwrap();
+        assert_eq!(
+            bytes.as_ref(),
+            fixture.frame.as_slice(),
+            "{} is not preserved",
+            fixture.path.display()
+        );
+    }
+}
+
+#[test]
+fn preserve_modified() {
+    let frame =
+        Fixture::read(&Path::new(CORPUS).join("synthetic/status_without_message.hex")).frame;
+    let mut preserved = PreservedPacket::try_from(Bytes::copy_from_slice(&frame[4..])).unwrap();
+
+    // reading does not encode the packet again
+    assert!(matches!(preserved.packet(), Packet::Status(status) if status.id == 3));
+    assert!(!preserved.is_modified());
+
+    let Packet::Status(status) = preserved.packet_mut() else {
+        panic!("not a status");
+    };
+    status.id = RequestId(9);
+    assert!(preserved.is_modified());
+    assert_eq!(preserved.original(), None);
+
+    let bytes = Bytes::try_from(preserved).unwrap();
+    let Packet::Status(status) = Packet::try_from(&mut bytes.slice(4..)).unwrap() else {
+        panic!("not a status");
+    };
+    assert_eq!(
+        (status.id, status.status_code),
+        (RequestId(9), StatusCode::NoSuchFile)
+    );
+    assert_ne!(bytes.len(), frame.len());
+}
+
+#[test]
+fn openssh_init() {
+    let Packet::Init(init) = fixture("openssh/init.hex") else {
+        panic!("not an init");
+    };
+
+    assert_eq!(init.version, 3);
+    assert!(init.extensions.is_empty());
+}
+
+#[test]
+fn openssh_open() {
+    let Packet::Open(open) = fixture("openssh/open_write.hex") else {
+        panic!("not an open");
+    };
+
+    assert_eq!(open.filename, "/dir/up.txt");
+    assert_eq!(
+        open.pflags.bits(),
+        (OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE).bits()
+    );
+    assert_eq!(open.attrs.permissions, Some(0o644));
+    assert_eq!(open.attrs.size, None);
+
+    let Packet::Open(open) = fixture("openssh/open_with_times.hex") else {
+        panic!("not an open");
+    };
+
+    assert_eq!(open.attrs.permissions, Some(0o644));
+    assert!(open.attrs.atime.is_some() && open.attrs.mtime.is_some());
+}
+
+#[test]
+fn openssh_read_write() {
+    let Packet::Read(read) = fixture("openssh/read.hex") else {
+        panic!("not a read");
+    };
+
+    assert_eq!(
+        (read.handle.as_str(), read.offset, read.len),
+        ("h3", 0, 32768)
+    );
+
+    let Packet::Write(write) = fixture("openssh/write.hex") else {
+        panic!("not a write");
+    };
+
+    assert_eq!(write.offset, 0);
+    assert_eq!(write.data, b"upload data\n");
+}
+
+#[test]
+fn openssh_symlink_order() {
+    let Packet::Symlink(symlink) = fixture("openssh/symlink.hex") else {
+        panic!("not a symlink");
+    };
+
+    // OpenSSH sends the target first, contrary to the specification
+    assert_eq!(symlink.linkpath, "/dir/a.txt");
+    assert_eq!(symlink.targetpath, "/dir/link");
+}
+
+#[test]
+fn openssh_hardlink() {
+    let Packet::Extended(extended) = fixture("openssh/extended_hardlink.hex") else {
+        panic!("not an extended request");
+    };
+
+    assert_eq!(extended.request, "hardlink@openssh.com");
+
+    let link: HardlinkExtension = de::from_bytes(&mut extended.data.into()).unwrap();
+    assert_eq!(link.oldpath, "/dir/a.txt");
+    assert_eq!(link.newpath, "/dir/hard");
+}
+
+#[test]
+fn home_directory() {
+    let Packet::Extended(extended) = fixture("synthetic/extended_home_directory.hex") else {
+        panic!("not an extended request");
+    };
+
+    assert_eq!(extended.request, "home-directory");
+
+    let home: HomeDirectoryExtension = de::from_bytes(&mut extended.data.into()).unwrap();
+    assert_eq!(home.username, "alice");
+
+    let Packet::Name(name) = fixture("synthetic/name_home_directory.hex") else {
+        panic!("not a name");
+    };
+
+    assert_eq!(name.id, extended.id);
+    assert_eq!(name.files.len(), 1);
+    assert_eq!(name.files[0].filename, "/home/alice");
+}
+
+#[test]
+fn attrs_with_extended_pairs() {
+    let Packet::Attrs(attrs) = fixture("synthetic/attrs_extended_pairs.hex") else {
+        panic!("not attrs");
+    };
+
+    assert_eq!(attrs.id, 7);
+    assert_eq!(attrs.attrs.size, Some(1234));
+    assert_eq!(attrs.attrs.permissions, Some(0o100644));
+    assert!(attrs.attrs.is_regular());
+
+    let names = attrs.attrs.extended.iter().map(|attr| attr.name.as_str());
+    assert_eq!(
+        names.collect::<Vec<_>>(),
+        ["mime@example.com", "acl@example.com"]
+    );
+    assert_eq!(
+        attrs.attrs.extended_attr("mime@example.com"),
+        Some(&b"text/plain"[..])
+    );
+    assert_eq!(
+        attrs.attrs.extended_attr("acl@example.com"),
+        Some(&b"\0\x01binary"[..])
+    );
+}
+
+#[test]
+fn attrs_acl() {
+    let Packet::Attrs(attrs) = fixture("synthetic/attrs_acl.hex") else {
+        panic!("not attrs");
+    };
+
+    let acl = attrs.attrs.acl().unwrap();
+    assert_eq!(acl.flags, None);
+    assert_eq!(
+        acl.entries,
+        [
+            AclEntry {
+                ace_type: AclEntry::ACCESS_ALLOWED,
+                ace_flags: 0,
+                ace_mask: 0x23,
+                who: "OWNER@".to_owned(),
+            },
+            AclEntry {
+                ace_type: AclEntry::ACCESS_DENIED,
+                ace_flags: 0x40,
+                ace_mask: 0x2,
+                who: "GROUP@".to_owned(),
+            },
+        ]
+    );
+
+    let raw = attrs.attrs.extended_attr("user.comment@example.com");
+    assert_eq!(raw, Some(&b"\xff\xfe raw"[..]));
+}
+
+#[test]
+fn attrs_acl_unknown_format() {
+    let Packet::Attrs(attrs) = fixture("synthetic/attrs_acl_unknown.hex") else {
+        panic!("not attrs");
+    };
+
+    // kept as raw data instead of failing
+    assert_eq!(attrs.attrs.acl(), None);
+    let raw = attrs.attrs.extended_attr(ACL).unwrap();
+    assert_eq!(raw, b"\0\0\0\x05OWNER@");
+}
+
+#[test]
+fn acl_version_6_format() {
+    let mut blob = 0x1u32.to_be_bytes().to_vec();
+    blob.extend(1u32.to_be_bytes());
+    blob.extend([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+    blob.extend(b"\0\0\0\x09EVERYONE@");
+
+    let acl = AclEntries::parse(&blob).unwrap();
+    assert_eq!(acl.flags, Some(1));
+    assert_eq!(acl.entries[0].who, "EVERYONE@");
+    assert_eq!(acl.entries[0].ace_mask, 1);
+
+    assert_eq!(AclEntries::parse(&blob[..blob.len() - 1]), None);
+}
+
+#[test]
+fn status_without_message() {
+    let Packet::Status(status) = fixture("synthetic/status_without_message.hex") else {
+        panic!("not a status");
+    };
+
+    assert_eq!(status.id, 3);
+    assert_eq!(status.status_code, StatusCode::NoSuchFile);
+    assert!(status.error_message.is_empty());
+    assert!(status.language_tag.is_empty());
+}
+
+#[test]
+fn version_with_vendor_id() {
+    let Packet::Version(version) = fixture("synthetic/version_vendor_id.hex") else {
+        panic!("not a version");
+    };
+
+    assert_eq!(version.version, 3);
+    assert!(version.extensions.contains_key("vendor-id"));
+    assert_eq!(version.extensions["newline"], "\r\n");
+}
+
+#[test]
+fn version_extension_values() {
+    let Packet::Version(version) = fixture("synthetic/version_values_nul.hex") else {
+        panic!("not a version");
+    };
+
+    assert_eq!(version.extensions[LIMITS], "1\0");
+    assert!(version.supports(LIMITS, "1"));
+    assert!(version.supports(FSYNC, "1"));
+    assert!(!version.supports(HARDLINK, "1"));
+
+    let Packet::Version(version) = fixture("synthetic/version_values_empty.hex") else {
+        panic!("not a version");
+    };
+
+    // the presence of limits@openssh.com is enough, not of the other extensions
+    assert!(version.supports(LIMITS, "1"));
+    assert!(version.supports(HARDLINK, "1"));
+    assert!(version.supports(STATVFS, "2"));
+    assert!(!version.supports(STATVFS, "1"));
+    assert!(!version.supports(FSYNC, "1"));
+}
+
+#[test]
+fn status_fields() {
+    let Packet::Status(Status {
+        id, status_code, ..
+    }) = fixture("russh-sftp/status_eof.hex")
+    else {
+        panic!("not a status");
+    };
+
+    assert_eq!((id, status_code), (RequestId(5), StatusCode::Eof));
+}
+
+#[test]
+fn empty_data() {
+    let Packet::Data(data) = fixture("synthetic/data_empty.hex") else {
+        panic!("not data");
+    };
+
+    // served by tests/empty_data.rs to the reads of `client::fs::File`
+    assert_eq!(data.id, 4);
+    assert!(data.data.is_empty());
+}
+
+#[test]
+fn proftpd_version() {
+    let Packet::Version(version) = fixture("proftpd/version.hex") else {
+        panic!("not a version");
+    };
+
+    assert_eq!(version.version, 3);
+    assert_eq!(version.extensions["newline"], "\n");
+    assert_eq!(version.extensions["versions"], "2,3,4,5,6");
+    assert!(version.extensions.contains_key("vendor-id"));
+    assert!(version.supports(HARDLINK, "1"));
+    assert!(version.supports(FSYNC, "1"));
+    assert!(version.supports(STATVFS, "2"));
+    assert!(!version.supports(LIMITS, "1"));
+}
+
+#[test]
+fn proftpd_read_dir() {
+    let Packet::Name(name) = fixture("proftpd/name_readdir.hex") else {
+        panic!("not a name");
+    };
+
+    let names = name.files.iter().map(|f| f.filename.as_str());
+    assert_eq!(names.collect::<Vec<_>>(), [".", "..", "notes.txt"]);
+
+    let file = &name.files[2];
+    assert!(file.longname.starts_with("-rw-r--r--"));
+    assert!(file.longname.ends_with(" notes.txt"));
+    assert!(file.attrs.is_regular());
+    assert_eq!(file.attrs.size, Some(13));
+    assert_eq!((file.attrs.uid, file.attrs.gid), (Some(1000), Some(1000)));
+    assert!(name.files[0].attrs.is_dir());
+    assert_eq!(name.files[1].attrs.uid, Some(0));
+
+    let Packet::Name(name) = fixture("proftpd/name_realpath.hex") else {
+        panic!("not a name");
+    };
+
+    assert_eq!(name.files.len(), 1);
+    assert_eq!(name.files[0].filename, "/home/alice");
+    assert!(name.files[0].attrs.is_dir());
+}
+
+#[test]
+fn proftpd_status() {
+    let Packet::Status(status) = fixture("proftpd/status_no_such_file.hex") else {
+        panic!("not a status");
+    };
+
+    assert_eq!(status.status_code, StatusCode::NoSuchFile);
+    assert_eq!(status.error_message, "No such file or directory");
+    assert_eq!(status.language_tag, "en-US");
+
+    let Packet::Handle(handle) = fixture("proftpd/handle.hex") else {
+        panic!("not a handle");
+    };
+
+    assert_eq!(handle.handle, "8c3e5a1f0b2d4e67");
+}
+
+#[test]
+fn bitvise_version() {
+    let Packet::Version(version) = fixture("bitvise/version.hex") else {
+        panic!("not a version");
+    };
+
+    assert_eq!(version.version, 3);
+    assert_eq!(version.extensions["newline"], "\r\n");
+    assert!(version.extensions.contains_key("vendor-id"));
+    assert!(version.supports(HARDLINK, "1"));
+    assert!(version.supports(STATVFS, "2"));
+}
+
+#[test]
+fn bitvise_windows_paths() {
+    let Packet::Name(name) = fixture("bitvise/name_realpath.hex") else {
+        panic!("not a name");
+    };
+
+    // neither longname nor attributes
+    let home = &name.files[0];
+    assert_eq!(home.filename, "/C/Users/alice");
+    assert!(home.longname.is_empty());
+    assert_eq!(home.attrs.permissions, None);
+
+    let Packet::Name(name) = fixture("bitvise/name_readdir.hex") else {
+        panic!("not a name");
+    };
+
+    let names = name.files.iter().map(|f| f.filename.as_str());
+    assert_eq!(names.collect::<Vec<_>>(), ["Documents", "report.txt"]);
+    assert!(name.files[0].attrs.is_dir());
+    assert!(name.files.iter().all(|f| f.attrs.uid.is_none()));
+
+    let Packet::Attrs(attrs) = fixture("bitvise/attrs.hex") else {
+        panic!("not attrs");
+    };
+
+    assert_eq!(attrs.attrs.permissions, Some(0o100666));
+    assert_eq!((attrs.attrs.uid, attrs.attrs.gid), (None, None));
+    assert!(attrs.attrs.mtime.is_some());
+}
+
+#[test]
+fn bitvise_status() {
+    let Packet::Status(status) = fixture("bitvise/status_no_such_file.hex") else {
+        panic!("not a status");
+    };
+
+    assert_eq!(status.status_code, StatusCode::NoSuchFile);
+    assert_eq!(
+        status.error_message,
+        "The system cannot find the file specified."
+    );
+    assert_eq!(status.language_tag, "en");
+
+    let Packet::Status(status) = fixture("bitvise/status_eof.hex") else {
+        panic!("not a status");
+    };
+
+    assert_eq!(status.status_code, StatusCode::Eof);
+    assert!(status.error_message.is_empty());
+}
@@ -0,0 +1,122 @@
+//! Attributes of SSH_FXP_OPEN as sent on the wire, empty unless a mode is asked for.
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use russh_sftp::{
+    client::SftpSession,
+    protocol::{FileAttributes, OpenFlags},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, DuplexStream},
+    sync::mpsc,
+};
+
+const SSH_FXP_VERSION: u8 = 2;
+const SSH_FXP_OPEN: u8 = 3;
+const SSH_FXP_HANDLE: u8 = 102;
+const SSH_FILEXFER_ATTR_PERMISSIONS: u32 = 0x04;
+
+async fn read_frame(stream: &mut DuplexStream) -> Option<Bytes> {
+    let len = stream.read_u32().await.ok()?;
+    let mut frame = vec![0; len as usize];
+    stream.read_exact(&mut frame).await.ok()?;
+    Some(frame.into())
+}
+
+async fn write_frame(stream: &mut DuplexStream, frame: BytesMut) {
+    stream.write_u32(frame.len() as u32).await.unwrap();
+    stream.write_all(&frame).await.unwrap();
+}
+
+/// Answers SSH_FXP_INIT, then each SSH_FXP_OPEN with a handle, forwarding its
+/// open flags, the flags of its attributes and the permissions if present
+async fn server(mut stream: DuplexStream, opened: mpsc::UnboundedSender<(u32, u32, Option<u32>)>) {
+    read_frame(&mut stream).await.unwrap();
+    let mut version = BytesMut::new();
+    version.put_u8(SSH_FXP_VERSION);
+    version.put_u32(3);
+    write_frame(&mut stream, version).await;
+
+    while let Some(mut frame) = read_frame(&mut stream).await {
+        if frame.get_u8() != SSH_FXP_OPEN {
+            continue;
+        }
+
+        let id = frame.get_u32();
+        let len = frame.get_u32() as usize;
+        frame.advance(len);
+        let pflags = frame.get_u32();
+        let attr_flags = frame.get_u32();
+        let permissions =
+            (attr_flags & SSH_FILEXFER_ATTR_PERMISSIONS != 0).then(|| frame.get_u32());
+        opened.send((pflags, attr_flags, permissions)).unwrap();
+
+        let mut handle = BytesMut::new();
+        handle.put_u8(SSH_FXP_HANDLE);
+        handle.put_u32(id);
+        handle.put_u32(1);
+        handle.put_u8(b'h');
+        write_frame(&mut stream, handle).await;
+    }
+}
+
+async fn session() -> (
+    SftpSession,
+    mpsc::UnboundedReceiver<(u32, u32, Option<u32>)>,
+) {
+    let (client, stream) = tokio::io::duplex(64 * 1024);
+    let (opened_tx, opened) = mpsc::unbounded_channel();
+    tokio::spawn(server(stream, opened_tx));
+    (SftpSession::new(client).await.unwrap(), opened)
+}
+
+#[tokio::test]
+async fn empty_attributes_by_default() {
+    let (sftp, mut opened) = session().await;
+
+    sftp.create("/new").await.unwrap();
+    let (pflags, attr_flags, _) = opened.recv().await.unwrap();
+    let flags = OpenFlags::CREATE | OpenFlags::TRUNCATE | OpenFlags::WRITE;
+    assert_eq!((pflags, attr_flags), (flags.bits(), 0));
+
+    sftp.open("/old").await.unwrap();
+    let (pflags, attr_flags, _) = opened.recv().await.unwrap();
+    assert_eq!((pflags, attr_flags), (OpenFlags::READ.bits(), 0));
+}
+
+#[tokio::test]
+async fn default_create_mode_sent_on_create() {
+    let (sftp, mut opened) = session().await;
+    sftp.set_default_create_mode(Some(0o640));
+
+    sftp.create("/new").await.unwrap();
+    let (_, attr_flags, permissions) = opened.recv().await.unwrap();
+    assert_eq!(attr_flags, SSH_FILEXFER_ATTR_PERMISSIONS);
+    assert_eq!(permissions, Some(0o640));
+
+    // nothing may be created
+    sftp.open_with_flags("/old", OpenFlags::READ | OpenFlags::WRITE)
+        .await
+        .unwrap();
+    let (_, attr_flags, _) = opened.recv().await.unwrap();
+    assert_eq!(attr_flags, 0);
+
+    sftp.set_default_create_mode(None);
+    sftp.create("/new").await.unwrap();
+    let (_, attr_flags, _) = opened.recv().await.unwrap();
+    assert_eq!(attr_flags, 0);
+}
+
+#[tokio::test]
+async fn explicit_attributes_sent_as_given() {
+    let (sftp, mut opened) = session().await;
+    sftp.set_default_create_mode(Some(0o640));
+
+    let mut attrs = FileAttributes::empty();
+    attrs.permissions = Some(0o600);
+    sftp.open_with_flags_and_attributes("/new", OpenFlags::CREATE | OpenFlags::WRITE, attrs)
+        .await
+        .unwrap();
+    let (_, attr_flags, permissions) = opened.recv().await.unwrap();
+    assert_eq!(attr_flags, SSH_FILEXFER_ATTR_PERMISSIONS);
+    assert_eq!(permissions, Some(0o600));
+}
@@ -0,0 +1,94 @@
+//! Requests built by the caller and sent through [`RawSftpSession::send_custom`] and
+//! [`RawSftpSession::send_raw_frame`], including malformed frames.
+
+mod common;
+
+use bytes::{BufMut, Bytes, BytesMut};
+use common::{raw_pair, Memory};
+use russh_sftp::{
+    client::{error::Error, RawSftpSession},
+    extensions,
+    protocol::{Packet, RequestId, Stat, Status, StatusCode},
+    server::Config,
+};
+
+const SSH_FXP_EXTENDED: u8 = 200;
+
+async fn session() -> RawSftpSession {
+    let memory = Memory::default();
+    memory
+        .files
+        .lock()
+        .unwrap()
+        .insert("/file".to_owned(), b"data".to_vec());
+
+    raw_pair(memory, Config::default()).await.0
+}
+
+#[tokio::test]
+async fn custom_packet_round_trip() {
+    let session = session().await;
+
+    let stat = Stat {
+        id: RequestId(1000),
+        path: "/file".to_owned(),
+    };
+    match session.send_custom(stat.into()).await.unwrap() {
+        Packet::Attrs(attrs) => {
+            assert_eq!(attrs.id, RequestId(1000));
+            assert_eq!(attrs.attrs.size, Some(4));
+        }
+        reply => panic!("{} is not attributes", reply.name()),
+    }
+
+    // the session keeps working with its own ids
+    assert_eq!(session.stat("/file").await.unwrap().attrs.size, Some(4));
+}
+
+#[tokio::test]
+async fn custom_responses_rejected() {
+    let session = session().await;
+
+    let status = Status::new(RequestId(1000), StatusCode::Ok);
+    let err = session.send_custom(status.into()).await.unwrap_err();
+    assert!(
+        matches!(&err, Error::UnexpectedBehavior(msg) if msg.contains("is not a request")),
+        "{err:?}"
+    );
+}
+
+#[tokio::test]
+async fn malformed_frame_answered_with_bad_message() {
+    let session = session().await;
+
+    // SSH_FXP_EXTENDED for hardlink@openssh.com whose paths are cut short
+    let mut payload = BytesMut::new();
+    payload.put_u8(SSH_FXP_EXTENDED);
+    payload.put_u32(7);
+    payload.put_u32(extensions::HARDLINK.len() as u32);
+    payload.put_slice(extensions::HARDLINK.as_bytes());
+    payload.put_u32(100);
+    payload.put_slice(b"/a");
+
+    let mut frame = BytesMut::new();
+    frame.put_u32(payload.len() as u32);
+    frame.put(payload);
+
+    let reply = session
+        .send_raw_frame(frame.freeze(), RequestId(7))
+        .await
+        .unwrap();
+    match reply {
+        Packet::Status(status) => {
+            assert_eq!(status.id, RequestId(7));
+            assert_eq!(status.status_code, StatusCode::BadMessage);
+        }
+        reply => panic!("{} is not a status", reply.name()),
+    }
+
+    // the server kept the session
+    assert_eq!(session.stat("/file").await.unwrap().attrs.size, Some(4));
+
+    let err = session.send_raw_frame(Bytes::new(), RequestId(8)).await;
+    assert!(matches!(err, Err(Error::UnexpectedBehavior(_))), "{err:?}");
+}
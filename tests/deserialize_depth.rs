@@ -0,0 +1,125 @@
+//! Crafted packets refused or parsed without exhausting a small stack, such as the
+//! 128 KB threads of musl.
+
+use bytes::{BufMut, Bytes, BytesMut};
+use russh_sftp::{
+    de,
+    protocol::{Extended, Packet},
+    Error,
+};
+use serde::Deserialize;
+
+/// Stack of the threads running the parsing, below the default of musl
+const SMALL_STACK: usize = 64 * 1024;
+
+const SSH_FXP_EXTENDED: u8 = 200;
+const SSH_FXP_NAME: u8 = 104;
+
+/// Type nesting itself as deep as the input asks
+#[derive(Debug, Deserialize)]
+enum Nest {
+    Leaf,
+    Node(Box<Nest>),
+}
+
+impl Nest {
+    fn depth(&self) -> usize {
+        let mut depth = 0;
+        let mut nest = self;
+        while let Nest::Node(inner) = nest {
+            depth += 1;
+            nest = inner;
+        }
+        depth
+    }
+}
+
+fn on_small_stack<T: Send + 'static>(parse: impl FnOnce() -> T + Send + 'static) -> T {
+    std::thread::Builder::new()
+        .stack_size(SMALL_STACK)
+        .spawn(parse)
+        .unwrap()
+        .join()
+        .unwrap()
+}
+
+/// `nodes` variants of [`Nest::Node`] around a [`Nest::Leaf`]
+fn nested(nodes: usize) -> Bytes {
+    let mut bytes = BytesMut::new();
+    for _ in 0..nodes {
+        bytes.put_u32(1);
+    }
+    bytes.put_u32(0);
+    bytes.freeze()
+}
+
+fn too_deep(result: Result<Nest, Error>) -> bool {
+    matches!(result, Err(Error::BadMessage(msg)) if msg == "nesting too deep")
+}
+
+#[test]
+fn nesting_refused_past_the_limit() {
+    let result = on_small_stack(|| de::from_bytes::<Nest>(&mut nested(1_000_000)));
+    assert!(too_deep(result));
+}
+
+#[test]
+fn nesting_within_the_limit() {
+    let nest = on_small_stack(|| de::from_bytes::<Nest>(&mut nested(10)).unwrap());
+    assert_eq!(nest.depth(), 10);
+
+    assert!(too_deep(de::from_bytes_with_max_depth::<Nest>(
+        &mut nested(10),
+        5
+    )));
+}
+
+#[test]
+fn extended_data_copied_at_once() {
+    let data = vec![0x5a; 4 * 1024 * 1024];
+    let mut bytes = BytesMut::new();
+    bytes.put_u8(SSH_FXP_EXTENDED);
+    bytes.put_u32(3);
+    bytes.put_u32(4);
+    bytes.put_slice(b"test");
+    bytes.put_slice(&data);
+
+    let packet = on_small_stack(move || Packet::try_from(&mut bytes.freeze()).unwrap());
+    let Packet::Extended(Extended {
+        request,
+        data: received,
+        ..
+    }) = packet
+    else {
+        panic!("{packet:?}");
+    };
+    assert_eq!(request, "test");
+    assert!(received == data);
+}
+
+#[test]
+fn name_with_huge_counts() {
+    // a count of entries far beyond the bytes that follow
+    let mut bytes = BytesMut::new();
+    bytes.put_u8(SSH_FXP_NAME);
+    bytes.put_u32(3);
+    bytes.put_u32(u32::MAX);
+    bytes.put_u32(1);
+    bytes.put_slice(b"a");
+    let result = on_small_stack(move || Packet::try_from(&mut bytes.freeze()).map(|_| ()));
+    assert!(result.is_err());
+
+    // an entry announcing more extended attributes than sent
+    let mut bytes = BytesMut::new();
+    bytes.put_u8(SSH_FXP_NAME);
+    bytes.put_u32(3);
+    bytes.put_u32(1);
+    for field in ["a", "a"] {
+        bytes.put_u32(field.len() as u32);
+        bytes.put_slice(field.as_bytes());
+    }
+    bytes.put_u32(0x8000_0000);
+    bytes.put_u32(u32::MAX);
+    let result = on_small_stack(move || Packet::try_from(&mut bytes.freeze()).map(|_| ()));
+    assert!(result.is_err());
+}
@@ -0,0 +1,230 @@
+//! JSON representation of every packet type, compared with `tests/snapshots/diagnostic.jsonl`
+//! so that changes of the format are noticed.
+//!
+//! The snapshot is regenerated with `cargo test --features diagnostics --test diagnostic -- --ignored`.
+
+use russh_sftp::protocol::{
+    Attrs, Close, Data, Extended, ExtendedReply, FSetStat, File, FileAttributes, Fstat, Handle,
+    Init, Lstat, MkDir, Name, Open, OpenDir, OpenFlags, Packet, Read, ReadDir, ReadLink, RealPath,
+    Remove, Rename, RequestId, RmDir, SetStat, Stat, Status, StatusCode, Symlink, Version, Write,
+};
+use std::{collections::HashMap, fs};
+
+const SNAPSHOT: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/snapshots/diagnostic.jsonl"
+);
+
+fn attrs() -> FileAttributes {
+    FileAttributes {
+        size: Some(13),
+        uid: Some(1000),
+        gid: Some(1000),
+        permissions: Some(0o100644),
+        atime: Some(1_700_000_000.into()),
+        mtime: Some(1_700_000_000.into()),
+        ..FileAttributes::empty()
+    }
+}
+
+fn packets() -> Vec<Packet> {
+    let extensions = HashMap::from([
+        ("limits@openssh.com".to_owned(), "1".to_owned()),
+        ("fsync@openssh.com".to_owned(), "1".to_owned()),
+    ]);
+    let handle = || "h1".to_owned();
+    let path = || "/dir/a.txt".to_owned();
+
+    vec![
+        Init {
+            version: 3,
+            extensions: HashMap::new(),
+        }
+        .into(),
+        Version {
+            version: 3,
+            extensions,
+        }
+        .into(),
+        Open {
+            id: RequestId(1),
+            filename: path(),
+            pflags: OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::from_bits_retain(0x100),
+            attrs: FileAttributes {
+                permissions: Some(0o644),
+                ..FileAttributes::empty()
+            },
+        }
+        .into(),
+        Close {
+            id: RequestId(2),
+            handle: handle(),
+        }
+        .into(),
+        Read {
+            id: RequestId(3),
+            handle: handle(),
+            offset: 0,
+            len: 32768,
+        }
+        .into(),
+        Write {
+            id: RequestId(4),
+            handle: handle(),
+            offset: 1024,
+            data: b"\x00\xffdata".to_vec(),
+        }
+        .into(),
+        Lstat {
+            id: RequestId(5),
+            path: path(),
+        }
+        .into(),
+        Fstat {
+            id: RequestId(6),
+            handle: handle(),
+        }
+        .into(),
+        SetStat {
+            id: RequestId(7),
+            path: path(),
+            attrs: attrs(),
+        }
+        .into(),
+        FSetStat {
+            id: RequestId(8),
+            handle: handle(),
+            attrs: FileAttributes {
+                size: Some(0),
+                ..FileAttributes::empty()
+            },
+        }
+        .into(),
+        OpenDir {
+            id: RequestId(9),
+            path: "/dir".to_owned(),
+        }
+        .into(),
+        ReadDir {
+            id: RequestId(10),
+            handle: handle(),
+        }
+        .into(),
+        Remove {
+            id: RequestId(11),
+            filename: path(),
+        }
+        .into(),
+        MkDir {
+            id: RequestId(12),
+            path: "/dir/sub".to_owned(),
+            attrs: FileAttributes::empty(),
+        }
+        .into(),
+        RmDir {
+            id: RequestId(13),
+            path: "/dir/sub".to_owned(),
+        }
+        .into(),
+        RealPath {
+            id: RequestId(14),
+            path: ".".to_owned(),
+        }
+        .into(),
+        Stat {
+            id: RequestId(15),
+            path: path(),
+        }
+        .into(),
+        Rename {
+            id: RequestId(16),
+            oldpath: path(),
+            newpath: "/dir/b.txt".to_owned(),
+        }
+        .into(),
+        ReadLink {
+            id: RequestId(17),
+            path: "/dir/link".to_owned(),
+        }
+        .into(),
+        Symlink {
+            id: RequestId(18),
+            linkpath: "/dir/link".to_owned(),
+            targetpath: path(),
+        }
+        .into(),
+        Status {
+            id: RequestId(19),
+            status_code: StatusCode::NoSuchFile,
+            error_message: "No such file".to_owned(),
+            language_tag: "en-US".to_owned(),
+        }
+        .into(),
+        Handle {
+            id: RequestId(20),
+            handle: handle(),
+        }
+        .into(),
+        Data {
+            id: RequestId(21),
+            data: b"hello\n".to_vec(),
+        }
+        .into(),
+        Name {
+            id: RequestId(22),
+            files: vec![File::new("a.txt", attrs())],
+        }
+        .into(),
+        Attrs {
+            id: RequestId(23),
+            attrs: attrs(),
+        }
+        .into(),
+        Extended {
+            id: RequestId(24),
+            request: "hardlink@openssh.com".to_owned(),
+            data: vec![0, 0, 0, 1, b'a', 0, 0, 0, 1, b'b'],
+        }
+        .into(),
+        ExtendedReply {
+            id: RequestId(25),
+            data: vec![0, 0, 0, 0, 0, 4, 0, 0],
+        }
+        .into(),
+    ]
+}
+
+fn snapshot() -> String {
+    packets()
+        .iter()
+        .map(|packet| packet.to_diagnostic_json().to_string() + "\n")
+        .collect()
+}
+
+#[test]
+#[ignore = "regenerates the snapshot"]
+fn generate_snapshot() {
+    fs::write(SNAPSHOT, snapshot()).unwrap();
+}
+
+#[test]
+fn snapshot_is_current() {
+    let current = fs::read_to_string(SNAPSHOT).unwrap_or_default();
+    for (current, expected) in current.lines().zip(snapshot().lines()) {
+        assert_eq!(current, expected);
+    }
+
+    assert_eq!(current, snapshot(), "regenerate with `-- --ignored`");
+}
+
+#[test]
+fn every_packet_type() {
+    let mut names = packets()
+        .iter()
+        .map(|packet| packet.to_diagnostic_json()["type"].to_string())
+        .collect::<Vec<_>>();
+    names.sort();
+    names.dedup();
+
+    assert_eq!(names.len(), 27);
+}
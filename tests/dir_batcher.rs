@@ -0,0 +1,154 @@
+//! Listings of a paginated backend served through [`DirBatcher`] with the pagination
+//! hint, fetching pages no further than the client reads.
+
+use russh_sftp::{
+    client::{RawSftpSession, SftpSession},
+    protocol::{File, Handle, Name, RequestId, Status, StatusCode, Version},
+    server::{self, Config, DirBatcher, Handler, ReadDirContext, SessionContext},
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+const OBJECTS: usize = 10_000;
+const PAGE: usize = 1000;
+
+/// Objects of a bucket listed a page at a time, as with ListObjects
+struct Pages {
+    next: usize,
+    page: VecDeque<File>,
+    fetches: Arc<AtomicU64>,
+}
+
+impl Iterator for Pages {
+    type Item = File;
+
+    fn next(&mut self) -> Option<File> {
+        if self.page.is_empty() && self.next < OBJECTS {
+            self.fetches.fetch_add(1, Ordering::Relaxed);
+            let end = OBJECTS.min(self.next + PAGE);
+            self.page = (self.next..end)
+                .map(|i| File::dummy(format!("object-{i:05}")))
+                .collect();
+            self.next = end;
+        }
+
+        self.page.pop_front()
+    }
+}
+
+#[derive(Default)]
+struct Bucket {
+    batcher: DirBatcher<Pages>,
+    hint: Option<ReadDirContext>,
+    fetches: Arc<AtomicU64>,
+    hints: Arc<Mutex<Vec<ReadDirContext>>>,
+}
+
+#[async_trait::async_trait]
+impl Handler for Bucket {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    fn set_context(&mut self, context: &SessionContext) {
+        self.hint = context.read_dir();
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn opendir(&mut self, id: RequestId, path: String) -> Result<Handle, Self::Error> {
+        let pages = Pages {
+            next: 0,
+            page: VecDeque::new(),
+            fetches: self.fetches.clone(),
+        };
+        self.batcher.insert(path.as_str(), pages);
+        Ok(Handle { id, handle: path })
+    }
+
+    async fn readdir(&mut self, id: RequestId, handle: String) -> Result<Name, Self::Error> {
+        let hint = self.hint.expect("hint of SSH_FXP_READDIR");
+        self.hints.lock().unwrap().push(hint);
+
+        let files = self.batcher.next_batch(&handle, Some(&hint))?;
+        Ok(Name { id, files })
+    }
+
+    async fn close(&mut self, id: RequestId, handle: String) -> Result<Status, Self::Error> {
+        self.batcher.remove(&handle);
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+}
+
+#[tokio::test]
+async fn whole_listing_fetched_once() {
+    let bucket = Bucket::default();
+    let (fetches, hints) = (bucket.fetches.clone(), bucket.hints.clone());
+    let (sftp, _server) = SftpSession::pair(bucket).await.unwrap();
+
+    let names = sftp
+        .read_dir("/bucket")
+        .await
+        .unwrap()
+        .map(|entry| entry.file_name())
+        .collect::<Vec<_>>();
+
+    let expected = (0..OBJECTS)
+        .map(|i| format!("object-{i:05}"))
+        .collect::<Vec<_>>();
+    assert_eq!(names, expected);
+
+    // every page is fetched once, however the batches cut across them
+    assert_eq!(fetches.load(Ordering::Relaxed), (OBJECTS / PAGE) as u64);
+
+    // the hint follows the entries returned so far, the last one ends the listing
+    let hints = hints.lock().unwrap();
+    assert!(hints.len() > 2, "{} batches", hints.len());
+    assert_eq!(hints[0].previously_returned, 0);
+    assert_eq!(hints.last().unwrap().previously_returned, OBJECTS as u64);
+    assert!(hints
+        .windows(2)
+        .all(|w| w[0].previously_returned < w[1].previously_returned));
+}
+
+#[tokio::test]
+async fn pages_fetched_no_further_than_read() {
+    let bucket = Bucket::default();
+    let fetches = bucket.fetches.clone();
+    let (client, stream) = tokio::io::duplex(64 * 1024);
+    let config = Config {
+        max_response_packet_len: Some(16 * 1024),
+        ..Default::default()
+    };
+    tokio::spawn(server::serve(stream, bucket, config));
+    let raw = RawSftpSession::new(client);
+    raw.init().await.unwrap();
+
+    let handle = raw.opendir("/bucket").await.unwrap().handle;
+    let first = raw.readdir(handle.as_str()).await.unwrap().files.len();
+    assert!(first < PAGE, "{first} entries");
+    assert_eq!(fetches.load(Ordering::Relaxed), 1);
+
+    let mut read = first;
+    while read < 3 * PAGE {
+        read += raw.readdir(handle.as_str()).await.unwrap().files.len();
+    }
+    assert_eq!(fetches.load(Ordering::Relaxed), read.div_ceil(PAGE) as u64);
+
+    // the rest of the listing is never fetched
+    raw.close(handle).await.unwrap();
+    assert!(fetches.load(Ordering::Relaxed) < (OBJECTS / PAGE) as u64);
+}
@@ -0,0 +1,246 @@
+//! Sizes and counts of a tree summed by `SftpSession::dir_summary`.
+
+use russh_sftp::{
+    client::{SftpSession, SummaryOptions},
+    protocol::{Attrs, File, FileAttributes, Handle, Name, RequestId, Status, StatusCode, Version},
+    server::Handler,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+enum Node {
+    /// Size and whether the listing reports it
+    File(u64, bool),
+    Dir,
+    /// Directory that cannot be listed
+    Denied,
+    Link(&'static str),
+}
+
+/// Fixed tree with symlinks and a directory without permission
+struct Tree {
+    nodes: BTreeMap<&'static str, Node>,
+    listed: HashSet<String>,
+}
+
+impl Tree {
+    fn new() -> Self {
+        let nodes = BTreeMap::from([
+            ("/data", Node::Dir),
+            ("/data/a.txt", Node::File(10, true)),
+            ("/data/b.bin", Node::File(32, true)),
+            ("/data/sub", Node::Dir),
+            ("/data/sub/c.txt", Node::File(5, true)),
+            ("/data/sub/unlisted", Node::File(100, false)),
+            ("/data/sub/deep", Node::Dir),
+            ("/data/sub/deep/d.txt", Node::File(7, true)),
+            ("/data/private", Node::Denied),
+            ("/data/private/secret", Node::File(1000, true)),
+            ("/data/cache", Node::Dir),
+            ("/data/cache/blob", Node::File(5000, true)),
+            ("/data/to_sub", Node::Link("/data/sub")),
+            ("/data/to_other", Node::Link("/other")),
+            ("/data/to_data", Node::Link("/data")),
+            ("/data/to_a", Node::Link("/data/a.txt")),
+            ("/other", Node::Dir),
+            ("/other/e.txt", Node::File(3, true)),
+        ]);
+
+        Self {
+            nodes,
+            listed: HashSet::new(),
+        }
+    }
+
+    fn resolve<'a>(&self, path: &'a str) -> &'a str {
+        match self.nodes.get(path) {
+            Some(Node::Link(target)) => target,
+            _ => path,
+        }
+    }
+
+    fn attrs(&self, path: &str, listing: bool) -> Result<FileAttributes, StatusCode> {
+        let (permissions, size) = match self.nodes.get(path) {
+            Some(Node::File(size, listed)) => (0o100644, (*listed || !listing).then_some(*size)),
+            Some(Node::Dir) => (0o040755, None),
+            Some(Node::Denied) => (0o040700, None),
+            Some(Node::Link(_)) => (0o120777, None),
+            None => return Err(StatusCode::NoSuchFile),
+        };
+
+        Ok(FileAttributes {
+            size,
+            permissions: Some(permissions),
+            ..FileAttributes::empty()
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Handler for Tree {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn opendir(&mut self, id: RequestId, path: String) -> Result<Handle, Self::Error> {
+        match self.nodes.get(path.as_str()) {
+            Some(Node::Dir) => Ok(Handle { id, handle: path }),
+            Some(Node::Denied) => Err(StatusCode::PermissionDenied),
+            _ => Err(StatusCode::NoSuchFile),
+        }
+    }
+
+    async fn readdir(&mut self, id: RequestId, handle: String) -> Result<Name, Self::Error> {
+        if !self.listed.insert(handle.clone()) {
+            return Err(StatusCode::Eof);
+        }
+
+        let prefix = format!("{handle}/");
+        let mut files = Vec::new();
+        for path in self.nodes.keys() {
+            let Some(name) = path.strip_prefix(&prefix) else {
+                continue;
+            };
+
+            if !name.contains('/') {
+                files.push(File::new(name, self.attrs(path, true)?));
+            }
+        }
+
+        Ok(Name { id, files })
+    }
+
+    async fn close(&mut self, id: RequestId, handle: String) -> Result<Status, Self::Error> {
+        self.listed.remove(&handle);
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+
+    async fn stat(&mut self, id: RequestId, path: String) -> Result<Attrs, Self::Error> {
+        Ok(Attrs {
+            id,
+            attrs: self.attrs(self.resolve(&path), false)?,
+        })
+    }
+
+    async fn lstat(&mut self, id: RequestId, path: String) -> Result<Attrs, Self::Error> {
+        Ok(Attrs {
+            id,
+            attrs: self.attrs(&path, false)?,
+        })
+    }
+
+    async fn realpath(&mut self, id: RequestId, path: String) -> Result<Name, Self::Error> {
+        Ok(Name {
+            id,
+            files: vec![File::dummy(self.resolve(&path))],
+        })
+    }
+}
+
+async fn connect() -> SftpSession {
+    SftpSession::pair(Tree::new()).await.unwrap().0
+}
+
+#[tokio::test]
+async fn whole_tree() {
+    let sftp = connect().await;
+    let summary = sftp
+        .dir_summary("/data", SummaryOptions::new())
+        .await
+        .unwrap();
+
+    // a.txt, b.bin, c.txt, d.txt and blob, unlisted has no size
+    assert_eq!(summary.files, 6);
+    assert_eq!(summary.size, 10 + 32 + 5 + 7 + 5000);
+    assert_eq!(summary.unknown_sizes, 1);
+    assert_eq!(summary.dirs, 4);
+    assert_eq!(summary.symlinks, 4);
+    assert_eq!(summary.others, 0);
+
+    // the contents of private are missing, not the rest
+    let errors = summary.errors.iter().map(|(path, _)| path.as_str());
+    assert_eq!(errors.collect::<Vec<_>>(), ["/data/private"]);
+    assert!(summary.errors[0]
+        .1
+        .to_string()
+        .contains("Permission denied"));
+}
+
+#[tokio::test]
+async fn stat_missing_sizes() {
+    let sftp = connect().await;
+    let options = SummaryOptions::new()
+        .stat_missing(true)
+        .exclude(|entry| entry.file_name() == "cache");
+    let summary = sftp.dir_summary("/data", options).await.unwrap();
+
+    assert_eq!(summary.files, 5);
+    assert_eq!(summary.size, 10 + 32 + 5 + 100 + 7);
+    assert_eq!(summary.unknown_sizes, 0);
+    assert_eq!(summary.dirs, 3);
+}
+
+#[tokio::test]
+async fn max_depth() {
+    let sftp = connect().await;
+    let summary = sftp
+        .dir_summary("/data", SummaryOptions::new().max_depth(Some(0)))
+        .await
+        .unwrap();
+
+    // the subdirectories are counted, not listed
+    assert_eq!(summary.files, 2);
+    assert_eq!(summary.size, 10 + 32);
+    assert_eq!(summary.dirs, 3);
+    assert!(summary.errors.is_empty());
+
+    let summary = sftp
+        .dir_summary("/data", SummaryOptions::new().max_depth(Some(1)))
+        .await
+        .unwrap();
+
+    assert_eq!(summary.files, 5);
+    assert_eq!(summary.dirs, 4);
+    assert_eq!(summary.errors.len(), 1);
+}
+
+#[tokio::test]
+async fn follow_symlinks() {
+    let sftp = connect().await;
+    let options = SummaryOptions::new()
+        .follow_symlinks(true)
+        .max_concurrent(1);
+    let summary = sftp.dir_summary("/data", options).await.unwrap();
+
+    // to_a counts a.txt again and to_other adds e.txt, while sub and data
+    // are listed once whichever path reaches them first
+    assert_eq!(summary.files, 8);
+    assert_eq!(summary.size, 10 + 32 + 5 + 7 + 5000 + 10 + 3);
+    assert_eq!(summary.dirs, 4 + 3);
+    assert_eq!(summary.symlinks, 0);
+}
+
+#[tokio::test]
+async fn unreadable_root_fails() {
+    let sftp = connect().await;
+
+    let err = sftp
+        .dir_summary("/data/private", SummaryOptions::new())
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("Permission denied"));
+
+    assert!(sftp
+        .dir_summary("/missing", SummaryOptions::new())
+        .await
+        .is_err());
+}
@@ -0,0 +1,151 @@
+//! Reads of a file from a server answering at its end with the zero-length data of
+//! `tests/corpus/synthetic/data_empty.hex` instead of an Eof status: taken as the end
+//! of file unless the session is strict, and never requested again.
+
+use bytes::{Bytes, BytesMut};
+use russh_sftp::{
+    client::SftpSession,
+    framing::read_packet,
+    protocol::{Attrs, Data, FileAttributes, Handle, Packet, Status, StatusCode, Version},
+};
+use std::{
+    fs,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, DuplexStream},
+    time,
+};
+
+const FIXTURE: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/corpus/synthetic/data_empty.hex"
+);
+
+const CONTENTS: &[u8] = b"abc";
+
+/// Frame of the fixture, including the length
+fn empty_data() -> Vec<u8> {
+    let text = fs::read_to_string(FIXTURE).unwrap();
+    let hex: String = text
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .flat_map(str::split_whitespace)
+        .collect();
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+/// Serves [`CONTENTS`] without a size, answering the reads at its end with the
+/// frame of the fixture, and counts these reads
+async fn scripted(mut stream: DuplexStream, at_end: Arc<AtomicU32>) {
+    let fixture = empty_data();
+    let mut buf = BytesMut::new();
+
+    while let Ok(mut payload) = read_packet(&mut stream, &mut buf).await {
+        let reply: Packet = match Packet::try_from(&mut payload).unwrap() {
+            Packet::Init(_) => Version::new().into(),
+            Packet::Open(open) => Handle {
+                id: open.id,
+                handle: open.filename,
+            }
+            .into(),
+            Packet::Fstat(fstat) => Attrs {
+                id: fstat.id,
+                attrs: FileAttributes::empty(),
+            }
+            .into(),
+            Packet::Read(read) if read.offset < CONTENTS.len() as u64 => Data {
+                id: read.id,
+                data: CONTENTS[read.offset as usize..].to_vec(),
+            }
+            .into(),
+            Packet::Read(read) => {
+                at_end.fetch_add(1, Ordering::Relaxed);
+                // the bytes of the fixture with the id of the request
+                let mut frame = fixture.clone();
+                frame[5..9].copy_from_slice(&read.id.0.to_be_bytes());
+                if stream.write_all(&frame).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+            request => Status::new(request.get_request_id(), StatusCode::Ok).into(),
+        };
+
+        if stream
+            .write_all(&Bytes::try_from(reply).unwrap())
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+async fn session(strict: bool) -> (SftpSession, Arc<AtomicU32>) {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let at_end = Arc::new(AtomicU32::new(0));
+    tokio::spawn(scripted(server, at_end.clone()));
+
+    let sftp = SftpSession::new(client).await.unwrap();
+    sftp.set_strict_empty_data(strict).await;
+    (sftp, at_end)
+}
+
+#[tokio::test]
+async fn read_to_end_stops_at_empty_data() {
+    let (sftp, at_end) = session(false).await;
+
+    let mut file = sftp.open("/file").await.unwrap();
+    let mut data = Vec::new();
+    let read = time::timeout(Duration::from_secs(1), file.read_to_end(&mut data))
+        .await
+        .expect("the same offset is requested again and again");
+
+    assert_eq!(read.unwrap(), CONTENTS.len());
+    assert_eq!(data, CONTENTS);
+    assert_eq!(at_end.load(Ordering::Relaxed), 1);
+}
+
+#[tokio::test]
+async fn read_ahead_stops_at_empty_data() {
+    let (sftp, _) = session(false).await;
+
+    let mut data = Vec::new();
+    let copied = time::timeout(
+        Duration::from_secs(1),
+        sftp.read_to_writer("/file", &mut data),
+    )
+    .await
+    .expect("the copy did not end");
+
+    assert_eq!(copied.unwrap(), CONTENTS.len() as u64);
+    assert_eq!(data, CONTENTS);
+}
+
+#[tokio::test]
+async fn strict_session_fails_on_empty_data() {
+    let (sftp, at_end) = session(true).await;
+
+    let mut file = sftp.open("/file").await.unwrap();
+    let mut data = Vec::new();
+    let err = file.read_to_end(&mut data).await.unwrap_err();
+    assert!(
+        err.to_string().contains("without any byte at offset 3"),
+        "{err}"
+    );
+    assert_eq!(data, CONTENTS);
+    assert_eq!(at_end.load(Ordering::Relaxed), 1);
+
+    let mut data = Vec::new();
+    let err = sftp.read_to_writer("/file", &mut data).await.unwrap_err();
+    assert!(err.to_string().contains("without any byte"), "{err}");
+}
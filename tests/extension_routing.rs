@@ -0,0 +1,151 @@
+//! Routing of fsync@openssh.com and hardlink@openssh.com to their Handler methods.
+
+mod common;
+
+use common::Memory;
+use russh_sftp::{
+    client::{error::Error, RawSftpSession},
+    extensions,
+    protocol::{Packet, RequestId, Status, StatusCode, Version},
+    server::{self, Handler},
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// Records the extension requests it receives
+#[derive(Clone, Default)]
+struct Linker {
+    calls: Arc<Mutex<Vec<String>>>,
+    advertised: bool,
+}
+
+impl Linker {
+    fn record(&self, id: RequestId, call: String) -> Result<Status, StatusCode> {
+        self.calls.lock().unwrap().push(call);
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+}
+
+#[async_trait::async_trait]
+impl Handler for Linker {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    fn supported_extensions(&self) -> &'static [&'static str] {
+        match self.advertised {
+            true => &[extensions::FSYNC, extensions::HARDLINK],
+            false => &[],
+        }
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn fsync(&mut self, id: RequestId, handle: String) -> Result<Status, Self::Error> {
+        self.record(id, format!("fsync {handle}"))
+    }
+
+    async fn hardlink(
+        &mut self,
+        id: RequestId,
+        oldpath: String,
+        newpath: String,
+    ) -> Result<Status, Self::Error> {
+        self.record(id, format!("hardlink {oldpath} {newpath}"))
+    }
+
+    async fn extended(
+        &mut self,
+        id: RequestId,
+        request: String,
+        data: Vec<u8>,
+    ) -> Result<Packet, Self::Error> {
+        self.record(id, format!("extended {request} {data:?}"))
+            .map(Packet::Status)
+    }
+}
+
+async fn session<H: Handler + Send + 'static>(handler: H) -> (RawSftpSession, Version) {
+    let (client, stream) = tokio::io::duplex(64 * 1024);
+    server::run(stream, handler).await;
+    let raw = RawSftpSession::new(client);
+    let version = raw.init().await.unwrap();
+    (raw, version)
+}
+
+#[tokio::test]
+async fn advertised_by_supported_extensions() {
+    let handler = Linker {
+        advertised: true,
+        ..Default::default()
+    };
+    let (_raw, version) = session(handler).await;
+    assert!(version.extensions.contains_key(extensions::FSYNC));
+    assert!(version.extensions.contains_key(extensions::HARDLINK));
+
+    let (_raw, version) = session(Linker::default()).await;
+    assert!(!version.extensions.contains_key(extensions::FSYNC));
+    assert!(!version.extensions.contains_key(extensions::HARDLINK));
+}
+
+#[tokio::test]
+async fn routed_to_dedicated_methods() {
+    let handler = Linker::default();
+    let (raw, _version) = session(handler.clone()).await;
+
+    raw.fsync("handle").await.unwrap();
+    raw.hardlink("/old", "/new").await.unwrap();
+    raw.extended("other@example.com", vec![1, 2]).await.unwrap();
+
+    assert_eq!(
+        *handler.calls.lock().unwrap(),
+        [
+            "fsync handle",
+            "hardlink /old /new",
+            "extended other@example.com [1, 2]",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn malformed_payload_is_a_bad_message() {
+    let handler = Linker::default();
+    let (raw, _version) = session(handler.clone()).await;
+
+    for request in [extensions::FSYNC, extensions::HARDLINK] {
+        match raw.extended(request, vec![0, 0]).await {
+            Ok(Packet::Status(status)) => {
+                assert_eq!(status.status_code, StatusCode::BadMessage, "{request}")
+            }
+            result => panic!("{request}: {result:?}"),
+        }
+    }
+    assert!(handler.calls.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn unsupported_by_default() {
+    let (raw, _version) = session(Memory::default()).await;
+
+    for result in [
+        raw.fsync("handle").await,
+        raw.hardlink("/old", "/new").await,
+    ] {
+        match result {
+            Err(Error::Status { status, .. }) => {
+                assert_eq!(status.status_code, StatusCode::OpUnsupported)
+            }
+            result => panic!("{result:?}"),
+        }
+    }
+}
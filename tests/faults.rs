@@ -0,0 +1,183 @@
+//! Faults injected by a `FaultLayer` in front of the in-memory server.
+
+mod common;
+
+use common::Memory;
+use russh_sftp::{
+    client::{error::Error, RawSftpSession, SftpSession},
+    protocol::{FileAttributes, OpenFlags, StatusCode},
+    server::{
+        testkit::{Fault, FaultLayer, Rule},
+        Config,
+    },
+};
+use std::{io, time::Duration};
+use tokio::{io::AsyncReadExt, time::Instant};
+
+async fn connect(layer: &FaultLayer) -> RawSftpSession {
+    let memory = Memory::default();
+    let data = b"hello world".to_vec();
+    memory.files.lock().unwrap().insert("/a".to_owned(), data);
+
+    let session = RawSftpSession::new(layer.serve(memory, Config::default()).await);
+    session.set_timeout(1).await;
+    session.init().await.unwrap();
+    session
+}
+
+async fn open(session: &RawSftpSession, path: &str) -> String {
+    let attrs = FileAttributes::empty();
+    let flags = OpenFlags::READ | OpenFlags::WRITE;
+    session.open(path, flags, attrs).await.unwrap().handle
+}
+
+fn status_code<T>(result: Result<T, Error>) -> StatusCode {
+    match result {
+        Err(Error::Status { status, .. }) => status.status_code,
+        Err(err) => panic!("not a status: {err}"),
+        Ok(_) => panic!("not an error"),
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn delayed_read_times_out_once() {
+    let layer = FaultLayer::new();
+    let session = connect(&layer).await;
+    let handle = open(&session, "/a").await;
+
+    layer.push_rule(Rule::new(Fault::Delay(Duration::from_secs(2))).packet("SSH_FXP_READ"));
+    let started = Instant::now();
+    let result = session.read(handle.as_str(), 0, 5).await;
+    assert!(matches!(result, Err(Error::Timeout)), "{result:?}");
+    assert_eq!(started.elapsed(), Duration::from_secs(1));
+
+    // the rule was applied once
+    assert_eq!(layer.rules(), 0);
+    let data = session.read(handle.as_str(), 0, 5).await.unwrap();
+    assert_eq!(data.data, b"hello");
+    assert_eq!(layer.applied(), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn delay_does_not_hold_back_other_replies() {
+    let layer = FaultLayer::new();
+    let session = connect(&layer).await;
+    let handle = open(&session, "/a").await;
+
+    let delay = Duration::from_millis(500);
+    layer.push_rule(Rule::new(Fault::Delay(delay)).packet("SSH_FXP_READ"));
+    let started = Instant::now();
+    let (read, stat) = tokio::join!(
+        async {
+            session.read(handle.as_str(), 0, 5).await.unwrap();
+            started.elapsed()
+        },
+        async {
+            session.fstat(handle.as_str()).await.unwrap();
+            started.elapsed()
+        },
+    );
+
+    assert_eq!(read, delay);
+    assert_eq!(stat, Duration::ZERO);
+}
+
+#[tokio::test(start_paused = true)]
+async fn dropped_reply_by_id() {
+    let layer = FaultLayer::new();
+    let session = connect(&layer).await;
+
+    // the opened handle is requested with the next id
+    let handle = open(&session, "/a").await;
+    layer.push_rule(Rule::new(Fault::DropReply).id(2));
+    assert!(matches!(
+        session.fstat(handle.as_str()).await,
+        Err(Error::Timeout)
+    ));
+    assert!(session.fstat(handle.as_str()).await.is_ok());
+}
+
+#[tokio::test]
+async fn persistent_status_on_handle() {
+    let layer = FaultLayer::new();
+    let session = connect(&layer).await;
+    let failing = open(&session, "/a").await;
+    session
+        .open(
+            "/b",
+            OpenFlags::CREATE | OpenFlags::WRITE,
+            FileAttributes::empty(),
+        )
+        .await
+        .unwrap();
+
+    let rule = Rule::new(Fault::ReplaceStatus(StatusCode::Failure))
+        .packet("SSH_FXP_WRITE")
+        .target(failing.as_str())
+        .persistent();
+    layer.push_rule(rule);
+
+    for offset in [0, 5] {
+        let result = session
+            .write(failing.as_str(), offset, b"data".to_vec())
+            .await;
+        assert_eq!(status_code(result), StatusCode::Failure);
+    }
+    session.write("/b", 0, b"data".to_vec()).await.unwrap();
+
+    assert_eq!(layer.rules(), 1);
+    assert_eq!(layer.applied(), 2);
+    layer.clear();
+    session
+        .write(failing.as_str(), 0, b"data".to_vec())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn rules_applied_in_order() {
+    let layer = FaultLayer::new();
+    let session = connect(&layer).await;
+    let handle = open(&session, "/a").await;
+
+    layer.push_rule(Rule::new(Fault::TruncateData(8)).packet("SSH_FXP_READ"));
+    layer.push_rule(Rule::new(Fault::TruncateData(3)).packet("SSH_FXP_READ"));
+    let data = session.read(handle.as_str(), 0, 11).await.unwrap();
+    assert_eq!(data.data, b"hel");
+
+    // the status replaces the data it follows
+    layer.push_rule(Rule::new(Fault::TruncateData(3)));
+    layer.push_rule(Rule::new(Fault::ReplaceStatus(StatusCode::Eof)));
+    let result = session.read(handle.as_str(), 0, 11).await;
+    assert_eq!(status_code(result), StatusCode::Eof);
+}
+
+async fn read_truncated(strict: bool) -> io::Result<Vec<u8>> {
+    let layer = FaultLayer::new();
+    let memory = Memory::default();
+    memory
+        .files
+        .lock()
+        .unwrap()
+        .insert("/a".to_owned(), vec![7; 10]);
+    let stream = layer.serve(memory, Config::default()).await;
+    let sftp = SftpSession::new(stream).await.unwrap();
+    sftp.set_strict_empty_data(strict).await;
+
+    layer.push_rule(Rule::new(Fault::TruncateData(0)).packet("SSH_FXP_READ"));
+    let mut file = sftp.open("/a").await.unwrap();
+    let mut data = Vec::new();
+    file.read_to_end(&mut data).await?;
+    Ok(data)
+}
+
+#[tokio::test]
+async fn empty_data_as_end_of_file() {
+    assert!(read_truncated(false).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn strict_empty_data_rejected() {
+    let err = read_truncated(true).await.unwrap_err();
+    assert!(err.to_string().contains("without any byte"), "{err}");
+}
@@ -0,0 +1,109 @@
+//! Files closed on drop on any thread, or handed off with their handle.
+
+use russh_sftp::{
+    client::{fs::File, SftpSession},
+    protocol::{FileAttributes, Handle, OpenFlags, RequestId, Status, StatusCode, Version},
+    server::Handler,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time;
+
+/// Records the handles it closes, the handles are the paths
+#[derive(Clone, Default)]
+struct Closes {
+    closed: Arc<Mutex<Vec<String>>>,
+}
+
+impl Closes {
+    /// Waits for the handle to be closed
+    async fn wait(&self, handle: &str) {
+        let closed = async {
+            while !self.closed.lock().unwrap().iter().any(|h| h == handle) {
+                time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        time::timeout(Duration::from_secs(5), closed).await.unwrap();
+    }
+}
+
+#[async_trait::async_trait]
+impl Handler for Closes {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn open(
+        &mut self,
+        id: RequestId,
+        filename: String,
+        _pflags: OpenFlags,
+        _attrs: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        Ok(Handle {
+            id,
+            handle: filename,
+        })
+    }
+
+    async fn close(&mut self, id: RequestId, handle: String) -> Result<Status, Self::Error> {
+        self.closed.lock().unwrap().push(handle);
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+}
+
+#[test]
+fn file_is_send_and_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<File>();
+}
+
+#[tokio::test]
+async fn closed_when_dropped_on_a_plain_thread() {
+    let handler = Closes::default();
+    let (sftp, _server) = SftpSession::pair(handler.clone()).await.unwrap();
+
+    let file = sftp.open("/dropped").await.unwrap();
+    std::thread::spawn(move || drop(file)).join().unwrap();
+    handler.wait("/dropped").await;
+}
+
+#[tokio::test]
+async fn closed_when_dropped_by_a_task() {
+    let handler = Closes::default();
+    let (sftp, _server) = SftpSession::pair(handler.clone()).await.unwrap();
+
+    let file = sftp.open("/dropped").await.unwrap();
+    tokio::spawn(async move { drop(file) }).await.unwrap();
+    handler.wait("/dropped").await;
+}
+
+#[tokio::test]
+async fn detached_handle_left_open() {
+    let handler = Closes::default();
+    let (sftp, _server) = SftpSession::pair(handler.clone()).await.unwrap();
+
+    let (handle, session) = sftp.open("/detached").await.unwrap().detach();
+    assert_eq!(handle, "/detached");
+
+    // a close sent on detach would be handled before this request
+    sftp.metadata("/").await.ok();
+    assert!(handler.closed.lock().unwrap().is_empty());
+
+    // wrapped again, it is closed on drop like any file
+    drop(File::from_raw_parts(session, handle, &[]));
+    handler.wait("/detached").await;
+}
@@ -0,0 +1,165 @@
+//! Times kept with nanoseconds and after 2106, sent as the 32-bit seconds of version 3.
+
+use bytes::Bytes;
+use russh_sftp::{
+    client::{error::Error, SftpSession},
+    de,
+    protocol::{FileAttributes, FileTime, RequestId, Status, StatusCode, Version},
+    ser,
+    server::Handler,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// 2200-01-01, after the last second of version 3
+const AFTER_2106: u64 = 7_258_118_400;
+
+/// Records the attributes of SSH_FXP_SETSTAT
+#[derive(Default)]
+struct Recorder(Arc<Mutex<Vec<FileAttributes>>>);
+
+#[async_trait::async_trait]
+impl Handler for Recorder {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn setstat(
+        &mut self,
+        id: RequestId,
+        _path: String,
+        attrs: FileAttributes,
+    ) -> Result<Status, Self::Error> {
+        self.0.lock().unwrap().push(attrs);
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+}
+
+fn round_trip(attrs: &FileAttributes) -> FileAttributes {
+    let mut bytes = ser::to_bytes(attrs).unwrap();
+    de::from_bytes(&mut bytes).unwrap()
+}
+
+fn times(atime: FileTime, mtime: FileTime) -> FileAttributes {
+    FileAttributes {
+        atime: Some(atime),
+        mtime: Some(mtime),
+        ..FileAttributes::empty()
+    }
+}
+
+#[test]
+fn nanoseconds_dropped_on_the_wire() {
+    let attrs = times(
+        FileTime::new(1_700_000_000, 250_000_000),
+        FileTime::from(1_700_000_001),
+    );
+    let received = round_trip(&attrs);
+    assert_eq!(received.atime, Some(FileTime::from_secs(1_700_000_000)));
+    assert_eq!(received.mtime, attrs.mtime);
+}
+
+#[test]
+fn modified_keeps_nanoseconds() {
+    let time = UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789);
+    let attrs = FileAttributes::with_times(Some(time), Some(time)).unwrap();
+    assert_eq!(attrs.modified().unwrap(), time);
+    assert_eq!(attrs.accessed().unwrap(), time);
+    assert_eq!(attrs.mtime.unwrap().subsec_nanos(), 123_456_789);
+
+    // an earlier time within the same second still compares lower
+    let earlier = FileAttributes::with_times(None, Some(time - Duration::from_nanos(1))).unwrap();
+    assert!(earlier.modified().unwrap() < attrs.modified().unwrap());
+}
+
+#[test]
+fn saturated_after_2106() {
+    let late = FileTime::from_secs(AFTER_2106);
+    let attrs = times(FileTime::from(7), late);
+    assert_eq!(attrs.time_beyond_v3(), Some(late));
+    assert_eq!(late.to_v3(), None);
+
+    let bytes = ser::to_bytes(&attrs).unwrap();
+    assert_eq!(
+        &bytes[4..],
+        [7u32.to_be_bytes(), u32::MAX.to_be_bytes()].concat()
+    );
+
+    let received = round_trip(&attrs);
+    assert_eq!(received.mtime, Some(FileTime::from(u32::MAX)));
+    assert_eq!(received.time_beyond_v3(), None);
+}
+
+#[test]
+fn system_time_conversions() {
+    let time = UNIX_EPOCH + Duration::from_secs(AFTER_2106);
+    let attrs = FileAttributes::with_times(Some(time), None).unwrap();
+    assert_eq!(attrs.atime, Some(FileTime::from_secs(AFTER_2106)));
+
+    let before = UNIX_EPOCH - Duration::from_secs(1);
+    assert!(FileAttributes::with_times(Some(before), None).is_err());
+    assert!(FileTime::try_from(SystemTime::now()).is_ok());
+    assert_eq!(
+        FileTime::new(1, 2_500_000_000),
+        FileTime::new(3, 500_000_000)
+    );
+}
+
+#[test]
+#[allow(deprecated)]
+fn u32_shims() {
+    let mut attrs = FileAttributes::empty();
+    attrs.set_atime_u32(Some(5));
+    attrs.set_mtime_u32(None);
+    assert_eq!((attrs.atime_u32(), attrs.mtime_u32()), (Some(5), None));
+
+    attrs.mtime = Some(FileTime::from_secs(AFTER_2106));
+    assert_eq!(attrs.mtime_u32(), Some(u32::MAX));
+}
+
+#[tokio::test]
+async fn strict_times_rejected_before_sending() {
+    let handler = Recorder::default();
+    let seen = handler.0.clone();
+    let (sftp, _server) = SftpSession::pair(handler).await.unwrap();
+    let late = FileTime::from_secs(AFTER_2106);
+    let attrs = times(FileTime::from(7), late);
+
+    sftp.set_metadata("/a", attrs.clone()).await.unwrap();
+    assert_eq!(
+        seen.lock().unwrap()[0].mtime,
+        Some(FileTime::from(u32::MAX))
+    );
+
+    sftp.set_strict_times(true).await;
+    let result = sftp.set_metadata("/a", attrs).await;
+    assert!(
+        matches!(result, Err(Error::TimeOverflow(time)) if time == late),
+        "{result:?}"
+    );
+    assert_eq!(seen.lock().unwrap().len(), 1);
+
+    // the times within range are still sent
+    let attrs = times(FileTime::from(7), FileTime::from(u32::MAX));
+    sftp.set_metadata("/a", attrs).await.unwrap();
+    assert_eq!(seen.lock().unwrap().len(), 2);
+}
+
+#[test]
+fn serialized_without_times() {
+    let bytes: Bytes = ser::to_bytes(&FileAttributes::empty()).unwrap();
+    assert_eq!(&bytes[..], 0u32.to_be_bytes());
+}
@@ -0,0 +1,192 @@
+//! Open flags and attribute flags not defined by version 3, rejected by
+//! strict servers and kept for inspection otherwise.
+
+mod common;
+
+use bytes::{BufMut, Bytes, BytesMut};
+use common::raw_stream;
+use russh_sftp::{
+    framing::{read_packet, write_packet},
+    protocol::{
+        FileAttr, FileAttributes, Handle, OpenFlags, Packet, RequestId, Status, StatusCode, Version,
+    },
+    server::{Config, Handler},
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::io::DuplexStream;
+
+const SSH_FXP_OPEN: u8 = 3;
+const SSH_FXP_SETSTAT: u8 = 9;
+const SSH_FXP_ATTRS: u8 = 105;
+
+/// Access disposition bit of version 5, unknown to version 3
+const DISPOSITION: u32 = 0x40;
+/// Creation time of version 4, announcing a field unknown to version 3
+const CREATETIME: u32 = 0x10;
+
+/// Records the unknown bits seen by the handler
+#[derive(Default)]
+struct Recorder(Arc<Mutex<Vec<u32>>>);
+
+#[async_trait::async_trait]
+impl Handler for Recorder {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn open(
+        &mut self,
+        id: RequestId,
+        filename: String,
+        pflags: OpenFlags,
+        _attrs: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        self.0.lock().unwrap().push(pflags.unknown_bits());
+        Ok(Handle {
+            id,
+            handle: filename,
+        })
+    }
+
+    async fn setstat(
+        &mut self,
+        id: RequestId,
+        _path: String,
+        attrs: FileAttributes,
+    ) -> Result<Status, Self::Error> {
+        assert_eq!(attrs.size, Some(7));
+        self.0.lock().unwrap().push(attrs.unknown_flags);
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+}
+
+fn put_string(bytes: &mut BytesMut, string: &str) {
+    bytes.put_u32(string.len() as u32);
+    bytes.put_slice(string.as_bytes());
+}
+
+fn open(pflags: u32) -> Bytes {
+    let mut bytes = BytesMut::new();
+    bytes.put_u8(SSH_FXP_OPEN);
+    bytes.put_u32(1);
+    put_string(&mut bytes, "/a.txt");
+    bytes.put_u32(pflags);
+    bytes.put_u32(0);
+    bytes.freeze()
+}
+
+fn setstat(flags: u32) -> Bytes {
+    let mut bytes = BytesMut::new();
+    bytes.put_u8(SSH_FXP_SETSTAT);
+    bytes.put_u32(2);
+    put_string(&mut bytes, "/a.txt");
+    bytes.put_u32(FileAttr::SIZE.bits() | flags);
+    bytes.put_u64(7);
+    bytes.freeze()
+}
+
+async fn connect(config: Config) -> (DuplexStream, Arc<Mutex<Vec<u32>>>) {
+    let handler = Recorder::default();
+    let seen = handler.0.clone();
+    let (client, _served) = raw_stream(handler, config).await;
+    (client, seen)
+}
+
+async fn request(client: &mut DuplexStream, payload: &[u8]) -> Packet {
+    write_packet(client, payload).await.unwrap();
+    let mut reply = read_packet(client, &mut BytesMut::new()).await.unwrap();
+    Packet::try_from(&mut reply).unwrap()
+}
+
+fn into_status(packet: Packet) -> Status {
+    match packet {
+        Packet::Status(status) => status,
+        packet => panic!("{} is not a status", packet.name()),
+    }
+}
+
+#[tokio::test]
+async fn strict_rejects_open_flags() {
+    let config = Config {
+        strict_flags: true,
+        ..Default::default()
+    };
+    let (mut client, seen) = connect(config).await;
+
+    let pflags = OpenFlags::READ.bits() | DISPOSITION;
+    let status = into_status(request(&mut client, &open(pflags)).await);
+    assert_eq!(
+        (status.id, status.status_code),
+        (RequestId(1), StatusCode::BadMessage)
+    );
+    assert_eq!(status.error_message, "unknown open flags 0x00000040");
+
+    // the known flags are still accepted
+    let reply = request(&mut client, &open(OpenFlags::READ.bits())).await;
+    assert!(matches!(reply, Packet::Handle(_)));
+    assert_eq!(*seen.lock().unwrap(), [0]);
+}
+
+#[tokio::test]
+async fn strict_rejects_attribute_flags() {
+    let config = Config {
+        strict_flags: true,
+        ..Default::default()
+    };
+    let (mut client, seen) = connect(config).await;
+
+    let status = into_status(request(&mut client, &setstat(CREATETIME)).await);
+    assert_eq!(
+        (status.id, status.status_code),
+        (RequestId(2), StatusCode::BadMessage)
+    );
+    assert_eq!(status.error_message, "unknown attribute flags 0x00000010");
+    assert!(seen.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn lenient_keeps_bits() {
+    let (mut client, seen) = connect(Config::default()).await;
+
+    let pflags = OpenFlags::READ.bits() | DISPOSITION;
+    let reply = request(&mut client, &open(pflags)).await;
+    assert!(matches!(reply, Packet::Handle(_)));
+
+    let status = into_status(request(&mut client, &setstat(CREATETIME)).await);
+    assert_eq!(status.status_code, StatusCode::Ok);
+
+    assert_eq!(*seen.lock().unwrap(), [DISPOSITION, CREATETIME]);
+}
+
+#[test]
+fn reply_attrs_unknown_flags() {
+    let mut bytes = BytesMut::new();
+    bytes.put_u8(SSH_FXP_ATTRS);
+    bytes.put_u32(3);
+    bytes.put_u32(FileAttr::SIZE.bits() | CREATETIME);
+    bytes.put_u64(7);
+
+    let Packet::Attrs(attrs) = Packet::try_from(&mut bytes.freeze()).unwrap() else {
+        panic!("not attrs");
+    };
+    assert_eq!(attrs.attrs.size, Some(7));
+    assert_eq!(attrs.attrs.unknown_flags, CREATETIME);
+    assert_eq!(attrs.attrs.flags(), FileAttr::SIZE);
+
+    // the unknown bits are not sent back, their fields are not known
+    let frame = Bytes::try_from(Packet::Attrs(attrs)).unwrap();
+    assert_eq!(&frame[9..13], FileAttr::SIZE.bits().to_be_bytes());
+}
@@ -0,0 +1,121 @@
+//! Packets split off chunks of any size by `FrameDecoder`, without tokio.
+
+use bytes::Bytes;
+use russh_sftp::{
+    framing::{encode_frame, FrameDecoder},
+    protocol::{Data, Packet, RequestId, Stat, Status, StatusCode},
+    Error,
+};
+
+fn frames() -> Vec<Bytes> {
+    vec![
+        encode_frame(Packet::Status(Status::new(RequestId(1), StatusCode::Ok))).unwrap(),
+        encode_frame(
+            Stat {
+                id: RequestId(2),
+                path: "/a/b".to_owned(),
+            }
+            .into(),
+        )
+        .unwrap(),
+        encode_frame(
+            Data {
+                id: RequestId(3),
+                data: vec![7; 1000],
+            }
+            .into(),
+        )
+        .unwrap(),
+    ]
+}
+
+/// Pushes the frames in chunks of `size` bytes and returns the decoded payloads
+fn decode_chunked(decoder: &mut FrameDecoder, size: usize) -> Vec<Bytes> {
+    let stream = frames().concat();
+    let mut decoded = Vec::new();
+
+    for chunk in stream.chunks(size) {
+        decoder.push(chunk);
+        while let Some(frame) = decoder.next_frame().unwrap() {
+            decoded.push(frame);
+        }
+    }
+
+    decoded
+}
+
+fn payloads() -> Vec<Bytes> {
+    frames().into_iter().map(|frame| frame.slice(4..)).collect()
+}
+
+#[test]
+fn one_byte_at_a_time() {
+    let mut decoder = FrameDecoder::new();
+    assert_eq!(decode_chunked(&mut decoder, 1), payloads());
+    assert_eq!(decoder.buffered(), 0);
+}
+
+#[test]
+fn boundaries_straddling_chunks() {
+    // every chunking, from lengths split in two to chunks holding several packets
+    let total = frames().concat().len();
+    for size in [2, 3, 5, 7, 13, 64, 999, 1001, total] {
+        let mut decoder = FrameDecoder::with_max_len(4096);
+        assert_eq!(decode_chunked(&mut decoder, size), payloads(), "{size}");
+        assert_eq!(decoder.buffered(), 0);
+    }
+}
+
+#[test]
+fn merged_frames() {
+    let mut decoder = FrameDecoder::new();
+    decoder.push(&frames().concat());
+    decoder.push(&frames()[0][..6]);
+
+    for payload in payloads() {
+        let mut frame = decoder.next_frame().unwrap().unwrap();
+        assert_eq!(frame, payload);
+        Packet::try_from(&mut frame).unwrap();
+    }
+
+    // the start of the next frame stays buffered
+    assert!(decoder.next_frame().unwrap().is_none());
+    assert_eq!(decoder.buffered(), 6);
+}
+
+#[test]
+fn length_out_of_bounds() {
+    let mut decoder = FrameDecoder::with_max_len(512);
+    let data = frames().remove(2);
+
+    // rejected once the length is known, before the packet is received
+    decoder.push(&data[..3]);
+    assert!(decoder.next_frame().unwrap().is_none());
+    decoder.push(&data[3..4]);
+    assert!(matches!(decoder.next_frame(), Err(Error::Framing(_))));
+
+    // the decoder stays failed
+    decoder.push(&data[4..]);
+    assert!(matches!(decoder.next_frame(), Err(Error::Framing(_))));
+}
+
+#[test]
+fn unknown_type() {
+    let mut decoder = FrameDecoder::with_max_len(512);
+    decoder.push(b"\0\0\0\x20");
+    assert!(decoder.next_frame().unwrap().is_none());
+
+    decoder.push(b"t");
+    let Err(Error::Framing(message)) = decoder.next_frame() else {
+        panic!("unknown type accepted");
+    };
+    assert!(message.contains("type"), "{message}");
+}
+
+#[test]
+fn unchecked_accepts_any_frame() {
+    let mut decoder = FrameDecoder::new();
+    decoder.push(b"\0\0\0\x03abc\0\0\0\0");
+    assert_eq!(decoder.next_frame().unwrap().unwrap(), &b"abc"[..]);
+    assert_eq!(decoder.next_frame().unwrap().unwrap(), &b""[..]);
+}
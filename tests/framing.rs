@@ -0,0 +1,148 @@
+//! Streams that lost the boundaries of the packets end at once,
+//! instead of every following byte being parsed as a packet.
+
+mod common;
+
+use bytes::{Bytes, BytesMut};
+use common::{raw_stream, Memory};
+use russh_sftp::{
+    client::{error::Error, CloseReason, SftpSession},
+    framing::{read_packet, write_packet},
+    protocol::{Packet, RequestId, Stat, StatusCode, Version},
+    server::{Config, EndReason},
+};
+use std::time::Duration;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, DuplexStream},
+    time::timeout,
+};
+
+const PROMPTLY: Duration = Duration::from_secs(1);
+
+fn frame(packet: Packet) -> Bytes {
+    Bytes::try_from(packet).unwrap()
+}
+
+/// Server side played by the test: completes the SSH_FXP_INIT exchange and
+/// returns the stream once the first request was received
+async fn connect() -> (SftpSession, DuplexStream) {
+    let (client, mut server) = tokio::io::duplex(64 * 1024);
+    let init = tokio::spawn(async move {
+        let mut buf = BytesMut::new();
+        read_packet(&mut server, &mut buf).await.unwrap();
+        server
+            .write_all(&frame(Version::new().into()))
+            .await
+            .unwrap();
+        server
+    });
+
+    let sftp = SftpSession::new(client).await.unwrap();
+    (sftp, init.await.unwrap())
+}
+
+async fn corrupted_reply(garbage: &[u8]) {
+    let (sftp, mut server) = connect().await;
+    let closed = tokio::spawn(sftp.closed());
+    let request = tokio::spawn(async move { sftp.metadata("/a").await });
+
+    read_packet(&mut server, &mut BytesMut::new())
+        .await
+        .unwrap();
+    server.write_all(garbage).await.unwrap();
+
+    // the pending request fails without waiting for its timeout
+    let Err(Error::Status { status, .. }) = timeout(PROMPTLY, request).await.unwrap().unwrap()
+    else {
+        panic!("request answered by a corrupted stream");
+    };
+    assert_eq!(status.status_code, StatusCode::ConnectionLost);
+
+    let reason = timeout(PROMPTLY, closed).await.unwrap().unwrap();
+    assert!(
+        matches!(reason, CloseReason::Failed(Error::Framing(_))),
+        "{reason:?}"
+    );
+
+    // the client closed its side of the stream
+    let mut rest = Vec::new();
+    timeout(PROMPTLY, server.read_to_end(&mut rest))
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn client_length_out_of_bounds() {
+    corrupted_reply(b"\xde\xad\xbe\xef garbage that follows").await;
+}
+
+#[tokio::test]
+async fn client_length_too_short() {
+    corrupted_reply(&[0, 0, 0, 1, 101]).await;
+}
+
+#[tokio::test]
+async fn client_unknown_type() {
+    // a plausible length followed by text, as after a misjudged boundary
+    corrupted_reply(b"\0\0\0\x20text that is not a packet at all").await;
+}
+
+async fn corrupted_request(config: Config, garbage: &[u8]) -> EndReason {
+    let memory = Memory::default();
+    let (mut client, served) = raw_stream(memory.clone(), config).await;
+    client.write_all(garbage).await.unwrap();
+
+    let result = timeout(PROMPTLY, served).await.unwrap().unwrap();
+    assert!(matches!(result, Err(russh_sftp::Error::Framing(_))));
+
+    // the session is closed rather than replying to the noise
+    let mut rest = Vec::new();
+    client.read_to_end(&mut rest).await.unwrap();
+    assert!(rest.is_empty());
+
+    let reason = memory.ended.lock().unwrap().take();
+    reason.unwrap()
+}
+
+#[tokio::test]
+async fn server_length_out_of_bounds() {
+    let reason = corrupted_request(Config::default(), b"GET / HTTP/1.1\r\n\r\n").await;
+    assert!(matches!(reason, EndReason::Framing(_)), "{reason:?}");
+}
+
+#[tokio::test]
+async fn server_unknown_type() {
+    let reason = corrupted_request(Config::default(), b"\0\0\0\x08\x63abcdefg").await;
+    assert!(matches!(reason, EndReason::Framing(_)), "{reason:?}");
+}
+
+#[tokio::test]
+async fn server_configured_bound() {
+    let stat = frame(
+        Stat {
+            id: RequestId(1),
+            path: "/".repeat(100),
+        }
+        .into(),
+    );
+
+    let config = Config {
+        max_request_packet_len: Some(64),
+        ..Default::default()
+    };
+    let reason = corrupted_request(config, &stat).await;
+    let EndReason::Framing(message) = reason else {
+        panic!("{reason:?}");
+    };
+    assert!(message.contains("5..=64"), "{message}");
+}
+
+#[tokio::test]
+async fn forwarding_unchecked() {
+    // packets of any type can still be read and written by proxies
+    let (mut a, mut b) = tokio::io::duplex(1024);
+    write_packet(&mut a, b"\x63abcd").await.unwrap();
+    let packet = read_packet(&mut b, &mut BytesMut::new()).await.unwrap();
+    assert_eq!(packet.as_ref(), b"\x63abcd");
+}
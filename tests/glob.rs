@@ -0,0 +1,239 @@
+//! Remote paths matched by `SftpSession::glob`.
+
+use futures::StreamExt;
+use russh_sftp::{
+    client::{error::Error, SftpSession},
+    protocol::{Attrs, File, FileAttributes, Handle, Name, RequestId, Status, StatusCode, Version},
+    server::Handler,
+};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+/// Working directory of the relative paths
+const HOME: &str = "/home";
+
+/// Fixed tree recording the directories listed
+struct Tree {
+    /// Paths and whether they are directories
+    nodes: BTreeMap<&'static str, bool>,
+    links: HashMap<&'static str, &'static str>,
+    listed: Arc<Mutex<Vec<String>>>,
+    reading: HashSet<String>,
+}
+
+impl Tree {
+    fn new(listed: Arc<Mutex<Vec<String>>>) -> Self {
+        let nodes = BTreeMap::from([
+            ("/home", true),
+            ("/home/notes.txt", false),
+            ("/logs", true),
+            ("/logs/app.log", false),
+            ("/logs/app.log.1.gz", false),
+            ("/logs/db.log", false),
+            ("/logs/2024", true),
+            ("/logs/2024/jan.gz", false),
+            ("/logs/2024/old", true),
+            ("/logs/2024/old/dec.gz", false),
+            ("/other", true),
+            ("/other/x.gz", false),
+            ("/srv", true),
+            ("/srv/a1", true),
+            ("/srv/a1/conf", false),
+            ("/srv/a2", true),
+            ("/srv/a2/conf", false),
+            ("/srv/b1", true),
+            ("/srv/b1/conf", false),
+        ]);
+
+        Self {
+            nodes,
+            links: HashMap::from([("/logs/elsewhere", "/other")]),
+            listed,
+            reading: HashSet::new(),
+        }
+    }
+
+    fn absolute(path: &str) -> String {
+        match path {
+            "." => HOME.to_owned(),
+            path if path.starts_with('/') => path.to_owned(),
+            path => format!("{HOME}/{path}"),
+        }
+    }
+
+    fn attrs(&self, path: &str) -> Result<FileAttributes, StatusCode> {
+        let permissions = match self.nodes.get(path) {
+            Some(true) => 0o040755,
+            Some(false) => 0o100644,
+            None if self.links.contains_key(path) => 0o120777,
+            None => return Err(StatusCode::NoSuchFile),
+        };
+
+        Ok(FileAttributes {
+            permissions: Some(permissions),
+            ..FileAttributes::empty()
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Handler for Tree {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn opendir(&mut self, id: RequestId, path: String) -> Result<Handle, Self::Error> {
+        let path = Self::absolute(&path);
+        match self.nodes.get(path.as_str()) {
+            Some(true) => {
+                self.listed.lock().unwrap().push(path.to_owned());
+                Ok(Handle { id, handle: path })
+            }
+            _ => Err(StatusCode::NoSuchFile),
+        }
+    }
+
+    async fn readdir(&mut self, id: RequestId, handle: String) -> Result<Name, Self::Error> {
+        if !self.reading.insert(handle.clone()) {
+            return Err(StatusCode::Eof);
+        }
+
+        let prefix = format!("{handle}/");
+        let paths = self.nodes.keys().chain(self.links.keys());
+        let mut files = Vec::new();
+        for path in paths {
+            let Some(name) = path.strip_prefix(&prefix) else {
+                continue;
+            };
+
+            if !name.contains('/') {
+                files.push(File::new(name, self.attrs(path)?));
+            }
+        }
+
+        Ok(Name { id, files })
+    }
+
+    async fn close(&mut self, id: RequestId, handle: String) -> Result<Status, Self::Error> {
+        self.reading.remove(&handle);
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+
+    async fn lstat(&mut self, id: RequestId, path: String) -> Result<Attrs, Self::Error> {
+        Ok(Attrs {
+            id,
+            attrs: self.attrs(&Self::absolute(&path))?,
+        })
+    }
+}
+
+async fn connect() -> (SftpSession, Arc<Mutex<Vec<String>>>) {
+    let listed = Arc::default();
+    let sftp = SftpSession::pair(Tree::new(Arc::clone(&listed)))
+        .await
+        .unwrap()
+        .0;
+    (sftp, listed)
+}
+
+async fn glob(sftp: &SftpSession, pattern: &str) -> Vec<String> {
+    let mut stream = sftp.glob(pattern).unwrap();
+    let mut paths = Vec::new();
+    while let Some(path) = stream.next().await {
+        paths.push(path.unwrap());
+    }
+
+    paths.sort();
+    paths
+}
+
+#[tokio::test]
+async fn star_within_directory() {
+    let (sftp, listed) = connect().await;
+
+    assert_eq!(
+        glob(&sftp, "/logs/*.log").await,
+        ["/logs/app.log", "/logs/db.log"]
+    );
+    assert_eq!(glob(&sftp, "/logs/app*").await.len(), 2);
+
+    // the literal prefix is entered without listing the root
+    assert_eq!(*listed.lock().unwrap(), ["/logs", "/logs"]);
+}
+
+#[tokio::test]
+async fn literal_paths() {
+    let (sftp, listed) = connect().await;
+
+    assert_eq!(glob(&sftp, "/logs/db.log").await, ["/logs/db.log"]);
+    assert!(glob(&sftp, "/logs/missing.log").await.is_empty());
+    assert!(glob(&sftp, "/missing/*").await.is_empty());
+    assert!(listed.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn recursive() {
+    let (sftp, _) = connect().await;
+
+    // `**` also stands for no directory, and does not enter the symlink
+    assert_eq!(
+        glob(&sftp, "/logs/**/*.gz").await,
+        [
+            "/logs/2024/jan.gz",
+            "/logs/2024/old/dec.gz",
+            "/logs/app.log.1.gz"
+        ]
+    );
+
+    let all = glob(&sftp, "/logs/2024/**").await;
+    assert_eq!(
+        all,
+        [
+            "/logs/2024/jan.gz",
+            "/logs/2024/old",
+            "/logs/2024/old/dec.gz"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn character_classes() {
+    let (sftp, listed) = connect().await;
+
+    assert_eq!(
+        glob(&sftp, "/srv/a[0-9]/conf").await,
+        ["/srv/a1/conf", "/srv/a2/conf"]
+    );
+    assert_eq!(glob(&sftp, "/srv/[!a]?/conf").await, ["/srv/b1/conf"]);
+    assert_eq!(glob(&sftp, "/srv/{a2,b1}").await, ["/srv/a2", "/srv/b1"]);
+
+    // only the matching directories are entered for a literal component
+    assert!(listed.lock().unwrap().iter().all(|dir| dir == "/srv"));
+}
+
+#[tokio::test]
+async fn relative_pattern() {
+    let (sftp, _) = connect().await;
+    assert_eq!(glob(&sftp, "*.txt").await, ["notes.txt"]);
+}
+
+#[tokio::test]
+async fn invalid_pattern() {
+    let (sftp, _) = connect().await;
+    assert!(matches!(
+        sftp.glob("/logs/[a-"),
+        Err(Error::InvalidPattern(_))
+    ));
+}
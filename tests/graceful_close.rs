@@ -0,0 +1,126 @@
+//! Closing the session while a slow read is in flight.
+
+use russh_sftp::{
+    client::SftpSession,
+    protocol::{Data, FileAttributes, Handle, OpenFlags, RequestId, Status, StatusCode, Version},
+    server::Handler,
+};
+use std::{collections::HashMap, time::Duration};
+use tokio::{io::AsyncReadExt, time};
+
+const DELAY: Duration = Duration::from_millis(300);
+
+/// Replies to reads after [`DELAY`]
+struct Slow;
+
+#[async_trait::async_trait]
+impl Handler for Slow {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn open(
+        &mut self,
+        id: RequestId,
+        filename: String,
+        _pflags: OpenFlags,
+        _attrs: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        Ok(Handle {
+            id,
+            handle: filename,
+        })
+    }
+
+    async fn close(&mut self, id: RequestId, _handle: String) -> Result<Status, Self::Error> {
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+
+    async fn read(
+        &mut self,
+        id: RequestId,
+        _handle: String,
+        _offset: u64,
+        len: u32,
+    ) -> Result<Data, Self::Error> {
+        time::sleep(DELAY).await;
+        Ok(Data {
+            id,
+            data: vec![7; len.min(16) as usize],
+        })
+    }
+}
+
+#[tokio::test]
+async fn slow_read_completes_before_the_stream_closes() {
+    let (sftp, server) = SftpSession::pair(Slow).await.unwrap();
+    let mut file = sftp.open("/slow").await.unwrap();
+
+    let mut buf = [0; 16];
+    let (read, closed) = tokio::join!(file.read(&mut buf), async {
+        time::sleep(DELAY / 3).await;
+        assert!(!server.is_finished());
+        sftp.close().await
+    });
+
+    assert_eq!(read.unwrap(), 16);
+    assert_eq!(buf, [7; 16]);
+    closed.unwrap();
+
+    // the server ends as the stream was shut down after the read
+    time::timeout(Duration::from_secs(5), server)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn new_requests_rejected_while_closing() {
+    let (sftp, _server) = SftpSession::pair(Slow).await.unwrap();
+    let mut file = sftp.open("/slow").await.unwrap();
+
+    let mut buf = [0; 16];
+    let (read, (rejected, close)) = tokio::join!(file.read(&mut buf), async {
+        time::sleep(DELAY / 3).await;
+        let closing = sftp.clone();
+        let close = tokio::spawn(async move { closing.close().await });
+        time::sleep(DELAY / 3).await;
+        (sftp.metadata("/slow").await, close)
+    });
+
+    assert!(read.is_ok());
+    let err = rejected.unwrap_err().to_string();
+    assert!(err.contains("session closing"), "{err}");
+    close.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn close_immediately_drops_the_pending_read() {
+    let (sftp, server) = SftpSession::pair(Slow).await.unwrap();
+    sftp.set_timeout(1).await;
+    let mut file = sftp.open("/slow").await.unwrap();
+
+    let mut buf = [0; 16];
+    let (read, closed) = tokio::join!(file.read(&mut buf), async {
+        time::sleep(DELAY / 3).await;
+        sftp.close_immediately()
+    });
+
+    closed.unwrap();
+    assert!(read.is_err());
+
+    // the reply is written to a stream already shut down
+    let served = time::timeout(Duration::from_secs(5), server).await.unwrap();
+    assert!(served.unwrap().is_err());
+}
@@ -0,0 +1,112 @@
+//! Frames queued by the client are written out whichever of the end of the
+//! stream and the closing of the session comes first.
+
+use bytes::{BufMut, BytesMut};
+use russh_sftp::{
+    client::{error::Error, SftpSession},
+    protocol::StatusCode,
+};
+use std::time::{Duration, Instant};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, DuplexStream, ReadHalf, WriteHalf},
+    task::JoinHandle,
+    time,
+};
+
+const SSH_FXP_STAT: u8 = 17;
+const SSH_FXP_VERSION: u8 = 2;
+
+/// Requests left in the queue of the client, each larger than the stream buffer
+const QUEUED: usize = 5;
+
+/// Reads the type of the next frame, [`None`] at the end of the stream
+async fn read_type(stream: &mut ReadHalf<DuplexStream>) -> Option<u8> {
+    let len = stream.read_u32().await.ok()?;
+    let mut frame = vec![0; len as usize];
+    stream.read_exact(&mut frame).await.unwrap();
+    Some(frame[0])
+}
+
+/// Session whose server answered the handshake and then stopped reading
+async fn connect() -> (SftpSession, ReadHalf<DuplexStream>, WriteHalf<DuplexStream>) {
+    let (client, stream) = tokio::io::duplex(64);
+    let (mut rd, mut wr) = tokio::io::split(stream);
+
+    let server = tokio::spawn(async move {
+        read_type(&mut rd).await.unwrap();
+        let mut version = BytesMut::new();
+        version.put_u32(5);
+        version.put_u8(SSH_FXP_VERSION);
+        version.put_u32(3);
+        wr.write_all(&version).await.unwrap();
+        (rd, wr)
+    });
+
+    let sftp = SftpSession::new(client).await.unwrap();
+    let (rd, wr) = server.await.unwrap();
+    (sftp, rd, wr)
+}
+
+/// Sends requests that stay queued as the server does not read them
+async fn queue_requests(sftp: &SftpSession) -> Vec<JoinHandle<Result<(), Error>>> {
+    let requests = (0..QUEUED)
+        .map(|i| {
+            let sftp = sftp.clone();
+            let path = format!("/{}/{i}", "queued".repeat(20));
+            tokio::spawn(async move { sftp.metadata(path).await.map(|_| ()) })
+        })
+        .collect();
+
+    time::sleep(Duration::from_millis(50)).await;
+    requests
+}
+
+/// Reads the requests written by the client until it shuts the stream down
+async fn received(mut rd: ReadHalf<DuplexStream>) -> Vec<u8> {
+    let mut types = Vec::new();
+    let read_all = async {
+        while let Some(r#type) = read_type(&mut rd).await {
+            types.push(r#type);
+        }
+    };
+
+    time::timeout(Duration::from_secs(5), read_all)
+        .await
+        .unwrap();
+    types
+}
+
+#[tokio::test]
+async fn eof_first() {
+    let (sftp, rd, mut wr) = connect().await;
+    let requests = queue_requests(&sftp).await;
+
+    // the server ends its half without replying
+    let ended = Instant::now();
+    wr.shutdown().await.unwrap();
+
+    // pending requests fail right away rather than waiting for their timeout
+    for request in requests {
+        match request.await.unwrap() {
+            Err(Error::Status { status, .. }) => {
+                assert_eq!(status.status_code, StatusCode::ConnectionLost)
+            }
+            result => panic!("{result:?}"),
+        }
+    }
+    assert!(ended.elapsed() < Duration::from_secs(1));
+
+    // the requests already queued are still delivered
+    assert_eq!(received(rd).await, [SSH_FXP_STAT; QUEUED]);
+}
+
+#[tokio::test]
+async fn close_first() {
+    let (sftp, rd, _wr) = connect().await;
+    let _requests = queue_requests(&sftp).await;
+
+    // the shutdown sentinel is behind the requests in the queue
+    sftp.close_immediately().unwrap();
+
+    assert_eq!(received(rd).await, [SSH_FXP_STAT; QUEUED]);
+}
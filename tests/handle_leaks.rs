@@ -0,0 +1,101 @@
+//! Handles tracked by the client to diagnose the files never closed.
+
+mod common;
+
+use common::Memory;
+use russh_sftp::{
+    client::{RawSftpSession, SftpSession},
+    protocol::{FileAttributes, OpenFlags},
+    server,
+};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+async fn raw_session() -> RawSftpSession {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let memory = Memory::default();
+    memory.files.lock().unwrap().extend([
+        ("/a".to_owned(), vec![7; 10]),
+        ("/d/".to_owned(), Vec::new()),
+    ]);
+    server::run(server, memory).await;
+
+    let session = RawSftpSession::new(client);
+    session.init().await.unwrap();
+    session
+}
+
+#[tokio::test]
+async fn leaked_file_reported() {
+    let (sftp, _server) = SftpSession::pair(Memory::default()).await.unwrap();
+    sftp.track_handles(None);
+
+    let mut leaked = sftp.create("/leaked").await.unwrap();
+    leaked.write_all(b"never closed").await.unwrap();
+    std::mem::forget(leaked);
+
+    let mut closed = sftp.create("/closed").await.unwrap();
+    closed.shutdown().await.unwrap();
+    sftp.create_dir("/dir").await.unwrap();
+    let _ = sftp.read_dir("/dir").await.unwrap();
+
+    let report = sftp.open_handles_report();
+    assert_eq!(report.len(), 1, "{report:?}");
+    assert_eq!(report[0].path, "/leaked");
+    assert_eq!(report[0].operation, "SSH_FXP_OPEN");
+}
+
+#[tokio::test]
+async fn report_cleared_after_close() {
+    let session = raw_session().await;
+    session.track_handles(None);
+
+    let attrs = FileAttributes::empty();
+    let file = session.open("/a", OpenFlags::READ, attrs).await.unwrap();
+    let dir = session.opendir("/d").await.unwrap();
+
+    let report = session.open_handles_report();
+    let mut operations = report.iter().map(|h| h.operation).collect::<Vec<_>>();
+    operations.sort();
+    assert_eq!(operations, ["SSH_FXP_OPEN", "SSH_FXP_OPENDIR"]);
+
+    session.close(file.handle.as_str()).await.unwrap();
+    let report = session.open_handles_report();
+    assert_eq!(report.len(), 1);
+    assert_eq!(
+        (report[0].handle.as_str(), report[0].path.as_str()),
+        (dir.handle.as_str(), "/d")
+    );
+
+    session.close(dir.handle).await.unwrap();
+    assert!(session.open_handles_report().is_empty());
+}
+
+#[tokio::test(start_paused = true)]
+async fn age_and_idle_time() {
+    let session = raw_session().await;
+    session.track_handles(Some(Duration::from_secs(60)));
+
+    let attrs = FileAttributes::empty();
+    let handle = session
+        .open("/a", OpenFlags::READ, attrs)
+        .await
+        .unwrap()
+        .handle;
+    tokio::time::advance(Duration::from_secs(90)).await;
+    session.read(handle.as_str(), 0, 4).await.unwrap();
+    tokio::time::advance(Duration::from_secs(30)).await;
+
+    let report = session.open_handles_report();
+    assert_eq!(report[0].age, Duration::from_secs(120));
+    assert_eq!(report[0].idle, Duration::from_secs(30));
+}
+
+#[tokio::test]
+async fn untracked_by_default() {
+    let session = raw_session().await;
+
+    let attrs = FileAttributes::empty();
+    session.open("/a", OpenFlags::READ, attrs).await.unwrap();
+    assert!(session.open_handles_report().is_empty());
+}
@@ -0,0 +1,231 @@
+//! Sessions of a `SessionHub` sharing the state of their handler factory.
+
+mod common;
+
+use common::Memory;
+use russh_sftp::{
+    client::SftpSession,
+    protocol::{Data, FileAttributes, Handle, OpenFlags, RequestId, Status, StatusCode, Version},
+    server::{serve_many, EndReason, Handler, HandlerFactory, HubStats, SessionHub, SessionInfo},
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+/// Files and open handles shared by all the sessions
+#[derive(Default)]
+struct Shared {
+    files: Memory,
+    /// Open path and user of each handle
+    handles: Mutex<HashMap<String, (String, String)>>,
+    ended: Mutex<Vec<(String, EndReason)>>,
+}
+
+#[derive(Default)]
+struct Store(Arc<Shared>);
+
+impl Store {
+    fn open_by(&self, path: &str) -> Vec<String> {
+        let handles = self.0.handles.lock().unwrap();
+        let mut users = handles
+            .values()
+            .filter(|(open, _)| open == path)
+            .map(|(_, user)| user.to_owned())
+            .collect::<Vec<_>>();
+        users.sort();
+        users
+    }
+}
+
+impl HandlerFactory for Store {
+    type Handler = Session;
+
+    fn make_handler(&self, info: SessionInfo) -> Session {
+        Session {
+            shared: self.0.clone(),
+            files: self.0.files.clone(),
+            info,
+        }
+    }
+
+    fn session_ended(&self, info: &SessionInfo, reason: &EndReason) {
+        let user = info.user.clone().unwrap_or_default();
+        self.0.ended.lock().unwrap().push((user, reason.clone()));
+    }
+}
+
+struct Session {
+    shared: Arc<Shared>,
+    /// The files of [`Shared`], served by path
+    files: Memory,
+    info: SessionInfo,
+}
+
+#[async_trait::async_trait]
+impl Handler for Session {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        version: u32,
+        extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        self.files.init(version, extensions).await
+    }
+
+    async fn open(
+        &mut self,
+        id: RequestId,
+        filename: String,
+        pflags: OpenFlags,
+        attrs: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        let opened = self.files.open(id, filename, pflags, attrs).await?;
+
+        // one handle per open file rather than per path
+        let handle = format!("{}-{id}", self.info.id);
+        let user = self.info.user.clone().unwrap_or_default();
+        let mut handles = self.shared.handles.lock().unwrap();
+        handles.insert(handle.clone(), (opened.handle, user));
+        Ok(Handle { id, handle })
+    }
+
+    async fn close(&mut self, id: RequestId, handle: String) -> Result<Status, Self::Error> {
+        self.shared.handles.lock().unwrap().remove(&handle);
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+
+    async fn read(
+        &mut self,
+        id: RequestId,
+        handle: String,
+        offset: u64,
+        len: u32,
+    ) -> Result<Data, Self::Error> {
+        let path = self.path(&handle)?;
+        self.files.read(id, path, offset, len).await
+    }
+
+    async fn write(
+        &mut self,
+        id: RequestId,
+        handle: String,
+        offset: u64,
+        data: Vec<u8>,
+    ) -> Result<Status, Self::Error> {
+        let path = self.path(&handle)?;
+        self.shared
+            .files
+            .clone()
+            .write(id, path, offset, data)
+            .await
+    }
+}
+
+impl Session {
+    fn path(&self, handle: &str) -> Result<String, StatusCode> {
+        let handles = self.shared.handles.lock().unwrap();
+        let (path, _) = handles.get(handle).ok_or(StatusCode::Failure)?;
+        Ok(path.to_owned())
+    }
+}
+
+async fn connect<F: HandlerFactory>(hub: &mut SessionHub<F>, user: &str) -> SftpSession {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    hub.spawn(server, SessionInfo::new().with_user(user));
+    SftpSession::new(client).await.unwrap()
+}
+
+#[tokio::test]
+async fn shared_state_across_sessions() {
+    let mut hub = SessionHub::new(Store::default(), Default::default());
+    let alice = connect(&mut hub, "alice").await;
+    let bob = connect(&mut hub, "bob").await;
+
+    // alice keeps the file open while bob reads it
+    let mut file = alice.create("/shared.txt").await.unwrap();
+    file.write_all(b"written by alice").await.unwrap();
+    file.flush().await.unwrap();
+
+    let mut read = bob.open("/shared.txt").await.unwrap();
+    assert_eq!(hub.factory().open_by("/shared.txt"), ["alice", "bob"]);
+
+    let mut contents = String::new();
+    read.read_to_string(&mut contents).await.unwrap();
+    assert_eq!(contents, "written by alice");
+
+    drop(read);
+    drop(file);
+    alice.close().await.unwrap();
+    bob.close().await.unwrap();
+    hub.join().await;
+
+    let stats = hub.stats();
+    assert_eq!(
+        (stats.sessions, stats.active, stats.closed, stats.failed),
+        (2, 0, 2, 0)
+    );
+    assert!(stats.requests >= 6, "{stats:?}");
+
+    let mut ended = hub.factory().0.ended.lock().unwrap().clone();
+    ended.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        ended,
+        [
+            ("alice".to_owned(), EndReason::Closed),
+            ("bob".to_owned(), EndReason::Closed)
+        ]
+    );
+}
+
+#[tokio::test]
+async fn session_ids_and_closures() {
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let factory = {
+        let seen = seen.clone();
+        move |info: SessionInfo| {
+            seen.lock().unwrap().push(info.clone());
+            Session {
+                shared: Arc::default(),
+                files: Memory::default(),
+                info,
+            }
+        }
+    };
+
+    let mut streams = Vec::new();
+    let mut clients: Vec<DuplexStream> = Vec::new();
+    for user in ["carol", "dave"] {
+        let (client, server) = tokio::io::duplex(1024);
+        streams.push((server, SessionInfo::new().with_user(user)));
+        clients.push(client);
+    }
+
+    // the clients hang up at once
+    drop(clients);
+    let stats = serve_many(factory, Default::default(), streams).await;
+    assert_eq!(
+        stats,
+        HubStats {
+            sessions: 2,
+            active: 0,
+            closed: 2,
+            failed: 0,
+            requests: 0,
+        }
+    );
+
+    let mut seen = seen.lock().unwrap().clone();
+    seen.sort_by_key(|info| info.id);
+    let ids = seen.iter().map(|info| (info.id, info.user.as_deref()));
+    assert_eq!(
+        ids.collect::<Vec<_>>(),
+        [(0, Some("carol")), (1, Some("dave"))]
+    );
+}
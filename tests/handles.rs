@@ -0,0 +1,82 @@
+//! Length of the handles replied by the server and the numeric encoding of handles.
+
+mod common;
+
+use common::Memory;
+use russh_sftp::{
+    client::{error::Error, rawsession::RawSftpSession},
+    protocol::{FileAttributes, Handle, OpenFlags, RequestId, StatusCode, MAX_HANDLE_LEN},
+    server::{self, Config, HandleAction},
+};
+
+async fn open(config: Config, path: &str) -> Result<Handle, Error> {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    server::run_with_config(server, Memory::default(), config).await;
+
+    let session = RawSftpSession::new(client);
+    session.init().await.unwrap();
+
+    // the handle of the in-memory server is the path
+    let flags = OpenFlags::CREATE | OpenFlags::WRITE;
+    session.open(path, flags, FileAttributes::empty()).await
+}
+
+#[tokio::test]
+async fn long_handle_rejected() {
+    let path = format!("/{}", "h".repeat(MAX_HANDLE_LEN - 1));
+    let handle = open(Config::default(), &path).await.unwrap();
+    assert_eq!(handle.handle, path);
+
+    let path = format!("/{}", "h".repeat(MAX_HANDLE_LEN));
+    match open(Config::default(), &path).await {
+        Err(Error::Status { status, .. }) => {
+            assert_eq!(status.status_code, StatusCode::Failure);
+            assert_eq!(
+                status.error_message,
+                "handle of 257 bytes is longer than 256"
+            );
+        }
+        result => panic!("long handle replied: {result:?}"),
+    }
+}
+
+#[tokio::test]
+async fn long_handle_truncated() {
+    let config = Config {
+        oversized_handle_action: HandleAction::Truncate,
+        ..Config::default()
+    };
+
+    // two-byte characters, the 256th byte is in the middle of one
+    let path = format!("/{}", "é".repeat(MAX_HANDLE_LEN));
+    let handle = open(config, &path).await.unwrap();
+    assert_eq!(handle.handle.len(), MAX_HANDLE_LEN - 1);
+    assert!(path.starts_with(&handle.handle));
+}
+
+#[test]
+fn checked_constructor() {
+    assert!(Handle::new_checked(RequestId(1), "h".repeat(MAX_HANDLE_LEN)).is_ok());
+    assert!(Handle::new_checked(RequestId(1), "h".repeat(MAX_HANDLE_LEN + 1)).is_err());
+}
+
+#[test]
+fn u64_round_trip() {
+    for value in [0, 1, 0xdead_beef, u64::MAX] {
+        let handle = Handle::encode_u64(value);
+        assert_eq!(handle.len(), 16);
+        assert_eq!(Handle::decode_u64(&handle), Some(value));
+    }
+
+    assert_eq!(Handle::encode_u64(0xdead_beef), "00000000deadbeef");
+
+    for handle in [
+        "",
+        "1",
+        "00000000DEADBEEF",
+        "000000000deadbeef",
+        "+0000000deadbeef",
+    ] {
+        assert_eq!(Handle::decode_u64(handle), None, "{handle}");
+    }
+}
@@ -0,0 +1,107 @@
+//! Liveness of sessions: the reason their stream ended, the health reported
+//! without a round trip and pings.
+
+mod common;
+
+use common::Memory;
+use russh_sftp::{
+    client::{error::Error, CloseReason, RawSftpSession, SftpSession},
+    protocol::StatusCode,
+    server::{
+        testkit::{Fault, FaultLayer, Rule},
+        Config,
+    },
+};
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+#[tokio::test]
+async fn closed_by_client() {
+    let (sftp, _server) = SftpSession::pair(Memory::default()).await.unwrap();
+    let closed = tokio::spawn(sftp.closed());
+
+    assert!(sftp.is_healthy());
+    sftp.ping(Duration::from_secs(5)).await.unwrap();
+
+    sftp.close().await.unwrap();
+    assert!(matches!(closed.await.unwrap(), CloseReason::Closed));
+    assert!(!sftp.is_healthy());
+}
+
+#[tokio::test]
+async fn closed_by_server() {
+    let (sftp, server) = SftpSession::pair(Memory::default()).await.unwrap();
+    server.abort();
+
+    assert!(matches!(sftp.closed().await, CloseReason::Eof));
+    assert!(!sftp.is_healthy());
+
+    let Err(Error::Status { status, .. }) = sftp.metadata("/").await else {
+        panic!("request sent after the end of the stream");
+    };
+    assert_eq!(status.status_code, StatusCode::ConnectionLost);
+}
+
+/// Never delivers any byte and fails every write
+struct Broken;
+
+impl AsyncRead for Broken {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        _buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Poll::Pending
+    }
+}
+
+impl AsyncWrite for Broken {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        _buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[tokio::test]
+async fn write_error_carried() {
+    let session = RawSftpSession::new(Broken);
+    assert!(session.is_healthy());
+    assert!(session.init().await.is_err());
+
+    match session.closed().await {
+        CloseReason::Failed(Error::IO(_)) => (),
+        reason => panic!("closed with {reason:?}"),
+    }
+    assert!(!session.is_healthy());
+    assert!(session.closed_reason().is_some());
+}
+
+#[tokio::test]
+async fn ping_timeout() {
+    let layer = FaultLayer::new();
+    let client = layer.serve(Memory::default(), Config::default()).await;
+    let sftp = SftpSession::new(client).await.unwrap();
+
+    let stalled = Rule::new(Fault::Delay(Duration::from_secs(60))).packet("SSH_FXP_REALPATH");
+    layer.push_rule(stalled);
+    let err = sftp.ping(Duration::from_millis(50)).await.unwrap_err();
+    assert!(matches!(err, Error::Timeout), "{err}");
+    // a stalled server is not detected without a round trip
+    assert!(sftp.is_healthy());
+}
@@ -0,0 +1,116 @@
+//! Requests in flight and waiting for a slot under a cap, counted until they are
+//! answered or cancelled.
+
+use russh_sftp::{
+    client::{rawsession::InflightStats, RawSftpSession},
+    protocol::{Attrs, FileAttributes, RequestId, StatusCode, Version},
+    server::{self, Config, Handler},
+};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::time;
+
+/// Answers the stat of `/slow` late and never that of `/hang`
+struct Hanging;
+
+#[async_trait::async_trait]
+impl Handler for Hanging {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn stat(&mut self, id: RequestId, path: String) -> Result<Attrs, Self::Error> {
+        match path.as_str() {
+            "/slow" => time::sleep(Duration::from_millis(50)).await,
+            "/hang" => std::future::pending().await,
+            _ => (),
+        }
+
+        Ok(Attrs {
+            id,
+            attrs: FileAttributes::empty(),
+        })
+    }
+}
+
+async fn session(max: usize) -> Arc<RawSftpSession> {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(server::serve(server, Hanging, Config::default()));
+    let raw = RawSftpSession::new(client);
+    raw.init().await.unwrap();
+    raw.set_max_inflight(max);
+    Arc::new(raw)
+}
+
+/// Waits until the stats of the session are `expected`
+async fn settled(raw: &RawSftpSession, expected: InflightStats) {
+    for _ in 0..100 {
+        if raw.inflight_stats() == expected {
+            return;
+        }
+        tokio::task::yield_now().await;
+    }
+    assert_eq!(raw.inflight_stats(), expected);
+}
+
+fn stats(in_flight: usize, waiting: usize) -> InflightStats {
+    InflightStats {
+        in_flight,
+        waiting,
+        max_in_flight: Some(1),
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn waiting_requests_counted() {
+    let raw = session(1).await;
+    let slow = tokio::spawn({
+        let raw = raw.clone();
+        async move { raw.stat("/slow").await }
+    });
+    settled(&raw, stats(1, 0)).await;
+
+    let waiting = tokio::spawn({
+        let raw = raw.clone();
+        async move { raw.stat("/file").await }
+    });
+    settled(&raw, stats(1, 1)).await;
+
+    // the slot is taken by the waiting request once the slow one is answered
+    slow.await.unwrap().unwrap();
+    waiting.await.unwrap().unwrap();
+    assert_eq!(raw.inflight_stats(), stats(0, 0));
+}
+
+#[tokio::test(start_paused = true)]
+async fn cancelled_waits_not_counted() {
+    let raw = session(1).await;
+    tokio::spawn({
+        let raw = raw.clone();
+        async move { raw.stat("/hang").await }
+    });
+    settled(&raw, stats(1, 0)).await;
+
+    // cancelled while waiting for the slot, by a timeout or by aborting the task
+    let timeout = time::timeout(Duration::from_millis(10), raw.stat("/file")).await;
+    assert!(timeout.is_err());
+    assert_eq!(raw.inflight_stats(), stats(1, 0));
+
+    let waiting = tokio::spawn({
+        let raw = raw.clone();
+        async move { raw.stat("/file").await }
+    });
+    settled(&raw, stats(1, 1)).await;
+    waiting.abort();
+    let _ = waiting.await;
+    assert_eq!(raw.inflight_stats(), stats(1, 0));
+}
@@ -0,0 +1,109 @@
+//! Sessions with legacy devices that stall unless the first request after
+//! SSH_FXP_VERSION is SSH_FXP_REALPATH of `.`.
+
+use bytes::{Bytes, BytesMut};
+use russh_sftp::{
+    client::{error::Error, InitProbe, SftpSession},
+    framing::read_packet,
+    protocol::{File, Name, Packet, Status, StatusCode, Version},
+};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncWriteExt, DuplexStream};
+
+const HOME: &str = "/home/admin";
+
+/// Scripted device: never answers anything if the first request is not
+/// SSH_FXP_REALPATH of `.`, and records the types of the requests
+async fn device(mut stream: DuplexStream, version: Version, requests: Arc<Mutex<Vec<String>>>) {
+    let mut buf = BytesMut::new();
+    read_packet(&mut stream, &mut buf).await.unwrap();
+    let reply = Bytes::try_from(Packet::Version(version)).unwrap();
+    stream.write_all(&reply).await.unwrap();
+
+    let mut first = true;
+    while let Ok(mut bytes) = read_packet(&mut stream, &mut buf).await {
+        let request = Packet::try_from(&mut bytes).unwrap();
+        requests.lock().unwrap().push(request.name().to_owned());
+
+        let reply = match request {
+            Packet::RealPath(realpath) if realpath.path == "." => Packet::Name(Name {
+                id: realpath.id,
+                files: vec![File::dummy(HOME)],
+            }),
+            _ if first => continue,
+            request => Packet::Status(Status::new(
+                request.get_request_id(),
+                StatusCode::OpUnsupported,
+            )),
+        };
+
+        first = false;
+        let reply = Bytes::try_from(reply).unwrap();
+        stream.write_all(&reply).await.unwrap();
+    }
+}
+
+fn legacy_version(limits: bool) -> Version {
+    match limits {
+        true => Version::new().with_extension("limits@openssh.com", "1"),
+        false => Version::new().with_extension("vendor-ext@example.com", "1"),
+    }
+}
+
+async fn connect(
+    version: Version,
+    init_probe: InitProbe,
+) -> (Result<SftpSession, Error>, Arc<Mutex<Vec<String>>>) {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let requests = Arc::default();
+    tokio::spawn(device(server, version, Arc::clone(&requests)));
+
+    let sftp = SftpSession::builder()
+        .timeout(1)
+        .init_probe(init_probe)
+        .connect(client)
+        .await;
+    (sftp, requests)
+}
+
+#[tokio::test]
+async fn stalls_without_probe() {
+    let (sftp, requests) = connect(legacy_version(true), InitProbe::None).await;
+
+    assert!(matches!(sftp, Err(Error::Timeout)));
+    assert_eq!(*requests.lock().unwrap(), ["SSH_FXP_EXTENDED"]);
+}
+
+#[tokio::test]
+async fn realpath_dot_first() {
+    let (sftp, requests) = connect(legacy_version(true), InitProbe::RealpathDot).await;
+    let sftp = sftp.unwrap();
+
+    // the limits are still queried, after the probe
+    assert_eq!(
+        *requests.lock().unwrap(),
+        ["SSH_FXP_REALPATH", "SSH_FXP_EXTENDED"]
+    );
+    assert_eq!(sftp.initial_dir(), Some(HOME));
+
+    // the home directory comes from the probe without another round trip
+    assert_eq!(sftp.home_dir(None).await.unwrap(), HOME);
+    assert_eq!(requests.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn auto_without_openssh_extensions() {
+    let (sftp, requests) = connect(legacy_version(false), InitProbe::Auto).await;
+
+    assert_eq!(sftp.unwrap().initial_dir(), Some(HOME));
+    assert_eq!(*requests.lock().unwrap(), ["SSH_FXP_REALPATH"]);
+}
+
+#[tokio::test]
+async fn auto_with_openssh_extensions() {
+    let version = Version::new().with_extension("fsync@openssh.com", "1");
+    let (sftp, requests) = connect(version, InitProbe::Auto).await;
+
+    assert_eq!(sftp.unwrap().initial_dir(), None);
+    assert!(requests.lock().unwrap().is_empty());
+}
@@ -0,0 +1,207 @@
+//! The SSH_FXP_INIT exchange enforced by the server loop, requests before it and
+//! a second SSH_FXP_INIT rejected without calling the handler.
+
+use bytes::{Bytes, BytesMut};
+use russh_sftp::{
+    framing::{read_packet, write_packet},
+    protocol::{Attrs, FileAttributes, Init, Packet, RequestId, Stat, Status, StatusCode, Version},
+    server::{self, Config, EndReason, Handler, SequenceAction},
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::{io::DuplexStream, task::JoinHandle};
+
+/// Records the calls of the handler, the first SSH_FXP_INIT of version 1 is refused
+#[derive(Clone, Default)]
+struct Recorder(Arc<Mutex<Vec<String>>>);
+
+impl Recorder {
+    fn calls(&self) -> Vec<String> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl Handler for Recorder {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        self.0.lock().unwrap().push(format!("init {version}"));
+        match version {
+            1 => Err(StatusCode::OpUnsupported),
+            _ => Ok(Version::new()),
+        }
+    }
+
+    async fn stat(&mut self, id: RequestId, path: String) -> Result<Attrs, Self::Error> {
+        self.0.lock().unwrap().push(format!("stat {path}"));
+        Ok(Attrs {
+            id,
+            attrs: FileAttributes::empty(),
+        })
+    }
+
+    async fn session_ended(&mut self, reason: EndReason) {
+        self.0.lock().unwrap().push(format!("ended {reason:?}"));
+    }
+}
+
+type Session = (
+    DuplexStream,
+    Recorder,
+    JoinHandle<Result<(), russh_sftp::Error>>,
+);
+
+fn connect(sequence_action: SequenceAction) -> Session {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let handler = Recorder::default();
+    let config = Config {
+        sequence_action,
+        ..Default::default()
+    };
+
+    let served = tokio::spawn(server::serve(server, handler.clone(), config));
+    (client, handler, served)
+}
+
+fn init(version: u32) -> Packet {
+    Init {
+        version,
+        extensions: HashMap::new(),
+    }
+    .into()
+}
+
+fn stat(id: u32) -> Packet {
+    Stat {
+        id: RequestId(id),
+        path: "/f".to_owned(),
+    }
+    .into()
+}
+
+async fn request(client: &mut DuplexStream, packet: Packet) -> Packet {
+    let frame = Bytes::try_from(packet).unwrap();
+    write_packet(client, &frame[4..]).await.unwrap();
+    let mut reply = read_packet(client, &mut BytesMut::new()).await.unwrap();
+    Packet::try_from(&mut reply).unwrap()
+}
+
+fn into_status(packet: Packet) -> Status {
+    match packet {
+        Packet::Status(status) => status,
+        packet => panic!("{} is not a status", packet.name()),
+    }
+}
+
+#[tokio::test]
+async fn request_before_init_rejected() {
+    let (mut client, handler, _served) = connect(SequenceAction::Reject);
+
+    let status = into_status(request(&mut client, stat(7)).await);
+    assert_eq!(status.id, RequestId(7));
+    assert_eq!(status.status_code, StatusCode::BadMessage);
+
+    // the session is kept and starts normally
+    assert!(matches!(
+        request(&mut client, init(3)).await,
+        Packet::Version(_)
+    ));
+    assert!(matches!(
+        request(&mut client, stat(8)).await,
+        Packet::Attrs(_)
+    ));
+    assert_eq!(handler.calls(), ["init 3", "stat /f"]);
+}
+
+#[tokio::test]
+async fn duplicate_init_rejected() {
+    let (mut client, handler, _served) = connect(SequenceAction::Reject);
+
+    assert!(matches!(
+        request(&mut client, init(3)).await,
+        Packet::Version(_)
+    ));
+    let status = into_status(request(&mut client, init(3)).await);
+    assert_eq!(status.status_code, StatusCode::BadMessage);
+
+    assert!(matches!(
+        request(&mut client, stat(1)).await,
+        Packet::Attrs(_)
+    ));
+    assert_eq!(handler.calls(), ["init 3", "stat /f"]);
+}
+
+#[tokio::test]
+async fn refused_init_retried() {
+    let (mut client, handler, _served) = connect(SequenceAction::Reject);
+
+    // no version was negotiated, so another SSH_FXP_INIT is not a duplicate
+    let status = into_status(request(&mut client, init(1)).await);
+    assert_eq!(status.status_code, StatusCode::OpUnsupported);
+    assert!(matches!(
+        request(&mut client, init(3)).await,
+        Packet::Version(_)
+    ));
+    assert_eq!(handler.calls(), ["init 1", "init 3"]);
+}
+
+#[tokio::test]
+async fn violations_disconnect() {
+    for violation in [stat(1), init(3)] {
+        let (mut client, handler, served) = connect(SequenceAction::Disconnect);
+        let name = violation.name();
+        if matches!(violation, Packet::Init(_)) {
+            request(&mut client, init(3)).await;
+        }
+
+        let status = into_status(request(&mut client, violation).await);
+        assert_eq!(status.status_code, StatusCode::BadMessage, "{name}");
+
+        let err = served.await.unwrap().unwrap_err();
+        assert!(
+            err.to_string().ends_with("SSH_FXP_INIT sequence violated"),
+            "{name}"
+        );
+        assert_eq!(
+            handler.calls().last().unwrap(),
+            "ended SequenceViolated",
+            "{name}"
+        );
+        assert!(!handler.calls().contains(&"stat /f".to_owned()), "{name}");
+    }
+}
+
+#[tokio::test]
+async fn init_of_a_proxied_session_rejected() {
+    let (mut client, server) = tokio::io::duplex(64 * 1024);
+    let handler = Recorder::default();
+    let client_init = Init {
+        version: 3,
+        extensions: HashMap::new(),
+    };
+    tokio::spawn(server::serve_initialized(
+        server,
+        handler.clone(),
+        Config::default(),
+        client_init,
+    ));
+
+    let status = into_status(request(&mut client, init(3)).await);
+    assert_eq!(status.status_code, StatusCode::BadMessage);
+    assert!(matches!(
+        request(&mut client, stat(1)).await,
+        Packet::Attrs(_)
+    ));
+    assert_eq!(handler.calls(), ["init 3", "stat /f"]);
+}
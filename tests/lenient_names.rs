@@ -0,0 +1,144 @@
+//! SSH_FXP_NAME entries without longname, as sent by servers negotiated down to
+//! version 3 that still follow the layout of version 4.
+
+use bytes::{BufMut, Bytes, BytesMut};
+use russh_sftp::{
+    client::{error::Error, RawSftpSession, SftpSession},
+    protocol::StatusCode,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+const SSH_FXP_INIT: u8 = 1;
+const SSH_FXP_CLOSE: u8 = 4;
+const SSH_FXP_OPENDIR: u8 = 11;
+const SSH_FXP_READDIR: u8 = 12;
+
+/// Reply to readdir with `report.txt` and `logs` and their attributes, without
+/// the longname of either entry
+const NAME_WITHOUT_LONGNAME: &str = "
+0000004f
+68
+00000004000000020000000a7265706f72742e7478740000000f000000000000
+0800000003e8000003e8000081a46553f1006553f100000000046c6f67730000
+000c000041ed6553f1006553f100";
+
+fn decode_hex(hex: &str) -> Vec<u8> {
+    let hex = hex.split_whitespace().collect::<String>();
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+async fn write_frame(stream: &mut DuplexStream, frame: &[u8]) {
+    stream.write_u32(frame.len() as u32).await.unwrap();
+    stream.write_all(frame).await.unwrap();
+}
+
+fn status(id: u32, code: StatusCode) -> BytesMut {
+    let mut status = BytesMut::new();
+    status.put_u8(101);
+    status.put_u32(id);
+    status.put_u32(code as u32);
+    status.put_u32(0);
+    status.put_u32(0);
+    status
+}
+
+/// Replies to readdir with the captured entries and then Eof
+async fn server(mut stream: DuplexStream) {
+    let captured = Bytes::from(decode_hex(NAME_WITHOUT_LONGNAME));
+    let mut listed = false;
+
+    while let Ok(len) = stream.read_u32().await {
+        let mut request = vec![0; len as usize];
+        stream.read_exact(&mut request).await.unwrap();
+        let id = u32::from_be_bytes(request[1..5].try_into().unwrap());
+
+        let reply = match request[0] {
+            SSH_FXP_INIT => {
+                let mut version = BytesMut::new();
+                version.put_u8(2);
+                version.put_u32(3);
+                version
+            }
+            SSH_FXP_OPENDIR => {
+                let mut handle = BytesMut::new();
+                handle.put_u8(102);
+                handle.put_u32(id);
+                handle.put_u32(3);
+                handle.put_slice(b"dir");
+                handle
+            }
+            SSH_FXP_READDIR if !listed => {
+                listed = true;
+                let mut name = BytesMut::from(&captured[4..]);
+                name[1..5].copy_from_slice(&id.to_be_bytes());
+                name
+            }
+            SSH_FXP_READDIR => status(id, StatusCode::Eof),
+            SSH_FXP_CLOSE => status(id, StatusCode::Ok),
+            _ => status(id, StatusCode::OpUnsupported),
+        };
+        write_frame(&mut stream, &reply).await;
+    }
+}
+
+async fn raw_session() -> RawSftpSession {
+    let (client, stream) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(server(stream));
+    let raw = RawSftpSession::new(client);
+    raw.init().await.unwrap();
+    raw
+}
+
+#[tokio::test]
+async fn entries_without_longname() {
+    let raw = raw_session().await;
+    raw.set_lenient_names(true);
+
+    let handle = raw.opendir("/var").await.unwrap().handle;
+    let name = raw.readdir(handle).await.unwrap();
+    let names = name.files.iter().map(|f| &f.filename).collect::<Vec<_>>();
+    assert_eq!(names, ["report.txt", "logs"]);
+
+    let report = &name.files[0];
+    assert_eq!(report.longname, "");
+    assert_eq!(report.attrs.size, Some(2048));
+    assert_eq!(report.attrs.permissions, Some(0o100644));
+    assert_eq!(
+        report.longname(),
+        "-rw-r--r-- 0 1000 1000 2048 Nov 14 2023 22:13 report.txt"
+    );
+
+    let logs = &name.files[1];
+    assert!(logs.attrs.is_dir());
+    assert!(logs.longname().starts_with("drwxr-xr-x"));
+}
+
+#[tokio::test]
+async fn listed_by_read_dir() {
+    let (client, stream) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(server(stream));
+    let sftp = SftpSession::new(client).await.unwrap();
+    sftp.set_lenient_names(true);
+
+    let entries = sftp.read_dir("/var").await.unwrap();
+    let names = entries.map(|e| e.file_name()).collect::<Vec<_>>();
+    assert_eq!(names, ["report.txt", "logs"]);
+}
+
+#[tokio::test]
+async fn dropped_by_default() {
+    let (client, stream) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(server(stream));
+    let sftp = SftpSession::new(client).await.unwrap();
+    sftp.set_timeout(1).await;
+
+    // the reply is not understood, so the request is left unanswered
+    match sftp.read_dir("/var").await {
+        Err(Error::Timeout) => (),
+        Err(err) => panic!("{err}"),
+        Ok(_) => panic!("listed without longname"),
+    }
+}
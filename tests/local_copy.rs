@@ -0,0 +1,140 @@
+//! Pipelined copies between remote and local files compared by hash, with sizes
+//! that are not a multiple of the chunk size.
+
+mod common;
+
+use common::{Local, Memory};
+use russh_sftp::{
+    client::{fs::ProgressEvent, SftpSession},
+    protocol::OpenFlags,
+};
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+use tokio::{fs, io::AsyncSeekExt};
+
+const CHUNK: usize = 1000;
+
+/// Sizes around the chunk size and over the number of requests in flight
+const SIZES: [usize; 6] = [0, 1, CHUNK - 1, CHUNK, 40 * CHUNK, 40 * CHUNK + 123];
+
+fn contents(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 31 % 251) as u8).collect()
+}
+
+fn hash(data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Opens the file for reading and writing, creating it
+async fn open(local: &Local) -> fs::File {
+    fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&local.0)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn copied_to_local() {
+    let server = Memory::default();
+    let (sftp, _server) = SftpSession::pair(server.clone()).await.unwrap();
+
+    for size in SIZES {
+        let data = contents(size);
+        let path = format!("/file-{size}");
+        server
+            .files
+            .lock()
+            .unwrap()
+            .insert(path.clone(), data.clone());
+
+        // stale contents longer than the remote file are truncated
+        let local = Local::new(&format!("download-{size}"));
+        fs::write(&local.0, vec![0xff; size + 2 * CHUNK])
+            .await
+            .unwrap();
+
+        let mut file = sftp.open(path).await.unwrap();
+        file.pin_chunk_size(CHUNK);
+        let mut events = Vec::new();
+        let copied = file
+            .copy_to_local_with_progress(&mut open(&local).await, |event| events.push(event))
+            .await
+            .unwrap();
+
+        assert_eq!(copied, size as u64);
+        assert_eq!(
+            hash(&fs::read(&local.0).await.unwrap()),
+            hash(&data),
+            "{size}"
+        );
+        assert_eq!(events.len(), size.div_ceil(CHUNK), "{size}");
+        assert_eq!(
+            events.last().map(|event| event.bytes_transferred),
+            (size > 0).then_some(size as u64)
+        );
+    }
+}
+
+#[tokio::test]
+async fn copied_from_local() {
+    let server = Memory::default();
+    let (sftp, _server) = SftpSession::pair(server.clone()).await.unwrap();
+
+    for size in SIZES {
+        let data = contents(size);
+        let local = Local::new(&format!("upload-{size}"));
+        fs::write(&local.0, &data).await.unwrap();
+
+        // read from the start regardless of the position
+        let mut local = open(&local).await;
+        local.seek(std::io::SeekFrom::End(0)).await.unwrap();
+
+        let path = format!("/file-{size}");
+        let flags = OpenFlags::CREATE | OpenFlags::TRUNCATE | OpenFlags::WRITE;
+        let mut file = sftp.open_with_flags(path.as_str(), flags).await.unwrap();
+        file.pin_chunk_size(CHUNK);
+        let mut acknowledged = 0;
+        let copied = file
+            .copy_from_local_with_progress(&mut local, |event: ProgressEvent| {
+                acknowledged = acknowledged.max(event.bytes_transferred)
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(copied, size as u64);
+        assert_eq!(acknowledged, size as u64);
+        let remote = server.files.lock().unwrap()[&path].clone();
+        assert_eq!(hash(&remote), hash(&data), "{size}");
+    }
+}
+
+#[tokio::test]
+async fn round_trip() {
+    let server = Memory::default();
+    let (sftp, _server) = SftpSession::pair(server).await.unwrap();
+    let size = 40 * CHUNK + 123;
+    let data = contents(size);
+
+    let source = Local::new("round-trip-source");
+    fs::write(&source.0, &data).await.unwrap();
+    let mut file = sftp.create("/round-trip").await.unwrap();
+    file.pin_chunk_size(CHUNK);
+    file.copy_from_local(&mut open(&source).await)
+        .await
+        .unwrap();
+
+    let target = Local::new("round-trip-target");
+    let mut file = sftp.open("/round-trip").await.unwrap();
+    file.pin_chunk_size(CHUNK);
+    file.copy_to_local(&mut open(&target).await).await.unwrap();
+
+    assert_eq!(hash(&fs::read(&target.0).await.unwrap()), hash(&data));
+}
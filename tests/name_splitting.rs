@@ -0,0 +1,186 @@
+//! Entries of SSH_FXP_NAME over the maximum response length split across
+//! consecutive SSH_FXP_READDIR, and the deferred entries dropped with the handle.
+
+use russh_sftp::{
+    client::{error::Error, RawSftpSession},
+    protocol::{File, Handle, Name, RequestId, Status, StatusCode, Version},
+    server::{self, Config, Handler},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+const MAX_LEN: u32 = 4096;
+const ENTRIES: usize = 500;
+
+/// Every directory has [`ENTRIES`] entries returned by a single SSH_FXP_READDIR,
+/// `/huge` has an entry too long for any reply. The handle is the path
+#[derive(Clone, Default)]
+struct Listing {
+    readdirs: Arc<AtomicU64>,
+    listed: HashSet<String>,
+}
+
+fn entry(i: usize) -> String {
+    format!("entry-{i:05}")
+}
+
+#[async_trait::async_trait]
+impl Handler for Listing {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn opendir(&mut self, id: RequestId, path: String) -> Result<Handle, Self::Error> {
+        self.listed.remove(&path);
+        Ok(Handle { id, handle: path })
+    }
+
+    async fn readdir(&mut self, id: RequestId, handle: String) -> Result<Name, Self::Error> {
+        self.readdirs.fetch_add(1, Ordering::Relaxed);
+        if !self.listed.insert(handle.clone()) {
+            return Err(StatusCode::Eof);
+        }
+
+        let mut files = (0..ENTRIES)
+            .map(|i| File::dummy(entry(i)))
+            .collect::<Vec<_>>();
+        if handle == "/huge" {
+            files.insert(1, File::dummy("x".repeat(MAX_LEN as usize)));
+        }
+
+        Ok(Name { id, files })
+    }
+
+    async fn close(&mut self, id: RequestId, _handle: String) -> Result<Status, Self::Error> {
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+}
+
+async fn connect(handler: Listing) -> RawSftpSession {
+    let config = Config {
+        max_response_packet_len: Some(MAX_LEN),
+        ..Default::default()
+    };
+
+    let (client, stream) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(server::serve(stream, handler, config));
+    let raw = RawSftpSession::new(client);
+    raw.init().await.unwrap();
+    raw
+}
+
+/// Names of the next reply, [`None`] at the end of the directory
+async fn next(raw: &RawSftpSession, handle: &str) -> Option<Vec<String>> {
+    match raw.readdir(handle).await {
+        Ok(name) => Some(name.files.into_iter().map(|f| f.filename).collect()),
+        Err(Error::Status { status, .. }) if status.status_code == StatusCode::Eof => None,
+        Err(err) => panic!("{err}"),
+    }
+}
+
+#[tokio::test]
+async fn entries_continued_across_readdirs() {
+    let handler = Listing::default();
+    let raw = connect(handler.clone()).await;
+    let handle = raw.opendir("/dir").await.unwrap().handle;
+
+    let mut replies = Vec::new();
+    while let Some(names) = next(&raw, &handle).await {
+        replies.push(names);
+    }
+
+    let total = replies.iter().map(Vec::len).sum::<usize>();
+    assert_eq!(total, ENTRIES);
+    assert!(replies.len() > 5, "{} replies", replies.len());
+    assert!(replies.iter().all(|names| !names.is_empty()));
+
+    let names = replies.concat();
+    let expected = (0..ENTRIES).map(entry).collect::<Vec<_>>();
+    assert_eq!(names, expected);
+
+    // one call with all the entries and one for the end of the directory
+    assert_eq!(handler.readdirs.load(Ordering::Relaxed), 2);
+}
+
+#[tokio::test]
+async fn deferred_entries_dropped_on_close() {
+    let handler = Listing::default();
+    let raw = connect(handler.clone()).await;
+
+    let handle = raw.opendir("/dir").await.unwrap().handle;
+    let first = next(&raw, &handle).await.unwrap();
+    assert!(first.len() < ENTRIES);
+    raw.close(handle).await.unwrap();
+
+    // the same handle starts over from the handler
+    let handle = raw.opendir("/dir").await.unwrap().handle;
+    assert_eq!(next(&raw, &handle).await.unwrap(), first);
+    assert_eq!(handler.readdirs.load(Ordering::Relaxed), 2);
+}
+
+#[tokio::test]
+async fn deferred_entries_dropped_when_the_handle_is_reissued() {
+    let handler = Listing::default();
+    let raw = connect(handler.clone()).await;
+
+    let handle = raw.opendir("/dir").await.unwrap().handle;
+    let first = next(&raw, &handle).await.unwrap();
+
+    // not closed, but returned again by the handler for a new listing
+    let handle = raw.opendir("/dir").await.unwrap().handle;
+    assert_eq!(next(&raw, &handle).await.unwrap(), first);
+    assert_eq!(handler.readdirs.load(Ordering::Relaxed), 2);
+}
+
+#[tokio::test]
+async fn abandoned_handles_forgotten() {
+    let handler = Listing::default();
+    let raw = connect(handler.clone()).await;
+
+    // more directories than the server tracks, none is closed
+    let handles = (0..300).map(|i| format!("/dir{i}")).collect::<Vec<_>>();
+    for path in &handles {
+        let handle = raw.opendir(path.as_str()).await.unwrap().handle;
+        next(&raw, &handle).await.unwrap();
+    }
+    assert_eq!(handler.readdirs.load(Ordering::Relaxed), 300);
+
+    // the oldest deferred entries were dropped, the handler ends the listing
+    assert_eq!(next(&raw, &handles[0]).await, None);
+    assert_eq!(handler.readdirs.load(Ordering::Relaxed), 301);
+
+    // the latest are still served without the handler
+    let deferred = next(&raw, &handles[299]).await.unwrap();
+    assert!(deferred.len() < ENTRIES);
+    assert_eq!(handler.readdirs.load(Ordering::Relaxed), 301);
+}
+
+#[tokio::test]
+async fn entry_over_the_limit_dropped() {
+    let raw = connect(Listing::default()).await;
+    let handle = raw.opendir("/huge").await.unwrap().handle;
+
+    let mut names = Vec::new();
+    while let Some(batch) = next(&raw, &handle).await {
+        names.extend(batch);
+    }
+
+    // the other entries are listed rather than the reply failing
+    let expected = (0..ENTRIES).map(entry).collect::<Vec<_>>();
+    assert_eq!(names, expected);
+}
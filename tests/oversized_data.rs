@@ -0,0 +1,151 @@
+//! Replies to SSH_FXP_READ carrying more data than requested.
+
+use russh_sftp::{
+    client::{error::Error, RawSftpSession, SftpSession},
+    protocol::{
+        Attrs, Data, FileAttributes, Handle, OpenFlags, RequestId, Status, StatusCode, Version,
+    },
+    server::{self, Handler},
+};
+use std::collections::HashMap;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+/// Extra bytes sent with each read, 48 KB to a read of 32 KB
+const EXTRA: usize = 16 * 1024;
+
+/// Serves one file, replying to each read with `EXTRA` bytes more than requested
+#[derive(Clone)]
+struct Oversized {
+    data: Vec<u8>,
+}
+
+impl Oversized {
+    fn new(len: usize) -> Self {
+        Self {
+            data: (0..len).map(|i| (i % 253) as u8).collect(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Handler for Oversized {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn open(
+        &mut self,
+        id: RequestId,
+        _filename: String,
+        _pflags: OpenFlags,
+        _attrs: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        Ok(Handle {
+            id,
+            handle: "file".to_owned(),
+        })
+    }
+
+    async fn close(&mut self, id: RequestId, _handle: String) -> Result<Status, Self::Error> {
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+
+    async fn fstat(&mut self, id: RequestId, _handle: String) -> Result<Attrs, Self::Error> {
+        Ok(Attrs {
+            id,
+            attrs: FileAttributes {
+                size: Some(self.data.len() as u64),
+                ..FileAttributes::empty()
+            },
+        })
+    }
+
+    async fn read(
+        &mut self,
+        id: RequestId,
+        _handle: String,
+        offset: u64,
+        len: u32,
+    ) -> Result<Data, Self::Error> {
+        let start = offset as usize;
+        if start >= self.data.len() {
+            return Err(StatusCode::Eof);
+        }
+
+        let end = (start + len as usize + EXTRA).min(self.data.len());
+        Ok(Data {
+            id,
+            data: self.data[start..end].to_vec(),
+        })
+    }
+}
+
+#[tokio::test]
+async fn raw_read_lenient_by_default() {
+    let (client, stream) = tokio::io::duplex(64 * 1024);
+    server::run(stream, Oversized::new(100_000)).await;
+    let raw = RawSftpSession::new(client);
+    raw.init().await.unwrap();
+
+    let handle = raw
+        .open("/file", OpenFlags::READ, FileAttributes::empty())
+        .await
+        .unwrap()
+        .handle;
+    let data = raw.read(&handle, 0, 32 * 1024).await.unwrap();
+    assert_eq!(data.data.len(), 48 * 1024);
+
+    raw.set_strict_data_len(true).await;
+    match raw.read(&handle, 0, 32 * 1024).await {
+        Err(Error::UnexpectedBehavior(msg)) => assert!(msg.contains("49152"), "{msg}"),
+        result => panic!("{result:?}"),
+    }
+}
+
+#[tokio::test]
+async fn file_reads_the_excess_later() {
+    let server = Oversized::new(100_000);
+    let (sftp, _server) = SftpSession::pair(server.clone()).await.unwrap();
+
+    // buffers smaller than the replies, the excess is returned by the next reads
+    let mut file = sftp.open("/file").await.unwrap();
+    let mut received = Vec::new();
+    let mut buf = [0; 1000];
+    loop {
+        match file.read(&mut buf).await.unwrap() {
+            0 => break,
+            len => received.extend_from_slice(&buf[..len]),
+        }
+    }
+    assert!(received == server.data);
+
+    let mut file = sftp.open("/file").await.unwrap();
+    let mut received = Vec::new();
+    file.read_to_end(&mut received).await.unwrap();
+    assert!(received == server.data);
+}
+
+#[tokio::test]
+async fn seek_discards_the_excess() {
+    let server = Oversized::new(100_000);
+    let (sftp, _server) = SftpSession::pair(server.clone()).await.unwrap();
+
+    let mut file = sftp.open("/file").await.unwrap();
+    let mut buf = [0; 10];
+    file.read_exact(&mut buf).await.unwrap();
+    assert_eq!(buf, server.data[..10]);
+
+    file.seek(std::io::SeekFrom::Start(70_000)).await.unwrap();
+    file.read_exact(&mut buf).await.unwrap();
+    assert_eq!(buf, server.data[70_000..70_010]);
+}
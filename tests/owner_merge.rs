@@ -0,0 +1,163 @@
+//! The fields sent in pairs, `uid`/`gid` and `atime`/`mtime`, completed with the
+//! current values by `set_metadata` and sent as is by the raw session.
+
+use russh_sftp::{
+    client::SftpSession,
+    protocol::{Attrs, FileAttributes, Handle, OpenFlags, RequestId, Status, StatusCode, Version},
+    server::Handler,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// One file owned by 1000:1000, records the attributes set on it
+#[derive(Clone, Default)]
+struct Owners {
+    set: Arc<Mutex<Vec<FileAttributes>>>,
+}
+
+impl Owners {
+    fn current() -> FileAttributes {
+        FileAttributes {
+            size: Some(10),
+            uid: Some(1000),
+            gid: Some(1000),
+            permissions: Some(0o100644),
+            atime: Some(1_600_000_000.into()),
+            mtime: Some(1_700_000_000.into()),
+            ..FileAttributes::empty()
+        }
+    }
+
+    fn record(&self, id: RequestId, attrs: FileAttributes) -> Result<Status, StatusCode> {
+        self.set.lock().unwrap().push(attrs);
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+
+    fn last(&self) -> FileAttributes {
+        self.set.lock().unwrap().pop().unwrap()
+    }
+}
+
+#[async_trait::async_trait]
+impl Handler for Owners {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn open(
+        &mut self,
+        id: RequestId,
+        filename: String,
+        _pflags: OpenFlags,
+        _attrs: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        Ok(Handle {
+            id,
+            handle: filename,
+        })
+    }
+
+    async fn close(&mut self, id: RequestId, _handle: String) -> Result<Status, Self::Error> {
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+
+    async fn stat(&mut self, id: RequestId, _path: String) -> Result<Attrs, Self::Error> {
+        Ok(Attrs {
+            id,
+            attrs: Self::current(),
+        })
+    }
+
+    async fn fstat(&mut self, id: RequestId, handle: String) -> Result<Attrs, Self::Error> {
+        self.stat(id, handle).await
+    }
+
+    async fn setstat(
+        &mut self,
+        id: RequestId,
+        _path: String,
+        attrs: FileAttributes,
+    ) -> Result<Status, Self::Error> {
+        self.record(id, attrs)
+    }
+
+    async fn fsetstat(
+        &mut self,
+        id: RequestId,
+        _handle: String,
+        attrs: FileAttributes,
+    ) -> Result<Status, Self::Error> {
+        self.record(id, attrs)
+    }
+}
+
+fn owner(uid: Option<u32>, gid: Option<u32>) -> FileAttributes {
+    let mut attrs = FileAttributes::empty();
+    attrs.set_owner(uid, gid);
+    attrs
+}
+
+#[tokio::test]
+async fn missing_half_of_the_owner_merged() {
+    let handler = Owners::default();
+    let (sftp, _server) = SftpSession::pair(handler.clone()).await.unwrap();
+
+    // owner, sent owner
+    let table = [
+        ((None, Some(50)), (Some(1000), Some(50))),
+        ((Some(50), None), (Some(50), Some(1000))),
+        ((Some(1), Some(2)), (Some(1), Some(2))),
+    ];
+    for ((uid, gid), sent) in table {
+        sftp.set_metadata("/f", owner(uid, gid)).await.unwrap();
+        let set = handler.last();
+        assert_eq!((set.uid, set.gid), sent, "{uid:?} {gid:?}");
+        assert_eq!(set.size, None);
+        assert_eq!(set.permissions, None);
+    }
+
+    let file = sftp.open("/f").await.unwrap();
+    file.set_metadata(owner(None, Some(50))).await.unwrap();
+    let set = handler.last();
+    assert_eq!((set.uid, set.gid), (Some(1000), Some(50)));
+}
+
+#[tokio::test]
+async fn missing_time_merged() {
+    let handler = Owners::default();
+    let (sftp, _server) = SftpSession::pair(handler.clone()).await.unwrap();
+
+    let times = FileAttributes {
+        mtime: Some(5.into()),
+        ..FileAttributes::empty()
+    };
+    sftp.set_metadata("/f", times).await.unwrap();
+    let set = handler.last();
+    assert_eq!(set.atime, Some(1_600_000_000.into()));
+    assert_eq!(set.mtime, Some(5.into()));
+    assert_eq!((set.uid, set.gid), (None, None));
+}
+
+#[tokio::test]
+async fn raw_setstat_sends_the_pair_as_is() {
+    let handler = Owners::default();
+    let (sftp, _server) = SftpSession::pair(handler.clone()).await.unwrap();
+    let raw = sftp.open("/f").await.unwrap().session().clone();
+
+    // the missing half is sent as 0, the current owner is not looked up
+    raw.setstat("/f", owner(None, Some(50))).await.unwrap();
+    let set = handler.last();
+    assert_eq!((set.uid, set.gid), (Some(0), Some(50)));
+}
@@ -0,0 +1,55 @@
+//! Sessions connected to a handler over an in-memory stream.
+
+mod common;
+
+use common::Memory;
+use russh_sftp::{
+    client::{error::Error, SftpSession},
+    server::Config,
+};
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+
+#[tokio::test]
+async fn large_transfer() {
+    let (sftp, server) = SftpSession::pair(Memory::default()).await.unwrap();
+
+    // several times the buffer of each direction, with many requests in flight
+    let data = (0..8 * 1024 * 1024)
+        .map(|i| (i % 251) as u8)
+        .collect::<Vec<_>>();
+    let mut file = sftp.create("/big").await.unwrap();
+    file.write_all(&data).await.unwrap();
+    file.shutdown().await.unwrap();
+    assert_eq!(sftp.read("/big").await.unwrap(), data);
+
+    sftp.close().await.unwrap();
+    server.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn config_applied() {
+    let config = Config {
+        status_formatter: Some(Arc::new(|code, _| {
+            (format!("memory: {code}"), "en".to_owned())
+        })),
+        ..Default::default()
+    };
+    let (sftp, _server) = SftpSession::pair_with_config(Memory::default(), config)
+        .await
+        .unwrap();
+
+    let Err(Error::Status { status, .. }) = sftp.metadata("/missing").await else {
+        panic!("stat of a missing file succeeded");
+    };
+    assert_eq!(status.error_message, "memory: No such file");
+}
+
+#[tokio::test]
+async fn server_aborted() {
+    let (sftp, server) = SftpSession::pair(Memory::default()).await.unwrap();
+    server.abort();
+    assert!(server.await.unwrap_err().is_cancelled());
+
+    assert!(sftp.metadata("/").await.is_err());
+}
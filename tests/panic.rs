@@ -0,0 +1,90 @@
+//! Containment of the panics of handlers.
+
+use russh_sftp::{
+    client::{error::Error, SftpSession},
+    protocol::{Attrs, RequestId, StatusCode, Version},
+    server::{self, Config, EndReason, Handler, PanicAction},
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// Panics on SSH_FXP_STAT and records the end of the session
+#[derive(Clone, Default)]
+struct Panicking {
+    ended: Arc<Mutex<Option<EndReason>>>,
+}
+
+#[async_trait::async_trait]
+impl Handler for Panicking {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn stat(&mut self, _id: RequestId, path: String) -> Result<Attrs, Self::Error> {
+        panic!("stat of {path}");
+    }
+
+    async fn session_ended(&mut self, reason: EndReason) {
+        *self.ended.lock().unwrap() = Some(reason);
+    }
+}
+
+#[tokio::test]
+async fn panic_replies_failure_and_ends_session() {
+    let handler = Panicking::default();
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let serving = tokio::spawn(server::serve(server, handler.clone(), Config::default()));
+    let sftp = SftpSession::new(client).await.unwrap();
+
+    match sftp.metadata("/file").await {
+        Err(Error::Status { status, .. }) => assert_eq!(status.status_code, StatusCode::Failure),
+        result => panic!("stat replied {result:?}"),
+    }
+
+    // the session is ended with the stream after the reply
+    let result = serving.await.unwrap();
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "Unexpected behavior: handler panicked: stat of /file"
+    );
+    assert_eq!(
+        *handler.ended.lock().unwrap(),
+        Some(EndReason::Panicked("stat of /file".to_owned()))
+    );
+
+    match sftp.metadata("/file").await {
+        Err(Error::Status { status, .. }) => {
+            assert_eq!(status.status_code, StatusCode::ConnectionLost)
+        }
+        result => panic!("stat after the end replied {result:?}"),
+    }
+}
+
+#[tokio::test]
+async fn panic_propagated() {
+    let handler = Panicking::default();
+    let config = Config {
+        panic_action: PanicAction::Propagate,
+        ..Config::default()
+    };
+
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let serving = tokio::spawn(server::serve(server, handler.clone(), config));
+    let sftp = SftpSession::new(client).await.unwrap();
+
+    let _ = sftp.metadata("/file").await;
+    assert!(serving.await.unwrap_err().is_panic());
+    assert_eq!(*handler.ended.lock().unwrap(), None);
+}
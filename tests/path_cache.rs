@@ -0,0 +1,201 @@
+//! Canonicalization cache of the session, its keys, eviction and invalidation.
+
+use russh_sftp::{
+    client::SftpSession,
+    protocol::{File, Name, RequestId, Status, StatusCode, Version},
+    server::Handler,
+};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// Resolves the relative paths under `/home` and follows the links, moved by renames
+#[derive(Clone, Default)]
+struct Resolver {
+    links: Arc<Mutex<HashMap<String, String>>>,
+    realpaths: Arc<AtomicUsize>,
+}
+
+impl Resolver {
+    fn link(&self, path: &str, target: &str) {
+        let mut links = self.links.lock().unwrap();
+        links.insert(path.to_owned(), target.to_owned());
+    }
+
+    fn realpaths(&self) -> usize {
+        self.realpaths.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait::async_trait]
+impl Handler for Resolver {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn realpath(&mut self, id: RequestId, path: String) -> Result<Name, Self::Error> {
+        self.realpaths.fetch_add(1, Ordering::Relaxed);
+
+        let absolute = match path.as_str() {
+            "." | "" => "/home".to_owned(),
+            _ if path.starts_with('/') => path,
+            _ => format!("/home/{path}"),
+        };
+        let resolved = self.links.lock().unwrap().get(&absolute).cloned();
+
+        Ok(Name {
+            id,
+            files: vec![File::dummy(resolved.unwrap_or(absolute))],
+        })
+    }
+
+    async fn rename(
+        &mut self,
+        id: RequestId,
+        oldpath: String,
+        _newpath: String,
+    ) -> Result<Status, Self::Error> {
+        self.links
+            .lock()
+            .unwrap()
+            .remove(&format!("/home/{oldpath}"));
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+
+    async fn rmdir(&mut self, id: RequestId, _path: String) -> Result<Status, Self::Error> {
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+}
+
+async fn session(capacity: usize) -> (SftpSession, Resolver) {
+    let server = Resolver::default();
+    let (sftp, _server) = SftpSession::pair(server.clone()).await.unwrap();
+    sftp.enable_path_cache(capacity);
+    (sftp, server)
+}
+
+#[tokio::test]
+async fn repeated_paths_answered_locally() {
+    let (sftp, server) = session(8).await;
+
+    for _ in 0..3 {
+        assert_eq!(sftp.canonicalize("data").await.unwrap(), "/home/data");
+    }
+    assert_eq!(server.realpaths(), 1);
+
+    let stats = sftp.path_cache_stats().unwrap();
+    assert_eq!((stats.hits, stats.misses, stats.len), (2, 1, 1));
+}
+
+#[tokio::test]
+async fn equivalent_spellings_share_the_entry() {
+    let (sftp, server) = session(8).await;
+
+    sftp.canonicalize("a/b").await.unwrap();
+    for path in ["a/./b", "a//b/", "./a/b"] {
+        assert_eq!(sftp.canonicalize(path).await.unwrap(), "/home/a/b");
+    }
+    assert_eq!(server.realpaths(), 1);
+}
+
+#[tokio::test]
+async fn parent_components_always_sent() {
+    let (sftp, server) = session(8).await;
+    server.link("/home/link", "/srv/target");
+
+    assert_eq!(sftp.canonicalize("link").await.unwrap(), "/srv/target");
+    sftp.canonicalize("link/..").await.unwrap();
+    sftp.canonicalize("link/..").await.unwrap();
+    assert_eq!(server.realpaths(), 3);
+    assert_eq!(sftp.path_cache_stats().unwrap().len, 1);
+}
+
+#[tokio::test]
+async fn least_recently_used_evicted() {
+    let (sftp, server) = session(2).await;
+
+    sftp.canonicalize("a").await.unwrap();
+    sftp.canonicalize("b").await.unwrap();
+    sftp.canonicalize("a").await.unwrap();
+    sftp.canonicalize("c").await.unwrap();
+    assert_eq!(server.realpaths(), 3);
+
+    // b was the least recently used
+    sftp.canonicalize("a").await.unwrap();
+    assert_eq!(server.realpaths(), 3);
+    sftp.canonicalize("b").await.unwrap();
+    assert_eq!(server.realpaths(), 4);
+    assert_eq!(sftp.path_cache_stats().unwrap().len, 2);
+}
+
+#[tokio::test]
+async fn rename_through_parent_invalidates() {
+    let (sftp, server) = session(8).await;
+    server.link("/home/b", "/srv/old");
+
+    assert_eq!(sftp.canonicalize("b").await.unwrap(), "/srv/old");
+    sftp.rename("x/../b", "c").await.unwrap();
+    server.link("/home/b", "/srv/new");
+
+    assert_eq!(sftp.canonicalize("b").await.unwrap(), "/srv/new");
+    assert_eq!(server.realpaths(), 2);
+}
+
+#[tokio::test]
+async fn rename_of_a_spelling_invalidates_the_entry() {
+    let (sftp, server) = session(8).await;
+    server.link("/home/b", "/srv/old");
+
+    sftp.canonicalize("b/").await.unwrap();
+    sftp.canonicalize("/home/b").await.unwrap();
+    sftp.canonicalize(".").await.unwrap();
+    sftp.rename("./b", "c").await.unwrap();
+    assert_eq!(sftp.path_cache_stats().unwrap().len, 1);
+
+    sftp.canonicalize("b").await.unwrap();
+    sftp.canonicalize("/home/b").await.unwrap();
+    assert_eq!(server.realpaths(), 5);
+}
+
+#[tokio::test]
+async fn remove_dir_invalidates_below() {
+    let (sftp, server) = session(8).await;
+
+    sftp.canonicalize("/home/tree/a").await.unwrap();
+    sftp.canonicalize("/home/tree/a/b").await.unwrap();
+    sftp.canonicalize("/home/other").await.unwrap();
+    sftp.remove_dir("/home//tree/").await.unwrap();
+
+    assert_eq!(sftp.path_cache_stats().unwrap().len, 1);
+    sftp.canonicalize("/home/other").await.unwrap();
+    assert_eq!(server.realpaths(), 3);
+}
+
+#[tokio::test]
+async fn relative_resolved_against_cached_base() {
+    let (sftp, server) = session(8).await;
+
+    sftp.canonicalize("/home/base").await.unwrap();
+    let path = sftp.resolve_relative("/home/base", "./x//y").await.unwrap();
+    assert_eq!(path, "/home/base/x/y");
+    assert_eq!(server.realpaths(), 1);
+
+    // the parent component is left to the server
+    let path = sftp.resolve_relative("/home/base", "../z").await.unwrap();
+    assert_eq!(path, "/home/base/../z");
+    assert_eq!(server.realpaths(), 2);
+}
@@ -0,0 +1,156 @@
+//! Type predicates on paths and entries, and creation of directories with parents.
+
+mod common;
+
+use common::Memory;
+use russh_sftp::{
+    client::{error::Error, SftpSession},
+    protocol::{Attrs, FileAttributes, RequestId, StatusCode, Version},
+    server::Handler,
+};
+use std::{collections::HashMap, sync::atomic::Ordering};
+
+/// A directory, a read-only file and a symlink to the directory, `/denied` cannot be stat
+struct Links;
+
+fn attrs(id: RequestId, permissions: u32) -> Result<Attrs, StatusCode> {
+    Ok(Attrs {
+        id,
+        attrs: FileAttributes {
+            permissions: Some(permissions),
+            ..FileAttributes::empty()
+        },
+    })
+}
+
+#[async_trait::async_trait]
+impl Handler for Links {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn stat(&mut self, id: RequestId, path: String) -> Result<Attrs, Self::Error> {
+        match path.as_str() {
+            "/dir" | "/link" => attrs(id, 0o040755),
+            "/file" => attrs(id, 0o100444),
+            "/denied" => Err(StatusCode::PermissionDenied),
+            _ => Err(StatusCode::NoSuchFile),
+        }
+    }
+
+    async fn lstat(&mut self, id: RequestId, path: String) -> Result<Attrs, Self::Error> {
+        match path.as_str() {
+            "/link" => attrs(id, 0o120777),
+            _ => self.stat(id, path).await,
+        }
+    }
+}
+
+fn assert_status<T: std::fmt::Debug>(result: Result<T, Error>, code: StatusCode) {
+    match result {
+        Err(Error::Status { status, .. }) => assert_eq!(status.status_code, code),
+        result => panic!("{result:?}"),
+    }
+}
+
+#[tokio::test]
+async fn types_of_paths() {
+    let (sftp, _server) = SftpSession::pair(Links).await.unwrap();
+
+    // path, is_dir, is_file, is_symlink
+    let table = [
+        ("/dir", true, false, false),
+        ("/file", false, true, false),
+        ("/link", true, false, true),
+        ("/missing", false, false, false),
+    ];
+    for (path, dir, file, symlink) in table {
+        assert_eq!(sftp.is_dir(path).await.unwrap(), dir, "{path}");
+        assert_eq!(sftp.is_file(path).await.unwrap(), file, "{path}");
+        assert_eq!(sftp.is_symlink(path).await.unwrap(), symlink, "{path}");
+    }
+
+    assert!(sftp.metadata("/file").await.unwrap().is_readonly());
+    assert!(!sftp.metadata("/dir").await.unwrap().is_readonly());
+}
+
+#[tokio::test]
+async fn other_errors_propagated() {
+    let (sftp, _server) = SftpSession::pair(Links).await.unwrap();
+
+    assert_status(sftp.is_dir("/denied").await, StatusCode::PermissionDenied);
+    assert_status(sftp.is_file("/denied").await, StatusCode::PermissionDenied);
+    assert_status(
+        sftp.is_symlink("/denied").await,
+        StatusCode::PermissionDenied,
+    );
+}
+
+#[tokio::test]
+async fn types_of_entries() {
+    let (sftp, _server) = SftpSession::pair(Memory::default()).await.unwrap();
+    sftp.create_dir("/root").await.unwrap();
+    sftp.create_dir("/root/dir").await.unwrap();
+    sftp.create("/root/file").await.unwrap();
+
+    let mut entries = sftp
+        .read_dir("/root")
+        .await
+        .unwrap()
+        .map(|e| (e.file_name(), e.is_dir(), e.is_file()))
+        .collect::<Vec<_>>();
+    entries.sort();
+    assert_eq!(
+        entries,
+        [
+            ("dir".to_owned(), true, false),
+            ("file".to_owned(), false, true)
+        ]
+    );
+}
+
+#[tokio::test]
+async fn create_dir_all_creates_the_missing_parents() {
+    let handler = Memory::default();
+    let (sftp, _server) = SftpSession::pair(handler.clone()).await.unwrap();
+    sftp.create_dir("/a").await.unwrap();
+
+    sftp.create_dir_all("/a/b/./c/").await.unwrap();
+    for path in ["/a", "/a/b", "/a/b/c"] {
+        assert!(sftp.is_dir(path).await.unwrap(), "{path}");
+    }
+
+    // already there
+    sftp.create_dir_all("/a/b/c").await.unwrap();
+}
+
+#[tokio::test]
+async fn create_dir_all_tolerates_races() {
+    let handler = Memory::default();
+    let (sftp, _server) = SftpSession::pair(handler.clone()).await.unwrap();
+
+    // both directories are created by someone else just before each mkdir
+    handler.collisions.store(2, Ordering::Relaxed);
+    sftp.create_dir_all("/x/y").await.unwrap();
+    assert_eq!(handler.collisions.load(Ordering::Relaxed), 0);
+    assert!(sftp.is_dir("/x/y").await.unwrap());
+}
+
+#[tokio::test]
+async fn create_dir_all_fails_on_a_file() {
+    let (sftp, _server) = SftpSession::pair(Memory::default()).await.unwrap();
+    sftp.create("/file").await.unwrap();
+
+    assert_status(sftp.create_dir_all("/file/sub").await, StatusCode::Failure);
+    assert!(!sftp.is_dir("/file/sub").await.unwrap());
+}
@@ -0,0 +1,136 @@
+//! Progress reported by the wrapper of File, matching the bytes transferred.
+
+mod common;
+
+use common::Memory;
+use russh_sftp::client::{
+    fs::{Direction, ProgressEvent},
+    SftpSession,
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// Not a multiple of any chunk length, so the last read hits the end of file mid-chunk
+const LEN: usize = 3 * 65536 + 1234;
+
+fn data() -> Vec<u8> {
+    (0..LEN).map(|i| (i % 251) as u8).collect()
+}
+
+async fn session(data: Option<Vec<u8>>) -> (SftpSession, Memory) {
+    let memory = Memory::default();
+    if let Some(data) = data {
+        let mut files = memory.files.lock().unwrap();
+        files.insert("/file".to_owned(), data);
+    }
+
+    let (sftp, _server) = SftpSession::pair(memory.clone()).await.unwrap();
+    (sftp, memory)
+}
+
+type Events = Arc<Mutex<Vec<ProgressEvent>>>;
+
+fn recorder() -> (Events, impl FnMut(ProgressEvent) + Unpin) {
+    let events = Events::default();
+    let recorded = events.clone();
+    (events, move |event| recorded.lock().unwrap().push(event))
+}
+
+#[tokio::test]
+async fn reads_counted_up_to_the_end() {
+    let (sftp, _memory) = session(Some(data())).await;
+    let (events, callback) = recorder();
+
+    let file = sftp.open("/file").await.unwrap();
+    let mut file = file.with_progress(callback).interval(Duration::ZERO);
+    let mut received = Vec::new();
+    tokio::io::copy(&mut file, &mut received).await.unwrap();
+
+    assert_eq!(received, data());
+    assert_eq!(file.bytes_read(), LEN as u64);
+
+    let events = events.lock().unwrap();
+    assert!(events.len() > 1, "{events:?}");
+    assert!(events.iter().all(|e| e.direction == Direction::Read));
+    assert!(events
+        .windows(2)
+        .all(|w| w[0].bytes_transferred <= w[1].bytes_transferred));
+
+    let last = events.last().unwrap();
+    assert_eq!(
+        (last.bytes_transferred, last.offset),
+        (LEN as u64, LEN as u64)
+    );
+}
+
+#[tokio::test]
+async fn read_to_end_in_small_buffers() {
+    let (sftp, _memory) = session(Some(data())).await;
+    let (events, callback) = recorder();
+
+    let file = sftp.open("/file").await.unwrap();
+    let mut file = file.with_progress(callback).interval(Duration::ZERO);
+    let mut buf = [0; 1000];
+    let mut total = 0;
+    loop {
+        match file.read(&mut buf).await.unwrap() {
+            0 => break,
+            len => total += len,
+        }
+    }
+
+    assert_eq!(total, LEN);
+    let events = events.lock().unwrap();
+    assert_eq!(events.last().unwrap().bytes_transferred, LEN as u64);
+    // the reads past the end add nothing
+    let sum: u64 = events
+        .windows(2)
+        .map(|w| w[1].bytes_transferred - w[0].bytes_transferred)
+        .sum();
+    assert_eq!(sum + events[0].bytes_transferred, LEN as u64);
+}
+
+#[tokio::test]
+async fn writes_counted_through_copy() {
+    let (sftp, memory) = session(None).await;
+    let (events, callback) = recorder();
+
+    let file = sftp.create("/file").await.unwrap();
+    let mut file = file.with_progress(callback).interval(Duration::ZERO);
+    tokio::io::copy(&mut data().as_slice(), &mut file)
+        .await
+        .unwrap();
+    file.shutdown().await.unwrap();
+
+    assert_eq!(file.bytes_written(), LEN as u64);
+    assert_eq!(memory.files.lock().unwrap()["/file"], data());
+
+    let events = events.lock().unwrap();
+    assert!(events.iter().all(|e| e.direction == Direction::Write));
+    let last = events.last().unwrap();
+    assert_eq!(
+        (last.bytes_transferred, last.offset),
+        (LEN as u64, LEN as u64)
+    );
+}
+
+#[tokio::test]
+async fn callbacks_throttled() {
+    let (sftp, _memory) = session(Some(data())).await;
+    let (events, callback) = recorder();
+
+    let file = sftp.open("/file").await.unwrap();
+    let mut file = file
+        .with_progress(callback)
+        .interval(Duration::from_secs(3600));
+    let mut buf = [0; 1000];
+    while file.read(&mut buf).await.unwrap() > 0 {}
+
+    // the first chunk and the end of file, which is always reported
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 2, "{events:?}");
+    assert_eq!(events[1].bytes_transferred, LEN as u64);
+}
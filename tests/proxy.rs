@@ -0,0 +1,161 @@
+//! Sessions spliced by a proxy that exchanged SSH_FXP_INIT itself, with the frames
+//! forwarded unchanged between the client and the backend.
+
+mod common;
+
+use bytes::{Bytes, BytesMut};
+use common::Memory;
+use russh_sftp::{
+    client::RawSftpSession,
+    framing::{read_packet, write_packet},
+    protocol::{FileAttributes, Init, OpenFlags, Packet, Version},
+    server::{self, Config},
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, DuplexStream};
+
+const SSH_FXP_STAT: u8 = 17;
+const SSH_FXP_ATTRS: u8 = 105;
+
+/// Type and request id of the forwarded frames, with `true` for those of the client
+type Forwarded = Arc<Mutex<Vec<(bool, u8, Option<u32>)>>>;
+
+/// Forwards the frames read from `from` to `to` without decoding them
+async fn pipe<R, W>(mut from: R, mut to: W, from_client: bool, forwarded: Forwarded)
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buf = BytesMut::new();
+    while let Ok(payload) = read_packet(&mut from, &mut buf).await {
+        let (r#type, id) = Packet::peek(&payload).unwrap();
+        forwarded.lock().unwrap().push((from_client, r#type, id));
+
+        if to.write_all(&Packet::frame(&payload)).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Forwards the frames between the two streams in both directions
+fn forward(client: DuplexStream, backend: DuplexStream) -> Forwarded {
+    let forwarded = Forwarded::default();
+    let (client_read, client_write) = tokio::io::split(client);
+    let (backend_read, backend_write) = tokio::io::split(backend);
+
+    tokio::spawn(pipe(client_read, backend_write, true, forwarded.clone()));
+    tokio::spawn(pipe(backend_read, client_write, false, forwarded.clone()));
+    forwarded
+}
+
+fn memory() -> Memory {
+    let memory = Memory::default();
+    memory
+        .files
+        .lock()
+        .unwrap()
+        .insert("/file".to_owned(), vec![0; 42]);
+    memory
+}
+
+#[tokio::test]
+async fn stat_round_trips_through_the_proxy() {
+    let (client, mut front) = tokio::io::duplex(64 * 1024);
+    let raw = RawSftpSession::new(client);
+    let init = tokio::spawn(async move { raw.init().await.map(|version| (raw, version)) });
+
+    // the proxy answers the SSH_FXP_INIT of the client itself
+    let mut buf = BytesMut::new();
+    let mut payload = read_packet(&mut front, &mut buf).await.unwrap();
+    let Packet::Init(client_init) = Packet::try_from(&mut payload).unwrap() else {
+        panic!("the client did not start with SSH_FXP_INIT");
+    };
+    let version = Bytes::try_from(Packet::from(Version::new())).unwrap();
+    front.write_all(&version).await.unwrap();
+    let (raw, version) = init.await.unwrap().unwrap();
+    assert_eq!(version.version, 3);
+
+    // and hands the SSH_FXP_INIT over to a backend which does not wait for one
+    let (proxy, backend) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(server::serve_initialized(
+        backend,
+        memory(),
+        Config::default(),
+        client_init,
+    ));
+    let forwarded = forward(front, proxy);
+
+    let attrs = raw.stat("/file").await.unwrap();
+    assert_eq!(attrs.attrs.size, Some(42));
+
+    let id = Some(attrs.id.0);
+    assert_eq!(
+        *forwarded.lock().unwrap(),
+        [(true, SSH_FXP_STAT, id), (false, SSH_FXP_ATTRS, id)]
+    );
+}
+
+#[tokio::test]
+async fn client_attached_to_a_negotiated_backend() {
+    let (mut proxy, backend) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(server::serve(backend, memory(), Config::default()));
+
+    // another component negotiates the version of the backend
+    let init = Init {
+        version: 3,
+        extensions: HashMap::new(),
+    };
+    let init = Bytes::try_from(Packet::from(init)).unwrap();
+    write_packet(&mut proxy, &init[4..]).await.unwrap();
+    let mut payload = read_packet(&mut proxy, &mut BytesMut::new()).await.unwrap();
+    let Packet::Version(version) = Packet::try_from(&mut payload).unwrap() else {
+        panic!("the backend did not reply with SSH_FXP_VERSION");
+    };
+
+    // the session starts with requests, without a second SSH_FXP_INIT
+    let (client, front) = tokio::io::duplex(64 * 1024);
+    let forwarded = forward(front, proxy);
+    let raw = RawSftpSession::attach(client, version.version, version.extensions);
+
+    let attrs = raw.stat("/file").await.unwrap();
+    assert_eq!(attrs.attrs.size, Some(42));
+
+    let id = Some(attrs.id.0);
+    assert_eq!(
+        *forwarded.lock().unwrap(),
+        [(true, SSH_FXP_STAT, id), (false, SSH_FXP_ATTRS, id)]
+    );
+}
+
+#[tokio::test]
+async fn both_ends_negotiated_elsewhere() {
+    let client_init = Init {
+        version: 3,
+        extensions: HashMap::new(),
+    };
+    let (proxy, backend) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(server::serve_initialized(
+        backend,
+        memory(),
+        Config::default(),
+        client_init,
+    ));
+
+    let (client, front) = tokio::io::duplex(64 * 1024);
+    let forwarded = forward(front, proxy);
+    let raw = RawSftpSession::attach(client, 3, HashMap::new());
+
+    let handle = raw.open("/file", OpenFlags::READ, FileAttributes::empty());
+    let handle = handle.await.unwrap().handle;
+    let data = raw.read(handle.as_str(), 40, 10).await.unwrap();
+    assert_eq!(data.data, [0; 2]);
+    raw.close(handle).await.unwrap();
+
+    // no SSH_FXP_INIT nor SSH_FXP_VERSION went through the proxy
+    let forwarded = forwarded.lock().unwrap();
+    assert_eq!(forwarded.len(), 6);
+    assert!(forwarded.iter().all(|(_, _, id)| id.is_some()));
+}
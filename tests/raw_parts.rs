@@ -0,0 +1,181 @@
+//! Raw requests on the handle of a high-level [`File`], and handles leaked by a
+//! file and wrapped again.
+
+use russh_sftp::{
+    client::{fs::File, SftpSession},
+    de,
+    protocol::{
+        ExtendedReply, FileAttributes, Handle, OpenFlags, Packet, RequestId, Status, StatusCode,
+        Version,
+    },
+    server::Handler,
+};
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+const HANDLE_LEN: &str = "handle-len@example.com";
+
+#[derive(Default)]
+struct Handles {
+    open: BTreeSet<String>,
+    closed: Vec<String>,
+}
+
+/// Serves `handle-len@example.com`, a vendor extension on handles replying the
+/// length of an open handle
+#[derive(Clone, Default)]
+struct Vendor(Arc<Mutex<Handles>>);
+
+impl Vendor {
+    fn closed(&self) -> Vec<String> {
+        self.0.lock().unwrap().closed.clone()
+    }
+
+    /// Waits for the close sent in the background on drop
+    async fn wait_closed(&self, count: usize) -> Vec<String> {
+        for _ in 0..100 {
+            if self.closed().len() >= count {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        self.closed()
+    }
+}
+
+#[async_trait::async_trait]
+impl Handler for Vendor {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn open(
+        &mut self,
+        id: RequestId,
+        filename: String,
+        _pflags: OpenFlags,
+        _attrs: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        let handle = format!("h{filename}");
+        self.0.lock().unwrap().open.insert(handle.clone());
+        Ok(Handle { id, handle })
+    }
+
+    async fn close(&mut self, id: RequestId, handle: String) -> Result<Status, Self::Error> {
+        let mut handles = self.0.lock().unwrap();
+        if !handles.open.remove(&handle) {
+            return Err(StatusCode::Failure);
+        }
+
+        handles.closed.push(handle);
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+
+    async fn extended(
+        &mut self,
+        id: RequestId,
+        request: String,
+        data: Vec<u8>,
+    ) -> Result<Packet, Self::Error> {
+        if request != HANDLE_LEN {
+            return Err(StatusCode::OpUnsupported);
+        }
+
+        let handle: String =
+            de::from_bytes(&mut data.into()).map_err(|_| StatusCode::BadMessage)?;
+        if !self.0.lock().unwrap().open.contains(&handle) {
+            return Err(StatusCode::Failure);
+        }
+
+        Ok(Packet::ExtendedReply(ExtendedReply {
+            id,
+            data: (handle.len() as u64).to_be_bytes().to_vec(),
+        }))
+    }
+}
+
+/// Sends `handle-len@example.com` on the handle of the file through its raw session
+async fn handle_len(file: &File) -> Result<u64, StatusCode> {
+    let data = russh_sftp::ser::to_bytes(&file.handle()).unwrap().to_vec();
+    match file.session().extended(HANDLE_LEN, data).await.unwrap() {
+        Packet::ExtendedReply(reply) => Ok(u64::from_be_bytes(reply.data.try_into().unwrap())),
+        Packet::Status(status) => Err(status.status_code),
+        packet => panic!("unexpected {}", packet.name()),
+    }
+}
+
+#[tokio::test]
+async fn raw_extension_on_a_high_level_handle() {
+    let handler = Vendor::default();
+    let (sftp, _server) = SftpSession::pair(handler.clone()).await.unwrap();
+
+    let file = sftp.open("/data.bin").await.unwrap();
+    assert_eq!(file.handle(), "h/data.bin");
+    assert_eq!(handle_len(&file).await, Ok("h/data.bin".len() as u64));
+
+    // the raw session is the one of the high-level session
+    let other = sftp.open("/other").await.unwrap();
+    assert!(Arc::ptr_eq(file.session(), other.session()));
+
+    // the file still owns the handle and closes it
+    drop(file);
+    assert_eq!(handler.wait_closed(1).await, ["h/data.bin"]);
+}
+
+#[tokio::test]
+async fn leaked_handle_wrapped_again() {
+    let handler = Vendor::default();
+    let (sftp, _server) = SftpSession::pair(handler.clone()).await.unwrap();
+
+    let file = sftp.open("/data.bin").await.unwrap();
+    let session = file.session().clone();
+    let handle = file.leak();
+
+    // not closed by the drop of the leaked file
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(handler.closed().is_empty());
+
+    let file = File::from_raw_parts(session, handle, &[]);
+    assert_eq!(handle_len(&file).await, Ok("h/data.bin".len() as u64));
+
+    drop(file);
+    assert_eq!(handler.wait_closed(1).await, ["h/data.bin"]);
+}
+
+#[tokio::test]
+async fn raw_handle_wrapped() {
+    let handler = Vendor::default();
+    let (sftp, _server) = SftpSession::pair(handler.clone()).await.unwrap();
+    let raw = sftp.open("/first").await.unwrap().session().clone();
+
+    let handle = raw
+        .open("/raw", OpenFlags::READ, FileAttributes::empty())
+        .await
+        .unwrap()
+        .handle;
+    let file = File::from_raw_parts(raw.clone(), handle, &[]);
+    assert_eq!(file.handle(), "h/raw");
+
+    // the closed handle is no longer usable by raw requests either
+    let data = russh_sftp::ser::to_bytes(&"h/raw").unwrap().to_vec();
+    drop(file);
+    handler.wait_closed(2).await;
+    match raw.extended(HANDLE_LEN, data).await.unwrap() {
+        Packet::Status(status) => assert_eq!(status.status_code, StatusCode::Failure),
+        packet => panic!("unexpected {}", packet.name()),
+    }
+}
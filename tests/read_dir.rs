@@ -0,0 +1,138 @@
+//! Order of the entries of a directory listed in several batches, and its `.`
+//! and `..` entries.
+
+use russh_sftp::{
+    client::SftpSession,
+    protocol::{File, FileAttributes, Handle, Name, RequestId, Status, StatusCode, Version},
+    server::{self, Handler},
+};
+use std::collections::{HashMap, VecDeque};
+
+/// Attributes of the `.` and `..` entries, told apart by their permissions
+fn attrs(name: &str) -> FileAttributes {
+    let permissions = match name {
+        "." => 0o040700,
+        ".." => 0o040755,
+        _ => return FileAttributes::empty(),
+    };
+
+    FileAttributes {
+        permissions: Some(permissions),
+        ..FileAttributes::empty()
+    }
+}
+
+/// Lists a directory in fixed batches
+struct Batches {
+    batches: VecDeque<Vec<&'static str>>,
+}
+
+#[async_trait::async_trait]
+impl Handler for Batches {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn opendir(&mut self, id: RequestId, path: String) -> Result<Handle, Self::Error> {
+        Ok(Handle { id, handle: path })
+    }
+
+    async fn readdir(&mut self, id: RequestId, _handle: String) -> Result<Name, Self::Error> {
+        let batch = self.batches.pop_front().ok_or(StatusCode::Eof)?;
+        Ok(Name {
+            id,
+            files: batch
+                .into_iter()
+                .map(|name| File::new(name, attrs(name)))
+                .collect(),
+        })
+    }
+
+    async fn close(&mut self, id: RequestId, _handle: String) -> Result<Status, Self::Error> {
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+}
+
+async fn connect() -> SftpSession {
+    let handler = Batches {
+        batches: VecDeque::from([vec![".", "c", "é"], vec!["a", "B", ".."], vec!["e", "b"]]),
+    };
+
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    server::run(server, handler).await;
+    SftpSession::new(client).await.unwrap()
+}
+
+#[tokio::test]
+async fn server_order() {
+    let sftp = connect().await;
+    let names = sftp
+        .read_dir("/dir")
+        .await
+        .unwrap()
+        .include_dot_entries(true)
+        .map(|entry| entry.file_name())
+        .collect::<Vec<_>>();
+
+    assert_eq!(names, [".", "c", "é", "a", "B", "..", "e", "b"]);
+}
+
+#[tokio::test]
+async fn sorted_by_name() {
+    let sftp = connect().await;
+    let names = sftp
+        .read_dir("/dir")
+        .await
+        .unwrap()
+        .sorted_by_name()
+        .map(|entry| entry.file_name())
+        .collect::<Vec<_>>();
+
+    // byte-wise, uppercase before lowercase and non-ASCII last
+    assert_eq!(names, ["B", "a", "b", "c", "e", "é"]);
+}
+
+#[tokio::test]
+async fn dot_entries() {
+    let sftp = connect().await;
+    let dir = sftp.read_dir("/dir").await.unwrap();
+
+    let current = dir.self_entry().unwrap();
+    assert_eq!(current.file_name(), ".");
+    assert_eq!(current.metadata().permissions, Some(0o040700));
+    let parent = dir.parent_entry().unwrap();
+    assert_eq!(parent.file_name(), "..");
+    assert_eq!(parent.metadata().permissions, Some(0o040755));
+
+    // skipped by default
+    let names = dir.map(|entry| entry.file_name()).collect::<Vec<_>>();
+    assert_eq!(names, ["c", "é", "a", "B", "e", "b"]);
+
+    // the batches of the handler are listed once per session
+    let dots = connect()
+        .await
+        .read_dir("/dir")
+        .await
+        .unwrap()
+        .include_dot_entries(true)
+        .filter(|entry| entry.file_name().starts_with('.'))
+        .map(|entry| (entry.file_name(), entry.metadata().permissions))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        dots,
+        [
+            (".".to_owned(), Some(0o040700)),
+            ("..".to_owned(), Some(0o040755))
+        ]
+    );
+}
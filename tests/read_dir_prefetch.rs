@@ -0,0 +1,149 @@
+//! Prefetching of the batches of a streamed directory listing.
+
+use futures::StreamExt;
+use russh_sftp::{
+    client::SftpSession,
+    protocol::{File, FileAttributes, Handle, Name, RequestId, Status, StatusCode, Version},
+    server::{self, Handler},
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time::{self, Instant};
+
+const LATENCY: Duration = Duration::from_millis(50);
+const BATCHES: usize = 5;
+
+/// Lists `BATCHES` batches of two entries, each taking `LATENCY`, and logs the requests
+#[derive(Clone, Default)]
+struct Slow {
+    log: Arc<Mutex<Vec<&'static str>>>,
+    sent: usize,
+}
+
+#[async_trait::async_trait]
+impl Handler for Slow {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn opendir(&mut self, id: RequestId, path: String) -> Result<Handle, Self::Error> {
+        Ok(Handle { id, handle: path })
+    }
+
+    async fn readdir(&mut self, id: RequestId, _handle: String) -> Result<Name, Self::Error> {
+        self.log.lock().unwrap().push("readdir");
+        time::sleep(LATENCY).await;
+
+        if self.sent == BATCHES {
+            return Err(StatusCode::Eof);
+        }
+
+        self.sent += 1;
+        let mut names = vec![format!("a{}", self.sent), format!("b{}", self.sent)];
+        if self.sent == 1 {
+            names.insert(0, ".".to_owned());
+        }
+
+        Ok(Name {
+            id,
+            files: names
+                .into_iter()
+                .map(|name| File::new(name, FileAttributes::empty()))
+                .collect(),
+        })
+    }
+
+    async fn close(&mut self, id: RequestId, _handle: String) -> Result<Status, Self::Error> {
+        self.log.lock().unwrap().push("close");
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+}
+
+async fn connect(handler: Slow) -> SftpSession {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    server::run(server, handler).await;
+    SftpSession::new(client).await.unwrap()
+}
+
+/// Lists the directory while spending `LATENCY` on each batch, returns the names and the time
+async fn consume(sftp: &SftpSession, prefetch: bool) -> (Vec<String>, Duration) {
+    let start = Instant::now();
+    let mut stream = sftp
+        .read_dir_stream("/dir")
+        .await
+        .unwrap()
+        .prefetch(prefetch);
+
+    let mut names = vec![];
+    while let Some(entry) = stream.next().await {
+        let name = entry.unwrap().file_name();
+        if name.starts_with('a') {
+            time::sleep(LATENCY).await;
+        }
+        names.push(name);
+    }
+
+    (names, start.elapsed())
+}
+
+#[tokio::test(start_paused = true)]
+async fn overlaps_consumer_and_server() {
+    let handler = Slow::default();
+    let sftp = connect(handler.clone()).await;
+
+    let (names, prefetched) = consume(&sftp, true).await;
+    assert_eq!(names.len(), BATCHES * 2);
+    assert_eq!(names[..3], ["a1", "b1", "a2"]);
+
+    // one request ahead and none after the end of the listing
+    let log = handler.log.lock().unwrap().clone();
+    let readdirs = log.iter().filter(|request| **request == "readdir").count();
+    assert_eq!(readdirs, BATCHES + 1);
+
+    let handler = Slow::default();
+    let sftp = connect(handler.clone()).await;
+    let (_, sequential) = consume(&sftp, false).await;
+
+    // about the slower side per batch instead of the sum of both
+    let batch = LATENCY.as_millis();
+    let (prefetched, sequential) = (prefetched.as_millis(), sequential.as_millis());
+    assert!(
+        prefetched < batch * (BATCHES as u128 + 2),
+        "{prefetched} ms"
+    );
+    assert!(
+        sequential >= batch * (2 * BATCHES as u128 + 1),
+        "{sequential} ms"
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn closes_after_outstanding_reply() {
+    let handler = Slow::default();
+    let sftp = connect(handler.clone()).await;
+
+    let mut stream = sftp.read_dir_stream("/dir").await.unwrap();
+    assert_eq!(stream.next().await.unwrap().unwrap().file_name(), "a1");
+
+    // the second batch is requested but not answered yet
+    drop(stream);
+    time::sleep(LATENCY * 4).await;
+
+    assert_eq!(
+        *handler.log.lock().unwrap(),
+        ["readdir", "readdir", "close"]
+    );
+}
@@ -0,0 +1,199 @@
+//! Downloads checked against the size of the remote file, with servers that pad
+//! the last chunk, files that change during the transfer and sizes that lie.
+
+mod common;
+
+use common::Memory;
+use russh_sftp::{
+    client::{error::Error, rawsession::SftpResult, SftpSession, TransferJob, TransferScheduler},
+    protocol::{
+        Attrs, Data, FileAttributes, Handle, OpenFlags, RequestId, Status, StatusCode, Version,
+    },
+    server::Handler,
+};
+use std::collections::HashMap;
+
+const SIZE: usize = 600_000;
+
+#[derive(Debug, Clone, Copy)]
+enum Fault {
+    None,
+    /// Appends zeros to the reads reaching the end of file
+    Padded(usize),
+    /// Appends bytes to the file once the first read is served
+    Grows(usize),
+    /// Ends the file at the offset while reporting the whole size
+    Truncated(usize),
+}
+
+/// Serves `/file` of [`Memory`] with its reads altered by the fault
+struct Remote {
+    memory: Memory,
+    fault: Fault,
+    served: bool,
+}
+
+#[async_trait::async_trait]
+impl Handler for Remote {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        version: u32,
+        extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        self.memory.init(version, extensions).await
+    }
+
+    async fn open(
+        &mut self,
+        id: RequestId,
+        filename: String,
+        pflags: OpenFlags,
+        attrs: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        self.memory.open(id, filename, pflags, attrs).await
+    }
+
+    async fn fstat(&mut self, id: RequestId, handle: String) -> Result<Attrs, Self::Error> {
+        self.memory.fstat(id, handle).await
+    }
+
+    async fn read(
+        &mut self,
+        id: RequestId,
+        handle: String,
+        offset: u64,
+        len: u32,
+    ) -> Result<Data, Self::Error> {
+        let len = match self.fault {
+            Fault::Truncated(end) if offset >= end as u64 => return Err(StatusCode::Eof),
+            Fault::Truncated(end) => len.min((end as u64 - offset) as u32),
+            _ => len,
+        };
+
+        let mut data = self.memory.read(id, handle.clone(), offset, len).await?;
+        let mut files = self.memory.files.lock().unwrap();
+        let file = files.get_mut(&handle).unwrap();
+        match self.fault {
+            Fault::Padded(padding) if offset as usize + data.data.len() == file.len() => {
+                data.data.resize(data.data.len() + padding, 0);
+            }
+            Fault::Grows(growth) if !self.served => {
+                let grown = file.len() + growth;
+                file.resize(grown, 1);
+            }
+            _ => (),
+        }
+
+        self.served = true;
+        Ok(data)
+    }
+
+    async fn close(&mut self, id: RequestId, handle: String) -> Result<Status, Self::Error> {
+        self.memory.close(id, handle).await
+    }
+}
+
+fn contents() -> Vec<u8> {
+    (0..SIZE).map(|i| (i * 7 % 253) as u8).collect()
+}
+
+async fn connect(fault: Fault) -> SftpSession {
+    let memory = Memory::default();
+    memory
+        .files
+        .lock()
+        .unwrap()
+        .insert("/file".to_owned(), contents());
+
+    let handler = Remote {
+        memory,
+        fault,
+        served: false,
+    };
+    let (sftp, _server) = SftpSession::pair(handler).await.unwrap();
+    sftp
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Download {
+    CopyToLocal,
+    Writer,
+    Scheduler,
+}
+
+/// Downloads the file with `how` and returns the bytes stored locally
+async fn download(fault: Fault, how: Download) -> SftpResult<Vec<u8>> {
+    let sftp = connect(fault).await;
+    let local = std::env::temp_dir().join(format!(
+        "russh-sftp-read-size-{}-{fault:?}-{how:?}",
+        std::process::id()
+    ));
+
+    let result = match how {
+        Download::CopyToLocal => {
+            let mut file = sftp.open("/file").await?;
+            let mut local = tokio::fs::File::create(&local).await?;
+            file.copy_to_local(&mut local).await
+        }
+        Download::Writer => {
+            let mut written = Vec::new();
+            let result = sftp.read_to_writer("/file", &mut written).await;
+            std::fs::write(&local, written)?;
+            result
+        }
+        Download::Scheduler => {
+            let mut scheduler = TransferScheduler::new(&sftp);
+            scheduler.add(TransferJob::download("/file", &local));
+            scheduler.run().await.remove(0)
+        }
+    };
+
+    let data = std::fs::read(&local)?;
+    std::fs::remove_file(&local)?;
+    result.map(|copied| {
+        assert_eq!(copied, data.len() as u64);
+        data
+    })
+}
+
+const DOWNLOADS: [Download; 3] = [Download::CopyToLocal, Download::Writer, Download::Scheduler];
+
+#[tokio::test]
+async fn padding_dropped() {
+    for fault in [Fault::None, Fault::Padded(1), Fault::Padded(4096)] {
+        for how in DOWNLOADS {
+            let data = download(fault, how).await.unwrap();
+            assert!(data == contents(), "{fault:?} {how:?}");
+        }
+    }
+}
+
+#[tokio::test]
+async fn growth_reported() {
+    for how in DOWNLOADS {
+        match download(Fault::Grows(1000), how).await {
+            Err(Error::SizeChanged { expected, actual }) => {
+                assert_eq!((expected, actual), (SIZE as u64, Some(SIZE as u64 + 1000)));
+            }
+            result => panic!("{how:?}: {:?}", result.map(|data| data.len())),
+        }
+    }
+}
+
+#[tokio::test]
+async fn missing_bytes_reported() {
+    for how in DOWNLOADS {
+        match download(Fault::Truncated(SIZE - 5000), how).await {
+            Err(Error::LengthMismatch { expected, received }) => {
+                assert_eq!((expected, received), (SIZE as u64, SIZE as u64 - 5000));
+            }
+            result => panic!("{how:?}: {:?}", result.map(|data| data.len())),
+        }
+    }
+}
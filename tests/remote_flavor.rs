@@ -0,0 +1,224 @@
+//! Paths of every operation as received by the server, normalized according to
+//! the remote flavor of the session.
+
+use russh_sftp::{
+    client::{RemoteFlavor, SftpSession},
+    protocol::{
+        Attrs, File, FileAttributes, Handle, Name, OpenFlags, RequestId, Status, StatusCode,
+        Version,
+    },
+    server::Handler,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// Paths given to the session, with those expected by a Windows server.
+/// A Unix server receives them as they are
+const FIXTURES: [(&str, &str); 6] = [
+    ("/home/user/file", "/home/user/file"),
+    (r"C:\Users\foo", "/C:/Users/foo"),
+    ("c:/dir\\sub", "/c:/dir/sub"),
+    ("/C:", "/C:/"),
+    ("//C:/a//b", "/C:/a/b"),
+    ("relative\\name", "relative/name"),
+];
+
+/// Records the paths of the requests and answers them successfully
+#[derive(Clone, Default)]
+struct Recording {
+    paths: Arc<Mutex<Vec<String>>>,
+}
+
+impl Recording {
+    fn record(&self, path: String) {
+        self.paths.lock().unwrap().push(path);
+    }
+
+    fn take(&self) -> Vec<String> {
+        std::mem::take(&mut self.paths.lock().unwrap())
+    }
+
+    fn ok(&self, id: RequestId, paths: &[&str]) -> Result<Status, StatusCode> {
+        paths.iter().for_each(|path| self.record(path.to_string()));
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+
+    fn attrs(&self, id: RequestId, path: String) -> Result<Attrs, StatusCode> {
+        self.record(path);
+        let attrs = FileAttributes {
+            permissions: Some(0o040755),
+            ..FileAttributes::empty()
+        };
+        Ok(Attrs { id, attrs })
+    }
+}
+
+#[async_trait::async_trait]
+impl Handler for Recording {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn open(
+        &mut self,
+        id: RequestId,
+        filename: String,
+        _pflags: OpenFlags,
+        _attrs: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        self.record(filename.clone());
+        Ok(Handle {
+            id,
+            handle: filename,
+        })
+    }
+
+    async fn close(&mut self, id: RequestId, _handle: String) -> Result<Status, Self::Error> {
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+
+    async fn lstat(&mut self, id: RequestId, path: String) -> Result<Attrs, Self::Error> {
+        self.attrs(id, path)
+    }
+
+    async fn setstat(
+        &mut self,
+        id: RequestId,
+        path: String,
+        _attrs: FileAttributes,
+    ) -> Result<Status, Self::Error> {
+        self.ok(id, &[&path])
+    }
+
+    async fn opendir(&mut self, id: RequestId, path: String) -> Result<Handle, Self::Error> {
+        self.record(path.clone());
+        Ok(Handle { id, handle: path })
+    }
+
+    async fn readdir(&mut self, _id: RequestId, _handle: String) -> Result<Name, Self::Error> {
+        Err(StatusCode::Eof)
+    }
+
+    async fn remove(&mut self, id: RequestId, filename: String) -> Result<Status, Self::Error> {
+        self.ok(id, &[&filename])
+    }
+
+    async fn mkdir(
+        &mut self,
+        id: RequestId,
+        path: String,
+        _attrs: FileAttributes,
+    ) -> Result<Status, Self::Error> {
+        self.ok(id, &[&path])
+    }
+
+    async fn rmdir(&mut self, id: RequestId, path: String) -> Result<Status, Self::Error> {
+        self.ok(id, &[&path])
+    }
+
+    async fn stat(&mut self, id: RequestId, path: String) -> Result<Attrs, Self::Error> {
+        self.attrs(id, path)
+    }
+
+    async fn rename(
+        &mut self,
+        id: RequestId,
+        oldpath: String,
+        newpath: String,
+    ) -> Result<Status, Self::Error> {
+        self.ok(id, &[&oldpath, &newpath])
+    }
+
+    async fn readlink(&mut self, id: RequestId, path: String) -> Result<Name, Self::Error> {
+        self.record(path.clone());
+        Ok(Name {
+            id,
+            files: vec![File::dummy(path)],
+        })
+    }
+
+    async fn symlink(
+        &mut self,
+        id: RequestId,
+        linkpath: String,
+        targetpath: String,
+    ) -> Result<Status, Self::Error> {
+        self.ok(id, &[&linkpath, &targetpath])
+    }
+}
+
+/// Runs each operation on `path` and returns the paths received for it
+async fn received(
+    sftp: &SftpSession,
+    server: &Recording,
+    path: &str,
+) -> Vec<(&'static str, Vec<String>)> {
+    let mut received = Vec::new();
+    let mut op = |name, result: bool| {
+        assert!(result, "{name} {path}");
+        received.push((name, server.take()));
+    };
+
+    op("open", sftp.open(path).await.is_ok());
+    op("create", sftp.create(path).await.is_ok());
+    op("metadata", sftp.metadata(path).await.is_ok());
+    op(
+        "symlink_metadata",
+        sftp.symlink_metadata(path).await.is_ok(),
+    );
+    op("is_dir", sftp.is_dir(path).await.unwrap());
+    op(
+        "set_metadata",
+        sftp.set_metadata(path, FileAttributes::empty())
+            .await
+            .is_ok(),
+    );
+    op("create_dir", sftp.create_dir(path).await.is_ok());
+    op("remove_dir", sftp.remove_dir(path).await.is_ok());
+    op("remove_file", sftp.remove_file(path).await.is_ok());
+    op("rename", sftp.rename(path, path).await.is_ok());
+    op(
+        "create_symlink",
+        sftp.create_symlink(path, path).await.is_ok(),
+    );
+    op("read_link", sftp.read_link(path).await.is_ok());
+    op("read_dir", sftp.read_dir(path).await.is_ok());
+    received
+}
+
+#[tokio::test]
+async fn paths_normalized_for_each_flavor() {
+    let server = Recording::default();
+    let (sftp, _server) = SftpSession::pair(server.clone()).await.unwrap();
+
+    for flavor in [RemoteFlavor::Unix, RemoteFlavor::Windows] {
+        sftp.set_remote_flavor(flavor);
+
+        for (path, windows) in FIXTURES {
+            let expected = match flavor {
+                RemoteFlavor::Unix => path,
+                RemoteFlavor::Windows => windows,
+            };
+
+            for (op, paths) in received(&sftp, &server, path).await {
+                let count = match op {
+                    "rename" | "create_symlink" => 2,
+                    _ => 1,
+                };
+                assert_eq!(paths, vec![expected; count], "{flavor:?} {op} {path}");
+            }
+        }
+    }
+}
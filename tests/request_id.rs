@@ -0,0 +1,163 @@
+//! Replies sent with the id of their request, whatever the id the handler returned.
+
+use bytes::{Bytes, BytesMut};
+use russh_sftp::{
+    client::RawSftpSession,
+    framing::read_packet,
+    protocol::{
+        Attrs, Data, FileAttributes, Handle, Init, Packet, Remove, RequestId, Stat, Status,
+        StatusCode, Version,
+    },
+    server::{self, Config, Handler, ValidationAction},
+};
+use std::collections::HashMap;
+use tokio::io::{AsyncWriteExt, DuplexStream};
+
+/// Replies with ids other than the ones of the requests
+struct WrongIds;
+
+#[async_trait::async_trait]
+impl Handler for WrongIds {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn stat(&mut self, id: RequestId, _path: String) -> Result<Attrs, Self::Error> {
+        Ok(Attrs {
+            id: id.next(),
+            attrs: FileAttributes::empty(),
+        })
+    }
+
+    async fn remove(&mut self, _id: RequestId, _filename: String) -> Result<Status, Self::Error> {
+        Ok(Status::new(RequestId::default(), StatusCode::Ok))
+    }
+}
+
+async fn connect(config: Config) -> DuplexStream {
+    let (mut client, server) = tokio::io::duplex(64 * 1024);
+    server::run_with_config(server, WrongIds, config).await;
+
+    let init = Init {
+        version: 3,
+        extensions: HashMap::new(),
+    };
+    client
+        .write_all(&Bytes::try_from(Packet::from(init)).unwrap())
+        .await
+        .unwrap();
+    read_packet(&mut client, &mut BytesMut::new())
+        .await
+        .unwrap();
+
+    client
+}
+
+async fn request(client: &mut DuplexStream, packet: Packet) -> Packet {
+    client
+        .write_all(&Bytes::try_from(packet).unwrap())
+        .await
+        .unwrap();
+    let mut reply = read_packet(client, &mut BytesMut::new()).await.unwrap();
+    Packet::try_from(&mut reply).unwrap()
+}
+
+#[tokio::test]
+async fn wrong_ids_replaced_on_the_wire() {
+    let mut client = connect(Config::default()).await;
+
+    let stat = Stat {
+        id: RequestId(7),
+        path: "/a".to_owned(),
+    };
+    match request(&mut client, Packet::Stat(stat)).await {
+        Packet::Attrs(attrs) => assert_eq!(attrs.id, RequestId(7)),
+        reply => panic!("{} is not attributes", reply.name()),
+    }
+
+    let remove = Remove {
+        id: RequestId(42),
+        filename: "/a".to_owned(),
+    };
+    match request(&mut client, Packet::Remove(remove)).await {
+        Packet::Status(status) => assert_eq!(status.id, RequestId(42)),
+        reply => panic!("{} is not a status", reply.name()),
+    }
+}
+
+#[tokio::test]
+async fn wrong_ids_replaced_before_validated() {
+    // replaced, the ids do not violate the rules and the replies are kept
+    let config = Config {
+        validate_responses: true,
+        validation_action: ValidationAction::Replace,
+        ..Default::default()
+    };
+    let mut client = connect(config).await;
+
+    let stat = Stat {
+        id: RequestId(7),
+        path: "/a".to_owned(),
+    };
+    match request(&mut client, Packet::Stat(stat)).await {
+        Packet::Attrs(attrs) => assert_eq!(attrs.id, RequestId(7)),
+        reply => panic!("{} is not attributes", reply.name()),
+    }
+
+    let remove = Remove {
+        id: RequestId(42),
+        filename: "/a".to_owned(),
+    };
+    match request(&mut client, Packet::Remove(remove)).await {
+        Packet::Status(status) => {
+            assert_eq!(status.id, RequestId(42));
+            assert_eq!(status.status_code, StatusCode::Ok);
+        }
+        reply => panic!("{} is not a status", reply.name()),
+    }
+}
+
+#[tokio::test]
+async fn client_receives_replies_with_wrong_ids() {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    server::run(server, WrongIds).await;
+
+    let session = RawSftpSession::new(client);
+    session.set_timeout(1).await;
+    session.init().await.unwrap();
+    session.stat("/a").await.unwrap();
+    session.remove("/a").await.unwrap();
+}
+
+#[test]
+fn next_wraps_around() {
+    assert_eq!(RequestId(1).next(), RequestId(2));
+    assert_eq!(RequestId(u32::MAX).next(), RequestId(0));
+    assert_eq!(u32::from(RequestId(3)), 3);
+}
+
+#[test]
+fn plain_ids_accepted() {
+    // the constructors take the ids of the code written before the newtype
+    let status = Status::new(5, StatusCode::Ok);
+    assert_eq!(status.id, RequestId(5));
+    assert_eq!(5, status.id);
+    assert_eq!(status.id, 5);
+
+    match Packet::error(6u32, StatusCode::Failure) {
+        Packet::Status(status) => assert_eq!(status.id, RequestId(6)),
+        packet => panic!("{} is not a status", packet.name()),
+    }
+    assert_eq!(Data::from_bytes(7, Bytes::new()).id, RequestId(7));
+    assert_eq!(Handle::new_checked(8, "h").unwrap().id, RequestId(8));
+}
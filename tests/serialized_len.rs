@@ -0,0 +1,281 @@
+//! Lengths computed by `serialized_len` against the serialized packets.
+
+mod common;
+
+use bytes::Bytes;
+use common::Rng;
+use russh_sftp::{
+    protocol::{
+        Attrs, Close, Data, Extended, ExtendedAttr, ExtendedReply, FSetStat, File, FileAttributes,
+        FileTime, Fstat, Full, Handle, Init, Lstat, MkDir, Name, NameBatcher, Open, OpenDir,
+        OpenFlags, Packet, Read, ReadDir, ReadLink, RealPath, Remove, Rename, RequestId, RmDir,
+        SetStat, Stat, Status, StatusCode, Symlink, Version, Write,
+    },
+    ser,
+};
+use std::collections::HashMap;
+
+/// Random values of the packets
+impl Rng {
+    fn maybe<T>(&mut self, value: impl FnOnce(&mut Self) -> T) -> Option<T> {
+        (self.below(2) == 0).then(|| value(self))
+    }
+
+    fn bytes(&mut self) -> Vec<u8> {
+        let len = self.below(40);
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+
+    /// Includes multi-byte characters, whose length differs from the count of chars
+    fn string(&mut self) -> String {
+        let len = self.below(20);
+        (0..len)
+            .map(|_| ['a', 'Z', '/', ' ', 'é', '日', '🦀'][self.below(7)])
+            .collect()
+    }
+
+    fn extensions(&mut self) -> HashMap<String, String> {
+        (0..self.below(4))
+            .map(|_| (self.string(), self.string()))
+            .collect()
+    }
+
+    fn attrs(&mut self) -> FileAttributes {
+        let extended = (0..self.below(3))
+            .map(|_| ExtendedAttr {
+                name: self.string(),
+                data: self.bytes(),
+            })
+            .collect();
+
+        FileAttributes {
+            size: self.maybe(|rng| rng.next()),
+            uid: self.maybe(|rng| rng.next() as u32),
+            // names are not part of version 3 attributes
+            user: self.maybe(|rng| rng.string()),
+            gid: self.maybe(|rng| rng.next() as u32),
+            group: self.maybe(|rng| rng.string()),
+            permissions: self.maybe(|rng| rng.next() as u32),
+            atime: self.maybe(|rng| FileTime::from(rng.next() as u32)),
+            mtime: self.maybe(|rng| FileTime::from(rng.next() as u32)),
+            extended,
+            ..FileAttributes::empty()
+        }
+    }
+
+    fn file(&mut self) -> File {
+        File {
+            filename: self.string(),
+            longname: self.string(),
+            attrs: self.attrs(),
+        }
+    }
+}
+
+macro_rules! check {
+    ($value:expr) => {{
+        let value = $value;
+        let len = value.serialized_len();
+        assert_eq!(len, ser::to_bytes(&value).unwrap().len(), "{value:?}");
+
+        let packet = Packet::from(value);
+        let len = packet.serialized_len();
+        let frame = Bytes::try_from(packet).unwrap();
+        assert_eq!(len, frame.len() - 4);
+    }};
+}
+
+#[test]
+fn every_packet_type() {
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+
+    for _ in 0..200 {
+        let id = RequestId(rng.next() as u32);
+
+        check!(Init {
+            version: 3,
+            extensions: rng.extensions(),
+        });
+        check!(Version {
+            version: 3,
+            extensions: rng.extensions(),
+        });
+        check!(Open {
+            id,
+            filename: rng.string(),
+            pflags: OpenFlags::from_bits_retain(rng.next() as u32),
+            attrs: rng.attrs(),
+        });
+        check!(Close {
+            id,
+            handle: rng.string(),
+        });
+        check!(Read {
+            id,
+            handle: rng.string(),
+            offset: rng.next(),
+            len: rng.next() as u32,
+        });
+        check!(Write {
+            id,
+            handle: rng.string(),
+            offset: rng.next(),
+            data: rng.bytes(),
+        });
+        check!(Lstat {
+            id,
+            path: rng.string(),
+        });
+        check!(Fstat {
+            id,
+            handle: rng.string(),
+        });
+        check!(SetStat {
+            id,
+            path: rng.string(),
+            attrs: rng.attrs(),
+        });
+        check!(FSetStat {
+            id,
+            handle: rng.string(),
+            attrs: rng.attrs(),
+        });
+        check!(OpenDir {
+            id,
+            path: rng.string(),
+        });
+        check!(ReadDir {
+            id,
+            handle: rng.string(),
+        });
+        check!(Remove {
+            id,
+            filename: rng.string(),
+        });
+        check!(MkDir {
+            id,
+            path: rng.string(),
+            attrs: rng.attrs(),
+        });
+        check!(RmDir {
+            id,
+            path: rng.string(),
+        });
+        check!(RealPath {
+            id,
+            path: rng.string(),
+        });
+        check!(Stat {
+            id,
+            path: rng.string(),
+        });
+        check!(Rename {
+            id,
+            oldpath: rng.string(),
+            newpath: rng.string(),
+        });
+        check!(ReadLink {
+            id,
+            path: rng.string(),
+        });
+        check!(Symlink {
+            id,
+            linkpath: rng.string(),
+            targetpath: rng.string(),
+        });
+        check!(Status {
+            id,
+            status_code: StatusCode::NoSuchFile,
+            error_message: rng.string(),
+            language_tag: rng.string(),
+        });
+        check!(Handle {
+            id,
+            handle: rng.string(),
+        });
+        check!(Data {
+            id,
+            data: rng.bytes(),
+        });
+        check!(Name {
+            id,
+            files: (0..rng.below(5)).map(|_| rng.file()).collect(),
+        });
+        check!(Attrs {
+            id,
+            attrs: rng.attrs(),
+        });
+        check!(Extended {
+            id,
+            request: rng.string(),
+            data: rng.bytes(),
+        });
+        check!(ExtendedReply {
+            id,
+            data: rng.bytes(),
+        });
+    }
+}
+
+#[test]
+fn entries_and_attributes() {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+
+    for _ in 0..200 {
+        let file = rng.file();
+        assert_eq!(file.serialized_len(), ser::to_bytes(&file).unwrap().len());
+        assert_eq!(
+            file.attrs.serialized_len(),
+            ser::to_bytes(&file.attrs).unwrap().len()
+        );
+    }
+}
+
+#[test]
+fn name_batches() {
+    let mut rng = Rng(0x0123_4567_89ab_cdef);
+    let files = (0..100).map(|_| rng.file()).collect::<Vec<_>>();
+    let max_len = 512;
+
+    let mut batcher = NameBatcher::new(max_len);
+    let mut batches = Vec::new();
+    for file in files.iter().cloned() {
+        if let Err(Full(file)) = batcher.push(file) {
+            batches.push(batcher.take(RequestId(batches.len() as u32)));
+            batcher.push(*file).unwrap();
+        }
+    }
+    batches.push(batcher.take(RequestId(batches.len() as u32)));
+    assert!(batcher.is_empty());
+
+    // every entry is in one batch, in order, and no batch could take the next entry
+    let batched = batches.iter().flat_map(|name| &name.files);
+    let names = batched.map(|file| &file.filename).collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        files.iter().map(|file| &file.filename).collect::<Vec<_>>()
+    );
+
+    for (batch, next) in batches.iter().zip(batches.iter().skip(1)) {
+        let frame = Bytes::try_from(Packet::Name(Name {
+            id: batch.id,
+            files: batch.files.clone(),
+        }))
+        .unwrap();
+        assert!(frame.len() - 4 <= max_len);
+        assert!(frame.len() - 4 + next.files[0].serialized_len() > max_len);
+    }
+}
+
+#[test]
+fn name_batch_too_small() {
+    let file = File::dummy("a-rather-long-file-name");
+    let mut batcher = NameBatcher::new(16);
+
+    let Err(Full(file)) = batcher.push(file) else {
+        panic!("entry pushed into a batch too small");
+    };
+    assert_eq!(file.filename, "a-rather-long-file-name");
+    assert!(batcher.is_empty());
+    assert_eq!(batcher.len(), 9);
+}
@@ -0,0 +1,85 @@
+//! Handlers that are not `Send` served on the current task.
+
+use russh_sftp::{
+    client::SftpSession,
+    protocol::{Attrs, FileAttributes, RequestId, StatusCode},
+    server::{self, Config, EndReason, LocalHandler},
+};
+use std::{cell::RefCell, rc::Rc};
+use tokio::task::LocalSet;
+
+/// Counts the requests in an `Rc`, held across an await
+#[derive(Default)]
+struct Counter {
+    stats: Rc<RefCell<Vec<String>>>,
+    ended: Rc<RefCell<Option<EndReason>>>,
+}
+
+#[async_trait::async_trait(?Send)]
+impl LocalHandler for Counter {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn session_ended(&mut self, reason: EndReason) {
+        *self.ended.borrow_mut() = Some(reason);
+    }
+
+    async fn stat(&mut self, id: RequestId, path: String) -> Result<Attrs, Self::Error> {
+        let stats = self.stats.clone();
+        tokio::task::yield_now().await;
+        stats.borrow_mut().push(path);
+
+        Ok(Attrs {
+            id,
+            attrs: FileAttributes::empty(),
+        })
+    }
+}
+
+#[tokio::test]
+async fn served_on_a_local_set() {
+    let handler = Counter::default();
+    let (stats, ended) = (handler.stats.clone(), handler.ended.clone());
+
+    LocalSet::new()
+        .run_until(async move {
+            let (client, stream) = tokio::io::duplex(64 * 1024);
+            let server =
+                tokio::task::spawn_local(server::serve_local(stream, handler, Config::default()));
+
+            let sftp = SftpSession::new(client).await.unwrap();
+            sftp.metadata("/a").await.unwrap();
+            sftp.metadata("/b").await.unwrap();
+            assert!(sftp.read_dir("/").await.is_err());
+
+            sftp.close().await.unwrap();
+            drop(sftp);
+            server.await.unwrap().unwrap();
+        })
+        .await;
+
+    assert_eq!(*stats.borrow(), ["/a", "/b"]);
+    assert_eq!(*ended.borrow(), Some(EndReason::Closed));
+}
+
+#[tokio::test]
+async fn driven_in_a_select_loop() {
+    let handler = Counter::default();
+    let stats = handler.stats.clone();
+
+    let (client, stream) = tokio::io::duplex(64 * 1024);
+    let server = server::serve_local(stream, handler, Config::default());
+    let client = async move {
+        let sftp = SftpSession::new(client).await.unwrap();
+        sftp.metadata("/a").await.unwrap();
+    };
+
+    tokio::select! {
+        result = server => panic!("server ended first: {result:?}"),
+        () = client => (),
+    }
+    assert_eq!(*stats.borrow(), ["/a"]);
+}
@@ -0,0 +1,125 @@
+//! Informational extensions of SSH_FXP_VERSION read through a typed view.
+
+use bytes::{BufMut, BytesMut};
+use russh_sftp::{
+    client::SftpSession,
+    extensions,
+    protocol::{Packet, StatusCode, Version},
+    server::Handler,
+};
+use std::collections::HashMap;
+
+const SSH_FXP_VERSION: u8 = 2;
+
+/// Advertises an informational extension next to fsync
+struct Informing;
+
+#[async_trait::async_trait]
+impl Handler for Informing {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    fn supported_extensions(&self) -> &'static [&'static str] {
+        &[extensions::FSYNC]
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new()
+            .with_informational("mod_sftp", "1.0.1")
+            .unwrap()
+            .with_informational("site-flag@example.com", "on")
+            .unwrap())
+    }
+}
+
+/// Version of a server advertising known, informational and binary-valued extensions
+fn fixture() -> Version {
+    let mut bytes = BytesMut::new();
+    bytes.put_u8(SSH_FXP_VERSION);
+    bytes.put_u32(3);
+    for (name, value) in [
+        (&b"fsync@openssh.com"[..], &b"1"[..]),
+        (b"posix-rename@openssh.com", b"1"),
+        (b"vendor-id", b"\x00\x00\x00\x07OpenSSH"),
+        (b"vendor-build", b"9.6\x00\x00"),
+        (b"site-token", b"\xff\x01"),
+    ] {
+        bytes.put_u32(name.len() as u32);
+        bytes.put_slice(name);
+        bytes.put_u32(value.len() as u32);
+        bytes.put_slice(value);
+    }
+
+    match Packet::try_from(&mut bytes.freeze()).unwrap() {
+        Packet::Version(version) => version,
+        packet => panic!("{packet:?}"),
+    }
+}
+
+#[test]
+fn informational_leaves_out_behavior() {
+    let capabilities = fixture().capabilities();
+    let names: Vec<_> = capabilities.informational().map(|(name, _)| name).collect();
+    assert_eq!(names, ["site-token", "vendor-build", "vendor-id"]);
+
+    assert!(capabilities.contains(extensions::FSYNC));
+    assert_eq!(capabilities.iter().count(), 5);
+}
+
+#[test]
+fn values_as_text() {
+    let capabilities = fixture().capabilities();
+    assert_eq!(capabilities.get_str("vendor-build").unwrap(), "9.6");
+    assert_eq!(capabilities.get("vendor-build").unwrap(), b"9.6\x00\x00");
+
+    // the bytes that are not UTF-8 were replaced when the version was read
+    assert_eq!(capabilities.get_str("site-token").unwrap(), "\u{fffd}\u{1}");
+    assert_eq!(capabilities.get_str("missing"), None);
+}
+
+#[test]
+fn found_by_prefix() {
+    let capabilities = fixture().capabilities();
+    let vendor: Vec<_> = capabilities
+        .find_prefix("vendor-")
+        .map(|(name, _)| name)
+        .collect();
+    assert_eq!(vendor, ["vendor-build", "vendor-id"]);
+    assert_eq!(capabilities.find_prefix("none-").count(), 0);
+}
+
+#[test]
+fn behavior_refused_as_informational() {
+    assert!(Version::new()
+        .with_informational(extensions::FSYNC, "1")
+        .is_err());
+    assert!(Version::new()
+        .with_informational("posix-rename@openssh.com", "1")
+        .is_err());
+
+    let version = Version::new().with_informational("mod_sftp", "1").unwrap();
+    assert_eq!(version.extensions["mod_sftp"], "1");
+}
+
+#[tokio::test]
+async fn read_by_the_session() {
+    let (sftp, _server) = SftpSession::pair(Informing).await.unwrap();
+
+    let capabilities = sftp.server_capabilities();
+    let informational: Vec<_> = capabilities.informational().collect();
+    assert_eq!(
+        informational,
+        [
+            ("mod_sftp", &b"1.0.1"[..]),
+            ("site-flag@example.com", &b"on"[..])
+        ]
+    );
+    assert!(capabilities.contains(extensions::FSYNC));
+}
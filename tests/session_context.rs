@@ -0,0 +1,131 @@
+//! State of the session seen by the handler, from SSH_FXP_INIT, the configuration
+//! and the requests handled so far.
+
+use russh_sftp::{
+    client::SftpSession,
+    protocol::{Attrs, FileAttributes, Handle, OpenFlags, RequestId, Status, StatusCode, Version},
+    server::{Config, Handler, SessionContext},
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::io::AsyncWriteExt;
+
+/// Records the context given with each request
+#[derive(Clone, Default)]
+struct Recorder {
+    seen: Arc<Mutex<Vec<SessionContext>>>,
+}
+
+impl Recorder {
+    fn last(&self) -> SessionContext {
+        self.seen.lock().unwrap().last().cloned().unwrap()
+    }
+}
+
+#[async_trait::async_trait]
+impl Handler for Recorder {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    fn set_context(&mut self, context: &SessionContext) {
+        self.seen.lock().unwrap().push(context.clone());
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn open(
+        &mut self,
+        id: RequestId,
+        filename: String,
+        _pflags: OpenFlags,
+        _attrs: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        Ok(Handle {
+            id,
+            handle: filename,
+        })
+    }
+
+    async fn close(&mut self, id: RequestId, _handle: String) -> Result<Status, Self::Error> {
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+
+    async fn stat(&mut self, id: RequestId, _path: String) -> Result<Attrs, Self::Error> {
+        Ok(Attrs {
+            id,
+            attrs: FileAttributes::empty(),
+        })
+    }
+}
+
+#[tokio::test]
+async fn populated_from_the_config() {
+    let server = Recorder::default();
+    let config = Config {
+        session_identity: Some("user@192.0.2.1".to_owned()),
+        language: Some("fr-FR".to_owned()),
+        max_request_packet_len: Some(65536),
+        max_response_packet_len: Some(32768),
+        ..Default::default()
+    };
+    let (sftp, _server) = SftpSession::pair_with_config(server.clone(), config)
+        .await
+        .unwrap();
+    sftp.metadata("/a").await.unwrap();
+
+    // already set when the handler is given SSH_FXP_INIT
+    let seen = server.seen.lock().unwrap().clone();
+    for context in seen {
+        assert_eq!(context.identity(), Some("user@192.0.2.1"));
+        assert_eq!(context.language(), Some("fr-FR"));
+        assert_eq!(context.max_request_packet_len(), 65536);
+        assert_eq!(context.max_response_packet_len(), Some(32768));
+    }
+}
+
+#[tokio::test]
+async fn defaults_without_config() {
+    let server = Recorder::default();
+    let (sftp, _server) = SftpSession::pair(server.clone()).await.unwrap();
+    sftp.metadata("/a").await.unwrap();
+
+    let context = server.last();
+    assert_eq!(context.identity(), None);
+    assert_eq!(
+        context.max_request_packet_len(),
+        russh_sftp::framing::DEFAULT_MAX_PACKET_LEN
+    );
+    assert_eq!(context.max_response_packet_len(), None);
+}
+
+#[tokio::test]
+async fn counters_follow_the_requests() {
+    let server = Recorder::default();
+    let (sftp, _server) = SftpSession::pair(server.clone()).await.unwrap();
+
+    let init = server.last();
+    assert_eq!((init.client_version(), init.version()), (Some(3), None));
+    assert_eq!((init.requests(), init.responses()), (1, 0));
+
+    let mut file = sftp.open("/a").await.unwrap();
+    sftp.metadata("/b").await.unwrap();
+    let context = server.last();
+    assert_eq!(context.version(), Some(3));
+    assert_eq!((context.requests(), context.responses()), (3, 2));
+    assert_eq!(context.open_handles(), 1);
+
+    file.shutdown().await.unwrap();
+    sftp.metadata("/b").await.unwrap();
+    assert_eq!(server.last().open_handles(), 0);
+}
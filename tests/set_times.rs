@@ -0,0 +1,206 @@
+//! Times set by path and by open file: only the times are sent, a single time is
+//! completed with the current one, and times that do not fit are never sent.
+
+use russh_sftp::{
+    client::{error::Error, SftpSession},
+    protocol::{
+        Attrs, FileAttr, FileAttributes, FileTime, Handle, OpenFlags, RequestId, Status,
+        StatusCode, Version,
+    },
+    server::Handler,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Times of the file before they are set
+const ATIME: u64 = 1_000_000;
+const MTIME: u64 = 2_000_000;
+
+/// 2200-01-01, after the last second of version 3
+const AFTER_2106: u64 = 7_258_118_400;
+
+/// Answers stat and fstat with [`ATIME`] and [`MTIME`] and records the attributes
+/// of SSH_FXP_SETSTAT and SSH_FXP_FSETSTAT
+#[derive(Clone, Default)]
+struct Times {
+    stats: Arc<Mutex<u32>>,
+    set: Arc<Mutex<Vec<FileAttributes>>>,
+}
+
+impl Times {
+    fn attrs(&self, id: RequestId) -> Result<Attrs, StatusCode> {
+        *self.stats.lock().unwrap() += 1;
+        let attrs = FileAttributes {
+            size: Some(10),
+            permissions: Some(0o100644),
+            atime: Some(FileTime::from_secs(ATIME)),
+            mtime: Some(FileTime::from_secs(MTIME)),
+            ..FileAttributes::empty()
+        };
+        Ok(Attrs { id, attrs })
+    }
+
+    fn record(&self, id: RequestId, attrs: FileAttributes) -> Result<Status, StatusCode> {
+        self.set.lock().unwrap().push(attrs);
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+}
+
+#[async_trait::async_trait]
+impl Handler for Times {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn open(
+        &mut self,
+        id: RequestId,
+        filename: String,
+        _pflags: OpenFlags,
+        _attrs: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        Ok(Handle {
+            id,
+            handle: filename,
+        })
+    }
+
+    async fn close(&mut self, id: RequestId, _handle: String) -> Result<Status, Self::Error> {
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+
+    async fn stat(&mut self, id: RequestId, _path: String) -> Result<Attrs, Self::Error> {
+        self.attrs(id)
+    }
+
+    async fn fstat(&mut self, id: RequestId, _handle: String) -> Result<Attrs, Self::Error> {
+        self.attrs(id)
+    }
+
+    async fn setstat(
+        &mut self,
+        id: RequestId,
+        _path: String,
+        attrs: FileAttributes,
+    ) -> Result<Status, Self::Error> {
+        self.record(id, attrs)
+    }
+
+    async fn fsetstat(
+        &mut self,
+        id: RequestId,
+        _handle: String,
+        attrs: FileAttributes,
+    ) -> Result<Status, Self::Error> {
+        self.record(id, attrs)
+    }
+}
+
+fn at(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+/// Sets the times by path or through an open file
+async fn set_times(
+    sftp: &SftpSession,
+    by_file: bool,
+    accessed: Option<SystemTime>,
+    modified: Option<SystemTime>,
+) -> Result<(), Error> {
+    match by_file {
+        true => {
+            let file = sftp.open("/file").await?;
+            file.set_times(accessed, modified).await
+        }
+        false => sftp.set_times("/file", accessed, modified).await,
+    }
+}
+
+#[tokio::test]
+async fn single_time_completed_with_the_current_one() {
+    let server = Times::default();
+    let (sftp, _server) = SftpSession::pair(server.clone()).await.unwrap();
+
+    let cases = [
+        (Some(at(5)), None, (5, MTIME), 1),
+        (None, Some(at(7)), (ATIME, 7), 1),
+        // nothing is queried with both times
+        (Some(at(5)), Some(at(7)), (5, 7), 0),
+    ];
+
+    for by_file in [false, true] {
+        for (accessed, modified, (atime, mtime), stats) in cases {
+            *server.stats.lock().unwrap() = 0;
+            set_times(&sftp, by_file, accessed, modified).await.unwrap();
+
+            let set = server.set.lock().unwrap().pop().unwrap();
+            assert_eq!(set.flags(), FileAttr::ACMODTIME, "{by_file}");
+            assert_eq!(set.atime, Some(FileTime::from_secs(atime)), "{by_file}");
+            assert_eq!(set.mtime, Some(FileTime::from_secs(mtime)), "{by_file}");
+            assert_eq!(*server.stats.lock().unwrap(), stats, "{by_file}");
+        }
+    }
+}
+
+#[tokio::test]
+async fn nothing_sent_without_times() {
+    let server = Times::default();
+    let (sftp, _server) = SftpSession::pair(server.clone()).await.unwrap();
+
+    for by_file in [false, true] {
+        set_times(&sftp, by_file, None, None).await.unwrap();
+    }
+
+    assert!(server.set.lock().unwrap().is_empty());
+    assert_eq!(*server.stats.lock().unwrap(), 0);
+}
+
+#[tokio::test]
+async fn overflowing_times_rejected() {
+    let server = Times::default();
+    let (sftp, _server) = SftpSession::pair(server.clone()).await.unwrap();
+    let late = at(AFTER_2106);
+
+    // whether or not the times are strict, and before the other time is queried
+    for strict in [false, true] {
+        sftp.set_strict_times(strict).await;
+
+        for by_file in [false, true] {
+            for (accessed, modified) in [(Some(late), None), (Some(at(5)), Some(late))] {
+                let result = set_times(&sftp, by_file, accessed, modified).await;
+                assert!(
+                    matches!(result, Err(Error::TimeOverflow(time))
+                        if time == FileTime::from_secs(AFTER_2106)),
+                    "{strict} {by_file}: {result:?}"
+                );
+            }
+
+            // the last second of version 3 still fits
+            let last = at(u32::MAX as u64);
+            set_times(&sftp, by_file, Some(last), Some(last))
+                .await
+                .unwrap();
+        }
+    }
+
+    // before the epoch
+    let before = UNIX_EPOCH - Duration::from_secs(1);
+    let result = sftp.set_times("/file", Some(before), None).await;
+    assert!(matches!(result, Err(Error::IO(_))), "{result:?}");
+
+    assert_eq!(server.set.lock().unwrap().len(), 4);
+    assert_eq!(*server.stats.lock().unwrap(), 0);
+}
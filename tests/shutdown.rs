@@ -0,0 +1,105 @@
+//! Shutdown of the sessions: nothing is written once `close` returns, the tasks
+//! owned by the caller have exited, and the stream of an unspawned session is left
+//! to the caller.
+
+mod common;
+
+use bytes::{Bytes, BytesMut};
+use common::Memory;
+use russh_sftp::{
+    client::{RawSftpSession, SftpSession},
+    framing::read_packet,
+    protocol::{Packet, Status, StatusCode, Version},
+    server::{self, Config},
+};
+use std::time::Duration;
+use tokio::{io::AsyncWriteExt, io::DuplexStream, task::JoinSet, time};
+
+/// Answers SSH_FXP_INIT and fails the other requests until the end of the stream,
+/// and returns the types of the packets received
+async fn scripted(mut stream: DuplexStream) -> Vec<u8> {
+    let mut buf = BytesMut::new();
+    let mut received = Vec::new();
+
+    while let Ok(mut payload) = read_packet(&mut stream, &mut buf).await {
+        received.push(payload[0]);
+        let reply: Packet = match Packet::try_from(&mut payload).unwrap() {
+            Packet::Init(_) => Version::new().into(),
+            request => Status::new(request.get_request_id(), StatusCode::OpUnsupported).into(),
+        };
+
+        let frame = Bytes::try_from(reply).unwrap();
+        if stream.write_all(&frame).await.is_err() {
+            break;
+        }
+    }
+
+    received
+}
+
+#[tokio::test]
+async fn nothing_written_after_close() {
+    for owned in [false, true] {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let script = tokio::spawn(scripted(server));
+
+        let mut tasks = JoinSet::new();
+        let sftp = match owned {
+            true => SftpSession::new_with_tasks(client, &mut tasks).await,
+            false => SftpSession::new(client).await,
+        };
+        let sftp = sftp.unwrap();
+        sftp.close().await.unwrap();
+
+        // the stream was shut down, the script sees its end right away
+        let received = time::timeout(Duration::from_secs(1), script).await;
+        let received = received.expect("the stream is still open").unwrap();
+        assert_eq!(received.first(), Some(&1), "{owned}: SSH_FXP_INIT first");
+
+        assert!(sftp.metadata("/file").await.is_err(), "{owned}");
+        assert_eq!(tasks.len(), if owned { 2 } else { 0 });
+        let exited = time::timeout(Duration::from_secs(1), tasks.join_all()).await;
+        assert!(exited.is_ok(), "{owned}: the tasks are still running");
+    }
+}
+
+#[tokio::test]
+async fn tasks_exit_with_the_stream() {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let serving = tokio::spawn(server::serve(server, Memory::default(), Config::default()));
+
+    let mut tasks = JoinSet::new();
+    let sftp = SftpSession::new_with_tasks(client, &mut tasks)
+        .await
+        .unwrap();
+    assert!(!sftp.try_exists("/file").await.unwrap());
+
+    // the server going away ends the tasks without a close
+    serving.abort();
+    let exited = time::timeout(Duration::from_secs(1), tasks.join_all()).await;
+    assert!(exited.is_ok(), "the tasks are still running");
+    assert!(!sftp.is_healthy());
+    sftp.close().await.unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn unspawned_stream_left_to_the_caller() {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(server::serve(server, Memory::default(), Config::default()));
+
+    let (raw, stream) = RawSftpSession::new_unspawned(client);
+    tokio::pin!(stream);
+    let sftp = tokio::select! {
+        _ = &mut stream => panic!("the stream ended before the session started"),
+        sftp = SftpSession::with_raw_session(raw) => sftp.unwrap(),
+    };
+
+    // closed while nobody drives the stream
+    sftp.set_timeout(1).await;
+    sftp.close().await.unwrap();
+
+    // which ends once driven again
+    time::timeout(Duration::from_secs(1), stream)
+        .await
+        .expect("the stream is still processed");
+}
@@ -0,0 +1,108 @@
+//! Requests exceeding the slow request threshold reported to the callback, with
+//! the target decoded from the request.
+
+use russh_sftp::{
+    client::RawSftpSession,
+    protocol::{Attrs, FileAttributes, RequestId, StatusCode, Version},
+    server::{self, Config, Handler, SlowRequestInfo},
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+const DELAY: Duration = Duration::from_millis(50);
+const THRESHOLD: Duration = Duration::from_millis(20);
+
+/// Paths and handles starting with `/slow` are answered after [`DELAY`]
+struct Sleeping;
+
+impl Sleeping {
+    async fn attrs(id: RequestId, target: &str) -> Attrs {
+        if target.starts_with("/slow") {
+            tokio::time::sleep(DELAY).await;
+        }
+
+        Attrs {
+            id,
+            attrs: FileAttributes::empty(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Handler for Sleeping {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn stat(&mut self, id: RequestId, path: String) -> Result<Attrs, Self::Error> {
+        Ok(Self::attrs(id, &path).await)
+    }
+
+    async fn fstat(&mut self, id: RequestId, handle: String) -> Result<Attrs, Self::Error> {
+        Ok(Self::attrs(id, &handle).await)
+    }
+}
+
+type Reports = Arc<Mutex<Vec<SlowRequestInfo>>>;
+
+async fn connect(threshold: Option<Duration>) -> (RawSftpSession, Reports) {
+    let reports = Reports::default();
+    let sink = reports.clone();
+    let config = Config {
+        slow_request_threshold: threshold,
+        on_slow_request: Some(Arc::new(move |info| sink.lock().unwrap().push(info))),
+        ..Default::default()
+    };
+
+    let (client, stream) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(server::serve(stream, Sleeping, config));
+    let raw = RawSftpSession::new(client);
+    raw.init().await.unwrap();
+    (raw, reports)
+}
+
+#[tokio::test]
+async fn slow_requests_reported() {
+    let (raw, reports) = connect(Some(THRESHOLD)).await;
+
+    let fast = raw.stat("/fast").await.unwrap();
+    let slow = raw.stat("/slow/file").await.unwrap();
+    let handle = raw.fstat("/slow-handle").await.unwrap();
+
+    let reports = reports.lock().unwrap();
+    let reported = reports
+        .iter()
+        .map(|info| (info.packet, info.id, info.target.as_deref()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        reported,
+        [
+            ("SSH_FXP_STAT", slow.id, Some("/slow/file")),
+            ("SSH_FXP_FSTAT", handle.id, Some("/slow-handle")),
+        ]
+    );
+    assert!(reports.iter().all(|info| info.id != fast.id));
+    assert!(reports.iter().all(|info| info.elapsed >= DELAY));
+}
+
+#[tokio::test]
+async fn nothing_reported_without_a_threshold() {
+    let (raw, reports) = connect(None).await;
+
+    raw.stat("/slow/file").await.unwrap();
+    raw.fstat("/slow-handle").await.unwrap();
+    assert!(reports.lock().unwrap().is_empty());
+}
@@ -0,0 +1,135 @@
+//! Spans of the requests on both sides, with the paths only when asked for.
+
+mod common;
+
+use common::Memory;
+use russh_sftp::{client::SftpSession, server::Config};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    Subscriber,
+};
+use tracing_subscriber::{layer::Context, prelude::*, registry::LookupSpan, Layer};
+
+type Fields = BTreeMap<String, String>;
+
+/// Collects the fields of the spans, in the order they are closed
+#[derive(Clone, Default)]
+struct Collector {
+    closed: Arc<Mutex<Vec<Fields>>>,
+}
+
+impl Collector {
+    fn spans(&self, side: &str, packet: &str) -> Vec<Fields> {
+        let closed = self.closed.lock().unwrap();
+        closed
+            .iter()
+            .filter(|fields| fields["side"] == side && fields["packet"] == packet)
+            .cloned()
+            .collect()
+    }
+}
+
+struct Visitor<'a>(&'a mut Fields);
+
+impl Visit for Visitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(field.name().to_owned(), format!("{value:?}"));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_owned(), value.to_owned());
+    }
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Collector {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut fields = Fields::new();
+        attrs.record(&mut Visitor(&mut fields));
+        ctx.span(id).unwrap().extensions_mut().insert(fields);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let span = ctx.span(id).unwrap();
+        let mut extensions = span.extensions_mut();
+        values.record(&mut Visitor(extensions.get_mut::<Fields>().unwrap()));
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let span = ctx.span(&id).unwrap();
+        let fields = span.extensions_mut().remove::<Fields>().unwrap();
+        self.closed.lock().unwrap().push(fields);
+    }
+}
+
+async fn exchange(collector: &Collector, record_paths: bool) {
+    let _guard = tracing_subscriber::registry()
+        .with(collector.clone())
+        .set_default();
+
+    let config = Config {
+        record_paths,
+        ..Default::default()
+    };
+    let (sftp, _server) = SftpSession::pair_with_config(Memory::default(), config)
+        .await
+        .unwrap();
+    sftp.set_record_paths(record_paths).await;
+
+    let mut file = sftp.create("/secret.txt").await.unwrap();
+    file.write_all(b"hello").await.unwrap();
+    file.shutdown().await.unwrap();
+    let mut file = sftp.open("/secret.txt").await.unwrap();
+    file.read_to_end(&mut Vec::new()).await.unwrap();
+    file.shutdown().await.unwrap();
+    assert!(sftp.metadata("/missing").await.is_err());
+
+    sftp.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn span_per_request_on_both_sides() {
+    let collector = Collector::default();
+    exchange(&collector, false).await;
+
+    for side in ["client", "server"] {
+        let writes = collector.spans(side, "SSH_FXP_WRITE");
+        assert_eq!(writes.len(), 1, "{side}");
+        assert_eq!(writes[0]["handle"], "/secret.txt");
+        assert_eq!(writes[0]["len"], "5");
+        assert_eq!(writes[0]["status"], "Ok");
+
+        let reads = collector.spans(side, "SSH_FXP_READ");
+        assert_eq!(reads[0]["reply"], "SSH_FXP_DATA");
+        assert_eq!(reads.last().unwrap()["status"], "Eof");
+
+        let stats = collector.spans(side, "SSH_FXP_STAT");
+        assert_eq!(stats[0]["status"], "NoSuchFile");
+    }
+}
+
+#[tokio::test]
+async fn paths_recorded_when_asked_for() {
+    let collector = Collector::default();
+    exchange(&collector, false).await;
+    for side in ["client", "server"] {
+        for fields in collector.spans(side, "SSH_FXP_OPEN") {
+            assert!(!fields.contains_key("path"), "{fields:?}");
+        }
+    }
+
+    let collector = Collector::default();
+    exchange(&collector, true).await;
+    for side in ["client", "server"] {
+        let opens = collector.spans(side, "SSH_FXP_OPEN");
+        assert_eq!(opens.len(), 2, "{side}");
+        assert!(opens.iter().all(|fields| fields["path"] == "/secret.txt"));
+        assert_eq!(collector.spans(side, "SSH_FXP_STAT")[0]["path"], "/missing");
+    }
+}
@@ -0,0 +1,179 @@
+//! Handles invalidated by the server when the file is renamed, reopened at the
+//! previous position, and the kinds of the I/O errors of the client errors.
+
+mod common;
+
+use common::Memory;
+use russh_sftp::{
+    client::{error::Error, SftpSession},
+    protocol::{FileTime, OpenFlags, RequestId, Status, StatusCode},
+};
+use std::io;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// Session on a server whose handles are the paths, so a rename invalidates them
+async fn connect() -> SftpSession {
+    let handler = Memory::default();
+    handler
+        .files
+        .lock()
+        .unwrap()
+        .insert("/old".to_owned(), b"0123456789".to_vec());
+    SftpSession::pair(handler).await.unwrap().0
+}
+
+fn status(code: StatusCode) -> Status {
+    Status::new(RequestId(1), code)
+}
+
+#[tokio::test]
+async fn stale_after_rename() {
+    let sftp = connect().await;
+    let mut file = sftp.open("/old").await.unwrap();
+
+    let mut buf = [0; 4];
+    file.read_exact(&mut buf).await.unwrap();
+    assert_eq!(file.position(), 4);
+
+    sftp.rename("/old", "/new").await.unwrap();
+
+    let err = file.read(&mut buf).await.unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    match err.into_inner().unwrap().downcast::<Error>() {
+        Ok(err) => assert!(matches!(*err, Error::StaleHandle(_)), "{err}"),
+        Err(err) => panic!("{err}"),
+    }
+
+    match file.metadata().await {
+        Err(Error::StaleHandle(status)) => assert_eq!(status.status_code, StatusCode::NoSuchFile),
+        result => panic!("{result:?}"),
+    }
+
+    // errors on paths are not about handles
+    match sftp.metadata("/old").await {
+        Err(Error::Status { status, .. }) => assert_eq!(status.status_code, StatusCode::NoSuchFile),
+        result => panic!("{result:?}"),
+    }
+}
+
+#[tokio::test]
+async fn resumed_at_the_previous_position() {
+    let sftp = connect().await;
+    let mut file = sftp.open("/old").await.unwrap();
+    let mut read = vec![0; 4];
+    file.read_exact(&mut read).await.unwrap();
+
+    sftp.rename("/old", "/new").await.unwrap();
+    assert!(file.read(&mut [0; 4]).await.is_err());
+
+    let mut file = sftp
+        .open_at_offset("/new", OpenFlags::READ, file.position())
+        .await
+        .unwrap();
+    assert_eq!(file.position(), 4);
+    file.read_to_end(&mut read).await.unwrap();
+    assert_eq!(read, b"0123456789");
+}
+
+#[tokio::test]
+async fn stale_writes() {
+    let sftp = connect().await;
+    let mut file = sftp
+        .open_with_flags("/old", OpenFlags::WRITE)
+        .await
+        .unwrap();
+    sftp.remove_file("/old").await.unwrap();
+
+    // reported by the write or by the flush of the pipelined write
+    let err = match file.write_all(b"lost").await {
+        Ok(()) => file.flush().await.unwrap_err(),
+        Err(err) => err,
+    };
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+}
+
+#[test]
+fn kinds_of_client_errors() {
+    let table = [
+        (
+            Error::Status {
+                status: status(StatusCode::PermissionDenied),
+                target: Some("/f".to_owned()),
+            },
+            io::ErrorKind::PermissionDenied,
+        ),
+        (status(StatusCode::Eof).into(), io::ErrorKind::UnexpectedEof),
+        (
+            status(StatusCode::OpUnsupported).into(),
+            io::ErrorKind::Unsupported,
+        ),
+        (status(StatusCode::Failure).into(), io::ErrorKind::Other),
+        (
+            Error::StaleHandle(status(StatusCode::NoSuchFile)),
+            io::ErrorKind::NotFound,
+        ),
+        (Error::IO("broken pipe".to_owned()), io::ErrorKind::Other),
+        (Error::Timeout, io::ErrorKind::TimedOut),
+        (
+            Error::Limited("read of 1 MB".to_owned()),
+            io::ErrorKind::InvalidInput,
+        ),
+        (
+            Error::InvalidPattern("[".to_owned()),
+            io::ErrorKind::InvalidInput,
+        ),
+        (
+            Error::TimeOverflow(FileTime::from_secs(1 << 32)),
+            io::ErrorKind::InvalidInput,
+        ),
+        (
+            Error::ExtensionUnsupported {
+                name: "hardlink@openssh.com",
+            },
+            io::ErrorKind::Unsupported,
+        ),
+        (
+            Error::VersionUnsupported("6".to_owned()),
+            io::ErrorKind::Unsupported,
+        ),
+        (
+            Error::LengthMismatch {
+                expected: 10,
+                received: 5,
+            },
+            io::ErrorKind::UnexpectedEof,
+        ),
+        (
+            Error::LengthMismatch {
+                expected: 5,
+                received: 10,
+            },
+            io::ErrorKind::InvalidData,
+        ),
+        (
+            Error::SizeChanged {
+                expected: 10,
+                actual: None,
+            },
+            io::ErrorKind::InvalidData,
+        ),
+        (
+            Error::Framing("length 0".to_owned()),
+            io::ErrorKind::InvalidData,
+        ),
+        (Error::UnexpectedPacket, io::ErrorKind::InvalidData),
+        (
+            Error::UnexpectedBehavior("reply".to_owned()),
+            io::ErrorKind::InvalidData,
+        ),
+    ];
+
+    for (err, kind) in table {
+        let message = err.to_string();
+        let io = io::Error::from(err);
+        assert_eq!(io.kind(), kind, "{message}");
+        // the client error is kept as the source
+        assert_eq!(io.to_string(), message);
+        assert!(io.get_ref().unwrap().is::<Error>(), "{message}");
+    }
+}
@@ -0,0 +1,230 @@
+//! Messages of the errors of handlers replied to the client, the targets
+//! of the failed requests and the mapping of the codes to I/O errors.
+
+use russh_sftp::{
+    client::{error::Error, SftpSession},
+    protocol::{
+        Data, FileAttributes, Handle, Name, OpenFlags, RequestId, Status, StatusCode, Version,
+    },
+    server::{self, Handler, StatusError},
+};
+use std::{collections::HashMap, io};
+use tokio::io::AsyncReadExt;
+
+/// Fails the modifications with messages for the user
+struct Quota;
+
+#[async_trait::async_trait]
+impl Handler for Quota {
+    type Error = StatusError;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported.into()
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn mkdir(
+        &mut self,
+        _id: RequestId,
+        _path: String,
+        _attrs: FileAttributes,
+    ) -> Result<Status, Self::Error> {
+        Err(StatusError::new(
+            StatusCode::Failure,
+            "disk quota exceeded: 1.2 GB over",
+        ))
+    }
+
+    async fn remove(&mut self, _id: RequestId, _filename: String) -> Result<Status, Self::Error> {
+        Err(StatusError::new(StatusCode::PermissionDenied, "read-only").with_language_tag("en"))
+    }
+
+    async fn opendir(&mut self, id: RequestId, _path: String) -> Result<Handle, Self::Error> {
+        Ok(Handle {
+            id,
+            handle: "00000000000000a1".to_owned(),
+        })
+    }
+
+    async fn readdir(&mut self, _id: RequestId, _handle: String) -> Result<Name, Self::Error> {
+        Err(StatusCode::PermissionDenied.into())
+    }
+
+    async fn open(
+        &mut self,
+        id: RequestId,
+        filename: String,
+        _pflags: OpenFlags,
+        _attrs: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        Ok(Handle {
+            id,
+            handle: filename,
+        })
+    }
+
+    async fn read(
+        &mut self,
+        _id: RequestId,
+        _handle: String,
+        _offset: u64,
+        _len: u32,
+    ) -> Result<Data, Self::Error> {
+        Err(StatusCode::PermissionDenied.into())
+    }
+}
+
+async fn connect<H: Handler + Send + 'static>(handler: H) -> SftpSession {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    server::run(server, handler).await;
+    SftpSession::new(client).await.unwrap()
+}
+
+fn into_status(err: Error) -> Status {
+    into_target(err).0
+}
+
+fn into_target(err: Error) -> (Status, Option<String>) {
+    match err {
+        Error::Status { status, target } => (status, target),
+        err => panic!("{err} is not a status"),
+    }
+}
+
+#[tokio::test]
+async fn message_reaches_client() {
+    let sftp = connect(Quota).await;
+
+    let err = sftp.create_dir("/dir").await.unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Failure: disk quota exceeded: 1.2 GB over (/dir)"
+    );
+
+    let status = into_status(sftp.remove_file("/file").await.unwrap_err());
+    assert_eq!(status.status_code, StatusCode::PermissionDenied);
+    assert_eq!(status.error_message, "read-only");
+    assert_eq!(status.language_tag, "en");
+
+    // without a message, the crate describes the code
+    let status = into_status(sftp.remove_dir("/dir").await.unwrap_err());
+    assert_eq!(status.status_code, StatusCode::OpUnsupported);
+    assert_eq!(status.error_message, StatusCode::OpUnsupported.to_string());
+    assert_eq!(status.language_tag, "en-US");
+}
+
+#[tokio::test]
+async fn message_kept_by_boxed_handlers() {
+    let sftp = connect(Quota.into_boxed()).await;
+
+    let err = sftp.create_dir("/dir").await.unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Failure: disk quota exceeded: 1.2 GB over (/dir)"
+    );
+}
+
+#[tokio::test]
+async fn target_of_failed_request() {
+    let sftp = connect(Quota).await;
+
+    let (status, target) = into_target(sftp.remove_file("/data/a.csv").await.unwrap_err());
+    assert_eq!(status.status_code, StatusCode::PermissionDenied);
+    assert_eq!(target.as_deref(), Some("/data/a.csv"));
+
+    // the listing fails on the handle, but reports the directory
+    let Err(err) = sftp.read_dir("/data").await else {
+        panic!("listed a directory without permission");
+    };
+    assert_eq!(
+        err.to_string(),
+        "Permission denied: Permission denied (/data)"
+    );
+    assert_eq!(into_target(err).1.as_deref(), Some("/data"));
+}
+
+#[test]
+fn code_descriptions() {
+    let descriptions = [
+        (StatusCode::Ok, "Success"),
+        (StatusCode::Eof, "End of file"),
+        (StatusCode::NoSuchFile, "No such file"),
+        (StatusCode::PermissionDenied, "Permission denied"),
+        (StatusCode::Failure, "Failure"),
+        (StatusCode::BadMessage, "Bad message"),
+        (StatusCode::NoConnection, "No connection"),
+        (StatusCode::ConnectionLost, "Connection lost"),
+        (StatusCode::OpUnsupported, "Operation unsupported"),
+    ];
+
+    for (code, description) in descriptions {
+        assert_eq!(code.to_string(), description);
+        assert_eq!(Status::new(RequestId(1), code).error_message, description);
+    }
+}
+
+#[test]
+fn code_to_io_kind() {
+    let kinds = [
+        (StatusCode::Ok, io::ErrorKind::Other),
+        (StatusCode::Eof, io::ErrorKind::UnexpectedEof),
+        (StatusCode::NoSuchFile, io::ErrorKind::NotFound),
+        (
+            StatusCode::PermissionDenied,
+            io::ErrorKind::PermissionDenied,
+        ),
+        (StatusCode::Failure, io::ErrorKind::Other),
+        (StatusCode::BadMessage, io::ErrorKind::Other),
+        (StatusCode::NoConnection, io::ErrorKind::NotConnected),
+        (StatusCode::ConnectionLost, io::ErrorKind::ConnectionAborted),
+        (StatusCode::OpUnsupported, io::ErrorKind::Unsupported),
+    ];
+
+    for (code, kind) in kinds {
+        assert_eq!(io::ErrorKind::from(code), kind, "{code:?}");
+    }
+}
+
+#[test]
+fn io_kind_to_code() {
+    let codes = [
+        (io::ErrorKind::UnexpectedEof, StatusCode::Eof),
+        (io::ErrorKind::NotFound, StatusCode::NoSuchFile),
+        (
+            io::ErrorKind::PermissionDenied,
+            StatusCode::PermissionDenied,
+        ),
+        (io::ErrorKind::Unsupported, StatusCode::OpUnsupported),
+        (io::ErrorKind::Other, StatusCode::Failure),
+        (io::ErrorKind::InvalidData, StatusCode::Failure),
+        // the connection codes are generated by clients only
+        (io::ErrorKind::NotConnected, StatusCode::Failure),
+        (io::ErrorKind::ConnectionAborted, StatusCode::Failure),
+    ];
+
+    for (kind, code) in codes {
+        assert_eq!(StatusCode::from(kind), code, "{kind:?}");
+    }
+}
+
+#[test]
+fn io_error_replied_with_message() {
+    let err = StatusError::from(io::Error::new(io::ErrorKind::NotFound, "no /a.csv"));
+    assert_eq!(err, StatusError::new(StatusCode::NoSuchFile, "no /a.csv"),);
+}
+
+#[tokio::test]
+async fn file_error_kind() {
+    let sftp = connect(Quota).await;
+    let mut file = sftp.open("/a.csv").await.unwrap();
+
+    let err = file.read(&mut [0; 16]).await.unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+}
@@ -0,0 +1,155 @@
+//! Messages and language tags of the statuses converted from handler errors,
+//! formatted for the language of the session.
+
+use bytes::{Bytes, BytesMut};
+use russh_sftp::{
+    framing::{read_packet, write_packet},
+    protocol::{Attrs, Init, Packet, Remove, RequestId, Stat, Status, StatusCode, Version},
+    server::{self, Config, Handler, SessionContext, StatusError},
+};
+use std::{collections::HashMap, sync::Arc};
+use tokio::io::{AsyncWriteExt, DuplexStream};
+
+/// `/denied` fails with a message of its own, other paths without one
+struct Denying;
+
+#[async_trait::async_trait]
+impl Handler for Denying {
+    type Error = StatusError;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported.into()
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn stat(&mut self, _id: RequestId, path: String) -> Result<Attrs, Self::Error> {
+        match path.as_str() {
+            "/denied" => Err(StatusError::new(StatusCode::PermissionDenied, "read-only")),
+            _ => Err(StatusCode::NoSuchFile.into()),
+        }
+    }
+}
+
+/// Messages in French or German, in English otherwise
+fn localized(code: StatusCode, context: &SessionContext) -> (String, String) {
+    let message = match (code, context.language()) {
+        (StatusCode::NoSuchFile, Some("fr")) => "Fichier introuvable",
+        (StatusCode::NoSuchFile, Some("de")) => "Datei nicht gefunden",
+        (StatusCode::OpUnsupported, Some("fr")) => "Opération non prise en charge",
+        _ => return (code.to_string(), "en".to_owned()),
+    };
+
+    (message.to_owned(), context.language().unwrap().to_owned())
+}
+
+async fn connect(config: Config, extensions: &[(&str, &str)]) -> DuplexStream {
+    let (mut client, server) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(server::serve(server, Denying, config));
+
+    let init = Init {
+        version: 3,
+        extensions: extensions
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect(),
+    };
+    let init = Bytes::try_from(Packet::from(init)).unwrap();
+    client.write_all(&init).await.unwrap();
+    read_packet(&mut client, &mut BytesMut::new())
+        .await
+        .unwrap();
+
+    client
+}
+
+/// Status replied on the wire to the request
+async fn status(client: &mut DuplexStream, request: Packet) -> Status {
+    let frame = Bytes::try_from(request).unwrap();
+    write_packet(client, &frame[4..]).await.unwrap();
+    let mut reply = read_packet(client, &mut BytesMut::new()).await.unwrap();
+    match Packet::try_from(&mut reply).unwrap() {
+        Packet::Status(status) => status,
+        packet => panic!("{} is not a status", packet.name()),
+    }
+}
+
+fn stat(id: u32, path: &str) -> Packet {
+    Stat {
+        id: RequestId(id),
+        path: path.to_owned(),
+    }
+    .into()
+}
+
+fn formatting(language: Option<&str>) -> Config {
+    Config {
+        status_formatter: Some(Arc::new(localized)),
+        language: language.map(str::to_owned),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn language_of_the_config() {
+    let mut client = connect(formatting(Some("fr")), &[]).await;
+
+    let status = status(&mut client, stat(1, "/missing")).await;
+    assert_eq!(status.id, RequestId(1));
+    assert_eq!(status.status_code, StatusCode::NoSuchFile);
+    assert_eq!(status.error_message, "Fichier introuvable");
+    assert_eq!(status.language_tag, "fr");
+}
+
+#[tokio::test]
+async fn language_of_the_client_preferred() {
+    let mut client = connect(formatting(Some("fr")), &[("language@example.com", "de")]).await;
+
+    let status = status(&mut client, stat(1, "/missing")).await;
+    assert_eq!(status.error_message, "Datei nicht gefunden");
+    assert_eq!(status.language_tag, "de");
+}
+
+#[tokio::test]
+async fn unimplemented_requests_formatted() {
+    let mut client = connect(formatting(Some("fr")), &[]).await;
+
+    let remove = Packet::Remove(Remove {
+        id: RequestId(2),
+        filename: "/f".to_owned(),
+    });
+    let status = status(&mut client, remove).await;
+    assert_eq!(status.status_code, StatusCode::OpUnsupported);
+    assert_eq!(status.error_message, "Opération non prise en charge");
+    assert_eq!(status.language_tag, "fr");
+}
+
+#[tokio::test]
+async fn message_of_the_handler_kept() {
+    let mut client = connect(formatting(Some("fr")), &[]).await;
+
+    let status = status(&mut client, stat(1, "/denied")).await;
+    assert_eq!(status.status_code, StatusCode::PermissionDenied);
+    assert_eq!(status.error_message, "read-only");
+    assert_eq!(status.language_tag, "en-US");
+}
+
+#[tokio::test]
+async fn unchanged_without_a_formatter() {
+    let config = Config {
+        language: Some("fr".to_owned()),
+        ..Default::default()
+    };
+    let mut client = connect(config, &[("language@example.com", "de")]).await;
+
+    let status = status(&mut client, stat(1, "/missing")).await;
+    let default = Status::new(RequestId(1), StatusCode::NoSuchFile);
+    assert_eq!(status.error_message, default.error_message);
+    assert_eq!(status.language_tag, default.language_tag);
+}
@@ -0,0 +1,142 @@
+//! Streaming of whole files between the session and readers or writers.
+
+mod common;
+
+use common::Memory;
+use russh_sftp::{client::SftpSession, server};
+use std::{
+    io,
+    pin::Pin,
+    sync::atomic::Ordering,
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+const CHUNK: usize = 261120;
+/// Chunks that may be requested ahead of the reader or the writer, with margin
+const AHEAD: usize = 16 + 2;
+
+fn contents() -> Vec<u8> {
+    (0..CHUNK * 40 + 1234)
+        .map(|i| (i * 31 % 251) as u8)
+        .collect()
+}
+
+async fn connect(memory: Memory) -> SftpSession {
+    let (client, server) = tokio::io::duplex(1024 * 1024);
+    server::run(server, memory).await;
+    SftpSession::new(client).await.unwrap()
+}
+
+/// Collects the written bytes and checks that the reads of the server stay
+/// close to them
+struct CountingWriter {
+    memory: Memory,
+    written: Vec<u8>,
+    max_ahead: usize,
+}
+
+impl AsyncWrite for CountingWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let requested = self.memory.reads.load(Ordering::Relaxed) as usize * CHUNK;
+        let ahead = requested.saturating_sub(self.written.len()) / CHUNK;
+        self.max_ahead = self.max_ahead.max(ahead);
+
+        self.written.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Yields the contents in small pieces and checks that the bytes taken
+/// stay close to the ones stored by the server
+struct CountingReader {
+    memory: Memory,
+    path: &'static str,
+    data: Vec<u8>,
+    taken: usize,
+    max_ahead: usize,
+}
+
+impl AsyncRead for CountingReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        let stored = this.memory.files.lock().unwrap()[this.path].len();
+        let ahead = this.taken.saturating_sub(stored) / CHUNK;
+        this.max_ahead = this.max_ahead.max(ahead);
+
+        let len = buf
+            .remaining()
+            .min(10_000)
+            .min(this.data.len() - this.taken);
+        buf.put_slice(&this.data[this.taken..this.taken + len]);
+        this.taken += len;
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[tokio::test]
+async fn read_to_writer_round_trip() {
+    let memory = Memory::default();
+    let data = contents();
+    memory
+        .files
+        .lock()
+        .unwrap()
+        .insert("/big".to_owned(), data.clone());
+
+    let sftp = connect(memory.clone()).await;
+    let mut writer = CountingWriter {
+        memory,
+        written: vec![],
+        max_ahead: 0,
+    };
+
+    let copied = sftp.read_to_writer("/big", &mut writer).await.unwrap();
+    assert_eq!(copied, data.len() as u64);
+    assert!(writer.written == data);
+    assert!(
+        writer.max_ahead <= AHEAD,
+        "{} chunks ahead",
+        writer.max_ahead
+    );
+}
+
+#[tokio::test]
+async fn write_from_reader_round_trip() {
+    let memory = Memory::default();
+    let data = contents();
+
+    let sftp = connect(memory.clone()).await;
+    let mut reader = CountingReader {
+        memory: memory.clone(),
+        path: "/big",
+        data: data.clone(),
+        taken: 0,
+        max_ahead: 0,
+    };
+
+    let copied = sftp.write_from_reader("/big", &mut reader).await.unwrap();
+    assert_eq!(copied, data.len() as u64);
+    assert!(memory.files.lock().unwrap()["/big"] == data);
+    assert!(
+        reader.max_ahead <= AHEAD,
+        "{} chunks ahead",
+        reader.max_ahead
+    );
+}
@@ -0,0 +1,255 @@
+//! Pending requests failed as soon as either half of the stream is lost,
+//! rather than after the response timeout, once the replies already received
+//! are delivered.
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use russh_sftp::{
+    client::{error::Error, RawSftpSession},
+    protocol::{Attrs, FileAttributes, Packet, RequestId, StatusCode},
+};
+use std::{
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream, ReadBuf},
+    sync::oneshot,
+    task::JoinHandle,
+    time::{self, timeout},
+};
+
+/// Well below the response timeout of 10 seconds
+const PROMPTLY: Duration = Duration::from_secs(2);
+
+/// Time the client keeps dispatching replies after a write error
+const LINGER: Duration = Duration::from_millis(100);
+
+const SSH_FXP_VERSION: u8 = 2;
+
+/// Reads a frame and returns its type
+async fn read_frame(stream: &mut DuplexStream) -> io::Result<u8> {
+    let len = stream.read_u32().await?;
+    let mut frame = vec![0; len as usize];
+    stream.read_exact(&mut frame).await?;
+    Ok(frame.as_slice().get_u8())
+}
+
+/// Reads a request and returns its id
+async fn read_id(stream: &mut DuplexStream) -> RequestId {
+    let len = stream.read_u32().await.unwrap();
+    let mut frame = vec![0; len as usize];
+    stream.read_exact(&mut frame).await.unwrap();
+    RequestId((&frame[1..]).get_u32())
+}
+
+/// Answers a stat request
+async fn reply(stream: &mut DuplexStream, id: RequestId) {
+    let attrs = Packet::from(Attrs {
+        id,
+        attrs: FileAttributes::empty(),
+    });
+    stream
+        .write_all(&Bytes::try_from(attrs).unwrap())
+        .await
+        .unwrap();
+}
+
+/// Answers SSH_FXP_INIT with version 3
+async fn accept(stream: &mut DuplexStream) {
+    read_frame(stream).await.unwrap();
+    let mut version = BytesMut::new();
+    version.put_u32(5);
+    version.put_u8(SSH_FXP_VERSION);
+    version.put_u32(3);
+    stream.write_all(&version).await.unwrap();
+}
+
+fn connection_lost(result: Result<impl std::fmt::Debug, Error>) {
+    match result {
+        Err(Error::Status { status, .. }) if status.status_code == StatusCode::ConnectionLost => (),
+        result => panic!("{result:?}"),
+    }
+}
+
+#[tokio::test]
+async fn pending_request_failed_on_eof() {
+    let (client, mut server) = tokio::io::duplex(64 * 1024);
+    let (received_tx, received) = oneshot::channel();
+    tokio::spawn(async move {
+        accept(&mut server).await;
+        read_frame(&mut server).await.unwrap();
+        received_tx.send(()).unwrap();
+        // dropped with the request unanswered
+    });
+
+    let session = RawSftpSession::new(client);
+    session.init().await.unwrap();
+
+    let pending = session.stat("/data");
+    let result = timeout(PROMPTLY, pending)
+        .await
+        .expect("failed only after the timeout");
+    received.await.unwrap();
+    connection_lost(result);
+
+    assert!(session.closed_reason().is_some());
+    let later = timeout(PROMPTLY, session.stat("/data")).await.unwrap();
+    connection_lost(later);
+}
+
+/// Duplex stream whose writes fail once broken
+struct Breakable {
+    inner: DuplexStream,
+    broken: Arc<AtomicBool>,
+}
+
+impl AsyncRead for Breakable {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Breakable {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if self.broken.load(Ordering::Relaxed) {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[tokio::test]
+async fn pending_requests_failed_on_write_error() {
+    let (client, mut server) = tokio::io::duplex(64 * 1024);
+    let broken = Arc::new(AtomicBool::new(false));
+    let (received_tx, received) = oneshot::channel();
+    tokio::spawn(async move {
+        accept(&mut server).await;
+        read_frame(&mut server).await.unwrap();
+        received_tx.send(()).unwrap();
+        // kept open without answering
+        std::future::pending::<()>().await;
+    });
+
+    let session = Arc::new(RawSftpSession::new(Breakable {
+        inner: client,
+        broken: broken.clone(),
+    }));
+    session.init().await.unwrap();
+
+    let first = tokio::spawn({
+        let session = session.clone();
+        async move { session.stat("/a").await }
+    });
+    received.await.unwrap();
+
+    broken.store(true, Ordering::Relaxed);
+    let second = timeout(PROMPTLY, session.stat("/b")).await.unwrap();
+    connection_lost(second);
+    let first = timeout(PROMPTLY, first).await.unwrap().unwrap();
+    connection_lost(first);
+
+    assert!(matches!(session.closed_reason(), Some(Error::IO(_))));
+}
+
+/// Session whose writes break on demand, with two stat requests read by the server
+async fn two_pending(
+    server: &mut DuplexStream,
+    client: DuplexStream,
+) -> (
+    Arc<RawSftpSession>,
+    Arc<AtomicBool>,
+    [(RequestId, StatHandle); 2],
+) {
+    let broken = Arc::new(AtomicBool::new(false));
+    let session = Arc::new(RawSftpSession::new(Breakable {
+        inner: client,
+        broken: broken.clone(),
+    }));
+    let init = tokio::spawn({
+        let session = session.clone();
+        async move { session.init().await.map(|_| ()) }
+    });
+    accept(server).await;
+    init.await.unwrap().unwrap();
+
+    let pending = ["/a", "/c"].map(|path| {
+        let session = session.clone();
+        tokio::spawn(async move { session.stat(path).await.map(|_| ()) })
+    });
+    let [a, c] = pending;
+    let ids = [read_id(server).await, read_id(server).await];
+    (session, broken, [(ids[0], a), (ids[1], c)])
+}
+
+type StatHandle = JoinHandle<Result<(), Error>>;
+
+#[tokio::test(start_paused = true)]
+async fn reply_after_a_write_error_delivered() {
+    let (client, mut server) = tokio::io::duplex(64 * 1024);
+    let (session, broken, [(a, first), (_, unanswered)]) = two_pending(&mut server, client).await;
+
+    // the write of another request fails while the reply of the first is on its way
+    broken.store(true, Ordering::Relaxed);
+    let failed = tokio::spawn({
+        let session = session.clone();
+        async move { session.stat("/b").await.map(|_| ()) }
+    });
+    time::sleep(LINGER / 2).await;
+    assert!(!failed.is_finished(), "failed before the linger");
+    reply(&mut server, a).await;
+
+    first.await.unwrap().unwrap();
+
+    // the reply renewed the linger
+    time::sleep(LINGER * 3 / 4).await;
+    assert!(
+        !unanswered.is_finished(),
+        "failed before the renewed linger"
+    );
+    assert!(session.closed_reason().is_none());
+
+    time::sleep(LINGER / 2).await;
+    connection_lost(unanswered.await.unwrap());
+    connection_lost(failed.await.unwrap());
+    assert!(matches!(session.closed_reason(), Some(Error::IO(_))));
+}
+
+#[tokio::test(start_paused = true)]
+async fn reply_before_eof_delivered() {
+    let (client, mut server) = tokio::io::duplex(64 * 1024);
+    let (session, _, [(a, first), (_, unanswered)]) = two_pending(&mut server, client).await;
+
+    // the reply and the end of the stream arrive together
+    reply(&mut server, a).await;
+    drop(server);
+
+    first.await.unwrap().unwrap();
+    connection_lost(unanswered.await.unwrap());
+    assert!(matches!(
+        session.closed_reason(),
+        Some(Error::IO(msg)) if msg.contains("EOF")
+    ));
+}
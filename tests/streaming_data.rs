@@ -0,0 +1,230 @@
+//! Replies to SSH_FXP_READ streamed from a reader, compared on the wire to the same
+//! data replied as a whole.
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use russh_sftp::{
+    client::SftpSession,
+    protocol::{Data, FileAttributes, Handle, OpenFlags, RequestId, Status, StatusCode, Version},
+    server::{self, Config, Handler, Responder, SessionContext},
+    Error,
+};
+use std::{collections::HashMap, io::Cursor};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, DuplexStream},
+    task::JoinHandle,
+};
+
+const SSH_FXP_INIT: u8 = 1;
+const SSH_FXP_READ: u8 = 5;
+
+/// Serves one blob, replied as a whole or streamed from a copy of it
+#[derive(Clone)]
+struct Blob {
+    data: Vec<u8>,
+    streaming: bool,
+    /// Bytes missing from the reader of the streamed data
+    short: usize,
+    responder: Responder,
+}
+
+impl Blob {
+    fn new(len: usize, streaming: bool) -> Self {
+        Self {
+            data: (0..len).map(|i| (i % 241) as u8).collect(),
+            streaming,
+            short: 0,
+            responder: Responder::default(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Handler for Blob {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    fn set_context(&mut self, context: &SessionContext) {
+        self.responder = context.responder().clone();
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn open(
+        &mut self,
+        id: RequestId,
+        _filename: String,
+        _pflags: OpenFlags,
+        _attrs: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        Ok(Handle {
+            id,
+            handle: "blob".to_owned(),
+        })
+    }
+
+    async fn close(&mut self, id: RequestId, _handle: String) -> Result<Status, Self::Error> {
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+
+    async fn read(
+        &mut self,
+        id: RequestId,
+        _handle: String,
+        offset: u64,
+        len: u32,
+    ) -> Result<Data, Self::Error> {
+        let start = offset as usize;
+        if start >= self.data.len() {
+            return Err(StatusCode::Eof);
+        }
+
+        let end = (start + len as usize).min(self.data.len());
+        if !self.streaming {
+            return Ok(Data::from_bytes(
+                id,
+                Bytes::copy_from_slice(&self.data[start..end]),
+            ));
+        }
+
+        let reader = Cursor::new(self.data[start..end - self.short].to_vec());
+        let len = (end - start) as u32;
+        Ok(self.responder.send_data_streaming(id, len, reader))
+    }
+}
+
+async fn write_frame(stream: &mut DuplexStream, frame: BytesMut) {
+    stream.write_u32(frame.len() as u32).await.unwrap();
+    stream.write_all(&frame).await.unwrap();
+}
+
+/// Reads a frame with its length
+async fn read_frame(stream: &mut DuplexStream) -> Bytes {
+    let len = stream.read_u32().await.unwrap();
+    let mut frame = vec![0; len as usize];
+    stream.read_exact(&mut frame).await.unwrap();
+
+    let mut bytes = BytesMut::new();
+    bytes.put_u32(len);
+    bytes.put_slice(&frame);
+    bytes.freeze()
+}
+
+async fn start(handler: Blob, config: Config) -> (DuplexStream, JoinHandle<Result<(), Error>>) {
+    let (mut client, stream) = tokio::io::duplex(16 * 1024);
+    let server = tokio::spawn(server::serve(stream, handler, config));
+
+    let mut init = BytesMut::new();
+    init.put_u8(SSH_FXP_INIT);
+    init.put_u32(3);
+    write_frame(&mut client, init).await;
+    read_frame(&mut client).await;
+
+    (client, server)
+}
+
+async fn send_read(client: &mut DuplexStream, offset: u64, len: u32) {
+    let mut read = BytesMut::new();
+    read.put_u8(SSH_FXP_READ);
+    read.put_u32(7);
+    read.put_u32(4);
+    read.put_slice(b"blob");
+    read.put_u64(offset);
+    read.put_u32(len);
+    write_frame(client, read).await;
+}
+
+/// Frame of the reply to a read of the blob of `blob_len` bytes
+async fn reply(streaming: bool, blob_len: usize, len: u32, config: Config) -> Bytes {
+    let (mut client, _server) = start(Blob::new(blob_len, streaming), config).await;
+    send_read(&mut client, 0, len).await;
+    read_frame(&mut client).await
+}
+
+#[tokio::test]
+async fn same_bytes_as_whole_data() {
+    for (blob_len, len) in [
+        (100, 100),
+        (100, 1000),
+        (100_000, 100_000),
+        (70_000, 65_536),
+    ] {
+        let whole = reply(false, blob_len, len, Config::default()).await;
+        let streamed = reply(true, blob_len, len, Config::default()).await;
+        assert_eq!(whole, streamed, "{blob_len} {len}");
+    }
+}
+
+#[tokio::test]
+async fn truncated_like_whole_data() {
+    let config = Config {
+        max_response_packet_len: Some(10_000),
+        ..Default::default()
+    };
+    let whole = reply(false, 100_000, 65_536, config.clone()).await;
+    let streamed = reply(true, 100_000, 65_536, config).await;
+    assert_eq!(whole, streamed);
+    assert_eq!(whole.len(), 4 + 10_000);
+
+    // beyond the default maximum of 256 KB
+    let whole = reply(false, 300_000, 300_000, Config::default()).await;
+    let streamed = reply(true, 300_000, 300_000, Config::default()).await;
+    assert_eq!(whole, streamed);
+}
+
+#[tokio::test]
+async fn followed_by_other_replies() {
+    let (mut client, _server) = start(Blob::new(100_000, true), Config::default()).await;
+    send_read(&mut client, 0, 50_000).await;
+    send_read(&mut client, 50_000, 50_000).await;
+    send_read(&mut client, 100_000, 10).await;
+
+    let mut received = Vec::new();
+    for _ in 0..2 {
+        let mut frame = read_frame(&mut client).await;
+        frame.advance(4 + 1 + 4);
+        let len = frame.get_u32() as usize;
+        assert_eq!(frame.len(), len);
+        received.extend_from_slice(&frame);
+    }
+    assert!(received == Blob::new(100_000, true).data);
+
+    // SSH_FXP_STATUS with Eof
+    let mut frame = read_frame(&mut client).await;
+    frame.advance(4);
+    assert_eq!(frame.get_u8(), 101);
+}
+
+#[tokio::test]
+async fn short_reader_ends_the_session() {
+    let handler = Blob {
+        short: 10,
+        ..Blob::new(1000, true)
+    };
+    let (mut client, server) = start(handler, Config::default()).await;
+    send_read(&mut client, 0, 1000).await;
+
+    match server.await.unwrap() {
+        Err(Error::IO(msg)) => assert!(msg.contains("10 of 1000 bytes short"), "{msg}"),
+        result => panic!("{result:?}"),
+    }
+}
+
+#[tokio::test]
+async fn read_by_the_client() {
+    let handler = Blob::new(200_000, true);
+    let (sftp, _server) = SftpSession::pair(handler.clone()).await.unwrap();
+
+    let mut received = Vec::new();
+    let mut file = sftp.open("/blob").await.unwrap();
+    file.read_to_end(&mut received).await.unwrap();
+    assert!(received == handler.data);
+}
@@ -0,0 +1,136 @@
+//! Order of the paths of SSH_FXP_SYMLINK on the wire, for the servers following
+//! OpenSSH and those following the specification draft.
+
+use russh_sftp::{
+    client::{rawsession::SymlinkOrder, SftpSession},
+    extensions,
+    protocol::{RequestId, Status, StatusCode, Version},
+    server::{Config, Handler},
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// Records the paths of SSH_FXP_SYMLINK as received, `linkpath` first
+#[derive(Clone, Default)]
+struct Recorder {
+    vendor: Option<&'static str>,
+    received: Arc<Mutex<Vec<(String, String)>>>,
+}
+
+impl Recorder {
+    fn vendor(vendor: &'static str) -> Self {
+        Self {
+            vendor: Some(vendor),
+            ..Default::default()
+        }
+    }
+
+    fn received(&self) -> Vec<(String, String)> {
+        self.received.lock().unwrap().clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl Handler for Recorder {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(match self.vendor {
+            Some(vendor) => Version::new().with_extension(extensions::VENDOR_ID, vendor),
+            None => Version::new(),
+        })
+    }
+
+    async fn symlink(
+        &mut self,
+        id: RequestId,
+        linkpath: String,
+        targetpath: String,
+    ) -> Result<Status, Self::Error> {
+        self.received.lock().unwrap().push((linkpath, targetpath));
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+}
+
+fn pair(first: &str, second: &str) -> (String, String) {
+    (first.to_owned(), second.to_owned())
+}
+
+#[tokio::test]
+async fn openssh_order_by_default() {
+    let server = Recorder::default();
+    let (sftp, _server) = SftpSession::pair(server.clone()).await.unwrap();
+    assert_eq!(sftp.symlink_order().await, SymlinkOrder::OpenSsh);
+
+    sftp.create_symlink("/link", "/target").await.unwrap();
+    assert_eq!(server.received(), [pair("/target", "/link")]);
+}
+
+#[tokio::test]
+async fn openssh_order_detected() {
+    let server = Recorder::vendor("OpenSSH_9.6");
+    let (sftp, _server) = SftpSession::pair(server.clone()).await.unwrap();
+
+    sftp.create_symlink("/link", "/target").await.unwrap();
+    assert_eq!(server.received(), [pair("/target", "/link")]);
+}
+
+#[tokio::test]
+async fn draft_order_detected() {
+    let server = Recorder::vendor("Example Corp SFTP");
+    let (sftp, _server) = SftpSession::pair(server.clone()).await.unwrap();
+    assert_eq!(sftp.symlink_order().await, SymlinkOrder::Draft);
+
+    sftp.create_symlink("/link", "/target").await.unwrap();
+    assert_eq!(server.received(), [pair("/link", "/target")]);
+}
+
+#[tokio::test]
+async fn order_set_explicitly() {
+    let server = Recorder::vendor("OpenSSH_9.6");
+    let (sftp, _server) = SftpSession::pair(server.clone()).await.unwrap();
+
+    sftp.set_symlink_order(SymlinkOrder::Draft).await;
+    sftp.create_symlink("/a", "/b").await.unwrap();
+    sftp.set_symlink_order(SymlinkOrder::OpenSsh).await;
+    sftp.create_symlink("/a", "/b").await.unwrap();
+    assert_eq!(server.received(), [pair("/a", "/b"), pair("/b", "/a")]);
+}
+
+#[tokio::test]
+#[allow(deprecated)]
+async fn deprecated_symlink_sent_as_given() {
+    for vendor in ["OpenSSH_9.6", "Example Corp SFTP"] {
+        let server = Recorder::vendor(vendor);
+        let (sftp, _server) = SftpSession::pair(server.clone()).await.unwrap();
+
+        sftp.symlink("/first", "/second").await.unwrap();
+        assert_eq!(server.received(), [pair("/first", "/second")], "{vendor}");
+    }
+}
+
+#[tokio::test]
+async fn swapped_for_openssh_clients() {
+    let server = Recorder::default();
+    let config = Config {
+        swap_symlink_paths: true,
+        ..Default::default()
+    };
+    let (sftp, _server) = SftpSession::pair_with_config(server.clone(), config)
+        .await
+        .unwrap();
+
+    // sent target first, handed to the handler in the order of the draft
+    sftp.create_symlink("/link", "/target").await.unwrap();
+    assert_eq!(server.received(), [pair("/link", "/target")]);
+}
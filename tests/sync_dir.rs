@@ -0,0 +1,151 @@
+//! Directories synced with fsync on a handle of SSH_FXP_OPENDIR.
+
+use russh_sftp::{
+    client::{error::Error, SftpSession},
+    extensions::{self, Capability},
+    protocol::{Handle, RequestId, Status, StatusCode, Version},
+    server::Handler,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// Records the requests and replies to fsync with `reply`
+#[derive(Clone)]
+struct Syncing {
+    advertised: bool,
+    reply: StatusCode,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl Syncing {
+    fn new(advertised: bool, reply: StatusCode) -> Self {
+        Self {
+            advertised,
+            reply,
+            requests: Arc::default(),
+        }
+    }
+
+    fn record(&self, request: &str, target: &str) {
+        let request = format!("{request} {target}");
+        self.requests.lock().unwrap().push(request);
+    }
+
+    fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl Handler for Syncing {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    fn supported_extensions(&self) -> &'static [&'static str] {
+        match self.advertised {
+            true => &[extensions::FSYNC],
+            false => &[],
+        }
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn opendir(&mut self, id: RequestId, path: String) -> Result<Handle, Self::Error> {
+        self.record("opendir", &path);
+        match path.as_str() {
+            "/missing" => Err(StatusCode::NoSuchFile),
+            _ => Ok(Handle { id, handle: path }),
+        }
+    }
+
+    async fn close(&mut self, id: RequestId, handle: String) -> Result<Status, Self::Error> {
+        self.record("close", &handle);
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+
+    async fn fsync(&mut self, id: RequestId, handle: String) -> Result<Status, Self::Error> {
+        self.record("fsync", &handle);
+        match self.reply {
+            StatusCode::Ok => Ok(Status::new(id, StatusCode::Ok)),
+            code => Err(code),
+        }
+    }
+}
+
+fn unsupported(result: Result<(), Error>) -> bool {
+    matches!(
+        result,
+        Err(Error::ExtensionUnsupported { name }) if name == extensions::FSYNC
+    )
+}
+
+#[tokio::test]
+async fn synced_and_closed() {
+    let server = Syncing::new(true, StatusCode::Ok);
+    let (sftp, _server) = SftpSession::pair(server.clone()).await.unwrap();
+
+    sftp.sync_dir("/data").await.unwrap();
+    assert_eq!(
+        server.requests(),
+        ["opendir /data", "fsync /data", "close /data"]
+    );
+}
+
+#[tokio::test]
+async fn failure_downgraded_to_unsupported() {
+    let server = Syncing::new(true, StatusCode::Failure);
+    let (sftp, _server) = SftpSession::pair(server.clone()).await.unwrap();
+
+    assert!(unsupported(sftp.sync_dir("/data").await));
+    assert_eq!(
+        server.requests(),
+        ["opendir /data", "fsync /data", "close /data"]
+    );
+
+    // the files are still synced
+    assert!(sftp.has_capability(Capability::Fsync));
+}
+
+#[tokio::test]
+async fn not_advertised() {
+    let server = Syncing::new(false, StatusCode::Ok);
+    let (sftp, _server) = SftpSession::pair(server.clone()).await.unwrap();
+
+    assert!(unsupported(sftp.sync_dir("/data").await));
+    assert!(server.requests().is_empty());
+}
+
+#[tokio::test]
+async fn rejected_fsync_disabled() {
+    let server = Syncing::new(true, StatusCode::OpUnsupported);
+    let (sftp, _server) = SftpSession::pair(server.clone()).await.unwrap();
+
+    assert!(unsupported(sftp.sync_dir("/data").await));
+    assert!(!sftp.has_capability(Capability::Fsync));
+    assert!(unsupported(sftp.sync_dir("/data").await));
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn missing_directory() {
+    let server = Syncing::new(true, StatusCode::Ok);
+    let (sftp, _server) = SftpSession::pair(server.clone()).await.unwrap();
+
+    let result = sftp.sync_dir("/missing").await;
+    assert!(
+        matches!(&result, Err(Error::Status { status, .. }) if status.status_code == StatusCode::NoSuchFile),
+        "{result:?}"
+    );
+    assert_eq!(server.requests(), ["opendir /missing"]);
+}
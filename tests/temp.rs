@@ -0,0 +1,134 @@
+//! Temporary directories and files created with random names and cleaned up.
+
+mod common;
+
+use common::Memory;
+use russh_sftp::client::{error::Error, SftpSession};
+use std::{sync::atomic::Ordering, time::Duration};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+async fn session() -> (SftpSession, Memory) {
+    let memory = Memory::default();
+    memory
+        .files
+        .lock()
+        .unwrap()
+        .insert("/work/".to_owned(), Vec::new());
+    let (sftp, _server) = SftpSession::pair(memory.clone()).await.unwrap();
+    (sftp, memory)
+}
+
+/// Paths of the memory under `/work/`, without the directory itself
+fn work(memory: &Memory) -> Vec<String> {
+    memory
+        .files
+        .lock()
+        .unwrap()
+        .keys()
+        .filter(|path| path.starts_with("/work/") && *path != "/work/")
+        .cloned()
+        .collect()
+}
+
+/// Fills the directory with a file and a subdirectory holding another file
+async fn fill(sftp: &SftpSession, dir: &str) {
+    sftp.create_dir(format!("{dir}/sub")).await.unwrap();
+    for path in [format!("{dir}/a"), format!("{dir}/sub/b")] {
+        let mut file = sftp.create(path).await.unwrap();
+        file.write_all(b"data").await.unwrap();
+        file.shutdown().await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn tempdir_removed_on_close() {
+    let (sftp, memory) = session().await;
+
+    let dir = sftp.tempdir_in("/work").await.unwrap();
+    let name = dir.path().strip_prefix("/work/.tmp").unwrap();
+    assert_eq!(name.len(), 16);
+    assert!(name.chars().all(|c| c.is_ascii_hexdigit()), "{name}");
+    assert!(sftp.is_dir(dir.path()).await.unwrap());
+
+    fill(&sftp, dir.path()).await;
+    assert_eq!(work(&memory).len(), 4);
+
+    dir.close().await.unwrap();
+    assert!(work(&memory).is_empty(), "{:?}", work(&memory));
+    assert!(sftp.is_dir("/work").await.unwrap());
+}
+
+#[tokio::test]
+async fn tempdir_removed_on_drop() {
+    let (sftp, memory) = session().await;
+
+    let dir = sftp.tempdir_in("/work").await.unwrap();
+    fill(&sftp, dir.path()).await;
+    drop(dir);
+
+    for _ in 0..100 {
+        if work(&memory).is_empty() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("left after the drop: {:?}", work(&memory));
+}
+
+#[tokio::test]
+async fn kept_tempdir_left_in_place() {
+    let (sftp, memory) = session().await;
+
+    let path = sftp.tempdir_in("/work").await.unwrap().keep();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(work(&memory), [format!("{path}/")]);
+}
+
+#[tokio::test]
+async fn tempdir_retried_on_collision() {
+    let (sftp, memory) = session().await;
+    memory.collisions.store(1, Ordering::Relaxed);
+
+    let dir = sftp.tempdir_in("/work").await.unwrap();
+    let paths = work(&memory);
+    assert_eq!(paths.len(), 2, "{paths:?}");
+
+    // the name taken by someone else is left alone
+    dir.close().await.unwrap();
+    let taken = work(&memory);
+    assert_eq!(taken.len(), 1);
+    assert!(paths.contains(&taken[0]));
+}
+
+#[tokio::test]
+async fn tempfile_retried_on_collision() {
+    let (sftp, memory) = session().await;
+    memory.collisions.store(2, Ordering::Relaxed);
+
+    let (path, mut file) = sftp.tempfile_in("/work", "job-").await.unwrap();
+    assert!(path.starts_with("/work/job-"), "{path}");
+    file.write_all(b"scratch").await.unwrap();
+    file.rewind().await.unwrap();
+    let mut data = String::new();
+    file.read_to_string(&mut data).await.unwrap();
+    assert_eq!(data, "scratch");
+    file.shutdown().await.unwrap();
+
+    let files = memory.files.lock().unwrap().clone();
+    let taken = files.iter().filter(|(_, data)| *data == b"taken").count();
+    assert_eq!(taken, 2);
+    assert_eq!(files[&path], b"scratch");
+}
+
+#[tokio::test]
+async fn collisions_give_up() {
+    let (sftp, memory) = session().await;
+    memory.collisions.store(100, Ordering::Relaxed);
+
+    let result = sftp
+        .tempfile_in("/work", "job-")
+        .await
+        .map(|(path, _)| path);
+    assert!(matches!(result, Err(Error::Status { .. })), "{result:?}");
+    assert_eq!(work(&memory).len(), 8);
+}
@@ -0,0 +1,130 @@
+//! Pacing of the requests of a session per `Config::max_requests_per_second`.
+
+use russh_sftp::{
+    client::SftpSession,
+    protocol::{Attrs, FileAttributes, RequestId, StatusCode, Version},
+    server::{self, Config, Handler, SessionContext},
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::time::{Duration, Instant};
+
+/// Replies to stat and records when each request was handled
+#[derive(Clone, Default)]
+struct Recorder {
+    handled: Arc<Mutex<Vec<Instant>>>,
+    context: Arc<Mutex<SessionContext>>,
+}
+
+#[async_trait::async_trait]
+impl Handler for Recorder {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    fn set_context(&mut self, context: &SessionContext) {
+        *self.context.lock().unwrap() = context.clone();
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn stat(&mut self, id: RequestId, _path: String) -> Result<Attrs, Self::Error> {
+        self.handled.lock().unwrap().push(Instant::now());
+        Ok(Attrs {
+            id,
+            attrs: FileAttributes::empty(),
+        })
+    }
+}
+
+async fn connect(recorder: &Recorder, rate: Option<u32>) -> Arc<SftpSession> {
+    let config = Config {
+        max_requests_per_second: rate,
+        ..Default::default()
+    };
+
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(server::serve(server, recorder.clone(), config));
+    Arc::new(SftpSession::new(client).await.unwrap())
+}
+
+/// Sends `count` stats at once and waits for all the replies
+async fn burst(sftp: &Arc<SftpSession>, count: usize) {
+    let stats = (0..count)
+        .map(|i| {
+            let sftp = sftp.clone();
+            tokio::spawn(async move { sftp.metadata(format!("/missing/{i}")).await })
+        })
+        .collect::<Vec<_>>();
+
+    for stat in stats {
+        stat.await.unwrap().unwrap();
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn burst_paced() {
+    let recorder = Recorder::default();
+    let sftp = connect(&recorder, Some(10)).await;
+
+    let started = Instant::now();
+    burst(&sftp, 30).await;
+
+    // SSH_FXP_INIT took a token: 9 at once, then 21 at 100 ms intervals
+    let handled = recorder.handled.lock().unwrap().clone();
+    assert_eq!(handled.len(), 30);
+    assert!(handled[8] - started < Duration::from_millis(1));
+    for pair in handled[9..].windows(2) {
+        let interval = pair[1] - pair[0];
+        assert!(interval >= Duration::from_millis(99), "{interval:?}");
+        assert!(interval <= Duration::from_millis(101), "{interval:?}");
+    }
+
+    let elapsed = started.elapsed();
+    assert!((2090..2110).contains(&elapsed.as_millis()), "{elapsed:?}");
+
+    let context = recorder.context.lock().unwrap().clone();
+    assert_eq!(context.throttled_requests(), 21);
+    assert!(context.request_tokens().unwrap() < 1.0);
+}
+
+#[tokio::test(start_paused = true)]
+async fn idle_session_refilled() {
+    let recorder = Recorder::default();
+    let sftp = connect(&recorder, Some(10)).await;
+
+    burst(&sftp, 10).await;
+    tokio::time::sleep(Duration::from_secs(5)).await;
+
+    // a second of requests is available again, no more
+    let started = Instant::now();
+    burst(&sftp, 12).await;
+    assert!((190..210).contains(&started.elapsed().as_millis()));
+
+    let context = recorder.context.lock().unwrap().clone();
+    assert_eq!(context.throttled_requests(), 1 + 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn unlimited_by_default() {
+    let recorder = Recorder::default();
+    let sftp = connect(&recorder, None).await;
+
+    let started = Instant::now();
+    burst(&sftp, 100).await;
+    assert_eq!(started.elapsed(), Duration::ZERO);
+
+    let context = recorder.context.lock().unwrap().clone();
+    assert_eq!(context.throttled_requests(), 0);
+    assert_eq!(context.request_tokens(), None);
+}
@@ -0,0 +1,208 @@
+//! Truncated and corrupted packets fail to decode without panicking.
+
+mod common;
+
+use bytes::{Bytes, BytesMut};
+use common::Rng;
+use russh_sftp::{
+    framing::{encode_frame, read_packet, FrameDecoder},
+    protocol::{
+        Attrs, Data, File, FileAttributes, Handle, Name, Open, OpenFlags, Packet, Read, Rename,
+        RequestId, Stat, Status, StatusCode, Version, Write,
+    },
+};
+
+/// Packets of which every strict prefix is invalid
+fn packets() -> Vec<Packet> {
+    vec![
+        Read {
+            id: RequestId(1),
+            handle: "handle".to_owned(),
+            offset: 4096,
+            len: 32768,
+        }
+        .into(),
+        Write {
+            id: RequestId(2),
+            handle: "handle".to_owned(),
+            offset: 0,
+            data: vec![7; 100],
+        }
+        .into(),
+        Stat {
+            id: RequestId(3),
+            path: "/a/b".to_owned(),
+        }
+        .into(),
+        Rename {
+            id: RequestId(4),
+            oldpath: "/a".to_owned(),
+            newpath: "/b".to_owned(),
+        }
+        .into(),
+        Handle {
+            id: RequestId(5),
+            handle: "handle".to_owned(),
+        }
+        .into(),
+        Data {
+            id: RequestId(6),
+            data: vec![7; 100],
+        }
+        .into(),
+    ]
+}
+
+/// Packets ending with attributes with every field
+fn ending_with_attrs() -> Vec<Packet> {
+    let attrs = FileAttributes {
+        size: Some(1000),
+        uid: Some(1),
+        gid: Some(2),
+        permissions: Some(0o100644),
+        atime: Some(3.into()),
+        mtime: Some(4.into()),
+        ..FileAttributes::empty()
+    };
+    let open = Open {
+        id: RequestId(10),
+        filename: "/a".to_owned(),
+        pflags: OpenFlags::READ,
+        attrs: attrs.clone(),
+    };
+    let name = Name {
+        id: RequestId(8),
+        files: vec![File::new("a", attrs.clone()), File::new("b", attrs.clone())],
+    };
+    let attrs = Attrs {
+        id: RequestId(7),
+        attrs,
+    };
+    vec![open.into(), name.into(), attrs.into()]
+}
+
+/// Encodes the packet without its length
+fn encode(packet: Packet) -> Bytes {
+    Bytes::try_from(packet).unwrap().slice(4..)
+}
+
+#[test]
+fn every_cut_fails() {
+    for packet in packets() {
+        let name = packet.name();
+        let payload = encode(packet);
+        for cut in 0..payload.len() {
+            let result = Packet::try_from(&mut payload.slice(..cut));
+            assert!(result.is_err(), "{name} cut at {cut}: {result:?}");
+        }
+
+        Packet::try_from(&mut payload.clone()).unwrap();
+    }
+}
+
+#[test]
+fn status_without_message() {
+    let payload = encode(Packet::Status(Status::new(RequestId(9), StatusCode::Eof)));
+
+    // the message and the language tag are optional for the servers omitting them
+    for cut in 0..payload.len() {
+        let result = Packet::try_from(&mut payload.slice(..cut));
+        match cut {
+            9 => assert!(matches!(result, Ok(Packet::Status(_)))),
+            _ => assert!(result.is_err(), "cut at {cut}: {result:?}"),
+        }
+    }
+}
+
+#[test]
+fn optional_fields() {
+    let mut version = Version::new();
+    version.extensions.insert("a".to_owned(), "1".to_owned());
+    let payload = encode(version.into());
+
+    // the extensions are read until the end, a cut within one fails
+    let boundaries = [5, payload.len()];
+    for cut in 0..payload.len() {
+        let result = Packet::try_from(&mut payload.slice(..cut));
+        assert_eq!(result.is_ok(), boundaries.contains(&cut), "cut at {cut}");
+    }
+
+    // attributes ending the packet may be left out
+    for packet in ending_with_attrs() {
+        let payload = encode(packet);
+        // the flags, the size, the ids, the permissions and the times
+        let omitted = payload.len() - (4 + 8 + 8 + 4 + 8);
+        for cut in 0..payload.len() {
+            let result = Packet::try_from(&mut payload.slice(..cut));
+            assert_eq!(result.is_ok(), cut == omitted, "cut at {cut}: {result:?}");
+        }
+    }
+}
+
+#[test]
+fn corrupted_bytes_never_panic() {
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    let payloads = packets()
+        .into_iter()
+        .chain(ending_with_attrs())
+        .map(encode)
+        .collect::<Vec<_>>();
+
+    for _ in 0..20_000 {
+        let mut payload = payloads[rng.below(payloads.len())].to_vec();
+        for _ in 0..1 + rng.below(4) {
+            let at = rng.below(payload.len());
+            payload[at] = match rng.below(3) {
+                // lengths and counts at their maximum
+                0 => 0xff,
+                1 => 0,
+                _ => rng.next() as u8,
+            };
+        }
+
+        let _ = Packet::try_from(&mut Bytes::from(payload));
+    }
+}
+
+#[tokio::test]
+async fn truncated_streams_fail() {
+    let frame = encode_frame(
+        Stat {
+            id: RequestId(11),
+            path: "/a/b".to_owned(),
+        }
+        .into(),
+    )
+    .unwrap();
+
+    for cut in 0..frame.len() {
+        let mut stream = &frame[..cut];
+        let result = read_packet(&mut stream, &mut BytesMut::new()).await;
+        assert!(result.is_err(), "cut at {cut}");
+    }
+
+    let mut stream = &frame[..];
+    let payload = read_packet(&mut stream, &mut BytesMut::new()).await;
+    assert_eq!(payload.unwrap(), frame.slice(4..));
+}
+
+#[test]
+fn split_frames_wait_for_the_rest() {
+    let frame = encode_frame(
+        Data {
+            id: RequestId(12),
+            data: vec![7; 100],
+        }
+        .into(),
+    )
+    .unwrap();
+
+    for cut in 0..frame.len() {
+        let mut decoder = FrameDecoder::new();
+        decoder.push(&frame[..cut]);
+        assert_eq!(decoder.next_frame().unwrap(), None, "cut at {cut}");
+
+        decoder.push(&frame[cut..]);
+        assert_eq!(decoder.next_frame().unwrap(), Some(frame.slice(4..)));
+    }
+}
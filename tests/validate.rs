@@ -0,0 +1,231 @@
+//! Rules of the validation of the replies, applied alone and by the server.
+
+use russh_sftp::{
+    client::{error::Error, RawSftpSession},
+    protocol::{
+        Data, ExtendedReply, File, FileAttributes, Handle, Name, OpenFlags, Packet, RequestId,
+        Status, StatusCode, Version,
+    },
+    server::{
+        self,
+        validate::{validate, RequestInfo, RULES},
+        Config, Handler, ValidationAction,
+    },
+};
+use std::collections::HashMap;
+
+fn data(id: u32, len: usize) -> Packet {
+    Data {
+        id: RequestId(id),
+        data: vec![7; len],
+    }
+    .into()
+}
+
+fn name(id: u32, count: usize) -> Packet {
+    Name {
+        id: RequestId(id),
+        files: (0..count).map(|i| File::dummy(format!("/{i}"))).collect(),
+    }
+    .into()
+}
+
+fn status(id: u32, code: StatusCode) -> Packet {
+    Packet::error(RequestId(id), code)
+}
+
+#[test]
+fn rules_in_order() {
+    let names = RULES.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        ["response type", "request id", "single name", "data length"]
+    );
+}
+
+#[test]
+fn rule_table() {
+    // request, reply, rule violated if any
+    let table = [
+        ("SSH_FXP_INIT", Version::new().into(), None),
+        ("SSH_FXP_STAT", Version::new().into(), Some("response type")),
+        ("SSH_FXP_READ", data(3, 10), None),
+        ("SSH_FXP_READ", status(3, StatusCode::Eof), None),
+        (
+            "SSH_FXP_READ",
+            status(3, StatusCode::Ok),
+            Some("response type"),
+        ),
+        ("SSH_FXP_READ", name(3, 1), Some("response type")),
+        ("SSH_FXP_READ", data(4, 10), Some("request id")),
+        ("SSH_FXP_READ", data(3, 0), Some("data length")),
+        ("SSH_FXP_CLOSE", status(3, StatusCode::Ok), None),
+        (
+            "SSH_FXP_CLOSE",
+            status(4, StatusCode::Failure),
+            Some("request id"),
+        ),
+        ("SSH_FXP_REALPATH", name(3, 1), None),
+        ("SSH_FXP_REALPATH", name(3, 0), Some("single name")),
+        ("SSH_FXP_READLINK", name(3, 2), Some("single name")),
+        ("SSH_FXP_READDIR", name(3, 0), None),
+        ("SSH_FXP_READDIR", name(3, 2), None),
+        (
+            "SSH_FXP_OPEN",
+            Handle {
+                id: RequestId(3),
+                handle: "h".to_owned(),
+            }
+            .into(),
+            None,
+        ),
+        (
+            "SSH_FXP_EXTENDED",
+            ExtendedReply {
+                id: RequestId(3),
+                data: vec![],
+            }
+            .into(),
+            None,
+        ),
+    ];
+
+    for (request, reply, violated) in table {
+        let info = RequestInfo {
+            name: request,
+            id: RequestId(3),
+        };
+        let result = validate(info, &reply);
+
+        match violated {
+            None => assert_eq!(result, Ok(()), "{request} {}", reply.name()),
+            Some(rule) => {
+                let err = result.unwrap_err();
+                assert!(err.starts_with(&format!("{rule}: ")), "{request}: {err}");
+            }
+        }
+    }
+}
+
+#[test]
+fn detailed_messages() {
+    let info = RequestInfo {
+        name: "SSH_FXP_READ",
+        id: RequestId(3),
+    };
+
+    assert_eq!(
+        validate(info, &status(3, StatusCode::Ok)).unwrap_err(),
+        "response type: Ok status is not a valid reply to SSH_FXP_READ, expected SSH_FXP_DATA"
+    );
+    assert_eq!(
+        validate(info, &name(3, 1)).unwrap_err(),
+        "response type: SSH_FXP_NAME is not a valid reply to SSH_FXP_READ"
+    );
+}
+
+/// Replies to reads with empty data and to realpath with two names
+struct Invalid;
+
+#[async_trait::async_trait]
+impl Handler for Invalid {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn open(
+        &mut self,
+        id: RequestId,
+        filename: String,
+        _pflags: OpenFlags,
+        _attrs: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        Ok(Handle {
+            id,
+            handle: filename,
+        })
+    }
+
+    async fn close(&mut self, id: RequestId, _handle: String) -> Result<Status, Self::Error> {
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+
+    async fn read(
+        &mut self,
+        id: RequestId,
+        _handle: String,
+        _offset: u64,
+        _len: u32,
+    ) -> Result<Data, Self::Error> {
+        Ok(Data { id, data: vec![] })
+    }
+
+    async fn realpath(&mut self, id: RequestId, _path: String) -> Result<Name, Self::Error> {
+        Ok(Name {
+            id,
+            files: vec![File::dummy("/a"), File::dummy("/b")],
+        })
+    }
+}
+
+async fn session(validation_action: ValidationAction) -> RawSftpSession {
+    let config = Config {
+        validate_responses: true,
+        validation_action,
+        ..Default::default()
+    };
+
+    let (client, stream) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(server::serve(stream, Invalid, config));
+    let raw = RawSftpSession::new(client);
+    raw.init().await.unwrap();
+    raw
+}
+
+#[tokio::test]
+async fn invalid_replies_sent_with_a_warning() {
+    let raw = session(ValidationAction::Warn).await;
+
+    let handle = raw
+        .open("/f", OpenFlags::READ, FileAttributes::empty())
+        .await
+        .unwrap()
+        .handle;
+    assert!(raw.read(handle, 0, 10).await.unwrap().data.is_empty());
+    assert_eq!(raw.realpath(".").await.unwrap().files.len(), 2);
+}
+
+#[tokio::test]
+async fn invalid_replies_replaced() {
+    let raw = session(ValidationAction::Replace).await;
+
+    let handle = raw
+        .open("/f", OpenFlags::READ, FileAttributes::empty())
+        .await
+        .unwrap()
+        .handle;
+    let results = [
+        raw.read(handle, 0, 10).await.map(|_| ()),
+        raw.realpath(".").await.map(|_| ()),
+    ];
+
+    for (result, rule) in results.into_iter().zip(["data length", "single name"]) {
+        match result {
+            Err(Error::Status { status, .. }) => {
+                assert_eq!(status.status_code, StatusCode::Failure);
+                assert!(status.error_message.starts_with(rule), "{status:?}");
+            }
+            result => panic!("{result:?}"),
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! Assembly of SSH_FXP_VERSION from the reply of the handler and the extensions
+//! advertised by the crate.
+
+use bytes::{Bytes, BytesMut};
+use russh_sftp::{
+    extensions,
+    framing::read_packet,
+    protocol::{Init, Packet, StatusCode, Version, MIN_VERSION, VERSION},
+    server::{self, Config, Handler},
+};
+use std::collections::HashMap;
+use tokio::io::AsyncWriteExt;
+
+/// Replies to SSH_FXP_INIT with a fixed version, on top of statvfs and fsync
+struct Advertiser {
+    reply: Option<Version>,
+}
+
+#[async_trait::async_trait]
+impl Handler for Advertiser {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    fn supported_extensions(&self) -> &'static [&'static str] {
+        &[extensions::STATVFS, extensions::FSYNC]
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(self.reply.take().unwrap())
+    }
+}
+
+/// Sends SSH_FXP_INIT of `version` and returns the reply of the server
+async fn exchange(reply: Version, config: Config, version: u32) -> Packet {
+    let (mut client, server) = tokio::io::duplex(4096);
+    let handler = Advertiser { reply: Some(reply) };
+    tokio::spawn(server::serve(server, handler, config));
+
+    let init = Packet::Init(Init {
+        version,
+        extensions: HashMap::new(),
+    });
+    let frame = Bytes::try_from(init).unwrap();
+    client.write_all(&frame).await.unwrap();
+
+    let mut buf = BytesMut::new();
+    let mut reply = read_packet(&mut client, &mut buf).await.unwrap();
+    Packet::try_from(&mut reply).unwrap()
+}
+
+fn advertised(packet: Packet) -> Version {
+    match packet {
+        Packet::Version(version) => version,
+        packet => panic!("{} instead of SSH_FXP_VERSION", packet.name()),
+    }
+}
+
+#[tokio::test]
+async fn handler_extensions_win() {
+    let reply = Version::new()
+        .with_extension(extensions::STATVFS, "1")
+        .with_extension("vendor@example.com", "1");
+    let version = advertised(exchange(reply, Config::default(), VERSION).await);
+
+    assert_eq!(version.extensions[extensions::STATVFS], "1");
+    assert_eq!(version.extensions[extensions::FSYNC], "1");
+    assert_eq!(version.extensions["vendor@example.com"], "1");
+}
+
+#[tokio::test]
+async fn strict_extensions_reject_conflicts() {
+    let config = Config {
+        strict_extensions: true,
+        ..Default::default()
+    };
+    let reply = Version::new().with_extension(extensions::STATVFS, "1");
+
+    match exchange(reply, config, VERSION).await {
+        Packet::Status(status) => assert_eq!(status.status_code, StatusCode::Failure),
+        packet => panic!("{} instead of a status", packet.name()),
+    }
+}
+
+#[tokio::test]
+async fn strict_extensions_accept_same_values() {
+    let config = Config {
+        strict_extensions: true,
+        ..Default::default()
+    };
+    let reply = Version::new().with_extension(extensions::STATVFS, "2");
+    let version = advertised(exchange(reply, config, VERSION).await);
+
+    assert_eq!(version.extensions[extensions::STATVFS], "2");
+    assert_eq!(version.extensions.len(), 2);
+}
+
+#[tokio::test]
+async fn version_downgraded_to_client() {
+    let version = advertised(exchange(Version::new(), Config::default(), 2).await);
+    assert_eq!(version.version, 2);
+
+    let version = advertised(exchange(Version::new(), Config::default(), 6).await);
+    assert_eq!(version.version, VERSION);
+}
+
+#[test]
+fn with_version() {
+    assert_eq!(Version::new().with_version(2).unwrap().version, 2);
+    assert!(Version::new().with_version(MIN_VERSION - 1).is_err());
+    assert!(Version::new().with_version(VERSION + 1).is_err());
+}
+
+#[test]
+fn merge_extensions() {
+    let mut version = Version::new()
+        .with_extension("a", "1")
+        .with_extension("a", "2");
+    assert_eq!(version.extensions["a"], "2");
+
+    let conflicting = [
+        ("b".to_owned(), "1".to_owned()),
+        ("a".to_owned(), "3".to_owned()),
+    ];
+    assert!(version.merge_extensions(conflicting.clone(), true).is_err());
+    assert_eq!(version.extensions.len(), 1);
+
+    version.merge_extensions(conflicting, false).unwrap();
+    assert_eq!(version.extensions["a"], "3");
+    assert_eq!(version.extensions["b"], "1");
+
+    version
+        .merge_extensions([("b".to_owned(), "1".to_owned())], true)
+        .unwrap();
+}
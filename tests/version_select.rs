@@ -0,0 +1,119 @@
+//! Switch of the protocol version with `version-select` among the versions
+//! advertised by the server.
+
+use russh_sftp::{
+    client::{error::Error, RawSftpSession, SftpSession},
+    extensions,
+    protocol::{Packet, RequestId, Status, StatusCode, Version},
+    server::{self, Handler},
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// Advertises versions and accepts the selection of those listed
+struct Selectable {
+    selected: Arc<Mutex<Vec<String>>>,
+}
+
+#[async_trait::async_trait]
+impl Handler for Selectable {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new().with_extension(extensions::VERSIONS, "2,3,4, 6,x\0"))
+    }
+
+    async fn extended(
+        &mut self,
+        id: RequestId,
+        request: String,
+        data: Vec<u8>,
+    ) -> Result<Packet, Self::Error> {
+        if request != extensions::VERSION_SELECT {
+            return Err(self.unimplemented());
+        }
+
+        let version = String::from_utf8(data[4..].to_vec()).unwrap();
+        self.selected.lock().unwrap().push(version.clone());
+
+        match version.as_str() {
+            "2" | "3" => Ok(Status::new(id, StatusCode::Ok).into()),
+            _ => Err(StatusCode::Failure),
+        }
+    }
+}
+
+fn run() -> (tokio::io::DuplexStream, Arc<Mutex<Vec<String>>>) {
+    let selected = Arc::new(Mutex::new(Vec::new()));
+    let handler = Selectable {
+        selected: selected.clone(),
+    };
+
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(server::serve(server, handler, Default::default()));
+    (client, selected)
+}
+
+#[tokio::test]
+async fn advertised_versions() {
+    let (client, _) = run();
+    let sftp = SftpSession::new(client).await.unwrap();
+
+    assert_eq!(sftp.server_versions(), [2, 3, 4, 6]);
+}
+
+#[tokio::test]
+async fn select_implemented_version() {
+    let (client, selected) = run();
+    let session = RawSftpSession::new(client);
+    assert_eq!(session.negotiated_version(), None);
+
+    let version = session.init().await.unwrap();
+    assert_eq!(version.versions(), [2, 3, 4, 6]);
+    assert_eq!(session.negotiated_version(), Some(3));
+
+    session.version_select("2").await.unwrap();
+    assert_eq!(session.negotiated_version(), Some(2));
+    assert_eq!(*selected.lock().unwrap(), ["2"]);
+}
+
+#[tokio::test]
+async fn unimplemented_version_not_sent() {
+    let (client, selected) = run();
+    let session = RawSftpSession::new(client);
+    session.init().await.unwrap();
+
+    for version in ["4", "6", "0", "x"] {
+        match session.version_select(version).await {
+            Err(Error::VersionUnsupported(refused)) => assert_eq!(refused, version),
+            result => panic!("{version} selected: {result:?}"),
+        }
+    }
+
+    assert_eq!(session.negotiated_version(), Some(3));
+    assert!(selected.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn rejected_selection_keeps_version() {
+    let (client, selected) = run();
+    let session = RawSftpSession::new(client);
+    session.init().await.unwrap();
+
+    let Err(Error::Status { status, .. }) = session.version_select("1").await else {
+        panic!("version 1 selected");
+    };
+    assert_eq!(status.status_code, StatusCode::Failure);
+    assert_eq!(session.negotiated_version(), Some(3));
+    assert_eq!(*selected.lock().unwrap(), ["1"]);
+}
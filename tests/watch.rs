@@ -0,0 +1,316 @@
+//! Changes reported by the polling watcher between the polls, with the clock
+//! paused so that each poll happens at a known time.
+
+use futures::StreamExt;
+use russh_sftp::{
+    client::{ChangeEvent, SftpSession, Watch},
+    protocol::{Attrs, File, FileAttributes, Handle, Name, RequestId, Status, StatusCode, Version},
+    server::Handler,
+};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time;
+
+const INTERVAL: Duration = Duration::from_secs(10);
+
+/// Entry of the tree, a directory if `size` is [`None`]
+#[derive(Clone, Copy)]
+struct Entry {
+    size: Option<u64>,
+    mtime: u32,
+}
+
+impl Entry {
+    fn attrs(&self) -> FileAttributes {
+        FileAttributes {
+            size: self.size,
+            permissions: Some(match self.size {
+                Some(_) => 0o100644,
+                None => 0o040755,
+            }),
+            mtime: Some(self.mtime.into()),
+            atime: Some(self.mtime.into()),
+            ..FileAttributes::empty()
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    entries: BTreeMap<String, Entry>,
+    /// Paths failing with PermissionDenied
+    denied: HashSet<String>,
+    listed: HashSet<String>,
+}
+
+/// Tree of files with modification times, shared with the test
+#[derive(Clone, Default)]
+struct Tree(Arc<Mutex<State>>);
+
+impl Tree {
+    fn dir(&self, path: &str) {
+        self.insert(path, None, 1);
+    }
+
+    fn file(&self, path: &str, size: u64, mtime: u32) {
+        self.insert(path, Some(size), mtime);
+    }
+
+    fn insert(&self, path: &str, size: Option<u64>, mtime: u32) {
+        let entry = Entry { size, mtime };
+        self.0
+            .lock()
+            .unwrap()
+            .entries
+            .insert(path.to_owned(), entry);
+    }
+
+    fn remove(&self, path: &str) {
+        self.0.lock().unwrap().entries.remove(path);
+    }
+
+    fn rename(&self, from: &str, to: &str) {
+        let mut state = self.0.lock().unwrap();
+        let entry = state.entries.remove(from).unwrap();
+        state.entries.insert(to.to_owned(), entry);
+    }
+
+    fn deny(&self, path: &str, denied: bool) {
+        let mut state = self.0.lock().unwrap();
+        match denied {
+            true => state.denied.insert(path.to_owned()),
+            false => state.denied.remove(path),
+        };
+    }
+
+    fn entry(&self, path: &str) -> Result<Entry, StatusCode> {
+        let state = self.0.lock().unwrap();
+        if state.denied.contains(path) {
+            return Err(StatusCode::PermissionDenied);
+        }
+
+        state
+            .entries
+            .get(path)
+            .copied()
+            .ok_or(StatusCode::NoSuchFile)
+    }
+}
+
+#[async_trait::async_trait]
+impl Handler for Tree {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn stat(&mut self, id: RequestId, path: String) -> Result<Attrs, Self::Error> {
+        Ok(Attrs {
+            id,
+            attrs: self.entry(&path)?.attrs(),
+        })
+    }
+
+    async fn opendir(&mut self, id: RequestId, path: String) -> Result<Handle, Self::Error> {
+        self.entry(&path)?;
+        self.0.lock().unwrap().listed.remove(&path);
+        Ok(Handle { id, handle: path })
+    }
+
+    async fn readdir(&mut self, id: RequestId, handle: String) -> Result<Name, Self::Error> {
+        let mut state = self.0.lock().unwrap();
+        if !state.listed.insert(handle.clone()) {
+            return Err(StatusCode::Eof);
+        }
+
+        let prefix = format!("{handle}/");
+        let files = state
+            .entries
+            .iter()
+            .filter_map(|(path, entry)| Some((path.strip_prefix(&prefix)?, entry)))
+            .filter(|(name, _)| !name.contains('/'))
+            .map(|(name, entry)| File::new(name, entry.attrs()))
+            .collect();
+
+        Ok(Name { id, files })
+    }
+
+    async fn close(&mut self, id: RequestId, _handle: String) -> Result<Status, Self::Error> {
+        Ok(Status::new(id, StatusCode::Ok))
+    }
+}
+
+/// Watches the paths once the baseline poll is done
+async fn watch(tree: &Tree, paths: &[&str]) -> Watch {
+    let (sftp, _server) = SftpSession::pair(tree.clone()).await.unwrap();
+    let watch = sftp.watch(paths.iter().map(|p| p.to_string()).collect(), INTERVAL);
+    time::sleep(INTERVAL / 2).await;
+    watch
+}
+
+/// The next `count` events, in the order they are reported
+async fn next_poll(watch: &mut Watch, count: usize) -> Vec<String> {
+    let mut events = Vec::new();
+    for _ in 0..count {
+        let event = watch.next().await.unwrap();
+        events.push(match event {
+            ChangeEvent::Created(path) => format!("created {path}"),
+            ChangeEvent::Removed(path) => format!("removed {path}"),
+            ChangeEvent::Modified(path) => format!("modified {path}"),
+            ChangeEvent::Renamed { from, to } => format!("renamed {from} {to}"),
+            ChangeEvent::Error { path, error } => format!("error {path}: {error}"),
+        });
+    }
+
+    events
+}
+
+/// Asserts that the next poll reports nothing
+async fn quiet(watch: &mut Watch) {
+    let next = time::timeout(INTERVAL * 3 / 2, watch.next()).await;
+    assert!(next.is_err(), "{:?}", next.unwrap());
+}
+
+#[tokio::test(start_paused = true)]
+async fn baseline_reports_nothing() {
+    let tree = Tree::default();
+    tree.dir("/dir");
+    tree.file("/dir/a", 1, 100);
+    let mut watch = watch(&tree, &["/dir", "/missing"]).await;
+
+    quiet(&mut watch).await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn files_created_modified_and_removed() {
+    let tree = Tree::default();
+    tree.dir("/dir");
+    tree.file("/dir/a", 1, 100);
+    tree.file("/dir/b", 1, 100);
+    tree.file("/single", 1, 100);
+    let mut watch = watch(&tree, &["/dir", "/single", "/later"]).await;
+
+    tree.file("/dir/new", 5, 200);
+    tree.file("/dir/a", 2, 100);
+    tree.remove("/dir/b");
+    tree.file("/single", 1, 101);
+    tree.file("/later", 1, 100);
+
+    assert_eq!(
+        next_poll(&mut watch, 5).await,
+        [
+            "modified /dir/a",
+            "removed /dir/b",
+            "created /dir/new",
+            "modified /single",
+            "created /later",
+        ]
+    );
+
+    tree.remove("/single");
+    tree.dir("/dir/sub");
+    assert_eq!(
+        next_poll(&mut watch, 2).await,
+        ["created /dir/sub", "removed /single"]
+    );
+
+    quiet(&mut watch).await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn renames_guessed_when_unambiguous() {
+    let tree = Tree::default();
+    tree.dir("/dir");
+    tree.file("/dir/a", 10, 100);
+    tree.file("/dir/b", 20, 200);
+    tree.file("/dir/c", 20, 200);
+    let mut watch = watch(&tree, &["/dir"]).await;
+
+    tree.rename("/dir/a", "/dir/moved");
+    assert_eq!(
+        next_poll(&mut watch, 1).await,
+        ["renamed /dir/a /dir/moved"]
+    );
+
+    // two entries share the stamp, the rename cannot be told apart
+    tree.rename("/dir/b", "/dir/d");
+    tree.rename("/dir/c", "/dir/e");
+    assert_eq!(
+        next_poll(&mut watch, 4).await,
+        [
+            "removed /dir/b",
+            "removed /dir/c",
+            "created /dir/d",
+            "created /dir/e"
+        ]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn errors_reported_and_the_state_kept() {
+    let tree = Tree::default();
+    tree.dir("/dir");
+    tree.file("/dir/a", 1, 100);
+    tree.file("/other", 1, 100);
+    let mut watch = watch(&tree, &["/dir", "/other"]).await;
+
+    tree.deny("/dir", true);
+    tree.file("/dir/b", 1, 100);
+    tree.file("/other", 2, 100);
+    assert_eq!(
+        next_poll(&mut watch, 2).await,
+        [
+            "error /dir: Permission denied: Permission denied (/dir)",
+            "modified /other",
+        ]
+    );
+
+    // compared with the state before the error
+    tree.deny("/dir", false);
+    assert_eq!(next_poll(&mut watch, 1).await, ["created /dir/b"]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn all_paths_polled_with_a_budget_of_one() {
+    let tree = Tree::default();
+    let paths = (0..20).map(|i| format!("/f{i}")).collect::<Vec<_>>();
+    let mut watch = watch(&tree, &paths.iter().map(String::as_str).collect::<Vec<_>>()).await;
+    watch.set_max_concurrent(0);
+
+    for path in &paths {
+        tree.file(path, 1, 100);
+    }
+
+    let expected = paths
+        .iter()
+        .map(|path| format!("created {path}"))
+        .collect::<Vec<_>>();
+    assert_eq!(next_poll(&mut watch, paths.len()).await, expected);
+}
+
+#[tokio::test(start_paused = true)]
+async fn polling_stops_with_the_stream() {
+    let tree = Tree::default();
+    tree.file("/f", 1, 100);
+    let watch = watch(&tree, &["/f"]).await;
+    // the handler of the server is still alive
+    assert_eq!(Arc::strong_count(&tree.0), 2);
+
+    drop(watch);
+    time::sleep(INTERVAL * 3).await;
+
+    // the polling task released the session, which ended the server
+    assert_eq!(Arc::strong_count(&tree.0), 1);
+}
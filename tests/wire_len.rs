@@ -0,0 +1,76 @@
+//! Lengths above the u32 prefixes of the protocol, rejected rather than wrapped.
+//! The sequences lie about their length instead of allocating 4 GB.
+#![cfg(target_pointer_width = "64")]
+
+use bytes::Bytes;
+use russh_sftp::{
+    protocol::{Packet, RequestId, Write},
+    ser, Error,
+};
+use serde::{ser::SerializeSeq, Serialize, Serializer};
+
+const OVER: usize = u32::MAX as usize + 1;
+
+/// Sequence announcing `len` elements and serializing none of them
+struct Announced(usize);
+
+impl Serialize for Announced {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_seq(Some(self.0))?.end()
+    }
+}
+
+#[derive(Serialize)]
+struct Request {
+    id: u32,
+    data: Announced,
+}
+
+fn bad_message(result: Result<Bytes, Error>) -> String {
+    match result {
+        Err(Error::BadMessage(message)) => message,
+        result => panic!("not rejected: {result:?}"),
+    }
+}
+
+#[test]
+fn wire_len_bounds() {
+    assert_eq!(ser::wire_len(0).unwrap(), 0);
+    assert_eq!(ser::wire_len(u32::MAX as usize).unwrap(), u32::MAX);
+
+    let Err(Error::BadMessage(message)) = ser::wire_len(OVER) else {
+        panic!("{OVER} accepted");
+    };
+    assert!(message.contains("4294967296"), "{message}");
+}
+
+#[test]
+fn sequence_over_limit() {
+    let message = bad_message(ser::to_bytes(&Announced(OVER)));
+    assert!(message.contains("4294967296"), "{message}");
+
+    // nothing is written for the fields before either
+    let request = Request {
+        id: 1,
+        data: Announced(OVER),
+    };
+    bad_message(ser::to_bytes(&request));
+}
+
+#[test]
+fn sequence_at_limit() {
+    let bytes = ser::to_bytes(&Announced(u32::MAX as usize)).unwrap();
+    assert_eq!(&bytes[..], u32::MAX.to_be_bytes());
+}
+
+#[test]
+fn packets_below_limit() {
+    let write = Write {
+        id: RequestId(1),
+        handle: "h".to_owned(),
+        offset: 0,
+        data: vec![7; 1024],
+    };
+    let frame = Bytes::try_from(Packet::Write(write)).unwrap();
+    assert_eq!(&frame[..4], (frame.len() as u32 - 4).to_be_bytes());
+}
@@ -0,0 +1,137 @@
+//! Replies to pipelined writes received out of order or carrying the error of
+//! another write, from a scripted server, and the file poisoned by the first error.
+
+mod common;
+
+use bytes::{Bytes, BytesMut};
+use common::Local;
+use russh_sftp::{
+    client::{error::Error, SftpSession},
+    framing::read_packet,
+    protocol::{Handle, Packet, Status, StatusCode, Version},
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    fs,
+    io::{AsyncWriteExt, DuplexStream},
+    time,
+};
+
+const CHUNK: usize = 100;
+const WRITES: usize = 4;
+
+/// Status replied to each of the writes, in the order of the replies
+type Script = Vec<(usize, StatusCode)>;
+
+/// Replies to the writes once all of them were received, in the order of `script`.
+/// Each reply is handled by the client before the next one is sent
+async fn scripted(mut stream: DuplexStream, script: Script, writes: Arc<Mutex<Vec<u64>>>) {
+    let mut buf = BytesMut::new();
+    let mut pending = Vec::new();
+
+    while let Ok(mut payload) = read_packet(&mut stream, &mut buf).await {
+        let replies = match Packet::try_from(&mut payload).unwrap() {
+            Packet::Init(_) => vec![Version::new().into()],
+            Packet::Open(open) => vec![Packet::Handle(Handle {
+                id: open.id,
+                handle: "h".to_owned(),
+            })],
+            Packet::Write(write) => {
+                writes.lock().unwrap().push(write.offset);
+                pending.push(write.id);
+                match pending.len() {
+                    WRITES => script
+                        .iter()
+                        .map(|(index, code)| Status::new(pending[*index], *code).into())
+                        .collect(),
+                    _ => Vec::new(),
+                }
+            }
+            Packet::Close(close) => vec![Status::new(close.id, StatusCode::Ok).into()],
+            request => {
+                vec![Status::new(request.get_request_id(), StatusCode::OpUnsupported).into()]
+            }
+        };
+
+        for reply in replies {
+            let frame = Bytes::try_from(reply).unwrap();
+            stream.write_all(&frame).await.unwrap();
+            time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+}
+
+/// Local file of [`WRITES`] chunks
+fn local(name: &str) -> Local {
+    Local::with_data(name, &[1; WRITES * CHUNK])
+}
+
+#[tokio::test(start_paused = true)]
+async fn acknowledged_despite_the_order() {
+    for order in [[0, 1, 2, 3], [3, 2, 1, 0], [2, 0, 3, 1]] {
+        let script = order.iter().map(|&w| (w, StatusCode::Ok)).collect();
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(scripted(server, script, Default::default()));
+        let sftp = SftpSession::new(client).await.unwrap();
+
+        let local = local(&format!("order-{}", order[0]));
+        let mut file = sftp.create("/file").await.unwrap();
+        file.pin_chunk_size(CHUNK);
+        let copied = file
+            .copy_from_local(&mut fs::File::open(&local.0).await.unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(copied, (WRITES * CHUNK) as u64, "{order:?}");
+        assert_eq!(file.acknowledged_size(), copied, "{order:?}");
+        file.flush().await.unwrap();
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn only_the_writes_before_the_first_error_landed() {
+    use StatusCode::{Failure, Ok};
+
+    let cases = [
+        // the error of the second write reported on the last one
+        (vec![(0, Ok), (2, Ok), (3, Failure), (1, Ok)], CHUNK as u64),
+        // a gap is not filled by the writes after the error
+        (vec![(1, Ok), (3, Failure), (0, Ok), (2, Ok)], 0),
+        (
+            vec![(0, Ok), (1, Ok), (2, Ok), (3, Failure)],
+            3 * CHUNK as u64,
+        ),
+        (vec![(0, Failure), (1, Ok), (2, Ok), (3, Ok)], 0),
+    ];
+
+    for (i, (script, acknowledged)) in cases.into_iter().enumerate() {
+        let writes = Arc::new(Mutex::new(Vec::new()));
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(scripted(server, script, writes.clone()));
+        let sftp = SftpSession::new(client).await.unwrap();
+
+        let local = local(&format!("error-{i}"));
+        let mut file = sftp.create("/file").await.unwrap();
+        file.pin_chunk_size(CHUNK);
+        let err = file
+            .copy_from_local(&mut fs::File::open(&local.0).await.unwrap())
+            .await
+            .unwrap_err();
+
+        assert!(
+            matches!(&err, Error::Status { status, .. } if status.status_code == Failure),
+            "{i}: {err}"
+        );
+        assert_eq!(file.acknowledged_size(), acknowledged, "{i}");
+
+        // the file is poisoned, nothing more is sent
+        let write = file.write_all(&[2; CHUNK]).await.unwrap_err();
+        assert_eq!(write.kind(), std::io::ErrorKind::Other, "{i}");
+        assert!(file.flush().await.is_err(), "{i}");
+        assert!(file.sync_all().await.is_err(), "{i}");
+        assert_eq!(writes.lock().unwrap().len(), WRITES, "{i}");
+    }
+}
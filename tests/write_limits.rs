@@ -0,0 +1,64 @@
+//! Write requests within the packet limit for long handles.
+
+mod common;
+
+use common::Memory;
+use russh_sftp::{
+    client::{
+        error::Error,
+        fs::File,
+        rawsession::{Limits, RawSftpSession},
+    },
+    protocol::{FileAttributes, OpenFlags, Write},
+    server,
+};
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+
+const PACKET_LEN: u64 = 1024;
+
+#[tokio::test]
+async fn long_handle_within_packet_limit() {
+    let memory = Memory::default();
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    server::run(server, memory.clone()).await;
+
+    let mut session = RawSftpSession::new(client);
+    session.init().await.unwrap();
+    session.set_limits(Arc::new(Limits {
+        packet_len: Some(PACKET_LEN),
+        write_len: Some(1000),
+        ..Limits::default()
+    }));
+
+    // the handle of the in-memory server is the path
+    let path = format!("/{}", "h".repeat(199));
+    let flags = OpenFlags::CREATE | OpenFlags::WRITE;
+    let handle = session
+        .open(path.as_str(), flags, FileAttributes::empty())
+        .await
+        .unwrap()
+        .handle;
+    assert_eq!(handle.len(), 200);
+
+    // the data is within the write limit, but not the framed packet
+    let max_data_len = PACKET_LEN as usize - Write::overhead(handle.len());
+    assert_eq!(max_data_len, 799);
+
+    session
+        .write(handle.as_str(), 0, vec![1; max_data_len])
+        .await
+        .unwrap();
+    let err = session
+        .write(handle.as_str(), 0, vec![1; max_data_len + 1])
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::Limited(_)), "{err}");
+
+    let mut file = File::from_raw_parts(Arc::new(session), handle, &[]);
+    assert_eq!(file.chunk_size(), max_data_len);
+
+    file.write_all(&[2; 5000]).await.unwrap();
+    file.shutdown().await.unwrap();
+    assert_eq!(memory.files.lock().unwrap()[&path], [2; 5000]);
+}